@@ -1,3 +1,4 @@
+use crate::server::auth::{validate_scope, Audience};
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
@@ -31,6 +32,14 @@ pub struct Cli {
     #[arg(short, long)]
     pub foreground: bool,
 
+    /// Reject unknown configuration keys instead of ignoring them
+    #[arg(long, env = "INFRACTL_STRICT_CONFIG")]
+    pub strict_config: bool,
+
+    /// Configuration profile to apply on top of the base settings (from `profiles:`)
+    #[arg(long, env = "INFRACTL_PROFILE")]
+    pub profile: Option<String>,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
@@ -45,6 +54,10 @@ pub enum Commands {
         /// Configuration file to validate
         #[arg(short, long)]
         config: Option<PathBuf>,
+
+        /// Only warn about unknown configuration keys (strict by default)
+        #[arg(long)]
+        lenient: bool,
     },
 
     /// Show current version
@@ -56,9 +69,27 @@ pub enum Commands {
         #[arg(short, long)]
         subject: String,
 
-        /// Token TTL (e.g., "24h", "7d")
-        #[arg(short, long, default_value = "24h")]
-        ttl: String,
+        /// Token TTL (e.g., "24h", "7d"; default: 24h, or `auth.refresh_token_ttl` with --refresh)
+        #[arg(short, long)]
+        ttl: Option<String>,
+
+        /// Route classes the token is valid for (repeatable)
+        #[arg(
+            short,
+            long = "audience",
+            value_enum,
+            default_values_t = [Audience::Webhook, Audience::Api]
+        )]
+        audience: Vec<Audience>,
+
+        /// Restrict the token to these scopes (repeatable), e.g. `deploy:web-app`,
+        /// `metrics:read`, `admin`; without any the token is limited by audience only
+        #[arg(long = "scope", value_parser = parse_scope)]
+        scope: Vec<String>,
+
+        /// Issue a refresh token, exchanged for short-lived access tokens at `POST /auth/refresh`
+        #[arg(long)]
+        refresh: bool,
     },
 
     /// Check health of an agent
@@ -70,6 +101,10 @@ pub enum Commands {
         /// Bearer token for authentication
         #[arg(short, long)]
         token: Option<String>,
+
+        /// Use the gRPC API (`server.grpc`) instead of `GET /health`
+        #[arg(long)]
+        grpc: bool,
     },
 
     /// Trigger a deployment
@@ -101,6 +136,103 @@ pub enum Commands {
         /// Bypass path restrictions for this deployment
         #[arg(short, long)]
         force: bool,
+
+        /// Trigger the deployment on every configured agent (Home mode)
+        #[arg(long, conflicts_with_all = ["agent", "target", "permanent", "reset"])]
+        all_agents: bool,
+
+        /// With --all-agents: only agents carrying this tag (repeatable, all must match)
+        #[arg(long = "tag", requires = "all_agents")]
+        tag: Vec<String>,
+    },
+
+    /// Approve a deploy job waiting for approval (`requires_approval`)
+    Approve {
+        /// Job ID returned when the deployment was triggered
+        job_id: String,
+
+        /// Approver identity (token subject, must be listed in `approvers`)
+        #[arg(short, long, env = "INFRACTL_APPROVER")]
+        subject: String,
+
+        /// Agent address the job is queued on (default: local service)
+        #[arg(short, long)]
+        agent: Option<String>,
+    },
+
+    /// Run an allowlisted command (`modules.exec.commands`) on agents via the Home service
+    Exec {
+        /// Command name
+        command: String,
+
+        /// Agent to run it on, by name (repeatable)
+        #[arg(short, long = "agent", required_unless_present = "all")]
+        agent: Vec<String>,
+
+        /// Run it on every agent
+        #[arg(long, conflicts_with = "agent")]
+        all: bool,
+
+        /// Only agents carrying this tag (repeatable, all must match)
+        #[arg(long = "tag")]
+        tag: Vec<String>,
+    },
+
+    /// Export metrics or deploy history from the Home service as CSV or JSON
+    Export {
+        /// Data to export
+        #[arg(value_enum)]
+        data: ExportData,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+
+        /// Only records from this time on (RFC 3339, e.g. "2025-01-01T00:00:00Z")
+        #[arg(long)]
+        from: Option<String>,
+
+        /// Only records up to this time (RFC 3339)
+        #[arg(long)]
+        to: Option<String>,
+
+        /// Only records of this agent
+        #[arg(short, long)]
+        agent: Option<String>,
+
+        /// Deploys only: only this deployment
+        #[arg(long)]
+        deployment: Option<String>,
+
+        /// Deploys only: only this status (e.g. "failed")
+        #[arg(long)]
+        status: Option<String>,
+
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Back up or restore the Home database (`modules.storage`)
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
+    },
+
+    /// Join tokens (Home) and enrollment of new agents
+    Agent {
+        #[command(subcommand)]
+        command: AgentCommands,
+    },
+
+    /// Encrypt a secret for use as an `ENC[age:...]` config value
+    EncryptValue {
+        /// age recipient (public key, "age1...")
+        #[arg(short, long, env = "INFRACTL_AGE_RECIPIENT")]
+        recipient: String,
+
+        /// Value to encrypt (read from stdin if omitted)
+        value: Option<String>,
     },
 
     /// Update infractl to the latest version
@@ -116,6 +248,121 @@ pub enum Commands {
         /// Include pre-release versions
         #[arg(long)]
         prerelease: bool,
+
+        /// Update every configured agent through the Home service instead
+        #[arg(long, conflicts_with_all = ["force", "repo", "prerelease"])]
+        all_agents: bool,
+
+        /// With --all-agents: only agents carrying this tag (repeatable, all must match)
+        #[arg(long = "tag", requires = "all_agents")]
+        tag: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportData {
+    Metrics,
+    Deploys,
+}
+
+impl ExportData {
+    /// `/api/{path}/export` of the Home service
+    pub fn path(self) -> &'static str {
+        match self {
+            Self::Metrics => "metrics",
+            Self::Deploys => "deploys",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum DbCommands {
+    /// Copy the database to a timestamped file; safe while the service runs
+    Backup {
+        /// Backup directory (default: `modules.storage.backup.dir`)
+        #[arg(short, long)]
+        dir: Option<PathBuf>,
+    },
+
+    /// Replace the database with a backup (stop the service first)
+    Restore {
+        /// Backup file
+        file: PathBuf,
+
+        /// Do not ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Show applied and pending schema migrations
+    Status,
+
+    /// Apply pending schema migrations, or revert to an older version
+    Migrate {
+        /// Target schema version (default: latest)
+        #[arg(long)]
+        to: Option<i32>,
+
+        /// Print the SQL that would run without changing the database
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Do not ask for confirmation before reverting migrations
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum AgentCommands {
+    /// Issue a single-use join token for a new agent (run on Home)
+    JoinToken {
+        /// Name the agent is enrolled as (default: the agent's hostname)
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// Tags of the enrolled agent (repeatable)
+        #[arg(long = "tag")]
+        tag: Vec<String>,
+
+        /// How long the token can be used (e.g., "1h", "7d")
+        #[arg(short, long, default_value = "24h")]
+        ttl: String,
+    },
+
+    /// Print the JWT secret for a configured agent's `auth.jwt_secret` (run on Home, `auth.agent_keys`)
+    Key {
+        /// Agent name
+        name: String,
+    },
+
+    /// Register this host with Home and write the agent config it returns to --config
+    Enroll {
+        /// Home address (e.g., "10.0.0.1:8111" or "https://home.example.com")
+        #[arg(long)]
+        home: String,
+
+        /// Token from `infractl agent join-token`
+        #[arg(long, env = "INFRACTL_JOIN_TOKEN")]
+        join_token: String,
+
+        /// URL Home reaches this agent at (default: the address Home sees, with --port)
+        #[arg(short, long)]
+        address: Option<String>,
+
+        /// Port this agent will listen on
+        #[arg(short, long, default_value_t = 8111)]
+        port: u16,
+
+        /// Overwrite an existing config file
+        #[arg(short, long)]
+        force: bool,
     },
 }
 
@@ -132,3 +379,7 @@ impl Cli {
         self.log_format.as_deref().unwrap_or("json")
     }
 }
+
+fn parse_scope(scope: &str) -> Result<String, String> {
+    validate_scope(scope).map(|()| scope.to_string())
+}
@@ -4,7 +4,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::net::IpAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tracing::{info, warn};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -16,6 +16,9 @@ pub enum Mode {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Additional config files (paths or globs) merged on top of this file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
     pub mode: Mode,
     #[serde(default = "default_version")]
     pub version: String,
@@ -31,6 +34,15 @@ pub struct Config {
     pub logging: LoggingConfig,
     #[serde(default)]
     pub notifications: NotificationsConfig,
+    /// Outgoing HTTP client settings (Home ↔ Agent, GitHub, Telegram)
+    #[serde(default)]
+    pub http_client: HttpClientConfig,
+    /// Named overlays merged on top of the base settings when selected via `--profile`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub profiles: HashMap<String, serde_json::Value>,
+    /// Profile applied during load (runtime only)
+    #[serde(skip)]
+    pub active_profile: Option<String>,
 }
 
 fn default_version() -> String {
@@ -50,6 +62,172 @@ pub struct ServerConfig {
     /// Home server address for Agent → Home config fetch (e.g. "10.0.0.1:8111")
     #[serde(default)]
     pub home_address: Option<String>,
+    /// Temporary bans for IPs with repeated auth failures or isolation violations
+    #[serde(default)]
+    pub ip_ban: IpBanConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    /// How long shutdown waits for in-flight requests, the running deploy job
+    /// and buffered writes before exiting
+    #[serde(default = "default_drain_timeout")]
+    pub drain_timeout: String,
+    /// Serve HTTPS instead of plain HTTP
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Serve the gRPC API (`proto/infractl.proto`) on the same port
+    #[serde(default)]
+    pub grpc: bool,
+    /// Agent mode: long-poll `home_address` for Home's requests instead of
+    /// waiting for inbound connections (agents behind NAT or CGNAT)
+    #[serde(default)]
+    pub reverse_connect: bool,
+    /// LAN discovery over mDNS: agents advertise `_infractl._tcp`, Home lists
+    /// the ones it does not know at `GET /api/agents/discovered`
+    #[serde(default)]
+    pub mdns: bool,
+}
+
+fn default_drain_timeout() -> String {
+    "30s".to_string()
+}
+
+/// PEM certificate chain and private key, reloaded when either file changes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+    /// How often the files are checked for changes (e.g. after certbot renewal)
+    #[serde(default = "default_tls_reload_interval")]
+    pub reload_interval: String,
+}
+
+fn default_tls_reload_interval() -> String {
+    "1m".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpBanConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Violations within `window` that trigger a ban
+    #[serde(default = "default_ban_max_violations")]
+    pub max_violations: usize,
+    #[serde(default = "default_ban_window")]
+    pub window: String,
+    #[serde(default = "default_ban_duration")]
+    pub ban_duration: String,
+    /// Networks that are never banned
+    #[serde(default = "default_ban_exempt_networks")]
+    pub exempt_networks: Vec<String>,
+}
+
+fn default_ban_max_violations() -> usize {
+    10
+}
+
+fn default_ban_window() -> String {
+    "10m".to_string()
+}
+
+fn default_ban_duration() -> String {
+    "1h".to_string()
+}
+
+fn default_ban_exempt_networks() -> Vec<String> {
+    vec!["127.0.0.1/32".to_string(), "::1/128".to_string()]
+}
+
+impl Default for IpBanConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_violations: default_ban_max_violations(),
+            window: default_ban_window(),
+            ban_duration: default_ban_duration(),
+            exempt_networks: default_ban_exempt_networks(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// Requests allowed per IP within `window`
+    #[serde(default = "default_rate_limit_max_requests")]
+    pub max_requests: usize,
+    #[serde(default = "default_rate_limit_window")]
+    pub window: String,
+    /// Requests an IP may send at once (default: `max_requests`)
+    #[serde(default)]
+    pub burst: Option<usize>,
+    /// Separate limits per route class; unset classes use the limits above
+    #[serde(default)]
+    pub routes: RateLimitRoutes,
+    /// Never limit the addresses in `agents` and `server.home_address`
+    #[serde(default = "default_true")]
+    pub exempt_agents: bool,
+    /// CIDR list of networks that are never limited
+    #[serde(default)]
+    pub exempt_networks: Vec<String>,
+    /// Upper bound on tracked IPs; least recently seen IPs are evicted first
+    #[serde(default = "default_rate_limit_max_tracked_ips")]
+    pub max_tracked_ips: usize,
+    /// How often expired entries are purged
+    #[serde(default = "default_rate_limit_cleanup_interval")]
+    pub cleanup_interval: String,
+}
+
+/// Per-class limits; each class has its own budget per IP
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RateLimitRoutes {
+    /// `/webhook/*`
+    #[serde(default)]
+    pub webhook: Option<RateLimitRule>,
+    /// `/api/*`, `/ws`, `/metrics` and gRPC
+    #[serde(default)]
+    pub api: Option<RateLimitRule>,
+    /// `/health` and `/`
+    #[serde(default)]
+    pub health: Option<RateLimitRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitRule {
+    pub max_requests: usize,
+    #[serde(default = "default_rate_limit_window")]
+    pub window: String,
+    #[serde(default)]
+    pub burst: Option<usize>,
+}
+
+fn default_rate_limit_max_requests() -> usize {
+    100
+}
+
+fn default_rate_limit_window() -> String {
+    "1m".to_string()
+}
+
+fn default_rate_limit_max_tracked_ips() -> usize {
+    10_000
+}
+
+fn default_rate_limit_cleanup_interval() -> String {
+    "1m".to_string()
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_requests: default_rate_limit_max_requests(),
+            window: default_rate_limit_window(),
+            burst: None,
+            routes: RateLimitRoutes::default(),
+            exempt_agents: true,
+            exempt_networks: Vec::new(),
+            max_tracked_ips: default_rate_limit_max_tracked_ips(),
+            cleanup_interval: default_rate_limit_cleanup_interval(),
+        }
+    }
 }
 
 fn default_bind() -> String {
@@ -81,6 +259,63 @@ impl Default for ServerConfig {
             isolation_mode: true,
             allowed_networks: default_allowed_networks(),
             home_address: None,
+            ip_ban: IpBanConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            drain_timeout: default_drain_timeout(),
+            tls: None,
+            grpc: false,
+            reverse_connect: false,
+            mdns: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpClientConfig {
+    #[serde(default = "default_http_connect_timeout")]
+    pub connect_timeout: String,
+    /// Total request timeout
+    #[serde(default = "default_http_timeout")]
+    pub timeout: String,
+    /// Retries for transient failures (connection errors; 5xx/timeouts on idempotent requests)
+    #[serde(default = "default_http_retries")]
+    pub retries: u32,
+    /// Base delay between retries (doubled per attempt, plus jitter)
+    #[serde(default = "default_http_retry_backoff")]
+    pub retry_backoff: String,
+    /// How long idle keep-alive connections are kept in the pool
+    #[serde(default = "default_http_pool_idle_timeout")]
+    pub pool_idle_timeout: String,
+}
+
+fn default_http_connect_timeout() -> String {
+    "5s".to_string()
+}
+
+fn default_http_timeout() -> String {
+    "30s".to_string()
+}
+
+fn default_http_retries() -> u32 {
+    2
+}
+
+fn default_http_retry_backoff() -> String {
+    "500ms".to_string()
+}
+
+fn default_http_pool_idle_timeout() -> String {
+    "90s".to_string()
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: default_http_connect_timeout(),
+            timeout: default_http_timeout(),
+            retries: default_http_retries(),
+            retry_backoff: default_http_retry_backoff(),
+            pool_idle_timeout: default_http_pool_idle_timeout(),
         }
     }
 }
@@ -92,18 +327,140 @@ pub struct AuthConfig {
     pub token_ttl: String,
     #[serde(default)]
     pub webhook_secrets: HashMap<String, String>,
+    /// Lifetime of access tokens issued by `POST /auth/refresh`
+    #[serde(default = "default_access_token_ttl")]
+    pub access_token_ttl: String,
+    /// Lifetime of refresh tokens issued by `infractl token --refresh`
+    #[serde(default = "default_refresh_token_ttl")]
+    pub refresh_token_ttl: String,
+    /// Server-side store of issued refresh tokens (hashes only)
+    #[serde(default = "default_refresh_tokens_file")]
+    pub refresh_tokens_file: String,
+    /// Server-side store of agent join tokens (hashes only, Home mode)
+    #[serde(default = "default_join_tokens_file")]
+    pub join_tokens_file: String,
+    /// Sign tokens for each agent with its own key derived from `jwt_secret`
+    /// (Home mode); agents are given their key instead of the shared secret
+    #[serde(default)]
+    pub agent_keys: bool,
+    /// Key generation of each agent, bumped on rotation (Home mode)
+    #[serde(default = "default_agent_keys_file")]
+    pub agent_keys_file: String,
+    /// Key received from Home on rotation; replaces `jwt_secret` when present (Agent mode)
+    #[serde(default = "default_rotated_key_file")]
+    pub rotated_key_file: String,
+    /// How long the previous key is still accepted after a rotation
+    #[serde(default = "default_key_rotation_overlap")]
+    pub key_rotation_overlap: String,
+    /// Single sign-on for `/monitoring`; the dashboard is served only after login
+    #[serde(default)]
+    pub oidc: Option<OidcConfig>,
+    /// Login page for `/monitoring` (password or token entry)
+    #[serde(default)]
+    pub dashboard: DashboardAuthConfig,
+}
+
+/// Session login for the monitoring dashboard
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DashboardAuthConfig {
+    /// Serve the dashboard only to logged-in sessions
+    #[serde(default = "default_true")]
+    pub require_login: bool,
+    /// Shared password accepted by the login page (a token can always be entered)
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Lifetime of the dashboard session cookie
+    #[serde(default = "default_session_ttl")]
+    pub session_ttl: String,
+}
+
+impl Default for DashboardAuthConfig {
+    fn default() -> Self {
+        Self {
+            require_login: true,
+            password: None,
+            session_ttl: default_session_ttl(),
+        }
+    }
+}
+
+/// OpenID Connect provider (authorization code flow with PKCE)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcConfig {
+    /// Issuer URL; endpoints are discovered from `/.well-known/openid-configuration`
+    pub issuer: String,
+    pub client_id: String,
+    /// Omit for public clients (PKCE only)
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    /// Callback URL registered with the provider, ending in `/auth/oidc/callback`
+    pub redirect_url: String,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+    /// Glob patterns of verified emails allowed in; at least one is required
+    #[serde(default)]
+    pub allowed_emails: Vec<String>,
+    /// Lifetime of the dashboard session cookie
+    #[serde(default = "default_session_ttl")]
+    pub session_ttl: String,
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    vec!["openid".to_string(), "email".to_string()]
+}
+
+fn default_session_ttl() -> String {
+    "8h".to_string()
 }
 
 fn default_token_ttl() -> String {
     "24h".to_string()
 }
 
+fn default_access_token_ttl() -> String {
+    "15m".to_string()
+}
+
+fn default_refresh_token_ttl() -> String {
+    "30d".to_string()
+}
+
+fn default_refresh_tokens_file() -> String {
+    "/var/lib/infractl/refresh_tokens.json".to_string()
+}
+
+fn default_join_tokens_file() -> String {
+    "/var/lib/infractl/join_tokens.json".to_string()
+}
+
+fn default_agent_keys_file() -> String {
+    "/var/lib/infractl/agent_keys.json".to_string()
+}
+
+fn default_rotated_key_file() -> String {
+    "/var/lib/infractl/jwt_key".to_string()
+}
+
+fn default_key_rotation_overlap() -> String {
+    "10m".to_string()
+}
+
 impl Default for AuthConfig {
     fn default() -> Self {
         Self {
             jwt_secret: String::new(),
             token_ttl: default_token_ttl(),
             webhook_secrets: HashMap::new(),
+            access_token_ttl: default_access_token_ttl(),
+            refresh_token_ttl: default_refresh_token_ttl(),
+            refresh_tokens_file: default_refresh_tokens_file(),
+            join_tokens_file: default_join_tokens_file(),
+            agent_keys: false,
+            agent_keys_file: default_agent_keys_file(),
+            rotated_key_file: default_rotated_key_file(),
+            key_rotation_overlap: default_key_rotation_overlap(),
+            oidc: None,
+            dashboard: DashboardAuthConfig::default(),
         }
     }
 }
@@ -150,7 +507,7 @@ fn default_config_check_interval() -> String {
     "1h".to_string()
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AgentConfig {
     pub name: String,
     pub address: String,
@@ -158,6 +515,38 @@ pub struct AgentConfig {
     pub timeout: String,
     #[serde(default = "default_health_interval")]
     pub health_interval: String,
+    /// Labels for selecting agents in fleet deploys, exec and updates
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Health checks missed in a row before the agent counts as offline
+    /// (default: `OFFLINE_AFTER_MISSED`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offline_after: Option<u32>,
+}
+
+impl AgentConfig {
+    /// Agent with the default timeout and health interval
+    pub fn new(name: &str, address: &str, tags: Vec<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            address: address.to_string(),
+            timeout: default_timeout(),
+            health_interval: default_health_interval(),
+            tags,
+            offline_after: None,
+        }
+    }
+
+    /// Whether the agent carries every tag of a `web,edge` selector (no selector matches all)
+    pub fn matches_tags(&self, selector: Option<&str>) -> bool {
+        selector.is_none_or(|selector| {
+            selector
+                .split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .all(|tag| self.tags.iter().any(|t| t == tag))
+        })
+    }
 }
 
 fn default_timeout() -> String {
@@ -178,6 +567,40 @@ pub struct ModulesConfig {
     pub deploy: DeployConfig,
     #[serde(default)]
     pub webhooks: WebhooksConfig,
+    #[serde(default)]
+    pub exec: ExecConfig,
+}
+
+/// Commands Home may run on this agent via `POST /api/exec/:command`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecConfig {
+    /// Allowlisted commands by name; nothing else can be run remotely
+    #[serde(default)]
+    pub commands: HashMap<String, CommandEntry>,
+    /// Agent: limit for each command. Home: how long to wait for each agent.
+    #[serde(default = "default_exec_timeout")]
+    pub timeout: String,
+}
+
+/// Names that end up in URLs, file names and token scopes
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+fn default_exec_timeout() -> String {
+    "5m".to_string()
+}
+
+impl Default for ExecConfig {
+    fn default() -> Self {
+        Self {
+            commands: HashMap::new(),
+            timeout: default_exec_timeout(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -192,12 +615,76 @@ pub struct MetricsConfig {
     pub docker_socket: Option<String>,
     #[serde(default = "default_true")]
     pub compose_projects: bool,
+    /// Agent mode: push metrics to `server.home_address` every `collect_interval`
+    #[serde(default = "default_true")]
+    pub push: bool,
+    /// Name the agent pushes under; must match its `agents[].name` on Home.
+    /// Defaults to the hostname.
+    #[serde(default)]
+    pub agent_name: Option<String>,
+    /// Agent mode: keep reports that fail to push and send them later
+    #[serde(default)]
+    pub buffer: MetricsBufferConfig,
+    /// Include this many processes by CPU and by memory in system metrics
+    /// (0 = off)
+    #[serde(default)]
+    pub top_processes: usize,
+    /// Commands whose output (a number or JSON) is reported as a metric
+    #[serde(default)]
+    pub custom: Vec<CustomMetricConfig>,
+}
+
+/// An app-specific gauge (`modules.metrics.custom[]`), e.g. a queue depth
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomMetricConfig {
+    pub name: String,
+    /// Run with `sh -c`; stdout must be a number or JSON
+    pub command: String,
+    /// Defaults to `collect_interval`
+    #[serde(default)]
+    pub interval: Option<String>,
+    #[serde(default = "default_custom_metric_timeout")]
+    pub timeout: String,
+}
+
+fn default_custom_metric_timeout() -> String {
+    "10s".to_string()
 }
 
 fn default_collect_interval() -> String {
     "30s".to_string()
 }
 
+/// Local store for metrics reports Home did not accept (agent mode)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsBufferConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_buffer_path")]
+    pub path: String,
+    /// Reports kept at most; the oldest are dropped beyond this
+    #[serde(default = "default_buffer_max_reports")]
+    pub max_reports: usize,
+}
+
+fn default_buffer_path() -> String {
+    "/var/lib/infractl/metrics-buffer.db".to_string()
+}
+
+fn default_buffer_max_reports() -> usize {
+    2880
+}
+
+impl Default for MetricsBufferConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: default_buffer_path(),
+            max_reports: default_buffer_max_reports(),
+        }
+    }
+}
+
 impl Default for MetricsConfig {
     fn default() -> Self {
         Self {
@@ -206,6 +693,11 @@ impl Default for MetricsConfig {
             docker_stats: true,
             docker_socket: None,
             compose_projects: true,
+            push: true,
+            agent_name: None,
+            buffer: MetricsBufferConfig::default(),
+            top_processes: 0,
+            custom: Vec::new(),
         }
     }
 }
@@ -220,6 +712,105 @@ pub struct StorageConfig {
     pub retention: RetentionConfig,
     #[serde(default)]
     pub aggregation: AggregationConfig,
+    #[serde(default)]
+    pub write_buffer: WriteBufferConfig,
+    /// Read-only connections for queries, next to the single writer
+    #[serde(default = "default_read_connections")]
+    pub read_connections: usize,
+    #[serde(default)]
+    pub backup: BackupConfig,
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    /// Apply pending schema migrations at startup; when off the server
+    /// refuses to start until `infractl db migrate` has run
+    #[serde(default = "default_true")]
+    pub auto_migrate: bool,
+    /// SQLCipher key the database is encrypted with (supports `${VAR}`,
+    /// `file://` and `ENC[age:...]`); needs a build with the `sqlcipher` feature
+    #[serde(default)]
+    pub encryption_key: Option<String>,
+    /// How raw metrics are split into tables
+    #[serde(default)]
+    pub partitioning: MetricsPartitioning,
+}
+
+/// Table layout of raw metrics (`modules.storage.partitioning`)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricsPartitioning {
+    /// Everything in `metrics_raw`
+    #[default]
+    None,
+    /// One `metrics_raw_YYYYMM` table per month; retention drops whole months
+    Monthly,
+}
+
+impl StorageConfig {
+    /// Where backups are written: `backup.dir`, or `backups/` next to the database
+    pub fn backup_dir(&self) -> PathBuf {
+        match &self.backup.dir {
+            Some(dir) => PathBuf::from(dir),
+            None => Path::new(&self.db_path)
+                .parent()
+                .unwrap_or(Path::new("."))
+                .join("backups"),
+        }
+    }
+}
+
+/// Copies of the database made with SQLite's online backup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupConfig {
+    /// Backup directory (default: `backups/` next to `db_path`)
+    #[serde(default)]
+    pub dir: Option<String>,
+    /// Newest backups kept; older ones are deleted after each backup
+    #[serde(default = "default_backup_keep")]
+    pub keep: usize,
+    /// Back up on this interval while the server runs (e.g. "24h"); off if unset
+    #[serde(default)]
+    pub interval: Option<String>,
+}
+
+/// Incremental vacuum, ANALYZE and WAL checkpoint on a cron schedule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Five-field cron expression in UTC
+    #[serde(default = "default_maintenance_schedule")]
+    pub schedule: String,
+}
+
+fn default_maintenance_schedule() -> String {
+    "30 3 * * *".to_string()
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            schedule: default_maintenance_schedule(),
+        }
+    }
+}
+
+fn default_backup_keep() -> usize {
+    7
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            keep: default_backup_keep(),
+            interval: None,
+        }
+    }
+}
+
+fn default_read_connections() -> usize {
+    crate::storage::DEFAULT_READ_CONNECTIONS
 }
 
 fn default_db_path() -> String {
@@ -233,6 +824,47 @@ impl Default for StorageConfig {
             db_path: default_db_path(),
             retention: RetentionConfig::default(),
             aggregation: AggregationConfig::default(),
+            write_buffer: WriteBufferConfig::default(),
+            read_connections: default_read_connections(),
+            backup: BackupConfig::default(),
+            maintenance: MaintenanceConfig::default(),
+            auto_migrate: true,
+            encryption_key: None,
+            partitioning: MetricsPartitioning::None,
+        }
+    }
+}
+
+/// Buffered writer for deploy history and metrics (flushed on size or interval)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WriteBufferConfig {
+    #[serde(default = "default_write_batch_size")]
+    pub batch_size: usize,
+    #[serde(default = "default_write_flush_interval")]
+    pub flush_interval: String,
+    /// Writes queued before senders have to wait for the writer
+    #[serde(default = "default_write_queue_capacity")]
+    pub queue_capacity: usize,
+}
+
+fn default_write_batch_size() -> usize {
+    100
+}
+
+fn default_write_queue_capacity() -> usize {
+    10_000
+}
+
+fn default_write_flush_interval() -> String {
+    "1s".to_string()
+}
+
+impl Default for WriteBufferConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: default_write_batch_size(),
+            flush_interval: default_write_flush_interval(),
+            queue_capacity: default_write_queue_capacity(),
         }
     }
 }
@@ -245,6 +877,12 @@ pub struct RetentionConfig {
     pub hourly_data: String,
     #[serde(default = "default_daily_retention")]
     pub daily_data: String,
+    /// Requests rejected by auth, network isolation or rate limiting
+    #[serde(default = "default_suspicious_retention")]
+    pub suspicious_requests: String,
+    /// Deploy history rows, deleted together with their log files
+    #[serde(default = "default_deploy_history_retention")]
+    pub deploy_history: String,
 }
 
 fn default_raw_retention() -> String {
@@ -259,12 +897,22 @@ fn default_daily_retention() -> String {
     "365d".to_string()
 }
 
+fn default_suspicious_retention() -> String {
+    "30d".to_string()
+}
+
+fn default_deploy_history_retention() -> String {
+    "90d".to_string()
+}
+
 impl Default for RetentionConfig {
     fn default() -> Self {
         Self {
             raw_data: default_raw_retention(),
             hourly_data: default_hourly_retention(),
             daily_data: default_daily_retention(),
+            suspicious_requests: default_suspicious_retention(),
+            deploy_history: default_deploy_history_retention(),
         }
     }
 }
@@ -316,17 +964,126 @@ pub struct DeployConfig {
     /// Additional allowed deploy paths (beyond built-in defaults)
     #[serde(default)]
     pub allowed_deploy_paths: Vec<String>,
+    /// Named partial deployments that deployments can inherit via `extends:`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub deployment_templates: HashMap<String, serde_json::Value>,
+    /// Container engine for docker_pull/docker_run and Docker metrics
+    #[serde(default)]
+    pub container_runtime: ContainerRuntime,
+    /// Variables for `{{ var }}` interpolation in deployment fields
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub vars: HashMap<String, String>,
+    /// Per-job output log files (deploy history keeps only the tail)
+    #[serde(default)]
+    pub logs: DeployLogsConfig,
+    /// Jobs run in parallel; jobs of one concurrency group never overlap
+    #[serde(default = "default_deploy_workers")]
+    pub workers: usize,
 }
 
-fn default_external_deployments_path() -> Option<String> {
-    Some("/etc/infractl".to_string())
+fn default_deploy_workers() -> usize {
+    1
 }
 
-fn default_work_dir() -> String {
-    "/opt/apps".to_string()
+/// Deploy output is written to `<dir>/<job_id>.log`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeployLogsConfig {
+    /// Log directory (default: `<work_dir>/logs`)
+    #[serde(default)]
+    pub dir: Option<String>,
+    /// Bytes of output kept in deploy history
+    #[serde(default = "default_log_tail_bytes")]
+    pub tail_bytes: usize,
+    /// Larger output keeps only its last `max_bytes` in the log file
+    #[serde(default = "default_log_max_bytes")]
+    pub max_bytes: usize,
+    /// Log files older than this are deleted
+    #[serde(default = "default_log_retention")]
+    pub retention: String,
 }
 
-fn default_deploy_timeout() -> String {
+fn default_log_tail_bytes() -> usize {
+    16 * 1024
+}
+
+fn default_log_max_bytes() -> usize {
+    50 * 1024 * 1024
+}
+
+fn default_log_retention() -> String {
+    "90d".to_string()
+}
+
+impl Default for DeployLogsConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            tail_bytes: default_log_tail_bytes(),
+            max_bytes: default_log_max_bytes(),
+            retention: default_log_retention(),
+        }
+    }
+}
+
+impl DeployConfig {
+    pub fn log_dir(&self) -> PathBuf {
+        match self.logs.dir {
+            Some(ref dir) => PathBuf::from(dir),
+            None => Path::new(&self.work_dir).join("logs"),
+        }
+    }
+}
+
+/// Container engine: `auto` picks Docker when its socket exists, otherwise Podman if installed
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerRuntime {
+    #[default]
+    Auto,
+    Docker,
+    Podman,
+}
+
+impl ContainerRuntime {
+    /// Resolve `auto` to the engine available on this host
+    pub fn resolve(self) -> Self {
+        match self {
+            ContainerRuntime::Auto => {
+                let docker_socket = std::env::var_os("DOCKER_HOST").is_some()
+                    || Path::new("/var/run/docker.sock").exists();
+                if !docker_socket && podman_installed() {
+                    ContainerRuntime::Podman
+                } else {
+                    ContainerRuntime::Docker
+                }
+            }
+            runtime => runtime,
+        }
+    }
+
+    /// CLI binary (`docker compose` / `podman compose`)
+    pub fn command(self) -> &'static str {
+        match self.resolve() {
+            ContainerRuntime::Podman => "podman",
+            _ => "docker",
+        }
+    }
+}
+
+fn podman_installed() -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join("podman").is_file()))
+}
+
+fn default_external_deployments_path() -> Option<String> {
+    Some("/etc/infractl".to_string())
+}
+
+fn default_work_dir() -> String {
+    "/opt/apps".to_string()
+}
+
+fn default_deploy_timeout() -> String {
     "300s".to_string()
 }
 
@@ -342,6 +1099,9 @@ pub struct DeploymentConfig {
     pub repo: Option<String>,
     #[serde(default)]
     pub branch: Option<String>,
+    /// git_pull: tag or commit to check out instead of the branch tip
+    #[serde(default, rename = "ref")]
+    pub git_ref: Option<String>,
     #[serde(default)]
     pub remote: Option<String>,
     #[serde(default)]
@@ -356,6 +1116,21 @@ pub struct DeploymentConfig {
     pub working_dir: Option<String>,
     #[serde(default)]
     pub user: Option<String>,
+    /// Scheduling priority of scripts and pre/post/shutdown commands (`nice -n`, -20..19)
+    #[serde(default)]
+    pub nice: Option<i32>,
+    /// CPU quota of scripts and commands, e.g. "50%" (one core = 100%)
+    #[serde(default)]
+    pub cpu_limit: Option<String>,
+    /// Memory cap of scripts and commands, e.g. "512M" or "2G"
+    #[serde(default)]
+    pub memory_limit: Option<String>,
+    /// Command validation: strict (default) or permissive
+    #[serde(default)]
+    pub shell_policy: ShellPolicy,
+    /// Glob patterns of commands allowed under the strict policy despite shell metacharacters
+    #[serde(default)]
+    pub shell_allow: Vec<String>,
     #[serde(default)]
     pub env: HashMap<String, String>,
     #[serde(default)]
@@ -367,8 +1142,18 @@ pub struct DeploymentConfig {
     pub shutdown: CommandList,
     #[serde(default)]
     pub timeout: Option<String>,
+    /// docker_pull: prune old images after pull; git_pull: prune deleted remote refs on fetch
     #[serde(default)]
     pub prune: bool,
+    /// git_pull: history depth for clone/fetch (default 1, 0 = full history)
+    #[serde(default)]
+    pub depth: Option<u32>,
+    /// git_pull: fetch all tags from the remote
+    #[serde(default)]
+    pub fetch_tags: bool,
+    /// git_pull: check out submodules (recursively) after clone/reset
+    #[serde(default)]
+    pub submodules: bool,
     /// Files to fetch from git: ["from:to", "dir/:dir/"]
     #[serde(default, alias = "git_compose_files")]
     pub git_files: Vec<String>,
@@ -384,28 +1169,108 @@ pub struct DeploymentConfig {
     /// Continue pipeline if triggered deployment fails
     #[serde(default)]
     pub continue_on_failure: bool,
-    /// Docker deploy strategy (default, force_recreate, restart)
+    /// Deployments that must have succeeded recently before this one runs
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// How recent a dependency's last success must be (default: 24h)
+    #[serde(default)]
+    pub depends_max_age: Option<String>,
+    /// Deployments sharing a group never run at the same time (default: own name)
+    #[serde(default)]
+    pub concurrency_group: Option<String>,
+    /// Docker deploy strategy (default, force_recreate, restart, canary)
     #[serde(default)]
     pub strategy: Option<DeployStrategy>,
+    /// Canary settings (required for strategy: canary)
+    #[serde(default)]
+    pub canary: Option<CanaryConfig>,
+    /// Re-run failed deployments with exponential backoff
+    #[serde(default)]
+    pub retry: Option<RetryConfig>,
     /// Deployment category: app (default) or system
     #[serde(default)]
     pub category: DeployCategory,
+    /// Queue priority: higher priority jobs run before queued lower ones
+    #[serde(default)]
+    pub priority: DeployPriority,
+    /// Cron expression (UTC) for scheduled runs, e.g. "0 3 * * *"
+    #[serde(default)]
+    pub schedule: Option<String>,
     /// Telegram notification config (required for type: telegram)
     #[serde(default)]
     pub telegram: Option<TelegramConfig>,
+    /// Helm release config (required for type: helm)
+    #[serde(default)]
+    pub helm: Option<HelmConfig>,
+    /// Container config (required for type: docker_run)
+    #[serde(default)]
+    pub container: Option<ContainerConfig>,
+    /// GitHub release asset (required for type: release_artifact)
+    #[serde(default)]
+    pub release: Option<ReleaseArtifactConfig>,
+    /// S3-compatible object (required for type: s3_artifact)
+    #[serde(default)]
+    pub s3: Option<S3ArtifactConfig>,
+    /// Private registry login used before pulling images (docker_pull, docker_run)
+    #[serde(default)]
+    pub registry_auth: Option<RegistryAuth>,
+    /// docker_pull: expected digest per compose image (`image: sha256:...`), checked after pull
+    #[serde(default)]
+    pub image_digests: HashMap<String, String>,
+    /// Env vars resolved from the secret store at execute time (`ENV_NAME: secret-name`),
+    /// redacted from deploy output and history
+    #[serde(default)]
+    pub secrets: HashMap<String, String>,
+    /// Variables for `{{ var }}` interpolation, overriding `modules.deploy.vars`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub vars: HashMap<String, String>,
+    /// Jobs wait in `awaiting_approval` until approved via `/webhook/approve/:job_id`
+    #[serde(default)]
+    pub requires_approval: bool,
+    /// Token subjects allowed to approve jobs of this deployment
+    #[serde(default)]
+    pub approvers: Vec<String>,
     /// Runtime-only: bypass path validation (set by CLI --force, never from config)
     #[serde(skip)]
     pub force: bool,
 }
 
-/// Command list: accepts a string (multiline split by \n) or array of strings
+/// Command list: accepts a string (multiline split by \n) or array of commands
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum CommandList {
     #[default]
     None,
     Single(String),
-    Multiple(Vec<String>),
+    Multiple(Vec<CommandEntry>),
+}
+
+/// Command list entry: a command string or `{ cmd, user }` to run it as another user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CommandEntry {
+    Plain(String),
+    WithUser {
+        cmd: String,
+        #[serde(default)]
+        user: Option<String>,
+    },
+}
+
+impl CommandEntry {
+    pub fn cmd(&self) -> &str {
+        match self {
+            CommandEntry::Plain(cmd) | CommandEntry::WithUser { cmd, .. } => cmd,
+        }
+    }
+
+    /// User for this command, overriding the deployment's `user`
+    pub fn user(&self) -> Option<&str> {
+        match self {
+            CommandEntry::Plain(_) => None,
+            CommandEntry::WithUser { user, .. } => user.as_deref(),
+        }
+    }
 }
 
 impl CommandList {
@@ -417,11 +1282,21 @@ impl CommandList {
         }
     }
 
+    #[allow(dead_code)]
     pub fn as_vec(&self) -> Vec<&str> {
+        self.entries().into_iter().map(|(cmd, _)| cmd).collect()
+    }
+
+    /// Commands with their per-command user (None = the deployment's `user`)
+    pub fn entries(&self) -> Vec<(&str, Option<&str>)> {
         match self {
             CommandList::None => vec![],
-            CommandList::Single(s) => s.lines().filter(|l| !l.trim().is_empty()).collect(),
-            CommandList::Multiple(v) => v.iter().map(|s| s.as_str()).collect(),
+            CommandList::Single(s) => s
+                .lines()
+                .filter(|l| !l.trim().is_empty())
+                .map(|l| (l, None))
+                .collect(),
+            CommandList::Multiple(v) => v.iter().map(|e| (e.cmd(), e.user())).collect(),
         }
     }
 }
@@ -466,6 +1341,10 @@ pub enum DeployType {
     DockerPull,
     CustomScript,
     Telegram,
+    Helm,
+    DockerRun,
+    ReleaseArtifact,
+    S3Artifact,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
@@ -477,6 +1356,28 @@ pub enum DeployCategory {
     Protected,
 }
 
+/// Deploy queue priority (ordered lowest to highest)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DeployPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Urgent,
+}
+
+/// How pre/post/shutdown commands and inline scripts are checked before running
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ShellPolicy {
+    /// Reject pipes, chaining, command substitution (unless listed in `shell_allow`)
+    #[default]
+    Strict,
+    /// Run commands as written
+    Permissive,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TelegramConfig {
     pub bot_token: String,
@@ -489,6 +1390,115 @@ pub struct TelegramConfig {
     pub silent: Option<bool>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HelmConfig {
+    /// Chart reference: `repo/chart`, OCI URL or local directory (relative to path)
+    pub chart: String,
+    /// Release name
+    pub release: String,
+    #[serde(default)]
+    pub namespace: Option<String>,
+    /// Values files (relative to path)
+    #[serde(default)]
+    pub values: Vec<String>,
+    /// Chart version (default: latest)
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub kubeconfig: Option<String>,
+    /// Wait until resources are ready before reporting success
+    #[serde(default = "default_true")]
+    pub wait: bool,
+    /// Roll back automatically if the upgrade fails
+    #[serde(default)]
+    pub atomic: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReleaseArtifactConfig {
+    /// GitHub repository, `owner/name`
+    pub repo: String,
+    /// Release tag (default: latest release)
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Asset file name; `.tar.gz`/`.tgz` assets are unpacked into `path`
+    pub asset: String,
+    /// Expected SHA256 of the asset (default: taken from the release's SHA256SUMS/checksums.txt)
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Consider prereleases when resolving the latest release
+    #[serde(default)]
+    pub prerelease: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct S3ArtifactConfig {
+    /// Endpoint URL, e.g. `https://minio.internal:9000` (objects are addressed path-style)
+    pub endpoint: String,
+    pub bucket: String,
+    /// Object key; `.tar.gz`/`.tgz` objects are unpacked into `path`
+    pub key: String,
+    #[serde(default = "default_s3_region")]
+    pub region: String,
+    /// Expected SHA256 of the object (default: read from the `<key>.sha256` object)
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Env var holding the access key (deployment `env`/`secrets` first, then the process env)
+    #[serde(default = "default_s3_access_key_env")]
+    pub access_key_env: String,
+    /// Env var holding the secret key
+    #[serde(default = "default_s3_secret_key_env")]
+    pub secret_key_env: String,
+}
+
+fn default_s3_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_s3_access_key_env() -> String {
+    "AWS_ACCESS_KEY_ID".to_string()
+}
+
+fn default_s3_secret_key_env() -> String {
+    "AWS_SECRET_ACCESS_KEY".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerConfig {
+    /// Container name (default: deployment name)
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Image reference, e.g. `nginx:1.27`
+    pub image: String,
+    /// Published ports: `[ip:]host:container[/proto]`
+    #[serde(default)]
+    pub ports: Vec<String>,
+    /// Bind mounts or named volumes: `source:target[:ro]` (relative sources resolve against path)
+    #[serde(default)]
+    pub volumes: Vec<String>,
+    /// Restart policy: no, always, unless-stopped, on-failure
+    #[serde(default = "default_restart_policy")]
+    pub restart: String,
+    #[serde(default)]
+    pub network: Option<String>,
+    /// Override the image command
+    #[serde(default)]
+    pub command: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryAuth {
+    /// Registry host, e.g. `ghcr.io` or `harbor.example.com`
+    pub registry: String,
+    pub username: String,
+    /// Password or access token (supports `${VAR}`, `file://` and `ENC[age:...]`)
+    pub password: String,
+}
+
+fn default_restart_policy() -> String {
+    "unless-stopped".to_string()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct PipelineConfig {
     #[serde(default)]
@@ -508,14 +1518,95 @@ pub enum DeployStrategy {
     ForceRecreate,
     /// docker compose restart (restart process in existing container, fastest)
     Restart,
+    /// Update `canary.services` first, watch their container stats for the
+    /// observation window, then promote to all services or roll back
+    Canary,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CanaryConfig {
+    /// Compose services updated first
+    pub services: Vec<String>,
+    /// How long the canary must stay healthy before promotion
+    #[serde(default = "default_canary_observation")]
+    pub observation: String,
+    /// How often container stats are sampled during observation
+    #[serde(default = "default_canary_interval")]
+    pub interval: String,
+    /// Roll back if a canary container exceeds this CPU usage
+    #[serde(default = "default_canary_threshold")]
+    pub max_cpu_percent: f64,
+    /// Roll back if a canary container exceeds this memory usage
+    #[serde(default = "default_canary_threshold")]
+    pub max_memory_percent: f64,
+}
+
+fn default_canary_observation() -> String {
+    "2m".to_string()
+}
+
+fn default_canary_interval() -> String {
+    "10s".to_string()
+}
+
+fn default_canary_threshold() -> f64 {
+    90.0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryConfig {
+    /// Total attempts, including the first run
+    #[serde(default = "default_retry_attempts")]
+    pub attempts: u32,
+    /// Delay before the first retry, doubled for every further attempt
+    #[serde(default = "default_retry_backoff")]
+    pub backoff: String,
+}
+
+fn default_retry_attempts() -> u32 {
+    3
+}
+
+fn default_retry_backoff() -> String {
+    "30s".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebhooksConfig {
     #[serde(default = "default_true")]
     pub enabled: bool,
     #[serde(default)]
     pub endpoints: Vec<WebhookEndpoint>,
+    /// Maximum clock skew accepted for `X-Webhook-Timestamp`
+    #[serde(default = "default_timestamp_tolerance")]
+    pub timestamp_tolerance: String,
+    /// Only accept signatures that cover a timestamp (`X-Webhook-Signature`);
+    /// turn off for GitHub and GitLab webhooks
+    #[serde(default = "default_true")]
+    pub require_timestamp: bool,
+    /// How long accepted signatures are remembered to reject replays
+    #[serde(default = "default_replay_window")]
+    pub replay_window: String,
+}
+
+fn default_timestamp_tolerance() -> String {
+    "5m".to_string()
+}
+
+fn default_replay_window() -> String {
+    "24h".to_string()
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            endpoints: Vec::new(),
+            timestamp_tolerance: default_timestamp_tolerance(),
+            require_timestamp: true,
+            replay_window: default_replay_window(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -531,13 +1622,34 @@ pub struct WebhookEndpoint {
     pub allowed_ips: Vec<String>,
     #[serde(default)]
     pub schedule_constraint: Option<ScheduleConstraint>,
+    /// Only deploy pushes to branches matching one of these globs
+    #[serde(default)]
+    pub branch_filter: Vec<String>,
+    /// Only deploy pushes that change a file matching one of these globs
+    #[serde(default)]
+    pub path_filter: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScheduleConstraint {
     pub allowed_hours: Vec<u8>,
+    /// IANA time zone name (read from the system tzdata) or POSIX TZ string
     #[serde(default = "default_timezone")]
     pub timezone: String,
+    /// What to do with a request outside `allowed_hours`
+    #[serde(default)]
+    pub outside_hours: OutsideHours,
+}
+
+/// Handling of webhook deploys outside the allowed hours
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutsideHours {
+    /// Refuse the request
+    #[default]
+    Reject,
+    /// Queue the job, released when the next allowed hour starts
+    Defer,
 }
 
 fn default_timezone() -> String {
@@ -600,6 +1712,9 @@ pub struct NotificationsConfig {
     pub enabled: bool,
     #[serde(default)]
     pub on_deploy: OnDeployNotify,
+    /// Agents going offline and recovering (Home mode)
+    #[serde(default)]
+    pub on_agent: OnAgentNotify,
     #[serde(default)]
     pub channels: Vec<NotificationChannel>,
 }
@@ -612,6 +1727,16 @@ pub struct OnDeployNotify {
     pub failure: bool,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct OnAgentNotify {
+    /// An agent missed `offline_after` health checks in a row
+    #[serde(default)]
+    pub offline: bool,
+    /// An offline agent answered again
+    #[serde(default)]
+    pub recovered: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NotificationChannel {
     #[serde(rename = "type")]
@@ -626,65 +1751,671 @@ pub struct NotificationChannel {
     pub headers: HashMap<String, String>,
 }
 
+/// Supported configuration file formats (selected by file extension)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl Config {
+    /// IP addresses of the fleet: `agents[].address` and `server.home_address`.
+    /// Hostnames are skipped.
+    pub fn fleet_ips(&self) -> Vec<IpAddr> {
+        self.agents
+            .iter()
+            .map(|agent| agent.address.as_str())
+            .chain(self.server.home_address.as_deref())
+            .filter_map(address_ip)
+            .collect()
+    }
+}
+
+/// IP of an address like `10.0.0.2:8111` or `http://[fd00::2]:8111`
+fn address_ip(address: &str) -> Option<IpAddr> {
+    let url = if address.contains("://") {
+        reqwest::Url::parse(address)
+    } else {
+        reqwest::Url::parse(&format!("http://{}", address))
+    }
+    .ok()?;
+    let host = url.host_str()?;
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
+
+impl ConfigFormat {
+    /// Detect format from file extension (defaults to YAML)
+    pub fn from_path(path: &Path) -> Self {
+        match path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("toml") => ConfigFormat::Toml,
+            Some("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Yaml,
+        }
+    }
+
+    /// Whether the path has an extension of a supported config format
+    fn is_config_file(path: &Path) -> bool {
+        path.extension().and_then(|e| e.to_str()).is_some_and(|e| {
+            ["yaml", "yml", "toml", "json"]
+                .iter()
+                .any(|ext| e.eq_ignore_ascii_case(ext))
+        })
+    }
+}
+
+/// Deserialize config content in the given format
+fn parse_content<T: serde::de::DeserializeOwned>(
+    content: &str,
+    format: ConfigFormat,
+    path: &Path,
+) -> Result<T> {
+    match format {
+        ConfigFormat::Yaml => serde_yaml::from_str(content)
+            .map_err(|e| InfraError::Config(format!("Failed to parse {}: {}", path.display(), e))),
+        ConfigFormat::Toml => toml::from_str(content)
+            .map_err(|e| InfraError::Config(format!("Failed to parse {}: {}", path.display(), e))),
+        ConfigFormat::Json => serde_json::from_str(content)
+            .map_err(|e| InfraError::Config(format!("Failed to parse {}: {}", path.display(), e))),
+    }
+}
+
+/// Deserialize config content, recording keys that don't map to any config field
+fn parse_typed<T: serde::de::DeserializeOwned>(
+    content: &str,
+    format: ConfigFormat,
+    path: &Path,
+    unknown: &mut Vec<String>,
+) -> Result<T> {
+    let mut track = |p: serde_ignored::Path| unknown.push(p.to_string());
+    let parse_err = |e: &dyn std::fmt::Display| {
+        InfraError::Config(format!("Failed to parse {}: {}", path.display(), e))
+    };
+
+    match format {
+        ConfigFormat::Yaml => {
+            serde_ignored::deserialize(serde_yaml::Deserializer::from_str(content), &mut track)
+                .map_err(|e| parse_err(&e))
+        }
+        ConfigFormat::Toml => {
+            serde_ignored::deserialize(toml::Deserializer::new(content), &mut track)
+                .map_err(|e| parse_err(&e))
+        }
+        ConfigFormat::Json => {
+            let mut de = serde_json::Deserializer::from_str(content);
+            serde_ignored::deserialize(&mut de, &mut track).map_err(|e| parse_err(&e))
+        }
+    }
+}
+
+/// Report unknown keys: an error in strict mode, a warning otherwise
+fn check_unknown_keys(unknown: &[String], source: &Path, strict: bool) -> Result<()> {
+    if unknown.is_empty() {
+        return Ok(());
+    }
+
+    if strict {
+        return Err(InfraError::Config(format!(
+            "Unknown configuration keys in {}: {}",
+            source.display(),
+            unknown.join(", ")
+        )));
+    }
+
+    warn!(
+        file = %source.display(),
+        keys = ?unknown,
+        "Ignoring unknown configuration keys (use --strict-config to reject)"
+    );
+    Ok(())
+}
+
+/// Process-wide strict mode for runtime config loads (set once from the CLI)
+static STRICT_MODE: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Enable or disable rejection of unknown keys for subsequent `load` calls
+pub fn set_strict_mode(strict: bool) {
+    STRICT_MODE.store(strict, std::sync::atomic::Ordering::Relaxed);
+}
+
+fn strict_mode() -> bool {
+    STRICT_MODE.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Process-wide profile for runtime config loads (set once from the CLI)
+static PROFILE: std::sync::RwLock<Option<String>> = std::sync::RwLock::new(None);
+
+/// Select the profile applied by subsequent `load` calls
+pub fn set_profile(profile: Option<String>) {
+    *PROFILE.write().unwrap_or_else(|e| e.into_inner()) = profile;
+}
+
+fn active_profile() -> Option<String> {
+    PROFILE.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// TOML has no top-level arrays, so external TOML files use `[[deployments]]`
+#[derive(Deserialize)]
+struct TomlDeployments {
+    #[serde(default)]
+    deployments: Vec<DeploymentConfig>,
+}
+
+/// Read and parse a single external deployments file
+fn load_deployments_file(
+    path: &Path,
+    strict: bool,
+    templates: &HashMap<String, serde_json::Value>,
+    vars: &HashMap<String, String>,
+) -> Result<Vec<DeploymentConfig>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| InfraError::Config(format!("Failed to read {}: {}", path.display(), e)))?;
+    let content = substitute_env_vars(&content)?;
+
+    let format = ConfigFormat::from_path(path);
+    let mut unknown = Vec::new();
+
+    // Deployments using `extends:` are expanded on the value tree before typed parsing
+    let mut raw: Option<serde_json::Value> = parse_content(&content, format, path).ok();
+    let list = raw.as_mut().and_then(|v| match format {
+        ConfigFormat::Toml => v.get_mut("deployments").and_then(|d| d.as_array_mut()),
+        _ => v.as_array_mut(),
+    });
+
+    let deployments = match list {
+        Some(list) if list.iter().any(|d| d.get("extends").is_some()) => {
+            expand_deployment_templates(list, templates)?;
+            let value = serde_json::Value::Array(std::mem::take(list));
+            serde_ignored::deserialize(value, |p| unknown.push(p.to_string())).map_err(|e| {
+                InfraError::Config(format!("Failed to parse {}: {}", path.display(), e))
+            })?
+        }
+        _ => match format {
+            ConfigFormat::Toml => {
+                parse_typed::<TomlDeployments>(&content, format, path, &mut unknown)?.deployments
+            }
+            _ => parse_typed::<Vec<DeploymentConfig>>(&content, format, path, &mut unknown)?,
+        },
+    };
+    check_unknown_keys(&unknown, path, strict)?;
+    let deployments = interpolate_deployment_vars(deployments, vars)
+        .map_err(|e| InfraError::Config(format!("{}: {}", path.display(), e)))?;
+
+    info!(file = %path.display(), count = deployments.len(), "Loaded external deployments");
+    Ok(deployments)
+}
+
 /// Load external deployments from a directory
-/// Looks for: {path}/deployments.yaml and {path}/deployments.d/*.yaml
-fn load_external_deployments(base_path: &str) -> Result<Vec<DeploymentConfig>> {
+/// Looks for: {path}/deployments.{yaml,yml,toml,json} and {path}/deployments.d/*.{yaml,yml,toml,json}
+fn load_external_deployments(
+    base_path: &str,
+    strict: bool,
+    templates: &HashMap<String, serde_json::Value>,
+    vars: &HashMap<String, String>,
+) -> Result<Vec<DeploymentConfig>> {
     let mut deployments = Vec::new();
     let base = Path::new(base_path);
 
-    // Load single file: deployments.yaml
-    let single_file = base.join("deployments.yaml");
-    if single_file.exists() {
-        let content = fs::read_to_string(&single_file).map_err(|e| {
-            InfraError::Config(format!("Failed to read {}: {}", single_file.display(), e))
-        })?;
-        let content = substitute_env_vars(&content)?;
-        let external: Vec<DeploymentConfig> = serde_yaml::from_str(&content).map_err(|e| {
-            InfraError::Config(format!("Failed to parse {}: {}", single_file.display(), e))
-        })?;
-        info!(file = %single_file.display(), count = external.len(), "Loaded external deployments");
-        deployments.extend(external);
+    // Load single file: deployments.yaml (or .yml/.toml/.json)
+    for ext in ["yaml", "yml", "toml", "json"] {
+        let single_file = base.join(format!("deployments.{}", ext));
+        if single_file.exists() {
+            deployments.extend(load_deployments_file(
+                &single_file,
+                strict,
+                templates,
+                vars,
+            )?);
+        }
     }
 
-    // Load directory: deployments.d/*.yaml
+    // Load directory: deployments.d/*.{yaml,yml,toml,json}
     let dir = base.join("deployments.d");
     if dir.is_dir() {
         let mut entries: Vec<_> = fs::read_dir(&dir)
             .map_err(|e| InfraError::Config(format!("Failed to read {}: {}", dir.display(), e)))?
             .filter_map(|e| e.ok())
             .map(|e| e.path())
-            .filter(|p| p.extension().is_some_and(|e| e == "yaml" || e == "yml"))
+            .filter(|p| ConfigFormat::is_config_file(p))
             .collect();
         entries.sort(); // alphabetical order for predictability
 
         for path in entries {
-            let content = fs::read_to_string(&path).map_err(|e| {
-                InfraError::Config(format!("Failed to read {}: {}", path.display(), e))
-            })?;
-            let content = substitute_env_vars(&content)?;
-            let external: Vec<DeploymentConfig> = serde_yaml::from_str(&content).map_err(|e| {
-                InfraError::Config(format!("Failed to parse {}: {}", path.display(), e))
-            })?;
-            info!(file = %path.display(), count = external.len(), "Loaded external deployments");
-            deployments.extend(external);
+            deployments.extend(load_deployments_file(&path, strict, templates, vars)?);
         }
     }
 
     Ok(deployments)
 }
 
+/// Maximum depth of nested `include:` directives
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// Read a config file (any supported format) into a generic value tree
+fn read_value(path: &Path) -> Result<serde_json::Value> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| InfraError::Config(format!("Failed to read {}: {}", path.display(), e)))?;
+    let content = substitute_env_vars(&content)?;
+    parse_content(&content, ConfigFormat::from_path(path), path)
+}
+
+/// Extract the `include:` list from a value tree (string or list of strings)
+fn include_patterns(value: &serde_json::Value) -> Vec<String> {
+    match value.get("include") {
+        Some(serde_json::Value::String(s)) => vec![s.clone()],
+        Some(serde_json::Value::Array(items)) => items
+            .iter()
+            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Expand include patterns relative to the including file's directory.
+/// Globs expand in alphabetical order; a plain path that does not exist is an error.
+fn resolve_includes(patterns: &[String], base_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for pattern in patterns {
+        let full = if Path::new(pattern).is_absolute() {
+            PathBuf::from(pattern)
+        } else {
+            base_dir.join(pattern)
+        };
+        let full_str = full.to_string_lossy().to_string();
+
+        let is_glob = pattern.contains(['*', '?', '[']);
+        if !is_glob {
+            if !full.is_file() {
+                return Err(InfraError::Config(format!(
+                    "Included config file not found: {}",
+                    full.display()
+                )));
+            }
+            files.push(full);
+            continue;
+        }
+
+        let mut matched: Vec<PathBuf> = glob::glob(&full_str)
+            .map_err(|e| {
+                InfraError::Config(format!("Invalid include pattern '{}': {}", pattern, e))
+            })?
+            .filter_map(|p| p.ok())
+            .filter(|p| p.is_file())
+            .collect();
+        matched.sort();
+
+        if matched.is_empty() {
+            warn!(pattern = %pattern, "Include pattern matched no files");
+        }
+        files.extend(matched);
+    }
+
+    Ok(files)
+}
+
+/// Load a config file and recursively merge its includes on top of it
+fn load_value_with_includes(path: &Path, stack: &mut Vec<PathBuf>) -> Result<serde_json::Value> {
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if stack.contains(&canonical) {
+        return Err(InfraError::Config(format!(
+            "Config include cycle detected at {}",
+            path.display()
+        )));
+    }
+    if stack.len() >= MAX_INCLUDE_DEPTH {
+        return Err(InfraError::Config(format!(
+            "Config includes nested deeper than {} levels at {}",
+            MAX_INCLUDE_DEPTH,
+            path.display()
+        )));
+    }
+
+    let mut value = read_value(path)?;
+    let patterns = include_patterns(&value);
+    if patterns.is_empty() {
+        return Ok(value);
+    }
+
+    stack.push(canonical);
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    for include in resolve_includes(&patterns, base_dir)? {
+        let overlay = load_value_with_includes(&include, stack)?;
+        info!(file = %include.display(), "Merged included config");
+        merge_values(&mut value, overlay);
+    }
+    stack.pop();
+
+    // Keep the directive of the top-level file only
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert(
+            "include".to_string(),
+            serde_json::Value::Array(
+                patterns
+                    .into_iter()
+                    .map(serde_json::Value::String)
+                    .collect(),
+            ),
+        );
+    }
+
+    Ok(value)
+}
+
+/// Deep-merge `overlay` into `base`:
+/// - maps merge recursively, overlay keys win
+/// - lists of named entries (deployments, agents) merge by `name`
+/// - any other value is replaced
+fn merge_values(base: &mut serde_json::Value, overlay: serde_json::Value) {
+    use serde_json::Value;
+
+    match (base, overlay) {
+        (Value::Object(base_map), Value::Object(overlay_map)) => {
+            for (key, value) in overlay_map {
+                if key == "include" {
+                    continue;
+                }
+                match base_map.get_mut(&key) {
+                    Some(existing) => merge_values(existing, value),
+                    None => {
+                        base_map.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(base_list), Value::Array(overlay_list))
+            if is_named_list(base_list) && is_named_list(&overlay_list) =>
+        {
+            for item in overlay_list {
+                let name = item.get("name").cloned();
+                match base_list
+                    .iter_mut()
+                    .find(|b| b.get("name") == name.as_ref())
+                {
+                    Some(existing) => merge_values(existing, item),
+                    None => base_list.push(item),
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn is_named_list(items: &[serde_json::Value]) -> bool {
+    items
+        .iter()
+        .all(|i| i.get("name").is_some_and(|n| n.is_string()))
+}
+
+/// Maximum depth of `extends:` chains between deployment templates
+const MAX_EXTENDS_DEPTH: usize = 8;
+
+/// Resolve a template by name, applying its own `extends:` chain first
+fn resolve_template(
+    name: &str,
+    templates: &HashMap<String, serde_json::Value>,
+    chain: &mut Vec<String>,
+) -> Result<serde_json::Value> {
+    if chain.iter().any(|n| n == name) {
+        chain.push(name.to_string());
+        return Err(InfraError::Config(format!(
+            "Deployment template cycle detected: {}",
+            chain.join(" -> ")
+        )));
+    }
+    if chain.len() >= MAX_EXTENDS_DEPTH {
+        return Err(InfraError::Config(format!(
+            "Deployment template '{}' extends deeper than {} levels",
+            name, MAX_EXTENDS_DEPTH
+        )));
+    }
+
+    let mut template = templates
+        .get(name)
+        .cloned()
+        .ok_or_else(|| InfraError::Config(format!("Unknown deployment template '{}'", name)))?;
+    if !template.is_object() {
+        return Err(InfraError::Config(format!(
+            "Deployment template '{}' must be a map",
+            name
+        )));
+    }
+
+    let parent = template.as_object_mut().and_then(|t| t.remove("extends"));
+    match parent {
+        Some(serde_json::Value::String(parent)) => {
+            chain.push(name.to_string());
+            let mut base = resolve_template(&parent, templates, chain)?;
+            chain.pop();
+            merge_values(&mut base, template);
+            Ok(base)
+        }
+        Some(_) => Err(InfraError::Config(format!(
+            "Deployment template '{}': 'extends' must be a template name",
+            name
+        ))),
+        None => Ok(template),
+    }
+}
+
+/// Expand `extends:` in deployment values: the template is the base and fields set on
+/// the deployment override it (maps like `env` merge key by key, lists are replaced)
+fn expand_deployment_templates(
+    deployments: &mut [serde_json::Value],
+    templates: &HashMap<String, serde_json::Value>,
+) -> Result<()> {
+    for deployment in deployments.iter_mut() {
+        let Some(obj) = deployment.as_object_mut() else {
+            continue;
+        };
+        let Some(extends) = obj.remove("extends") else {
+            continue;
+        };
+
+        let name = obj
+            .get("name")
+            .and_then(|n| n.as_str())
+            .unwrap_or("<unnamed>")
+            .to_string();
+        let template = extends.as_str().ok_or_else(|| {
+            InfraError::Config(format!(
+                "Deployment '{}': 'extends' must be a template name",
+                name
+            ))
+        })?;
+
+        let mut base = resolve_template(template, templates, &mut Vec::new())
+            .map_err(|e| InfraError::Config(format!("Deployment '{}': {}", name, e)))?;
+        merge_values(&mut base, std::mem::take(deployment));
+        *deployment = base;
+    }
+
+    Ok(())
+}
+
+/// Expand deployment templates declared in a whole config value tree
+fn expand_config_templates(value: &mut serde_json::Value) -> Result<()> {
+    let templates: HashMap<String, serde_json::Value> = value
+        .pointer("/modules/deploy/deployment_templates")
+        .and_then(|t| t.as_object())
+        .map(|t| t.clone().into_iter().collect())
+        .unwrap_or_default();
+
+    if let Some(list) = value
+        .pointer_mut("/modules/deploy/deployments")
+        .and_then(|d| d.as_array_mut())
+    {
+        expand_deployment_templates(list, &templates)?;
+    }
+
+    Ok(())
+}
+
+/// Replace `{{ var }}` in every string field of the deployments. Deployment `vars`
+/// override the global ones. Anything that is not a declared variable (Go, Helm or
+/// Jinja templates in commands) is left alone, and `\{{ var }}` keeps a declared
+/// name literal.
+fn interpolate_deployment_vars(
+    deployments: Vec<DeploymentConfig>,
+    global: &HashMap<String, String>,
+) -> Result<Vec<DeploymentConfig>> {
+    let re = Regex::new(r"(\\)?\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").unwrap();
+
+    deployments
+        .into_iter()
+        .map(|deployment| {
+            let name = deployment.name.clone();
+            let mut vars = global.clone();
+            vars.extend(deployment.vars.clone());
+
+            let mut value = serde_json::to_value(&deployment)
+                .map_err(|e| InfraError::Config(format!("Deployment '{}': {}", name, e)))?;
+            interpolate_value(&mut value, &re, &vars);
+            serde_json::from_value(value)
+                .map_err(|e| InfraError::Config(format!("Deployment '{}': {}", name, e)))
+        })
+        .collect()
+}
+
+fn interpolate_value(value: &mut serde_json::Value, re: &Regex, vars: &HashMap<String, String>) {
+    match value {
+        serde_json::Value::String(s) if s.contains("{{") => {
+            *s = re
+                .replace_all(s, |caps: &regex::Captures| {
+                    let text = &caps[0];
+                    if caps.get(1).is_some() {
+                        // Escaped: drop the backslash
+                        return text[1..].to_string();
+                    }
+                    vars.get(&caps[2])
+                        .cloned()
+                        .unwrap_or_else(|| text.to_string())
+                })
+                .into_owned();
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                interpolate_value(item, re, vars);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for item in map.values_mut() {
+                interpolate_value(item, re, vars);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Merge the selected profile from `profiles:` on top of the base settings.
+/// Profiles merge like includes: maps recursively, `agents`/`deployments` by name.
+fn apply_profile(value: &mut serde_json::Value, profile: &str) -> Result<()> {
+    let profiles = value.get("profiles").and_then(|p| p.as_object());
+    let Some(mut overlay) = profiles.and_then(|p| p.get(profile)).cloned() else {
+        let mut available: Vec<&str> = profiles
+            .map(|p| p.keys().map(|k| k.as_str()).collect())
+            .unwrap_or_default();
+        available.sort();
+        return Err(InfraError::Config(format!(
+            "Unknown config profile '{}' (available: {})",
+            profile,
+            if available.is_empty() {
+                "none".to_string()
+            } else {
+                available.join(", ")
+            }
+        )));
+    };
+
+    if let Some(obj) = overlay.as_object_mut() {
+        obj.remove("profiles");
+    } else {
+        return Err(InfraError::Config(format!(
+            "Config profile '{}' must be a map",
+            profile
+        )));
+    }
+
+    merge_values(value, overlay);
+    info!(profile = %profile, "Applied config profile");
+    Ok(())
+}
+
+/// Whether any inline deployment declares `extends:`
+fn uses_templates(value: &serde_json::Value) -> bool {
+    value
+        .pointer("/modules/deploy/deployments")
+        .and_then(|d| d.as_array())
+        .is_some_and(|list| list.iter().any(|d| d.get("extends").is_some()))
+}
+
 /// Load config from file with environment variable substitution
+/// Format is selected by extension: .yaml/.yml (default), .toml, .json
+/// Unknown keys are rejected when strict mode is enabled (`--strict-config`)
 pub fn load(path: &Path) -> Result<Config> {
+    load_with_mode(path, strict_mode())
+}
+
+/// Load config, rejecting unknown keys when `strict` is set
+pub fn load_with_mode(path: &Path, strict: bool) -> Result<Config> {
+    load_with_profile(path, strict, active_profile().as_deref())
+}
+
+/// Load config with an optional profile from `profiles:` applied over the base settings
+pub fn load_with_profile(path: &Path, strict: bool, profile: Option<&str>) -> Result<Config> {
     let content = std::fs::read_to_string(path)
         .map_err(|e| InfraError::Config(format!("Failed to read config file: {}", e)))?;
 
     let content = substitute_env_vars(&content)?;
-
-    let mut config: Config = serde_yaml::from_str(&content)?;
+    let format = ConfigFormat::from_path(path);
+
+    // Includes, profiles and templates are resolved on the value tree; plain configs
+    // are parsed directly so that errors keep their line numbers
+    let raw: Option<serde_json::Value> = parse_content(&content, format, path).ok();
+    let has_includes = raw
+        .as_ref()
+        .is_some_and(|v| !include_patterns(v).is_empty());
+    let has_templates = raw.as_ref().is_some_and(uses_templates);
+
+    let mut unknown = Vec::new();
+    let mut config: Config = if has_includes || has_templates || profile.is_some() {
+        let mut value = match raw {
+            Some(raw) if !has_includes => raw,
+            _ => load_value_with_includes(path, &mut Vec::new())?,
+        };
+        if let Some(profile) = profile {
+            apply_profile(&mut value, profile)?;
+        }
+        expand_config_templates(&mut value)?;
+        serde_ignored::deserialize(value, |p| unknown.push(p.to_string()))
+            .map_err(|e| InfraError::Config(format!("Failed to parse merged config: {}", e)))?
+    } else {
+        parse_typed(&content, format, path, &mut unknown)?
+    };
+    check_unknown_keys(&unknown, path, strict)?;
+    config.active_profile = profile.map(|p| p.to_string());
+
+    config.modules.deploy.deployments = interpolate_deployment_vars(
+        std::mem::take(&mut config.modules.deploy.deployments),
+        &config.modules.deploy.vars,
+    )?;
 
     // Load and merge external deployments
     if let Some(ref ext_path) = config.modules.deploy.external_deployments_path {
-        match load_external_deployments(ext_path) {
+        match load_external_deployments(
+            ext_path,
+            strict,
+            &config.modules.deploy.deployment_templates,
+            &config.modules.deploy.vars,
+        ) {
             Ok(external) => {
                 let base_names: HashSet<_> = config
                     .modules
@@ -705,55 +2436,291 @@ pub fn load(path: &Path) -> Result<Config> {
                     }
                 }
             }
+            Err(e) if strict => return Err(e),
             Err(e) => {
                 warn!(path = %ext_path, error = %e, "Failed to load external deployments");
             }
         }
     }
 
+    resolve_secret_refs(&mut config)?;
+    load_rotated_key(&mut config)?;
     validate(&config)?;
 
     Ok(config)
 }
 
-/// Substitute ${VAR} patterns with environment variables
+/// An agent whose key Home rotated signs and checks tokens with that key
+fn load_rotated_key(config: &mut Config) -> Result<()> {
+    if config.mode != Mode::Agent {
+        return Ok(());
+    }
+    match fs::read_to_string(&config.auth.rotated_key_file) {
+        Ok(key) => {
+            config.auth.jwt_secret = key.trim_end().to_string();
+            Ok(())
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(InfraError::Config(format!(
+            "Failed to read rotated key {}: {}",
+            config.auth.rotated_key_file, e
+        ))),
+    }
+}
+
+/// Store the key Home rotated this agent to, readable by the owner only
+pub fn save_rotated_key(path: &str, key: &str) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let path = Path::new(path);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension("new");
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&temp)
+        .and_then(|mut file| file.write_all(key.as_bytes()))
+        .and_then(|()| fs::rename(&temp, path))?;
+    Ok(())
+}
+
+/// Prefix marking a config value as a reference to a secret file
+const SECRET_FILE_PREFIX: &str = "file://";
+
+/// Marker of an age-encrypted config value: `ENC[age:<base64 ciphertext>]`
+const ENCRYPTED_PREFIX: &str = "ENC[age:";
+const ENCRYPTED_SUFFIX: &str = "]";
+
+/// Age identity used when neither INFRACTL_AGE_KEY nor INFRACTL_AGE_KEY_FILE is set
+const DEFAULT_AGE_KEY_FILE: &str = "/etc/infractl/age.key";
+
+/// Resolves secret references in config values; the age key is only read
+/// once the first encrypted value is found
+#[derive(Default)]
+struct SecretResolver {
+    identities: Option<Vec<Box<dyn age::Identity>>>,
+}
+
+impl SecretResolver {
+    /// Resolve a config value:
+    /// - `file:///path` — the file's contents (trailing newline trimmed)
+    /// - `ENC[age:...]` — the decrypted value
+    ///
+    /// Other values are returned unchanged.
+    fn resolve(&mut self, value: &str, field: &str) -> Result<String> {
+        if let Some(path) = value.strip_prefix(SECRET_FILE_PREFIX) {
+            let content = fs::read_to_string(path).map_err(|e| {
+                InfraError::Config(format!(
+                    "Failed to read secret file '{}' for {}: {}",
+                    path, field, e
+                ))
+            })?;
+            return Ok(content.trim_end_matches(['\r', '\n']).to_string());
+        }
+
+        if let Some(encoded) = value
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .and_then(|v| v.strip_suffix(ENCRYPTED_SUFFIX))
+        {
+            if self.identities.is_none() {
+                self.identities = Some(load_age_identities()?);
+            }
+            let identities = self.identities.as_deref().unwrap_or_default();
+            return decrypt_value(encoded, identities)
+                .map_err(|e| InfraError::Config(format!("Failed to decrypt {}: {}", field, e)));
+        }
+
+        Ok(value.to_string())
+    }
+}
+
+/// Load age identities from INFRACTL_AGE_KEY, INFRACTL_AGE_KEY_FILE or the default key file
+fn load_age_identities() -> Result<Vec<Box<dyn age::Identity>>> {
+    let (source, content) = match std::env::var("INFRACTL_AGE_KEY") {
+        Ok(key) if !key.is_empty() => ("INFRACTL_AGE_KEY".to_string(), key),
+        _ => {
+            let path = std::env::var("INFRACTL_AGE_KEY_FILE")
+                .unwrap_or_else(|_| DEFAULT_AGE_KEY_FILE.to_string());
+            let content = fs::read_to_string(&path).map_err(|e| {
+                InfraError::Config(format!(
+                    "Config contains encrypted values but the age key '{}' could not be read: {} \
+                     (set INFRACTL_AGE_KEY or INFRACTL_AGE_KEY_FILE)",
+                    path, e
+                ))
+            })?;
+            (path, content)
+        }
+    };
+
+    age::IdentityFile::from_buffer(content.as_bytes())
+        .map_err(|e| e.to_string())
+        .and_then(|f| f.into_identities().map_err(|e| e.to_string()))
+        .map_err(|e| InfraError::Config(format!("Invalid age key in {}: {}", source, e)))
+}
+
+/// Decrypt the base64 payload of an `ENC[age:...]` value
+fn decrypt_value(
+    encoded: &str,
+    identities: &[Box<dyn age::Identity>],
+) -> std::result::Result<String, String> {
+    use base64::Engine;
+    use std::io::Read;
+
+    let ciphertext = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| format!("invalid base64: {}", e))?;
+
+    let decryptor = age::Decryptor::new(&ciphertext[..]).map_err(|e| e.to_string())?;
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|i| i.as_ref()))
+        .map_err(|e| e.to_string())?;
+
+    let mut plaintext = String::new();
+    reader
+        .read_to_string(&mut plaintext)
+        .map_err(|e| e.to_string())?;
+    Ok(plaintext)
+}
+
+/// Encrypt a value for an age recipient, producing an `ENC[age:...]` config value
+pub fn encrypt_value(recipient: &str, plaintext: &str) -> Result<String> {
+    use base64::Engine;
+
+    let recipient: age::x25519::Recipient = recipient
+        .trim()
+        .parse()
+        .map_err(|e| InfraError::Config(format!("Invalid age recipient: {}", e)))?;
+    let ciphertext = age::encrypt(&recipient, plaintext.as_bytes())
+        .map_err(|e| InfraError::Config(format!("Encryption failed: {}", e)))?;
+
+    Ok(format!(
+        "{}{}{}",
+        ENCRYPTED_PREFIX,
+        base64::engine::general_purpose::STANDARD.encode(ciphertext),
+        ENCRYPTED_SUFFIX
+    ))
+}
+
+/// Replace `file://` references and `ENC[age:...]` values in secret-bearing fields
+/// (systemd credentials, docker secrets, encrypted values in a shared config repo)
+fn resolve_secret_refs(config: &mut Config) -> Result<()> {
+    let mut resolver = SecretResolver::default();
+
+    config.auth.jwt_secret = resolver.resolve(&config.auth.jwt_secret, "auth.jwt_secret")?;
+
+    for (name, secret) in config.auth.webhook_secrets.iter_mut() {
+        *secret = resolver.resolve(secret, &format!("auth.webhook_secrets.{}", name))?;
+    }
+
+    if let Some(secret) = config
+        .auth
+        .oidc
+        .as_mut()
+        .and_then(|oidc| oidc.client_secret.as_mut())
+    {
+        *secret = resolver.resolve(secret, "auth.oidc.client_secret")?;
+    }
+
+    if let Some(password) = config.auth.dashboard.password.as_mut() {
+        *password = resolver.resolve(password, "auth.dashboard.password")?;
+    }
+
+    if let Some(key) = config.modules.storage.encryption_key.as_mut() {
+        *key = resolver.resolve(key, "modules.storage.encryption_key")?;
+    }
+
+    for endpoint in config.modules.webhooks.endpoints.iter_mut() {
+        if let Some(secret) = endpoint.secret.as_mut() {
+            *secret = resolver.resolve(secret, &format!("webhook {} secret", endpoint.path))?;
+        }
+    }
+
+    for deployment in config.modules.deploy.deployments.iter_mut() {
+        for (key, value) in deployment.env.iter_mut() {
+            *value = resolver.resolve(
+                value,
+                &format!("deployment {} env {}", deployment.name, key),
+            )?;
+        }
+        if let Some(ssh_key) = deployment.ssh_key.as_mut() {
+            *ssh_key =
+                resolver.resolve(ssh_key, &format!("deployment {} ssh_key", deployment.name))?;
+        }
+        if let Some(telegram) = deployment.telegram.as_mut() {
+            telegram.bot_token = resolver.resolve(
+                &telegram.bot_token,
+                &format!("deployment {} telegram.bot_token", deployment.name),
+            )?;
+        }
+        if let Some(auth) = deployment.registry_auth.as_mut() {
+            auth.password = resolver.resolve(
+                &auth.password,
+                &format!("deployment {} registry_auth.password", deployment.name),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Substitute environment variables in config content:
+/// - `${VAR}` — value of VAR (empty with a warning if unset)
+/// - `${VAR:-default}` — value of VAR, or `default` if unset or empty
+/// - `${VAR:?message}` — value of VAR, or fail config load with `message`
 fn substitute_env_vars(content: &str) -> Result<String> {
-    let re = Regex::new(r"\$\{([^}]+)\}").unwrap();
-    let mut result = content.to_string();
+    let re = Regex::new(r"\$\{([^}:]+)(?::([-?])([^}]*))?\}").unwrap();
     let mut missing_vars = Vec::new();
+    let mut errors = Vec::new();
 
-    for cap in re.captures_iter(content) {
+    let result = re.replace_all(content, |cap: &regex::Captures| {
         let var_name = &cap[1];
-        let placeholder = &cap[0];
-
-        match std::env::var(var_name) {
-            Ok(value) => {
-                result = result.replace(placeholder, &value);
-            }
-            Err(_) => {
+        let value = std::env::var(var_name).ok();
+
+        match cap.get(2).map(|m| m.as_str()) {
+            Some("-") => match value {
+                Some(v) if !v.is_empty() => v,
+                _ => cap[3].to_string(),
+            },
+            Some("?") => match value {
+                Some(v) if !v.is_empty() => v,
+                _ => {
+                    let message = cap[3].trim();
+                    errors.push(if message.is_empty() {
+                        InfraError::EnvVar(var_name.to_string()).to_string()
+                    } else {
+                        format!("Environment variable '{}' not set: {}", var_name, message)
+                    });
+                    String::new()
+                }
+            },
+            _ => value.unwrap_or_else(|| {
                 missing_vars.push(var_name.to_string());
-            }
+                String::new()
+            }),
         }
+    });
+
+    if !errors.is_empty() {
+        return Err(InfraError::Config(errors.join("; ")));
     }
 
     if !missing_vars.is_empty() {
-        // For non-critical vars, we can leave them empty or use defaults
-        // For critical vars like JWT_SECRET in production, this would error
-        for var in &missing_vars {
-            let placeholder = format!("${{{}}}", var);
-            result = result.replace(&placeholder, "");
-        }
         tracing::warn!(
             missing = ?missing_vars,
             "Some environment variables are not set"
         );
     }
 
-    Ok(result)
+    Ok(result.into_owned())
 }
 
 /// Minimum JWT secret length (OWASP recommends 32+ bytes for HS256)
-const MIN_JWT_SECRET_LENGTH: usize = 32;
+pub const MIN_JWT_SECRET_LENGTH: usize = 32;
 
 /// Validate configuration
 fn validate(config: &Config) -> Result<()> {
@@ -772,9 +2739,285 @@ fn validate(config: &Config) -> Result<()> {
             config.auth.jwt_secret.len()
         )));
     }
-
-    // Validate allowed networks are valid CIDR
-    for network in &config.server.allowed_networks {
+
+    // Validate allowed networks are valid CIDR
+    for network in &config.server.allowed_networks {
+        network
+            .parse::<ipnetwork::IpNetwork>()
+            .map_err(|_| InfraError::Config(format!("Invalid network CIDR: {}", network)))?;
+    }
+
+    // Validate durations (IP ban, webhooks, HTTP client)
+    let ip_ban = &config.server.ip_ban;
+    for network in &ip_ban.exempt_networks {
+        network
+            .parse::<ipnetwork::IpNetwork>()
+            .map_err(|_| InfraError::Config(format!("Invalid network CIDR: {}", network)))?;
+    }
+    let webhooks = &config.modules.webhooks;
+    for (field, value) in [
+        ("server.ip_ban.window", &ip_ban.window),
+        ("server.ip_ban.ban_duration", &ip_ban.ban_duration),
+        ("server.drain_timeout", &config.server.drain_timeout),
+        ("auth.access_token_ttl", &config.auth.access_token_ttl),
+        ("auth.refresh_token_ttl", &config.auth.refresh_token_ttl),
+        (
+            "auth.key_rotation_overlap",
+            &config.auth.key_rotation_overlap,
+        ),
+        ("server.rate_limit.window", &config.server.rate_limit.window),
+        (
+            "server.rate_limit.cleanup_interval",
+            &config.server.rate_limit.cleanup_interval,
+        ),
+        (
+            "modules.webhooks.timestamp_tolerance",
+            &webhooks.timestamp_tolerance,
+        ),
+        ("modules.webhooks.replay_window", &webhooks.replay_window),
+        (
+            "http_client.connect_timeout",
+            &config.http_client.connect_timeout,
+        ),
+        ("http_client.timeout", &config.http_client.timeout),
+        (
+            "http_client.retry_backoff",
+            &config.http_client.retry_backoff,
+        ),
+        (
+            "http_client.pool_idle_timeout",
+            &config.http_client.pool_idle_timeout,
+        ),
+        (
+            "modules.storage.write_buffer.flush_interval",
+            &config.modules.storage.write_buffer.flush_interval,
+        ),
+        (
+            "modules.metrics.collect_interval",
+            &config.modules.metrics.collect_interval,
+        ),
+        ("modules.exec.timeout", &config.modules.exec.timeout),
+    ] {
+        humantime::parse_duration(value).map_err(|e| {
+            InfraError::Config(format!(
+                "Invalid duration for {}: '{}' ({})",
+                field, value, e
+            ))
+        })?;
+    }
+    let backup = &config.modules.storage.backup;
+    if let Some(interval) = &backup.interval {
+        match humantime::parse_duration(interval) {
+            Ok(interval) if !interval.is_zero() => {}
+            _ => {
+                return Err(InfraError::Config(format!(
+                    "Invalid duration for modules.storage.backup.interval: '{}'",
+                    interval
+                )))
+            }
+        }
+    }
+    if backup.keep == 0 {
+        return Err(InfraError::Config(
+            "modules.storage.backup.keep must be at least 1".to_string(),
+        ));
+    }
+    match config.modules.storage.encryption_key.as_deref() {
+        Some(_) if !cfg!(feature = "sqlcipher") => {
+            return Err(InfraError::Config(
+                "modules.storage.encryption_key needs infractl built with the `sqlcipher` feature"
+                    .to_string(),
+            ));
+        }
+        Some("") => {
+            return Err(InfraError::Config(
+                "modules.storage.encryption_key is empty".to_string(),
+            ));
+        }
+        _ => {}
+    }
+    if config.modules.metrics.buffer.max_reports == 0 {
+        return Err(InfraError::Config(
+            "modules.metrics.buffer.max_reports must be at least 1".to_string(),
+        ));
+    }
+    let maintenance = &config.modules.storage.maintenance;
+    crate::deploy::CronSchedule::parse(&maintenance.schedule).map_err(|e| {
+        InfraError::Config(format!(
+            "Invalid modules.storage.maintenance.schedule '{}': {}",
+            maintenance.schedule, e
+        ))
+    })?;
+    if humantime::parse_duration(&config.modules.metrics.collect_interval)
+        .is_ok_and(|interval| interval.is_zero())
+    {
+        return Err(InfraError::Config(
+            "modules.metrics.collect_interval must be greater than zero".to_string(),
+        ));
+    }
+    let mut custom_names = HashSet::new();
+    for metric in &config.modules.metrics.custom {
+        if !valid_name(&metric.name) {
+            return Err(InfraError::Config(format!(
+                "Invalid modules.metrics.custom name '{}' (letters, digits, '-', '_' and '.' only)",
+                metric.name
+            )));
+        }
+        if !custom_names.insert(metric.name.as_str()) {
+            return Err(InfraError::Config(format!(
+                "Duplicate modules.metrics.custom name '{}'",
+                metric.name
+            )));
+        }
+        if metric.command.trim().is_empty() {
+            return Err(InfraError::Config(format!(
+                "modules.metrics.custom '{}' has an empty command",
+                metric.name
+            )));
+        }
+        for (field, value) in [
+            ("interval", metric.interval.as_deref()),
+            ("timeout", Some(metric.timeout.as_str())),
+        ] {
+            let Some(value) = value else { continue };
+            if !humantime::parse_duration(value).is_ok_and(|d| !d.is_zero()) {
+                return Err(InfraError::Config(format!(
+                    "Invalid modules.metrics.custom '{}' {} '{}'",
+                    metric.name, field, value
+                )));
+            }
+        }
+    }
+    for (name, entry) in &config.modules.exec.commands {
+        if !valid_name(name) {
+            return Err(InfraError::Config(format!(
+                "Invalid modules.exec command name '{}' (letters, digits, '-', '_' and '.' only)",
+                name
+            )));
+        }
+        if entry.cmd().trim().is_empty() {
+            return Err(InfraError::Config(format!(
+                "modules.exec command '{}' is empty",
+                name
+            )));
+        }
+    }
+    if let Some(ref oidc) = config.auth.oidc {
+        for (field, url) in [
+            ("auth.oidc.issuer", &oidc.issuer),
+            ("auth.oidc.redirect_url", &oidc.redirect_url),
+        ] {
+            if !(url.starts_with("https://") || url.starts_with("http://")) {
+                return Err(InfraError::Config(format!(
+                    "{} must be an http(s) URL, got '{}'",
+                    field, url
+                )));
+            }
+        }
+        if oidc.client_id.is_empty() {
+            return Err(InfraError::Config(
+                "auth.oidc.client_id must not be empty".to_string(),
+            ));
+        }
+        if !oidc.scopes.iter().any(|s| s == "openid") {
+            return Err(InfraError::Config(
+                "auth.oidc.scopes must include 'openid'".to_string(),
+            ));
+        }
+        if oidc.allowed_emails.is_empty() {
+            return Err(InfraError::Config(
+                "auth.oidc.allowed_emails must not be empty: list the email globs allowed to log in"
+                    .to_string(),
+            ));
+        }
+        for pattern in &oidc.allowed_emails {
+            glob::Pattern::new(pattern).map_err(|e| {
+                InfraError::Config(format!(
+                    "Invalid auth.oidc.allowed_emails pattern '{}': {}",
+                    pattern, e
+                ))
+            })?;
+        }
+        humantime::parse_duration(&oidc.session_ttl).map_err(|e| {
+            InfraError::Config(format!(
+                "Invalid duration for auth.oidc.session_ttl: '{}' ({})",
+                oidc.session_ttl, e
+            ))
+        })?;
+    }
+
+    if config.auth.dashboard.password.as_deref() == Some("") {
+        return Err(InfraError::Config(
+            "auth.dashboard.password must not be empty".to_string(),
+        ));
+    }
+    humantime::parse_duration(&config.auth.dashboard.session_ttl).map_err(|e| {
+        InfraError::Config(format!(
+            "Invalid duration for auth.dashboard.session_ttl: '{}' ({})",
+            config.auth.dashboard.session_ttl, e
+        ))
+    })?;
+
+    if let Some(ref tls) = config.server.tls {
+        if tls.cert_path.is_empty() || tls.key_path.is_empty() {
+            return Err(InfraError::Config(
+                "server.tls requires 'cert_path' and 'key_path'".to_string(),
+            ));
+        }
+        humantime::parse_duration(&tls.reload_interval).map_err(|e| {
+            InfraError::Config(format!(
+                "Invalid duration for server.tls.reload_interval: '{}' ({})",
+                tls.reload_interval, e
+            ))
+        })?;
+    }
+    if config.server.reverse_connect && config.server.home_address.is_none() {
+        return Err(InfraError::Config(
+            "server.reverse_connect requires server.home_address".to_string(),
+        ));
+    }
+    if ip_ban.enabled && ip_ban.max_violations == 0 {
+        return Err(InfraError::Config(
+            "server.ip_ban.max_violations must be at least 1".to_string(),
+        ));
+    }
+    let rate_limit = &config.server.rate_limit;
+    if rate_limit.max_requests == 0 || rate_limit.max_tracked_ips == 0 {
+        return Err(InfraError::Config(
+            "server.rate_limit.max_requests and max_tracked_ips must be at least 1".to_string(),
+        ));
+    }
+    for (class, rule) in [
+        ("webhook", &rate_limit.routes.webhook),
+        ("api", &rate_limit.routes.api),
+        ("health", &rate_limit.routes.health),
+    ] {
+        let Some(rule) = rule else { continue };
+        if rule.max_requests == 0 {
+            return Err(InfraError::Config(format!(
+                "server.rate_limit.routes.{}.max_requests must be at least 1",
+                class
+            )));
+        }
+        humantime::parse_duration(&rule.window).map_err(|e| {
+            InfraError::Config(format!(
+                "Invalid duration for server.rate_limit.routes.{}.window: '{}' ({})",
+                class, rule.window, e
+            ))
+        })?;
+        if rule.burst == Some(0) {
+            return Err(InfraError::Config(format!(
+                "server.rate_limit.routes.{}.burst must be at least 1",
+                class
+            )));
+        }
+    }
+    if rate_limit.burst == Some(0) {
+        return Err(InfraError::Config(
+            "server.rate_limit.burst must be at least 1".to_string(),
+        ));
+    }
+    for network in &rate_limit.exempt_networks {
         network
             .parse::<ipnetwork::IpNetwork>()
             .map_err(|_| InfraError::Config(format!("Invalid network CIDR: {}", network)))?;
@@ -793,6 +3036,29 @@ fn validate(config: &Config) -> Result<()> {
                 agent.name
             )));
         }
+        for (field, value) in [
+            ("timeout", &agent.timeout),
+            ("health_interval", &agent.health_interval),
+        ] {
+            let duration = humantime::parse_duration(value).map_err(|e| {
+                InfraError::Config(format!(
+                    "Invalid duration for agent '{}' {}: '{}' ({})",
+                    agent.name, field, value, e
+                ))
+            })?;
+            if duration.is_zero() {
+                return Err(InfraError::Config(format!(
+                    "Agent '{}' {} must be greater than zero",
+                    agent.name, field
+                )));
+            }
+        }
+        if agent.offline_after == Some(0) {
+            return Err(InfraError::Config(format!(
+                "Agent '{}' offline_after must be at least 1",
+                agent.name
+            )));
+        }
     }
 
     // Validate deployments
@@ -819,6 +3085,29 @@ fn validate(config: &Config) -> Result<()> {
                         deploy.name
                     )));
                 }
+                if deploy.strategy == Some(DeployStrategy::Canary) {
+                    let canary = deploy
+                        .canary
+                        .as_ref()
+                        .filter(|c| !c.services.is_empty())
+                        .ok_or_else(|| {
+                            InfraError::Config(format!(
+                                "Deployment '{}' with strategy canary requires 'canary.services'",
+                                deploy.name
+                            ))
+                        })?;
+                    for (field, value) in [
+                        ("observation", &canary.observation),
+                        ("interval", &canary.interval),
+                    ] {
+                        humantime::parse_duration(value).map_err(|e| {
+                            InfraError::Config(format!(
+                                "Deployment '{}' has invalid canary.{} '{}': {}",
+                                deploy.name, field, value, e
+                            ))
+                        })?;
+                    }
+                }
             }
             DeployType::CustomScript => {
                 if deploy.script.is_none() {
@@ -836,7 +3125,340 @@ fn validate(config: &Config) -> Result<()> {
                     )));
                 }
             }
+            DeployType::Helm => {
+                if !deploy
+                    .helm
+                    .as_ref()
+                    .is_some_and(|h| !h.chart.is_empty() && !h.release.is_empty())
+                {
+                    return Err(InfraError::Config(format!(
+                        "Deployment '{}' of type helm requires 'helm.chart' and 'helm.release'",
+                        deploy.name
+                    )));
+                }
+            }
+            DeployType::DockerRun => {
+                let container = deploy
+                    .container
+                    .as_ref()
+                    .filter(|c| !c.image.is_empty())
+                    .ok_or_else(|| {
+                        InfraError::Config(format!(
+                            "Deployment '{}' of type docker_run requires 'container.image'",
+                            deploy.name
+                        ))
+                    })?;
+                crate::deploy::validate_container(container).map_err(|e| {
+                    InfraError::Config(format!(
+                        "Deployment '{}' has invalid container config: {}",
+                        deploy.name, e
+                    ))
+                })?;
+            }
+            DeployType::ReleaseArtifact => {
+                let valid = deploy.release.as_ref().is_some_and(|r| {
+                    r.repo.split('/').filter(|part| !part.is_empty()).count() == 2
+                        && !r.asset.is_empty()
+                        && !r.asset.contains('/')
+                });
+                if !valid || deploy.path.is_none() {
+                    return Err(InfraError::Config(format!(
+                        "Deployment '{}' of type release_artifact requires 'path', \
+                         'release.repo' (owner/name) and 'release.asset' (file name)",
+                        deploy.name
+                    )));
+                }
+            }
+            DeployType::S3Artifact => {
+                let valid = deploy.s3.as_ref().is_some_and(|s3| {
+                    (s3.endpoint.starts_with("http://") || s3.endpoint.starts_with("https://"))
+                        && !s3.bucket.is_empty()
+                        && !s3.key.is_empty()
+                        && !s3.key.ends_with('/')
+                });
+                if !valid || deploy.path.is_none() {
+                    return Err(InfraError::Config(format!(
+                        "Deployment '{}' of type s3_artifact requires 'path', \
+                         's3.endpoint' (http/https URL), 's3.bucket' and 's3.key'",
+                        deploy.name
+                    )));
+                }
+            }
+        }
+    }
+
+    for deploy in &config.modules.deploy.deployments {
+        if let Some(ref schedule) = deploy.schedule {
+            crate::deploy::CronSchedule::parse(schedule).map_err(|e| {
+                InfraError::Config(format!(
+                    "Deployment '{}' has invalid schedule '{}': {}",
+                    deploy.name, schedule, e
+                ))
+            })?;
+        }
+        if let Some(ref git_ref) = deploy.git_ref {
+            crate::deploy::validate_git_ref(git_ref).map_err(|e| {
+                InfraError::Config(format!("Deployment '{}' has {}", deploy.name, e))
+            })?;
+        }
+        for pattern in &deploy.shell_allow {
+            glob::Pattern::new(pattern).map_err(|e| {
+                InfraError::Config(format!(
+                    "Deployment '{}' has invalid shell_allow pattern '{}': {}",
+                    deploy.name, pattern, e
+                ))
+            })?;
+        }
+        crate::deploy::ResourceLimits::from(deploy)
+            .validate()
+            .map_err(|e| InfraError::Config(format!("Deployment '{}' has {}", deploy.name, e)))?;
+        for secret in deploy.secrets.values() {
+            crate::deploy::validate_secret_name(secret).map_err(|e| {
+                InfraError::Config(format!("Deployment '{}' has {}", deploy.name, e))
+            })?;
+        }
+        for (image, digest) in &deploy.image_digests {
+            let valid = digest
+                .strip_prefix("sha256:")
+                .is_some_and(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+            if !valid {
+                return Err(InfraError::Config(format!(
+                    "Deployment '{}' has invalid digest '{}' for image '{}' (expected sha256:<64 hex>)",
+                    deploy.name, digest, image
+                )));
+            }
+        }
+        if deploy.requires_approval && deploy.approvers.is_empty() {
+            return Err(InfraError::Config(format!(
+                "Deployment '{}' requires_approval needs at least one entry in 'approvers'",
+                deploy.name
+            )));
+        }
+        if let Some(ref auth) = deploy.registry_auth {
+            if auth.registry.is_empty() || auth.username.is_empty() {
+                return Err(InfraError::Config(format!(
+                    "Deployment '{}' registry_auth requires 'registry' and 'username'",
+                    deploy.name
+                )));
+            }
+        }
+        if let Some(ref retry) = deploy.retry {
+            if retry.attempts == 0 {
+                return Err(InfraError::Config(format!(
+                    "Deployment '{}' retry.attempts must be at least 1",
+                    deploy.name
+                )));
+            }
+            humantime::parse_duration(&retry.backoff).map_err(|e| {
+                InfraError::Config(format!(
+                    "Deployment '{}' has invalid retry.backoff '{}': {}",
+                    deploy.name, retry.backoff, e
+                ))
+            })?;
+        }
+    }
+
+    // Without a `modules.deploy` section the derived default (0 workers) applies
+    if config.modules.deploy.enabled && config.modules.deploy.workers == 0 {
+        return Err(InfraError::Config(
+            "modules.deploy.workers must be at least 1".to_string(),
+        ));
+    }
+
+    validate_triggers(&config.modules.deploy)?;
+    validate_dependencies(&config.modules.deploy.deployments)?;
+
+    // Webhook endpoints must point at an existing deployment
+    for endpoint in &config.modules.webhooks.endpoints {
+        if let Some(ref name) = endpoint.deployment {
+            if !config
+                .modules
+                .deploy
+                .deployments
+                .iter()
+                .any(|d| &d.name == name)
+            {
+                return Err(InfraError::Config(format!(
+                    "Webhook endpoint '{}' references unknown deployment '{}'",
+                    endpoint.path, name
+                )));
+            }
         }
+        if let Some(ref constraint) = endpoint.schedule_constraint {
+            if constraint.allowed_hours.is_empty()
+                || constraint.allowed_hours.iter().any(|h| *h > 23)
+            {
+                return Err(InfraError::Config(format!(
+                    "Webhook endpoint '{}' schedule_constraint.allowed_hours must list hours 0-23",
+                    endpoint.path
+                )));
+            }
+            tz::TimeZone::from_posix_tz(&constraint.timezone).map_err(|e| {
+                InfraError::Config(format!(
+                    "Webhook endpoint '{}' has unknown timezone '{}': {}",
+                    endpoint.path, constraint.timezone, e
+                ))
+            })?;
+        }
+        for pattern in endpoint.branch_filter.iter().chain(&endpoint.path_filter) {
+            glob::Pattern::new(pattern).map_err(|e| {
+                InfraError::Config(format!(
+                    "Webhook endpoint '{}' has invalid filter '{}': {}",
+                    endpoint.path, pattern, e
+                ))
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Outgoing trigger edges of a deployment: its own hooks plus the global
+/// on_success/on_error hooks, which only fire for app deployments
+fn trigger_targets<'a>(deploy: &'a DeploymentConfig, global: &'a DeployConfig) -> Vec<&'a str> {
+    let mut targets = Vec::new();
+    targets.extend(deploy.on_success.as_vec());
+    targets.extend(deploy.on_error.as_vec());
+    targets.extend(deploy.pipeline.on_start.as_vec());
+    targets.extend(deploy.pipeline.on_finish.as_vec());
+    if deploy.category == DeployCategory::App {
+        targets.extend(global.on_success.as_vec());
+        targets.extend(global.on_error.as_vec());
+    }
+    targets
+}
+
+/// Check that every trigger points to an existing deployment and that
+/// trigger chains contain no cycles (which would loop deployments forever)
+fn validate_triggers(deploy_config: &DeployConfig) -> Result<()> {
+    let deployments: HashMap<&str, &DeploymentConfig> = deploy_config
+        .deployments
+        .iter()
+        .map(|d| (d.name.as_str(), d))
+        .collect();
+
+    for (hook, trigger) in [
+        ("on_success", &deploy_config.on_success),
+        ("on_error", &deploy_config.on_error),
+    ] {
+        for target in trigger.as_vec() {
+            if !deployments.contains_key(target) {
+                return Err(InfraError::Config(format!(
+                    "Global {} trigger references unknown deployment '{}'",
+                    hook, target
+                )));
+            }
+        }
+    }
+
+    for deploy in &deploy_config.deployments {
+        for target in trigger_targets(deploy, deploy_config) {
+            if !deployments.contains_key(target) {
+                return Err(InfraError::Config(format!(
+                    "Deployment '{}' triggers unknown deployment '{}'",
+                    deploy.name, target
+                )));
+            }
+        }
+    }
+
+    // Depth-first search; a node seen again while still on the stack closes a cycle
+    fn visit<'a>(
+        name: &'a str,
+        deployments: &HashMap<&'a str, &'a DeploymentConfig>,
+        global: &'a DeployConfig,
+        stack: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+    ) -> Result<()> {
+        if done.contains(name) {
+            return Ok(());
+        }
+        if let Some(pos) = stack.iter().position(|n| *n == name) {
+            let mut cycle = stack[pos..].to_vec();
+            cycle.push(name);
+            return Err(InfraError::Config(format!(
+                "Trigger cycle detected: {}",
+                cycle.join(" -> ")
+            )));
+        }
+
+        stack.push(name);
+        for target in trigger_targets(deployments[name], global) {
+            visit(target, deployments, global, stack, done)?;
+        }
+        stack.pop();
+        done.insert(name);
+        Ok(())
+    }
+
+    let mut done = HashSet::new();
+    for deploy in &deploy_config.deployments {
+        visit(
+            &deploy.name,
+            &deployments,
+            deploy_config,
+            &mut Vec::new(),
+            &mut done,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// `depends_on` must name existing deployments and form a DAG
+fn validate_dependencies(deployments: &[DeploymentConfig]) -> Result<()> {
+    let by_name: HashMap<&str, &DeploymentConfig> =
+        deployments.iter().map(|d| (d.name.as_str(), d)).collect();
+
+    for deploy in deployments {
+        for dep in &deploy.depends_on {
+            if !by_name.contains_key(dep.as_str()) {
+                return Err(InfraError::Config(format!(
+                    "Deployment '{}' depends on unknown deployment '{}'",
+                    deploy.name, dep
+                )));
+            }
+        }
+        if let Some(ref max_age) = deploy.depends_max_age {
+            humantime::parse_duration(max_age).map_err(|e| {
+                InfraError::Config(format!(
+                    "Deployment '{}' has invalid depends_max_age '{}': {}",
+                    deploy.name, max_age, e
+                ))
+            })?;
+        }
+    }
+
+    fn visit<'a>(
+        name: &'a str,
+        by_name: &HashMap<&'a str, &'a DeploymentConfig>,
+        stack: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+    ) -> Result<()> {
+        if done.contains(name) {
+            return Ok(());
+        }
+        if let Some(pos) = stack.iter().position(|n| *n == name) {
+            let mut cycle = stack[pos..].to_vec();
+            cycle.push(name);
+            return Err(InfraError::Config(format!(
+                "Dependency cycle detected: {}",
+                cycle.join(" -> ")
+            )));
+        }
+
+        stack.push(name);
+        for dep in &by_name[name].depends_on {
+            visit(dep, by_name, stack, done)?;
+        }
+        stack.pop();
+        done.insert(name);
+        Ok(())
+    }
+
+    let mut done = HashSet::new();
+    for deploy in deployments {
+        visit(&deploy.name, &by_name, &mut Vec::new(), &mut done)?;
     }
 
     Ok(())
@@ -911,6 +3533,65 @@ pub fn remove_assignment(base_path: &Path, name: &str) -> Result<()> {
     Ok(())
 }
 
+/// File a deployment pushed from Home is kept in: `{external_deployments_path}/deployments.d/{name}.yaml`
+pub fn pushed_deployment_path(external_path: &str, name: &str) -> PathBuf {
+    Path::new(external_path)
+        .join("deployments.d")
+        .join(format!("{}.yaml", name))
+}
+
+/// Write a deployment pushed from Home and load the config with it. A file
+/// that breaks the config is rolled back, so the next start still works.
+pub fn save_pushed_deployment(
+    config_path: &Path,
+    external_path: &str,
+    deployment: &DeploymentConfig,
+) -> Result<DeployConfig> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let path = pushed_deployment_path(external_path, &deployment.name);
+    let previous = fs::read(&path).ok();
+    let content = serde_yaml::to_string(&vec![deployment])
+        .map_err(|e| InfraError::Config(format!("Failed to serialize deployment: {}", e)))?;
+    let write = |content: &[u8]| {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let temp = path.with_extension("yaml.new");
+        fs::create_dir_all(dir)
+            .and_then(|()| {
+                fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .mode(0o600)
+                    .open(&temp)
+            })
+            .and_then(|mut file| file.write_all(content))
+            .and_then(|()| fs::rename(&temp, &path))
+            .map_err(|e| InfraError::Config(format!("Failed to write {}: {}", path.display(), e)))
+    };
+
+    write(content.as_bytes())?;
+    match load(config_path) {
+        Ok(config) => {
+            info!(name = %deployment.name, file = %path.display(), "Saved pushed deployment");
+            Ok(config.modules.deploy)
+        }
+        Err(e) => {
+            let restored = match previous {
+                Some(ref previous) => write(previous),
+                None => fs::remove_file(&path).map_err(|e| {
+                    InfraError::Config(format!("Failed to remove {}: {}", path.display(), e))
+                }),
+            };
+            if let Err(restore) = restored {
+                warn!(error = %restore, "Failed to roll back pushed deployment");
+            }
+            Err(e)
+        }
+    }
+}
+
 /// Check if an IP is allowed based on network configuration
 #[allow(dead_code)]
 pub fn is_ip_allowed(ip: &IpAddr, allowed_networks: &[String]) -> bool {
@@ -937,6 +3618,415 @@ mod tests {
         std::env::remove_var("TEST_VAR");
     }
 
+    #[test]
+    fn test_env_substitution_default_value() {
+        std::env::remove_var("INFRACTL_TEST_UNSET");
+        std::env::set_var("INFRACTL_TEST_EMPTY", "");
+        std::env::set_var("INFRACTL_TEST_SET", "real");
+
+        let content = "a: ${INFRACTL_TEST_UNSET:-fallback}\nb: ${INFRACTL_TEST_EMPTY:-x}\nc: ${INFRACTL_TEST_SET:-x}";
+        let result = substitute_env_vars(content).unwrap();
+        assert_eq!(result, "a: fallback\nb: x\nc: real");
+
+        std::env::remove_var("INFRACTL_TEST_EMPTY");
+        std::env::remove_var("INFRACTL_TEST_SET");
+    }
+
+    #[test]
+    fn test_env_substitution_required_value() {
+        std::env::remove_var("INFRACTL_TEST_REQUIRED");
+
+        let err = substitute_env_vars("key: ${INFRACTL_TEST_REQUIRED:?set the API key}")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("INFRACTL_TEST_REQUIRED"));
+        assert!(err.contains("set the API key"));
+
+        let err = substitute_env_vars("key: ${INFRACTL_TEST_REQUIRED:?}")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("INFRACTL_TEST_REQUIRED"));
+
+        std::env::set_var("INFRACTL_TEST_REQUIRED", "present");
+        let result = substitute_env_vars("key: ${INFRACTL_TEST_REQUIRED:?missing}").unwrap();
+        assert_eq!(result, "key: present");
+        std::env::remove_var("INFRACTL_TEST_REQUIRED");
+    }
+
+    #[test]
+    fn test_env_substitution_missing_plain_var_blank() {
+        std::env::remove_var("INFRACTL_TEST_PLAIN_UNSET");
+        let result = substitute_env_vars("key: '${INFRACTL_TEST_PLAIN_UNSET}'").unwrap();
+        assert_eq!(result, "key: ''");
+    }
+
+    #[test]
+    fn test_secret_file_references() {
+        let dir = std::env::temp_dir().join(format!("infractl-secrets-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let jwt_file = dir.join("jwt");
+        let token_file = dir.join("token");
+        fs::write(&jwt_file, "0123456789abcdef0123456789abcdef\n").unwrap();
+        fs::write(&token_file, "s3cr3t").unwrap();
+
+        let config_path = dir.join("config.yaml");
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+mode: agent
+server: {{}}
+auth:
+  jwt_secret: "file://{jwt}"
+  webhook_secrets:
+    github: "file://{token}"
+modules:
+  deploy:
+    deployments:
+      - name: app
+        type: custom_script
+        script: /bin/true
+        env:
+          API_TOKEN: "file://{token}"
+          PLAIN: value
+        registry_auth:
+          registry: ghcr.io
+          username: deploy
+          password: "file://{token}"
+"#,
+                jwt = jwt_file.display(),
+                token = token_file.display()
+            ),
+        )
+        .unwrap();
+
+        let config = load_with_mode(&config_path, false).unwrap();
+        assert_eq!(config.auth.jwt_secret, "0123456789abcdef0123456789abcdef");
+        assert_eq!(config.auth.webhook_secrets["github"], "s3cr3t");
+        let env = &config.modules.deploy.deployments[0].env;
+        assert_eq!(env["API_TOKEN"], "s3cr3t");
+        assert_eq!(env["PLAIN"], "value");
+        let auth = config.modules.deploy.deployments[0]
+            .registry_auth
+            .as_ref()
+            .unwrap();
+        assert_eq!(auth.password, "s3cr3t");
+
+        fs::remove_file(&token_file).unwrap();
+        let err = load_with_mode(&config_path, false).unwrap_err().to_string();
+        assert!(err.contains("secret file"));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    fn trigger_test_config(deployments: &str) -> Config {
+        serde_yaml::from_str(&format!(
+            "mode: agent\nserver: {{}}\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\nmodules:\n  deploy:\n    external_deployments_path: null\n{}",
+            deployments
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_trigger_validation_ok() {
+        let config = trigger_test_config(
+            r#"    on_error: notify
+    deployments:
+      - name: build
+        type: custom_script
+        script: make
+        trigger: [deploy]
+      - name: deploy
+        type: custom_script
+        script: make deploy
+      - name: notify
+        type: custom_script
+        script: notify.sh
+        category: system
+"#,
+        );
+        assert!(validate(&config).is_ok());
+    }
+
+    #[test]
+    fn test_trigger_validation_cycle() {
+        let config = trigger_test_config(
+            r#"    deployments:
+      - name: a
+        type: custom_script
+        script: a.sh
+        on_success: b
+      - name: b
+        type: custom_script
+        script: b.sh
+        on_error: a
+"#,
+        );
+        let err = validate(&config).unwrap_err().to_string();
+        assert!(
+            err.contains("Trigger cycle detected: a -> b -> a"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_trigger_validation_global_cycle() {
+        // Global hooks fire for app deployments, so an app target re-triggers itself
+        let config = trigger_test_config(
+            r#"    on_error: notify
+    deployments:
+      - name: notify
+        type: custom_script
+        script: notify.sh
+"#,
+        );
+        let err = validate(&config).unwrap_err().to_string();
+        assert!(err.contains("notify -> notify"), "{}", err);
+    }
+
+    #[test]
+    fn test_trigger_validation_dangling() {
+        let config = trigger_test_config(
+            r#"    deployments:
+      - name: a
+        type: custom_script
+        script: a.sh
+        pipeline:
+          on_finish: missing
+"#,
+        );
+        let err = validate(&config).unwrap_err().to_string();
+        assert!(
+            err.contains("'a' triggers unknown deployment 'missing'"),
+            "{}",
+            err
+        );
+
+        let mut config = trigger_test_config(
+            r#"    deployments:
+      - name: a
+        type: custom_script
+        script: a.sh
+"#,
+        );
+        config.modules.webhooks.endpoints.push(WebhookEndpoint {
+            path: "/hooks/b".to_string(),
+            deployment: Some("b".to_string()),
+            event: None,
+            secret: None,
+            allowed_ips: Vec::new(),
+            schedule_constraint: None,
+            branch_filter: Vec::new(),
+            path_filter: Vec::new(),
+        });
+        let err = validate(&config).unwrap_err().to_string();
+        assert!(err.contains("unknown deployment 'b'"), "{}", err);
+    }
+
+    #[test]
+    fn test_deployment_templates() {
+        let dir = std::env::temp_dir().join(format!("infractl-templates-{}", std::process::id()));
+        let ext = dir.join("ext");
+        fs::create_dir_all(ext.join("deployments.d")).unwrap();
+
+        fs::write(
+            ext.join("deployments.d/stack.yaml"),
+            r#"
+- name: worker
+  extends: compose
+  path: /opt/apps/worker
+"#,
+        )
+        .unwrap();
+
+        let config_path = dir.join("config.yaml");
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+mode: agent
+server: {{}}
+auth:
+  jwt_secret: "0123456789abcdef0123456789abcdef"
+modules:
+  deploy:
+    external_deployments_path: "{}"
+    deployment_templates:
+      base:
+        ssh_key: /root/.ssh/deploy
+        env:
+          REGISTRY: ghcr.io
+          TIER: base
+      compose:
+        extends: base
+        type: docker_pull
+        compose_file: docker-compose.yml
+        pre_deploy: ["docker compose pull"]
+    deployments:
+      - name: api
+        extends: compose
+        path: /opt/apps/api
+        env:
+          TIER: api
+        pre_deploy: ["echo api"]
+"#,
+                ext.display()
+            ),
+        )
+        .unwrap();
+
+        let config = load_with_mode(&config_path, true).unwrap();
+        let deployments = &config.modules.deploy.deployments;
+
+        let api = &deployments[0];
+        assert_eq!(api.deploy_type, DeployType::DockerPull);
+        assert_eq!(api.ssh_key.as_deref(), Some("/root/.ssh/deploy"));
+        assert_eq!(api.compose_file.as_deref(), Some("docker-compose.yml"));
+        assert_eq!(api.env["REGISTRY"], "ghcr.io");
+        assert_eq!(api.env["TIER"], "api");
+        assert_eq!(api.pre_deploy.as_vec(), vec!["echo api"]);
+
+        let worker = &deployments[1];
+        assert_eq!(worker.name, "worker");
+        assert_eq!(worker.path.as_deref(), Some("/opt/apps/worker"));
+        assert_eq!(worker.env["TIER"], "base");
+        assert_eq!(worker.pre_deploy.as_vec(), vec!["docker compose pull"]);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_deployment_template_errors() {
+        let templates: HashMap<String, serde_json::Value> = [
+            ("a".to_string(), serde_json::json!({ "extends": "b" })),
+            ("b".to_string(), serde_json::json!({ "extends": "a" })),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut list = vec![serde_json::json!({ "name": "x", "extends": "a" })];
+        let err = expand_deployment_templates(&mut list, &templates)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("cycle detected: a -> b -> a"), "{}", err);
+
+        let mut list = vec![serde_json::json!({ "name": "x", "extends": "missing" })];
+        let err = expand_deployment_templates(&mut list, &templates)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Deployment 'x': "), "{}", err);
+        assert!(
+            err.contains("Unknown deployment template 'missing'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_config_profiles() {
+        let dir = std::env::temp_dir().join(format!("infractl-profiles-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.yaml");
+        fs::write(
+            &config_path,
+            r#"
+mode: home
+server:
+  port: 8111
+auth:
+  jwt_secret: "0123456789abcdef0123456789abcdef"
+agents:
+  - name: web
+    address: "http://10.0.0.1:8111"
+  - name: db
+    address: "http://10.0.0.2:8111"
+modules:
+  deploy:
+    external_deployments_path: null
+profiles:
+  staging:
+    agents:
+      - name: web
+        address: "http://10.1.0.1:8111"
+    modules:
+      storage:
+        retention:
+          hourly_data: "1d"
+"#,
+        )
+        .unwrap();
+
+        let base = load_with_profile(&config_path, true, None).unwrap();
+        assert_eq!(base.agents[0].address, "http://10.0.0.1:8111");
+        assert!(base.active_profile.is_none());
+
+        let staging = load_with_profile(&config_path, true, Some("staging")).unwrap();
+        assert_eq!(staging.active_profile.as_deref(), Some("staging"));
+        assert_eq!(staging.server.port, 8111);
+        assert_eq!(staging.agents.len(), 2);
+        assert_eq!(staging.agents[0].address, "http://10.1.0.1:8111");
+        assert_eq!(staging.agents[1].address, "http://10.0.0.2:8111");
+        assert_eq!(staging.modules.storage.retention.hourly_data, "1d");
+
+        let err = load_with_profile(&config_path, true, Some("prod"))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("Unknown config profile 'prod' (available: staging)"),
+            "{}",
+            err
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_config_without_deploy_section() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        fs::write(
+            &config_path,
+            "mode: agent\nserver:\n  port: 8111\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\n",
+        )
+        .unwrap();
+
+        let config = load_with_profile(&config_path, true, None).unwrap();
+        assert!(!config.modules.deploy.enabled);
+    }
+
+    #[test]
+    fn test_encrypted_values() {
+        let identity = age::x25519::Identity::generate();
+        let recipient = identity.to_public().to_string();
+
+        let encrypted = encrypt_value(&recipient, "webhook-s3cr3t").unwrap();
+        assert!(encrypted.starts_with("ENC[age:"));
+        assert!(!encrypted.contains("webhook-s3cr3t"));
+
+        let identities: Vec<Box<dyn age::Identity>> = vec![Box::new(identity)];
+        let mut resolver = SecretResolver {
+            identities: Some(identities),
+        };
+        assert_eq!(
+            resolver.resolve(&encrypted, "test").unwrap(),
+            "webhook-s3cr3t"
+        );
+        assert_eq!(resolver.resolve("plain", "test").unwrap(), "plain");
+
+        // A different key cannot decrypt the value
+        let other: Vec<Box<dyn age::Identity>> = vec![Box::new(age::x25519::Identity::generate())];
+        let mut resolver = SecretResolver {
+            identities: Some(other),
+        };
+        let err = resolver.resolve(&encrypted, "auth.jwt_secret").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Failed to decrypt auth.jwt_secret"));
+
+        assert!(encrypt_value("not-a-recipient", "x").is_err());
+    }
+
     #[test]
     fn test_ip_allowed() {
         let networks = vec!["10.0.0.0/8".to_string(), "127.0.0.1/32".to_string()];
@@ -946,6 +4036,28 @@ mod tests {
         assert!(!is_ip_allowed(&"8.8.8.8".parse().unwrap(), &networks));
     }
 
+    #[test]
+    fn test_fleet_ips() {
+        let yaml = r#"
+mode: home
+server:
+  home_address: "https://[fd00::1]:8111"
+auth:
+  jwt_secret: "0123456789abcdef0123456789abcdef"
+agents:
+  - name: web
+    address: "10.0.0.2:8111"
+  - name: db
+    address: "http://db.internal:8111"
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let ips = config.fleet_ips();
+
+        assert_eq!(ips.len(), 2);
+        assert!(ips.contains(&"10.0.0.2".parse().unwrap()));
+        assert!(ips.contains(&"fd00::1".parse().unwrap()));
+    }
+
     #[test]
     fn test_trigger_config_none() {
         let trigger = TriggerConfig::None;
@@ -1067,6 +4179,29 @@ on_error:
         assert_eq!(cfg.on_error.as_vec(), vec!["rollback", "notify"]);
     }
 
+    #[test]
+    fn test_command_list_per_command_user() {
+        let yaml = r#"
+name: test
+type: custom_script
+script: echo hello
+user: deploy
+pre_deploy:
+  - docker compose pull
+  - cmd: chown -R www-data /srv/app/cache
+    user: root
+"#;
+        let cfg: DeploymentConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            cfg.pre_deploy.entries(),
+            vec![
+                ("docker compose pull", None),
+                ("chown -R www-data /srv/app/cache", Some("root")),
+            ]
+        );
+        assert_eq!(cfg.user.as_deref(), Some("deploy"));
+    }
+
     #[test]
     fn test_pipeline_config_deserialize() {
         let yaml = r#"
@@ -1252,4 +4387,429 @@ deployments: []
         let cfg: DeployConfig = serde_yaml::from_str(yaml).unwrap();
         assert!(cfg.allowed_deploy_paths.is_empty());
     }
+
+    #[test]
+    fn test_container_runtime_config() {
+        let cfg: DeployConfig = serde_yaml::from_str("deployments: []").unwrap();
+        assert_eq!(cfg.container_runtime, ContainerRuntime::Auto);
+
+        let cfg: DeployConfig =
+            serde_yaml::from_str("container_runtime: podman\ndeployments: []").unwrap();
+        assert_eq!(cfg.container_runtime, ContainerRuntime::Podman);
+        assert_eq!(cfg.container_runtime.command(), "podman");
+        assert_eq!(ContainerRuntime::Docker.resolve(), ContainerRuntime::Docker);
+    }
+    #[test]
+    fn test_config_format_from_path() {
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("/etc/infractl/config.yaml")),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("config.yml")),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("config.toml")),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("config.JSON")),
+            ConfigFormat::Json
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("config")),
+            ConfigFormat::Yaml
+        );
+        assert!(ConfigFormat::is_config_file(Path::new("app.TOML")));
+        assert!(ConfigFormat::is_config_file(Path::new("app.Yml")));
+        assert!(!ConfigFormat::is_config_file(Path::new("app.yaml.bak")));
+    }
+
+    #[test]
+    fn test_load_toml_config_with_external_deployments() {
+        let dir = tempfile::tempdir().unwrap();
+        let ext = dir.path().join("deployments.d");
+        fs::create_dir_all(&ext).unwrap();
+        fs::write(
+            ext.join("web.toml"),
+            r#"
+[[deployments]]
+name = "web"
+type = "custom_script"
+script = "echo web"
+"#,
+        )
+        .unwrap();
+
+        let config_path = dir.path().join("config.toml");
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+mode = "agent"
+
+[server]
+port = 9000
+
+[auth]
+jwt_secret = "0123456789abcdef0123456789abcdef"
+
+[modules.deploy]
+external_deployments_path = "{}"
+
+[[modules.deploy.deployments]]
+name = "api"
+type = "custom_script"
+script = "echo api"
+on_success = ["web"]
+"#,
+                dir.path().display()
+            ),
+        )
+        .unwrap();
+
+        let config = load(&config_path).unwrap();
+        assert_eq!(config.mode, Mode::Agent);
+        assert_eq!(config.server.port, 9000);
+        let names: Vec<_> = config
+            .modules
+            .deploy
+            .deployments
+            .iter()
+            .map(|d| d.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["api", "web"]);
+    }
+
+    #[test]
+    fn test_deployment_vars_interpolation() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("deployments.yaml"),
+            r#"
+- name: "api-{{ env }}"
+  type: custom_script
+  path: "/opt/apps/api-{{ env }}"
+  script: "docker inspect --format '{{.State.Status}}' api-{{env}}"
+  vars:
+    env: prod
+- name: worker
+  type: custom_script
+  path: "/opt/apps/worker-{{ env }}"
+  script: "echo {{ region }}"
+"#,
+        )
+        .unwrap();
+
+        let config_path = dir.path().join("config.yaml");
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+mode: agent
+server: {{}}
+auth:
+  jwt_secret: "0123456789abcdef0123456789abcdef"
+modules:
+  deploy:
+    external_deployments_path: "{}"
+    vars:
+      env: staging
+      region: eu-west
+"#,
+                dir.path().display()
+            ),
+        )
+        .unwrap();
+
+        let config = load_with_mode(&config_path, true).unwrap();
+        let api = &config.modules.deploy.deployments[0];
+        assert_eq!(api.name, "api-prod");
+        assert_eq!(api.path.as_deref(), Some("/opt/apps/api-prod"));
+        assert_eq!(
+            api.script.as_deref(),
+            Some("docker inspect --format '{{.State.Status}}' api-prod")
+        );
+        let worker = &config.modules.deploy.deployments[1];
+        assert_eq!(worker.path.as_deref(), Some("/opt/apps/worker-staging"));
+        assert_eq!(worker.script.as_deref(), Some("echo eu-west"));
+
+        // Templates of other tools are not variables; `\{{` escapes a declared one
+        let literal: Vec<DeploymentConfig> = serde_yaml::from_str(
+            r#"
+- name: chart
+  type: custom_script
+  script: make
+  pre_deploy:
+    - "helm template . --set image={{ .Values.image }}"
+    - "docker ps --format '{{json .Names}}'"
+    - "ansible all -m debug -a 'msg={{ inventory_hostname }}'"
+    - 'echo \{{ env }} is {{ env }}'
+"#,
+        )
+        .unwrap();
+        let vars = HashMap::from([("env".to_string(), "prod".to_string())]);
+        let chart = &interpolate_deployment_vars(literal, &vars).unwrap()[0];
+        assert_eq!(
+            chart.pre_deploy.as_vec(),
+            [
+                "helm template . --set image={{ .Values.image }}",
+                "docker ps --format '{{json .Names}}'",
+                "ansible all -m debug -a 'msg={{ inventory_hostname }}'",
+                "echo {{ env }} is prod",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dependency_cycle_rejected() {
+        let deployments =
+            |yaml: &str| -> Vec<DeploymentConfig> { serde_yaml::from_str(yaml).unwrap() };
+
+        let ok = deployments(
+            "- {name: db, type: custom_script}\n\
+             - {name: api, type: custom_script, depends_on: [db]}\n\
+             - {name: web, type: custom_script, depends_on: [api, db], depends_max_age: 1h}",
+        );
+        assert!(validate_dependencies(&ok).is_ok());
+
+        let cycle = deployments(
+            "- {name: db, type: custom_script, depends_on: [web]}\n\
+             - {name: api, type: custom_script, depends_on: [db]}\n\
+             - {name: web, type: custom_script, depends_on: [api]}",
+        );
+        let err = validate_dependencies(&cycle).unwrap_err().to_string();
+        assert!(err.contains("db -> web -> api -> db"), "{}", err);
+
+        let unknown = deployments("- {name: api, type: custom_script, depends_on: [db]}");
+        assert!(validate_dependencies(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_load_json_config_with_external_deployments() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("deployments.json"),
+            r#"[{"name": "worker", "type": "custom_script", "script": "echo worker"}]"#,
+        )
+        .unwrap();
+
+        let config_path = dir.path().join("config.json");
+        fs::write(
+            &config_path,
+            serde_json::json!({
+                "mode": "home",
+                "server": {},
+                "auth": { "jwt_secret": "0123456789abcdef0123456789abcdef" },
+                "modules": {
+                    "deploy": {
+                        "external_deployments_path": dir.path().to_string_lossy()
+                    }
+                }
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let config = load(&config_path).unwrap();
+        assert_eq!(config.mode, Mode::Home);
+        assert_eq!(config.modules.deploy.deployments.len(), 1);
+        assert_eq!(config.modules.deploy.deployments[0].name, "worker");
+    }
+    #[test]
+    fn test_merge_values_named_lists_and_maps() {
+        let mut base = serde_json::json!({
+            "server": { "port": 8111, "bind": "0.0.0.0" },
+            "agents": [{ "name": "a", "address": "10.0.0.1:8111" }],
+            "allowed": ["10.0.0.0/8"]
+        });
+        let overlay = serde_json::json!({
+            "server": { "port": 9000 },
+            "agents": [
+                { "name": "a", "address": "10.0.0.9:8111" },
+                { "name": "b", "address": "10.0.0.2:8111" }
+            ],
+            "allowed": ["192.168.0.0/16"]
+        });
+        merge_values(&mut base, overlay);
+
+        assert_eq!(base["server"]["port"], 9000);
+        assert_eq!(base["server"]["bind"], "0.0.0.0");
+        assert_eq!(base["agents"].as_array().unwrap().len(), 2);
+        assert_eq!(base["agents"][0]["address"], "10.0.0.9:8111");
+        assert_eq!(base["allowed"], serde_json::json!(["192.168.0.0/16"]));
+    }
+
+    #[test]
+    fn test_load_config_with_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("conf.d")).unwrap();
+        fs::write(
+            dir.path().join("conf.d/10-shared.yaml"),
+            r#"
+server:
+  port: 9100
+modules:
+  deploy:
+    deployments:
+      - name: api
+        type: custom_script
+        script: echo shared
+"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("conf.d/20-host.toml"),
+            r#"
+[server]
+bind = "10.0.0.5"
+"#,
+        )
+        .unwrap();
+
+        let config_path = dir.path().join("config.yaml");
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+include:
+  - "conf.d/*"
+mode: agent
+server:
+  port: 8111
+auth:
+  jwt_secret: "0123456789abcdef0123456789abcdef"
+modules:
+  deploy:
+    external_deployments_path: "{}"
+"#,
+                dir.path().display()
+            ),
+        )
+        .unwrap();
+
+        let config = load(&config_path).unwrap();
+        assert_eq!(config.server.port, 9100);
+        assert_eq!(config.server.bind, "10.0.0.5");
+        assert_eq!(config.modules.deploy.deployments.len(), 1);
+        assert_eq!(config.include, vec!["conf.d/*".to_string()]);
+    }
+
+    #[test]
+    fn test_include_cycle_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.yaml"), "include: b.yaml\nmode: agent\n").unwrap();
+        fs::write(dir.path().join("b.yaml"), "include: a.yaml\n").unwrap();
+
+        let err = load(&dir.path().join("a.yaml")).unwrap_err();
+        assert!(err.to_string().contains("cycle"));
+    }
+
+    #[test]
+    fn test_missing_include_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("config.yaml"),
+            "include: [missing.yaml]\nmode: agent\n",
+        )
+        .unwrap();
+
+        let err = load(&dir.path().join("config.yaml")).unwrap_err();
+        assert!(err.to_string().contains("not found"));
+    }
+    #[test]
+    fn test_unknown_keys_rejected_in_strict_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+mode: agent
+server: {{}}
+auth:
+  jwt_secret: "0123456789abcdef0123456789abcdef"
+modules:
+  deploy:
+    external_deployments_path: "{}"
+    deployments:
+      - name: api
+        type: custom_script
+        script: echo api
+        post_depoly: echo typo
+"#,
+                dir.path().display()
+            ),
+        )
+        .unwrap();
+
+        let err = load_with_mode(&config_path, true).unwrap_err();
+        assert!(err.to_string().contains("post_depoly"));
+
+        // Lenient mode ignores the key
+        let config = load_with_mode(&config_path, false).unwrap();
+        assert_eq!(config.modules.deploy.deployments.len(), 1);
+    }
+
+    #[test]
+    fn test_unknown_keys_in_external_deployments_strict() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("deployments.yaml"),
+            "- name: web\n  type: custom_script\n  script: echo\n  force: true\n",
+        )
+        .unwrap();
+        let config_path = dir.path().join("config.yaml");
+        fs::write(
+            &config_path,
+            format!(
+                "mode: agent\nserver: {{}}\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\nmodules:\n  deploy:\n    external_deployments_path: \"{}\"\n",
+                dir.path().display()
+            ),
+        )
+        .unwrap();
+
+        let err = load_with_mode(&config_path, true).unwrap_err();
+        assert!(err.to_string().contains("force"));
+    }
+
+    #[test]
+    fn test_save_pushed_deployment() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        fs::write(
+            &config_path,
+            format!(
+                "mode: agent\nserver: {{}}\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\nmodules:\n  deploy:\n    external_deployments_path: \"{}\"\n",
+                dir.path().display()
+            ),
+        )
+        .unwrap();
+        let ext = dir.path().to_string_lossy().to_string();
+
+        let web: DeploymentConfig =
+            serde_yaml::from_str("name: web\ntype: custom_script\nscript: echo deployed\n")
+                .unwrap();
+        let deploy = save_pushed_deployment(&config_path, &ext, &web).unwrap();
+        assert!(deploy.deployments.iter().any(|d| d.name == "web"));
+        assert!(pushed_deployment_path(&ext, "web").exists());
+
+        // A deployment the config rejects is not left behind
+        let broken: DeploymentConfig =
+            serde_yaml::from_str("name: api\ntype: custom_script\nextends: missing\n").unwrap();
+        assert!(save_pushed_deployment(&config_path, &ext, &broken).is_err());
+        assert!(!pushed_deployment_path(&ext, "api").exists());
+        assert!(load(&config_path).is_ok());
+    }
+
+    #[test]
+    fn test_agent_matches_tags() {
+        let agent = AgentConfig::new("edge-1", "10.0.0.5:8111", vec!["web".into(), "edge".into()]);
+        assert!(agent.matches_tags(None));
+        assert!(agent.matches_tags(Some("web")));
+        assert!(agent.matches_tags(Some("web, edge")));
+        assert!(!agent.matches_tags(Some("web,db")));
+        assert!(!agent.matches_tags(Some("db")));
+    }
 }
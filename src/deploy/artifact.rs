@@ -0,0 +1,162 @@
+use crate::config::ReleaseArtifactConfig;
+use crate::http_client::HttpClient;
+use crate::updater::binary::BinaryUpdater;
+use crate::updater::github::{parse_checksum, GitHubClient};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tracing::info;
+
+/// Checksum of the artifact installed in a deployment path, used to skip unchanged deploys
+const INSTALLED_FILE: &str = ".infractl-artifact";
+
+pub struct ReleaseArtifactDeploy {
+    http: HttpClient,
+}
+
+impl ReleaseArtifactDeploy {
+    pub fn new(http: HttpClient) -> Self {
+        Self { http }
+    }
+
+    /// Download a GitHub release asset, verify its checksum and install it into `path`.
+    /// Returns (output, has_changes); an asset that is already installed is skipped.
+    pub async fn deploy(
+        &self,
+        config: &ReleaseArtifactConfig,
+        path: &str,
+    ) -> Result<(String, bool), String> {
+        let github = GitHubClient::new(&config.repo, self.http.clone());
+        let release = match config.tag.as_deref() {
+            Some(tag) => github.get_release_by_tag(tag).await?,
+            None => github.get_latest_release(config.prerelease).await?,
+        };
+        let asset = release
+            .assets
+            .iter()
+            .find(|a| a.name == config.asset)
+            .ok_or_else(|| {
+                format!(
+                    "Asset '{}' not found in release {}",
+                    config.asset, release.tag_name
+                )
+            })?;
+
+        let expected = match config.sha256.as_deref() {
+            Some(sha256) => sha256.to_lowercase(),
+            None => {
+                let checksums = github.download_checksum(&release).await?;
+                parse_checksum(&checksums, &asset.name)
+                    .ok_or_else(|| format!("No checksum for '{}' in release", asset.name))?
+                    .to_lowercase()
+            }
+        };
+
+        let mut output = format!("[release] {} {}\n", config.repo, release.tag_name);
+        if installed_checksum(path).as_deref() == Some(expected.as_str()) {
+            output.push_str(&format!("[release] {} already installed\n", asset.name));
+            return Ok((output, false));
+        }
+
+        let data = github.download_asset(asset).await?;
+        BinaryUpdater::verify_checksum(&data, &expected)?;
+        output.push_str(&format!(
+            "[download] {} ({} bytes, sha256 verified)\n",
+            asset.name,
+            data.len()
+        ));
+
+        output.push_str(&install(data, asset.name.clone(), path.to_string(), expected).await?);
+        Ok((output, true))
+    }
+}
+
+/// Install a verified artifact into `path`: `.tar.gz`/`.tgz` archives are
+/// unpacked, anything else is written as an executable file of that name
+pub async fn install(
+    data: Vec<u8>,
+    name: String,
+    path: String,
+    sha256: String,
+) -> Result<String, String> {
+    tokio::task::spawn_blocking(move || install_blocking(&data, &name, Path::new(&path), &sha256))
+        .await
+        .map_err(|e| format!("Install task failed: {}", e))?
+}
+
+fn install_blocking(data: &[u8], name: &str, path: &Path, sha256: &str) -> Result<String, String> {
+    fs::create_dir_all(path)
+        .map_err(|e| format!("Failed to create '{}': {}", path.display(), e))?;
+
+    let output = if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        // `unpack` refuses entries with absolute paths or `..`
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(data));
+        archive
+            .unpack(path)
+            .map_err(|e| format!("Failed to unpack '{}': {}", name, e))?;
+        format!("[unpack] {} -> {}\n", name, path.display())
+    } else {
+        // Replace atomically so a running binary is never half-written
+        let target = path.join(name);
+        let temp = path.join(format!(".{}.new", name));
+        fs::write(&temp, data).map_err(|e| format!("Failed to write '{}': {}", name, e))?;
+        fs::set_permissions(&temp, fs::Permissions::from_mode(0o755))
+            .map_err(|e| format!("Failed to set permissions on '{}': {}", name, e))?;
+        fs::rename(&temp, &target)
+            .map_err(|e| format!("Failed to install '{}': {}", target.display(), e))?;
+        format!("[install] {}\n", target.display())
+    };
+
+    fs::write(path.join(INSTALLED_FILE), format!("{}\n", sha256))
+        .map_err(|e| format!("Failed to record installed artifact: {}", e))?;
+    info!(artifact = %name, path = %path.display(), "Artifact installed");
+    Ok(output)
+}
+
+/// SHA256 of the artifact last installed into `path`
+pub fn installed_checksum(path: &str) -> Option<String> {
+    fs::read_to_string(Path::new(path).join(INSTALLED_FILE))
+        .ok()
+        .map(|checksum| checksum.trim().to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tarball(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        let mut builder = tar::Builder::new(encoder);
+        for (name, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *contents).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_install_unpacks_tarball_and_records_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = tarball(&[("app/server", b"binary"), ("app/config.toml", b"port = 1")]);
+
+        let output = install_blocking(&data, "app.tar.gz", dir.path(), "abc123").unwrap();
+        assert!(output.starts_with("[unpack] app.tar.gz"));
+        assert_eq!(fs::read(dir.path().join("app/server")).unwrap(), b"binary");
+
+        let path = dir.path().to_str().unwrap();
+        assert_eq!(installed_checksum(path).as_deref(), Some("abc123"));
+    }
+
+    #[test]
+    fn test_install_plain_file_is_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        install_blocking(b"#!/bin/sh\n", "tool", dir.path(), "def456").unwrap();
+
+        let meta = fs::metadata(dir.path().join("tool")).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o777, 0o755);
+        assert!(!dir.path().join(".tool.new").exists());
+    }
+}
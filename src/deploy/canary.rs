@@ -0,0 +1,151 @@
+use crate::config::{CanaryConfig, ContainerRuntime};
+use crate::metrics::{ContainerInfo, ContainerStats, DockerCollector};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Watch the canary containers for the observation window.
+/// Fails as soon as one of them stops running or exceeds a threshold.
+pub async fn observe(
+    container_ids: &[String],
+    config: &CanaryConfig,
+    runtime: ContainerRuntime,
+) -> Result<String, String> {
+    let observation =
+        humantime::parse_duration(&config.observation).unwrap_or(Duration::from_secs(120));
+    let interval = humantime::parse_duration(&config.interval).unwrap_or(Duration::from_secs(10));
+
+    let collector = DockerCollector::new(runtime)
+        .await
+        .map_err(|e| format!("Cannot connect to Docker for canary observation: {}", e))?;
+
+    info!(
+        services = ?config.services,
+        observation = ?observation,
+        "Observing canary"
+    );
+
+    let deadline = Instant::now() + observation;
+    let mut samples = 0;
+    loop {
+        let metrics = collector.collect().await;
+        if let Err(e) = check(&metrics.containers, container_ids, config) {
+            warn!(error = %e, "Canary unhealthy");
+            return Err(e);
+        }
+        samples += 1;
+
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        tokio::time::sleep(interval.min(deadline - now)).await;
+    }
+
+    Ok(format!(
+        "[canary] {} healthy for {:?} ({} samples)\n",
+        config.services.join(", "),
+        observation,
+        samples
+    ))
+}
+
+/// Check one sample of the canary containers (matched by id prefix, as
+/// `docker compose ps -q` returns full ids and the collector short ones)
+fn check(
+    containers: &[ContainerInfo],
+    container_ids: &[String],
+    config: &CanaryConfig,
+) -> Result<(), String> {
+    let canaries: Vec<&ContainerInfo> = containers
+        .iter()
+        .filter(|c| !c.id.is_empty() && container_ids.iter().any(|id| id.starts_with(&c.id)))
+        .collect();
+
+    if canaries.is_empty() {
+        return Err(format!(
+            "no containers found for canary services {}",
+            config.services.join(", ")
+        ));
+    }
+
+    for container in canaries {
+        if container.state != "running" {
+            return Err(format!(
+                "container {} is {} ({})",
+                container.name, container.state, container.status
+            ));
+        }
+        if let Some(exceeded) = container.stats.as_ref().and_then(|s| exceeded(s, config)) {
+            return Err(format!("container {} {}", container.name, exceeded));
+        }
+    }
+
+    Ok(())
+}
+
+/// Describe the first threshold a container exceeds
+fn exceeded(stats: &ContainerStats, config: &CanaryConfig) -> Option<String> {
+    if stats.cpu_percent > config.max_cpu_percent {
+        return Some(format!(
+            "CPU {:.1}% exceeds {:.1}%",
+            stats.cpu_percent, config.max_cpu_percent
+        ));
+    }
+    if stats.memory_percent > config.max_memory_percent {
+        return Some(format!(
+            "memory {:.1}% exceeds {:.1}%",
+            stats.memory_percent, config.max_memory_percent
+        ));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container(id: &str, state: &str, cpu: f64) -> ContainerInfo {
+        ContainerInfo {
+            id: id.to_string(),
+            name: format!("app-{}", id),
+            image: "app:latest".to_string(),
+            state: state.to_string(),
+            status: "Up 5 seconds".to_string(),
+            created: 0,
+            stats: Some(ContainerStats {
+                cpu_percent: cpu,
+                memory_usage: 0,
+                memory_limit: 1,
+                memory_percent: 10.0,
+                network_rx_bytes: 0,
+                network_tx_bytes: 0,
+                block_read_bytes: 0,
+                block_write_bytes: 0,
+                pids: 1,
+            }),
+            compose_project: None,
+            compose_service: Some("api".to_string()),
+        }
+    }
+
+    fn config() -> CanaryConfig {
+        serde_yaml::from_str("services: [api]").unwrap()
+    }
+
+    #[test]
+    fn test_canary_check() {
+        let ids = vec!["aaaaaaaaaaaa0123".to_string()];
+        let other = container("bbbbbbbbbbbb", "exited", 0.0);
+
+        let healthy = [container("aaaaaaaaaaaa", "running", 5.0), other.clone()];
+        assert!(check(&healthy, &ids, &config()).is_ok());
+
+        let busy = [container("aaaaaaaaaaaa", "running", 99.0)];
+        assert!(check(&busy, &ids, &config()).unwrap_err().contains("CPU"));
+
+        let crashed = [container("aaaaaaaaaaaa", "restarting", 0.0)];
+        assert!(check(&crashed, &ids, &config()).is_err());
+
+        assert!(check(&[other], &ids, &config()).is_err());
+    }
+}
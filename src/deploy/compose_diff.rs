@@ -0,0 +1,198 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Difference between the compose config last applied and the one on disk
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct ComposeDiff {
+    /// No applied config was recorded yet (first deploy)
+    pub first_deploy: bool,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<ServiceChange>,
+    /// Changed top-level sections other than services (networks, volumes, ...)
+    pub other: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ServiceChange {
+    pub service: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<ImageChange>,
+    /// Changed service keys besides `image` (environment, ports, ...)
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ImageChange {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+impl ComposeDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.other.is_empty()
+    }
+
+    /// Human-readable summary for the deploy output
+    pub fn summary(&self) -> String {
+        if self.first_deploy {
+            return "first deploy, no applied config recorded\n".to_string();
+        }
+        if self.is_empty() {
+            return "no changes\n".to_string();
+        }
+
+        let mut out = String::new();
+        for service in &self.added {
+            out.push_str(&format!("+ {}\n", service));
+        }
+        for service in &self.removed {
+            out.push_str(&format!("- {}\n", service));
+        }
+        for change in &self.changed {
+            let mut parts = Vec::new();
+            if let Some(ref image) = change.image {
+                parts.push(format!(
+                    "image {} -> {}",
+                    image.from.as_deref().unwrap_or("none"),
+                    image.to.as_deref().unwrap_or("none")
+                ));
+            }
+            if !change.fields.is_empty() {
+                parts.push(change.fields.join(", "));
+            }
+            out.push_str(&format!("~ {}: {}\n", change.service, parts.join("; ")));
+        }
+        for section in &self.other {
+            out.push_str(&format!("~ [{}]\n", section));
+        }
+        out
+    }
+}
+
+/// Diff two rendered configs (`docker compose config --format json`)
+pub fn diff_compose(old: &Value, new: &Value) -> ComposeDiff {
+    let empty = serde_json::Map::new();
+    let old_services = old["services"].as_object().unwrap_or(&empty);
+    let new_services = new["services"].as_object().unwrap_or(&empty);
+
+    let mut diff = ComposeDiff::default();
+
+    for (name, new_service) in new_services {
+        let Some(old_service) = old_services.get(name) else {
+            diff.added.push(name.clone());
+            continue;
+        };
+        if old_service == new_service {
+            continue;
+        }
+
+        let image = (old_service["image"] != new_service["image"]).then(|| ImageChange {
+            from: old_service["image"].as_str().map(String::from),
+            to: new_service["image"].as_str().map(String::from),
+        });
+        diff.changed.push(ServiceChange {
+            service: name.clone(),
+            image,
+            fields: changed_keys(old_service, new_service)
+                .into_iter()
+                .filter(|key| key != "image")
+                .collect(),
+        });
+    }
+
+    diff.removed = old_services
+        .keys()
+        .filter(|name| !new_services.contains_key(*name))
+        .cloned()
+        .collect();
+
+    // The project name follows the directory, not the file contents
+    diff.other = changed_keys(old, new)
+        .into_iter()
+        .filter(|key| key != "services" && key != "name")
+        .collect();
+
+    diff
+}
+
+/// Keys whose values differ between two JSON objects, sorted
+fn changed_keys(old: &Value, new: &Value) -> Vec<String> {
+    let empty = serde_json::Map::new();
+    let old = old.as_object().unwrap_or(&empty);
+    let new = new.as_object().unwrap_or(&empty);
+
+    let mut keys: Vec<String> = old
+        .keys()
+        .chain(new.keys())
+        .filter(|key| old.get(*key) != new.get(*key))
+        .cloned()
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// Where the config applied by the last successful deploy is kept:
+/// `.<compose file>.applied.json` next to the compose file
+pub fn applied_config_path(compose_file: &str) -> PathBuf {
+    let path = Path::new(compose_file);
+    let name = path
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_else(|| "docker-compose.yml".to_string());
+    path.with_file_name(format!(".{}.applied.json", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_compose() {
+        let old = json!({
+            "name": "app",
+            "services": {
+                "api": { "image": "ghcr.io/acme/api:1.2", "environment": { "LOG": "info" } },
+                "worker": { "image": "ghcr.io/acme/worker:1.2" },
+                "cron": { "image": "ghcr.io/acme/cron:1.0" }
+            }
+        });
+        let new = json!({
+            "name": "app",
+            "services": {
+                "api": { "image": "ghcr.io/acme/api:1.3", "environment": { "LOG": "debug" } },
+                "worker": { "image": "ghcr.io/acme/worker:1.2" },
+                "redis": { "image": "redis:7" }
+            },
+            "volumes": { "data": {} }
+        });
+
+        let diff = diff_compose(&old, &new);
+        assert_eq!(diff.added, vec!["redis"]);
+        assert_eq!(diff.removed, vec!["cron"]);
+        assert_eq!(
+            diff.changed,
+            vec![ServiceChange {
+                service: "api".to_string(),
+                image: Some(ImageChange {
+                    from: Some("ghcr.io/acme/api:1.2".to_string()),
+                    to: Some("ghcr.io/acme/api:1.3".to_string()),
+                }),
+                fields: vec!["environment".to_string()],
+            }]
+        );
+        assert_eq!(diff.other, vec!["volumes"]);
+        assert!(diff_compose(&new, &new).is_empty());
+
+        assert_eq!(
+            applied_config_path("/opt/apps/api/docker-compose.yml"),
+            PathBuf::from("/opt/apps/api/.docker-compose.yml.applied.json")
+        );
+    }
+}
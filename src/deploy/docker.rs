@@ -1,24 +1,33 @@
-use crate::config::DeployStrategy;
+use super::canary;
+use super::compose_diff::{applied_config_path, diff_compose, ComposeDiff};
+use crate::config::{
+    CanaryConfig, ContainerRuntime, DeployStrategy, DeploymentConfig, RegistryAuth,
+};
+use std::collections::HashMap;
 use std::path::Path;
 use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
 use tracing::{debug, info};
 
-pub struct DockerDeploy;
+pub struct DockerDeploy {
+    runtime: ContainerRuntime,
+}
 
 impl DockerDeploy {
-    pub fn new() -> Self {
-        Self
+    pub fn new(runtime: ContainerRuntime) -> Self {
+        Self { runtime }
     }
 
-    /// Pull images and restart containers using docker-compose
+    /// Pull images and restart containers using docker-compose.
+    /// Returns the output and the `image@digest` references of the pulled images.
     pub async fn pull_and_restart(
         &self,
         compose_file: &str,
-        services: &[String],
-        prune: bool,
-        strategy: &DeployStrategy,
-    ) -> Result<String, String> {
+        config: &DeploymentConfig,
+    ) -> Result<(String, Vec<String>), String> {
+        let services = config.services.as_slice();
+        let strategy = config.strategy.clone().unwrap_or_default();
         let compose_path = Path::new(compose_file);
         let working_dir = compose_path
             .parent()
@@ -32,6 +41,26 @@ impl DockerDeploy {
 
         let mut output = String::new();
 
+        // Remember the current images, to keep them reachable for rollback
+        let snapshot = match self
+            .snapshot_images(&working_dir, &compose_filename, services)
+            .await
+        {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                output.push_str(&format!("[rollback] Warning: {}\n", e));
+                Vec::new()
+            }
+        };
+
+        if let Some(ref auth) = config.registry_auth {
+            let login_output = self.login(auth).await?;
+            output.push_str(&format!(
+                "[docker login {}]\n{}\n",
+                auth.registry, login_output
+            ));
+        }
+
         // Pull images
         info!("Pulling Docker images");
         let pull_output = self
@@ -39,6 +68,24 @@ impl DockerDeploy {
             .await?;
         output.push_str(&format!("[docker compose pull]\n{}\n", pull_output));
 
+        let tagged = self.tag_previous_images(&snapshot).await;
+        if !tagged.is_empty() {
+            output.push_str(&format!("[rollback] tagged {}\n", tagged.join(", ")));
+        }
+
+        // Refuse to start images whose digest differs from the pinned one
+        let image_digests = self
+            .verify_digests(
+                &working_dir,
+                &compose_filename,
+                services,
+                &config.image_digests,
+            )
+            .await?;
+        for digest in &image_digests {
+            output.push_str(&format!("[digest] {}\n", digest));
+        }
+
         // Apply strategy
         match strategy {
             DeployStrategy::Default => {
@@ -77,10 +124,26 @@ impl DockerDeploy {
                     .await?;
                 output.push_str(&format!("[docker compose restart]\n{}\n", restart_output));
             }
+            DeployStrategy::Canary => {
+                let canary = config
+                    .canary
+                    .as_ref()
+                    .ok_or("strategy canary requires 'canary' config")?;
+                let canary_output = self
+                    .canary_rollout(
+                        compose_file,
+                        &working_dir,
+                        &compose_filename,
+                        services,
+                        canary,
+                    )
+                    .await?;
+                output.push_str(&canary_output);
+            }
         }
 
         // Prune old images if requested
-        if prune {
+        if config.prune {
             info!("Pruning old images");
             match self.prune_images().await {
                 Ok(prune_output) => {
@@ -92,6 +155,298 @@ impl DockerDeploy {
             }
         }
 
+        Ok((output, image_digests))
+    }
+
+    /// Start the canary services on the new images, observe them, then either
+    /// promote (start everything else) or roll the canary services back
+    async fn canary_rollout(
+        &self,
+        compose_file: &str,
+        working_dir: &str,
+        compose_filename: &str,
+        services: &[String],
+        canary: &CanaryConfig,
+    ) -> Result<String, String> {
+        let mut output = String::new();
+
+        info!(services = ?canary.services, "Starting canary services");
+        let up_output = self
+            .run_compose_command(working_dir, compose_filename, "up", &canary.services)
+            .await?;
+        output.push_str(&format!(
+            "[canary] docker compose up -d {}\n{}\n",
+            canary.services.join(" "),
+            up_output
+        ));
+
+        let container_ids: Vec<String> = self
+            .run_compose_command_extra(
+                working_dir,
+                compose_filename,
+                "ps",
+                &["-a", "-q"],
+                &canary.services,
+            )
+            .await?
+            .lines()
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect();
+
+        match canary::observe(&container_ids, canary, self.runtime).await {
+            Ok(observe_output) => output.push_str(&observe_output),
+            Err(reason) => {
+                let rollback = match self.rollback(compose_file, &canary.services).await {
+                    Ok(_) => "canary services rolled back".to_string(),
+                    Err(e) => format!("rollback failed: {}", e),
+                };
+                return Err(format!("Canary failed: {} ({})", reason, rollback));
+            }
+        }
+
+        info!("Canary healthy, promoting");
+        let up_output = self
+            .run_compose_command(working_dir, compose_filename, "up", services)
+            .await?;
+        output.push_str(&format!(
+            "[canary] promoted: docker compose up -d\n{}\n",
+            up_output
+        ));
+
+        Ok(output)
+    }
+
+    /// Render the compose file and diff it against the config applied by the
+    /// last successful deploy. Returns the diff and the rendered config.
+    pub async fn preview(
+        &self,
+        compose_file: &str,
+    ) -> Result<(ComposeDiff, serde_json::Value), String> {
+        let compose_path = Path::new(compose_file);
+        let working_dir = compose_path
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|| ".".to_string());
+        let compose_filename = compose_path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_else(|| "docker-compose.yml".to_string());
+
+        let output = self
+            .run_compose_command_extra(
+                &working_dir,
+                &compose_filename,
+                "config",
+                &["--format", "json"],
+                &[],
+            )
+            .await?;
+        // stdout (the JSON document) comes first, compose warnings on stderr follow it
+        let rendered: serde_json::Value = serde_json::Deserializer::from_str(output.trim_start())
+            .into_iter()
+            .next()
+            .ok_or("Empty compose config output")?
+            .map_err(|e| format!("Invalid compose config output: {}", e))?;
+
+        let applied = std::fs::read_to_string(applied_config_path(compose_file))
+            .ok()
+            .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok());
+        let diff = match applied {
+            Some(ref applied) => diff_compose(applied, &rendered),
+            None => ComposeDiff {
+                first_deploy: true,
+                ..Default::default()
+            },
+        };
+        Ok((diff, rendered))
+    }
+
+    /// Record the rendered config of a successful deploy for the next preview
+    pub fn save_applied_config(
+        &self,
+        compose_file: &str,
+        rendered: &serde_json::Value,
+    ) -> Result<(), String> {
+        let path = applied_config_path(compose_file);
+        let content = serde_json::to_string_pretty(rendered).map_err(|e| e.to_string())?;
+        std::fs::write(&path, content)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Image references used by the compose file (optionally limited to services)
+    async fn compose_images(
+        &self,
+        working_dir: &str,
+        compose_file: &str,
+        services: &[String],
+    ) -> Result<Vec<String>, String> {
+        let images = self
+            .run_compose_command_extra(working_dir, compose_file, "config", &["--images"], services)
+            .await?;
+        Ok(images
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.contains(char::is_whitespace))
+            .map(String::from)
+            .collect())
+    }
+
+    /// `image@digest` of every compose image; fails if a pinned image is
+    /// missing from the compose file or its local digest differs
+    async fn verify_digests(
+        &self,
+        working_dir: &str,
+        compose_file: &str,
+        services: &[String],
+        pinned: &HashMap<String, String>,
+    ) -> Result<Vec<String>, String> {
+        let images = self
+            .compose_images(working_dir, compose_file, services)
+            .await?;
+        if let Some(image) = pinned.keys().find(|image| !images.contains(image)) {
+            return Err(format!(
+                "Pinned image {} is not used by the compose file",
+                image
+            ));
+        }
+
+        let mut digests = Vec::new();
+        for image in &images {
+            let expected = pinned.get(image).map(String::as_str);
+            let repo_digests = match self
+                .run_docker_command(&[
+                    "image",
+                    "inspect",
+                    "--format",
+                    "{{json .RepoDigests}}",
+                    image,
+                ])
+                .await
+            {
+                Ok(out) => out,
+                // Locally built images may not exist yet; only pinned ones must
+                Err(e) if expected.is_some() => return Err(e),
+                Err(_) => continue,
+            };
+            if let Some(digest) = check_digest(image, &repo_digests, expected)? {
+                info!(image = %image, digest = %digest, "Image digest");
+                digests.push(format!("{}@{}", image, digest));
+            }
+        }
+        Ok(digests)
+    }
+
+    /// Local image ID of every compose image that can get a rollback tag.
+    /// Images not present locally yet (first deploy) are skipped.
+    async fn snapshot_images(
+        &self,
+        working_dir: &str,
+        compose_file: &str,
+        services: &[String],
+    ) -> Result<Vec<(String, String)>, String> {
+        let mut snapshot = Vec::new();
+        for image in self
+            .compose_images(working_dir, compose_file, services)
+            .await?
+        {
+            if previous_tag(&image).is_none() {
+                continue;
+            }
+            if let Some(id) = self.image_id(&image).await {
+                snapshot.push((image, id));
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// Tag the images of a snapshot that the pull replaced with their rollback
+    /// tag. Images the pull left unchanged keep their previous rollback point,
+    /// so a no-op redeploy does not overwrite it.
+    async fn tag_previous_images(&self, snapshot: &[(String, String)]) -> Vec<String> {
+        let mut tagged = Vec::new();
+        for (image, id) in snapshot {
+            if self.image_id(image).await.as_ref() == Some(id) {
+                continue;
+            }
+            let Some(previous) = previous_tag(image) else {
+                continue;
+            };
+            if self
+                .run_docker_command(&["image", "tag", id, &previous])
+                .await
+                .is_ok()
+            {
+                tagged.push(previous);
+            }
+        }
+        tagged
+    }
+
+    /// Local image ID of an image reference, None if it is not pulled
+    async fn image_id(&self, image: &str) -> Option<String> {
+        self.run_docker_command(&["image", "inspect", "--format", "{{.Id}}", image])
+            .await
+            .ok()
+            .and_then(|out| out.lines().next().map(|id| id.trim().to_string()))
+            .filter(|id| !id.is_empty())
+    }
+
+    /// Re-tag the images saved before the last pull and restart the services
+    pub async fn rollback(
+        &self,
+        compose_file: &str,
+        services: &[String],
+    ) -> Result<String, String> {
+        let compose_path = Path::new(compose_file);
+        let working_dir = compose_path
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|| ".".to_string());
+
+        let compose_filename = compose_path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_else(|| "docker-compose.yml".to_string());
+
+        let mut output = String::new();
+        let mut restored = 0;
+
+        for image in self
+            .compose_images(&working_dir, &compose_filename, services)
+            .await?
+        {
+            let Some(previous) = previous_tag(&image) else {
+                output.push_str(&format!(
+                    "[rollback] skipped {} (pinned by digest)\n",
+                    image
+                ));
+                continue;
+            };
+            match self
+                .run_docker_command(&["image", "tag", &previous, &image])
+                .await
+            {
+                Ok(_) => {
+                    info!(image = %image, "Restored previous image");
+                    output.push_str(&format!("[rollback] {} -> {}\n", previous, image));
+                    restored += 1;
+                }
+                Err(_) => {
+                    output.push_str(&format!("[rollback] no previous image for {}\n", image));
+                }
+            }
+        }
+
+        if restored == 0 {
+            return Err("No previous images recorded".to_string());
+        }
+
+        let up_output = self
+            .run_compose_command(&working_dir, &compose_filename, "up", services)
+            .await?;
+        output.push_str(&format!("[docker compose up -d]\n{}\n", up_output));
+
         Ok(output)
     }
 
@@ -129,6 +484,84 @@ impl DockerDeploy {
             .await
     }
 
+    /// Start the stopped containers of the services (`docker compose start`)
+    pub async fn start(&self, compose_file: &str, services: &[String]) -> Result<String, String> {
+        let compose_path = Path::new(compose_file);
+        let working_dir = compose_path
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|| ".".to_string());
+
+        let compose_filename = compose_path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_else(|| "docker-compose.yml".to_string());
+
+        self.run_compose_command(&working_dir, &compose_filename, "start", services)
+            .await
+    }
+
+    /// Restart the services' containers without pulling (`docker compose restart`)
+    pub async fn restart(&self, compose_file: &str, services: &[String]) -> Result<String, String> {
+        let compose_path = Path::new(compose_file);
+        let working_dir = compose_path
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|| ".".to_string());
+
+        let compose_filename = compose_path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_else(|| "docker-compose.yml".to_string());
+
+        self.run_compose_command(&working_dir, &compose_filename, "restart", services)
+            .await
+    }
+
+    /// Log in to a private registry, passing the password on stdin
+    pub async fn login(&self, auth: &RegistryAuth) -> Result<String, String> {
+        info!(registry = %auth.registry, username = %auth.username, "Logging in to registry");
+
+        let mut child = Command::new(self.runtime.command())
+            .args([
+                "login",
+                &auth.registry,
+                "--username",
+                &auth.username,
+                "--password-stdin",
+            ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Failed to execute docker login: {}", e))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(auth.password.as_bytes())
+                .await
+                .map_err(|e| format!("Failed to pass registry password: {}", e))?;
+        }
+
+        let output = child
+            .wait_with_output()
+            .await
+            .map_err(|e| format!("Failed to execute docker login: {}", e))?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        if output.status.success() {
+            Ok(format!("{}{}", stdout, stderr))
+        } else {
+            Err(format!(
+                "Login to {} failed: {}\n{}",
+                auth.registry, stderr, stdout
+            ))
+        }
+    }
+
     async fn run_compose_command(
         &self,
         working_dir: &str,
@@ -163,11 +596,12 @@ impl DockerDeploy {
         let service_refs: Vec<&str> = services.iter().map(|s| s.as_str()).collect();
         args.extend(service_refs);
 
-        let mut cmd = Command::new("docker");
+        let mut cmd = Command::new(self.runtime.command());
         cmd.args(&args)
             .current_dir(working_dir)
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
 
         debug!(args = ?args, "Running docker compose command");
 
@@ -190,8 +624,11 @@ impl DockerDeploy {
     }
 
     async fn run_docker_command(&self, args: &[&str]) -> Result<String, String> {
-        let mut cmd = Command::new("docker");
-        cmd.args(args).stdout(Stdio::piped()).stderr(Stdio::piped());
+        let mut cmd = Command::new(self.runtime.command());
+        cmd.args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
 
         debug!(args = ?args, "Running docker command");
 
@@ -211,8 +648,101 @@ impl DockerDeploy {
     }
 }
 
+/// Pick the digest of a pulled image from its `RepoDigests` JSON
+/// (`["repo@sha256:..."]`). A pinned digest must be among them.
+fn check_digest(
+    image: &str,
+    repo_digests: &str,
+    expected: Option<&str>,
+) -> Result<Option<String>, String> {
+    let repo_digests: Vec<String> = serde_json::from_str(repo_digests.trim()).unwrap_or_default();
+    let digests: Vec<&str> = repo_digests
+        .iter()
+        .filter_map(|d| d.rsplit_once('@').map(|(_, digest)| digest))
+        .collect();
+
+    match expected {
+        Some(expected) if digests.contains(&expected) => Ok(Some(expected.to_string())),
+        Some(expected) => Err(format!(
+            "Digest mismatch for {}: expected {}, got {}",
+            image,
+            expected,
+            if digests.is_empty() {
+                "none".to_string()
+            } else {
+                digests.join(", ")
+            }
+        )),
+        None => Ok(digests.first().map(|d| d.to_string())),
+    }
+}
+
+/// Rollback tag for an image reference: `repo:tag` -> `repo:infractl-previous-tag`.
+/// Digest-pinned references can't change, so they have none.
+fn previous_tag(image: &str) -> Option<String> {
+    if image.contains('@') {
+        return None;
+    }
+
+    // A ':' before the last '/' is a registry port, not a tag
+    let name_start = image.rfind('/').map(|i| i + 1).unwrap_or(0);
+    let (repo, tag) = match image[name_start..].rfind(':') {
+        Some(i) => (&image[..name_start + i], &image[name_start + i + 1..]),
+        None => (image, "latest"),
+    };
+
+    let mut previous = format!("infractl-previous-{}", tag);
+    previous.truncate(128);
+    Some(format!("{}:{}", repo, previous))
+}
+
 impl Default for DockerDeploy {
     fn default() -> Self {
-        Self::new()
+        Self::new(ContainerRuntime::Auto)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_digest() {
+        let pinned = "sha256:0b1e0e9bd6c7cf8aaf2b5b9dcf4a6a5e3c0f1b9d8e7a6c5b4a3f2e1d0c9b8a7f";
+        let inspect = format!(r#"["ghcr.io/acme/api@{}"]"#, pinned);
+
+        assert_eq!(
+            check_digest("ghcr.io/acme/api:1.2", &inspect, Some(pinned)).unwrap(),
+            Some(pinned.to_string())
+        );
+        assert_eq!(
+            check_digest("ghcr.io/acme/api:1.2", &inspect, None).unwrap(),
+            Some(pinned.to_string())
+        );
+        assert!(
+            check_digest("ghcr.io/acme/api:1.2", &inspect, Some("sha256:00"))
+                .unwrap_err()
+                .contains("Digest mismatch")
+        );
+        // Locally built image: no repo digest
+        assert_eq!(check_digest("app:dev", "[]", None).unwrap(), None);
+        assert!(check_digest("app:dev", "[]", Some(pinned)).is_err());
+    }
+
+    #[test]
+    fn test_previous_tag() {
+        assert_eq!(
+            previous_tag("nginx").as_deref(),
+            Some("nginx:infractl-previous-latest")
+        );
+        assert_eq!(
+            previous_tag("ghcr.io/acme/api:1.4").as_deref(),
+            Some("ghcr.io/acme/api:infractl-previous-1.4")
+        );
+        assert_eq!(
+            previous_tag("registry:5000/api").as_deref(),
+            Some("registry:5000/api:infractl-previous-latest")
+        );
+        assert_eq!(previous_tag("api@sha256:abcd"), None);
     }
 }
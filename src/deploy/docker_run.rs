@@ -0,0 +1,244 @@
+use crate::config::{ContainerConfig, ContainerRuntime, RegistryAuth};
+use crate::metrics::connect_docker;
+use bollard::auth::DockerCredentials;
+use bollard::container::{
+    Config, CreateContainerOptions, RemoveContainerOptions, StartContainerOptions,
+};
+use bollard::errors::Error as DockerError;
+use bollard::image::CreateImageOptions;
+use bollard::models::{HostConfig, PortBinding, PortMap, RestartPolicy, RestartPolicyNameEnum};
+use bollard::Docker;
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::{debug, info};
+
+/// Single container managed through the Docker API (no compose file)
+pub struct DockerRunDeploy {
+    runtime: ContainerRuntime,
+}
+
+impl DockerRunDeploy {
+    pub fn new(runtime: ContainerRuntime) -> Self {
+        Self { runtime }
+    }
+
+    /// Pull the image and replace the running container with a fresh one
+    pub async fn deploy(
+        &self,
+        name: &str,
+        config: &ContainerConfig,
+        registry_auth: Option<&RegistryAuth>,
+        working_dir: Option<&str>,
+        env: &HashMap<String, String>,
+    ) -> Result<String, String> {
+        let docker = self.connect()?;
+        let mut output = String::new();
+
+        info!(container = %name, image = %config.image, "Pulling image");
+        let mut pull = docker.create_image(
+            Some(CreateImageOptions {
+                from_image: config.image.as_str(),
+                ..Default::default()
+            }),
+            None,
+            registry_auth.map(|auth| DockerCredentials {
+                username: Some(auth.username.clone()),
+                password: Some(auth.password.clone()),
+                serveraddress: Some(auth.registry.clone()),
+                ..Default::default()
+            }),
+        );
+        while let Some(progress) = pull.next().await {
+            let progress =
+                progress.map_err(|e| format!("Failed to pull {}: {}", config.image, e))?;
+            if let Some(error) = progress.error {
+                return Err(format!("Failed to pull {}: {}", config.image, error));
+            }
+            if let Some(status) = progress.status {
+                debug!(image = %config.image, status = %status, "Pull progress");
+            }
+        }
+        output.push_str(&format!("Pulled {}\n", config.image));
+
+        if remove_container(&docker, name).await? {
+            output.push_str(&format!("Removed container {}\n", name));
+        }
+
+        let container = container_config(config, working_dir, env)?;
+        docker
+            .create_container(
+                Some(CreateContainerOptions {
+                    name,
+                    platform: None,
+                }),
+                container,
+            )
+            .await
+            .map_err(|e| format!("Failed to create container {}: {}", name, e))?;
+        docker
+            .start_container(name, None::<StartContainerOptions<String>>)
+            .await
+            .map_err(|e| format!("Failed to start container {}: {}", name, e))?;
+
+        info!(container = %name, image = %config.image, "Container started");
+        output.push_str(&format!("Started container {}\n", name));
+        Ok(output)
+    }
+
+    /// Stop and remove the container
+    pub async fn remove(&self, name: &str) -> Result<String, String> {
+        let docker = self.connect()?;
+        if remove_container(&docker, name).await? {
+            Ok(format!("Removed container {}\n", name))
+        } else {
+            Ok(format!("Container {} not found\n", name))
+        }
+    }
+
+    fn connect(&self) -> Result<Docker, String> {
+        connect_docker(self.runtime).map_err(|e| format!("Failed to connect to Docker: {}", e))
+    }
+}
+
+/// Force-remove a container; returns false if it did not exist
+async fn remove_container(docker: &Docker, name: &str) -> Result<bool, String> {
+    let options = RemoveContainerOptions {
+        force: true,
+        ..Default::default()
+    };
+    match docker.remove_container(name, Some(options)).await {
+        Ok(()) => Ok(true),
+        Err(DockerError::DockerResponseServerError {
+            status_code: 404, ..
+        }) => Ok(false),
+        Err(e) => Err(format!("Failed to remove container {}: {}", name, e)),
+    }
+}
+
+fn container_config(
+    config: &ContainerConfig,
+    working_dir: Option<&str>,
+    env: &HashMap<String, String>,
+) -> Result<Config<String>, String> {
+    let mut port_bindings = PortMap::new();
+    let mut exposed_ports = HashMap::new();
+    for port in &config.ports {
+        let (container_port, binding) = parse_port(port)?;
+        exposed_ports.insert(container_port.clone(), HashMap::new());
+        if let Some(binding) = binding {
+            port_bindings
+                .entry(container_port)
+                .or_insert_with(|| Some(Vec::new()))
+                .get_or_insert_with(Vec::new)
+                .push(binding);
+        }
+    }
+
+    let binds = config
+        .volumes
+        .iter()
+        .map(|volume| resolve_volume(volume, working_dir))
+        .collect();
+
+    let mut env: Vec<String> = env.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    env.sort();
+
+    Ok(Config {
+        image: Some(config.image.clone()),
+        env: Some(env),
+        cmd: (!config.command.is_empty()).then(|| config.command.clone()),
+        exposed_ports: Some(exposed_ports),
+        host_config: Some(HostConfig {
+            binds: Some(binds),
+            port_bindings: Some(port_bindings),
+            restart_policy: Some(RestartPolicy {
+                name: Some(restart_policy(&config.restart)?),
+                ..Default::default()
+            }),
+            network_mode: config.network.clone(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+fn restart_policy(restart: &str) -> Result<RestartPolicyNameEnum, String> {
+    match restart {
+        "no" | "always" | "unless-stopped" | "on-failure" => restart
+            .parse()
+            .map_err(|_| format!("invalid restart policy '{}'", restart)),
+        _ => Err(format!("invalid restart policy '{}'", restart)),
+    }
+}
+
+/// Parse `[ip:]host:container[/proto]` or `container[/proto]` into the
+/// container port key (`80/tcp`) and an optional host binding
+fn parse_port(spec: &str) -> Result<(String, Option<PortBinding>), String> {
+    let (ports, proto) = spec.split_once('/').unwrap_or((spec, "tcp"));
+    if !matches!(proto, "tcp" | "udp" | "sctp") {
+        return Err(format!("invalid protocol in port '{}'", spec));
+    }
+
+    let parts: Vec<&str> = ports.split(':').collect();
+    let (host_ip, host_port, container_port) = match parts[..] {
+        [container] => (None, None, container),
+        [host, container] => (None, Some(host), container),
+        [ip, host, container] => (Some(ip), Some(host), container),
+        _ => return Err(format!("invalid port '{}'", spec)),
+    };
+
+    let is_port = |s: &str| s.parse::<u16>().is_ok_and(|p| p > 0);
+    if !is_port(container_port) || host_port.is_some_and(|p| !is_port(p)) {
+        return Err(format!("invalid port '{}'", spec));
+    }
+
+    let binding = host_port.map(|host_port| PortBinding {
+        host_ip: host_ip.map(String::from),
+        host_port: Some(host_port.to_string()),
+    });
+    Ok((format!("{}/{}", container_port, proto), binding))
+}
+
+/// Relative bind mount sources (`./data:/data`) resolve against the deployment path
+fn resolve_volume(volume: &str, working_dir: Option<&str>) -> String {
+    match (volume.split_once(':'), working_dir) {
+        (Some((source, rest)), Some(dir)) if source.starts_with('.') => {
+            format!("{}:{}", Path::new(dir).join(source).display(), rest)
+        }
+        _ => volume.to_string(),
+    }
+}
+
+/// Check ports and restart policy without touching Docker
+pub fn validate_container(config: &ContainerConfig) -> Result<(), String> {
+    for port in &config.ports {
+        parse_port(port)?;
+    }
+    restart_policy(&config.restart)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_port() {
+        let (key, binding) = parse_port("127.0.0.1:8080:80").unwrap();
+        assert_eq!(key, "80/tcp");
+        let binding = binding.unwrap();
+        assert_eq!(binding.host_ip.as_deref(), Some("127.0.0.1"));
+        assert_eq!(binding.host_port.as_deref(), Some("8080"));
+
+        let (key, binding) = parse_port("5353:53/udp").unwrap();
+        assert_eq!(key, "53/udp");
+        assert_eq!(binding.unwrap().host_port.as_deref(), Some("5353"));
+
+        assert_eq!(parse_port("9000").unwrap(), ("9000/tcp".to_string(), None));
+
+        for spec in ["", "http", "8080:80/xyz", "0:80", "1:2:3:4"] {
+            assert!(parse_port(spec).is_err(), "{}", spec);
+        }
+    }
+}
@@ -1,11 +1,20 @@
+use super::artifact::ReleaseArtifactDeploy;
 use super::docker::DockerDeploy;
-use super::git::GitDeploy;
-use super::script::ScriptRunner;
+use super::docker_run::DockerRunDeploy;
+use super::git::{GitDeploy, GitFetchOptions};
+use super::helm::HelmDeploy;
+use super::s3::S3ArtifactDeploy;
+use super::script::{CommandPolicy, ResourceLimits, ScriptRunner};
+use super::secrets::{redact, FileSecretStore};
 use super::telegram::TelegramDeploy;
-use super::DeployResult;
-use crate::config::{DeployType, DeploymentConfig};
+use super::{job_log, ComposeDiff, DeployResult};
+use crate::config::{ContainerConfig, ContainerRuntime, DeployType, DeploymentConfig};
+use crate::http_client::HttpClient;
+use std::borrow::Cow;
+use std::future::Future;
 use std::time::Instant;
-use tracing::{error, info};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
 
 /// Default allowed base directories for deployments
 const DEFAULT_ALLOWED_PATHS: &[&str] = &["/opt/apps", "/srv", "/var/www", "/home", "/tmp"];
@@ -53,26 +62,82 @@ fn validate_deployment_path(path: &str, extra_paths: &[String], force: bool) ->
 }
 
 pub struct DeployExecutor {
+    artifact: ReleaseArtifactDeploy,
     git: GitDeploy,
     docker: DockerDeploy,
+    docker_run: DockerRunDeploy,
+    helm: HelmDeploy,
+    s3: S3ArtifactDeploy,
     script: ScriptRunner,
+    secrets: FileSecretStore,
     telegram: TelegramDeploy,
 }
 
 impl DeployExecutor {
     pub fn new() -> Self {
+        Self::with_http_client(HttpClient::default(), ContainerRuntime::Auto)
+    }
+
+    /// Executor using the shared HTTP client (Telegram notifications) and the
+    /// configured container runtime (docker_pull, docker_run)
+    pub fn with_http_client(http: HttpClient, runtime: ContainerRuntime) -> Self {
+        let runtime = runtime.resolve();
         Self {
+            artifact: ReleaseArtifactDeploy::new(http.clone()),
             git: GitDeploy::new(),
-            docker: DockerDeploy::new(),
+            docker: DockerDeploy::new(runtime),
+            docker_run: DockerRunDeploy::new(runtime),
+            helm: HelmDeploy::new(),
+            s3: S3ArtifactDeploy::new(http.clone()),
             script: ScriptRunner::new(),
-            telegram: TelegramDeploy::new(),
+            secrets: FileSecretStore::new(),
+            telegram: TelegramDeploy::new(http),
         }
     }
 
+    /// Execute a deployment that can be aborted through `cancel`. Dropping the
+    /// in-flight execution kills any git/docker/script child process it spawned.
+    pub async fn execute_cancellable(
+        &self,
+        config: &DeploymentConfig,
+        allowed_deploy_paths: &[String],
+        cancel: &CancellationToken,
+    ) -> DeployResult {
+        let start = Instant::now();
+
+        tokio::select! {
+            result = self.execute(config, allowed_deploy_paths) => result,
+            _ = cancel.cancelled() => {
+                warn!(deployment = %config.name, "Deployment cancelled");
+                DeployResult {
+                    success: false,
+                    skipped: false,
+                    output: String::new(),
+                    error: Some("Deployment cancelled".to_string()),
+                    duration_ms: start.elapsed().as_millis() as i64,
+                    image_digests: Vec::new(),
+                }
+            }
+        }
+    }
+
+    /// Execute a deployment with its `secrets` injected into env; secret
+    /// values are redacted from the output and error
     pub async fn execute(
         &self,
         config: &DeploymentConfig,
         allowed_deploy_paths: &[String],
+    ) -> DeployResult {
+        self.with_secrets(config, |config| async move {
+            self.execute_with_env(&config, allowed_deploy_paths).await
+        })
+        .await
+    }
+
+    async fn execute_with_env(
+        &self,
+        config: &DeploymentConfig,
+        allowed_deploy_paths: &[String],
     ) -> DeployResult {
         let start = Instant::now();
         let mut output = String::new();
@@ -94,6 +159,7 @@ impl DeployExecutor {
                         output: String::new(),
                         error: Some("Telegram type requires 'telegram' config".to_string()),
                         duration_ms: start.elapsed().as_millis() as i64,
+                        image_digests: Vec::new(),
                     };
                 }
             };
@@ -104,6 +170,7 @@ impl DeployExecutor {
                     output: out,
                     error: None,
                     duration_ms: start.elapsed().as_millis() as i64,
+                    image_digests: Vec::new(),
                 },
                 Err(e) => DeployResult {
                     success: false,
@@ -111,6 +178,7 @@ impl DeployExecutor {
                     output: String::new(),
                     error: Some(e),
                     duration_ms: start.elapsed().as_millis() as i64,
+                    image_digests: Vec::new(),
                 },
             };
         }
@@ -126,6 +194,7 @@ impl DeployExecutor {
                     output,
                     error: Some(e),
                     duration_ms: start.elapsed().as_millis() as i64,
+                    image_digests: Vec::new(),
                 };
             }
         }
@@ -143,6 +212,7 @@ impl DeployExecutor {
                         output,
                         error: Some(error_msg),
                         duration_ms: start.elapsed().as_millis() as i64,
+                        image_digests: Vec::new(),
                     };
                 }
                 info!(path = %path, "Created deployment directory");
@@ -152,19 +222,24 @@ impl DeployExecutor {
         // Run pre-deploy commands
         if !config.pre_deploy.is_empty() {
             info!("Running pre-deploy commands");
-            for cmd in config.pre_deploy.as_vec() {
+            for (cmd, user) in config.pre_deploy.entries() {
                 match self
                     .script
                     .run_command(
                         cmd,
                         config.path.as_deref(),
                         &config.env,
-                        config.user.as_deref(),
+                        user.or(config.user.as_deref()),
+                        &ResourceLimits::from(config),
+                        &CommandPolicy::from(config),
                     )
                     .await
                 {
                     Ok(cmd_output) => {
-                        output.push_str(&format!("[pre-deploy] {}\n{}\n", cmd, cmd_output));
+                        push_output(
+                            &mut output,
+                            &format!("[pre-deploy] {}\n{}\n", cmd, cmd_output),
+                        );
                     }
                     Err(e) => {
                         let error_msg = format!("Pre-deploy command failed: {}", e);
@@ -175,6 +250,7 @@ impl DeployExecutor {
                             output,
                             error: Some(error_msg),
                             duration_ms: start.elapsed().as_millis() as i64,
+                            image_digests: Vec::new(),
                         };
                     }
                 }
@@ -215,7 +291,7 @@ impl DeployExecutor {
                                 )
                                 .await
                             {
-                                Ok(fetch_output) => output.push_str(&fetch_output),
+                                Ok(fetch_output) => push_output(&mut output, &fetch_output),
                                 Err(e) => {
                                     let error_msg = format!("git_files fetch failed: {}", e);
                                     error!("{}", error_msg);
@@ -225,6 +301,7 @@ impl DeployExecutor {
                                         output,
                                         error: Some(error_msg),
                                         duration_ms: start.elapsed().as_millis() as i64,
+                                        image_digests: Vec::new(),
                                     };
                                 }
                             }
@@ -238,6 +315,7 @@ impl DeployExecutor {
                                 output,
                                 error: Some(error_msg),
                                 duration_ms: start.elapsed().as_millis() as i64,
+                                image_digests: Vec::new(),
                             };
                         }
                     }
@@ -251,6 +329,7 @@ impl DeployExecutor {
                         output,
                         error: Some(error_msg),
                         duration_ms: start.elapsed().as_millis() as i64,
+                        image_digests: Vec::new(),
                     };
                 }
             }
@@ -258,6 +337,7 @@ impl DeployExecutor {
 
         // Execute main deployment based on type
         let mut skipped = false;
+        let mut image_digests = Vec::new();
         let result = match config.deploy_type {
             DeployType::GitPull => match self.execute_git_pull(config).await {
                 Ok((deploy_output, has_changes)) => {
@@ -268,14 +348,69 @@ impl DeployExecutor {
                 }
                 Err(e) => Err(e),
             },
-            DeployType::DockerPull => self.execute_docker_pull(config).await,
+            DeployType::DockerPull => {
+                self.execute_docker_pull(config)
+                    .await
+                    .map(|(deploy_output, digests)| {
+                        image_digests = digests;
+                        deploy_output
+                    })
+            }
             DeployType::CustomScript => self.execute_custom_script(config).await,
+            DeployType::Helm => match config.helm.as_ref() {
+                Some(helm) => {
+                    self.helm
+                        .upgrade(helm, config.path.as_deref(), &config.env)
+                        .await
+                }
+                None => Err("Helm type requires 'helm' config".to_string()),
+            },
+            DeployType::DockerRun => match config.container.as_ref() {
+                Some(container) => {
+                    self.docker_run
+                        .deploy(
+                            container_name(config, container),
+                            container,
+                            config.registry_auth.as_ref(),
+                            config.path.as_deref(),
+                            &config.env,
+                        )
+                        .await
+                }
+                None => Err("DockerRun type requires 'container' config".to_string()),
+            },
+            DeployType::ReleaseArtifact => {
+                match (config.release.as_ref(), config.path.as_deref()) {
+                    (Some(release), Some(path)) => self.artifact.deploy(release, path).await.map(
+                        |(deploy_output, has_changes)| {
+                            if !has_changes {
+                                skipped = true;
+                            }
+                            deploy_output
+                        },
+                    ),
+                    _ => {
+                        Err("ReleaseArtifact type requires 'release' config and 'path'".to_string())
+                    }
+                }
+            }
+            DeployType::S3Artifact => match (config.s3.as_ref(), config.path.as_deref()) {
+                (Some(s3), Some(path)) => self.s3.deploy(s3, path, &config.env).await.map(
+                    |(deploy_output, has_changes)| {
+                        if !has_changes {
+                            skipped = true;
+                        }
+                        deploy_output
+                    },
+                ),
+                _ => Err("S3Artifact type requires 's3' config and 'path'".to_string()),
+            },
             DeployType::Telegram => unreachable!("Telegram handled above"),
         };
 
         match result {
             Ok(deploy_output) => {
-                output.push_str(&deploy_output);
+                push_output(&mut output, &deploy_output);
             }
             Err(e) => {
                 let error_msg = format!("Deployment failed: {}", e);
@@ -286,6 +421,7 @@ impl DeployExecutor {
                     output,
                     error: Some(error_msg),
                     duration_ms: start.elapsed().as_millis() as i64,
+                    image_digests: Vec::new(),
                 };
             }
         }
@@ -293,19 +429,24 @@ impl DeployExecutor {
         // Run post-deploy commands (skip if no changes detected)
         if !skipped && !config.post_deploy.is_empty() {
             info!("Running post-deploy commands");
-            for cmd in config.post_deploy.as_vec() {
+            for (cmd, user) in config.post_deploy.entries() {
                 match self
                     .script
                     .run_command(
                         cmd,
                         config.path.as_deref(),
                         &config.env,
-                        config.user.as_deref(),
+                        user.or(config.user.as_deref()),
+                        &ResourceLimits::from(config),
+                        &CommandPolicy::from(config),
                     )
                     .await
                 {
                     Ok(cmd_output) => {
-                        output.push_str(&format!("[post-deploy] {}\n{}\n", cmd, cmd_output));
+                        push_output(
+                            &mut output,
+                            &format!("[post-deploy] {}\n{}\n", cmd, cmd_output),
+                        );
                     }
                     Err(e) => {
                         let error_msg = format!("Post-deploy command failed: {}", e);
@@ -316,6 +457,7 @@ impl DeployExecutor {
                             output,
                             error: Some(error_msg),
                             duration_ms: start.elapsed().as_millis() as i64,
+                            image_digests: Vec::new(),
                         };
                     }
                 }
@@ -328,6 +470,33 @@ impl DeployExecutor {
             output,
             error: None,
             duration_ms: start.elapsed().as_millis() as i64,
+            image_digests,
+        }
+    }
+
+    /// Run `op` on the config with secrets resolved from the store and added
+    /// to env, then redact the secret values from its result
+    async fn with_secrets<'a, F, Fut>(&self, config: &'a DeploymentConfig, op: F) -> DeployResult
+    where
+        F: FnOnce(Cow<'a, DeploymentConfig>) -> Fut,
+        Fut: Future<Output = DeployResult>,
+    {
+        match self.secrets.inject(config) {
+            Ok((config, values)) => {
+                job_log::redact_secrets(&values);
+                redact(op(config).await, &values)
+            }
+            Err(e) => {
+                error!(deployment = %config.name, error = %e, "Secret resolution failed");
+                DeployResult {
+                    success: false,
+                    skipped: false,
+                    output: String::new(),
+                    error: Some(e),
+                    duration_ms: 0,
+                    image_digests: Vec::new(),
+                }
+            }
         }
     }
 
@@ -340,6 +509,12 @@ impl DeployExecutor {
         let branch = config.branch.as_deref().unwrap_or("main");
         let remote = config.remote.as_deref().unwrap_or("origin");
         let git_dir = std::path::Path::new(path).join(".git");
+        let options = GitFetchOptions {
+            depth: config.depth,
+            fetch_tags: config.fetch_tags,
+            prune: config.prune,
+            submodules: config.submodules,
+        };
 
         // Check if repo exists, if not - clone first (always has changes)
         if !git_dir.exists() {
@@ -360,19 +535,55 @@ impl DeployExecutor {
 
             let clone_output = self
                 .git
-                .clone(repo_url, path, Some(branch), config.ssh_key.as_deref())
+                .clone(
+                    repo_url,
+                    path,
+                    Some(branch),
+                    config.ssh_key.as_deref(),
+                    &options,
+                )
                 .await?;
 
+            // Pinned revision: check it out on top of the fresh clone
+            if let Some(git_ref) = config.git_ref.as_deref() {
+                let (pull_output, _) = self
+                    .git
+                    .pull(
+                        path,
+                        remote,
+                        branch,
+                        Some(git_ref),
+                        config.ssh_key.as_deref(),
+                        &options,
+                    )
+                    .await?;
+                return Ok((
+                    format!("[git clone] {}\n{}", clone_output, pull_output),
+                    true,
+                ));
+            }
+
             return Ok((format!("[git clone] {}\n", clone_output), true));
         }
 
         // Repo exists, do pull — returns (output, has_changes)
         self.git
-            .pull(path, remote, branch, config.ssh_key.as_deref())
+            .pull(
+                path,
+                remote,
+                branch,
+                config.git_ref.as_deref(),
+                config.ssh_key.as_deref(),
+                &options,
+            )
             .await
     }
 
-    async fn execute_docker_pull(&self, config: &DeploymentConfig) -> Result<String, String> {
+    /// Returns the output and the `image@digest` references of the pulled images
+    async fn execute_docker_pull(
+        &self,
+        config: &DeploymentConfig,
+    ) -> Result<(String, Vec<String>), String> {
         let path = config
             .path
             .as_ref()
@@ -395,20 +606,69 @@ impl DeployExecutor {
             return Err(format!("Compose file not found: {}", full_compose_path));
         }
 
+        // Show what the deploy changes compared to the last applied config
+        let preview = self.docker.preview(&full_compose_path).await;
+        match preview {
+            Ok((ref diff, _)) => {
+                output.push_str(&format!("[compose diff]\n{}\n", diff.summary()));
+            }
+            Err(ref e) => output.push_str(&format!("[compose diff] Warning: {}\n", e)),
+        }
+
         // Run docker compose
-        let strategy = config.strategy.clone().unwrap_or_default();
-        let docker_output = self
+        let (docker_output, image_digests) = self
             .docker
-            .pull_and_restart(
-                &full_compose_path,
-                config.services.as_slice(),
-                config.prune,
-                &strategy,
-            )
+            .pull_and_restart(&full_compose_path, config)
             .await?;
         output.push_str(&docker_output);
 
-        Ok(output)
+        if let Ok((_, rendered)) = preview {
+            if let Err(e) = self
+                .docker
+                .save_applied_config(&full_compose_path, &rendered)
+            {
+                output.push_str(&format!("[compose diff] Warning: {}\n", e));
+            }
+        }
+
+        Ok((output, image_digests))
+    }
+
+    /// Diff a docker_pull deployment's compose file against the config applied
+    /// by its last successful deploy, without changing anything
+    pub async fn preview(
+        &self,
+        config: &DeploymentConfig,
+        allowed_deploy_paths: &[String],
+    ) -> Result<ComposeDiff, String> {
+        if config.deploy_type != DeployType::DockerPull {
+            return Err(format!(
+                "Preview is not supported for {:?} deployments",
+                config.deploy_type
+            ));
+        }
+        let path = config
+            .path
+            .as_deref()
+            .ok_or_else(|| "docker_pull requires 'path' to be set".to_string())?;
+        validate_deployment_path(path, allowed_deploy_paths, config.force)?;
+
+        let compose_file = config
+            .compose_file
+            .as_deref()
+            .unwrap_or("docker-compose.yaml");
+        let full_compose_path = std::path::Path::new(path)
+            .join(compose_file)
+            .to_string_lossy()
+            .to_string();
+        if !std::path::Path::new(&full_compose_path).exists() {
+            return Err(format!("Compose file not found: {}", full_compose_path));
+        }
+
+        self.docker
+            .preview(&full_compose_path)
+            .await
+            .map(|(diff, _)| diff)
     }
 
     async fn execute_custom_script(&self, config: &DeploymentConfig) -> Result<String, String> {
@@ -425,7 +685,13 @@ impl DeployExecutor {
 
         if is_file {
             self.script
-                .run_script(script, working_dir, &config.env, config.user.as_deref())
+                .run_script(
+                    script,
+                    working_dir,
+                    &config.env,
+                    config.user.as_deref(),
+                    &ResourceLimits::from(config),
+                )
                 .await
         } else {
             // Inline script — run via sh -c
@@ -435,6 +701,8 @@ impl DeployExecutor {
                     working_dir.or(config.path.as_deref()),
                     &config.env,
                     config.user.as_deref(),
+                    &ResourceLimits::from(config),
+                    &CommandPolicy::from(config),
                 )
                 .await
         }
@@ -442,6 +710,13 @@ impl DeployExecutor {
 
     /// Execute shutdown commands for a deployment
     pub async fn shutdown(&self, config: &DeploymentConfig) -> DeployResult {
+        self.with_secrets(config, |config| async move {
+            self.shutdown_with_env(&config).await
+        })
+        .await
+    }
+
+    async fn shutdown_with_env(&self, config: &DeploymentConfig) -> DeployResult {
         let start = Instant::now();
         let mut output = String::new();
 
@@ -454,19 +729,24 @@ impl DeployExecutor {
         // If explicit shutdown commands are specified, use them
         if !config.shutdown.is_empty() {
             info!("Running shutdown commands");
-            for cmd in config.shutdown.as_vec() {
+            for (cmd, user) in config.shutdown.entries() {
                 match self
                     .script
                     .run_command(
                         cmd,
                         config.path.as_deref(),
                         &config.env,
-                        config.user.as_deref(),
+                        user.or(config.user.as_deref()),
+                        &ResourceLimits::from(config),
+                        &CommandPolicy::from(config),
                     )
                     .await
                 {
                     Ok(cmd_output) => {
-                        output.push_str(&format!("[shutdown] {}\n{}\n", cmd, cmd_output));
+                        push_output(
+                            &mut output,
+                            &format!("[shutdown] {}\n{}\n", cmd, cmd_output),
+                        );
                     }
                     Err(e) => {
                         let error_msg = format!("Shutdown command failed: {}", e);
@@ -477,10 +757,65 @@ impl DeployExecutor {
                             output,
                             error: Some(error_msg),
                             duration_ms: start.elapsed().as_millis() as i64,
+                            image_digests: Vec::new(),
                         };
                     }
                 }
             }
+        } else if let (DeployType::Helm, Some(helm)) = (&config.deploy_type, &config.helm) {
+            // Default: helm uninstall for helm
+            match self
+                .helm
+                .uninstall(helm, config.path.as_deref(), &config.env)
+                .await
+            {
+                Ok(helm_output) => {
+                    push_output(
+                        &mut output,
+                        &format!("[shutdown] helm uninstall\n{}\n", helm_output),
+                    );
+                }
+                Err(e) => {
+                    let error_msg = format!("Helm uninstall failed: {}", e);
+                    error!("{}", error_msg);
+                    return DeployResult {
+                        success: false,
+                        skipped: false,
+                        output,
+                        error: Some(error_msg),
+                        duration_ms: start.elapsed().as_millis() as i64,
+                        image_digests: Vec::new(),
+                    };
+                }
+            }
+        } else if let (DeployType::DockerRun, Some(container)) =
+            (&config.deploy_type, &config.container)
+        {
+            // Default: remove the container for docker_run
+            match self
+                .docker_run
+                .remove(container_name(config, container))
+                .await
+            {
+                Ok(remove_output) => {
+                    push_output(
+                        &mut output,
+                        &format!("[shutdown] docker rm\n{}\n", remove_output),
+                    );
+                }
+                Err(e) => {
+                    let error_msg = format!("Container removal failed: {}", e);
+                    error!("{}", error_msg);
+                    return DeployResult {
+                        success: false,
+                        skipped: false,
+                        output,
+                        error: Some(error_msg),
+                        duration_ms: start.elapsed().as_millis() as i64,
+                        image_digests: Vec::new(),
+                    };
+                }
+            }
         } else if config.deploy_type == DeployType::DockerPull {
             // Default: docker compose down for docker_pull
             if let Some(ref path) = config.path {
@@ -497,10 +832,10 @@ impl DeployExecutor {
                     info!(compose_file = %full_compose_path, "Running docker compose down");
                     match self.docker.down(&full_compose_path).await {
                         Ok(docker_output) => {
-                            output.push_str(&format!(
-                                "[shutdown] docker compose down\n{}\n",
-                                docker_output
-                            ));
+                            push_output(
+                                &mut output,
+                                &format!("[shutdown] docker compose down\n{}\n", docker_output),
+                            );
                         }
                         Err(e) => {
                             let error_msg = format!("Docker compose down failed: {}", e);
@@ -511,13 +846,14 @@ impl DeployExecutor {
                                 output,
                                 error: Some(error_msg),
                                 duration_ms: start.elapsed().as_millis() as i64,
+                                image_digests: Vec::new(),
                             };
                         }
                     }
                 }
             }
         } else {
-            output.push_str("[shutdown] No shutdown commands configured\n");
+            push_output(&mut output, "[shutdown] No shutdown commands configured\n");
         }
 
         DeployResult {
@@ -526,11 +862,242 @@ impl DeployExecutor {
             output,
             error: None,
             duration_ms: start.elapsed().as_millis() as i64,
+            image_digests: Vec::new(),
+        }
+    }
+
+    /// Start the stopped containers of a docker_pull deployment (`services` only, if set)
+    pub async fn start(
+        &self,
+        config: &DeploymentConfig,
+        allowed_deploy_paths: &[String],
+    ) -> DeployResult {
+        self.with_secrets(config, |config| async move {
+            self.compose_lifecycle_with_env(&config, allowed_deploy_paths, "start")
+                .await
+        })
+        .await
+    }
+
+    /// Restart the containers of a docker_pull deployment without pulling or redeploying
+    pub async fn restart(
+        &self,
+        config: &DeploymentConfig,
+        allowed_deploy_paths: &[String],
+    ) -> DeployResult {
+        self.with_secrets(config, |config| async move {
+            self.compose_lifecycle_with_env(&config, allowed_deploy_paths, "restart")
+                .await
+        })
+        .await
+    }
+
+    async fn compose_lifecycle_with_env(
+        &self,
+        config: &DeploymentConfig,
+        allowed_deploy_paths: &[String],
+        action: &str,
+    ) -> DeployResult {
+        let start = Instant::now();
+
+        info!(
+            deployment = %config.name,
+            deploy_type = ?config.deploy_type,
+            action = %action,
+            "Running compose lifecycle action"
+        );
+
+        let result = match (&config.deploy_type, config.path.as_deref()) {
+            (DeployType::DockerPull, Some(path)) => {
+                match validate_deployment_path(path, allowed_deploy_paths, config.force) {
+                    Ok(()) => {
+                        let compose_file = config
+                            .compose_file
+                            .as_deref()
+                            .unwrap_or("docker-compose.yaml");
+                        let full_compose_path = std::path::Path::new(path)
+                            .join(compose_file)
+                            .to_string_lossy()
+                            .to_string();
+                        let services = config.services.as_slice();
+                        if action == "restart" {
+                            self.docker.restart(&full_compose_path, services).await
+                        } else {
+                            self.docker.start(&full_compose_path, services).await
+                        }
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            (DeployType::DockerPull, None) => Err(format!("{} requires 'path' to be set", action)),
+            (deploy_type, _) => Err(format!(
+                "{} is not supported for {:?} deployments",
+                action, deploy_type
+            )),
+        };
+
+        match result {
+            Ok(compose_output) => DeployResult {
+                success: true,
+                skipped: false,
+                output: format!("[docker compose {}]\n{}\n", action, compose_output),
+                error: None,
+                duration_ms: start.elapsed().as_millis() as i64,
+                image_digests: Vec::new(),
+            },
+            Err(e) => {
+                let error_msg = format!("Compose {} failed: {}", action, e);
+                error!("{}", error_msg);
+                DeployResult {
+                    success: false,
+                    skipped: false,
+                    output: String::new(),
+                    error: Some(error_msg),
+                    duration_ms: start.elapsed().as_millis() as i64,
+                    image_digests: Vec::new(),
+                }
+            }
+        }
+    }
+
+    /// Revert a deployment to the state before its last successful pull and
+    /// re-run post_deploy: git_pull resets to the recorded commit, docker_pull
+    /// re-tags the images saved before `docker compose pull`, helm rolls the
+    /// release back to its previous revision
+    pub async fn rollback(
+        &self,
+        config: &DeploymentConfig,
+        allowed_deploy_paths: &[String],
+    ) -> DeployResult {
+        self.with_secrets(config, |config| async move {
+            self.rollback_with_env(&config, allowed_deploy_paths).await
+        })
+        .await
+    }
+
+    async fn rollback_with_env(
+        &self,
+        config: &DeploymentConfig,
+        allowed_deploy_paths: &[String],
+    ) -> DeployResult {
+        let start = Instant::now();
+        let mut output = String::new();
+
+        info!(
+            deployment = %config.name,
+            deploy_type = ?config.deploy_type,
+            "Rolling back deployment"
+        );
+
+        let path = match config.path.as_deref() {
+            Some(path) => validate_deployment_path(path, allowed_deploy_paths, config.force)
+                .map(|_| Some(path)),
+            // helm releases are rolled back in the cluster, no path needed
+            None if config.deploy_type == DeployType::Helm => Ok(None),
+            None => Err("Rollback requires 'path' to be set".to_string()),
+        };
+
+        let result = match (&config.deploy_type, path) {
+            (_, Err(e)) => Err(e),
+            (DeployType::Helm, Ok(path)) => match config.helm.as_ref() {
+                Some(helm) => self.helm.rollback(helm, path, &config.env).await,
+                None => Err("Helm type requires 'helm' config".to_string()),
+            },
+            (DeployType::GitPull, Ok(Some(path))) => {
+                self.git
+                    .rollback(path, config.submodules, config.ssh_key.as_deref())
+                    .await
+            }
+            (DeployType::DockerPull, Ok(Some(path))) => {
+                let compose_file = config
+                    .compose_file
+                    .as_deref()
+                    .unwrap_or("docker-compose.yaml");
+                let full_compose_path = std::path::Path::new(path)
+                    .join(compose_file)
+                    .to_string_lossy()
+                    .to_string();
+                self.docker
+                    .rollback(&full_compose_path, config.services.as_slice())
+                    .await
+            }
+            (deploy_type, Ok(_)) => Err(format!(
+                "Rollback is not supported for {:?} deployments",
+                deploy_type
+            )),
+        };
+
+        match result {
+            Ok(rollback_output) => push_output(&mut output, &rollback_output),
+            Err(e) => {
+                let error_msg = format!("Rollback failed: {}", e);
+                error!("{}", error_msg);
+                return DeployResult {
+                    success: false,
+                    skipped: false,
+                    output,
+                    error: Some(error_msg),
+                    duration_ms: start.elapsed().as_millis() as i64,
+                    image_digests: Vec::new(),
+                };
+            }
+        }
+
+        for (cmd, user) in config.post_deploy.entries() {
+            match self
+                .script
+                .run_command(
+                    cmd,
+                    config.path.as_deref(),
+                    &config.env,
+                    user.or(config.user.as_deref()),
+                    &ResourceLimits::from(config),
+                    &CommandPolicy::from(config),
+                )
+                .await
+            {
+                Ok(cmd_output) => {
+                    push_output(
+                        &mut output,
+                        &format!("[post-deploy] {}\n{}\n", cmd, cmd_output),
+                    );
+                }
+                Err(e) => {
+                    let error_msg = format!("Post-deploy command failed: {}", e);
+                    error!("{}", error_msg);
+                    return DeployResult {
+                        success: false,
+                        skipped: false,
+                        output,
+                        error: Some(error_msg),
+                        duration_ms: start.elapsed().as_millis() as i64,
+                        image_digests: Vec::new(),
+                    };
+                }
+            }
+        }
+
+        DeployResult {
+            success: true,
+            skipped: false,
+            output,
+            error: None,
+            duration_ms: start.elapsed().as_millis() as i64,
+            image_digests: Vec::new(),
         }
     }
 }
 
 /// Parse file mappings from "from:to" format
+/// Add a step's output to the job output. While the job streams to its log
+/// file the step goes there and only a bounded tail is kept in memory.
+fn push_output(output: &mut String, text: &str) {
+    output.push_str(text);
+    if job_log::emit(text) {
+        job_log::trim_streamed(output);
+    }
+}
+
 fn parse_file_mappings(files: &[String]) -> Result<Vec<(String, String)>, String> {
     files
         .iter()
@@ -544,6 +1111,11 @@ fn parse_file_mappings(files: &[String]) -> Result<Vec<(String, String)>, String
         .collect()
 }
 
+/// docker_run container name: `container.name` or the deployment name
+fn container_name<'a>(config: &'a DeploymentConfig, container: &'a ContainerConfig) -> &'a str {
+    container.name.as_deref().unwrap_or(&config.name)
+}
+
 impl Default for DeployExecutor {
     fn default() -> Self {
         Self::new()
@@ -0,0 +1,154 @@
+use crate::config::HelmConfig;
+use std::collections::HashMap;
+use std::process::Stdio;
+use tokio::process::Command;
+use tracing::{debug, info};
+
+pub struct HelmDeploy;
+
+impl HelmDeploy {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Install or upgrade the release (`helm upgrade --install`)
+    pub async fn upgrade(
+        &self,
+        config: &HelmConfig,
+        working_dir: Option<&str>,
+        env: &HashMap<String, String>,
+    ) -> Result<String, String> {
+        info!(release = %config.release, chart = %config.chart, "Running helm upgrade");
+        self.run_helm_command(&upgrade_args(config), working_dir, env)
+            .await
+    }
+
+    /// Revert the release to its previous revision
+    pub async fn rollback(
+        &self,
+        config: &HelmConfig,
+        working_dir: Option<&str>,
+        env: &HashMap<String, String>,
+    ) -> Result<String, String> {
+        info!(release = %config.release, "Running helm rollback");
+        let mut args = vec!["rollback".to_string(), config.release.clone()];
+        args.extend(common_args(config));
+        if config.wait {
+            args.push("--wait".to_string());
+        }
+        self.run_helm_command(&args, working_dir, env).await
+    }
+
+    /// Remove the release
+    pub async fn uninstall(
+        &self,
+        config: &HelmConfig,
+        working_dir: Option<&str>,
+        env: &HashMap<String, String>,
+    ) -> Result<String, String> {
+        info!(release = %config.release, "Running helm uninstall");
+        let mut args = vec!["uninstall".to_string(), config.release.clone()];
+        args.extend(common_args(config));
+        self.run_helm_command(&args, working_dir, env).await
+    }
+
+    async fn run_helm_command(
+        &self,
+        args: &[String],
+        working_dir: Option<&str>,
+        env: &HashMap<String, String>,
+    ) -> Result<String, String> {
+        let mut cmd = Command::new("helm");
+        cmd.args(args)
+            .envs(env)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        if let Some(dir) = working_dir {
+            cmd.current_dir(dir);
+        }
+
+        debug!(args = ?args, "Running helm command");
+
+        let output = cmd
+            .output()
+            .await
+            .map_err(|e| format!("Failed to execute helm: {}", e))?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        if output.status.success() {
+            Ok(format!("{}{}", stdout, stderr))
+        } else {
+            Err(format!("Helm command failed: {}\n{}", stderr, stdout))
+        }
+    }
+}
+
+fn upgrade_args(config: &HelmConfig) -> Vec<String> {
+    let mut args = vec![
+        "upgrade".to_string(),
+        "--install".to_string(),
+        config.release.clone(),
+        config.chart.clone(),
+    ];
+    args.extend(common_args(config));
+    if config.namespace.is_some() {
+        args.push("--create-namespace".to_string());
+    }
+    for values in &config.values {
+        args.push("--values".to_string());
+        args.push(values.clone());
+    }
+    if let Some(ref version) = config.version {
+        args.push("--version".to_string());
+        args.push(version.clone());
+    }
+    if config.atomic {
+        args.push("--atomic".to_string());
+    } else if config.wait {
+        args.push("--wait".to_string());
+    }
+    args
+}
+
+/// Flags shared by every helm subcommand
+fn common_args(config: &HelmConfig) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(ref namespace) = config.namespace {
+        args.push("--namespace".to_string());
+        args.push(namespace.clone());
+    }
+    if let Some(ref kubeconfig) = config.kubeconfig {
+        args.push("--kubeconfig".to_string());
+        args.push(kubeconfig.clone());
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upgrade_args() {
+        let config = HelmConfig {
+            chart: "bitnami/redis".to_string(),
+            release: "cache".to_string(),
+            namespace: Some("data".to_string()),
+            values: vec!["values.yaml".to_string(), "prod.yaml".to_string()],
+            version: Some("19.0.1".to_string()),
+            kubeconfig: None,
+            wait: true,
+            atomic: false,
+        };
+
+        assert_eq!(
+            upgrade_args(&config).join(" "),
+            "upgrade --install cache bitnami/redis --namespace data --create-namespace \
+             --values values.yaml --values prod.yaml --version 19.0.1 --wait"
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::future::Future;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, warn};
+
+/// Output kept in memory while a job streams to its log file, enough for the
+/// history tail
+const MEMORY_TAIL_BYTES: usize = 256 * 1024;
+
+tokio::task_local! {
+    static CURRENT: Arc<JobLog>;
+}
+
+/// Log file of a running job, `<dir>/<job_id>.log`. Output is appended step
+/// by step while the job runs, so it can be followed during the job and
+/// memory use does not grow with the output.
+pub struct JobLog {
+    path: PathBuf,
+    file: Mutex<File>,
+    /// Secret values of the deployment, redacted before anything is written
+    secrets: Mutex<Vec<String>>,
+}
+
+impl JobLog {
+    pub fn create(dir: &Path, job_id: &str) -> std::io::Result<Arc<Self>> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}.log", job_id));
+        let file = File::create(&path)?;
+        Ok(Arc::new(Self {
+            path,
+            file: Mutex::new(file),
+            secrets: Mutex::new(Vec::new()),
+        }))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn append(&self, text: &str) {
+        let secrets = self.secrets.lock().unwrap();
+        let text = crate::deploy::secrets::redact_text(text, &secrets);
+        if let Err(e) = self.file.lock().unwrap().write_all(text.as_bytes()) {
+            debug!(path = %self.path.display(), error = %e, "Failed to append to job log");
+        }
+    }
+
+    /// Finish the log once the job is done: keep the last `max_bytes` of the
+    /// file and return the last `tail_bytes` for the history. A job that
+    /// printed nothing leaves no file (None).
+    pub fn finish(&self, max_bytes: usize, tail_bytes: usize) -> std::io::Result<Option<String>> {
+        let mut file = File::open(&self.path)?;
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            fs::remove_file(&self.path)?;
+            return Ok(None);
+        }
+
+        let keep = len.min(max_bytes.max(tail_bytes));
+        file.seek(SeekFrom::Start((len - keep) as u64))?;
+        let mut bytes = Vec::with_capacity(keep);
+        file.read_to_end(&mut bytes)?;
+        // Start on a char boundary
+        let skip = bytes.iter().take_while(|b| (**b & 0xC0) == 0x80).count();
+        let kept = String::from_utf8_lossy(&bytes[skip..]);
+        let omitted = len - keep + skip;
+
+        if len > max_bytes {
+            fs::write(&self.path, tail_after(omitted, &kept, max_bytes).as_bytes())?;
+        }
+        Ok(Some(tail_after(omitted, &kept, tail_bytes).into_owned()))
+    }
+}
+
+/// Run `job` with `log` receiving the output passed to [`emit`]
+pub async fn scope<F: Future>(log: Option<Arc<JobLog>>, job: F) -> F::Output {
+    match log {
+        Some(log) => CURRENT.scope(log, job).await,
+        None => job.await,
+    }
+}
+
+/// Append `text` to the log of the job running on this task. Returns false
+/// when no log is streaming.
+pub fn emit(text: &str) -> bool {
+    CURRENT.try_with(|log| log.append(text)).is_ok()
+}
+
+/// Secret values to redact from the rest of the streamed output
+pub fn redact_secrets(values: &[String]) {
+    let _ = CURRENT.try_with(|log| log.secrets.lock().unwrap().extend_from_slice(values));
+}
+
+/// Drop the front of output that was already streamed to the log file, so a
+/// chatty job keeps only a bounded tail in memory
+pub fn trim_streamed(output: &mut String) {
+    if output.len() <= MEMORY_TAIL_BYTES {
+        return;
+    }
+    let mut start = output.len() - MEMORY_TAIL_BYTES;
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    output.drain(..start);
+}
+
+/// Delete log files in `dir` last written more than `retention` ago.
+/// Returns the number removed.
+pub fn prune(dir: &Path, retention: Duration) -> usize {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    let Some(cutoff) = SystemTime::now().checked_sub(retention) else {
+        return 0;
+    };
+
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("log") {
+            continue;
+        }
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .is_ok_and(|modified| modified < cutoff);
+        if expired {
+            match fs::remove_file(&path) {
+                Ok(()) => removed += 1,
+                Err(e) => warn!(path = %path.display(), error = %e, "Failed to remove job log"),
+            }
+        }
+    }
+    removed
+}
+
+/// Write a job's output to `<dir>/<job_id>.log`, keeping its last `max_bytes`
+pub async fn write(
+    dir: &Path,
+    job_id: &str,
+    output: &str,
+    max_bytes: usize,
+) -> std::io::Result<PathBuf> {
+    tokio::fs::create_dir_all(dir).await?;
+    let path = dir.join(format!("{}.log", job_id));
+    tokio::fs::write(&path, tail(output, max_bytes).as_bytes()).await?;
+    Ok(path)
+}
+
+/// Last `max_bytes` of the output (cut on a char boundary), marked when cut
+pub fn tail(output: &str, max_bytes: usize) -> Cow<'_, str> {
+    tail_after(0, output, max_bytes)
+}
+
+/// Like [`tail`], for output whose first `omitted` bytes are already gone
+fn tail_after(omitted: usize, output: &str, max_bytes: usize) -> Cow<'_, str> {
+    if output.len() <= max_bytes && omitted == 0 {
+        return Cow::Borrowed(output);
+    }
+
+    let mut start = output.len().saturating_sub(max_bytes);
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    Cow::Owned(format!(
+        "[... {} bytes omitted ...]\n{}",
+        omitted + start,
+        &output[start..]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_job_log_tail() {
+        assert_eq!(tail("short", 16), "short");
+        assert_eq!(
+            tail("line 1\nline 2\n", 7),
+            "[... 7 bytes omitted ...]\nline 2\n"
+        );
+        // Never splits a multi-byte character
+        assert_eq!(tail("ab€cd", 4), "[... 5 bytes omitted ...]\ncd");
+
+        let dir = tempfile::tempdir().unwrap();
+        let logs = dir.path().join("logs");
+        let path = write(&logs, "job-1", "step 1\nstep 2\n", 1024)
+            .await
+            .unwrap();
+        assert_eq!(path, logs.join("job-1.log"));
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "step 1\nstep 2\n");
+    }
+
+    #[tokio::test]
+    async fn test_job_log_streams_while_the_job_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = JobLog::create(dir.path(), "job-1").unwrap();
+        let path = log.path().to_path_buf();
+
+        scope(Some(log.clone()), async {
+            redact_secrets(&["hunter2-token".to_string()]);
+            assert!(emit("step 1 hunter2-token\n"));
+            // Written before the job ends
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "step 1 ***\n");
+            assert!(emit("step 2\n"));
+        })
+        .await;
+        assert!(!emit("outside"));
+
+        assert_eq!(
+            log.finish(1024, 7).unwrap().as_deref(),
+            Some("[... 11 bytes omitted ...]\nstep 2\n")
+        );
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "step 1 ***\nstep 2\n"
+        );
+        assert_eq!(
+            log.finish(7, 7).unwrap().as_deref(),
+            Some("[... 11 bytes omitted ...]\nstep 2\n")
+        );
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "[... 11 bytes omitted ...]\nstep 2\n"
+        );
+
+        // Nothing printed: no file
+        let empty = JobLog::create(dir.path(), "job-2").unwrap();
+        assert_eq!(empty.finish(1024, 16).unwrap(), None);
+        assert!(!empty.path().exists());
+
+        assert_eq!(prune(dir.path(), Duration::from_secs(3600)), 0);
+        assert_eq!(prune(dir.path(), Duration::ZERO), 1);
+        assert!(!path.exists());
+    }
+}
@@ -1,20 +1,35 @@
+mod artifact;
+mod canary;
+mod compose_diff;
 mod docker;
+mod docker_run;
 mod executor;
 mod git;
+mod helm;
+mod job_log;
 mod queue;
+mod s3;
+mod schedule;
 mod script;
+mod secrets;
 mod telegram;
 
+pub use compose_diff::ComposeDiff;
+pub use docker_run::validate_container;
 pub use executor::DeployExecutor;
-pub use queue::{DeployJob, DeployQueue, JobStatus};
-
-use crate::config::{DeployCategory, DeployConfig, DeploymentConfig, TriggerConfig};
-use crate::storage::{Database, DeployRecord, DeployStatus};
+pub use git::validate_git_ref;
+pub use queue::{DeployJob, DeployQueue, JobEvent, JobKind, JobStatus};
+pub use schedule::{start_scheduler, CronSchedule};
+pub use script::{CommandPolicy, ResourceLimits, ScriptRunner};
+pub use secrets::validate_secret_name;
+
+use crate::config::{DeployCategory, DeployConfig, DeploymentConfig, RetryConfig, TriggerConfig};
+use crate::storage::{DbWrite, DbWriter, DeployRecord, DeployStatus};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use time::OffsetDateTime;
-use tokio::sync::RwLock;
+use tokio::sync::{watch, RwLock, Semaphore};
 use tracing::{error, info, warn};
 
 /// Result of a deployment operation
@@ -25,185 +40,408 @@ pub struct DeployResult {
     pub output: String,
     pub error: Option<String>,
     pub duration_ms: i64,
+    /// `image@digest` of the images a docker_pull deployment started
+    pub image_digests: Vec<String>,
 }
 
-/// Start the deployment worker
+/// Start the deployment worker. Up to `workers` jobs run at the same time;
+/// `DeployQueue::next_job` keeps jobs of one concurrency group apart.
 pub async fn start_worker(
     queue: Arc<DeployQueue>,
     executor: Arc<DeployExecutor>,
-    db: Option<Arc<Database>>,
+    db_writer: Option<DbWriter>,
     deploy_config: Arc<RwLock<DeployConfig>>,
     config_path: PathBuf,
+    shutdown: watch::Receiver<bool>,
 ) {
-    info!("Starting deployment worker");
+    let workers = deploy_config.read().await.workers.max(1);
+    info!(workers, "Starting deployment worker");
+
+    // Pick up jobs that were pending when the service last stopped
+    let restored = queue.restore(&deploy_config.read().await.deployments).await;
+    if restored > 0 {
+        info!(jobs = restored, "Restored pending deployment jobs");
+    }
+
+    let slots = Arc::new(Semaphore::new(workers));
+    let worker = Arc::new(Worker {
+        queue: queue.clone(),
+        executor,
+        db_writer,
+        deploy_config,
+        config_path,
+    });
 
     loop {
-        if let Some(job) = queue.next_job().await {
-            let current_deploy_config = deploy_config.read().await.clone();
+        let Ok(slot) = slots.clone().acquire_owned().await else {
+            break;
+        };
 
-            info!(
-                deployment = %job.deployment_name,
-                agent = %job.agent_name,
-                pipeline_id = %job.pipeline_id,
-                "Processing deployment job"
-            );
+        // Stop taking jobs once shutdown starts; running jobs are allowed to finish
+        if *shutdown.borrow() {
+            break;
+        }
 
-            // Update job status to running
-            queue.update_status(&job.id, JobStatus::Running).await;
-
-            // Record in database
-            let deploy_id = if let Some(ref db) = db {
-                let record = DeployRecord {
-                    id: None,
-                    agent_name: job.agent_name.clone(),
-                    deployment_name: job.deployment_name.clone(),
-                    deploy_type: format!("{:?}", job.config.deploy_type),
-                    status: DeployStatus::Running,
-                    started_at: OffsetDateTime::now_utc(),
-                    completed_at: None,
-                    duration_ms: None,
-                    trigger_source: job.trigger_source.clone(),
-                    commit_sha: None,
-                    output: None,
-                    error_message: None,
-                };
-                db.insert_deploy(&record).ok()
-            } else {
-                None
+        if let Some(job) = queue.next_job().await {
+            // Dependencies still being deployed: put the job back and look again later
+            let dependency = match job.kind {
+                JobKind::Deploy => active_dependency(&queue, &job.config).await,
+                _ => None,
             };
+            if let Some(dependency) = dependency {
+                info!(
+                    deployment = %job.deployment_name,
+                    dependency = %dependency,
+                    "Waiting for dependency to finish deploying"
+                );
+                queue.defer(&job.id, DEPENDENCY_WAIT).await;
+                continue;
+            }
 
-            // Check if this is the root of a pipeline chain
-            let is_pipeline_root = job.trigger_source.is_none()
-                || !job.trigger_source.as_ref().unwrap().starts_with("trigger:");
+            let worker = worker.clone();
+            tokio::spawn(async move {
+                worker.run(job).await;
+                drop(slot);
+            });
+        } else {
+            drop(slot);
+        }
 
-            // 1. Pipeline on_start (only for root of chain)
-            if is_pipeline_root && !job.config.pipeline.on_start.is_empty() {
-                fire_triggers(
-                    &job.config.pipeline.on_start,
-                    &job,
-                    &queue,
-                    &current_deploy_config.deployments,
-                    &build_trigger_env(&job, None, "on_start"),
-                )
-                .await;
-            }
+        // Small delay to prevent busy loop
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+    }
 
-            // 2. Execute deployment
-            let result = executor
-                .execute(&job.config, &current_deploy_config.allowed_deploy_paths)
-                .await;
+    // Wait for the running jobs
+    let _ = slots.acquire_many(workers as u32).await;
+    info!("Deployment worker stopped");
+}
 
-            // Update status based on result
-            let final_status = if result.success {
-                JobStatus::Completed
-            } else {
-                JobStatus::Failed
+/// State shared by the jobs the worker runs in parallel
+struct Worker {
+    queue: Arc<DeployQueue>,
+    executor: Arc<DeployExecutor>,
+    db_writer: Option<DbWriter>,
+    deploy_config: Arc<RwLock<DeployConfig>>,
+    config_path: PathBuf,
+}
+
+impl Worker {
+    /// Run one job: execute it, record the result, then retry or fire triggers
+    async fn run(&self, job: DeployJob) {
+        let current_deploy_config = self.deploy_config.read().await.clone();
+
+        info!(
+            deployment = %job.deployment_name,
+            agent = %job.agent_name,
+            pipeline_id = %job.pipeline_id,
+            kind = %job.kind,
+            "Processing deployment job"
+        );
+
+        // Update job status to running
+        self.queue.update_status(&job.id, JobStatus::Running).await;
+
+        // Record in database (buffered, committed by the writer task)
+        if let Some(ref writer) = self.db_writer {
+            let record = DeployRecord {
+                id: None,
+                agent_name: job.agent_name.clone(),
+                deployment_name: job.deployment_name.clone(),
+                deploy_type: format!("{:?}", job.config.deploy_type),
+                status: DeployStatus::Running,
+                started_at: OffsetDateTime::now_utc(),
+                completed_at: None,
+                duration_ms: None,
+                trigger_source: job.trigger_source.clone(),
+                commit_sha: None,
+                output: None,
+                error_message: None,
+                attempt: job.attempt,
+                image_digests: None,
+                log_file: None,
             };
-            queue.update_status(&job.id, final_status).await;
-
-            // Update database record
-            if let (Some(ref db), Some(id)) = (&db, deploy_id) {
-                let status = if result.success {
-                    DeployStatus::Success
-                } else {
-                    DeployStatus::Failed
-                };
-                let _ = db.update_deploy_status(
-                    id,
-                    status,
-                    Some(OffsetDateTime::now_utc()),
-                    Some(result.duration_ms),
-                    Some(&result.output),
-                    result.error.as_deref(),
-                );
-            }
+            writer
+                .send(DbWrite::DeployStarted {
+                    job_id: job.id.clone(),
+                    record,
+                })
+                .await;
+        }
 
-            let latest_deploy_config = reload_deploy_config(&deploy_config, &config_path)
-                .await
-                .unwrap_or(current_deploy_config);
+        // Check if this is the root of a pipeline chain
+        let is_pipeline_root = job.trigger_source.is_none()
+            || !job.trigger_source.as_ref().unwrap().starts_with("trigger:");
+
+        // 1. Pipeline on_start (only for root of chain, not again on retries)
+        if is_pipeline_root
+            && job.kind == JobKind::Deploy
+            && job.attempt == 1
+            && !job.config.pipeline.on_start.is_empty()
+        {
+            fire_triggers(
+                &job.config.pipeline.on_start,
+                &job,
+                &self.queue,
+                &current_deploy_config.deployments,
+                &build_trigger_env(&job, None, "on_start"),
+            )
+            .await;
+        }
 
-            if result.success && !result.skipped {
-                info!(
+        // Output streams to the job's log file while the job runs
+        let log_dir = current_deploy_config.log_dir();
+        let log = match job_log::JobLog::create(&log_dir, &job.id) {
+            Ok(log) => Some(log),
+            Err(e) => {
+                warn!(
                     deployment = %job.deployment_name,
-                    duration_ms = result.duration_ms,
-                    "Deployment completed successfully"
+                    dir = %log_dir.display(),
+                    error = %e,
+                    "Failed to create deploy log file"
                 );
-
-                // 3a. Deployment on_success triggers
-                if !job.config.on_success.is_empty() {
-                    let env = build_trigger_env(&job, Some(&result), "on_success");
-                    fire_triggers(
-                        &job.config.on_success,
-                        &job,
-                        &queue,
-                        &latest_deploy_config.deployments,
-                        &env,
+                None
+            }
+        };
+
+        // 2. Execute deployment (fails without running if a dependency is stale)
+        let allowed_deploy_paths = &current_deploy_config.allowed_deploy_paths;
+        let result = job_log::scope(log.clone(), async {
+            match job.kind {
+                JobKind::Deploy => match check_dependencies(&self.queue, &job).await {
+                    Ok(()) => {
+                        self.executor
+                            .execute_cancellable(&job.config, allowed_deploy_paths, &job.cancel)
+                            .await
+                    }
+                    Err(error) => DeployResult {
+                        success: false,
+                        skipped: false,
+                        output: String::new(),
+                        error: Some(error),
+                        duration_ms: 0,
+                        image_digests: Vec::new(),
+                    },
+                },
+                JobKind::Shutdown => self.executor.shutdown(&job.config).await,
+                JobKind::Start => self.executor.start(&job.config, allowed_deploy_paths).await,
+                JobKind::Restart => {
+                    self.executor
+                        .restart(&job.config, allowed_deploy_paths)
+                        .await
+                }
+                JobKind::Rollback => {
+                    self.executor
+                        .rollback(&job.config, allowed_deploy_paths)
+                        .await
+                }
+            }
+        })
+        .await;
+        let cancelled = job.cancel.is_cancelled() && !result.success;
+
+        // Cap the log file and take the history tail from it; output that was
+        // never streamed (or a log that failed) falls back to the result
+        let logs = &current_deploy_config.logs;
+        let streamed = log.as_ref().and_then(|log| {
+            match log.finish(logs.max_bytes, logs.tail_bytes) {
+                Ok(tail) => tail.map(|tail| (log.path().display().to_string(), tail)),
+                Err(e) => {
+                    warn!(deployment = %job.deployment_name, error = %e, "Failed to finish deploy log file");
+                    None
+                }
+            }
+        });
+        let (log_file, output_tail) = match streamed {
+            Some((path, tail)) => (Some(path), tail),
+            None if result.output.is_empty() => (None, String::new()),
+            None => match job_log::write(&log_dir, &job.id, &result.output, logs.max_bytes).await {
+                Ok(path) => (
+                    Some(path.display().to_string()),
+                    job_log::tail(&result.output, logs.tail_bytes).into_owned(),
+                ),
+                Err(e) => {
+                    warn!(
+                        deployment = %job.deployment_name,
+                        dir = %log_dir.display(),
+                        error = %e,
+                        "Failed to write deploy log file"
+                    );
+                    (
+                        None,
+                        job_log::tail(&result.output, logs.tail_bytes).into_owned(),
                     )
-                    .await;
                 }
+            },
+        };
+        if let Ok(retention) = humantime::parse_duration(&logs.retention) {
+            let removed = job_log::prune(&log_dir, retention);
+            if removed > 0 {
+                info!(removed, dir = %log_dir.display(), "Expired deploy log files removed");
+            }
+        }
 
-                // 3b. Global on_success triggers (skip for system/protected deployments)
-                if !latest_deploy_config.on_success.is_empty()
-                    && job.config.category == DeployCategory::App
-                {
-                    let env = build_trigger_env(&job, Some(&result), "on_success");
-                    fire_triggers(
-                        &latest_deploy_config.on_success,
-                        &job,
-                        &queue,
-                        &latest_deploy_config.deployments,
-                        &env,
-                    )
+        // Update status based on result
+        let final_status = if result.success {
+            JobStatus::Completed
+        } else if cancelled {
+            JobStatus::Cancelled
+        } else {
+            JobStatus::Failed
+        };
+        self.queue.update_status(&job.id, final_status).await;
+
+        // Update database record
+        if let Some(ref writer) = self.db_writer {
+            let status = if result.success {
+                DeployStatus::Success
+            } else if cancelled {
+                DeployStatus::Cancelled
+            } else {
+                DeployStatus::Failed
+            };
+            if !result.image_digests.is_empty() {
+                writer
+                    .send(DbWrite::DeployImageDigests {
+                        job_id: job.id.clone(),
+                        image_digests: result.image_digests.join("\n"),
+                    })
                     .await;
-                }
-            } else if result.skipped {
+            }
+
+            // Full output is in the log file; the history keeps its tail
+            if let Some(log_file) = log_file {
+                writer
+                    .send(DbWrite::DeployLogFile {
+                        job_id: job.id.clone(),
+                        log_file,
+                    })
+                    .await;
+            }
+
+            writer
+                .send(DbWrite::DeployFinished {
+                    job_id: job.id.clone(),
+                    status,
+                    completed_at: OffsetDateTime::now_utc(),
+                    duration_ms: result.duration_ms,
+                    output: output_tail,
+                    error_message: result.error.clone(),
+                })
+                .await;
+        }
+
+        // Shutdown/start/restart/rollback have no retries, triggers or pipeline hooks
+        if job.kind != JobKind::Deploy {
+            if result.success {
                 info!(
                     deployment = %job.deployment_name,
-                    "Deployment skipped (no changes), triggers not fired"
+                    kind = %job.kind,
+                    duration_ms = result.duration_ms,
+                    "Lifecycle job completed successfully"
                 );
             } else {
-                error!(
+                warn!(
                     deployment = %job.deployment_name,
+                    kind = %job.kind,
                     error = ?result.error,
-                    "Deployment failed"
+                    "Lifecycle job failed"
                 );
+            }
+            return;
+        }
 
-                // 4a. Deployment on_error triggers
-                if !job.config.on_error.is_empty() {
-                    let env = build_trigger_env(&job, Some(&result), "on_error");
-                    fire_triggers(
-                        &job.config.on_error,
-                        &job,
-                        &queue,
-                        &latest_deploy_config.deployments,
-                        &env,
-                    )
-                    .await;
-                }
+        // Failed attempts are re-queued while the retry policy allows it
+        let retry_delay = match job.config.retry {
+            Some(ref retry) if !result.success && !cancelled => retry_delay(retry, job.attempt),
+            _ => None,
+        };
 
-                // 4b. Global on_error triggers (skip for system/protected deployments)
-                if !latest_deploy_config.on_error.is_empty()
-                    && job.config.category == DeployCategory::App
-                {
-                    let env = build_trigger_env(&job, Some(&result), "on_error");
-                    fire_triggers(
-                        &latest_deploy_config.on_error,
-                        &job,
-                        &queue,
-                        &latest_deploy_config.deployments,
-                        &env,
-                    )
-                    .await;
-                }
+        let latest_deploy_config = reload_deploy_config(&self.deploy_config, &self.config_path)
+            .await
+            .unwrap_or(current_deploy_config);
+
+        if result.success && !result.skipped {
+            info!(
+                deployment = %job.deployment_name,
+                duration_ms = result.duration_ms,
+                "Deployment completed successfully"
+            );
+
+            // 3a. Deployment on_success triggers
+            if !job.config.on_success.is_empty() {
+                let env = build_trigger_env(&job, Some(&result), "on_success");
+                fire_triggers(
+                    &job.config.on_success,
+                    &job,
+                    &self.queue,
+                    &latest_deploy_config.deployments,
+                    &env,
+                )
+                .await;
+            }
+
+            // 3b. Global on_success triggers (skip for system/protected deployments)
+            if !latest_deploy_config.on_success.is_empty()
+                && job.config.category == DeployCategory::App
+            {
+                let env = build_trigger_env(&job, Some(&result), "on_success");
+                fire_triggers(
+                    &latest_deploy_config.on_success,
+                    &job,
+                    &self.queue,
+                    &latest_deploy_config.deployments,
+                    &env,
+                )
+                .await;
+            }
+        } else if result.skipped {
+            info!(
+                deployment = %job.deployment_name,
+                "Deployment skipped (no changes), triggers not fired"
+            );
+        } else if cancelled {
+            warn!(
+                deployment = %job.deployment_name,
+                "Deployment cancelled, on_error triggers not fired"
+            );
+        } else if let Some(delay) = retry_delay {
+            warn!(
+                deployment = %job.deployment_name,
+                attempt = job.attempt,
+                retry_in = ?delay,
+                error = ?result.error,
+                "Deployment failed, retrying"
+            );
+            self.queue.enqueue(job.retry(delay)).await;
+        } else {
+            error!(
+                deployment = %job.deployment_name,
+                error = ?result.error,
+                "Deployment failed"
+            );
+
+            // 4a. Deployment on_error triggers
+            if !job.config.on_error.is_empty() {
+                let env = build_trigger_env(&job, Some(&result), "on_error");
+                fire_triggers(
+                    &job.config.on_error,
+                    &job,
+                    &self.queue,
+                    &latest_deploy_config.deployments,
+                    &env,
+                )
+                .await;
             }
 
-            // 5. Pipeline on_finish (ALWAYS, if this is a terminal node)
-            if is_chain_terminal(&job, &result) && !job.config.pipeline.on_finish.is_empty() {
-                let env = build_trigger_env(&job, Some(&result), "on_finish");
+            // 4b. Global on_error triggers (skip for system/protected deployments)
+            if !latest_deploy_config.on_error.is_empty()
+                && job.config.category == DeployCategory::App
+            {
+                let env = build_trigger_env(&job, Some(&result), "on_error");
                 fire_triggers(
-                    &job.config.pipeline.on_finish,
+                    &latest_deploy_config.on_error,
                     &job,
-                    &queue,
+                    &self.queue,
                     &latest_deploy_config.deployments,
                     &env,
                 )
@@ -211,11 +449,79 @@ pub async fn start_worker(
             }
         }
 
-        // Small delay to prevent busy loop
-        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        // 5. Pipeline on_finish (ALWAYS, if this is a terminal node and no retry is pending)
+        if retry_delay.is_none()
+            && is_chain_terminal(&job, &result)
+            && !job.config.pipeline.on_finish.is_empty()
+        {
+            let env = build_trigger_env(&job, Some(&result), "on_finish");
+            fire_triggers(
+                &job.config.pipeline.on_finish,
+                &job,
+                &self.queue,
+                &latest_deploy_config.deployments,
+                &env,
+            )
+            .await;
+        }
     }
 }
 
+/// How long a job waits before re-checking a dependency that is still deploying
+const DEPENDENCY_WAIT: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Default for `depends_max_age`
+const DEFAULT_DEPENDS_MAX_AGE: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+/// First dependency that has a pending or running job
+async fn active_dependency(queue: &DeployQueue, config: &DeploymentConfig) -> Option<String> {
+    for dependency in &config.depends_on {
+        if queue.has_active_job(dependency).await {
+            return Some(dependency.clone());
+        }
+    }
+    None
+}
+
+/// Every dependency must have succeeded within `depends_max_age`
+async fn check_dependencies(queue: &DeployQueue, job: &DeployJob) -> Result<(), String> {
+    if job.config.depends_on.is_empty() {
+        return Ok(());
+    }
+
+    let max_age = job
+        .config
+        .depends_max_age
+        .as_deref()
+        .and_then(|age| humantime::parse_duration(age).ok())
+        .unwrap_or(DEFAULT_DEPENDS_MAX_AGE);
+    let cutoff = OffsetDateTime::now_utc() - max_age;
+
+    for dependency in &job.config.depends_on {
+        match queue.last_success(&job.agent_name, dependency).await {
+            Some(completed_at) if completed_at >= cutoff => {}
+            _ => {
+                return Err(format!(
+                    "Dependency '{}' has no successful deploy in the last {}",
+                    dependency,
+                    humantime::format_duration(max_age)
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Backoff before the next attempt, or None once `attempts` are used up.
+/// The delay doubles with every attempt: backoff, 2*backoff, 4*backoff, ...
+fn retry_delay(retry: &RetryConfig, attempt: u32) -> Option<std::time::Duration> {
+    if attempt >= retry.attempts {
+        return None;
+    }
+    let backoff = humantime::parse_duration(&retry.backoff).ok()?;
+    Some(backoff.saturating_mul(1 << (attempt - 1).min(10)))
+}
+
 async fn reload_deploy_config(
     deploy_config: &Arc<RwLock<DeployConfig>>,
     config_path: &Path,
@@ -391,6 +697,7 @@ mod tests {
             path: None,
             repo: None,
             branch: None,
+            git_ref: None,
             remote: None,
             ssh_key: None,
             compose_file: None,
@@ -398,19 +705,44 @@ mod tests {
             script: Some("echo test".to_string()),
             working_dir: None,
             user: None,
+            nice: None,
+            cpu_limit: None,
+            memory_limit: None,
+            shell_policy: Default::default(),
+            shell_allow: vec![],
             env: Default::default(),
             pre_deploy: Default::default(),
             post_deploy: Default::default(),
             shutdown: Default::default(),
             timeout: None,
             prune: false,
+            depth: None,
+            fetch_tags: false,
+            submodules: false,
             git_files: vec![],
             on_success: Default::default(),
             on_error: Default::default(),
             pipeline: Default::default(),
             continue_on_failure: false,
+            depends_on: vec![],
+            depends_max_age: None,
+            concurrency_group: None,
             strategy: None,
+            canary: None,
+            retry: None,
+            priority: Default::default(),
+            schedule: None,
             telegram: None,
+            helm: None,
+            container: None,
+            release: None,
+            s3: None,
+            registry_auth: None,
+            image_digests: Default::default(),
+            secrets: Default::default(),
+            vars: Default::default(),
+            requires_approval: false,
+            approvers: vec![],
             force: false,
         }
     }
@@ -496,4 +828,17 @@ mod tests {
         assert!(job.is_some());
         assert_eq!(job.unwrap().deployment_name, "notifier");
     }
+
+    #[test]
+    fn test_retry_delay_doubles_until_attempts_used() {
+        let retry = RetryConfig {
+            attempts: 3,
+            backoff: "10s".to_string(),
+        };
+        let secs = |attempt| retry_delay(&retry, attempt).map(|d| d.as_secs());
+
+        assert_eq!(secs(1), Some(10));
+        assert_eq!(secs(2), Some(20));
+        assert_eq!(secs(3), None);
+    }
 }
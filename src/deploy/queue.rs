@@ -1,20 +1,70 @@
-use crate::config::DeploymentConfig;
+use crate::config::{DeployPriority, DeploymentConfig};
+use crate::storage::{Database, DbWrite, DbWriter, DeployStatus, QueuedJob};
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
 use time::OffsetDateTime;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum JobStatus {
     Pending,
+    /// Waiting for `/webhook/approve/:job_id` (deployments with `requires_approval`)
+    #[serde(rename = "awaiting_approval")]
+    AwaitingApproval,
     Running,
     Completed,
     Failed,
     Cancelled,
 }
 
+/// What the worker does with a job
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    #[default]
+    Deploy,
+    /// Run the deployment's shutdown commands (`/webhook/shutdown/:name`)
+    Shutdown,
+    /// `docker compose start` (`/webhook/start/:name`)
+    Start,
+    /// `docker compose restart` (`/webhook/restart/:name`)
+    Restart,
+    /// Revert to the state before the last pull (`/webhook/rollback/:name`)
+    Rollback,
+}
+
+impl std::fmt::Display for JobKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobKind::Deploy => write!(f, "deploy"),
+            JobKind::Shutdown => write!(f, "shutdown"),
+            JobKind::Start => write!(f, "start"),
+            JobKind::Restart => write!(f, "restart"),
+            JobKind::Rollback => write!(f, "rollback"),
+        }
+    }
+}
+
+impl std::str::FromStr for JobKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deploy" => Ok(JobKind::Deploy),
+            "shutdown" => Ok(JobKind::Shutdown),
+            "start" => Ok(JobKind::Start),
+            "restart" => Ok(JobKind::Restart),
+            "rollback" => Ok(JobKind::Rollback),
+            _ => Err(format!("Unknown job kind: {}", s)),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DeployJob {
     pub id: String,
@@ -22,11 +72,22 @@ pub struct DeployJob {
     pub agent_name: String,
     pub deployment_name: String,
     pub config: DeploymentConfig,
+    pub kind: JobKind,
     pub status: JobStatus,
     pub created_at: OffsetDateTime,
     pub started_at: Option<OffsetDateTime>,
     pub completed_at: Option<OffsetDateTime>,
     pub trigger_source: Option<String>,
+    /// Queue priority, taken from the deployment config
+    pub priority: DeployPriority,
+    /// Cancelled to abort the job while it is running
+    pub cancel: CancellationToken,
+    /// Retry attempt (1 for the first run)
+    pub attempt: u32,
+    /// Retries and deferred jobs wait in the queue until this time
+    pub not_before: Option<OffsetDateTime>,
+    /// Token subject that approved the job (`requires_approval`)
+    pub approved_by: Option<String>,
 }
 
 impl DeployJob {
@@ -42,12 +103,115 @@ impl DeployJob {
             pipeline_id: pipeline_id.unwrap_or_else(|| Uuid::new_v4().to_string()),
             agent_name,
             deployment_name,
+            priority: config.priority,
+            kind: JobKind::Deploy,
+            status: if config.requires_approval {
+                JobStatus::AwaitingApproval
+            } else {
+                JobStatus::Pending
+            },
             config,
-            status: JobStatus::Pending,
             created_at: OffsetDateTime::now_utc(),
             started_at: None,
             completed_at: None,
             trigger_source,
+            cancel: CancellationToken::new(),
+            attempt: 1,
+            not_before: None,
+            approved_by: None,
+        }
+    }
+
+    /// Shutdown, start or restart of a deployment; these need no approval
+    pub fn lifecycle(
+        kind: JobKind,
+        agent_name: String,
+        deployment_name: String,
+        config: DeploymentConfig,
+    ) -> Self {
+        let mut job = Self::new(
+            agent_name,
+            deployment_name,
+            config,
+            Some(kind.to_string()),
+            None,
+        );
+        job.kind = kind;
+        job.status = JobStatus::Pending;
+        job
+    }
+
+    /// Jobs of the same group never run at the same time; a deployment without
+    /// `concurrency_group` forms a group of its own
+    pub fn concurrency_group(&self) -> &str {
+        self.config
+            .concurrency_group
+            .as_deref()
+            .unwrap_or(&self.deployment_name)
+    }
+
+    /// Next attempt of a failed job, runnable after `delay`
+    pub fn retry(&self, delay: std::time::Duration) -> Self {
+        let mut job = Self::new(
+            self.agent_name.clone(),
+            self.deployment_name.clone(),
+            self.config.clone(),
+            self.trigger_source.clone(),
+            Some(self.pipeline_id.clone()),
+        );
+        job.kind = self.kind;
+        job.attempt = self.attempt + 1;
+        job.not_before = Some(job.created_at + delay);
+        // Retries of an approved job need no new approval, others still do
+        job.approved_by = self.approved_by.clone();
+        job.status = job.initial_status();
+        job
+    }
+
+    /// Deploys of a `requires_approval` deployment wait for approval until
+    /// someone approved them; lifecycle jobs never do
+    fn initial_status(&self) -> JobStatus {
+        if self.kind == JobKind::Deploy
+            && self.config.requires_approval
+            && self.approved_by.is_none()
+        {
+            JobStatus::AwaitingApproval
+        } else {
+            JobStatus::Pending
+        }
+    }
+
+    /// Cancelled while it was still waiting for approval; retrying such a job
+    /// would skip the approval that never happened
+    pub fn cancelled_before_approval(&self) -> bool {
+        self.status == JobStatus::Cancelled && self.initial_status() == JobStatus::AwaitingApproval
+    }
+}
+
+/// Job status transition, broadcast to live dashboard subscribers
+#[derive(Debug, Clone, Serialize)]
+pub struct JobEvent {
+    pub id: String,
+    pub pipeline_id: String,
+    pub deployment: String,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    pub priority: DeployPriority,
+    pub attempt: u32,
+    pub trigger_source: Option<String>,
+}
+
+impl From<&DeployJob> for JobEvent {
+    fn from(job: &DeployJob) -> Self {
+        Self {
+            id: job.id.clone(),
+            pipeline_id: job.pipeline_id.clone(),
+            deployment: job.deployment_name.clone(),
+            kind: job.kind,
+            status: job.status.clone(),
+            priority: job.priority,
+            attempt: job.attempt,
+            trigger_source: job.trigger_source.clone(),
         }
     }
 }
@@ -56,6 +220,10 @@ pub struct DeployQueue {
     jobs: RwLock<VecDeque<DeployJob>>,
     history: RwLock<Vec<DeployJob>>,
     max_history: usize,
+    /// Pending jobs are mirrored to SQLite so they survive restarts; the
+    /// writer records the deploy history
+    store: Option<(Arc<Database>, DbWriter)>,
+    events: broadcast::Sender<JobEvent>,
 }
 
 impl DeployQueue {
@@ -64,34 +232,187 @@ impl DeployQueue {
             jobs: RwLock::new(VecDeque::new()),
             history: RwLock::new(Vec::new()),
             max_history,
+            store: None,
+            events: broadcast::channel(256).0,
+        }
+    }
+
+    /// Queue backed by the `deploy_queue` table
+    pub fn with_store(max_history: usize, db: Arc<Database>, writer: DbWriter) -> Self {
+        Self {
+            store: Some((db, writer)),
+            ..Self::new(max_history)
+        }
+    }
+
+    /// Receive every job status transition (queued, running, finished)
+    pub fn subscribe(&self) -> broadcast::Receiver<JobEvent> {
+        self.events.subscribe()
+    }
+
+    fn notify(&self, job: &DeployJob) {
+        // No receivers is fine: nobody is watching
+        let _ = self.events.send(JobEvent::from(job));
+    }
+
+    /// Writer the deploy history goes through, when the queue has a store
+    pub fn writer(&self) -> Option<&DbWriter> {
+        self.store.as_ref().map(|(_, writer)| writer)
+    }
+
+    /// Save a waiting job's row. Queue rows are written straight away rather
+    /// than through the buffered writer, so an acknowledged job survives a
+    /// crash.
+    async fn persist(&self, job: &DeployJob) {
+        let queued = queued_job(job);
+        self.write_row(&queued.id.clone(), move |db| db.insert_queued_job(&queued))
+            .await;
+    }
+
+    /// Remove a job's row once it runs or is dropped
+    async fn unpersist(&self, job_id: &str) {
+        let id = job_id.to_string();
+        self.write_row(job_id, move |db| db.delete_queued_job(&id))
+            .await;
+    }
+
+    async fn write_row<F>(&self, job_id: &str, write: F)
+    where
+        F: FnOnce(&Database) -> rusqlite::Result<()> + Send + 'static,
+    {
+        let Some((db, _)) = &self.store else {
+            return;
+        };
+        let db = db.clone();
+        let result = tokio::task::spawn_blocking(move || write(&db))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|r| r.map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!(job_id = %job_id, error = %e, "Failed to persist deploy queue");
         }
     }
 
     /// Add a new job to the queue
     pub async fn enqueue(&self, job: DeployJob) -> String {
         let id = job.id.clone();
+        self.persist(&job).await;
+        self.notify(&job);
         let mut jobs = self.jobs.write().await;
         jobs.push_back(job);
         id
     }
 
-    /// Get the next pending job
+    /// Reload jobs that were still pending when the service stopped.
+    /// Their config is taken from the current deployments; jobs for
+    /// deployments that no longer exist are dropped.
+    pub async fn restore(&self, deployments: &[DeploymentConfig]) -> usize {
+        let Some((db, _)) = &self.store else {
+            return 0;
+        };
+
+        let queued = match db.get_queued_jobs() {
+            Ok(queued) => queued,
+            Err(e) => {
+                warn!(error = %e, "Failed to load persisted deploy queue");
+                return 0;
+            }
+        };
+
+        let mut jobs = self.jobs.write().await;
+        let mut restored = 0;
+        let mut unknown = Vec::new();
+        for queued in queued {
+            if jobs.iter().any(|j| j.id == queued.id) {
+                continue;
+            }
+            let Some(config) = deployments
+                .iter()
+                .find(|d| d.name == queued.deployment_name)
+            else {
+                warn!(
+                    deployment = %queued.deployment_name,
+                    job_id = %queued.id,
+                    "Dropping persisted job for unknown deployment"
+                );
+                unknown.push(queued.id);
+                continue;
+            };
+
+            // Approvals are not persisted: first attempts are approved again
+            let status = if config.requires_approval
+                && queued.attempt == 1
+                && queued.kind == JobKind::Deploy
+            {
+                JobStatus::AwaitingApproval
+            } else {
+                JobStatus::Pending
+            };
+            let job = DeployJob {
+                id: queued.id,
+                pipeline_id: queued.pipeline_id,
+                agent_name: queued.agent_name,
+                deployment_name: queued.deployment_name,
+                priority: config.priority,
+                config: config.clone(),
+                kind: queued.kind,
+                status,
+                created_at: queued.created_at,
+                started_at: None,
+                completed_at: None,
+                trigger_source: queued.trigger_source,
+                cancel: CancellationToken::new(),
+                attempt: queued.attempt,
+                not_before: queued.not_before,
+                approved_by: None,
+            };
+            self.notify(&job);
+            jobs.push_back(job);
+            restored += 1;
+        }
+        drop(jobs);
+
+        for job_id in unknown {
+            self.unpersist(&job_id).await;
+        }
+        restored
+    }
+
+    /// Get the next pending job: highest priority first, FIFO within a priority.
+    /// Jobs whose concurrency group already has a running job are passed over.
     pub async fn next_job(&self) -> Option<DeployJob> {
         let mut jobs = self.jobs.write().await;
 
-        // Find the first pending job
-        if let Some(pos) = jobs.iter().position(|j| j.status == JobStatus::Pending) {
-            let mut job = jobs.remove(pos)?;
-            job.status = JobStatus::Running;
-            job.started_at = Some(OffsetDateTime::now_utc());
+        let busy: HashSet<&str> = jobs
+            .iter()
+            .filter(|j| j.status == JobStatus::Running)
+            .map(|j| j.concurrency_group())
+            .collect();
 
-            // Keep in queue for status tracking
-            jobs.push_front(job.clone());
+        // Skip retries whose backoff has not elapsed yet
+        let now = OffsetDateTime::now_utc();
+        let next = jobs
+            .iter()
+            .enumerate()
+            .filter(|(_, j)| {
+                j.status == JobStatus::Pending
+                    && j.not_before.is_none_or(|t| t <= now)
+                    && !busy.contains(j.concurrency_group())
+            })
+            .min_by_key(|(_, j)| std::cmp::Reverse(j.priority))
+            .map(|(pos, _)| pos);
 
-            Some(job)
-        } else {
-            None
-        }
+        let mut job = jobs.remove(next?)?;
+        job.status = JobStatus::Running;
+        job.started_at = Some(OffsetDateTime::now_utc());
+
+        // Keep in queue for status tracking
+        jobs.push_front(job.clone());
+        drop(jobs);
+        self.unpersist(&job.id).await;
+        self.notify(&job);
+
+        Some(job)
     }
 
     /// Update job status
@@ -99,7 +420,10 @@ impl DeployQueue {
         let mut jobs = self.jobs.write().await;
 
         if let Some(job) = jobs.iter_mut().find(|j| j.id == job_id) {
-            job.status = status.clone();
+            if job.status != status {
+                job.status = status.clone();
+                self.notify(job);
+            }
 
             if matches!(
                 status,
@@ -110,14 +434,7 @@ impl DeployQueue {
                 // Move to history
                 let completed_job = job.clone();
                 drop(jobs);
-
-                let mut history = self.history.write().await;
-                history.push(completed_job);
-
-                // Trim history if needed
-                while history.len() > self.max_history {
-                    history.remove(0);
-                }
+                self.archive(completed_job).await;
 
                 // Remove from active queue
                 let mut jobs = self.jobs.write().await;
@@ -126,6 +443,103 @@ impl DeployQueue {
         }
     }
 
+    async fn archive(&self, job: DeployJob) {
+        let mut history = self.history.write().await;
+        history.push(job);
+
+        // Trim history if needed
+        while history.len() > self.max_history {
+            history.remove(0);
+        }
+    }
+
+    /// Drop a waiting (pending or awaiting approval) job from the queue; it
+    /// is kept in the history as cancelled. Returns false if the job is not waiting.
+    pub async fn remove(&self, job_id: &str) -> bool {
+        let removed = {
+            let mut jobs = self.jobs.write().await;
+            let Some(pos) = jobs.iter().position(|j| {
+                j.id == job_id
+                    && matches!(j.status, JobStatus::Pending | JobStatus::AwaitingApproval)
+            }) else {
+                return false;
+            };
+            jobs.remove(pos)
+        };
+
+        if let Some(job) = removed {
+            self.archive(self.drop_waiting(job).await).await;
+        }
+        true
+    }
+
+    /// Drop every waiting job; running jobs are left alone. Returns the number removed.
+    pub async fn clear(&self) -> usize {
+        let removed: Vec<DeployJob> = {
+            let mut jobs = self.jobs.write().await;
+            let (waiting, rest): (VecDeque<_>, VecDeque<_>) =
+                std::mem::take(&mut *jobs).into_iter().partition(|j| {
+                    matches!(j.status, JobStatus::Pending | JobStatus::AwaitingApproval)
+                });
+            *jobs = rest;
+            waiting.into()
+        };
+
+        let count = removed.len();
+        for job in removed {
+            self.archive(self.drop_waiting(job).await).await;
+        }
+        count
+    }
+
+    async fn drop_waiting(&self, mut job: DeployJob) -> DeployJob {
+        job.status = JobStatus::Cancelled;
+        job.completed_at = Some(OffsetDateTime::now_utc());
+        self.unpersist(&job.id).await;
+        self.notify(&job);
+        job
+    }
+
+    /// Move a waiting job ahead of the other jobs of its priority.
+    /// Returns false if the job is not waiting.
+    pub async fn promote(&self, job_id: &str) -> bool {
+        let mut jobs = self.jobs.write().await;
+        let Some(pos) = jobs.iter().position(|j| {
+            j.id == job_id && matches!(j.status, JobStatus::Pending | JobStatus::AwaitingApproval)
+        }) else {
+            return false;
+        };
+
+        if let Some(job) = jobs.remove(pos) {
+            jobs.push_front(job);
+        }
+        true
+    }
+
+    /// Queue a failed or cancelled job again as its next attempt, using
+    /// `config` (the deployment's current config). Returns the new job id,
+    /// or None if the job is unknown, did not fail, or was cancelled before
+    /// it was approved. The retry waits for approval unless the job was
+    /// approved.
+    pub async fn retry(&self, job_id: &str, config: DeploymentConfig) -> Option<String> {
+        let job = self.get_job(job_id).await?;
+        if !matches!(job.status, JobStatus::Failed | JobStatus::Cancelled)
+            || job.cancelled_before_approval()
+        {
+            return None;
+        }
+
+        let mut retry = job.retry(std::time::Duration::ZERO);
+        retry.not_before = None;
+        retry.priority = config.priority;
+        retry.config = DeploymentConfig {
+            force: job.config.force,
+            ..config
+        };
+        retry.status = retry.initial_status();
+        Some(self.enqueue(retry).await)
+    }
+
     /// Get current queue status
     pub async fn get_queue_status(&self) -> Vec<DeployJob> {
         let jobs = self.jobs.read().await;
@@ -159,23 +573,154 @@ impl DeployQueue {
         None
     }
 
-    /// Cancel a pending job
-    #[allow(dead_code)]
+    /// Cancel a job. Pending jobs are removed from the queue and kept in the
+    /// history as cancelled; running jobs have their token cancelled and are
+    /// marked cancelled by the worker once the executor has stopped.
     pub async fn cancel(&self, job_id: &str) -> bool {
+        let removed = {
+            let mut jobs = self.jobs.write().await;
+            let Some(pos) = jobs.iter().position(|j| j.id == job_id) else {
+                return false;
+            };
+
+            match jobs[pos].status {
+                JobStatus::Pending | JobStatus::AwaitingApproval => jobs.remove(pos),
+                JobStatus::Running => {
+                    jobs[pos].cancel.cancel();
+                    return true;
+                }
+                _ => return false,
+            }
+        };
+
+        if let Some(job) = removed {
+            self.archive(self.drop_waiting(job).await).await;
+        }
+        true
+    }
+
+    /// Put a job taken by `next_job` back as pending, runnable after `delay`
+    pub async fn defer(&self, job_id: &str, delay: std::time::Duration) {
+        let deferred = {
+            let mut jobs = self.jobs.write().await;
+            let Some(job) = jobs.iter_mut().find(|j| j.id == job_id) else {
+                return;
+            };
+            job.status = JobStatus::Pending;
+            job.started_at = None;
+            job.not_before = Some(OffsetDateTime::now_utc() + delay);
+            job.clone()
+        };
+
+        self.persist(&deferred).await;
+        self.notify(&deferred);
+    }
+
+    /// Jobs currently being executed
+    pub async fn running(&self) -> Vec<DeployJob> {
+        let jobs = self.jobs.read().await;
+        jobs.iter()
+            .filter(|j| j.status == JobStatus::Running)
+            .cloned()
+            .collect()
+    }
+
+    /// Shutdown could not wait for the running jobs: mark their history rows
+    /// interrupted and persist them again so the next start re-runs them.
+    /// Returns the number of jobs checkpointed.
+    pub async fn checkpoint_running(&self) -> usize {
+        let jobs = self.running().await;
+        let now = OffsetDateTime::now_utc();
+
+        for job in &jobs {
+            let duration_ms = job
+                .started_at
+                .map_or(0, |started| (now - started).whole_milliseconds() as i64);
+            if let Some(writer) = self.writer() {
+                writer
+                    .send(DbWrite::DeployFinished {
+                        job_id: job.id.clone(),
+                        status: DeployStatus::Interrupted,
+                        completed_at: now,
+                        duration_ms,
+                        output: String::new(),
+                        error_message: Some("Interrupted by shutdown".to_string()),
+                    })
+                    .await;
+            }
+            self.persist(job).await;
+        }
+        jobs.len()
+    }
+
+    /// When a deployment was last deployed successfully (in memory or in the
+    /// deploy history, which outlives restarts when the queue has a store).
+    /// Shutdown, start, restart and rollback jobs do not count.
+    pub async fn last_success(
+        &self,
+        agent_name: &str,
+        deployment_name: &str,
+    ) -> Option<OffsetDateTime> {
+        let in_memory = {
+            let history = self.history.read().await;
+            history
+                .iter()
+                .filter(|j| {
+                    j.agent_name == agent_name
+                        && j.deployment_name == deployment_name
+                        && j.kind == JobKind::Deploy
+                        && j.status == JobStatus::Completed
+                })
+                .filter_map(|j| j.completed_at)
+                .max()
+        };
+
+        let stored = match &self.store {
+            Some((db, _)) => {
+                let db = db.clone();
+                let (agent, deployment) = (agent_name.to_string(), deployment_name.to_string());
+                tokio::task::spawn_blocking(move || db.last_successful_deploy(&agent, &deployment))
+                    .await
+                    .map_err(|e| e.to_string())
+                    .and_then(|r| r.map_err(|e| e.to_string()))
+                    .unwrap_or_else(|e| {
+                        warn!(error = %e, deployment = %deployment_name, "Failed to query deploy history");
+                        None
+                    })
+            }
+            None => None,
+        };
+
+        in_memory.max(stored)
+    }
+
+    /// Release a job awaiting approval; false if it is not awaiting approval
+    pub async fn approve(&self, job_id: &str, approver: &str) -> bool {
         let mut jobs = self.jobs.write().await;
 
-        if let Some(job) = jobs
-            .iter_mut()
-            .find(|j| j.id == job_id && j.status == JobStatus::Pending)
-        {
-            job.status = JobStatus::Cancelled;
-            job.completed_at = Some(OffsetDateTime::now_utc());
-            true
-        } else {
-            false
+        match jobs.iter_mut().find(|j| j.id == job_id) {
+            Some(job) if job.status == JobStatus::AwaitingApproval => {
+                job.status = JobStatus::Pending;
+                job.approved_by = Some(approver.to_string());
+                self.notify(job);
+                true
+            }
+            _ => false,
         }
     }
 
+    /// Whether a deployment already has a pending, awaiting or running job
+    pub async fn has_active_job(&self, deployment_name: &str) -> bool {
+        let jobs = self.jobs.read().await;
+        jobs.iter().any(|j| {
+            j.deployment_name == deployment_name
+                && matches!(
+                    j.status,
+                    JobStatus::Pending | JobStatus::AwaitingApproval | JobStatus::Running
+                )
+        })
+    }
+
     /// Get queue length
     pub async fn len(&self) -> usize {
         let jobs = self.jobs.read().await;
@@ -215,6 +760,20 @@ impl DeployQueue {
     }
 }
 
+fn queued_job(job: &DeployJob) -> QueuedJob {
+    QueuedJob {
+        id: job.id.clone(),
+        pipeline_id: job.pipeline_id.clone(),
+        agent_name: job.agent_name.clone(),
+        deployment_name: job.deployment_name.clone(),
+        kind: job.kind,
+        created_at: job.created_at,
+        trigger_source: job.trigger_source.clone(),
+        attempt: job.attempt,
+        not_before: job.not_before,
+    }
+}
+
 impl Default for DeployQueue {
     fn default() -> Self {
         Self::new(100)
@@ -225,6 +784,7 @@ impl Default for DeployQueue {
 mod tests {
     use super::*;
     use crate::config::{DeployType, DeploymentConfig};
+    use crate::storage::DeployRecord;
 
     fn test_config() -> DeploymentConfig {
         DeploymentConfig {
@@ -233,6 +793,7 @@ mod tests {
             path: None,
             repo: None,
             branch: None,
+            git_ref: None,
             remote: None,
             ssh_key: None,
             compose_file: None,
@@ -240,20 +801,45 @@ mod tests {
             script: Some("echo test".to_string()),
             working_dir: None,
             user: None,
+            nice: None,
+            cpu_limit: None,
+            memory_limit: None,
+            shell_policy: Default::default(),
+            shell_allow: vec![],
             env: Default::default(),
             pre_deploy: Default::default(),
             post_deploy: Default::default(),
             shutdown: Default::default(),
             timeout: None,
             prune: false,
+            depth: None,
+            fetch_tags: false,
+            submodules: false,
             git_files: vec![],
             on_success: Default::default(),
             on_error: Default::default(),
             pipeline: Default::default(),
             continue_on_failure: false,
+            depends_on: vec![],
+            depends_max_age: None,
+            concurrency_group: None,
             strategy: None,
+            canary: None,
+            retry: None,
+            priority: Default::default(),
+            schedule: None,
             category: Default::default(),
             telegram: None,
+            helm: None,
+            container: None,
+            release: None,
+            s3: None,
+            registry_auth: None,
+            image_digests: Default::default(),
+            secrets: Default::default(),
+            vars: Default::default(),
+            requires_approval: false,
+            approvers: vec![],
             force: false,
         }
     }
@@ -328,4 +914,452 @@ mod tests {
         let result = queue.get_pipeline_jobs("nonexistent").await;
         assert!(result.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_pending_jobs_survive_restart() {
+        let db = Arc::new(Database::new(":memory:").unwrap());
+        db.migrate().unwrap();
+        let writer = DbWriter::spawn(db.clone(), &Default::default());
+
+        let queue = DeployQueue::with_store(100, db.clone(), writer.clone());
+        let started = DeployJob::new("local".into(), "test".into(), test_config(), None, None);
+        let pending = DeployJob::new("local".into(), "test".into(), test_config(), None, None);
+        let pending_id = pending.id.clone();
+        queue.enqueue(started).await;
+        queue.enqueue(pending).await;
+        queue.next_job().await.unwrap();
+
+        // Fresh queue after a crash, before the writer flushed: only the job
+        // that never started comes back
+        let restarted = DeployQueue::with_store(100, db, writer);
+        assert_eq!(restarted.restore(&[test_config()]).await, 1);
+        let job = restarted.next_job().await.unwrap();
+        assert_eq!(job.id, pending_id);
+        assert_eq!(job.config.name, "test");
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_job_runs_again_after_restart() {
+        let db = Arc::new(Database::new(":memory:").unwrap());
+        db.migrate().unwrap();
+        let writer = DbWriter::spawn(db.clone(), &Default::default());
+
+        let queue = DeployQueue::with_store(100, db.clone(), writer.clone());
+        let job = DeployJob::new("local".into(), "test".into(), test_config(), None, None);
+        let job_id = job.id.clone();
+        queue.enqueue(job).await;
+        let running = queue.next_job().await.unwrap();
+        writer
+            .send(DbWrite::DeployStarted {
+                job_id: running.id.clone(),
+                record: crate::storage::DeployRecord {
+                    id: None,
+                    agent_name: "local".into(),
+                    deployment_name: "test".into(),
+                    deploy_type: "Git".into(),
+                    status: DeployStatus::Running,
+                    started_at: OffsetDateTime::now_utc(),
+                    completed_at: None,
+                    duration_ms: None,
+                    trigger_source: None,
+                    commit_sha: None,
+                    output: None,
+                    error_message: None,
+                    attempt: 1,
+                    image_digests: None,
+                    log_file: None,
+                },
+            })
+            .await;
+
+        assert_eq!(queue.checkpoint_running().await, 1);
+        writer.flush().await;
+
+        let history = db
+            .get_deploy_history(&crate::storage::DeployHistoryQuery {
+                limit: 10,
+                ..Default::default()
+            })
+            .unwrap()
+            .deployments;
+        assert_eq!(history[0].status, DeployStatus::Interrupted);
+
+        let restarted = DeployQueue::with_store(100, db, writer);
+        assert_eq!(restarted.restore(&[test_config()]).await, 1);
+        assert_eq!(restarted.next_job().await.unwrap().id, job_id);
+    }
+
+    #[tokio::test]
+    async fn test_remove_clear_and_promote() {
+        let queue = DeployQueue::new(100);
+        let mut ids = Vec::new();
+        for name in ["a", "b", "c"] {
+            let mut config = test_config();
+            config.name = name.to_string();
+            ids.push(
+                queue
+                    .enqueue(DeployJob::new(
+                        "local".into(),
+                        name.into(),
+                        config,
+                        None,
+                        None,
+                    ))
+                    .await,
+            );
+        }
+
+        assert!(queue.promote(&ids[2]).await);
+        assert!(queue.remove(&ids[0]).await);
+        assert!(!queue.remove(&ids[0]).await);
+        assert_eq!(
+            queue.get_job(&ids[0]).await.unwrap().status,
+            JobStatus::Cancelled
+        );
+
+        let next = queue.next_job().await.unwrap();
+        assert_eq!(next.id, ids[2]);
+        // Running jobs are not removed or cleared
+        assert!(!queue.remove(&next.id).await);
+        assert_eq!(queue.clear().await, 1);
+        assert_eq!(queue.len().await, 0);
+        assert_eq!(queue.get_queue_status().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_failed_job() {
+        let queue = DeployQueue::new(100);
+        let id = queue
+            .enqueue(DeployJob::new(
+                "local".into(),
+                "test".into(),
+                test_config(),
+                None,
+                None,
+            ))
+            .await;
+        assert!(queue.retry(&id, test_config()).await.is_none());
+
+        queue.next_job().await.unwrap();
+        queue.update_status(&id, JobStatus::Failed).await;
+
+        let retry_id = queue.retry(&id, test_config()).await.unwrap();
+        let retry = queue.next_job().await.unwrap();
+        assert_eq!(retry.id, retry_id);
+        assert_eq!(retry.attempt, 2);
+    }
+
+    #[test]
+    fn test_job_kind_round_trip() {
+        for kind in [
+            JobKind::Deploy,
+            JobKind::Shutdown,
+            JobKind::Start,
+            JobKind::Restart,
+            JobKind::Rollback,
+        ] {
+            assert_eq!(kind.to_string().parse::<JobKind>(), Ok(kind));
+        }
+        assert!("stop".parse::<JobKind>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_job_survives_restart() {
+        let db = Arc::new(Database::new(":memory:").unwrap());
+        db.migrate().unwrap();
+        let writer = DbWriter::spawn(db.clone(), &Default::default());
+
+        let mut config = test_config();
+        config.requires_approval = true;
+        let queue = DeployQueue::with_store(100, db.clone(), writer.clone());
+        let job = DeployJob::lifecycle(
+            JobKind::Shutdown,
+            "local".into(),
+            "test".into(),
+            config.clone(),
+        );
+        assert_eq!(job.status, JobStatus::Pending);
+        assert_eq!(job.trigger_source.as_deref(), Some("shutdown"));
+        queue.enqueue(job).await;
+        writer.flush().await;
+
+        let restarted = DeployQueue::with_store(100, db, writer);
+        assert_eq!(restarted.restore(&[config]).await, 1);
+        let job = restarted.next_job().await.unwrap();
+        assert_eq!(job.kind, JobKind::Shutdown);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_running_job_signals_token() {
+        let queue = DeployQueue::new(100);
+        let job = DeployJob::new("local".into(), "test".into(), test_config(), None, None);
+        let id = queue.enqueue(job).await;
+
+        let running = queue.next_job().await.unwrap();
+        assert!(!running.cancel.is_cancelled());
+        assert!(queue.cancel(&id).await);
+        assert!(running.cancel.is_cancelled());
+
+        // Finished jobs can no longer be cancelled
+        queue.update_status(&id, JobStatus::Cancelled).await;
+        assert!(!queue.cancel(&id).await);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_pending_job_moves_it_to_history() {
+        let queue = DeployQueue::new(100);
+        let job = DeployJob::new("local".into(), "test".into(), test_config(), None, None);
+        let id = queue.enqueue(job).await;
+
+        assert!(queue.cancel(&id).await);
+        assert!(queue.get_queue_status().await.is_empty());
+        let history = queue.get_history(10).await;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].id, id);
+        assert_eq!(history[0].status, JobStatus::Cancelled);
+        assert!(history[0].completed_at.is_some());
+        assert!(!queue.cancel(&id).await);
+    }
+
+    #[tokio::test]
+    async fn test_retry_waits_for_backoff() {
+        let queue = DeployQueue::new(100);
+        let job = DeployJob::new("local".into(), "test".into(), test_config(), None, None);
+
+        let retry = job.retry(std::time::Duration::from_secs(60));
+        assert_eq!(retry.attempt, 2);
+        assert_eq!(retry.pipeline_id, job.pipeline_id);
+        assert_ne!(retry.id, job.id);
+
+        queue.enqueue(retry).await;
+        assert!(queue.next_job().await.is_none());
+        assert_eq!(queue.len().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_deferred_job_waits_and_last_success() {
+        let queue = DeployQueue::new(100);
+        let id = queue
+            .enqueue(DeployJob::new(
+                "local".into(),
+                "test".into(),
+                test_config(),
+                None,
+                None,
+            ))
+            .await;
+
+        let job = queue.next_job().await.unwrap();
+        queue
+            .defer(&job.id, std::time::Duration::from_secs(60))
+            .await;
+        assert!(queue.next_job().await.is_none());
+        assert!(queue.has_active_job("test").await);
+        assert!(queue.last_success("local", "test").await.is_none());
+
+        queue.update_status(&id, JobStatus::Completed).await;
+        assert!(queue.last_success("local", "test").await.is_some());
+        assert!(queue.last_success("other", "test").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_last_success_survives_restart() {
+        let db = Arc::new(Database::new(":memory:").unwrap());
+        db.migrate().unwrap();
+        let writer = DbWriter::spawn(db.clone(), &Default::default());
+        let record = |deployment: &str, trigger_source: Option<&str>| DeployRecord {
+            id: None,
+            agent_name: "local".into(),
+            deployment_name: deployment.into(),
+            deploy_type: "Git".into(),
+            status: DeployStatus::Running,
+            started_at: OffsetDateTime::now_utc(),
+            completed_at: None,
+            duration_ms: None,
+            trigger_source: trigger_source.map(String::from),
+            commit_sha: None,
+            output: None,
+            error_message: None,
+            attempt: 1,
+            image_digests: None,
+            log_file: None,
+        };
+        for (job_id, record) in [
+            ("job-1", record("db", None)),
+            ("job-2", record("cache", Some("rollback"))),
+        ] {
+            writer
+                .send(DbWrite::DeployStarted {
+                    job_id: job_id.into(),
+                    record,
+                })
+                .await;
+            writer
+                .send(DbWrite::DeployFinished {
+                    job_id: job_id.into(),
+                    status: DeployStatus::Success,
+                    completed_at: OffsetDateTime::now_utc(),
+                    duration_ms: 10,
+                    output: String::new(),
+                    error_message: None,
+                })
+                .await;
+        }
+        writer.flush().await;
+
+        // Nothing in memory after a restart: the history answers
+        let restarted = DeployQueue::with_store(100, db, writer);
+        assert!(restarted.last_success("local", "db").await.is_some());
+        assert!(restarted.last_success("other", "db").await.is_none());
+        // A rollback is not a successful deploy
+        assert!(restarted.last_success("local", "cache").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_job_awaits_approval() {
+        let queue = DeployQueue::new(100);
+        let mut config = test_config();
+        config.requires_approval = true;
+        config.approvers = vec!["ops-lead".to_string()];
+        let id = queue
+            .enqueue(DeployJob::new(
+                "local".into(),
+                "test".into(),
+                config,
+                None,
+                None,
+            ))
+            .await;
+
+        assert!(queue.next_job().await.is_none());
+        assert!(queue.has_active_job("test").await);
+
+        assert!(queue.approve(&id, "ops-lead").await);
+        assert!(!queue.approve(&id, "ops-lead").await);
+        let job = queue.next_job().await.unwrap();
+        assert_eq!(job.approved_by.as_deref(), Some("ops-lead"));
+
+        // A retry keeps the approval
+        let retry = job.retry(std::time::Duration::ZERO);
+        assert_eq!(retry.status, JobStatus::Pending);
+        assert_eq!(retry.approved_by.as_deref(), Some("ops-lead"));
+    }
+
+    #[tokio::test]
+    async fn test_retry_does_not_skip_approval() {
+        let queue = DeployQueue::new(100);
+        let mut config = test_config();
+        config.requires_approval = true;
+        let job = || DeployJob::new("local".into(), "test".into(), config.clone(), None, None);
+
+        // Cancelled while awaiting approval: nothing to retry
+        let id = queue.enqueue(job()).await;
+        assert!(queue.cancel(&id).await);
+        assert!(queue.retry(&id, config.clone()).await.is_none());
+
+        // The deployment started requiring approval after the job failed
+        let mut unapproved = job();
+        unapproved.status = JobStatus::Pending;
+        unapproved.config.requires_approval = false;
+        let id = queue.enqueue(unapproved).await;
+        let failed = queue.next_job().await.unwrap();
+        queue.update_status(&id, JobStatus::Failed).await;
+        assert_eq!(
+            failed.retry(std::time::Duration::ZERO).status,
+            JobStatus::Pending
+        );
+        let retry_id = queue.retry(&id, config.clone()).await.unwrap();
+        let retry = queue.get_job(&retry_id).await.unwrap();
+        assert_eq!(retry.status, JobStatus::AwaitingApproval);
+
+        // Lifecycle jobs never wait for approval
+        let stop = DeployJob::lifecycle(JobKind::Shutdown, "local".into(), "test".into(), config);
+        assert_eq!(
+            stop.retry(std::time::Duration::ZERO).status,
+            JobStatus::Pending
+        );
+    }
+
+    #[tokio::test]
+    async fn test_next_job_by_priority_then_fifo() {
+        let queue = DeployQueue::new(100);
+        let job = |name: &str, priority| {
+            let mut config = test_config();
+            config.name = name.to_string();
+            config.priority = priority;
+            DeployJob::new("local".into(), name.into(), config, None, None)
+        };
+
+        queue
+            .enqueue(job("routine-1", DeployPriority::Normal))
+            .await;
+        queue.enqueue(job("cleanup", DeployPriority::Low)).await;
+        queue
+            .enqueue(job("routine-2", DeployPriority::Normal))
+            .await;
+        queue.enqueue(job("hotfix", DeployPriority::Urgent)).await;
+
+        let mut order = Vec::new();
+        while let Some(job) = queue.next_job().await {
+            order.push(job.deployment_name.clone());
+            queue.update_status(&job.id, JobStatus::Completed).await;
+        }
+        assert_eq!(order, ["hotfix", "routine-1", "routine-2", "cleanup"]);
+    }
+
+    #[tokio::test]
+    async fn test_next_job_skips_busy_concurrency_group() {
+        let queue = DeployQueue::new(100);
+        let job = |name: &str, group: Option<&str>| {
+            let mut config = test_config();
+            config.name = name.to_string();
+            config.concurrency_group = group.map(String::from);
+            DeployJob::new("local".into(), name.into(), config, None, None)
+        };
+
+        queue.enqueue(job("api", Some("nginx"))).await;
+        queue.enqueue(job("web", Some("nginx"))).await;
+        queue.enqueue(job("worker", None)).await;
+        queue.enqueue(job("worker", None)).await;
+
+        // "web" shares the group of the running "api"; a second "worker" job waits for the first
+        let first = queue.next_job().await.unwrap();
+        let second = queue.next_job().await.unwrap();
+        assert_eq!(first.deployment_name, "api");
+        assert_eq!(second.deployment_name, "worker");
+        assert!(queue.next_job().await.is_none());
+
+        queue.update_status(&first.id, JobStatus::Completed).await;
+        assert_eq!(queue.next_job().await.unwrap().deployment_name, "web");
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_see_status_transitions() {
+        let queue = DeployQueue::new(100);
+        let mut events = queue.subscribe();
+
+        let id = queue
+            .enqueue(DeployJob::new(
+                "local".into(),
+                "test".into(),
+                test_config(),
+                None,
+                None,
+            ))
+            .await;
+        queue.next_job().await.unwrap();
+        // Repeated status is not re-broadcast
+        queue.update_status(&id, JobStatus::Running).await;
+        queue.update_status(&id, JobStatus::Completed).await;
+
+        let mut statuses = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            assert_eq!(event.id, id);
+            statuses.push(event.status);
+        }
+        assert_eq!(
+            statuses,
+            [JobStatus::Pending, JobStatus::Running, JobStatus::Completed]
+        );
+    }
 }
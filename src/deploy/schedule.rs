@@ -0,0 +1,240 @@
+use super::{DeployJob, DeployQueue};
+use crate::config::DeployConfig;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::sync::{watch, RwLock};
+use tracing::{info, warn};
+
+/// Five-field cron expression (minute hour day-of-month month day-of-week),
+/// evaluated in UTC. Fields accept `*`, values, ranges (`1-5`), steps
+/// (`*/15`, `0-30/10`) and lists (`1,15`); `@hourly`, `@daily`/`@midnight`,
+/// `@weekly`, `@monthly` and `@yearly`/`@annually` are also recognised.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Day-of-month / day-of-week were `*` (standard cron: if both are
+    /// restricted, a day matches when either one does)
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let expr = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "expected 5 fields (minute hour day month weekday), got {}",
+                fields.len()
+            ));
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7).map_err(|e| format!("weekday: {}", e))?;
+        // 7 is Sunday as well
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59).map_err(|e| format!("minute: {}", e))?,
+            hours: parse_field(hour, 0, 23).map_err(|e| format!("hour: {}", e))?,
+            days: parse_field(day, 1, 31).map_err(|e| format!("day: {}", e))?,
+            months: parse_field(month, 1, 12).map_err(|e| format!("month: {}", e))?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    /// Whether the schedule fires in the minute containing `at` (UTC)
+    pub fn matches(&self, at: OffsetDateTime) -> bool {
+        let at = at.to_offset(time::UtcOffset::UTC);
+        let bit = |mask: u64, value: u8| mask & (1 << value) != 0;
+
+        let day = bit(self.days, at.day());
+        let weekday = bit(self.weekdays, at.weekday().number_days_from_sunday());
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        };
+
+        bit(self.minutes, at.minute())
+            && bit(self.hours, at.hour())
+            && bit(self.months, u8::from(at.month()))
+            && day_matches
+    }
+}
+
+/// Parse one cron field into a bitmask of allowed values
+fn parse_field(field: &str, min: u8, max: u8) -> Result<u64, String> {
+    let mut mask = 0u64;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u8 = step
+                    .parse()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("invalid step '{}'", step))?;
+                (range, Some(step))
+            }
+            None => (part, None),
+        };
+
+        let value = |s: &str| -> Result<u8, String> {
+            s.parse::<u8>()
+                .ok()
+                .filter(|v| (min..=max).contains(v))
+                .ok_or_else(|| format!("'{}' is not in {}-{}", s, min, max))
+        };
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // "5/15" means every 15 starting at 5
+                None if step.is_some() => (value(range)?, max),
+                None => {
+                    let v = value(range)?;
+                    (v, v)
+                }
+            },
+        };
+        if start > end {
+            return Err(format!("invalid range '{}'", range));
+        }
+
+        for v in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            mask |= 1 << v;
+        }
+    }
+
+    Ok(mask)
+}
+
+/// Enqueue deployments whose `schedule` matches the current minute.
+/// A deployment that still has a pending or running job is skipped.
+pub async fn start_scheduler(
+    queue: Arc<DeployQueue>,
+    deploy_config: Arc<RwLock<DeployConfig>>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    info!("Starting deployment scheduler");
+
+    loop {
+        // Wake up at the start of every minute
+        let now = OffsetDateTime::now_utc();
+        let wait = Duration::from_secs(60)
+            - Duration::from_secs(u64::from(now.second()))
+            - Duration::from_nanos(u64::from(now.nanosecond()));
+
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = shutdown.wait_for(|stopping| *stopping) => break,
+        }
+
+        let now = OffsetDateTime::now_utc();
+        let deployments: Vec<_> = deploy_config
+            .read()
+            .await
+            .deployments
+            .iter()
+            .filter(|d| d.schedule.is_some())
+            .cloned()
+            .collect();
+
+        for deployment in deployments {
+            let expr = deployment.schedule.as_deref().unwrap_or_default();
+            let schedule = match CronSchedule::parse(expr) {
+                Ok(schedule) => schedule,
+                Err(e) => {
+                    warn!(deployment = %deployment.name, error = %e, "Invalid schedule");
+                    continue;
+                }
+            };
+            if !schedule.matches(now) {
+                continue;
+            }
+
+            if queue.has_active_job(&deployment.name).await {
+                info!(
+                    deployment = %deployment.name,
+                    "Scheduled deployment skipped, previous job still queued or running"
+                );
+                continue;
+            }
+
+            info!(deployment = %deployment.name, "Enqueuing scheduled deployment");
+            let job = DeployJob::new(
+                "local".to_string(),
+                deployment.name.clone(),
+                deployment,
+                Some("schedule".to_string()),
+                None,
+            );
+            queue.enqueue(job).await;
+        }
+    }
+
+    info!("Deployment scheduler stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_parse_and_match() {
+        let nightly = CronSchedule::parse("30 3 * * *").unwrap();
+        assert!(nightly.matches(datetime!(2024-05-01 03:30:59 UTC)));
+        assert!(!nightly.matches(datetime!(2024-05-01 03:31 UTC)));
+
+        // Every 15 minutes during working hours on weekdays (2024-05-04 is a Saturday)
+        let busy = CronSchedule::parse("*/15 9-17 * * 1-5").unwrap();
+        assert!(busy.matches(datetime!(2024-05-03 09:45 UTC)));
+        assert!(!busy.matches(datetime!(2024-05-03 09:50 UTC)));
+        assert!(!busy.matches(datetime!(2024-05-04 09:45 UTC)));
+
+        // Restricted day-of-month and day-of-week match either
+        let either = CronSchedule::parse("0 0 1 * 7").unwrap();
+        assert!(either.matches(datetime!(2024-05-01 00:00 UTC)));
+        assert!(either.matches(datetime!(2024-05-05 00:00 UTC)));
+        assert!(!either.matches(datetime!(2024-05-06 00:00 UTC)));
+
+        assert_eq!(
+            CronSchedule::parse("@daily").unwrap(),
+            CronSchedule::parse("0 0 * * *").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid() {
+        for expr in [
+            "",
+            "* * * *",
+            "60 * * * *",
+            "* 5-2 * * *",
+            "*/0 * * * *",
+            "* * 0 * *",
+        ] {
+            assert!(CronSchedule::parse(expr).is_err(), "{}", expr);
+        }
+    }
+}
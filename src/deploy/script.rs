@@ -1,5 +1,6 @@
+use crate::config::{DeploymentConfig, ShellPolicy};
 use std::collections::HashMap;
-use std::process::Stdio;
+use std::process::{Output, Stdio};
 use tokio::process::Command;
 use tokio::time::{timeout, Duration};
 use tracing::{debug, info, warn};
@@ -38,6 +39,153 @@ fn validate_command(cmd: &str) -> Result<(), String> {
     Ok(())
 }
 
+/// Per-deployment command validation: `shell_policy` and the `shell_allow` globs
+#[derive(Debug, Clone, Default)]
+pub struct CommandPolicy {
+    pub policy: ShellPolicy,
+    pub allow: Vec<String>,
+}
+
+impl From<&DeploymentConfig> for CommandPolicy {
+    fn from(config: &DeploymentConfig) -> Self {
+        Self {
+            policy: config.shell_policy,
+            allow: config.shell_allow.clone(),
+        }
+    }
+}
+
+impl CommandPolicy {
+    fn check(&self, command: &str) -> Result<(), String> {
+        if self.policy == ShellPolicy::Permissive {
+            return Ok(());
+        }
+        let allowed = self
+            .allow
+            .iter()
+            .any(|pattern| glob::Pattern::new(pattern).is_ok_and(|p| p.matches(command)));
+        if allowed {
+            debug!(command = %command, "Command matches shell_allow, validation skipped");
+            return Ok(());
+        }
+        validate_command(command)
+    }
+}
+
+/// CPU, memory and scheduling limits for the scripts and commands of a deployment.
+/// CPU and memory caps run the process in a transient `systemd-run --scope` cgroup.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceLimits {
+    pub nice: Option<i32>,
+    /// systemd `CPUQuota`, e.g. "50%"
+    pub cpu_limit: Option<String>,
+    /// systemd `MemoryMax`, e.g. "512M"
+    pub memory_limit: Option<String>,
+}
+
+impl From<&DeploymentConfig> for ResourceLimits {
+    fn from(config: &DeploymentConfig) -> Self {
+        Self {
+            nice: config.nice,
+            cpu_limit: config.cpu_limit.clone(),
+            memory_limit: config.memory_limit.clone(),
+        }
+    }
+}
+
+impl ResourceLimits {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(nice) = self.nice {
+            if !(-20..=19).contains(&nice) {
+                return Err(format!("invalid nice {} (expected -20..19)", nice));
+            }
+        }
+        if let Some(ref cpu) = self.cpu_limit {
+            let valid = cpu
+                .strip_suffix('%')
+                .and_then(|percent| percent.parse::<u32>().ok())
+                .is_some_and(|percent| percent > 0);
+            if !valid {
+                return Err(format!("invalid cpu_limit '{}' (expected e.g. 50%)", cpu));
+            }
+        }
+        if let Some(ref memory) = self.memory_limit {
+            let digits = memory.trim_end_matches(['K', 'M', 'G', 'T']);
+            let valid = !digits.is_empty()
+                && memory.len() - digits.len() <= 1
+                && digits.chars().all(|c| c.is_ascii_digit());
+            if !valid {
+                return Err(format!(
+                    "invalid memory_limit '{}' (expected bytes or K/M/G/T suffix)",
+                    memory
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// `program args`, wrapped in `systemd-run --scope` and `nice` when limits are set
+    fn argv<'a>(&self, program: &'a str, args: &[&'a str]) -> Vec<String> {
+        let mut argv = Vec::new();
+        if self.cpu_limit.is_some() || self.memory_limit.is_some() {
+            argv.extend(["systemd-run", "--scope", "--quiet", "--collect"].map(String::from));
+            if let Some(ref cpu) = self.cpu_limit {
+                argv.push("-p".to_string());
+                argv.push(format!("CPUQuota={}", cpu));
+            }
+            if let Some(ref memory) = self.memory_limit {
+                argv.push("-p".to_string());
+                argv.push(format!("MemoryMax={}", memory));
+            }
+            argv.push("--".to_string());
+        }
+        if let Some(nice) = self.nice {
+            argv.extend(["nice".to_string(), "-n".to_string(), nice.to_string()]);
+        }
+        argv.push(program.to_string());
+        argv.extend(args.iter().map(|arg| arg.to_string()));
+        argv
+    }
+
+    fn command(&self, program: &str, args: &[&str]) -> Command {
+        let argv = self.argv(program, args);
+        let mut cmd = Command::new(&argv[0]);
+        cmd.args(&argv[1..]);
+        cmd
+    }
+}
+
+/// Run `cmd` in a process group of its own and collect its output. When the
+/// future is dropped before the command exits (timeout, cancelled job), the
+/// whole group is killed, so grandchildren such as `docker compose` or `make`
+/// stop too. Processes left in the background by a command that finished are
+/// not touched.
+async fn output_in_group(mut cmd: Command) -> std::io::Result<Output> {
+    #[cfg(unix)]
+    cmd.process_group(0);
+    let child = cmd.kill_on_drop(true).spawn()?;
+    let mut group = ProcessGroup(child.id());
+    let output = child.wait_with_output().await;
+    group.0 = None;
+    output
+}
+
+/// Kills the process group led by the given pid when dropped
+struct ProcessGroup(Option<u32>);
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pid) = self.0.and_then(|pid| i32::try_from(pid).ok()) {
+            // SAFETY: killpg has no memory-safety preconditions. A pid is not
+            // handed out again while a process group of that id has members.
+            unsafe {
+                libc::killpg(pid, libc::SIGKILL);
+            }
+        }
+    }
+}
+
 pub struct ScriptRunner {
     default_timeout: Duration,
 }
@@ -49,7 +197,6 @@ impl ScriptRunner {
         }
     }
 
-    #[allow(dead_code)]
     pub fn with_timeout(timeout_secs: u64) -> Self {
         Self {
             default_timeout: Duration::from_secs(timeout_secs),
@@ -59,26 +206,24 @@ impl ScriptRunner {
     /// Run a shell command (optionally as specified user via sudo)
     ///
     /// # Security
-    /// Commands are validated against dangerous shell metacharacters
-    /// to prevent command injection attacks.
+    /// Under the strict policy, commands not in `shell_allow` are validated
+    /// against dangerous shell metacharacters to prevent command injection.
     pub async fn run_command(
         &self,
         command: &str,
         working_dir: Option<&str>,
         env: &HashMap<String, String>,
         run_as_user: Option<&str>,
+        limits: &ResourceLimits,
+        policy: &CommandPolicy,
     ) -> Result<String, String> {
         // Validate command for injection attacks
-        validate_command(command)?;
+        policy.check(command)?;
 
         let mut cmd = if let Some(user) = run_as_user {
-            let mut c = Command::new("sudo");
-            c.args(["-u", user, "sh", "-c", command]);
-            c
+            limits.command("sudo", &["-u", user, "sh", "-c", command])
         } else {
-            let mut c = Command::new("sh");
-            c.arg("-c").arg(command);
-            c
+            limits.command("sh", &["-c", command])
         };
 
         if let Some(dir) = working_dir {
@@ -94,7 +239,7 @@ impl ScriptRunner {
 
         debug!(command = %command, "Running shell command");
 
-        let output = timeout(self.default_timeout, cmd.output())
+        let output = timeout(self.default_timeout, output_in_group(cmd))
             .await
             .map_err(|_| format!("Command timed out after {:?}", self.default_timeout))?
             .map_err(|e| format!("Failed to execute command: {}", e))?;
@@ -121,17 +266,14 @@ impl ScriptRunner {
         working_dir: Option<&str>,
         env: &HashMap<String, String>,
         run_as_user: Option<&str>,
+        limits: &ResourceLimits,
     ) -> Result<String, String> {
-        info!(script = %script_path, user = ?run_as_user, "Running script");
+        info!(script = %script_path, user = ?run_as_user, limits = ?limits, "Running script");
 
         let mut cmd = if let Some(user) = run_as_user {
-            let mut c = Command::new("sudo");
-            c.args(["-u", user, "bash", script_path]);
-            c
+            limits.command("sudo", &["-u", user, "bash", script_path])
         } else {
-            let mut c = Command::new("bash");
-            c.arg(script_path);
-            c
+            limits.command("bash", &[script_path])
         };
 
         if let Some(dir) = working_dir {
@@ -144,7 +286,7 @@ impl ScriptRunner {
 
         cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
 
-        let output = timeout(self.default_timeout, cmd.output())
+        let output = timeout(self.default_timeout, output_in_group(cmd))
             .await
             .map_err(|_| format!("Script timed out after {:?}", self.default_timeout))?
             .map_err(|e| format!("Failed to execute script: {}", e))?;
@@ -172,11 +314,15 @@ impl ScriptRunner {
         working_dir: Option<&str>,
         env: &HashMap<String, String>,
         run_as_user: Option<&str>,
+        limits: &ResourceLimits,
+        policy: &CommandPolicy,
     ) -> Result<String, String> {
         let mut output = String::new();
 
         for cmd in commands {
-            let result = self.run_command(cmd, working_dir, env, run_as_user).await?;
+            let result = self
+                .run_command(cmd, working_dir, env, run_as_user, limits, policy)
+                .await?;
             output.push_str(&format!("$ {}\n{}\n", cmd, result));
         }
 
@@ -189,3 +335,104 @@ impl Default for ScriptRunner {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_policy() {
+        let strict = CommandPolicy {
+            policy: ShellPolicy::Strict,
+            allow: vec!["docker compose logs * | tail *".to_string()],
+        };
+        assert!(strict.check("docker compose up -d").is_ok());
+        assert!(strict.check("docker compose logs api | tail -n 50").is_ok());
+        assert!(strict.check("make && make install").is_err());
+        assert!(strict.check("echo $(id)").is_err());
+
+        let permissive = CommandPolicy {
+            policy: ShellPolicy::Permissive,
+            allow: vec![],
+        };
+        assert!(permissive.check("make && make install").is_ok());
+    }
+
+    #[test]
+    fn test_resource_limits_wrap_command() {
+        let none = ResourceLimits::default();
+        assert_eq!(none.argv("sh", &["-c", "make"]), ["sh", "-c", "make"]);
+
+        let limits = ResourceLimits {
+            nice: Some(10),
+            cpu_limit: Some("50%".to_string()),
+            memory_limit: Some("512M".to_string()),
+        };
+        assert_eq!(
+            limits.argv("bash", &["build.sh"]),
+            [
+                "systemd-run",
+                "--scope",
+                "--quiet",
+                "--collect",
+                "-p",
+                "CPUQuota=50%",
+                "-p",
+                "MemoryMax=512M",
+                "--",
+                "nice",
+                "-n",
+                "10",
+                "bash",
+                "build.sh"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resource_limits_validate() {
+        let limits = |nice, cpu: &str, memory: &str| ResourceLimits {
+            nice,
+            cpu_limit: Some(cpu.to_string()),
+            memory_limit: Some(memory.to_string()),
+        };
+        assert!(limits(Some(5), "150%", "2G").validate().is_ok());
+        assert!(limits(None, "50%", "1048576").validate().is_ok());
+        assert!(limits(Some(20), "50%", "2G").validate().is_err());
+        assert!(limits(None, "50", "2G").validate().is_err());
+        assert!(limits(None, "0%", "2G").validate().is_err());
+        assert!(limits(None, "50%", "2GB").validate().is_err());
+        assert!(limits(None, "50%", "M").validate().is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_timeout_kills_the_process_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("survived");
+        let runner = ScriptRunner {
+            default_timeout: Duration::from_millis(200),
+        };
+        let permissive = CommandPolicy {
+            policy: ShellPolicy::Permissive,
+            allow: vec![],
+        };
+
+        // The grandchild would create the marker after the timeout
+        let command = format!("sh -c 'sleep 1; touch {}'", marker.display());
+        let result = runner
+            .run_command(
+                &command,
+                None,
+                &HashMap::new(),
+                None,
+                &ResourceLimits::default(),
+                &permissive,
+            )
+            .await;
+        assert!(result.unwrap_err().contains("timed out"));
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!marker.exists());
+    }
+}
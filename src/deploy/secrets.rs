@@ -0,0 +1,149 @@
+use super::DeployResult;
+use crate::config::DeploymentConfig;
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+/// Secret store used when INFRACTL_SECRETS_DIR is not set
+const DEFAULT_SECRETS_DIR: &str = "/etc/infractl/secrets.d";
+
+/// Values shorter than this are not redacted (they would mangle unrelated output)
+const MIN_REDACT_LEN: usize = 4;
+
+const REDACTED: &str = "***";
+
+/// File-based secret store: one file per secret, named after the secret
+pub struct FileSecretStore {
+    dir: PathBuf,
+}
+
+impl FileSecretStore {
+    pub fn new() -> Self {
+        let dir = std::env::var("INFRACTL_SECRETS_DIR")
+            .unwrap_or_else(|_| DEFAULT_SECRETS_DIR.to_string());
+        Self::with_dir(dir)
+    }
+
+    pub fn with_dir(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Read a secret (trailing newline trimmed)
+    pub fn get(&self, name: &str) -> Result<String, String> {
+        validate_secret_name(name)?;
+        let path = self.dir.join(name);
+        std::fs::read_to_string(&path)
+            .map(|content| content.trim_end_matches(['\r', '\n']).to_string())
+            .map_err(|e| {
+                format!(
+                    "Failed to read secret '{}' from {}: {}",
+                    name,
+                    path.display(),
+                    e
+                )
+            })
+    }
+
+    /// Deployment config with its `secrets` added to `env`, plus the secret
+    /// values to redact from the result
+    pub fn inject<'a>(
+        &self,
+        config: &'a DeploymentConfig,
+    ) -> Result<(Cow<'a, DeploymentConfig>, Vec<String>), String> {
+        if config.secrets.is_empty() {
+            return Ok((Cow::Borrowed(config), Vec::new()));
+        }
+
+        let mut config = config.clone();
+        let mut values = Vec::new();
+        for (env_name, secret_name) in &config.secrets {
+            let value = self.get(secret_name)?;
+            config.env.insert(env_name.clone(), value.clone());
+            values.push(value);
+        }
+        Ok((Cow::Owned(config), values))
+    }
+}
+
+impl Default for FileSecretStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Secret names are plain file names inside the store
+pub fn validate_secret_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("invalid secret name '{}'", name))
+    }
+}
+
+/// Replace secret values (and each line of multi-line ones) in the output and error
+pub fn redact(mut result: DeployResult, secrets: &[String]) -> DeployResult {
+    if secrets.is_empty() {
+        return result;
+    }
+
+    result.output = redact_text(&result.output, secrets).into_owned();
+    if let Some(ref mut error) = result.error {
+        *error = redact_text(error, secrets).into_owned();
+    }
+    result
+}
+
+/// Replace secret values (and each line of multi-line ones) in `text`
+pub fn redact_text<'a>(text: &'a str, secrets: &[String]) -> Cow<'a, str> {
+    let mut needles: Vec<&str> = secrets
+        .iter()
+        .flat_map(|s| std::iter::once(s.as_str()).chain(s.lines().map(str::trim)))
+        .filter(|s| s.len() >= MIN_REDACT_LEN && text.contains(*s))
+        .collect();
+    if needles.is_empty() {
+        return Cow::Borrowed(text);
+    }
+    // Longest first, so a value is not partially replaced by one of its lines
+    needles.sort_by_key(|s| std::cmp::Reverse(s.len()));
+    needles.dedup();
+
+    let mut text = text.to_string();
+    for needle in needles {
+        text = text.replace(needle, REDACTED);
+    }
+    Cow::Owned(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        let result = DeployResult {
+            success: false,
+            skipped: false,
+            output: "token=hunter2-token\n-----BEGIN KEY-----\nabcdefgh\n".to_string(),
+            error: Some("auth failed for hunter2-token (id 42)".to_string()),
+            duration_ms: 0,
+            image_digests: Vec::new(),
+        };
+        let secrets = vec![
+            "hunter2-token".to_string(),
+            "-----BEGIN KEY-----\nabcdefgh".to_string(),
+            "42".to_string(),
+        ];
+
+        let result = redact(result, &secrets);
+        assert_eq!(result.output, "token=***\n***\n");
+        assert_eq!(result.error.as_deref(), Some("auth failed for *** (id 42)"));
+
+        assert!(validate_secret_name("ghcr-token").is_ok());
+        assert!(validate_secret_name("../passwd").is_err());
+        assert!(validate_secret_name(".hidden").is_err());
+    }
+}
@@ -1,12 +1,15 @@
 use crate::config::TelegramConfig;
+use crate::http_client::HttpClient;
 use std::collections::HashMap;
 use tracing::{error, info};
 
-pub struct TelegramDeploy;
+pub struct TelegramDeploy {
+    http: HttpClient,
+}
 
 impl TelegramDeploy {
-    pub fn new() -> Self {
-        Self
+    pub fn new(http: HttpClient) -> Self {
+        Self { http }
     }
 
     pub async fn send(
@@ -39,13 +42,17 @@ impl TelegramDeploy {
             "disable_notification": silent,
         });
 
-        let resp = reqwest::Client::new()
+        let request = self
+            .http
+            .client()
             .post(&url)
             .json(&body)
-            .timeout(std::time::Duration::from_secs(10))
-            .send()
+            .timeout(std::time::Duration::from_secs(10));
+        let resp = self
+            .http
+            .send(request)
             .await
-            .map_err(|e| format!("Telegram API error: {}", e))?;
+            .map_err(|e| format!("Telegram API error: {}", e.without_url()))?;
 
         if !resp.status().is_success() {
             let status = resp.status();
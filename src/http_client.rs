@@ -0,0 +1,128 @@
+use crate::config::HttpClientConfig;
+use reqwest::{Client, RequestBuilder, Response};
+use std::time::Duration;
+use tracing::debug;
+
+/// Shared HTTP client: one connection pool for all outgoing requests
+/// (Home ↔ Agent, GitHub, Telegram, config sync) with consistent timeouts
+/// and retries
+#[derive(Clone)]
+pub struct HttpClient {
+    client: Client,
+    retries: u32,
+    retry_backoff: Duration,
+}
+
+impl HttpClient {
+    pub fn new(config: &HttpClientConfig) -> Self {
+        let duration = |value: &str, default: u64| {
+            humantime::parse_duration(value).unwrap_or(Duration::from_secs(default))
+        };
+
+        let client = Client::builder()
+            .user_agent(format!("infractl/{}", env!("CARGO_PKG_VERSION")))
+            .connect_timeout(duration(&config.connect_timeout, 5))
+            .timeout(duration(&config.timeout, 30))
+            .pool_idle_timeout(duration(&config.pool_idle_timeout, 90))
+            .tcp_keepalive(Duration::from_secs(60))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            client,
+            retries: config.retries,
+            retry_backoff: humantime::parse_duration(&config.retry_backoff)
+                .unwrap_or(Duration::from_millis(500)),
+        }
+    }
+
+    /// Underlying client for building requests
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Send a request, retrying transient failures with exponential backoff and jitter.
+    /// Connection failures are always retried (nothing reached the server); timeouts,
+    /// 429 and 5xx responses only for idempotent methods, so a deploy trigger is never
+    /// sent twice.
+    pub async fn send(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        let idempotent = request
+            .try_clone()
+            .and_then(|r| r.build().ok())
+            .is_some_and(|r| r.method().is_idempotent());
+
+        let mut attempt = 0;
+        loop {
+            // Streaming bodies cannot be replayed
+            let Some(current) = request.try_clone().filter(|_| attempt < self.retries) else {
+                return request.send().await;
+            };
+
+            let retry = match current.send().await {
+                Ok(resp) => {
+                    let status = resp.status();
+                    if !(idempotent && (status.is_server_error() || status.as_u16() == 429)) {
+                        return Ok(resp);
+                    }
+                    format!("status {}", status)
+                }
+                Err(e) if e.is_connect() || (idempotent && e.is_timeout()) => e.to_string(),
+                Err(e) => return Err(e),
+            };
+
+            let delay = backoff(self.retry_backoff, attempt);
+            debug!(attempt = attempt + 1, delay = ?delay, reason = %retry, "Retrying HTTP request");
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new(&HttpClientConfig::default())
+    }
+}
+
+/// Exponential backoff (base * 2^attempt) plus up to 50% random jitter
+fn backoff(base: Duration, attempt: u32) -> Duration {
+    let delay = base.saturating_mul(1 << attempt.min(6));
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let jitter = delay.mul_f64(f64::from(nanos % 1000) / 2000.0);
+    delay + jitter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_with_jitter() {
+        let base = Duration::from_millis(100);
+        for attempt in 0..4 {
+            let delay = backoff(base, attempt);
+            let min = base * (1 << attempt);
+            assert!(delay >= min && delay <= min.mul_f64(1.5), "{:?}", delay);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connect_errors_are_retried() {
+        let http = HttpClient::new(&HttpClientConfig {
+            retries: 2,
+            retry_backoff: "1ms".to_string(),
+            connect_timeout: "1s".to_string(),
+            ..HttpClientConfig::default()
+        });
+
+        // Nothing listens on port 1
+        let err = http
+            .send(http.client().post("http://127.0.0.1:1/webhook/deploy/app"))
+            .await
+            .unwrap_err();
+        assert!(err.is_connect());
+    }
+}
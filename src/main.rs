@@ -2,6 +2,7 @@ mod cli;
 mod config;
 mod deploy;
 mod error;
+mod http_client;
 mod logging;
 mod metrics;
 mod server;
@@ -9,6 +10,9 @@ mod storage;
 mod updater;
 
 use anyhow::Result;
+use server::auth::Audience;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
 use tracing::info;
 
 const DEFAULT_REPO: &str = "razumnyak/infractl";
@@ -16,12 +20,16 @@ const DEFAULT_REPO: &str = "razumnyak/infractl";
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = cli::parse();
+    config::set_strict_mode(cli.strict_config);
+    config::set_profile(cli.profile.clone());
 
     // Handle self-update before full initialization
     if let Some(cli::Commands::SelfUpdate {
         force,
         repo,
         prerelease,
+        all_agents: false,
+        ..
     }) = &cli.command
     {
         // Initialize minimal logging for self-update
@@ -62,12 +70,18 @@ async fn main() -> Result<()> {
         }
 
         // Validate config command
-        Some(cli::Commands::Validate { config: cfg_path }) => {
+        Some(cli::Commands::Validate {
+            config: cfg_path,
+            lenient,
+        }) => {
             let path = cfg_path.as_ref().unwrap_or(&cli.config);
-            match config::load(path) {
+            match config::load_with_mode(path, !*lenient) {
                 Ok(cfg) => {
                     println!("Configuration is valid");
                     println!("  Mode: {:?}", cfg.mode);
+                    if let Some(ref profile) = cfg.active_profile {
+                        println!("  Profile: {}", profile);
+                    }
                     println!("  Port: {}", cfg.server.port);
                     println!("  Deployments: {}", cfg.modules.deploy.deployments.len());
                 }
@@ -80,12 +94,41 @@ async fn main() -> Result<()> {
         }
 
         // Token generation command
-        Some(cli::Commands::Token { subject, ttl }) => {
+        Some(cli::Commands::Token {
+            subject,
+            ttl,
+            audience,
+            scope,
+            refresh,
+        }) => {
             let config = config::load(&cli.config)?;
-            let ttl_hours = server::auth::parse_ttl_to_hours(ttl);
+
+            if *refresh {
+                let ttl = ttl.as_deref().unwrap_or(&config.auth.refresh_token_ttl);
+                let ttl = match humantime::parse_duration(ttl) {
+                    Ok(ttl) => time::Duration::seconds(ttl.as_secs() as i64),
+                    Err(e) => {
+                        eprintln!("Error: Invalid TTL format '{}': {}", ttl, e);
+                        std::process::exit(1);
+                    }
+                };
+                let audiences: Vec<String> =
+                    audience.iter().map(|a| a.as_str().to_string()).collect();
+                let store = server::auth::RefreshStore::new(&config.auth.refresh_tokens_file);
+                match store.issue(subject, &audiences, scope, ttl) {
+                    Ok(token) => println!("{}", token),
+                    Err(e) => {
+                        eprintln!("Failed to issue refresh token: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                return Ok(());
+            }
+
+            let ttl_hours = server::auth::parse_ttl_to_hours(ttl.as_deref().unwrap_or("24h"));
             let jwt_manager = server::auth::JwtManager::new(&config.auth.jwt_secret);
 
-            match jwt_manager.generate_token(subject, ttl_hours) {
+            match jwt_manager.generate_scoped_token(subject, ttl_hours, audience, scope) {
                 Ok(token) => {
                     println!("{}", token);
                 }
@@ -97,28 +140,65 @@ async fn main() -> Result<()> {
             return Ok(());
         }
 
+        // Encrypt a config value
+        Some(cli::Commands::EncryptValue { recipient, value }) => {
+            let plaintext = match value {
+                Some(v) => v.clone(),
+                None => {
+                    let mut input = String::new();
+                    std::io::Read::read_to_string(&mut std::io::stdin(), &mut input)?;
+                    input.trim_end_matches(['\r', '\n']).to_string()
+                }
+            };
+
+            match config::encrypt_value(recipient, &plaintext) {
+                Ok(encrypted) => println!("{}", encrypted),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+
         // Health check command
-        Some(cli::Commands::Health { address, token }) => {
-            let url = if address.starts_with("http") {
-                format!("{}/health", address)
+        Some(cli::Commands::Health {
+            address,
+            token,
+            grpc,
+        }) => {
+            let base = if address.starts_with("http") {
+                address.clone()
             } else {
-                format!("http://{}/health", address)
+                format!("http://{}", address)
             };
 
-            let client = reqwest::Client::new();
-            let mut req = client.get(&url);
+            let loaded = config::load(&cli.config).ok();
 
             // Use provided token, or generate from config if not provided
+            let token = token.clone().or_else(|| {
+                loaded.as_ref().and_then(|config| {
+                    server::auth::JwtManager::new(&config.auth.jwt_secret)
+                        .generate_token("cli", 1, &[Audience::Api])
+                        .ok()
+                })
+            });
+
+            if *grpc {
+                grpc_health(base, token).await;
+                return Ok(());
+            }
+
+            let http = loaded
+                .as_ref()
+                .map(|c| http_client::HttpClient::new(&c.http_client))
+                .unwrap_or_default();
+            let mut req = http.client().get(format!("{}/health", base));
             if let Some(t) = token {
                 req = req.header("Authorization", format!("Bearer {}", t));
-            } else if let Ok(config) = config::load(&cli.config) {
-                let jwt_manager = server::auth::JwtManager::new(&config.auth.jwt_secret);
-                if let Ok(t) = jwt_manager.generate_token("cli", 1) {
-                    req = req.header("Authorization", format!("Bearer {}", t));
-                }
             }
 
-            match req.send().await {
+            match http.send(req).await {
                 Ok(resp) => {
                     let status = resp.status();
                     let body = resp.text().await.unwrap_or_default();
@@ -146,6 +226,8 @@ async fn main() -> Result<()> {
             permanent,
             reset,
             force,
+            all_agents,
+            tag,
         }) => {
             let cfg = config::load(&cli.config)?;
             let config_dir = cli
@@ -192,7 +274,7 @@ async fn main() -> Result<()> {
                 // Generate token
                 let jwt_manager = server::auth::JwtManager::new(&cfg.auth.jwt_secret);
                 let token = jwt_manager
-                    .generate_token("cli", 1)
+                    .generate_token("cli", 1, &[Audience::Webhook])
                     .map_err(|e| anyhow::anyhow!("Failed to generate token: {}", e))?;
 
                 // Determine target (--agent or saved assignment)
@@ -201,7 +283,7 @@ async fn main() -> Result<()> {
                     .or_else(|| config::load_assignments(config_dir).get(&name).cloned());
 
                 // Call shutdown endpoint
-                let client = reqwest::Client::new();
+                let http = http_client::HttpClient::new(&cfg.http_client);
                 let (url, target_desc) = match &target_agent {
                     Some(addr) => (
                         format!("http://{}/webhook/shutdown/{}", addr, name),
@@ -218,17 +300,16 @@ async fn main() -> Result<()> {
 
                 println!("Stopping deployment '{}' on {}...", name, target_desc);
 
-                match client
+                let req = http
+                    .client()
                     .post(&url)
-                    .header("Authorization", format!("Bearer {}", token))
-                    .send()
-                    .await
-                {
+                    .header("Authorization", format!("Bearer {}", token));
+                match http.send(req).await {
                     Ok(resp) => {
                         let status = resp.status();
                         let body = resp.text().await.unwrap_or_default();
                         if status.is_success() {
-                            println!("Shutdown completed");
+                            println!("Shutdown queued");
                             println!("{}", body);
                         } else {
                             eprintln!("Shutdown failed ({}): {}", status, body);
@@ -272,9 +353,74 @@ async fn main() -> Result<()> {
             // Generate token from config
             let jwt_manager = server::auth::JwtManager::new(&cfg.auth.jwt_secret);
             let token = jwt_manager
-                .generate_token("cli", 1)
+                .generate_token("cli", 1, &[Audience::Webhook])
                 .map_err(|e| anyhow::anyhow!("Failed to generate token: {}", e))?;
 
+            // Fleet deploy: the running Home service forwards to every agent
+            if *all_agents {
+                let token = jwt_manager
+                    .generate_token("cli", 1, &[Audience::Api])
+                    .map_err(|e| anyhow::anyhow!("Failed to generate token: {}", e))?;
+                let http = http_client::HttpClient::new(&cfg.http_client);
+                let url = format!(
+                    "http://127.0.0.1:{}/api/fleet/deploy/{}",
+                    cfg.server.port, name
+                );
+
+                println!("Triggering fleet deployment: {}", name);
+                let mut req = http
+                    .client()
+                    .post(&url)
+                    .header("Authorization", format!("Bearer {}", token));
+                if !tag.is_empty() {
+                    req = req.query(&[("tag", tag.join(","))]);
+                }
+                let resp = match http.send(req).await {
+                    Ok(resp) => resp,
+                    Err(e) => {
+                        eprintln!("Failed to connect to infractl service: {}", e);
+                        eprintln!("Is the service running? Check: systemctl status infractl");
+                        std::process::exit(1);
+                    }
+                };
+
+                let status = resp.status();
+                let body: serde_json::Value = resp.json().await.unwrap_or_default();
+                if !status.is_success() {
+                    eprintln!(
+                        "Fleet deployment failed ({}): {}",
+                        status,
+                        body["error"].as_str().unwrap_or_default()
+                    );
+                    std::process::exit(1);
+                }
+
+                for result in body["results"].as_array().into_iter().flatten() {
+                    let agent = result["agent"].as_str().unwrap_or_default();
+                    if result["success"].as_bool() == Some(true) {
+                        println!(
+                            "  {}: triggered (job {})",
+                            agent,
+                            result["job_id"].as_str().unwrap_or("-")
+                        );
+                    } else {
+                        println!(
+                            "  {}: failed: {}",
+                            agent,
+                            result["message"].as_str().unwrap_or_default()
+                        );
+                    }
+                }
+                println!(
+                    "\nTriggered: {}, failed: {}",
+                    body["triggered"], body["failed"]
+                );
+                if body["failed"].as_u64().unwrap_or(0) > 0 {
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+
             // Determine target agent (priority: --agent > --target > saved assignment)
             let target_agent = agent
                 .clone()
@@ -293,19 +439,18 @@ async fn main() -> Result<()> {
             }
 
             // Execute deployment
+            let http = http_client::HttpClient::new(&cfg.http_client);
             match target_agent {
                 Some(addr) => {
                     // Forward to agent
                     println!("Forwarding deployment '{}' to agent: {}", name, addr);
                     let url = format!("http://{}/webhook/deploy/{}", addr, name);
 
-                    let client = reqwest::Client::new();
-                    match client
+                    let req = http
+                        .client()
                         .post(&url)
-                        .header("Authorization", format!("Bearer {}", token))
-                        .send()
-                        .await
-                    {
+                        .header("Authorization", format!("Bearer {}", token));
+                    match http.send(req).await {
                         Ok(resp) => {
                             let status = resp.status();
                             let body = resp.text().await.unwrap_or_default();
@@ -329,14 +474,14 @@ async fn main() -> Result<()> {
                     let port = cfg.server.port;
                     let url = format!("http://127.0.0.1:{}/webhook/deploy/{}", port, name);
 
-                    let client = reqwest::Client::new();
-                    let mut req = client
+                    let mut req = http
+                        .client()
                         .post(&url)
                         .header("Authorization", format!("Bearer {}", token));
                     if *force {
                         req = req.header("X-Deploy-Force", "true");
                     }
-                    match req.send().await {
+                    match http.send(req).await {
                         Ok(resp) => {
                             let status = resp.status();
                             let body = resp.text().await.unwrap_or_default();
@@ -359,11 +504,489 @@ async fn main() -> Result<()> {
             return Ok(());
         }
 
+        // Approve a job waiting for approval
+        Some(cli::Commands::Approve {
+            job_id,
+            subject,
+            agent,
+        }) => {
+            let cfg = config::load(&cli.config)?;
+            let jwt_manager = server::auth::JwtManager::new(&cfg.auth.jwt_secret);
+            let token = jwt_manager
+                .generate_token(subject, 1, &[Audience::Webhook])
+                .map_err(|e| anyhow::anyhow!("Failed to generate token: {}", e))?;
+
+            let addr = agent
+                .clone()
+                .unwrap_or_else(|| format!("127.0.0.1:{}", cfg.server.port));
+            let url = format!("http://{}/webhook/approve/{}", addr, job_id);
+
+            let http = http_client::HttpClient::new(&cfg.http_client);
+            let req = http
+                .client()
+                .post(&url)
+                .header("Authorization", format!("Bearer {}", token));
+            match http.send(req).await {
+                Ok(resp) => {
+                    let status = resp.status();
+                    let body = resp.text().await.unwrap_or_default();
+                    if status.is_success() {
+                        println!("Job {} approved", job_id);
+                    } else {
+                        eprintln!("Approval failed ({}): {}", status, body);
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to connect to {}: {}", addr, e);
+                    std::process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+
+        // Run a command on agents through Home
+        Some(cli::Commands::Exec {
+            command,
+            agent,
+            all,
+            tag,
+        }) => {
+            let cfg = config::load(&cli.config)?;
+            let jwt_manager = server::auth::JwtManager::new(&cfg.auth.jwt_secret);
+            let token = jwt_manager
+                .generate_token("cli", 1, &[Audience::Api])
+                .map_err(|e| anyhow::anyhow!("Failed to generate token: {}", e))?;
+            let wait = humantime::parse_duration(&cfg.modules.exec.timeout)
+                .unwrap_or(std::time::Duration::from_secs(300));
+
+            let http = http_client::HttpClient::new(&cfg.http_client);
+            let url = format!("http://127.0.0.1:{}/api/fleet/exec", cfg.server.port);
+            // Not retried: the command may already be running on some agents
+            let resp = http
+                .client()
+                .post(&url)
+                .timeout(wait + std::time::Duration::from_secs(30))
+                .header("Authorization", format!("Bearer {}", token))
+                .json(&serde_json::json!({
+                    "command": command,
+                    "agents": agent,
+                    "all": all,
+                    "tag": (!tag.is_empty()).then(|| tag.join(",")),
+                }))
+                .send()
+                .await;
+            let mut resp = match resp {
+                Ok(resp) => resp,
+                Err(e) => {
+                    eprintln!("Failed to connect to infractl service: {}", e);
+                    eprintln!("Is the service running? Check: systemctl status infractl");
+                    std::process::exit(1);
+                }
+            };
+            if !resp.status().is_success() {
+                let status = resp.status();
+                let body: serde_json::Value = resp.json().await.unwrap_or_default();
+                eprintln!(
+                    "Exec failed ({}): {}",
+                    status,
+                    body["error"].as_str().unwrap_or_default()
+                );
+                std::process::exit(1);
+            }
+
+            // One JSON line per agent as it finishes, then the summary
+            let mut buffer = Vec::new();
+            let mut failed = 0;
+            while let Some(chunk) = resp.chunk().await? {
+                buffer.extend_from_slice(&chunk);
+                while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = buffer.drain(..=end).collect();
+                    let Ok(result) = serde_json::from_slice::<serde_json::Value>(&line) else {
+                        continue;
+                    };
+                    match result["agent"].as_str() {
+                        Some(agent) => {
+                            let outcome = if result["success"].as_bool() == Some(true) {
+                                "ok"
+                            } else {
+                                "failed"
+                            };
+                            println!("== {} ({}, {}ms)", agent, outcome, result["duration_ms"]);
+                            println!(
+                                "{}",
+                                result["output"].as_str().unwrap_or_default().trim_end()
+                            );
+                        }
+                        None => {
+                            failed = result["failed"].as_u64().unwrap_or(0);
+                            println!(
+                                "\nSucceeded: {}, failed: {}",
+                                result["succeeded"], result["failed"]
+                            );
+                        }
+                    }
+                }
+            }
+            if failed > 0 {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+
+        // Export metrics or deploy history
+        Some(cli::Commands::Export {
+            data,
+            format,
+            from,
+            to,
+            agent,
+            deployment,
+            status,
+            output,
+        }) => {
+            let cfg = config::load(&cli.config)?;
+            let jwt_manager = server::auth::JwtManager::new(&cfg.auth.jwt_secret);
+            let token = jwt_manager
+                .generate_token("cli", 1, &[Audience::Api])
+                .map_err(|e| anyhow::anyhow!("Failed to generate token: {}", e))?;
+
+            let format = match format {
+                cli::ExportFormat::Csv => "csv",
+                cli::ExportFormat::Json => "json",
+            };
+            let query: Vec<(&str, &str)> = [
+                ("format", Some(format)),
+                ("from", from.as_deref()),
+                ("to", to.as_deref()),
+                ("agent", agent.as_deref()),
+                ("deployment", deployment.as_deref()),
+                ("status", status.as_deref()),
+            ]
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value?)))
+            .collect();
+
+            let http = http_client::HttpClient::new(&cfg.http_client);
+            let url = format!(
+                "http://127.0.0.1:{}/api/{}/export",
+                cfg.server.port,
+                data.path()
+            );
+            // Exports of long histories outlast the default request timeout
+            let resp = http
+                .client()
+                .get(&url)
+                .query(&query)
+                .timeout(std::time::Duration::from_secs(24 * 3600))
+                .header("Authorization", format!("Bearer {}", token))
+                .send()
+                .await;
+            let mut resp = match resp {
+                Ok(resp) => resp,
+                Err(e) => {
+                    eprintln!("Failed to connect to infractl service: {}", e);
+                    eprintln!("Is the service running? Check: systemctl status infractl");
+                    std::process::exit(1);
+                }
+            };
+            if !resp.status().is_success() {
+                let status = resp.status();
+                let body: serde_json::Value = resp.json().await.unwrap_or_default();
+                eprintln!(
+                    "Export failed ({}): {}",
+                    status,
+                    body["error"].as_str().unwrap_or_default()
+                );
+                std::process::exit(1);
+            }
+
+            let mut out: Box<dyn Write> = match output {
+                Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+                None => Box::new(std::io::stdout().lock()),
+            };
+            while let Some(chunk) = resp.chunk().await? {
+                out.write_all(&chunk)?;
+            }
+            out.flush()?;
+            return Ok(());
+        }
+
+        // Back up the database
+        Some(cli::Commands::Db {
+            command: cli::DbCommands::Backup { dir },
+        }) => {
+            let cfg = config::load(&cli.config)?;
+            let storage = &cfg.modules.storage;
+            let dir = dir.clone().unwrap_or_else(|| storage.backup_dir());
+            let key = storage.encryption_key.as_deref();
+            let db = storage::Database::open_with_key(&storage.db_path, 0, key)?;
+            match storage::backup::backup(&db, &dir, storage.backup.keep) {
+                Ok(result) => {
+                    println!("{} ({} bytes)", result.path.display(), result.size_bytes);
+                    for removed in result.removed {
+                        println!("Removed old backup {}", removed.display());
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+
+        // Restore the database from a backup
+        Some(cli::Commands::Db {
+            command: cli::DbCommands::Restore { file, yes },
+        }) => {
+            let cfg = config::load(&cli.config)?;
+            let db_path = std::path::Path::new(&cfg.modules.storage.db_path);
+            if !*yes {
+                eprint!(
+                    "Replace {} with {}? The service must be stopped. [y/N] ",
+                    db_path.display(),
+                    file.display()
+                );
+                let mut answer = String::new();
+                std::io::stdin().read_line(&mut answer)?;
+                if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                    eprintln!("Aborted");
+                    std::process::exit(1);
+                }
+            }
+            let key = cfg.modules.storage.encryption_key.as_deref();
+            if let Err(e) = storage::backup::restore(file, db_path, key) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+            println!("Restored {} from {}", db_path.display(), file.display());
+            return Ok(());
+        }
+
+        // Show the schema migrations of the database
+        Some(cli::Commands::Db {
+            command: cli::DbCommands::Status,
+        }) => {
+            let cfg = config::load(&cli.config)?;
+            let storage = &cfg.modules.storage;
+            let key = storage.encryption_key.as_deref();
+            let db = storage::Database::open_with_key(&storage.db_path, 0, key)?;
+            let versions = storage::migrations::status(&db)?;
+            println!("{:<8} {:<20} DESCRIPTION", "VERSION", "APPLIED");
+            for v in &versions {
+                println!(
+                    "{:<8} {:<20} {}",
+                    v.version,
+                    v.applied_at.as_deref().unwrap_or("pending"),
+                    v.description
+                );
+            }
+            let pending = versions.iter().filter(|v| v.applied_at.is_none()).count();
+            println!("\n{} pending migration(s)", pending);
+            return Ok(());
+        }
+
+        // Apply or revert schema migrations
+        Some(cli::Commands::Db {
+            command: cli::DbCommands::Migrate { to, dry_run, yes },
+        }) => {
+            let cfg = config::load(&cli.config)?;
+            let storage = &cfg.modules.storage;
+            let key = storage.encryption_key.as_deref();
+            let db = storage::Database::open_with_key(&storage.db_path, 0, key)?;
+            let target = to.unwrap_or_else(storage::migrations::latest_version);
+            let steps = match storage::migrations::plan(&db, target) {
+                Ok(steps) => steps,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if steps.is_empty() {
+                println!("Schema is at version {}, nothing to do", target);
+                return Ok(());
+            }
+            if *dry_run {
+                for step in &steps {
+                    println!(
+                        "-- {:?} {}: {}",
+                        step.direction, step.migration.version, step.migration.description
+                    );
+                    // Strip the indentation the SQL has in the source
+                    let sql = step.sql().trim_matches('\n');
+                    let indent = sql
+                        .lines()
+                        .filter(|l| !l.trim().is_empty())
+                        .map(|l| l.len() - l.trim_start().len())
+                        .min()
+                        .unwrap_or(0);
+                    for line in sql.lines() {
+                        println!("{}", line.get(indent..).unwrap_or_default());
+                    }
+                    println!();
+                }
+                return Ok(());
+            }
+            let reverting = steps
+                .iter()
+                .any(|s| s.direction == storage::migrations::Direction::Down);
+            if reverting && !*yes {
+                eprint!(
+                    "Revert {} to version {}? Dropped tables and columns lose their data. [y/N] ",
+                    cfg.modules.storage.db_path, target
+                );
+                let mut answer = String::new();
+                std::io::stdin().read_line(&mut answer)?;
+                if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                    eprintln!("Aborted");
+                    std::process::exit(1);
+                }
+            }
+            match storage::migrations::migrate(&db, target) {
+                Ok(steps) => {
+                    for step in steps {
+                        println!(
+                            "{:?} {}: {}",
+                            step.direction, step.migration.version, step.migration.description
+                        );
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+
+        // Issue a join token for a new agent
+        Some(cli::Commands::Agent {
+            command: cli::AgentCommands::JoinToken { name, tag, ttl },
+        }) => {
+            let cfg = config::load(&cli.config)?;
+            if !cfg.auth.agent_keys {
+                eprintln!("Enrollment requires per-agent keys: set auth.agent_keys: true");
+                std::process::exit(1);
+            }
+            let ttl = match humantime::parse_duration(ttl) {
+                Ok(ttl) => time::Duration::seconds(ttl.as_secs() as i64),
+                Err(e) => {
+                    eprintln!("Error: Invalid TTL format '{}': {}", ttl, e);
+                    std::process::exit(1);
+                }
+            };
+            let store = server::auth::JoinStore::new(&cfg.auth.join_tokens_file);
+            match store.issue(name.as_deref(), tag, ttl) {
+                Ok(token) => println!("{}", token),
+                Err(e) => {
+                    eprintln!("Failed to issue join token: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+
+        // Print the current key of an agent (per-agent keys)
+        Some(cli::Commands::Agent {
+            command: cli::AgentCommands::Key { name },
+        }) => {
+            let cfg = config::load(&cli.config)?;
+            if !cfg.auth.agent_keys {
+                eprintln!("Per-agent keys are disabled: agents use auth.jwt_secret");
+                std::process::exit(1);
+            }
+            let store = server::auth::AgentKeyStore::new(&cfg.auth.agent_keys_file);
+            match store.signing_key(&cfg.auth.jwt_secret, name) {
+                Ok(key) => println!("{}", key),
+                Err(e) => {
+                    eprintln!("Failed to read agent keys: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+
+        // Enroll this host as an agent of Home
+        Some(cli::Commands::Agent {
+            command:
+                cli::AgentCommands::Enroll {
+                    home,
+                    join_token,
+                    address,
+                    port,
+                    force,
+                },
+        }) => {
+            enroll(
+                &cli.config,
+                home,
+                join_token,
+                address.as_deref(),
+                *port,
+                *force,
+            )
+            .await;
+            return Ok(());
+        }
+
         // Run or no command - continue to start server
         Some(cli::Commands::Run) | None => {}
 
-        // SelfUpdate is handled earlier
-        Some(cli::Commands::SelfUpdate { .. }) => unreachable!(),
+        // Fleet update: the running Home service updates every agent
+        Some(cli::Commands::SelfUpdate { tag, .. }) => {
+            let cfg = config::load(&cli.config)?;
+            let token = server::auth::JwtManager::new(&cfg.auth.jwt_secret)
+                .generate_token("cli", 1, &[Audience::Api])
+                .map_err(|e| anyhow::anyhow!("Failed to generate token: {}", e))?;
+            let http = http_client::HttpClient::new(&cfg.http_client);
+            let url = format!("http://127.0.0.1:{}/api/fleet/update", cfg.server.port);
+
+            println!("Updating agents");
+            // Not retried: agents may already be swapping their binary
+            let mut req = http
+                .client()
+                .post(&url)
+                .timeout(std::time::Duration::from_secs(330))
+                .header("Authorization", format!("Bearer {}", token));
+            if !tag.is_empty() {
+                req = req.query(&[("tag", tag.join(","))]);
+            }
+            let resp = match req.send().await {
+                Ok(resp) => resp,
+                Err(e) => {
+                    eprintln!("Failed to connect to infractl service: {}", e);
+                    eprintln!("Is the service running? Check: systemctl status infractl");
+                    std::process::exit(1);
+                }
+            };
+
+            let status = resp.status();
+            let body: serde_json::Value = resp.json().await.unwrap_or_default();
+            if !status.is_success() {
+                eprintln!(
+                    "Fleet update failed ({}): {}",
+                    status,
+                    body["error"].as_str().unwrap_or_default()
+                );
+                std::process::exit(1);
+            }
+
+            for result in body["results"].as_array().into_iter().flatten() {
+                let agent = result["agent"].as_str().unwrap_or_default();
+                let message = result["message"].as_str().unwrap_or_default();
+                if result["success"].as_bool() == Some(true) {
+                    println!("  {}: {}", agent, message);
+                } else {
+                    println!("  {}: failed: {}", agent, message);
+                }
+            }
+            println!("\nUpdated: {}, failed: {}", body["updated"], body["failed"]);
+            if body["failed"].as_u64().unwrap_or(0) > 0 {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
     }
 
     logging::init(&cli)?;
@@ -372,7 +995,116 @@ async fn main() -> Result<()> {
 
     let config = config::load(&cli.config)?;
 
-    info!(mode = ?config.mode, "Configuration loaded");
+    info!(mode = ?config.mode, profile = ?config.active_profile, "Configuration loaded");
 
     server::run(config, cli).await
 }
+
+/// `infractl agent enroll`: redeem a join token on Home and write the returned config
+async fn enroll(
+    config_path: &std::path::Path,
+    home: &str,
+    join_token: &str,
+    address: Option<&str>,
+    port: u16,
+    force: bool,
+) {
+    if config_path.exists() && !force {
+        eprintln!(
+            "{} already exists, use --force to replace it",
+            config_path.display()
+        );
+        std::process::exit(1);
+    }
+
+    let base = if home.starts_with("http") {
+        home.trim_end_matches('/').to_string()
+    } else {
+        format!("http://{}", home)
+    };
+    let hostname = sysinfo::System::host_name();
+    let http = http_client::HttpClient::default();
+    let req = http
+        .client()
+        .post(format!("{}/auth/enroll", base))
+        .json(&serde_json::json!({
+            "join_token": join_token,
+            "hostname": hostname,
+            "address": address,
+            "port": port,
+            "home_address": home,
+        }));
+
+    let body: serde_json::Value = match http.send(req).await {
+        Ok(resp) => {
+            let status = resp.status();
+            let body: serde_json::Value = resp.json().await.unwrap_or_default();
+            if !status.is_success() {
+                eprintln!(
+                    "Enrollment failed ({}): {}",
+                    status,
+                    body["error"].as_str().unwrap_or_default()
+                );
+                std::process::exit(1);
+            }
+            body
+        }
+        Err(e) => {
+            eprintln!("Failed to connect to {}: {}", home, e);
+            std::process::exit(1);
+        }
+    };
+
+    let config = body["config"].as_str().unwrap_or_default();
+    if let Some(dir) = config_path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    let written = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(config_path)
+        .and_then(|mut file| file.write_all(config.as_bytes()));
+    if let Err(e) = written {
+        eprintln!("Failed to write {}: {}", config_path.display(), e);
+        std::process::exit(1);
+    }
+
+    println!(
+        "Enrolled as '{}' (Home reaches it at {})",
+        body["name"].as_str().unwrap_or_default(),
+        body["address"].as_str().unwrap_or_default()
+    );
+    println!("Config written to {}", config_path.display());
+}
+
+/// `infractl health --grpc`: call `infractl.v1.Agent/Health`
+async fn grpc_health(endpoint: String, token: Option<String>) {
+    use server::grpc::proto::{agent_client::AgentClient, HealthRequest};
+
+    let mut client = match AgentClient::connect(endpoint).await {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Failed to connect: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut request = tonic::Request::new(HealthRequest { light: false });
+    if let Some(value) = token.and_then(|t| format!("Bearer {}", t).parse().ok()) {
+        request.metadata_mut().insert("authorization", value);
+    }
+
+    match client.health(request).await {
+        Ok(reply) => println!("{:#?}", reply.into_inner()),
+        Err(status) => {
+            eprintln!(
+                "Health check failed ({:?}): {}",
+                status.code(),
+                status.message()
+            );
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::process::Stdio;
+use std::sync::RwLock;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::process::Command;
+
+/// One value of a custom metric and when its command printed it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomSample {
+    pub value: Value,
+    #[serde(with = "time::serde::rfc3339")]
+    pub collected_at: OffsetDateTime,
+}
+
+/// Values of the custom metrics (`modules.metrics.custom`). `/health` shows
+/// the latest value of each; a metrics report carries only the samples taken
+/// since the previous report, so every sample reaches Home once. A metric
+/// whose command fails is left out until it succeeds again, rather than
+/// reporting a stale value.
+#[derive(Default)]
+pub struct CustomMetrics(RwLock<Samples>);
+
+#[derive(Default)]
+struct Samples {
+    latest: BTreeMap<String, CustomSample>,
+    unreported: BTreeMap<String, CustomSample>,
+}
+
+impl CustomMetrics {
+    pub fn set(&self, name: &str, value: Value) {
+        let sample = CustomSample {
+            value,
+            collected_at: OffsetDateTime::now_utc(),
+        };
+        let mut samples = self.0.write().unwrap();
+        samples.unreported.insert(name.to_string(), sample.clone());
+        samples.latest.insert(name.to_string(), sample);
+    }
+
+    pub fn clear(&self, name: &str) {
+        let mut samples = self.0.write().unwrap();
+        samples.latest.remove(name);
+        samples.unreported.remove(name);
+    }
+
+    /// Latest value of each metric
+    pub fn snapshot(&self) -> BTreeMap<String, Value> {
+        let samples = self.0.read().unwrap();
+        samples
+            .latest
+            .iter()
+            .map(|(name, sample)| (name.clone(), sample.value.clone()))
+            .collect()
+    }
+
+    /// Samples not yet handed to a report, newest per metric
+    pub fn take_unreported(&self) -> BTreeMap<String, CustomSample> {
+        std::mem::take(&mut self.0.write().unwrap().unreported)
+    }
+}
+
+/// Run a custom metric command with `sh -c` and parse its stdout
+pub async fn run(command: &str, timeout: Duration) -> Result<Value, String> {
+    let output = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(timeout, output)
+        .await
+        .map_err(|_| format!("Command timed out after {:?}", timeout))?
+        .map_err(|e| format!("Failed to execute command: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Command failed with exit code {}: {}",
+            output.status.code().unwrap_or(-1),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    parse_output(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// A number (`42`, `0.5`) or any JSON value; surrounding whitespace is ignored
+fn parse_output(stdout: &str) -> Result<Value, String> {
+    let stdout = stdout.trim();
+    if stdout.is_empty() {
+        return Err("Command printed nothing".to_string());
+    }
+    serde_json::from_str(stdout).map_err(|_| {
+        let preview: String = stdout.chars().take(80).collect();
+        format!("Output is neither a number nor JSON: {}", preview)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples_are_reported_once() {
+        let metrics = CustomMetrics::default();
+        metrics.set("queue_depth", Value::from(3));
+        metrics.set("queue_depth", Value::from(7));
+        metrics.set("workers", Value::from(2));
+
+        let reported = metrics.take_unreported();
+        assert_eq!(reported.len(), 2);
+        assert_eq!(reported["queue_depth"].value, Value::from(7));
+        assert!(metrics.take_unreported().is_empty());
+        // Still shown as the latest value
+        assert_eq!(metrics.snapshot()["queue_depth"], Value::from(7));
+
+        metrics.set("workers", Value::from(4));
+        metrics.clear("workers");
+        assert!(metrics.take_unreported().is_empty());
+        assert!(!metrics.snapshot().contains_key("workers"));
+    }
+
+    #[tokio::test]
+    async fn test_custom_metric_output() {
+        let timeout = Duration::from_secs(5);
+        assert_eq!(run("echo ' 42 '", timeout).await.unwrap(), Value::from(42));
+        assert_eq!(
+            run(r#"echo '{"depth": 3, "workers": 2}'"#, timeout)
+                .await
+                .unwrap(),
+            serde_json::json!({"depth": 3, "workers": 2})
+        );
+        assert!(run("echo ready", timeout).await.is_err());
+        assert!(run("true", timeout).await.is_err());
+        assert!(run("echo 1; exit 3", timeout).await.is_err());
+        assert!(run("sleep 5", Duration::from_millis(100)).await.is_err());
+    }
+}
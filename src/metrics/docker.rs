@@ -1,11 +1,15 @@
+use crate::config::ContainerRuntime;
 use bollard::container::{ListContainersOptions, Stats, StatsOptions};
 use bollard::Docker;
 use futures::StreamExt;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use tracing::warn;
 
-#[derive(Debug, Clone, Serialize)]
+/// Rootful Podman API socket
+const PODMAN_ROOT_SOCKET: &str = "/run/podman/podman.sock";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DockerMetrics {
     pub available: bool,
     pub version: Option<String>,
@@ -18,7 +22,7 @@ pub struct DockerMetrics {
     pub compose_projects: Vec<ComposeProject>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContainerInfo {
     pub id: String,
     pub name: String,
@@ -34,7 +38,7 @@ pub struct ContainerInfo {
     pub compose_service: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContainerStats {
     pub cpu_percent: f64,
     pub memory_usage: u64,
@@ -47,7 +51,7 @@ pub struct ContainerStats {
     pub pids: u64,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ComposeProject {
     pub name: String,
     pub working_dir: Option<String>,
@@ -61,9 +65,9 @@ pub struct DockerCollector {
 }
 
 impl DockerCollector {
-    pub async fn new() -> Result<Self, bollard::errors::Error> {
-        // Try to connect to Docker socket
-        let client = Docker::connect_with_socket_defaults()?;
+    pub async fn new(runtime: ContainerRuntime) -> Result<Self, bollard::errors::Error> {
+        // Try to connect to the Docker (or Podman) socket
+        let client = connect_docker(runtime)?;
 
         // Verify connection
         client.ping().await?;
@@ -292,8 +296,23 @@ impl DockerCollector {
     }
 }
 
+/// Connect to the engine API. Podman serves a Docker-compatible API on its
+/// own socket: rootful `/run/podman/podman.sock`, rootless under `$XDG_RUNTIME_DIR`.
+pub fn connect_docker(runtime: ContainerRuntime) -> Result<Docker, bollard::errors::Error> {
+    if runtime.resolve() != ContainerRuntime::Podman {
+        return Docker::connect_with_socket_defaults();
+    }
+
+    let rootless = std::env::var("XDG_RUNTIME_DIR")
+        .map(|dir| format!("{}/podman/podman.sock", dir))
+        .ok()
+        .filter(|_| !std::path::Path::new(PODMAN_ROOT_SOCKET).exists());
+    let socket = rootless.as_deref().unwrap_or(PODMAN_ROOT_SOCKET);
+    Docker::connect_with_socket(socket, 120, bollard::API_DEFAULT_VERSION)
+}
+
 /// Check if Docker is available on the system
 #[allow(dead_code)]
 pub async fn is_docker_available() -> bool {
-    DockerCollector::new().await.is_ok()
+    DockerCollector::new(ContainerRuntime::Auto).await.is_ok()
 }
@@ -1,5 +1,8 @@
+pub mod custom;
 mod docker;
+mod report;
 mod system;
 
-pub use docker::{DockerCollector, DockerMetrics};
+pub use docker::{connect_docker, ContainerInfo, ContainerStats, DockerCollector, DockerMetrics};
+pub use report::MetricsReport;
 pub use system::{SystemCollector, SystemMetrics};
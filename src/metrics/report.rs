@@ -0,0 +1,148 @@
+use super::custom::CustomSample;
+use super::system::NetworkInfo;
+use super::{DockerMetrics, SystemMetrics};
+use crate::storage::{ContainerMetricRecord, CustomMetricRecord, MetricRecord};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use time::OffsetDateTime;
+
+/// Metrics an agent pushes to Home's `POST /api/ingest/metrics`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsReport {
+    #[serde(with = "time::serde::rfc3339")]
+    pub collected_at: OffsetDateTime,
+    pub version: String,
+    pub uptime_seconds: u64,
+    pub system: SystemMetrics,
+    #[serde(default)]
+    pub docker: Option<DockerMetrics>,
+    /// Samples of `modules.metrics.custom` taken since the previous report,
+    /// by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom: BTreeMap<String, CustomSample>,
+}
+
+impl MetricsReport {
+    /// Row stored for `agent_name`; disk usage is that of `/` and the full
+    /// report is kept as `raw_json`
+    pub fn record(&self, agent_name: &str) -> MetricRecord {
+        let system = &self.system;
+        MetricRecord {
+            id: None,
+            agent_name: agent_name.to_string(),
+            collected_at: self.collected_at,
+            cpu_usage: system.cpu_usage as f64,
+            memory_usage_percent: system.memory_usage_percent as f64,
+            memory_used: system.memory_used,
+            memory_total: system.memory_total,
+            load_one: system.load_avg.one,
+            load_five: system.load_avg.five,
+            load_fifteen: system.load_avg.fifteen,
+            disk_usage_percent: system
+                .disks
+                .iter()
+                .find(|disk| disk.mount_point == "/")
+                .map(|disk| disk.usage_percent as f64),
+            containers_running: self.docker.as_ref().map(|d| d.containers_running),
+            containers_total: self.docker.as_ref().map(|d| d.containers_total),
+            raw_json: serde_json::to_string(self).ok(),
+            network_rx_bytes: Some(network_total(system, |n| n.received_bytes)),
+            network_tx_bytes: Some(network_total(system, |n| n.transmitted_bytes)),
+        }
+    }
+
+    /// Rows stored for each container the report has stats for
+    pub fn container_records(&self, agent_name: &str) -> Vec<ContainerMetricRecord> {
+        let Some(docker) = &self.docker else {
+            return Vec::new();
+        };
+        docker
+            .containers
+            .iter()
+            .filter_map(|container| {
+                let stats = container.stats.as_ref()?;
+                Some(ContainerMetricRecord {
+                    id: None,
+                    agent_name: agent_name.to_string(),
+                    container_name: container.name.clone(),
+                    compose_project: container.compose_project.clone(),
+                    compose_service: container.compose_service.clone(),
+                    collected_at: self.collected_at,
+                    cpu_percent: stats.cpu_percent,
+                    memory_usage: stats.memory_usage,
+                    memory_limit: stats.memory_limit,
+                    memory_percent: stats.memory_percent,
+                    network_rx_bytes: stats.network_rx_bytes,
+                    network_tx_bytes: stats.network_tx_bytes,
+                    block_read_bytes: stats.block_read_bytes,
+                    block_write_bytes: stats.block_write_bytes,
+                    pids: stats.pids,
+                })
+            })
+            .collect()
+    }
+
+    /// Rows stored for each custom metric sample, at the time it was taken:
+    /// numbers in `value`, anything else as JSON text
+    pub fn custom_records(&self, agent_name: &str) -> Vec<CustomMetricRecord> {
+        self.custom
+            .iter()
+            .map(|(name, sample)| CustomMetricRecord {
+                id: None,
+                agent_name: agent_name.to_string(),
+                name: name.clone(),
+                collected_at: sample.collected_at,
+                value: sample.value.as_f64(),
+                json: (!sample.value.is_number()).then(|| sample.value.to_string()),
+            })
+            .collect()
+    }
+}
+
+/// Sum of a counter over all interfaces but loopback
+fn network_total(system: &SystemMetrics, counter: impl Fn(&NetworkInfo) -> u64) -> u64 {
+    system
+        .networks
+        .iter()
+        .filter(|n| n.name != "lo")
+        .map(counter)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::SystemCollector;
+
+    #[test]
+    fn test_report_round_trip() {
+        let sampled_at =
+            OffsetDateTime::now_utc().replace_nanosecond(0).unwrap() - time::Duration::seconds(40);
+        let report = MetricsReport {
+            collected_at: OffsetDateTime::now_utc(),
+            version: "1.2.3".to_string(),
+            uptime_seconds: 60,
+            system: SystemCollector::collect(),
+            docker: None,
+            custom: BTreeMap::from([(
+                "queue_depth".to_string(),
+                CustomSample {
+                    value: serde_json::json!(7),
+                    collected_at: sampled_at,
+                },
+            )]),
+        };
+
+        let json = serde_json::to_string(&report).unwrap();
+        let parsed: MetricsReport = serde_json::from_str(&json).unwrap();
+        let record = parsed.record("web-1");
+        assert_eq!(record.agent_name, "web-1");
+        assert_eq!(record.memory_total, report.system.memory_total);
+        assert_eq!(record.containers_total, None);
+        assert_eq!(record.raw_json.as_deref(), Some(json.as_str()));
+        let custom = parsed.custom_records("web-1");
+        assert_eq!(custom[0].value, Some(7.0));
+        assert_eq!(custom[0].json, None);
+        assert_eq!(custom[0].collected_at, sampled_at);
+    }
+}
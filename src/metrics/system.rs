@@ -1,7 +1,14 @@
-use serde::Serialize;
-use sysinfo::{Disks, Networks, System};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+use sysinfo::{
+    Components, Disks, Networks, ProcessesToUpdate, System, Users, MINIMUM_CPU_UPDATE_INTERVAL,
+};
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SystemMetrics {
     pub cpu_usage: f32,
     pub cpu_count: usize,
@@ -19,16 +26,22 @@ pub struct SystemMetrics {
     pub kernel_version: Option<String>,
     pub disks: Vec<DiskInfo>,
     pub networks: Vec<NetworkInfo>,
+    /// Hardware sensors; empty where the platform exposes none (VMs, containers)
+    #[serde(default)]
+    pub sensors: SensorMetrics,
+    /// Busiest processes, when `modules.metrics.top_processes` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub processes: Option<TopProcesses>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoadAverage {
     pub one: f64,
     pub five: f64,
     pub fifteen: f64,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DiskInfo {
     pub name: String,
     pub mount_point: String,
@@ -37,9 +50,40 @@ pub struct DiskInfo {
     pub free: u64,
     pub usage_percent: f32,
     pub fs_type: String,
+    /// Throughput and operations per second since the previous collection;
+    /// `None` on the first one and for devices without I/O counters (Linux
+    /// `/proc/diskstats` only)
+    #[serde(default)]
+    pub io: Option<DiskIo>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DiskIo {
+    pub read_bytes_per_sec: f64,
+    pub write_bytes_per_sec: f64,
+    pub read_iops: f64,
+    pub write_iops: f64,
+}
+
+/// Cumulative counters of one block device
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DiskCounters {
+    reads: u64,
+    read_bytes: u64,
+    writes: u64,
+    write_bytes: u64,
+}
+
+/// Counters per device or interface and when they were read, kept from one
+/// collection to the next to turn them into rates
+type Sample<T> = Option<(Instant, HashMap<String, T>)>;
+
+static LAST_DISK_COUNTERS: Mutex<Sample<DiskCounters>> = Mutex::new(None);
+
+/// Received and transmitted bytes per interface
+static LAST_NETWORK_COUNTERS: Mutex<Sample<(u64, u64)>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkInfo {
     pub name: String,
     pub received_bytes: u64,
@@ -48,14 +92,82 @@ pub struct NetworkInfo {
     pub transmitted_packets: u64,
     pub errors_in: u64,
     pub errors_out: u64,
+    /// Traffic since the previous collection; `None` on the first one and
+    /// for interfaces that just appeared or whose counters were reset
+    #[serde(default)]
+    pub rates: Option<NetworkRates>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NetworkRates {
+    pub received_bytes_per_sec: f64,
+    pub transmitted_bytes_per_sec: f64,
+    /// Bytes received since the previous collection
+    pub received_delta: u64,
+    /// Bytes transmitted since the previous collection
+    pub transmitted_delta: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SensorMetrics {
+    pub temperatures: Vec<TemperatureInfo>,
+    pub fans: Vec<FanInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemperatureInfo {
+    pub label: String,
+    /// Degrees Celsius
+    pub temperature: Option<f32>,
+    /// Highest reading since the sensor was first read
+    pub max: Option<f32>,
+    /// Temperature the hardware considers critical
+    pub critical: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FanInfo {
+    pub label: String,
+    pub rpm: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopProcesses {
+    /// Highest CPU usage first
+    pub by_cpu: Vec<ProcessInfo>,
+    /// Largest resident memory first
+    pub by_memory: Vec<ProcessInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub name: String,
+    pub user: Option<String>,
+    /// Percent of one core, so above 100 for multi-threaded processes
+    pub cpu_usage: f32,
+    /// Resident set size in bytes
+    pub memory: u64,
 }
 
 pub struct SystemCollector;
 
 impl SystemCollector {
     pub fn collect() -> SystemMetrics {
+        Self::collect_with_processes(0)
+    }
+
+    /// Like [`SystemCollector::collect`], plus the `top` processes by CPU and
+    /// by memory (none when 0). Process CPU usage is measured over a short
+    /// wait, so this takes a little longer.
+    pub fn collect_with_processes(top: usize) -> SystemMetrics {
         let mut sys = System::new_all();
         sys.refresh_all();
+        let processes = (top > 0).then(|| {
+            std::thread::sleep(MINIMUM_CPU_UPDATE_INTERVAL);
+            sys.refresh_processes(ProcessesToUpdate::All, true);
+            Self::top_processes(&sys, top)
+        });
 
         let cpu_usage = sys.global_cpu_usage();
         let cpu_count = sys.cpus().len();
@@ -76,6 +188,10 @@ impl SystemCollector {
 
         let disks = Self::collect_disks();
         let networks = Self::collect_networks();
+        let sensors = SensorMetrics {
+            temperatures: Self::collect_temperatures(),
+            fans: read_hwmon_fans(Path::new("/sys/class/hwmon")),
+        };
 
         SystemMetrics {
             cpu_usage,
@@ -98,11 +214,57 @@ impl SystemCollector {
             kernel_version: System::kernel_version(),
             disks,
             networks,
+            sensors,
+            processes,
+        }
+    }
+
+    fn collect_temperatures() -> Vec<TemperatureInfo> {
+        // Readings the sensor cannot provide are NaN
+        let reading = |value: f32| (!value.is_nan()).then_some(value);
+        Components::new_with_refreshed_list()
+            .iter()
+            .map(|component| TemperatureInfo {
+                label: component.label().to_string(),
+                temperature: reading(component.temperature()),
+                max: reading(component.max()),
+                critical: component.critical().and_then(reading),
+            })
+            .collect()
+    }
+
+    fn top_processes(sys: &System, top: usize) -> TopProcesses {
+        let users = Users::new_with_refreshed_list();
+        let mut processes: Vec<ProcessInfo> = sys
+            .processes()
+            .values()
+            // Threads are listed as processes on Linux; count each process once
+            .filter(|process| process.thread_kind().is_none())
+            .map(|process| ProcessInfo {
+                pid: process.pid().as_u32(),
+                name: process.name().to_string_lossy().to_string(),
+                user: process
+                    .user_id()
+                    .and_then(|uid| users.get_user_by_id(uid))
+                    .map(|user| user.name().to_string()),
+                cpu_usage: process.cpu_usage(),
+                memory: process.memory(),
+            })
+            .collect();
+
+        processes.sort_by(|a, b| b.cpu_usage.total_cmp(&a.cpu_usage));
+        let by_cpu = processes.iter().take(top).cloned().collect();
+        processes.sort_by_key(|p| std::cmp::Reverse(p.memory));
+        processes.truncate(top);
+        TopProcesses {
+            by_cpu,
+            by_memory: processes,
         }
     }
 
     fn collect_disks() -> Vec<DiskInfo> {
         let disks = Disks::new_with_refreshed_list();
+        let io = Self::disk_io();
 
         disks
             .iter()
@@ -116,8 +278,10 @@ impl SystemCollector {
                     0.0
                 };
 
+                let name = disk.name().to_string_lossy().to_string();
                 DiskInfo {
-                    name: disk.name().to_string_lossy().to_string(),
+                    io: io.get(name.trim_start_matches("/dev/")).copied(),
+                    name,
                     mount_point: disk.mount_point().to_string_lossy().to_string(),
                     total,
                     used,
@@ -129,8 +293,45 @@ impl SystemCollector {
             .collect()
     }
 
+    /// I/O rates per device name (`sda1`) since the previous call
+    fn disk_io() -> HashMap<String, DiskIo> {
+        let Ok(stats) = fs::read_to_string("/proc/diskstats") else {
+            return HashMap::new();
+        };
+        let now = Instant::now();
+        let counters = parse_diskstats(&stats);
+        let mut last = LAST_DISK_COUNTERS.lock().unwrap();
+        let rates = match last.as_ref() {
+            Some((at, previous)) => disk_rates(previous, &counters, (now - *at).as_secs_f64()),
+            None => HashMap::new(),
+        };
+        *last = Some((now, counters));
+        rates
+    }
+
     fn collect_networks() -> Vec<NetworkInfo> {
         let networks = Networks::new_with_refreshed_list();
+        let now = Instant::now();
+        let counters: HashMap<String, (u64, u64)> = networks
+            .iter()
+            .map(|(name, data)| {
+                (
+                    name.clone(),
+                    (data.total_received(), data.total_transmitted()),
+                )
+            })
+            .collect();
+        let rates = {
+            let mut last = LAST_NETWORK_COUNTERS.lock().unwrap();
+            let rates = match last.as_ref() {
+                Some((at, previous)) => {
+                    network_rates(previous, &counters, (now - *at).as_secs_f64())
+                }
+                None => HashMap::new(),
+            };
+            *last = Some((now, counters));
+            rates
+        };
 
         networks
             .iter()
@@ -142,7 +343,224 @@ impl SystemCollector {
                 transmitted_packets: data.total_packets_transmitted(),
                 errors_in: data.total_errors_on_received(),
                 errors_out: data.total_errors_on_transmitted(),
+                rates: rates.get(name).copied(),
             })
             .collect()
     }
 }
+
+/// Fan speeds from the Linux hwmon tree (`fan<N>_input`), labelled by
+/// `fan<N>_label` or `<chip name> fan<N>`
+fn read_hwmon_fans(root: &Path) -> Vec<FanInfo> {
+    let read = |path: &Path| fs::read_to_string(path).ok().map(|s| s.trim().to_string());
+    let Ok(chips) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut chips: Vec<_> = chips
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect();
+    chips.sort();
+
+    let mut fans = Vec::new();
+    for chip in chips {
+        let chip_name = read(&chip.join("name")).unwrap_or_default();
+        let Ok(entries) = fs::read_dir(&chip) else {
+            continue;
+        };
+        let mut inputs: Vec<String> = entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| name.starts_with("fan") && name.ends_with("_input"))
+            .collect();
+        inputs.sort();
+        for input in inputs {
+            let Some(rpm) = read(&chip.join(&input)).and_then(|v| v.parse().ok()) else {
+                continue;
+            };
+            let fan = input.trim_end_matches("_input");
+            let label = read(&chip.join(format!("{}_label", fan)))
+                .unwrap_or_else(|| format!("{} {}", chip_name, fan).trim().to_string());
+            fans.push(FanInfo { label, rpm });
+        }
+    }
+    fans
+}
+
+/// Counters per device from `/proc/diskstats` (sectors are 512 bytes
+/// whatever the device's sector size)
+fn parse_diskstats(stats: &str) -> HashMap<String, DiskCounters> {
+    stats
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let counter = |i: usize| fields.get(i)?.parse::<u64>().ok();
+            Some((
+                fields.get(2)?.to_string(),
+                DiskCounters {
+                    reads: counter(3)?,
+                    read_bytes: counter(5)? * 512,
+                    writes: counter(7)?,
+                    write_bytes: counter(9)? * 512,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Per-second rates between two samples `elapsed` seconds apart; counters
+/// that went backwards (device re-added) are skipped
+fn disk_rates(
+    previous: &HashMap<String, DiskCounters>,
+    current: &HashMap<String, DiskCounters>,
+    elapsed: f64,
+) -> HashMap<String, DiskIo> {
+    if elapsed <= 0.0 {
+        return HashMap::new();
+    }
+    current
+        .iter()
+        .filter_map(|(name, now)| {
+            let before = previous.get(name)?;
+            let rate = |now: u64, before: u64| Some(now.checked_sub(before)? as f64 / elapsed);
+            Some((
+                name.clone(),
+                DiskIo {
+                    read_bytes_per_sec: rate(now.read_bytes, before.read_bytes)?,
+                    write_bytes_per_sec: rate(now.write_bytes, before.write_bytes)?,
+                    read_iops: rate(now.reads, before.reads)?,
+                    write_iops: rate(now.writes, before.writes)?,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Per-interface traffic between two samples of (received, transmitted)
+/// byte counters taken `elapsed` seconds apart
+fn network_rates(
+    previous: &HashMap<String, (u64, u64)>,
+    current: &HashMap<String, (u64, u64)>,
+    elapsed: f64,
+) -> HashMap<String, NetworkRates> {
+    if elapsed <= 0.0 {
+        return HashMap::new();
+    }
+    current
+        .iter()
+        .filter_map(|(name, &(rx, tx))| {
+            let &(rx_before, tx_before) = previous.get(name)?;
+            let received_delta = rx.checked_sub(rx_before)?;
+            let transmitted_delta = tx.checked_sub(tx_before)?;
+            Some((
+                name.clone(),
+                NetworkRates {
+                    received_bytes_per_sec: received_delta as f64 / elapsed,
+                    transmitted_bytes_per_sec: transmitted_delta as f64 / elapsed,
+                    received_delta,
+                    transmitted_delta,
+                },
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disk_rates_from_diskstats() {
+        let before = parse_diskstats(
+            "   8       0 sda 100 0 2000 50 40 0 800 20 0 60 70 0 0 0 0\n\
+             259       1 nvme0n1p1 10 0 80 1 5 0 40 1 0 2 2\n\
+             broken line\n",
+        );
+        assert_eq!(before.len(), 2);
+        assert_eq!(before["sda"].read_bytes, 2000 * 512);
+
+        let after = parse_diskstats(
+            "   8       0 sda 300 0 6000 90 50 0 1600 30 0 80 90 0 0 0 0\n\
+             259       1 nvme0n1p1 5 0 40 1 5 0 40 1 0 2 2\n",
+        );
+        let rates = disk_rates(&before, &after, 2.0);
+        assert_eq!(
+            rates["sda"],
+            DiskIo {
+                read_bytes_per_sec: 4000.0 * 512.0 / 2.0,
+                write_bytes_per_sec: 800.0 * 512.0 / 2.0,
+                read_iops: 100.0,
+                write_iops: 5.0,
+            }
+        );
+        // Counters reset: no rate rather than a negative one
+        assert!(!rates.contains_key("nvme0n1p1"));
+    }
+
+    #[test]
+    fn test_network_rates_between_samples() {
+        let before = HashMap::from([
+            ("eth0".to_string(), (1_000, 500)),
+            ("wg0".to_string(), (9_000, 9_000)),
+        ]);
+        let after = HashMap::from([
+            ("eth0".to_string(), (31_000, 2_500)),
+            ("wg0".to_string(), (100, 50)),
+            ("docker0".to_string(), (10, 10)),
+        ]);
+        let rates = network_rates(&before, &after, 30.0);
+        assert_eq!(
+            rates["eth0"],
+            NetworkRates {
+                received_bytes_per_sec: 1_000.0,
+                transmitted_bytes_per_sec: 2_000.0 / 30.0,
+                received_delta: 30_000,
+                transmitted_delta: 2_000,
+            }
+        );
+        // Reset counters and new interfaces have no rate yet
+        assert_eq!(rates.len(), 1);
+    }
+
+    #[test]
+    fn test_hwmon_fans() {
+        let root = tempfile::tempdir().unwrap();
+        let chip = root.path().join("hwmon0");
+        fs::create_dir(&chip).unwrap();
+        fs::write(chip.join("name"), "nct6775\n").unwrap();
+        fs::write(chip.join("fan1_input"), "1200\n").unwrap();
+        fs::write(chip.join("fan2_input"), "850\n").unwrap();
+        fs::write(chip.join("fan2_label"), "CPU Fan\n").unwrap();
+        fs::write(chip.join("temp1_input"), "42000\n").unwrap();
+
+        let fans: Vec<(String, u32)> = read_hwmon_fans(root.path())
+            .into_iter()
+            .map(|fan| (fan.label, fan.rpm))
+            .collect();
+        assert_eq!(
+            fans,
+            [
+                ("nct6775 fan1".to_string(), 1200),
+                ("CPU Fan".to_string(), 850)
+            ]
+        );
+        assert!(read_hwmon_fans(&root.path().join("missing")).is_empty());
+    }
+
+    #[test]
+    fn test_top_processes_sorted_and_limited() {
+        assert!(SystemCollector::collect().processes.is_none());
+
+        let top = SystemCollector::collect_with_processes(3)
+            .processes
+            .unwrap();
+        assert!(!top.by_memory.is_empty() && top.by_memory.len() <= 3);
+        assert!(top.by_cpu.len() <= 3);
+        assert!(top
+            .by_memory
+            .windows(2)
+            .all(|pair| pair[0].memory >= pair[1].memory));
+        assert!(top
+            .by_cpu
+            .windows(2)
+            .all(|pair| pair[0].cpu_usage >= pair[1].cpu_usage));
+    }
+}
@@ -0,0 +1,490 @@
+use crate::config::AgentConfig;
+use crate::server::notify;
+use crate::server::AppState;
+use crate::storage::{AgentEvent, AgentStatus, Database};
+use axum::http::Method;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+/// Health checks missed in a row before an agent is marked offline,
+/// unless the agent sets `offline_after`
+pub const OFFLINE_AFTER_MISSED: u32 = 3;
+
+/// Fields of the agent's `/health?light=true` reply that are stored
+#[derive(Debug, Deserialize)]
+struct AgentHealth {
+    version: Option<String>,
+    uptime_seconds: Option<u64>,
+}
+
+/// Running health polls by agent name, each with the config it polls with
+/// and the token that stops it
+#[derive(Default)]
+pub struct HealthPolls(Mutex<HashMap<String, (AgentConfig, CancellationToken)>>);
+
+/// Poll every agent's `/health` on its `health_interval` until shutdown
+/// starts (Home mode with storage)
+pub async fn spawn_health_polls(state: &Arc<AppState>) {
+    if state.db.is_none() {
+        return;
+    }
+    let agents = state.live.read().await.agents.clone();
+    sync_health_polls(state, &agents);
+    info!(agents = agents.len(), "Agent health polling started");
+}
+
+/// Match the running polls to `agents` after a reload: removed agents stop
+/// being polled, new ones start, changed ones restart with their new config
+pub fn sync_health_polls(state: &Arc<AppState>, agents: &[AgentConfig]) {
+    if state.db.is_none() {
+        return;
+    }
+    state
+        .health_polls
+        .0
+        .lock()
+        .unwrap()
+        .retain(|name, (polled, cancel)| {
+            let current = agents.iter().any(|a| a == polled);
+            if !current {
+                debug!(agent = %name, "Agent health poll stopped");
+                cancel.cancel();
+            }
+            current
+        });
+    for agent in agents {
+        spawn_health_poll(state, agent);
+    }
+}
+
+/// Start polling one agent, e.g. right after it enrolled. An agent already
+/// polled with the same config is left alone; one with another config is
+/// restarted.
+pub fn spawn_health_poll(state: &Arc<AppState>, agent: &AgentConfig) {
+    if state.db.is_none() {
+        return;
+    }
+    let mut polls = state.health_polls.0.lock().unwrap();
+    if let Some((polled, cancel)) = polls.get(&agent.name) {
+        if polled == agent {
+            return;
+        }
+        cancel.cancel();
+    }
+    let cancel = CancellationToken::new();
+    polls.insert(agent.name.clone(), (agent.clone(), cancel.clone()));
+    tokio::spawn(poll_agent(state.clone(), agent.clone(), cancel));
+}
+
+async fn poll_agent(state: Arc<AppState>, agent: AgentConfig, cancel: CancellationToken) {
+    let interval =
+        humantime::parse_duration(&agent.health_interval).unwrap_or(Duration::from_secs(30));
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut shutdown = state.shutdown.subscribe();
+    let mut missed = 0;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown.wait_for(|stopping| *stopping) => break,
+            _ = cancel.cancelled() => break,
+        }
+        tokio::select! {
+            _ = poll_once(&state, &agent, &mut missed) => {}
+            _ = cancel.cancelled() => break,
+        }
+    }
+}
+
+/// Run a database call on the blocking pool, off the async runtime
+async fn with_db<T, F>(db: &Arc<Database>, call: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&Database) -> rusqlite::Result<T> + Send + 'static,
+{
+    let db = db.clone();
+    tokio::task::spawn_blocking(move || call(&db))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// One health check; `missed` counts consecutive failures
+async fn poll_once(state: &AppState, agent: &AgentConfig, missed: &mut u32) {
+    let Some(db) = state.db.as_ref() else {
+        return;
+    };
+
+    let offline_after = agent.offline_after.unwrap_or(OFFLINE_AFTER_MISSED);
+    let status = match check(state, agent).await {
+        Ok(health) => {
+            if *missed >= offline_after {
+                info!(agent = %agent.name, "Agent is back online");
+                record_event(state, agent, "online", None).await;
+                if state.config.notifications.on_agent.recovered {
+                    notify::send(
+                        state,
+                        "agent_recovered",
+                        &agent.name,
+                        format!("Agent {} is back online", agent.name),
+                    );
+                }
+            }
+            *missed = 0;
+            AgentStatus {
+                agent_name: agent.name.clone(),
+                last_seen: OffsetDateTime::now_utc(),
+                status: "online".to_string(),
+                version: health.version,
+                uptime_seconds: health.uptime_seconds,
+            }
+        }
+        Err(e) => {
+            *missed += 1;
+            debug!(agent = %agent.name, missed = *missed, error = %e, "Agent health check failed");
+            if *missed != offline_after {
+                return;
+            }
+
+            warn!(agent = %agent.name, error = %e, "Agent is offline");
+            record_event(state, agent, "offline", Some(e.clone())).await;
+            if state.config.notifications.on_agent.offline {
+                notify::send(
+                    state,
+                    "agent_offline",
+                    &agent.name,
+                    format!(
+                        "Agent {} is offline after {} missed health checks: {}",
+                        agent.name, offline_after, e
+                    ),
+                );
+            }
+            // Keep when it was last reachable
+            let name = agent.name.clone();
+            let previous = with_db(db, move |db| db.get_agent_status(&name))
+                .await
+                .ok()
+                .flatten();
+            AgentStatus {
+                agent_name: agent.name.clone(),
+                last_seen: previous
+                    .as_ref()
+                    .map_or_else(OffsetDateTime::now_utc, |p| p.last_seen),
+                status: "offline".to_string(),
+                version: previous.as_ref().and_then(|p| p.version.clone()),
+                uptime_seconds: None,
+            }
+        }
+    };
+
+    if let Err(e) = with_db(db, move |db| db.update_agent_status(&status)).await {
+        warn!(agent = %agent.name, error = %e, "Failed to store agent status");
+    }
+}
+
+/// Store an offline/online transition for `GET /api/agents/{name}/events`
+async fn record_event(state: &AppState, agent: &AgentConfig, event: &str, detail: Option<String>) {
+    let Some(db) = state.db.as_ref() else {
+        return;
+    };
+    let event = AgentEvent {
+        id: None,
+        agent_name: agent.name.clone(),
+        event: event.to_string(),
+        occurred_at: OffsetDateTime::now_utc(),
+        detail,
+    };
+    if let Err(e) = with_db(db, move |db| db.insert_agent_event(&event)).await {
+        warn!(agent = %agent.name, error = %e, "Failed to store agent event");
+    }
+}
+
+async fn check(state: &AppState, agent: &AgentConfig) -> Result<AgentHealth, String> {
+    // No retries: the next interval is the retry, and missed checks are counted
+    let reply = call(
+        state,
+        agent,
+        AgentRequest::new(Method::GET, "/health?light=true"),
+        agent_timeout(agent),
+    )
+    .await?;
+    if !reply.is_success() {
+        return Err(format!("Agent returned {}", reply.status));
+    }
+    reply
+        .json()
+        .map_err(|e| format!("Invalid health response: {}", e))
+}
+
+/// Request to an agent's API, sent directly or over its reverse connection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentRequest {
+    pub method: String,
+    /// Path and query, e.g. `/webhook/status/42`
+    pub path: String,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    /// Request bodies are text: every agent API takes JSON
+    #[serde(default)]
+    pub body: String,
+    /// Retry transient failures (direct requests only, see `HttpClient::send`)
+    #[serde(skip)]
+    pub retry: bool,
+}
+
+impl AgentRequest {
+    pub fn new(method: Method, path: &str) -> Self {
+        Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: Vec::new(),
+            body: String::new(),
+            retry: false,
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn bearer(self, token: &str) -> Self {
+        self.header("authorization", &format!("Bearer {}", token))
+    }
+
+    pub fn json<T: Serialize>(mut self, body: &T) -> Self {
+        self.body = serde_json::to_string(body).unwrap_or_default();
+        self.header("content-type", "application/json")
+    }
+
+    pub fn retry(mut self) -> Self {
+        self.retry = true;
+        self
+    }
+}
+
+/// Agent's answer to an [`AgentRequest`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentReply {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default)]
+    pub body: String,
+}
+
+impl AgentReply {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> serde_json::Result<T> {
+        serde_json::from_str(&self.body)
+    }
+
+    /// `error` of an error reply, empty when there is none
+    pub fn error(&self) -> String {
+        self.json::<serde_json::Value>()
+            .ok()
+            .and_then(|body| body["error"].as_str().map(String::from))
+            .unwrap_or_default()
+    }
+}
+
+/// Send a request to an agent: over its reverse connection while it holds
+/// one (`server.reverse_connect`), otherwise to its `address`
+pub async fn call(
+    state: &AppState,
+    agent: &AgentConfig,
+    request: AgentRequest,
+    timeout: Duration,
+) -> Result<AgentReply, String> {
+    if state.tunnels.is_connected(&agent.name) {
+        return state.tunnels.send(&agent.name, request, timeout).await;
+    }
+
+    let method = Method::from_bytes(request.method.as_bytes())
+        .map_err(|e| format!("Invalid method: {}", e))?;
+    let mut builder = state
+        .http
+        .client()
+        .request(method, agent_url(&agent.address, &request.path))
+        .timeout(timeout)
+        .body(request.body.clone());
+    for (name, value) in &request.headers {
+        builder = builder.header(name, value);
+    }
+    let resp = if request.retry {
+        state.http.send(builder).await
+    } else {
+        builder.send().await
+    }
+    .map_err(|e| format!("Failed to connect: {}", e))?;
+
+    let status = resp.status().as_u16();
+    let headers = resp
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    let body = resp
+        .text()
+        .await
+        .map_err(|e| format!("Failed to read response: {}", e))?;
+    Ok(AgentReply {
+        status,
+        headers,
+        body,
+    })
+}
+
+pub fn agent_timeout(agent: &AgentConfig) -> Duration {
+    humantime::parse_duration(&agent.timeout).unwrap_or(Duration::from_secs(10))
+}
+
+/// Agent addresses may be configured with or without a scheme
+pub fn agent_url(address: &str, path: &str) -> String {
+    let address = address.trim_end_matches('/');
+    if address.starts_with("http://") || address.starts_with("https://") {
+        format!("{}{}", address, path)
+    } else {
+        format!("http://{}{}", address, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::storage::{Database, DbWriter};
+    use axum::{http::StatusCode, routing::get, Json, Router};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn test_agent_url() {
+        assert_eq!(
+            agent_url("http://10.0.0.10:8111/", "/webhook/deploy/app"),
+            "http://10.0.0.10:8111/webhook/deploy/app"
+        );
+        assert_eq!(
+            agent_url("10.0.0.10:8111", "/webhook/deploy/app"),
+            "http://10.0.0.10:8111/webhook/deploy/app"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_agent_goes_offline_after_missed_checks() {
+        let healthy = Arc::new(AtomicBool::new(true));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let agent_healthy = healthy.clone();
+        tokio::spawn(async move {
+            let app = Router::new().route(
+                "/health",
+                get(move || async move {
+                    if !agent_healthy.load(Ordering::Relaxed) {
+                        return Err(StatusCode::SERVICE_UNAVAILABLE);
+                    }
+                    Ok(Json(serde_json::json!({
+                        "status": "healthy",
+                        "version": "1.2.3",
+                        "uptime_seconds": 60,
+                    })))
+                }),
+            );
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let config: Config = serde_yaml::from_str(&format!(
+            "mode: home\nserver: {{}}\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\nagents:\n  - name: web-1\n    address: \"{}\"\n",
+            address
+        ))
+        .unwrap();
+        let agent = config.agents[0].clone();
+        let db = Arc::new(Database::new(":memory:").unwrap());
+        db.migrate().unwrap();
+        let writer = DbWriter::spawn(db.clone(), &config.modules.storage.write_buffer);
+        let state = AppState::with_database(config, db.clone(), writer);
+
+        let mut missed = 0;
+        poll_once(&state, &agent, &mut missed).await;
+        let online = db.get_agent_status("web-1").unwrap().unwrap();
+        assert_eq!(online.status, "online");
+        assert_eq!(online.version.as_deref(), Some("1.2.3"));
+
+        healthy.store(false, Ordering::Relaxed);
+        for _ in 1..OFFLINE_AFTER_MISSED {
+            poll_once(&state, &agent, &mut missed).await;
+            assert_eq!(
+                db.get_agent_status("web-1").unwrap().unwrap().status,
+                "online"
+            );
+        }
+        poll_once(&state, &agent, &mut missed).await;
+        let offline = db.get_agent_status("web-1").unwrap().unwrap();
+        assert_eq!(offline.status, "offline");
+        assert_eq!(offline.last_seen, online.last_seen);
+        let events = db.get_agent_events("web-1", 10).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, "offline");
+        assert!(events[0].detail.is_some());
+
+        healthy.store(true, Ordering::Relaxed);
+        poll_once(&state, &agent, &mut missed).await;
+        let events = db.get_agent_events("web-1", 10).unwrap();
+        assert_eq!(events[0].event, "online");
+        assert_eq!(events.len(), 2);
+
+        // A per-agent threshold replaces the default
+        let strict = AgentConfig {
+            offline_after: Some(1),
+            ..agent.clone()
+        };
+        healthy.store(false, Ordering::Relaxed);
+        poll_once(&state, &strict, &mut missed).await;
+        assert_eq!(
+            db.get_agent_status("web-1").unwrap().unwrap().status,
+            "offline"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_health_polls_follow_reloads() {
+        let config: Config = serde_yaml::from_str(
+            "mode: home\nserver: {}\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\n",
+        )
+        .unwrap();
+        let db = Arc::new(Database::new(":memory:").unwrap());
+        db.migrate().unwrap();
+        let writer = DbWriter::spawn(db.clone(), &config.modules.storage.write_buffer);
+        let state = AppState::with_database(config, db, writer);
+        let token = |name: &str| {
+            let polls = state.health_polls.0.lock().unwrap();
+            polls.get(name).map(|(_, cancel)| cancel.clone())
+        };
+
+        let web = AgentConfig::new("web-1", "127.0.0.1:1", Vec::new());
+        let db_agent = AgentConfig::new("db-1", "127.0.0.1:1", Vec::new());
+        sync_health_polls(&state, &[web.clone(), db_agent.clone()]);
+        let web_poll = token("web-1").unwrap();
+        let db_poll = token("db-1").unwrap();
+
+        // Unchanged agents keep their poll, removed ones stop, changed ones restart
+        let moved = AgentConfig::new("db-1", "127.0.0.1:2", Vec::new());
+        sync_health_polls(&state, &[web, moved]);
+        assert!(!web_poll.is_cancelled());
+        assert!(db_poll.is_cancelled());
+        assert!(!token("db-1").unwrap().is_cancelled());
+
+        sync_health_polls(&state, &[]);
+        assert!(web_poll.is_cancelled());
+        assert!(token("web-1").is_none());
+    }
+}
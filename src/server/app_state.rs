@@ -1,66 +1,242 @@
-use crate::config::{Config, Mode};
+use crate::config::{AgentConfig, Config, Mode, WebhooksConfig};
 use crate::deploy::{DeployExecutor, DeployQueue};
+use crate::http_client::HttpClient;
+use crate::metrics::custom::CustomMetrics;
+use crate::server::agents::HealthPolls;
+use crate::server::auth::{
+    AgentKeyStore, JoinStore, JwtKeys, JwtManager, RefreshStore, ReplayGuard,
+};
+use crate::server::discovery::Discovered;
+use crate::server::middleware::ip_ban::IpBanList;
 use crate::server::middleware::rate_limit::RateLimiter;
-use crate::storage::Database;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use crate::server::oidc::OidcClient;
+use crate::server::tunnel::Tunnels;
+use crate::storage::{Database, DbWriter};
+use crate::updater::Updater;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use tokio::sync::{watch, RwLock};
+use tracing::warn;
+
+/// Settings that SIGHUP replaces without a restart (see `reload`)
+#[derive(Debug, Clone)]
+pub struct LiveConfig {
+    pub isolation_mode: bool,
+    pub allowed_networks: Vec<String>,
+    pub webhooks: WebhooksConfig,
+    /// Configured agents followed by those enrolled with join tokens
+    pub agents: Vec<AgentConfig>,
+}
+
+impl From<&Config> for LiveConfig {
+    fn from(config: &Config) -> Self {
+        Self {
+            isolation_mode: config.server.isolation_mode,
+            allowed_networks: config.server.allowed_networks.clone(),
+            webhooks: config.modules.webhooks.clone(),
+            agents: config.agents.clone(),
+        }
+    }
+}
+
+impl LiveConfig {
+    /// Settings of `config` plus the agents enrolled in `db`; a configured
+    /// agent wins over an enrolled one of the same name
+    pub fn load(config: &Config, db: Option<&Database>) -> Self {
+        let mut live = Self::from(config);
+        let enrolled = match db.map(Database::get_enrolled_agents) {
+            Some(Ok(enrolled)) => enrolled,
+            Some(Err(e)) => {
+                warn!(error = %e, "Failed to load enrolled agents");
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+        for agent in enrolled {
+            if !live.agents.iter().any(|a| a.name == agent.name) {
+                live.agents
+                    .push(AgentConfig::new(&agent.name, &agent.address, agent.tags));
+            }
+        }
+        live
+    }
+}
 
 pub struct AppState {
+    /// Config as loaded at startup; see `live` for the reloadable parts
     pub config: Config,
+    pub live: RwLock<LiveConfig>,
     /// Runtime deployment config. This is refreshed after deploy jobs so
     /// updated deployments.d files can be used by subsequent triggers.
     pub deploy_config: Arc<RwLock<crate::config::DeployConfig>>,
     pub start_time: std::time::Instant,
     pub rate_limiter: RateLimiter,
+    /// Temporarily banned IPs (repeated auth failures / isolation violations)
+    pub ip_bans: IpBanList,
+    /// Recently accepted webhook signatures (replay protection)
+    pub webhook_replay: ReplayGuard,
+    /// Issued refresh tokens (`POST /auth/refresh`)
+    pub refresh_tokens: RefreshStore,
+    /// Issued agent join tokens (`POST /auth/enroll`)
+    pub join_tokens: JoinStore,
+    /// Key this server signs and checks tokens with; rotated by Home in Agent mode
+    pub jwt_keys: JwtKeys,
+    /// Key generation of each agent (Home mode, `auth.agent_keys`)
+    pub agent_keys: Option<AgentKeyStore>,
+    /// SSO login for the monitoring dashboard (`auth.oidc`)
+    pub oidc: Option<OidcClient>,
+    /// Shared HTTP client for outgoing requests
+    pub http: HttpClient,
     /// Database connection (Home mode only)
     pub db: Option<Arc<Database>>,
+    /// Buffered writer for hot-path inserts (Home mode only)
+    pub db_writer: Option<DbWriter>,
     /// Deployment queue
     pub deploy_queue: Option<Arc<DeployQueue>>,
-    /// Deployment executor for direct operations (shutdown)
+    /// Deployment executor for direct operations (compose diff)
     pub deploy_executor: Option<Arc<DeployExecutor>>,
+    /// Set while the deployment worker task is running (see `WorkerGuard`)
+    pub worker_running: AtomicBool,
+    /// Self-update and config sync (`updates.enabled`), set once at startup
+    pub updater: OnceLock<Arc<Updater>>,
+    /// Config file the server was started with, set once at startup
+    pub config_path: OnceLock<PathBuf>,
+    /// Requests for agents on reverse connections (Home mode)
+    pub tunnels: Tunnels,
+    /// Agents found on the LAN over mDNS (Home mode, `server.mdns`)
+    pub discovered: Discovered,
+    /// Latest values of `modules.metrics.custom`
+    pub custom_metrics: CustomMetrics,
+    /// Agent health polls (Home mode), restarted on reload
+    pub health_polls: HealthPolls,
+    /// Flipped once a shutdown signal arrives
+    pub shutdown: watch::Sender<bool>,
+}
+
+fn agent_key_store(config: &Config) -> Option<AgentKeyStore> {
+    (config.mode == Mode::Home && config.auth.agent_keys)
+        .then(|| AgentKeyStore::new(&config.auth.agent_keys_file))
+}
+
+/// Marks the deployment worker as running for as long as it is alive,
+/// including when the worker task panics
+pub struct WorkerGuard(Arc<AppState>);
+
+impl WorkerGuard {
+    pub fn new(state: Arc<AppState>) -> Self {
+        state.worker_running.store(true, Ordering::Relaxed);
+        Self(state)
+    }
+}
+
+impl Drop for WorkerGuard {
+    fn drop(&mut self) {
+        self.0.worker_running.store(false, Ordering::Relaxed);
+    }
 }
 
 impl AppState {
     pub fn new(config: Config) -> Arc<Self> {
+        Arc::new(Self::build(config))
+    }
+
+    pub fn with_database(config: Config, db: Arc<Database>, db_writer: DbWriter) -> Arc<Self> {
+        let mut state = Self::build(config);
+        state.ip_bans =
+            IpBanList::new(&state.config.server.ip_ban).with_store(&db, db_writer.clone());
+        state.live = RwLock::new(LiveConfig::load(&state.config, Some(&db)));
+        state.set_deploy_store(&db, &db_writer);
+        state.db = Some(db);
+        state.db_writer = Some(db_writer);
+        Arc::new(state)
+    }
+
+    /// Agent state whose deploy queue and history live in a local database.
+    /// The database is not exposed as `db`/`db_writer`, so Home-only routes
+    /// stay off.
+    pub fn with_deploy_store(config: Config, db: Arc<Database>, db_writer: DbWriter) -> Arc<Self> {
+        let mut state = Self::build(config);
+        state.set_deploy_store(&db, &db_writer);
+        Arc::new(state)
+    }
+
+    fn set_deploy_store(&mut self, db: &Arc<Database>, db_writer: &DbWriter) {
+        if self.deploy_queue.is_some() {
+            self.deploy_queue = Some(Arc::new(DeployQueue::with_store(
+                100,
+                db.clone(),
+                db_writer.clone(),
+            )));
+        }
+    }
+
+    fn build(config: Config) -> Self {
+        let http = HttpClient::new(&config.http_client);
         let (deploy_queue, deploy_executor) = if config.modules.deploy.enabled {
             (
                 Some(Arc::new(DeployQueue::default())),
-                Some(Arc::new(DeployExecutor::new())),
+                Some(Arc::new(DeployExecutor::with_http_client(
+                    http.clone(),
+                    config.modules.deploy.container_runtime,
+                ))),
             )
         } else {
             (None, None)
         };
 
-        Arc::new(Self {
+        let oidc = config
+            .auth
+            .oidc
+            .clone()
+            .map(|oidc| OidcClient::new(oidc, http.clone()));
+
+        Self {
             deploy_config: Arc::new(RwLock::new(config.modules.deploy.clone())),
+            ip_bans: IpBanList::new(&config.server.ip_ban),
+            rate_limiter: RateLimiter::for_fleet(&config.server.rate_limit, config.fleet_ips()),
+            refresh_tokens: RefreshStore::new(&config.auth.refresh_tokens_file),
+            join_tokens: JoinStore::new(&config.auth.join_tokens_file),
+            jwt_keys: JwtKeys::new(&config.auth.jwt_secret),
+            agent_keys: agent_key_store(&config),
+            live: RwLock::new(LiveConfig::from(&config)),
             config,
             start_time: std::time::Instant::now(),
-            rate_limiter: RateLimiter::default(),
+            webhook_replay: ReplayGuard::default(),
+            oidc,
+            http,
             db: None,
+            db_writer: None,
             deploy_queue,
             deploy_executor,
-        })
+            worker_running: AtomicBool::new(false),
+            updater: OnceLock::new(),
+            config_path: OnceLock::new(),
+            tunnels: Tunnels::default(),
+            discovered: Discovered::default(),
+            custom_metrics: CustomMetrics::default(),
+            health_polls: HealthPolls::default(),
+            shutdown: watch::channel(false).0,
+        }
     }
 
-    pub fn with_database(config: Config, db: Arc<Database>) -> Arc<Self> {
-        let (deploy_queue, deploy_executor) = if config.modules.deploy.enabled {
-            (
-                Some(Arc::new(DeployQueue::default())),
-                Some(Arc::new(DeployExecutor::new())),
-            )
-        } else {
-            (None, None)
-        };
+    /// Signer for tokens Home sends to an agent: the agent's own key with
+    /// `auth.agent_keys`, the shared secret otherwise
+    pub fn agent_signer(&self, agent: &str) -> Result<JwtManager, String> {
+        match &self.agent_keys {
+            Some(store) => Ok(JwtManager::new(
+                &store.signing_key(&self.config.auth.jwt_secret, agent)?,
+            )),
+            None => Ok(self.jwt_keys.signer()),
+        }
+    }
 
-        Arc::new(Self {
-            deploy_config: Arc::new(RwLock::new(config.modules.deploy.clone())),
-            config,
-            start_time: std::time::Instant::now(),
-            rate_limiter: RateLimiter::default(),
-            db: Some(db),
-            deploy_queue,
-            deploy_executor,
-        })
+    /// Writer for deploy history: the database writer on Home, the deploy
+    /// store's on agents
+    pub fn deploy_writer(&self) -> Option<&DbWriter> {
+        self.db_writer
+            .as_ref()
+            .or_else(|| self.deploy_queue.as_ref()?.writer())
     }
 
     pub fn uptime_seconds(&self) -> u64 {
@@ -1,6 +1,6 @@
-use crate::server::auth::JwtManager;
+use crate::server::etag;
 use axum::{
-    http::{header, StatusCode},
+    http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Response},
 };
 use rust_embed::Embed;
@@ -9,26 +9,58 @@ use rust_embed::Embed;
 #[folder = "src/assets/"]
 pub struct Assets;
 
-/// Dashboard session token TTL in hours
-const DASHBOARD_TOKEN_TTL_HOURS: i64 = 1;
+/// Entry page of the monitoring SPA
+const DASHBOARD_PAGE: &str = "dashboard.html";
 
-pub async fn serve_dashboard_with_token(jwt_secret: &str) -> Response {
-    match Assets::get("dashboard.html") {
-        Some(content) => {
-            let html = String::from_utf8_lossy(&content.data);
+/// Session login form (`GET /auth/login`)
+const LOGIN_PAGE: &str = "login.html";
+
+pub async fn serve_dashboard(headers: &HeaderMap) -> Response {
+    serve_asset(DASHBOARD_PAGE, headers).await
+}
+
+/// Serve anything under /monitoring/*path from the embedded assets folder
+///
+/// - Files with an extension (html, js, css, images, ...) are served as-is
+/// - Extensionless paths resolve to `{path}.html`, falling back to the
+///   dashboard entry page so client-side routing works on reload
+///
+/// Pages hold no session data (the API token comes from `/monitoring/token`),
+/// so every asset is cacheable and revalidated with its ETag.
+pub async fn serve_monitoring_path(path: &str, headers: &HeaderMap) -> Response {
+    let path = path.trim_start_matches('/');
+
+    if path.is_empty() {
+        return serve_asset(DASHBOARD_PAGE, headers).await;
+    }
+
+    if path.contains("..") || path.contains('\\') {
+        return (StatusCode::NOT_FOUND, "Asset not found").into_response();
+    }
+
+    let has_extension = std::path::Path::new(path).extension().is_some();
+    if has_extension {
+        return serve_asset(path, headers).await;
+    }
 
-            // Generate session token for dashboard API access
-            let jwt_manager = JwtManager::new(jwt_secret);
-            let token = jwt_manager
-                .generate_token("dashboard", DASHBOARD_TOKEN_TTL_HOURS)
-                .unwrap_or_default();
+    let page = format!("{}.html", path.trim_end_matches('/'));
+    if Assets::get(&page).is_some() {
+        serve_asset(&page, headers).await
+    } else {
+        serve_asset(DASHBOARD_PAGE, headers).await
+    }
+}
 
-            // Inject token into HTML before </head>
-            let token_script = format!(
-                r#"<script>window.INFRACTL_TOKEN="{}";</script></head>"#,
-                token
+/// Serve the login form; `password` and `sso` toggle the password field and SSO button
+pub async fn serve_login_page(password: bool, sso: bool) -> Response {
+    match Assets::get(LOGIN_PAGE) {
+        Some(content) => {
+            let html = String::from_utf8_lossy(&content.data);
+            let options_script = format!(
+                r#"<script>window.INFRACTL_LOGIN={{"password":{},"sso":{}}};</script></head>"#,
+                password, sso
             );
-            let html = html.replace("</head>", &token_script);
+            let html = html.replace("</head>", &options_script);
 
             (
                 StatusCode::OK,
@@ -37,24 +69,74 @@ pub async fn serve_dashboard_with_token(jwt_secret: &str) -> Response {
             )
                 .into_response()
         }
-        None => (StatusCode::NOT_FOUND, "Dashboard not found").into_response(),
+        None => (StatusCode::NOT_FOUND, "Login page not found").into_response(),
     }
 }
 
-#[allow(dead_code)]
-pub async fn serve_asset(path: &str) -> Response {
+pub async fn serve_asset(path: &str, headers: &HeaderMap) -> Response {
     let mime = mime_guess::from_path(path).first_or_octet_stream();
+    let content_type = if mime == mime_guess::mime::TEXT_HTML {
+        "text/html; charset=utf-8"
+    } else {
+        mime.as_ref()
+    };
 
     match Assets::get(path) {
         Some(content) => {
+            let etag = format!("\"{}\"", hex::encode(&content.metadata.sha256_hash()[..16]));
+            if let Some(not_modified) = etag::not_modified(headers, &etag) {
+                return not_modified;
+            }
             let body = content.data.into_owned();
-            (
-                StatusCode::OK,
-                [(header::CONTENT_TYPE, mime.as_ref())],
-                body,
+            etag::tagged(
+                (StatusCode::OK, [(header::CONTENT_TYPE, content_type)], body),
+                &etag,
             )
-                .into_response()
         }
         None => (StatusCode::NOT_FOUND, "Asset not found").into_response(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_monitoring_root_serves_dashboard() {
+        let resp = serve_monitoring_path("", &HeaderMap::new()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_monitoring_subpage_falls_back_to_dashboard() {
+        let resp = serve_monitoring_path("deploys", &HeaderMap::new()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/html; charset=utf-8"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_monitoring_missing_asset_not_found() {
+        let resp = serve_monitoring_path("js/missing.js", &HeaderMap::new()).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_monitoring_traversal_rejected() {
+        let resp = serve_monitoring_path("../config.rs", &HeaderMap::new()).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_dashboard_revalidates_with_etag() {
+        let resp = serve_dashboard(&HeaderMap::new()).await;
+        let etag = resp.headers().get(header::ETAG).unwrap().clone();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag);
+        let resp = serve_dashboard(&headers).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+    }
+}
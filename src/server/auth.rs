@@ -1,7 +1,82 @@
+use axum::http::Method;
 use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use time::{Duration, OffsetDateTime};
 
+mod join;
+mod keys;
+mod refresh;
+
+pub use join::JoinStore;
+pub use keys::{agent_key, AgentKeyStore, JwtKeys};
+pub use refresh::RefreshStore;
+
+/// Route classes a token may be used for (JWT `aud` claim)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Audience {
+    /// Deployment triggers and job status (`/webhook/*`)
+    Webhook,
+    /// REST API (`/api/*`)
+    Api,
+    /// Agent → Home calls (deployment config fetch)
+    Agent,
+    /// Dashboard session: read-only API access
+    Dashboard,
+}
+
+impl Audience {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Audience::Webhook => "webhook",
+            Audience::Api => "api",
+            Audience::Agent => "agent",
+            Audience::Dashboard => "dashboard",
+        }
+    }
+
+    /// Audiences accepted for a request. Only webhook tokens can trigger actions
+    /// under `/webhook/*`; dashboard tokens are limited to reads.
+    pub fn accepted_for(method: &Method, path: &str) -> &'static [Audience] {
+        if let Some(path) = proxied_path(path) {
+            return Self::accepted_for(method, path);
+        }
+        let read_only = method == Method::GET || method == Method::HEAD;
+
+        // gRPC calls are always POST: agents push to Home, Home and operators query agents.
+        // The JSON metrics push and reverse connections are agent-only as well.
+        if path.starts_with("/infractl.v1.Home/")
+            || path == "/api/ingest/metrics"
+            || path.starts_with("/api/tunnel/")
+        {
+            return &[Audience::Agent];
+        }
+        if path.starts_with("/infractl.v1.Agent/") {
+            return &[Audience::Agent, Audience::Api];
+        }
+
+        if path.starts_with("/webhook/") {
+            if read_only {
+                &[Audience::Webhook, Audience::Api, Audience::Dashboard]
+            } else {
+                &[Audience::Webhook]
+            }
+        } else if path.starts_with("/api/deployments/") && read_only {
+            &[Audience::Agent, Audience::Api, Audience::Dashboard]
+        } else if read_only {
+            &[Audience::Api, Audience::Dashboard]
+        } else {
+            &[Audience::Api]
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
     /// Subject (agent name or identifier)
@@ -12,23 +87,172 @@ pub struct Claims {
     pub iat: i64,
     /// Issuer
     pub iss: String,
+    /// Audiences (route classes) this token is valid for
+    #[serde(deserialize_with = "one_or_many")]
+    pub aud: Vec<String>,
+    /// Permissions within the audiences, e.g. `deploy:web-app`; empty means unrestricted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<String>,
+}
+
+/// `aud` may be a single string or an array (RFC 7519)
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(s) => vec![s],
+        OneOrMany::Many(v) => v,
+    })
 }
 
 impl Claims {
-    #[allow(dead_code)]
-    pub fn new(subject: &str, ttl_hours: i64) -> Self {
+    pub fn new(subject: &str, ttl_hours: i64, audiences: &[Audience]) -> Self {
+        Self::with_ttl(subject, Duration::hours(ttl_hours), audiences)
+    }
+
+    pub fn with_ttl(subject: &str, ttl: Duration, audiences: &[Audience]) -> Self {
         let now = OffsetDateTime::now_utc();
         Self {
             sub: subject.to_string(),
-            exp: (now + Duration::hours(ttl_hours)).unix_timestamp(),
+            exp: (now + ttl).unix_timestamp(),
             iat: now.unix_timestamp(),
             iss: "infractl".to_string(),
+            aud: audiences.iter().map(|a| a.as_str().to_string()).collect(),
+            scopes: Vec::new(),
         }
     }
 
     pub fn is_expired(&self) -> bool {
         OffsetDateTime::now_utc().unix_timestamp() > self.exp
     }
+
+    /// Whether the token carries any of the given audiences
+    pub fn has_audience(&self, accepted: &[Audience]) -> bool {
+        accepted
+            .iter()
+            .any(|a| self.aud.iter().any(|aud| aud == a.as_str()))
+    }
+
+    /// Whether the token's scopes allow `required` (see [`required_scope`]).
+    /// Tokens without scopes are limited by their audiences only.
+    pub fn has_scope(&self, required: &str) -> bool {
+        if self.scopes.is_empty() {
+            return true;
+        }
+        let (resource, target) = required.split_once(':').unwrap_or((required, ""));
+        self.scopes.iter().any(|scope| {
+            scope == ADMIN_SCOPE
+                || match scope.split_once(':') {
+                    Some((r, pattern)) if r == resource => glob::Pattern::new(pattern)
+                        .map(|p| p.matches(target))
+                        .unwrap_or(false),
+                    _ => false,
+                }
+        })
+    }
+}
+
+/// Scope that grants every route
+pub const ADMIN_SCOPE: &str = "admin";
+
+/// Scope resources; `deploy` and `control` take a deployment name (or glob),
+/// `exec` a command name
+const SCOPE_RESOURCES: &[(&str, &[&str])] = &[
+    ("deploy", &[]),
+    ("control", &[]),
+    ("exec", &[]),
+    ("jobs", &["read", "write"]),
+    ("metrics", &["read", "write"]),
+    ("history", &["read"]),
+    ("config", &["read", "write"]),
+];
+
+/// Check a scope given to `infractl token --scope`
+pub fn validate_scope(scope: &str) -> Result<(), String> {
+    if scope == ADMIN_SCOPE {
+        return Ok(());
+    }
+    let (resource, target) = scope
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid scope '{}': expected 'resource:target'", scope))?;
+    let (_, actions) = SCOPE_RESOURCES
+        .iter()
+        .find(|(r, _)| *r == resource)
+        .ok_or_else(|| format!("Unknown scope resource '{}'", resource))?;
+
+    if actions.is_empty() {
+        glob::Pattern::new(target)
+            .map_err(|e| format!("Invalid deployment pattern in scope '{}': {}", scope, e))?;
+        if target.is_empty() {
+            return Err(format!("Scope '{}' needs a deployment name", scope));
+        }
+    } else if !actions.contains(&target) {
+        return Err(format!(
+            "Invalid scope '{}': expected {}",
+            scope,
+            actions
+                .iter()
+                .map(|a| format!("{}:{}", resource, a))
+                .collect::<Vec<_>>()
+                .join(" or ")
+        ));
+    }
+    Ok(())
+}
+
+/// Agent path of a request Home proxies: `/api/agents/{name}/proxy/{path}` → `/{path}`
+pub fn proxied_path(path: &str) -> Option<&str> {
+    let (_, rest) = path.strip_prefix("/api/agents/")?.split_once('/')?;
+    rest.strip_prefix("proxy")
+        .filter(|rest| rest.starts_with('/'))
+}
+
+/// Scope a request needs from a token that has scopes
+pub fn required_scope(method: &Method, path: &str) -> String {
+    if let Some(path) = proxied_path(path) {
+        return required_scope(method, path);
+    }
+    let read_only = method == Method::GET || method == Method::HEAD;
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    let scope = match (read_only, segments.as_slice()) {
+        (false, ["webhook", "deploy" | "rollback", name]) => return format!("deploy:{}", name),
+        (false, ["api", "fleet", "deploy", name]) => return format!("deploy:{}", name),
+        (false, ["webhook", "shutdown" | "start" | "restart", name]) => {
+            return format!("control:{}", name)
+        }
+        (false, ["api", "exec", command]) => return format!("exec:{}", command),
+        (false, ["webhook", "cancel" | "approve", _]) => "jobs:write",
+        (false, ["api", "queue", _, "promote"]) => "jobs:write",
+        (false, ["api", "queue", _]) if method == Method::DELETE => "jobs:write",
+        (true, ["webhook", "status", _] | ["webhook", "queue"] | ["api", "pipeline", _]) => {
+            "jobs:read"
+        }
+        (true, ["ws"]) => "jobs:read",
+        (
+            true,
+            ["api", "metrics", ..]
+            | ["api", "agents", ..]
+            | ["api", "containers", ..]
+            | ["api", "fleet", "inventory" | "health"],
+        )
+        | (true, ["metrics"]) => "metrics:read",
+        (true, ["api", "deploys", ..]) => "history:read",
+        (true, ["api", "deployments", ..]) => "config:read",
+        (false, ["api", "deployments", _] | ["api", "agents", _, "deployments"]) => "config:write",
+        (false, ["infractl.v1.Agent", "Health"]) => "metrics:read",
+        (false, ["infractl.v1.Agent", "GetJob"]) => "jobs:read",
+        (false, ["infractl.v1.Home", "PushMetrics"] | ["api", "ingest", "metrics"]) => {
+            "metrics:write"
+        }
+        _ => ADMIN_SCOPE,
+    };
+    scope.to_string()
 }
 
 pub struct JwtManager {
@@ -44,7 +268,9 @@ impl JwtManager {
         let mut validation = Validation::new(Algorithm::HS256);
         validation.set_issuer(&["infractl"]);
         // Require all standard claims
-        validation.set_required_spec_claims(&["exp", "iat", "iss", "sub"]);
+        validation.set_required_spec_claims(&["exp", "iat", "iss", "sub", "aud"]);
+        // Audience depends on the route and is checked by the caller (Claims::has_audience)
+        validation.validate_aud = false;
 
         Self {
             encoding_key: EncodingKey::from_secret(secret.as_bytes()),
@@ -53,12 +279,31 @@ impl JwtManager {
         }
     }
 
-    #[allow(dead_code)]
-    pub fn generate_token(&self, subject: &str, ttl_hours: i64) -> Result<String, JwtError> {
-        let claims = Claims::new(subject, ttl_hours);
+    pub fn generate_token(
+        &self,
+        subject: &str,
+        ttl_hours: i64,
+        audiences: &[Audience],
+    ) -> Result<String, JwtError> {
+        self.generate_scoped_token(subject, ttl_hours, audiences, &[])
+    }
+
+    /// Token restricted to `scopes` within its audiences
+    pub fn generate_scoped_token(
+        &self,
+        subject: &str,
+        ttl_hours: i64,
+        audiences: &[Audience],
+        scopes: &[String],
+    ) -> Result<String, JwtError> {
+        let mut claims = Claims::new(subject, ttl_hours, audiences);
+        claims.scopes = scopes.to_vec();
+        self.encode_claims(&claims)
+    }
+
+    pub fn encode_claims(&self, claims: &Claims) -> Result<String, JwtError> {
         // Explicitly use HS256 algorithm header
-        encode(&Header::new(Algorithm::HS256), &claims, &self.encoding_key)
-            .map_err(JwtError::Encode)
+        encode(&Header::new(Algorithm::HS256), claims, &self.encoding_key).map_err(JwtError::Encode)
     }
 
     pub fn validate_token(&self, token: &str) -> Result<Claims, JwtError> {
@@ -97,6 +342,83 @@ impl std::fmt::Display for JwtError {
 
 impl std::error::Error for JwtError {}
 
+/// Compare two byte strings in constant time (only the length may leak)
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Replace the JSON file at `path` atomically (write a temp file, then rename),
+/// readable by the owner only. Used by the token and key stores.
+fn save_private_json<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<(), String> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let temp = path.with_extension("json.new");
+    let json = serde_json::to_vec_pretty(value).map_err(|e| e.to_string())?;
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&temp)
+        .and_then(|mut file| file.write_all(&json).and_then(|()| file.sync_all()))
+        .and_then(|()| fs::rename(&temp, path))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Hold an exclusive `flock` on `<path>.lock` while a store file is read and
+/// replaced, so the server and the CLI never overwrite each other's changes.
+/// The lock is released when the returned file is dropped.
+fn lock_store(path: &Path) -> Result<fs::File, String> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let lock_path = path.with_extension("json.lock");
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .mode(0o600)
+        .open(&lock_path)
+        .and_then(|file| file.lock().map(|()| file))
+        .map_err(|e| format!("Failed to lock {}: {}", lock_path.display(), e))
+}
+
+/// Upper bound on remembered webhook deliveries
+const MAX_TRACKED_DELIVERIES: usize = 10_000;
+
+/// Remembers accepted webhook signatures so that a captured request cannot be replayed
+#[derive(Clone, Default)]
+pub struct ReplayGuard {
+    seen: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl ReplayGuard {
+    /// Record a replay key. Returns false if it was already seen within `window`.
+    pub fn check_and_record(&self, id: &str, window: std::time::Duration) -> bool {
+        let now = Instant::now();
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+
+        seen.retain(|_, &mut at| now.duration_since(at) < window);
+        if seen.contains_key(id) {
+            return false;
+        }
+
+        if seen.len() >= MAX_TRACKED_DELIVERIES {
+            if let Some(oldest) = seen
+                .iter()
+                .min_by_key(|(_, &at)| at)
+                .map(|(id, _)| id.clone())
+            {
+                seen.remove(&oldest);
+            }
+        }
+
+        seen.insert(id.to_string(), now);
+        true
+    }
+}
+
 /// Parse TTL string like "24h", "7days", "1week" to hours using humantime
 /// Returns error on invalid format instead of silent fallback
 pub fn parse_ttl_to_hours(ttl: &str) -> i64 {
@@ -135,9 +457,186 @@ mod tests {
     #[test]
     fn test_jwt_roundtrip() {
         let manager = JwtManager::new("test_secret");
-        let token = manager.generate_token("agent-01", 24).unwrap();
+        let token = manager
+            .generate_token("agent-01", 24, &[Audience::Agent])
+            .unwrap();
         let claims = manager.validate_token(&token).unwrap();
         assert_eq!(claims.sub, "agent-01");
+        assert_eq!(claims.aud, vec!["agent"]);
+    }
+
+    #[test]
+    fn test_token_without_audience_rejected() {
+        #[derive(Serialize)]
+        struct LegacyClaims {
+            sub: String,
+            exp: i64,
+            iat: i64,
+            iss: String,
+        }
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let legacy = LegacyClaims {
+            sub: "cli".to_string(),
+            exp: now + 3600,
+            iat: now,
+            iss: "infractl".to_string(),
+        };
+        let token = encode(
+            &Header::new(Algorithm::HS256),
+            &legacy,
+            &EncodingKey::from_secret(b"test_secret"),
+        )
+        .unwrap();
+
+        assert!(JwtManager::new("test_secret")
+            .validate_token(&token)
+            .is_err());
+    }
+
+    #[test]
+    fn test_route_audiences() {
+        let dashboard = Claims::new("dashboard", 1, &[Audience::Dashboard]);
+        let webhook = Claims::new("ci", 1, &[Audience::Webhook]);
+
+        let deploy = Audience::accepted_for(&Method::POST, "/webhook/deploy/app");
+        assert!(!dashboard.has_audience(deploy));
+        assert!(webhook.has_audience(deploy));
+
+        let queue = Audience::accepted_for(&Method::GET, "/webhook/queue");
+        assert!(dashboard.has_audience(queue));
+
+        let metrics = Audience::accepted_for(&Method::GET, "/api/metrics");
+        assert!(dashboard.has_audience(metrics));
+        assert!(!webhook.has_audience(metrics));
+
+        let unban = Audience::accepted_for(&Method::DELETE, "/api/bans/10.0.0.1");
+        assert!(!dashboard.has_audience(unban));
+
+        let agent = Claims::new("agent", 1, &[Audience::Agent]);
+        assert!(agent.has_audience(Audience::accepted_for(&Method::GET, "/api/deployments/app")));
+        assert!(!agent.has_audience(metrics));
+
+        let push = Audience::accepted_for(&Method::POST, "/infractl.v1.Home/PushMetrics");
+        assert!(agent.has_audience(push));
+        assert!(!webhook.has_audience(push));
+        assert_eq!(
+            required_scope(&Method::POST, "/infractl.v1.Home/PushMetrics"),
+            "metrics:write"
+        );
+        let ingest = Audience::accepted_for(&Method::POST, "/api/ingest/metrics");
+        assert!(agent.has_audience(ingest));
+        assert!(!webhook.has_audience(ingest));
+        assert_eq!(
+            required_scope(&Method::POST, "/api/ingest/metrics"),
+            "metrics:write"
+        );
+
+        // Proxied requests are checked as if sent to the agent directly
+        let proxied =
+            Audience::accepted_for(&Method::POST, "/api/agents/web-1/proxy/webhook/deploy/app");
+        assert!(webhook.has_audience(proxied));
+        assert!(!dashboard.has_audience(proxied));
+        assert_eq!(
+            required_scope(&Method::POST, "/api/agents/web-1/proxy/webhook/deploy/app"),
+            "deploy:app"
+        );
+        assert_eq!(
+            required_scope(&Method::GET, "/api/agents/web-1/proxy/api/metrics"),
+            "metrics:read"
+        );
+        assert_eq!(proxied_path("/api/agents/web-1/deployments"), None);
+    }
+
+    #[test]
+    fn test_scopes() {
+        let mut ci = Claims::new("ci", 1, &[Audience::Webhook, Audience::Api]);
+        ci.scopes = vec!["deploy:web-*".to_string(), "jobs:read".to_string()];
+
+        let deploy = required_scope(&Method::POST, "/webhook/deploy/web-app");
+        assert_eq!(deploy, "deploy:web-app");
+        assert!(ci.has_scope(&deploy));
+        assert!(!ci.has_scope(&required_scope(&Method::POST, "/webhook/deploy/db")));
+        assert!(!ci.has_scope(&required_scope(&Method::POST, "/webhook/shutdown/web-app")));
+        assert!(ci.has_scope(&required_scope(&Method::GET, "/webhook/status/job-1")));
+        assert!(!ci.has_scope(&required_scope(&Method::GET, "/api/metrics")));
+        assert!(!ci.has_scope(&required_scope(&Method::DELETE, "/api/bans/10.0.0.1")));
+        assert_eq!(
+            required_scope(&Method::DELETE, "/api/queue/job-1"),
+            "jobs:write"
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/api/queue/job-1/promote"),
+            "jobs:write"
+        );
+        // Retrying runs a deployment again and clearing drops everyone's jobs
+        assert_eq!(
+            required_scope(&Method::POST, "/api/queue/job-1/retry"),
+            ADMIN_SCOPE
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/api/queue/clear"),
+            ADMIN_SCOPE
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/api/exec/docker-prune"),
+            "exec:docker-prune"
+        );
+        assert_eq!(
+            required_scope(&Method::PUT, "/api/deployments/web-app"),
+            "config:write"
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/api/agents/web-1/deployments"),
+            "config:write"
+        );
+        // The command name is in the body: fleet-wide exec is admin only
+        assert_eq!(
+            required_scope(&Method::POST, "/api/fleet/exec"),
+            ADMIN_SCOPE
+        );
+
+        ci.scopes = vec![ADMIN_SCOPE.to_string()];
+        assert!(ci.has_scope(&required_scope(&Method::DELETE, "/api/bans/10.0.0.1")));
+
+        // Tokens without scopes keep working
+        ci.scopes.clear();
+        assert!(ci.has_scope("metrics:read"));
+    }
+
+    #[test]
+    fn test_validate_scope() {
+        assert!(validate_scope("admin").is_ok());
+        assert!(validate_scope("deploy:web-app").is_ok());
+        assert!(validate_scope("control:*").is_ok());
+        assert!(validate_scope("exec:docker-*").is_ok());
+        assert!(validate_scope("metrics:read").is_ok());
+        assert!(validate_scope("metrics:write").is_ok());
+        assert!(validate_scope("config:write").is_ok());
+        assert!(validate_scope("history:write").is_err());
+        assert!(validate_scope("deploy:").is_err());
+        assert!(validate_scope("secrets:read").is_err());
+        assert!(validate_scope("deploy").is_err());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+
+    #[test]
+    fn test_replay_guard() {
+        let guard = ReplayGuard::default();
+        let window = std::time::Duration::from_secs(60);
+        assert!(guard.check_and_record("delivery-1", window));
+        assert!(!guard.check_and_record("delivery-1", window));
+        assert!(guard.check_and_record("delivery-2", window));
+
+        // Expired entries are forgotten
+        assert!(guard.check_and_record("delivery-3", std::time::Duration::ZERO));
+        assert!(guard.check_and_record("delivery-3", std::time::Duration::ZERO));
     }
 
     #[test]
@@ -0,0 +1,144 @@
+use super::constant_time_eq;
+use super::refresh::hash;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+/// Prefix that tells join tokens apart from JWTs and refresh tokens
+const TOKEN_PREFIX: &str = "jt_";
+
+/// A join token for one new agent; only its SHA256 is kept
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JoinGrant {
+    pub id: String,
+    pub token_sha256: String,
+    /// Name given to the agent; without one the agent's hostname is used
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+/// Join tokens stored in a JSON file shared by the server and `infractl agent join-token`.
+/// A token enrolls a single agent and is spent on use.
+pub struct JoinStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl JoinStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Issue a join token valid for `ttl`; returns the token (shown once)
+    pub fn issue(
+        &self,
+        name: Option<&str>,
+        tags: &[String],
+        ttl: Duration,
+    ) -> Result<String, String> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut grants = self.load()?;
+
+        let token = format!(
+            "{}{}{}",
+            TOKEN_PREFIX,
+            Uuid::new_v4().simple(),
+            Uuid::new_v4().simple()
+        );
+        let now = OffsetDateTime::now_utc();
+        grants.push(JoinGrant {
+            id: Uuid::new_v4().to_string(),
+            token_sha256: hash(&token),
+            name: name.map(String::from),
+            tags: tags.to_vec(),
+            created_at: now.unix_timestamp(),
+            expires_at: (now + ttl).unix_timestamp(),
+        });
+        self.save(&grants)?;
+        Ok(token)
+    }
+
+    /// The grant of an unspent, unexpired join token, leaving it unspent
+    pub fn peek(&self, token: &str) -> Result<Option<JoinGrant>, String> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let grants = self.load()?;
+        Ok(find(&grants, token).map(|pos| grants[pos].clone()))
+    }
+
+    /// Spend a join token. `None` if it is unknown, already used or expired.
+    pub fn redeem(&self, token: &str) -> Result<Option<JoinGrant>, String> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut grants = self.load()?;
+
+        let Some(pos) = find(&grants, token) else {
+            return Ok(None);
+        };
+        let grant = grants.remove(pos);
+        self.save(&grants)?;
+        Ok(Some(grant))
+    }
+
+    /// Unexpired grants (a missing file means none)
+    fn load(&self) -> Result<Vec<JoinGrant>, String> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("Failed to read {}: {}", self.path.display(), e)),
+        };
+        let mut grants: Vec<JoinGrant> = serde_json::from_str(&content)
+            .map_err(|e| format!("Invalid join token store {}: {}", self.path.display(), e))?;
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        grants.retain(|g| g.expires_at > now);
+        Ok(grants)
+    }
+
+    /// Replace the file atomically, readable by the owner only
+    fn save(&self, grants: &[JoinGrant]) -> Result<(), String> {
+        super::save_private_json(&self.path, grants)
+    }
+}
+
+fn find(grants: &[JoinGrant], token: &str) -> Option<usize> {
+    if !token.starts_with(TOKEN_PREFIX) {
+        return None;
+    }
+    let hashed = hash(token);
+    grants
+        .iter()
+        .position(|g| constant_time_eq(g.token_sha256.as_bytes(), hashed.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_token_is_single_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JoinStore::new(dir.path().join("join_tokens.json"));
+
+        let token = store
+            .issue(Some("web-3"), &["web".to_string()], Duration::hours(1))
+            .unwrap();
+        assert!(store.peek(&token).unwrap().is_some());
+        let grant = store.redeem(&token).unwrap().unwrap();
+        assert_eq!(grant.name.as_deref(), Some("web-3"));
+        assert_eq!(grant.tags, ["web"]);
+        assert!(store.redeem(&token).unwrap().is_none());
+        assert!(store.peek(&token).unwrap().is_none());
+
+        let expired = store.issue(None, &[], Duration::seconds(-1)).unwrap();
+        assert!(store.redeem(&expired).unwrap().is_none());
+    }
+}
@@ -0,0 +1,244 @@
+use super::{Audience, Claims, JwtError, JwtManager};
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::Instant;
+use time::{Duration, OffsetDateTime};
+use tracing::error;
+
+/// Signing key of one agent: `HMAC-SHA256(jwt_secret, "agent:{name}:{generation}")`.
+/// Home derives it on demand, the agent only ever holds its own.
+pub fn agent_key(master: &str, agent: &str, generation: u32) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(master.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("agent:{}:{}", agent, generation).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Key generation of an agent; the previous one stays valid until `previous_expires_at`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AgentKeyState {
+    pub generation: u32,
+    #[serde(default)]
+    pub previous_expires_at: Option<i64>,
+}
+
+/// Per-agent key generations stored in a JSON file (Home mode, `auth.agent_keys`).
+/// Agents without an entry are on generation 0. The file is read once, when
+/// the store is opened, and kept in memory, so checking a token never touches
+/// the disk; `rotate` updates both.
+pub struct AgentKeyStore {
+    path: PathBuf,
+    /// A file that could not be read fails every lookup, rather than putting
+    /// all agents back on generation 0
+    states: RwLock<Result<HashMap<String, AgentKeyState>, String>>,
+    lock: Mutex<()>,
+}
+
+impl AgentKeyStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let states = load(&path);
+        if let Err(e) = &states {
+            error!(error = %e, "Agent keys unavailable, agent tokens will be rejected");
+        }
+        Self {
+            path,
+            states: RwLock::new(states),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn state(&self, agent: &str) -> Result<AgentKeyState, String> {
+        let states = self.states.read().unwrap_or_else(|e| e.into_inner());
+        let states = states.as_ref().map_err(Clone::clone)?;
+        Ok(states.get(agent).cloned().unwrap_or_default())
+    }
+
+    /// Key Home signs the agent's tokens with
+    pub fn signing_key(&self, master: &str, agent: &str) -> Result<String, String> {
+        Ok(agent_key(master, agent, self.state(agent)?.generation))
+    }
+
+    /// Check a token an agent signed with its own key. Only `agent` audience
+    /// tokens for the agent's own name are accepted, so an agent key cannot
+    /// be used to act as anyone else.
+    pub fn validate(&self, master: &str, token: &str) -> Result<Claims, JwtError> {
+        let subject = peek_subject(token)?;
+        let state = self.state(&subject).map_err(|_| JwtError::Invalid)?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        let mut generations = vec![state.generation];
+        if state.generation > 0 && state.previous_expires_at.is_some_and(|at| at > now) {
+            generations.push(state.generation - 1);
+        }
+        let mut result = Err(JwtError::Invalid);
+        for generation in generations {
+            result =
+                JwtManager::new(&agent_key(master, &subject, generation)).validate_token(token);
+            if result.is_ok() {
+                break;
+            }
+        }
+
+        let claims = result?;
+        if claims.sub != subject || claims.aud != [Audience::Agent.as_str()] {
+            return Err(JwtError::Invalid);
+        }
+        Ok(claims)
+    }
+
+    /// Move the agent to its next key; the current one stays valid for `overlap`
+    pub fn rotate(&self, agent: &str, overlap: Duration) -> Result<u32, String> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut states = self
+            .states
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()?;
+        let state = states.entry(agent.to_string()).or_default();
+        state.generation += 1;
+        state.previous_expires_at = Some((OffsetDateTime::now_utc() + overlap).unix_timestamp());
+        let generation = state.generation;
+        // Saved first, so memory never runs ahead of the file
+        super::save_private_json(&self.path, &states)?;
+        *self.states.write().unwrap_or_else(|e| e.into_inner()) = Ok(states);
+        Ok(generation)
+    }
+}
+
+/// A missing file means every agent is on generation 0
+fn load(path: &Path) -> Result<HashMap<String, AgentKeyState>, String> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| format!("Invalid agent key store {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+/// Subject of a token, before its signature is checked
+fn peek_subject(token: &str) -> Result<String, JwtError> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.insecure_disable_signature_validation();
+    validation.validate_aud = false;
+    validation.validate_exp = false;
+    validation.required_spec_claims.clear();
+    decode::<Claims>(token, &DecodingKey::from_secret(&[]), &validation)
+        .map(|data| data.claims.sub)
+        .map_err(JwtError::Decode)
+}
+
+/// The key this server signs and checks tokens with. Agents replace it when
+/// Home rotates their key; the previous one is still accepted for a while.
+pub struct JwtKeys {
+    current: RwLock<String>,
+    previous: RwLock<Option<(String, Instant)>>,
+}
+
+impl JwtKeys {
+    pub fn new(secret: &str) -> Self {
+        Self {
+            current: RwLock::new(secret.to_string()),
+            previous: RwLock::new(None),
+        }
+    }
+
+    pub fn signer(&self) -> JwtManager {
+        JwtManager::new(&self.current.read().unwrap())
+    }
+
+    /// Check a token against the current key, then the previous one
+    pub fn validate(&self, token: &str) -> Result<Claims, JwtError> {
+        let result = self.signer().validate_token(token);
+        if result.is_ok() {
+            return result;
+        }
+        match &*self.previous.read().unwrap() {
+            Some((previous, until)) if Instant::now() < *until => {
+                JwtManager::new(previous).validate_token(token)
+            }
+            _ => result,
+        }
+    }
+
+    /// Switch to `secret`, accepting the current key for `overlap`
+    pub fn rotate(&self, secret: &str, overlap: std::time::Duration) {
+        let mut current = self.current.write().unwrap();
+        let previous = std::mem::replace(&mut *current, secret.to_string());
+        *self.previous.write().unwrap() = Some((previous, Instant::now() + overlap));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER: &str = "0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn test_agent_key_validation_and_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = AgentKeyStore::new(dir.path().join("agent_keys.json"));
+        assert_ne!(agent_key(MASTER, "web-1", 0), agent_key(MASTER, "web-2", 0));
+        assert_ne!(agent_key(MASTER, "web-1", 0), agent_key(MASTER, "web-1", 1));
+
+        let web_1 = JwtManager::new(&agent_key(MASTER, "web-1", 0));
+        let push = web_1
+            .generate_token("web-1", 1, &[Audience::Agent])
+            .unwrap();
+        assert_eq!(store.validate(MASTER, &push).unwrap().sub, "web-1");
+
+        // web-1's key can neither impersonate web-2 nor reach the API
+        let forged = web_1
+            .generate_token("web-2", 1, &[Audience::Agent])
+            .unwrap();
+        assert!(store.validate(MASTER, &forged).is_err());
+        let api = web_1.generate_token("web-1", 1, &[Audience::Api]).unwrap();
+        assert!(store.validate(MASTER, &api).is_err());
+
+        // The old key works during the overlap, the new one right away
+        assert_eq!(store.rotate("web-1", Duration::minutes(10)).unwrap(), 1);
+        assert!(store.validate(MASTER, &push).is_ok());
+        let rotated = JwtManager::new(&store.signing_key(MASTER, "web-1").unwrap())
+            .generate_token("web-1", 1, &[Audience::Agent])
+            .unwrap();
+        assert!(store.validate(MASTER, &rotated).is_ok());
+
+        // Kept in memory: tokens are checked without reading the file
+        let path = dir.path().join("agent_keys.json");
+        std::fs::write(&path, "not json").unwrap();
+        assert!(store.validate(MASTER, &rotated).is_ok());
+        assert!(AgentKeyStore::new(&path).state("web-1").is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        store.rotate("web-1", Duration::ZERO).unwrap();
+        assert!(store.validate(MASTER, &rotated).is_err());
+        assert_eq!(
+            AgentKeyStore::new(&path).state("web-1").unwrap().generation,
+            2
+        );
+    }
+
+    #[test]
+    fn test_jwt_keys_overlap() {
+        let keys = JwtKeys::new(MASTER);
+        let old = keys
+            .signer()
+            .generate_token("home", 1, &[Audience::Api])
+            .unwrap();
+        keys.rotate(
+            &agent_key(MASTER, "web-1", 1),
+            std::time::Duration::from_secs(60),
+        );
+        assert!(keys.validate(&old).is_ok());
+
+        keys.rotate(&agent_key(MASTER, "web-1", 2), std::time::Duration::ZERO);
+        assert!(keys.validate(&old).is_err());
+    }
+}
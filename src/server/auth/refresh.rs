@@ -0,0 +1,263 @@
+use super::constant_time_eq;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use time::{Duration, OffsetDateTime};
+use tracing::warn;
+use uuid::Uuid;
+
+/// Prefix that tells refresh tokens apart from JWTs
+const TOKEN_PREFIX: &str = "rt_";
+
+/// A refresh token issued to a subject; only its SHA256 is kept
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshGrant {
+    pub id: String,
+    pub token_sha256: String,
+    pub subject: String,
+    pub audiences: Vec<String>,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    pub created_at: i64,
+    pub expires_at: i64,
+    /// Id of the first grant of the rotation chain this grant belongs to
+    /// (empty for grants stored before families: the grant's own id)
+    #[serde(default)]
+    pub family: String,
+    /// When the token was redeemed; spent grants are kept until they expire
+    /// to detect reuse
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub used_at: Option<i64>,
+}
+
+impl RefreshGrant {
+    fn family(&self) -> &str {
+        if self.family.is_empty() {
+            &self.id
+        } else {
+            &self.family
+        }
+    }
+}
+
+/// Refresh tokens stored in a JSON file shared by the server and `infractl token --refresh`.
+/// Every redeemed token is replaced by a new one of the same family. Presenting
+/// a spent token again means it was copied: the whole family is revoked, so
+/// neither the thief nor the client can refresh any further.
+pub struct RefreshStore {
+    path: PathBuf,
+}
+
+impl RefreshStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Issue a refresh token valid for `ttl`; returns the token (shown once)
+    pub fn issue(
+        &self,
+        subject: &str,
+        audiences: &[String],
+        scopes: &[String],
+        ttl: Duration,
+    ) -> Result<String, String> {
+        let _lock = super::lock_store(&self.path)?;
+        let mut grants = self.load()?;
+
+        let now = OffsetDateTime::now_utc();
+        let (token, grant) = new_grant(
+            subject,
+            audiences,
+            scopes,
+            now.unix_timestamp(),
+            (now + ttl).unix_timestamp(),
+            None,
+        );
+        grants.push(grant);
+        self.save(&grants)?;
+        Ok(token)
+    }
+
+    /// Exchange a refresh token for its grant and a replacement token with the
+    /// same expiry. `None` if the token is unknown, already used or expired;
+    /// an already used token also revokes its family.
+    pub fn redeem(&self, token: &str) -> Result<Option<(RefreshGrant, String)>, String> {
+        let _lock = super::lock_store(&self.path)?;
+        let mut grants = self.load()?;
+
+        let Some(pos) = find(&grants, token) else {
+            return Ok(None);
+        };
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let old = &mut grants[pos];
+        if old.used_at.is_some() {
+            let family = old.family().to_string();
+            warn!(
+                target: "suspicious",
+                subject = %old.subject,
+                family = %family,
+                "Spent refresh token reused, revoking its family"
+            );
+            grants.retain(|g| g.family() != family);
+            self.save(&grants)?;
+            return Ok(None);
+        }
+
+        old.used_at = Some(now);
+        let old = old.clone();
+        let (replacement, grant) = new_grant(
+            &old.subject,
+            &old.audiences,
+            &old.scopes,
+            now,
+            old.expires_at,
+            Some(old.family()),
+        );
+        grants.push(grant.clone());
+        self.save(&grants)?;
+        Ok(Some((grant, replacement)))
+    }
+
+    /// Revoke a refresh token and every token rotated from or into it.
+    /// Returns false if it was not known.
+    pub fn revoke(&self, token: &str) -> Result<bool, String> {
+        let _lock = super::lock_store(&self.path)?;
+        let mut grants = self.load()?;
+
+        match find(&grants, token) {
+            Some(pos) => {
+                let family = grants[pos].family().to_string();
+                grants.retain(|g| g.family() != family);
+                self.save(&grants)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Unexpired grants (a missing file means none)
+    fn load(&self) -> Result<Vec<RefreshGrant>, String> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("Failed to read {}: {}", self.path.display(), e)),
+        };
+        let mut grants: Vec<RefreshGrant> = serde_json::from_str(&content)
+            .map_err(|e| format!("Invalid refresh token store {}: {}", self.path.display(), e))?;
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        grants.retain(|g| g.expires_at > now);
+        Ok(grants)
+    }
+
+    /// Replace the file atomically, readable by the owner only
+    fn save(&self, grants: &[RefreshGrant]) -> Result<(), String> {
+        super::save_private_json(&self.path, grants)
+    }
+}
+
+fn new_grant(
+    subject: &str,
+    audiences: &[String],
+    scopes: &[String],
+    created_at: i64,
+    expires_at: i64,
+    family: Option<&str>,
+) -> (String, RefreshGrant) {
+    let token = format!(
+        "{}{}{}",
+        TOKEN_PREFIX,
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    );
+    let id = Uuid::new_v4().to_string();
+    let grant = RefreshGrant {
+        family: family.unwrap_or(&id).to_string(),
+        id,
+        token_sha256: hash(&token),
+        subject: subject.to_string(),
+        audiences: audiences.to_vec(),
+        scopes: scopes.to_vec(),
+        created_at,
+        expires_at,
+        used_at: None,
+    };
+    (token, grant)
+}
+
+fn find(grants: &[RefreshGrant], token: &str) -> Option<usize> {
+    if !token.starts_with(TOKEN_PREFIX) {
+        return None;
+    }
+    let hashed = hash(token);
+    grants
+        .iter()
+        .position(|g| constant_time_eq(g.token_sha256.as_bytes(), hashed.as_bytes()))
+}
+
+pub(super) fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refresh_token_rotates() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RefreshStore::new(dir.path().join("refresh_tokens.json"));
+
+        let token = store
+            .issue(
+                "ci",
+                &["webhook".to_string()],
+                &["deploy:web".to_string()],
+                Duration::days(1),
+            )
+            .unwrap();
+
+        let (grant, replacement) = store.redeem(&token).unwrap().unwrap();
+        assert_eq!(grant.subject, "ci");
+        assert_eq!(grant.scopes, ["deploy:web"]);
+
+        // The redeemed token is spent, its replacement works once
+        let (_, next) = store.redeem(&replacement).unwrap().unwrap();
+        assert!(store.revoke(&next).unwrap());
+        assert!(store.redeem(&next).unwrap().is_none());
+        assert!(!store.revoke(&token).unwrap());
+    }
+
+    #[test]
+    fn test_reused_refresh_token_revokes_its_family() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RefreshStore::new(dir.path().join("refresh_tokens.json"));
+        let issue = || {
+            store
+                .issue("ci", &["webhook".to_string()], &[], Duration::days(1))
+                .unwrap()
+        };
+        let stolen = issue();
+        let other = issue();
+
+        // The thief refreshes first, then the client presents the spent token
+        let (_, thief) = store.redeem(&stolen).unwrap().unwrap();
+        assert!(store.redeem(&stolen).unwrap().is_none());
+        assert!(store.redeem(&thief).unwrap().is_none());
+
+        // Other families are not affected
+        assert!(store.redeem(&other).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_expired_refresh_token_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RefreshStore::new(dir.path().join("refresh_tokens.json"));
+
+        let token = store
+            .issue("ci", &["api".to_string()], &[], Duration::seconds(-1))
+            .unwrap();
+        assert!(store.redeem(&token).unwrap().is_none());
+    }
+}
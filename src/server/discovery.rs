@@ -0,0 +1,206 @@
+use crate::server::{push, AppState};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use time::OffsetDateTime;
+use tracing::{debug, info, warn};
+
+/// mDNS service type agents advertise
+pub const SERVICE_TYPE: &str = "_infractl._tcp.local.";
+
+/// An agent seen on the LAN
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveredAgent {
+    pub name: String,
+    /// Address to put into `agents[].address`
+    pub address: String,
+    pub hostname: String,
+    pub version: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub last_seen: OffsetDateTime,
+}
+
+/// Agents found by mDNS browsing, keyed by service instance (Home mode)
+#[derive(Default)]
+pub struct Discovered {
+    agents: Mutex<HashMap<String, DiscoveredAgent>>,
+}
+
+impl Discovered {
+    pub fn list(&self) -> Vec<DiscoveredAgent> {
+        let mut agents: Vec<_> = self.agents.lock().unwrap().values().cloned().collect();
+        agents.sort_by(|a, b| a.name.cmp(&b.name));
+        agents
+    }
+
+    fn insert(&self, fullname: &str, agent: DiscoveredAgent) {
+        self.agents
+            .lock()
+            .unwrap()
+            .insert(fullname.to_string(), agent);
+    }
+
+    fn remove(&self, fullname: &str) {
+        self.agents.lock().unwrap().remove(fullname);
+    }
+}
+
+/// Advertise this agent as `{agent_name}._infractl._tcp.local.` until
+/// shutdown starts (agent mode, `server.mdns`)
+pub fn advertise(state: &Arc<AppState>) {
+    let name = push::agent_name(&state.config);
+    let host = sysinfo::System::host_name().unwrap_or_else(|| name.clone());
+    let tls = state.config.server.tls.is_some().to_string();
+    let properties = [
+        ("name", name.as_str()),
+        ("version", env!("CARGO_PKG_VERSION")),
+        ("tls", tls.as_str()),
+    ];
+
+    let registered = ServiceInfo::new(
+        SERVICE_TYPE,
+        &name,
+        &format!("{}.local.", host),
+        (),
+        state.config.server.port,
+        &properties[..],
+    )
+    .and_then(|service| {
+        let service = service.enable_addr_auto();
+        let fullname = service.get_fullname().to_string();
+        let daemon = ServiceDaemon::new()?;
+        daemon.register(service)?;
+        Ok((daemon, fullname))
+    });
+    let (daemon, fullname) = match registered {
+        Ok(registered) => registered,
+        Err(e) => {
+            warn!(error = %e, "Failed to advertise agent over mDNS");
+            return;
+        }
+    };
+    info!(name = %name, service = SERVICE_TYPE, "Advertising agent over mDNS");
+
+    let mut shutdown = state.shutdown.subscribe();
+    tokio::spawn(async move {
+        let _ = shutdown.wait_for(|stopping| *stopping).await;
+        // Lets Home drop the agent right away instead of waiting for the TTL
+        let _ = daemon.unregister(&fullname);
+        let _ = daemon.shutdown();
+    });
+}
+
+/// Browse for advertising agents and keep `state.discovered` up to date
+/// until shutdown starts (Home mode, `server.mdns`)
+pub fn discover(state: &Arc<AppState>) {
+    let daemon = match ServiceDaemon::new() {
+        Ok(daemon) => daemon,
+        Err(e) => {
+            warn!(error = %e, "Failed to start mDNS discovery");
+            return;
+        }
+    };
+    let events = match daemon.browse(SERVICE_TYPE) {
+        Ok(events) => events,
+        Err(e) => {
+            warn!(error = %e, "Failed to start mDNS discovery");
+            return;
+        }
+    };
+    info!(service = SERVICE_TYPE, "Discovering agents over mDNS");
+
+    let state = state.clone();
+    // The mDNS receiver is blocking; check for shutdown between events
+    tokio::task::spawn_blocking(move || {
+        while !*state.shutdown.borrow() {
+            let Ok(event) = events.recv_timeout(Duration::from_secs(1)) else {
+                continue;
+            };
+            match event {
+                ServiceEvent::ServiceResolved(info) => {
+                    let Some(agent) = discovered_agent(&info) else {
+                        continue;
+                    };
+                    debug!(name = %agent.name, address = %agent.address, "Agent discovered");
+                    state.discovered.insert(info.get_fullname(), agent);
+                }
+                ServiceEvent::ServiceRemoved(_, fullname) => state.discovered.remove(&fullname),
+                _ => {}
+            }
+        }
+        let _ = daemon.shutdown();
+    });
+}
+
+fn discovered_agent(info: &ServiceInfo) -> Option<DiscoveredAgent> {
+    // Prefer IPv4: it is what `agents[].address` usually holds
+    let ip = info
+        .get_addresses()
+        .iter()
+        .min_by_key(|ip| ip.is_ipv6())
+        .copied()?;
+    let host = match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => format!("[{}]", ip),
+    };
+    let scheme = match info.get_property_val_str("tls") {
+        Some("true") => "https://",
+        _ => "",
+    };
+    let name = info
+        .get_property_val_str("name")
+        .map(String::from)
+        .unwrap_or_else(|| {
+            info.get_fullname()
+                .trim_end_matches(SERVICE_TYPE)
+                .trim_end_matches('.')
+                .to_string()
+        });
+
+    Some(DiscoveredAgent {
+        name,
+        address: format!("{}{}:{}", scheme, host, info.get_port()),
+        hostname: info.get_hostname().trim_end_matches('.').to_string(),
+        version: info.get_property_val_str("version").map(String::from),
+        last_seen: OffsetDateTime::now_utc(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discovered_agent_address() {
+        let info = ServiceInfo::new(
+            SERVICE_TYPE,
+            "web-3",
+            "pi.local.",
+            "192.168.1.20,fe80::1",
+            8111,
+            &[("name", "web-3"), ("version", "0.1.21"), ("tls", "false")][..],
+        )
+        .unwrap();
+        let agent = discovered_agent(&info).unwrap();
+        assert_eq!(agent.name, "web-3");
+        assert_eq!(agent.address, "192.168.1.20:8111");
+        assert_eq!(agent.hostname, "pi.local");
+        assert_eq!(agent.version.as_deref(), Some("0.1.21"));
+
+        let info = ServiceInfo::new(
+            SERVICE_TYPE,
+            "nas",
+            "nas.local.",
+            "192.168.1.30",
+            8443,
+            &[("tls", "true")][..],
+        )
+        .unwrap();
+        let agent = discovered_agent(&info).unwrap();
+        assert_eq!(agent.name, "nas");
+        assert_eq!(agent.address, "https://192.168.1.30:8443");
+    }
+}
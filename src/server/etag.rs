@@ -0,0 +1,62 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+
+/// Strong ETag of a response body
+pub fn of_bytes(data: &[u8]) -> String {
+    let digest = Sha256::digest(data);
+    format!("\"{}\"", hex::encode(&digest[..16]))
+}
+
+/// Whether `If-None-Match` already names `etag` (or `*`)
+pub fn matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == etag || tag == "*")
+}
+
+/// `304 Not Modified` when the client's copy is current, so the caller can
+/// skip building the body
+pub fn not_modified(headers: &HeaderMap, etag: &str) -> Option<Response> {
+    matches(headers, etag).then(|| tagged(StatusCode::NOT_MODIFIED, etag))
+}
+
+/// Attach `etag`; `no-cache` makes clients revalidate on every use
+pub fn tagged(response: impl IntoResponse, etag: &str) -> Response {
+    let mut response = response.into_response();
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        let headers = response.headers_mut();
+        headers.insert(header::ETAG, value);
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_if_none_match() {
+        let etag = of_bytes(b"body");
+        let mut headers = HeaderMap::new();
+        assert!(!matches(&headers, &etag));
+
+        headers.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_str(&format!("\"other\", W/{}", etag)).unwrap(),
+        );
+        assert!(matches(&headers, &etag));
+        assert_eq!(
+            not_modified(&headers, &etag).unwrap().status(),
+            StatusCode::NOT_MODIFIED
+        );
+        assert!(!matches(&headers, &of_bytes(b"changed")));
+    }
+}
@@ -0,0 +1,270 @@
+use crate::config::Mode;
+use crate::metrics::{SystemCollector, SystemMetrics};
+use crate::server::auth::Claims;
+use crate::server::AppState;
+use crate::storage::{AgentStatus, DbWrite, MetricRecord};
+use axum::{
+    body::Body,
+    extract::Request as HttpRequest,
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response as HttpResponse},
+    Router,
+};
+use std::sync::Arc;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tonic::{Code, Request, Response, Status};
+
+/// Largest middleware error body read back for the gRPC status message
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+pub mod proto {
+    tonic::include_proto!("infractl.v1");
+}
+
+use proto::agent_server::{Agent, AgentServer};
+use proto::home_server::{Home, HomeServer};
+
+/// gRPC services, mounted on the HTTP router so they share its port, TLS and
+/// middleware (network isolation, rate limiting, JWT audiences and scopes)
+pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    let mut router = Router::new().route_service(
+        "/infractl.v1.Agent/*rpc",
+        AgentServer::new(AgentService {
+            state: state.clone(),
+        }),
+    );
+    if state.config.mode == Mode::Home {
+        router = router.route_service(
+            "/infractl.v1.Home/*rpc",
+            HomeServer::new(HomeService { state }),
+        );
+    }
+    router
+}
+
+/// Middleware rejections (auth, isolation, rate limiting) are plain HTTP errors
+/// with a JSON body; gRPC clients get them as the matching status instead
+pub async fn status_errors(request: HttpRequest, next: Next) -> HttpResponse {
+    let is_grpc = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/grpc"));
+
+    let response = next.run(request).await;
+    if !is_grpc || response.status() == StatusCode::OK {
+        return response;
+    }
+
+    let status = response.status();
+    let code = match status {
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED => Code::Unimplemented,
+        StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT => Code::Unavailable,
+        StatusCode::REQUEST_TIMEOUT => Code::DeadlineExceeded,
+        _ => Code::Internal,
+    };
+    let body = axum::body::to_bytes(response.into_body(), MAX_ERROR_BODY)
+        .await
+        .unwrap_or_default();
+    let message = serde_json::from_slice::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|json| json.get("error")?.as_str().map(String::from))
+        .unwrap_or_else(|| status.canonical_reason().unwrap_or_default().to_string());
+
+    Status::new(code, message)
+        .into_http()
+        .map(Body::new)
+        .into_response()
+}
+
+struct AgentService {
+    state: Arc<AppState>,
+}
+
+#[tonic::async_trait]
+impl Agent for AgentService {
+    async fn health(
+        &self,
+        request: Request<proto::HealthRequest>,
+    ) -> Result<Response<proto::HealthReply>, Status> {
+        let system =
+            (!request.into_inner().light).then(|| system_metrics(&SystemCollector::collect()));
+
+        Ok(Response::new(proto::HealthReply {
+            status: "healthy".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            uptime_seconds: self.state.uptime_seconds(),
+            mode: format!("{:?}", self.state.config.mode).to_lowercase(),
+            system,
+        }))
+    }
+
+    async fn get_job(
+        &self,
+        request: Request<proto::GetJobRequest>,
+    ) -> Result<Response<proto::Job>, Status> {
+        let queue = self
+            .state
+            .deploy_queue
+            .as_ref()
+            .ok_or_else(|| Status::unavailable("Deployment queue not available"))?;
+        let job = queue
+            .get_job(&request.into_inner().job_id)
+            .await
+            .ok_or_else(|| Status::not_found("Job not found"))?;
+
+        Ok(Response::new(proto::Job {
+            id: job.id,
+            deployment: job.deployment_name,
+            agent: job.agent_name,
+            kind: job.kind.to_string(),
+            status: format!("{:?}", job.status),
+            created_at: format_rfc3339(job.created_at),
+            started_at: job.started_at.map(format_rfc3339),
+            completed_at: job.completed_at.map(format_rfc3339),
+            attempt: job.attempt,
+        }))
+    }
+}
+
+struct HomeService {
+    state: Arc<AppState>,
+}
+
+#[tonic::async_trait]
+impl Home for HomeService {
+    async fn push_metrics(
+        &self,
+        request: Request<proto::MetricsReport>,
+    ) -> Result<Response<proto::PushMetricsReply>, Status> {
+        // Set by jwt_auth: the agent pushes under its own token subject
+        let agent_name = request
+            .extensions()
+            .get::<Claims>()
+            .map(|claims| claims.sub.clone())
+            .ok_or_else(|| Status::unauthenticated("Missing token"))?;
+        let writer = self
+            .state
+            .db_writer
+            .as_ref()
+            .ok_or_else(|| Status::unavailable("Database not available"))?;
+
+        let report = request.into_inner();
+        let system = report
+            .system
+            .ok_or_else(|| Status::invalid_argument("Missing system metrics"))?;
+        let collected_at = match report.collected_at {
+            0 => OffsetDateTime::now_utc(),
+            ts => OffsetDateTime::from_unix_timestamp(ts)
+                .map_err(|_| Status::invalid_argument("Invalid collected_at"))?,
+        };
+
+        writer
+            .send(DbWrite::Metric(MetricRecord {
+                id: None,
+                agent_name: agent_name.clone(),
+                collected_at,
+                cpu_usage: system.cpu_usage as f64,
+                memory_usage_percent: system.memory_usage_percent as f64,
+                memory_used: system.memory_used,
+                memory_total: system.memory_total,
+                load_one: system.load_one,
+                load_five: system.load_five,
+                load_fifteen: system.load_fifteen,
+                disk_usage_percent: system.disk_usage_percent,
+                containers_running: report.containers_running,
+                containers_total: report.containers_total,
+                raw_json: None,
+                network_rx_bytes: None,
+                network_tx_bytes: None,
+            }))
+            .await;
+        writer
+            .send(DbWrite::AgentStatus(AgentStatus {
+                agent_name,
+                last_seen: OffsetDateTime::now_utc(),
+                status: "online".to_string(),
+                version: Some(report.version).filter(|v| !v.is_empty()),
+                uptime_seconds: Some(report.uptime_seconds),
+            }))
+            .await;
+
+        Ok(Response::new(proto::PushMetricsReply {}))
+    }
+}
+
+/// Wire form of the collected metrics; disk usage is that of `/`
+fn system_metrics(metrics: &SystemMetrics) -> proto::SystemMetrics {
+    proto::SystemMetrics {
+        hostname: metrics.hostname.clone(),
+        cpu_usage: metrics.cpu_usage,
+        cpu_count: metrics.cpu_count as u32,
+        memory_total: metrics.memory_total,
+        memory_used: metrics.memory_used,
+        memory_usage_percent: metrics.memory_usage_percent,
+        load_one: metrics.load_avg.one,
+        load_five: metrics.load_avg.five,
+        load_fifteen: metrics.load_avg.fifteen,
+        disk_usage_percent: metrics
+            .disks
+            .iter()
+            .find(|disk| disk.mount_point == "/")
+            .map(|disk| disk.usage_percent as f64),
+        uptime: metrics.uptime,
+    }
+}
+
+fn format_rfc3339(dt: OffsetDateTime) -> String {
+    dt.format(&Rfc3339).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::server::auth::Audience;
+    use crate::storage::{Database, DbWriter};
+
+    #[tokio::test]
+    async fn test_push_metrics_marks_agent_online() {
+        let config: Config = serde_yaml::from_str(
+            "mode: home\nserver: {}\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\n",
+        )
+        .unwrap();
+        let db = Arc::new(Database::new(":memory:").unwrap());
+        db.migrate().unwrap();
+        let writer = DbWriter::spawn(db.clone(), &config.modules.storage.write_buffer);
+        let service = HomeService {
+            state: AppState::with_database(config, db.clone(), writer.clone()),
+        };
+
+        let report = proto::MetricsReport {
+            system: Some(proto::SystemMetrics {
+                cpu_usage: 12.5,
+                ..Default::default()
+            }),
+            version: "1.2.3".to_string(),
+            uptime_seconds: 60,
+            ..Default::default()
+        };
+
+        let anonymous = service.push_metrics(Request::new(report.clone())).await;
+        assert_eq!(anonymous.unwrap_err().code(), Code::Unauthenticated);
+
+        let mut request = Request::new(report);
+        request
+            .extensions_mut()
+            .insert(Claims::new("web-1", 1, &[Audience::Agent]));
+        service.push_metrics(request).await.unwrap();
+        writer.flush().await;
+
+        let status = db.get_agent_status("web-1").unwrap().unwrap();
+        assert_eq!(status.status, "online");
+        assert_eq!(status.version.as_deref(), Some("1.2.3"));
+    }
+}
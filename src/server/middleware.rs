@@ -1,9 +1,10 @@
 use crate::config::is_ip_allowed;
-use crate::server::auth::JwtManager;
+use crate::server::auth::{required_scope, Audience};
 use crate::server::AppState;
+use crate::storage::{DbWrite, SuspiciousRequest};
 use axum::{
     extract::{ConnectInfo, Request, State},
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
     Json, Router,
@@ -12,6 +13,7 @@ use serde::Serialize;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Instant;
+use time::OffsetDateTime;
 use tower::ServiceBuilder;
 use tower_http::{compression::CompressionLayer, trace::TraceLayer};
 use tracing::{info, warn};
@@ -49,7 +51,11 @@ pub async fn network_isolation(
     request: Request,
     next: Next,
 ) -> Response {
-    if !state.config.server.isolation_mode {
+    let allowed = {
+        let live = state.live.read().await;
+        !live.isolation_mode || is_ip_allowed(&addr.ip(), &live.allowed_networks)
+    };
+    if allowed {
         return next.run(request).await;
     }
 
@@ -57,35 +63,54 @@ pub async fn network_isolation(
     let path = request.uri().path().to_string();
     let method = request.method().to_string();
 
-    if is_ip_allowed(&client_ip, &state.config.server.allowed_networks) {
-        next.run(request).await
-    } else {
-        // Log suspicious request
-        log_suspicious_request(&client_ip.to_string(), &method, &path, "network_violation");
+    // Log suspicious request
+    log_suspicious_request(
+        &state,
+        &client_ip.to_string(),
+        &method,
+        &path,
+        request.headers(),
+        "network_violation",
+    )
+    .await;
+    state.ip_bans.record_violation(client_ip).await;
 
-        ErrorResponse::new(StatusCode::FORBIDDEN, "Access denied: unauthorized network")
-            .into_response()
-    }
+    ErrorResponse::new(StatusCode::FORBIDDEN, "Access denied: unauthorized network").into_response()
 }
 
 /// JWT authentication middleware
 pub async fn jwt_auth(
     State(state): State<Arc<AppState>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
-    request: Request,
+    mut request: Request,
     next: Next,
 ) -> Response {
     let path = request.uri().path();
 
-    // Skip auth for health checks, root, and monitoring dashboard
-    if path == "/health" || path == "/" || path == "/monitoring" {
+    // Skip auth for health checks, root, monitoring dashboard (incl. its assets) and
+    // the /auth endpoints, which authenticate with a refresh token or the SSO provider
+    if path == "/health"
+        || path.starts_with("/health/")
+        || path == "/"
+        || path == "/monitoring"
+        || path.starts_with("/monitoring/")
+        || path.starts_with("/auth/")
+    {
         return next.run(request).await;
     }
 
+    // Browsers cannot set headers on WebSocket requests: /ws also takes ?token=
+    let query_token = (path == "/ws")
+        .then(|| request.uri().query())
+        .flatten()
+        .and_then(|q| q.split('&').find_map(|kv| kv.strip_prefix("token=")))
+        .map(|token| format!("Bearer {}", token));
+
     let auth_header = request
         .headers()
         .get("Authorization")
-        .and_then(|h| h.to_str().ok());
+        .and_then(|h| h.to_str().ok())
+        .or(query_token.as_deref());
 
     let client_ip = addr.ip().to_string();
     let method = request.method().to_string();
@@ -94,31 +119,97 @@ pub async fn jwt_auth(
     match auth_header {
         Some(header) if header.starts_with("Bearer ") => {
             let token = &header[7..];
-            let jwt_manager = JwtManager::new(&state.config.auth.jwt_secret);
+            // With `auth.agent_keys`, agents sign with their own key instead
+            let validated = match (state.jwt_keys.validate(token), &state.agent_keys) {
+                (Err(_), Some(agent_keys)) => {
+                    agent_keys.validate(&state.config.auth.jwt_secret, token)
+                }
+                (validated, _) => validated,
+            };
 
-            match jwt_manager.validate_token(token) {
+            match validated {
+                Ok(claims)
+                    if !claims.has_audience(Audience::accepted_for(request.method(), &path)) =>
+                {
+                    log_suspicious_request(
+                        &state,
+                        &client_ip,
+                        &method,
+                        &path,
+                        request.headers(),
+                        &format!("audience_mismatch: {}", claims.aud.join(",")),
+                    )
+                    .await;
+                    state.ip_bans.record_violation(addr.ip()).await;
+                    ErrorResponse::new(
+                        StatusCode::FORBIDDEN,
+                        "Token audience not permitted for this route",
+                    )
+                    .into_response()
+                }
+                Ok(claims) if !claims.has_scope(&required_scope(request.method(), &path)) => {
+                    log_suspicious_request(
+                        &state,
+                        &client_ip,
+                        &method,
+                        &path,
+                        request.headers(),
+                        &format!("scope_denied: {}", claims.scopes.join(",")),
+                    )
+                    .await;
+                    state.ip_bans.record_violation(addr.ip()).await;
+                    ErrorResponse::new(
+                        StatusCode::FORBIDDEN,
+                        "Token scope not permitted for this route",
+                    )
+                    .into_response()
+                }
                 Ok(claims) => {
                     info!(subject = %claims.sub, path = %path, "Authenticated request");
+                    // Handlers that act on behalf of the caller read the claims
+                    request.extensions_mut().insert(claims);
                     next.run(request).await
                 }
                 Err(e) => {
                     log_suspicious_request(
+                        &state,
                         &client_ip,
                         &method,
                         &path,
+                        request.headers(),
                         &format!("invalid_jwt: {}", e),
-                    );
+                    )
+                    .await;
+                    state.ip_bans.record_violation(addr.ip()).await;
                     ErrorResponse::new(StatusCode::UNAUTHORIZED, &e.to_string()).into_response()
                 }
             }
         }
         Some(_) => {
-            log_suspicious_request(&client_ip, &method, &path, "malformed_auth_header");
+            log_suspicious_request(
+                &state,
+                &client_ip,
+                &method,
+                &path,
+                request.headers(),
+                "malformed_auth_header",
+            )
+            .await;
+            state.ip_bans.record_violation(addr.ip()).await;
             ErrorResponse::new(StatusCode::UNAUTHORIZED, "Invalid authorization header")
                 .into_response()
         }
         None => {
-            log_suspicious_request(&client_ip, &method, &path, "missing_auth");
+            log_suspicious_request(
+                &state,
+                &client_ip,
+                &method,
+                &path,
+                request.headers(),
+                "missing_auth",
+            )
+            .await;
+            state.ip_bans.record_violation(addr.ip()).await;
             ErrorResponse::new(StatusCode::UNAUTHORIZED, "Missing authorization token")
                 .into_response()
         }
@@ -147,8 +238,24 @@ pub async fn request_timing(request: Request, next: Next) -> Response {
     response
 }
 
-/// Log suspicious requests to a separate target for security analysis
-fn log_suspicious_request(ip: &str, method: &str, path: &str, reason: &str) {
+/// Headers never stored with a suspicious request
+const SECRET_HEADERS: [&str; 4] = [
+    "authorization",
+    "cookie",
+    "proxy-authorization",
+    "x-api-key",
+];
+
+/// Log suspicious requests to a separate target for security analysis and
+/// queue them for the `suspicious_requests` table (`GET /api/suspicious`)
+async fn log_suspicious_request(
+    state: &AppState,
+    ip: &str,
+    method: &str,
+    path: &str,
+    headers: &HeaderMap,
+    reason: &str,
+) {
     warn!(
         target: "suspicious",
         ip = %ip,
@@ -157,66 +264,715 @@ fn log_suspicious_request(ip: &str, method: &str, path: &str, reason: &str) {
         reason = %reason,
         "Suspicious request detected"
     );
+
+    let Some(ref writer) = state.db_writer else {
+        return;
+    };
+    let recorded: serde_json::Map<String, serde_json::Value> = headers
+        .iter()
+        .filter(|(name, _)| !SECRET_HEADERS.contains(&name.as_str()))
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.into())))
+        .collect();
+    writer
+        .send(DbWrite::SuspiciousRequest(SuspiciousRequest {
+            id: None,
+            recorded_at: OffsetDateTime::now_utc(),
+            source_ip: ip.to_string(),
+            method: Some(method.to_string()),
+            path: Some(path.to_string()),
+            reason: reason.to_string(),
+            user_agent: headers
+                .get(header::USER_AGENT)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+            headers: serde_json::to_string(&recorded).ok(),
+        }))
+        .await;
+}
+
+/// Temporary bans for IPs that keep failing auth or isolation checks (fail2ban-style)
+pub mod ip_ban {
+    use crate::config::{is_ip_allowed, IpBanConfig};
+    use crate::storage::{Database, DbWrite, DbWriter, IpBan};
+    use serde::Serialize;
+    use std::collections::HashMap;
+    use std::net::IpAddr;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use time::OffsetDateTime;
+    use tokio::sync::RwLock;
+    use tracing::{debug, warn};
+
+    #[derive(Default)]
+    struct BanState {
+        violations: HashMap<IpAddr, Vec<Instant>>,
+        bans: HashMap<IpAddr, Instant>,
+    }
+
+    /// Active ban, as listed by `GET /api/bans`
+    #[derive(Debug, Serialize)]
+    pub struct BanEntry {
+        pub ip: String,
+        #[serde(with = "time::serde::rfc3339")]
+        pub banned_until: OffsetDateTime,
+        pub expires_in_seconds: u64,
+    }
+
+    #[derive(Clone)]
+    pub struct IpBanList {
+        state: Arc<RwLock<BanState>>,
+        enabled: bool,
+        max_violations: usize,
+        window: Duration,
+        ban_duration: Duration,
+        exempt_networks: Vec<String>,
+        /// Bans are mirrored to SQLite (Home mode) so they survive restarts
+        store: Option<DbWriter>,
+    }
+
+    impl IpBanList {
+        pub fn new(config: &IpBanConfig) -> Self {
+            Self {
+                state: Arc::new(RwLock::new(BanState::default())),
+                enabled: config.enabled,
+                max_violations: config.max_violations.max(1),
+                window: humantime::parse_duration(&config.window)
+                    .unwrap_or(Duration::from_secs(600)),
+                ban_duration: humantime::parse_duration(&config.ban_duration)
+                    .unwrap_or(Duration::from_secs(3600)),
+                exempt_networks: config.exempt_networks.clone(),
+                store: None,
+            }
+        }
+
+        /// Ban list backed by the `ip_bans` table; bans still active there are restored
+        pub fn with_store(mut self, db: &Database, writer: DbWriter) -> Self {
+            match db.get_ip_bans() {
+                Ok(bans) => {
+                    let now = Instant::now();
+                    let wall_now = OffsetDateTime::now_utc();
+                    let state = Arc::get_mut(&mut self.state)
+                        .expect("ban list is not shared yet")
+                        .get_mut();
+                    for ban in bans {
+                        let Ok(ip) = ban.ip.parse() else { continue };
+                        let remaining = (ban.banned_until - wall_now).unsigned_abs();
+                        state.bans.insert(ip, now + remaining);
+                    }
+                }
+                Err(e) => warn!(error = %e, "Failed to load persisted IP bans"),
+            }
+            self.store = Some(writer);
+            self
+        }
+
+        /// Active bans, longest remaining first
+        pub async fn list(&self) -> Vec<BanEntry> {
+            let now = Instant::now();
+            let wall_now = OffsetDateTime::now_utc();
+            let state = self.state.read().await;
+
+            let mut bans: Vec<BanEntry> = state
+                .bans
+                .iter()
+                .filter(|(_, &until)| until > now)
+                .map(|(ip, &until)| {
+                    let remaining = until - now;
+                    BanEntry {
+                        ip: ip.to_string(),
+                        banned_until: wall_now + remaining,
+                        expires_in_seconds: remaining.as_secs(),
+                    }
+                })
+                .collect();
+            bans.sort_by_key(|ban| std::cmp::Reverse(ban.banned_until));
+            bans
+        }
+
+        /// Check whether the IP is currently banned (expired bans are dropped)
+        pub async fn is_banned(&self, ip: IpAddr) -> bool {
+            if !self.enabled {
+                return false;
+            }
+
+            let now = Instant::now();
+            {
+                let state = self.state.read().await;
+                match state.bans.get(&ip) {
+                    None => return false,
+                    Some(&until) if until > now => return true,
+                    Some(_) => {}
+                }
+            }
+
+            self.state.write().await.bans.remove(&ip);
+            false
+        }
+
+        /// Record an auth failure or isolation violation.
+        /// Returns true if this violation caused the IP to be banned.
+        pub async fn record_violation(&self, ip: IpAddr) -> bool {
+            if !self.enabled || is_ip_allowed(&ip, &self.exempt_networks) {
+                return false;
+            }
+
+            let now = Instant::now();
+            let mut state = self.state.write().await;
+
+            let entry = state.violations.entry(ip).or_default();
+            entry.retain(|&t| now.duration_since(t) < self.window);
+            entry.push(now);
+
+            if entry.len() < self.max_violations {
+                return false;
+            }
+
+            state.violations.remove(&ip);
+            state.bans.insert(ip, now + self.ban_duration);
+            // The writer channel may be full: never wait on it with the lock held
+            drop(state);
+            if let Some(ref writer) = self.store {
+                writer
+                    .send(DbWrite::IpBanned(IpBan {
+                        ip: ip.to_string(),
+                        banned_until: OffsetDateTime::now_utc() + self.ban_duration,
+                    }))
+                    .await;
+            }
+
+            warn!(
+                target: "suspicious",
+                ip = %ip,
+                ban_duration = ?self.ban_duration,
+                "IP banned after repeated violations"
+            );
+            true
+        }
+
+        /// Forget violations older than `window` and expired bans.
+        /// Returns the number of IPs dropped.
+        pub async fn cleanup(&self) -> usize {
+            let now = Instant::now();
+            let mut state = self.state.write().await;
+
+            let before = state.violations.len() + state.bans.len();
+            state.violations.retain(|_, times| {
+                times.retain(|&t| now.duration_since(t) < self.window);
+                !times.is_empty()
+            });
+            state.bans.retain(|_, &mut until| until > now);
+            before - state.violations.len() - state.bans.len()
+        }
+
+        /// Lift a ban and forget recorded violations. Returns false if the IP was not banned.
+        pub async fn unban(&self, ip: IpAddr) -> bool {
+            let mut state = self.state.write().await;
+            state.violations.remove(&ip);
+            let banned = state
+                .bans
+                .remove(&ip)
+                .is_some_and(|until| until > Instant::now());
+            drop(state);
+            if let Some(ref writer) = self.store {
+                writer
+                    .send(DbWrite::IpUnbanned { ip: ip.to_string() })
+                    .await;
+            }
+            banned
+        }
+    }
+
+    /// Periodically drop stale violations and expired bans, so a scan from
+    /// many source IPs does not grow the list without bound
+    pub async fn cleanup_task(bans: IpBanList, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let removed = bans.cleanup().await;
+            if removed > 0 {
+                debug!(removed, "IP ban list cleanup");
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn config(max_violations: usize, ban_duration: &str) -> IpBanConfig {
+            IpBanConfig {
+                max_violations,
+                ban_duration: ban_duration.to_string(),
+                ..IpBanConfig::default()
+            }
+        }
+
+        #[tokio::test]
+        async fn test_ban_after_max_violations() {
+            let bans = IpBanList::new(&config(3, "1h"));
+            let ip: IpAddr = "203.0.113.7".parse().unwrap();
+
+            assert!(!bans.record_violation(ip).await);
+            assert!(!bans.record_violation(ip).await);
+            assert!(!bans.is_banned(ip).await);
+            assert!(bans.record_violation(ip).await);
+            assert!(bans.is_banned(ip).await);
+
+            assert!(bans.unban(ip).await);
+            assert!(!bans.is_banned(ip).await);
+            assert!(!bans.unban(ip).await);
+        }
+
+        #[tokio::test]
+        async fn test_exempt_and_expired() {
+            let bans = IpBanList::new(&config(1, "0s"));
+            let local: IpAddr = "127.0.0.1".parse().unwrap();
+            assert!(!bans.record_violation(local).await);
+            assert!(!bans.is_banned(local).await);
+
+            // Zero-length ban expires immediately
+            let ip: IpAddr = "203.0.113.8".parse().unwrap();
+            assert!(bans.record_violation(ip).await);
+            assert!(!bans.is_banned(ip).await);
+        }
+
+        #[tokio::test]
+        async fn test_cleanup_drops_stale_entries() {
+            let bans = IpBanList::new(&IpBanConfig {
+                window: "0s".to_string(),
+                ..config(3, "0s")
+            });
+            for last in 1..=100u8 {
+                bans.record_violation(IpAddr::from([203, 0, 113, last]))
+                    .await;
+            }
+            assert_eq!(bans.cleanup().await, 100);
+            assert_eq!(bans.cleanup().await, 0);
+
+            let banned = IpBanList::new(&config(1, "1h"));
+            let ip: IpAddr = "203.0.113.9".parse().unwrap();
+            assert!(banned.record_violation(ip).await);
+            assert_eq!(banned.cleanup().await, 0);
+            assert!(banned.is_banned(ip).await);
+        }
+
+        #[tokio::test]
+        async fn test_bans_survive_restart() {
+            let db = Database::new(":memory:").unwrap();
+            db.migrate().unwrap();
+            let db = Arc::new(db);
+            let writer = DbWriter::spawn(db.clone(), &Default::default());
+            let ip: IpAddr = "203.0.113.10".parse().unwrap();
+
+            let bans = IpBanList::new(&config(1, "1h")).with_store(&db, writer.clone());
+            assert!(bans.record_violation(ip).await);
+            writer.flush().await;
+
+            let restarted = IpBanList::new(&config(1, "1h")).with_store(&db, writer.clone());
+            assert!(restarted.is_banned(ip).await);
+            let listed = restarted.list().await;
+            assert_eq!(listed.len(), 1);
+            assert!(listed[0].expires_in_seconds > 3500);
+
+            assert!(restarted.unban(ip).await);
+            writer.flush().await;
+            assert!(db.get_ip_bans().unwrap().is_empty());
+        }
+
+        #[tokio::test]
+        async fn test_disabled() {
+            let bans = IpBanList::new(&IpBanConfig {
+                enabled: false,
+                max_violations: 1,
+                ..IpBanConfig::default()
+            });
+            let ip: IpAddr = "203.0.113.9".parse().unwrap();
+            assert!(!bans.record_violation(ip).await);
+            assert!(!bans.is_banned(ip).await);
+        }
+    }
+}
+
+/// IP ban middleware - rejects requests from temporarily banned IPs
+pub async fn ip_ban(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if state.ip_bans.is_banned(addr.ip()).await {
+        return ErrorResponse::new(
+            StatusCode::FORBIDDEN,
+            "Access denied: IP temporarily banned",
+        )
+        .into_response();
+    }
+
+    next.run(request).await
 }
 
 /// Rate limiting state
 pub mod rate_limit {
-    use std::collections::HashMap;
+    use crate::config::{RateLimitConfig, RateLimitRule};
+    use ipnetwork::IpNetwork;
+    use std::collections::{HashMap, HashSet};
     use std::net::IpAddr;
+    use std::sync::atomic::{AtomicU64, Ordering};
     use std::sync::Arc;
     use std::time::{Duration, Instant};
     use tokio::sync::RwLock;
+    use tracing::debug;
+
+    /// Route classes with separate budgets
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum RouteClass {
+        Webhook,
+        Api,
+        Health,
+        Other,
+    }
+
+    impl RouteClass {
+        pub fn of(path: &str) -> Self {
+            if path.starts_with("/webhook/") {
+                Self::Webhook
+            } else if path.starts_with("/api/")
+                || path == "/ws"
+                || path == "/metrics"
+                || path.starts_with("/infractl.v1.")
+            {
+                Self::Api
+            } else if path == "/health" || path.starts_with("/health/") || path == "/" {
+                Self::Health
+            } else {
+                Self::Other
+            }
+        }
+    }
+
+    /// Token bucket limits: `burst` requests at once, refilled at
+    /// `max_requests` per `window`
+    #[derive(Debug, Clone, Copy)]
+    struct Limit {
+        burst: f64,
+        per_second: f64,
+        /// Time for an empty bucket to refill completely
+        refill: Duration,
+    }
+
+    impl Limit {
+        fn new(max_requests: usize, window: &str, burst: Option<usize>) -> Self {
+            let window = humantime::parse_duration(window).unwrap_or(Duration::from_secs(60));
+            let max_requests = max_requests.max(1) as f64;
+            let burst = burst.unwrap_or(max_requests as usize).max(1) as f64;
+            let per_second = max_requests / window.as_secs_f64().max(0.001);
+            Self {
+                burst,
+                per_second,
+                refill: Duration::from_secs_f64(burst / per_second),
+            }
+        }
+
+        fn from_rule(rule: &RateLimitRule) -> Self {
+            Self::new(rule.max_requests, &rule.window, rule.burst)
+        }
+    }
+
+    struct Bucket {
+        tokens: f64,
+        updated: Instant,
+    }
+
+    struct Entry {
+        buckets: HashMap<RouteClass, Bucket>,
+        last_seen: Instant,
+    }
+
+    /// Limiter counters exported on `/metrics`
+    pub struct RateLimiterStats {
+        pub tracked_ips: usize,
+        pub rejected_total: u64,
+        pub evicted_total: u64,
+    }
+
+    /// Limits and exemptions, replaced as a whole on config reload
+    struct Rules {
+        limits: HashMap<RouteClass, Limit>,
+        /// Longest refill time; idle entries older than this are full again
+        idle: Duration,
+        exempt_networks: Vec<IpNetwork>,
+        exempt_ips: HashSet<IpAddr>,
+        max_tracked_ips: usize,
+    }
+
+    impl Rules {
+        fn new(config: &RateLimitConfig, agent_ips: Vec<IpAddr>) -> Self {
+            let default = Limit::new(config.max_requests, &config.window, config.burst);
+            let rule =
+                |rule: &Option<RateLimitRule>| rule.as_ref().map_or(default, Limit::from_rule);
+            let limits = HashMap::from([
+                (RouteClass::Webhook, rule(&config.routes.webhook)),
+                (RouteClass::Api, rule(&config.routes.api)),
+                (RouteClass::Health, rule(&config.routes.health)),
+                (RouteClass::Other, default),
+            ]);
+            let idle = limits
+                .values()
+                .map(|limit| limit.refill)
+                .max()
+                .unwrap_or_default();
+
+            Self {
+                limits,
+                idle,
+                exempt_networks: config
+                    .exempt_networks
+                    .iter()
+                    .filter_map(|network| network.parse().ok())
+                    .collect(),
+                exempt_ips: if config.exempt_agents {
+                    agent_ips.into_iter().collect()
+                } else {
+                    HashSet::new()
+                },
+                max_tracked_ips: config.max_tracked_ips.max(1),
+            }
+        }
+
+        fn is_exempt(&self, ip: IpAddr) -> bool {
+            self.exempt_ips.contains(&ip)
+                || self
+                    .exempt_networks
+                    .iter()
+                    .any(|network| network.contains(ip))
+        }
+    }
 
     #[derive(Clone)]
     pub struct RateLimiter {
-        requests: Arc<RwLock<HashMap<IpAddr, Vec<Instant>>>>,
-        max_requests: usize,
-        window: Duration,
+        requests: Arc<RwLock<HashMap<IpAddr, Entry>>>,
+        rules: Arc<RwLock<Rules>>,
+        rejected: Arc<AtomicU64>,
+        evicted: Arc<AtomicU64>,
     }
 
     impl RateLimiter {
-        pub fn new(max_requests: usize, window_secs: u64) -> Self {
+        pub fn new(config: &RateLimitConfig) -> Self {
+            Self::for_fleet(config, Vec::new())
+        }
+
+        /// Limiter that exempts the fleet's own addresses (unless `exempt_agents` is off)
+        pub fn for_fleet(config: &RateLimitConfig, agent_ips: Vec<IpAddr>) -> Self {
             Self {
                 requests: Arc::new(RwLock::new(HashMap::new())),
-                max_requests,
-                window: Duration::from_secs(window_secs),
+                rules: Arc::new(RwLock::new(Rules::new(config, agent_ips))),
+                rejected: Arc::new(AtomicU64::new(0)),
+                evicted: Arc::new(AtomicU64::new(0)),
             }
         }
 
-        pub async fn check(&self, ip: IpAddr) -> bool {
+        /// Apply new limits; tracked IPs keep their buckets (capped at the new burst)
+        pub async fn reconfigure(&self, config: &RateLimitConfig, agent_ips: Vec<IpAddr>) {
+            *self.rules.write().await = Rules::new(config, agent_ips);
+        }
+
+        pub async fn check(&self, ip: IpAddr, path: &str) -> bool {
+            let rules = self.rules.read().await;
+            if rules.is_exempt(ip) {
+                return true;
+            }
+
+            let class = RouteClass::of(path);
+            let limit = rules.limits[&class];
             let now = Instant::now();
             let mut requests = self.requests.write().await;
 
-            let entry = requests.entry(ip).or_insert_with(Vec::new);
+            if !requests.contains_key(&ip) && requests.len() >= rules.max_tracked_ips {
+                self.evict_lru(&mut requests, &rules, now);
+            }
 
-            // Remove old requests outside the window
-            entry.retain(|&t| now.duration_since(t) < self.window);
+            let entry = requests.entry(ip).or_insert_with(|| Entry {
+                buckets: HashMap::new(),
+                last_seen: now,
+            });
+            entry.last_seen = now;
+
+            let bucket = entry.buckets.entry(class).or_insert(Bucket {
+                tokens: limit.burst,
+                updated: now,
+            });
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * limit.per_second).min(limit.burst);
+            bucket.updated = now;
 
-            if entry.len() >= self.max_requests {
+            if bucket.tokens < 1.0 {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
                 return false;
             }
 
-            entry.push(now);
+            bucket.tokens -= 1.0;
             true
         }
 
-        /// Cleanup old entries periodically
-        #[allow(dead_code)]
-        pub async fn cleanup(&self) {
+        /// Make room for a new IP: drop idle entries, then the least recently seen one
+        fn evict_lru(&self, requests: &mut HashMap<IpAddr, Entry>, rules: &Rules, now: Instant) {
+            let before = requests.len();
+            requests.retain(|_, e| now.duration_since(e.last_seen) < rules.idle);
+
+            if requests.len() >= rules.max_tracked_ips {
+                if let Some(oldest) = requests
+                    .iter()
+                    .min_by_key(|(_, e)| e.last_seen)
+                    .map(|(ip, _)| *ip)
+                {
+                    requests.remove(&oldest);
+                }
+            }
+
+            let evicted = (before - requests.len()) as u64;
+            self.evicted.fetch_add(evicted, Ordering::Relaxed);
+        }
+
+        /// Drop IPs whose buckets have refilled completely. Returns the number removed.
+        pub async fn cleanup(&self) -> usize {
+            let rules = self.rules.read().await;
             let now = Instant::now();
             let mut requests = self.requests.write().await;
 
-            requests.retain(|_, times| {
-                times.retain(|&t| now.duration_since(t) < self.window);
-                !times.is_empty()
+            let before = requests.len();
+            requests.retain(|_, entry| {
+                entry.buckets.retain(|class, bucket| {
+                    now.duration_since(bucket.updated) < rules.limits[class].refill
+                });
+                !entry.buckets.is_empty()
             });
+            before - requests.len()
+        }
+
+        pub async fn stats(&self) -> RateLimiterStats {
+            RateLimiterStats {
+                tracked_ips: self.requests.read().await.len(),
+                rejected_total: self.rejected.load(Ordering::Relaxed),
+                evicted_total: self.evicted.load(Ordering::Relaxed),
+            }
         }
     }
 
     impl Default for RateLimiter {
         fn default() -> Self {
             // 100 requests per minute by default
-            Self::new(100, 60)
+            Self::new(&RateLimitConfig::default())
+        }
+    }
+
+    /// Periodically purge expired entries so scanning traffic doesn't pile up
+    pub async fn cleanup_task(limiter: RateLimiter, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let removed = limiter.cleanup().await;
+            if removed > 0 {
+                debug!(removed, "Rate limiter cleanup");
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::config::RateLimitRoutes;
+
+        fn limiter(max_requests: usize, max_tracked_ips: usize) -> RateLimiter {
+            RateLimiter::new(&RateLimitConfig {
+                max_requests,
+                max_tracked_ips,
+                ..RateLimitConfig::default()
+            })
+        }
+
+        #[tokio::test]
+        async fn test_rejects_over_limit() {
+            let limiter = limiter(2, 10);
+            let ip: IpAddr = "203.0.113.7".parse().unwrap();
+
+            assert!(limiter.check(ip, "/api/status").await);
+            assert!(limiter.check(ip, "/api/status").await);
+            assert!(!limiter.check(ip, "/api/status").await);
+            assert_eq!(limiter.stats().await.rejected_total, 1);
+        }
+
+        #[tokio::test]
+        async fn test_evicts_least_recently_seen_ip() {
+            let limiter = limiter(100, 2);
+            let a: IpAddr = "203.0.113.1".parse().unwrap();
+            let b: IpAddr = "203.0.113.2".parse().unwrap();
+            let c: IpAddr = "203.0.113.3".parse().unwrap();
+
+            limiter.check(a, "/health").await;
+            limiter.check(b, "/health").await;
+            limiter.check(a, "/health").await;
+            limiter.check(c, "/health").await;
+
+            let stats = limiter.stats().await;
+            assert_eq!(stats.tracked_ips, 2);
+            assert_eq!(stats.evicted_total, 1);
+            let requests = limiter.requests.read().await;
+            assert!(requests.contains_key(&a) && requests.contains_key(&c));
+        }
+
+        #[tokio::test]
+        async fn test_route_classes_have_separate_budgets() {
+            let limiter = RateLimiter::new(&RateLimitConfig {
+                max_requests: 1,
+                routes: RateLimitRoutes {
+                    webhook: Some(RateLimitRule {
+                        max_requests: 1,
+                        window: "1h".to_string(),
+                        burst: Some(3),
+                    }),
+                    ..Default::default()
+                },
+                ..RateLimitConfig::default()
+            });
+            let ip: IpAddr = "203.0.113.7".parse().unwrap();
+
+            for _ in 0..3 {
+                assert!(limiter.check(ip, "/webhook/app").await);
+            }
+            assert!(!limiter.check(ip, "/webhook/app").await);
+
+            // Webhook traffic doesn't use up the API budget
+            assert!(limiter.check(ip, "/api/status").await);
+            assert!(!limiter.check(ip, "/api/status").await);
+        }
+
+        #[tokio::test]
+        async fn test_exempt_agents_and_networks() {
+            let config = RateLimitConfig {
+                max_requests: 1,
+                exempt_networks: vec!["10.0.0.0/8".to_string()],
+                ..RateLimitConfig::default()
+            };
+            let agent: IpAddr = "192.0.2.10".parse().unwrap();
+            let internal: IpAddr = "10.1.2.3".parse().unwrap();
+
+            let limiter = RateLimiter::for_fleet(&config, vec![agent]);
+            for ip in [agent, internal] {
+                for _ in 0..5 {
+                    assert!(limiter.check(ip, "/api/status").await);
+                }
+            }
+            assert_eq!(limiter.stats().await.tracked_ips, 0);
+
+            let limiter = RateLimiter::for_fleet(
+                &RateLimitConfig {
+                    exempt_agents: false,
+                    ..config
+                },
+                vec![agent],
+            );
+            assert!(limiter.check(agent, "/api/status").await);
+            assert!(!limiter.check(agent, "/api/status").await);
         }
     }
 }
@@ -230,16 +986,24 @@ pub async fn rate_limiting(
 ) -> Response {
     let client_ip = addr.ip();
 
-    if !state.rate_limiter.check(client_ip).await {
+    if !state
+        .rate_limiter
+        .check(client_ip, request.uri().path())
+        .await
+    {
+        state.ip_bans.record_violation(client_ip).await;
         let path = request.uri().path().to_string();
         let method = request.method().to_string();
 
         log_suspicious_request(
+            &state,
             &client_ip.to_string(),
             &method,
             &path,
+            request.headers(),
             "rate_limit_exceeded",
-        );
+        )
+        .await;
 
         return ErrorResponse::new(StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded")
             .into_response();
@@ -247,3 +1011,47 @@ pub async fn rate_limiting(
 
     next.run(request).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::storage::{Database, DbWriter};
+
+    #[tokio::test]
+    async fn test_suspicious_requests_are_stored_without_secrets() {
+        let config: Config = serde_yaml::from_str(
+            "mode: home\nserver: {}\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\n",
+        )
+        .unwrap();
+        let db = Arc::new(Database::new(":memory:").unwrap());
+        db.migrate().unwrap();
+        let writer = DbWriter::spawn(db.clone(), &config.modules.storage.write_buffer);
+        let state = AppState::with_database(config, db.clone(), writer.clone());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::USER_AGENT, "curl/8.5".parse().unwrap());
+        headers.insert(header::AUTHORIZATION, "Bearer stolen".parse().unwrap());
+        headers.insert("x-forwarded-for", "198.51.100.4".parse().unwrap());
+        log_suspicious_request(
+            &state,
+            "203.0.113.7",
+            "GET",
+            "/api/config",
+            &headers,
+            "invalid_jwt: InvalidSignature",
+        )
+        .await;
+        writer.flush().await;
+
+        let stored = db.get_suspicious_requests(10).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].source_ip, "203.0.113.7");
+        assert_eq!(stored[0].path.as_deref(), Some("/api/config"));
+        assert_eq!(stored[0].reason, "invalid_jwt: InvalidSignature");
+        assert_eq!(stored[0].user_agent.as_deref(), Some("curl/8.5"));
+        let headers = stored[0].headers.as_deref().unwrap();
+        assert!(headers.contains("198.51.100.4"));
+        assert!(!headers.contains("stolen"));
+    }
+}
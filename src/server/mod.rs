@@ -1,9 +1,20 @@
+mod agents;
 mod app_state;
 mod assets;
 pub mod auth;
+mod discovery;
+mod etag;
+pub mod grpc;
 pub mod middleware;
+mod notify;
+mod oidc;
+mod push;
+mod reload;
 mod routes;
+mod session;
 mod shutdown;
+mod tls;
+mod tunnel;
 
 pub use app_state::AppState;
 
@@ -15,11 +26,16 @@ use crate::storage::{self, parse_retention_days};
 use crate::updater::{self, Updater};
 use anyhow::Result;
 use axum::{middleware as axum_mw, Router};
+use futures::future::{BoxFuture, FutureExt};
+use std::future::IntoFuture;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::net::TcpListener;
 use tracing::{info, warn};
 
+/// How long interrupted jobs may take to be written after the drain timeout
+const CHECKPOINT_FLUSH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
 pub async fn run(config: Config, cli: Cli) -> Result<()> {
     // Initialize database for Home mode
     let state = if config.mode == Mode::Home && config.modules.storage.enabled {
@@ -32,39 +48,118 @@ pub async fn run(config: Config, cli: Cli) -> Result<()> {
         let db_clone = db.clone();
         tokio::spawn(aggregation::daily_aggregation_task(db_clone));
 
-        // Parse retention settings and start cleanup task
-        let raw_days = parse_retention_days(&config.modules.storage.retention.raw_data);
-        let hourly_days = parse_retention_days(&config.modules.storage.retention.hourly_data);
-        let daily_days = parse_retention_days(&config.modules.storage.retention.daily_data);
+        spawn_retention(&config, &db);
 
-        let db_clone = db.clone();
-        tokio::spawn(aggregation::retention_task(
-            db_clone,
-            raw_days,
-            hourly_days,
-            daily_days,
-        ));
+        let settings = &config.modules.storage;
+        if let Some(interval) = settings.backup.interval.as_deref() {
+            if let Ok(interval) = humantime::parse_duration(interval) {
+                tokio::spawn(storage::backup::backup_task(
+                    db.clone(),
+                    settings.backup_dir(),
+                    settings.backup.keep,
+                    interval,
+                ));
+            }
+        }
 
-        AppState::with_database(config.clone(), db)
+        let writer = storage::DbWriter::spawn(db.clone(), &config.modules.storage.write_buffer);
+        AppState::with_database(config.clone(), db, writer)
+    } else if config.mode == Mode::Agent
+        && config.modules.deploy.enabled
+        && config.modules.storage.enabled
+    {
+        // Agents keep their deploy queue and history in a local database,
+        // so queued jobs and dependency checks survive restarts
+        let db = storage::init(&config).await?;
+        spawn_retention(&config, &db);
+        let writer = storage::DbWriter::spawn(db.clone(), &config.modules.storage.write_buffer);
+        AppState::with_deploy_store(config.clone(), db, writer)
     } else {
         AppState::new(config.clone())
     };
 
+    // Purge expired rate limiter and IP ban entries
+    let cleanup_interval = humantime::parse_duration(&config.server.rate_limit.cleanup_interval)
+        .unwrap_or(std::time::Duration::from_secs(60));
+    tokio::spawn(middleware::rate_limit::cleanup_task(
+        state.rate_limiter.clone(),
+        cleanup_interval,
+    ));
+    tokio::spawn(middleware::ip_ban::cleanup_task(
+        state.ip_bans.clone(),
+        cleanup_interval,
+    ));
+
+    let _ = state.config_path.set(cli.config.clone());
+    let shutdown_rx = state.shutdown.subscribe();
+
+    tokio::spawn(reload::watch_sighup(state.clone(), cli.config.clone()));
+
+    if config.mode == Mode::Home {
+        agents::spawn_health_polls(&state).await;
+    }
+    let maintenance = &config.modules.storage.maintenance;
+    if let (Some(db), true) = (&state.db, maintenance.enabled) {
+        tokio::spawn(storage::maintenance::maintenance_task(
+            db.clone(),
+            maintenance.schedule.clone(),
+            shutdown_rx.clone(),
+        ));
+    }
+    if config.server.mdns {
+        match config.mode {
+            Mode::Agent => discovery::advertise(&state),
+            Mode::Home => discovery::discover(&state),
+        }
+    }
+    if config.modules.metrics.enabled {
+        for metric in &config.modules.metrics.custom {
+            tokio::spawn(push::run_custom_metric(state.clone(), metric.clone()));
+        }
+    }
+    if config.mode == Mode::Agent && config.modules.metrics.enabled && config.modules.metrics.push {
+        if let Some(ref home_address) = config.server.home_address {
+            tokio::spawn(push::push_metrics(state.clone(), home_address.clone()));
+        }
+    }
+
     // Start deployment worker if enabled
+    let mut worker = None;
     if config.modules.deploy.enabled {
         if let Some(ref queue) = state.deploy_queue {
             let queue_clone = queue.clone();
-            let executor = Arc::new(DeployExecutor::new());
-            let db_clone = state.db.clone();
+            let executor = Arc::new(DeployExecutor::with_http_client(
+                state.http.clone(),
+                config.modules.deploy.container_runtime,
+            ));
+            let db_writer = state.deploy_writer().cloned();
             let deploy_config = state.deploy_config.clone();
             let config_path = cli.config.clone();
 
-            tokio::spawn(async move {
-                deploy::start_worker(queue_clone, executor, db_clone, deploy_config, config_path)
-                    .await;
-            });
+            let worker_shutdown = shutdown_rx.clone();
+
+            let running = app_state::WorkerGuard::new(state.clone());
+
+            worker = Some(tokio::spawn(async move {
+                let _running = running;
+                deploy::start_worker(
+                    queue_clone,
+                    executor,
+                    db_writer,
+                    deploy_config,
+                    config_path,
+                    worker_shutdown,
+                )
+                .await;
+            }));
 
             info!("Deployment worker started");
+
+            tokio::spawn(deploy::start_scheduler(
+                queue.clone(),
+                state.deploy_config.clone(),
+                shutdown_rx.clone(),
+            ));
         }
     }
 
@@ -72,7 +167,12 @@ pub async fn run(config: Config, cli: Cli) -> Result<()> {
     if config.updates.enabled {
         let config_path = cli.config.to_str().unwrap_or("/etc/infractl/config.yaml");
         let update_config = config.updates.clone();
-        let updater_instance = Arc::new(Updater::new(&update_config, config_path));
+        let updater_instance = Arc::new(Updater::new(
+            &update_config,
+            config_path,
+            state.http.clone(),
+        ));
+        let _ = state.updater.set(updater_instance.clone());
 
         // Start self-update checker
         if update_config.self_update.enabled {
@@ -109,22 +209,141 @@ pub async fn run(config: Config, cli: Cli) -> Result<()> {
 
     let app = create_router(state.clone());
 
+    if config.mode == Mode::Agent && config.server.reverse_connect {
+        if let Some(ref home_address) = config.server.home_address {
+            tokio::spawn(tunnel::connect(
+                state.clone(),
+                app.clone(),
+                home_address.clone(),
+            ));
+        }
+    }
+
     let addr = SocketAddr::new(config.server.bind.parse()?, config.server.port);
 
     let listener = TcpListener::bind(addr).await?;
-    info!(address = %addr, mode = ?config.mode, "Server listening");
+    let make_service = app.into_make_service_with_connect_info::<SocketAddr>();
 
-    axum::serve(
-        listener,
-        app.into_make_service_with_connect_info::<SocketAddr>(),
-    )
-    .with_graceful_shutdown(shutdown::signal())
-    .await?;
+    let server: BoxFuture<'static, std::io::Result<()>> = match config.server.tls {
+        Some(ref tls_config) => {
+            let rustls = tls::load(tls_config).await?;
+            tokio::spawn(tls::watch_certificate(
+                rustls.clone(),
+                tls_config.clone(),
+                shutdown_rx.clone(),
+            ));
+            info!(address = %addr, mode = ?config.mode, "Server listening (HTTPS)");
+
+            let handle = axum_server::Handle::new();
+            tokio::spawn({
+                let state = state.clone();
+                let handle = handle.clone();
+                async move {
+                    shutdown::signal().await;
+                    state.shutdown.send_replace(true);
+                    handle.graceful_shutdown(None);
+                }
+            });
+            axum_server::from_tcp_rustls(listener.into_std()?, rustls)
+                .handle(handle)
+                .serve(make_service)
+                .boxed()
+        }
+        None => {
+            info!(address = %addr, mode = ?config.mode, "Server listening");
+            axum::serve(listener, make_service)
+                .with_graceful_shutdown({
+                    let state = state.clone();
+                    async move {
+                        shutdown::signal().await;
+                        state.shutdown.send_replace(true);
+                    }
+                })
+                .into_future()
+                .boxed()
+        }
+    };
+
+    // Stop accepting connections, then let in-flight requests, the running
+    // deploy job and buffered DB writes finish
+    let drain = async {
+        server.await?;
+        if let Some(worker) = worker {
+            let _ = worker.await;
+        }
+        if let Some(writer) = state.deploy_writer() {
+            writer.flush().await;
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+
+    let drain_timeout = humantime::parse_duration(&config.server.drain_timeout)
+        .unwrap_or(std::time::Duration::from_secs(30));
+    let mut signalled = shutdown_rx;
+    let deadline = async {
+        let _ = signalled.wait_for(|stopping| *stopping).await;
+        info!(timeout = ?drain_timeout, "Draining in-flight work");
+        if let Some(ref queue) = state.deploy_queue {
+            for job in queue.running().await {
+                info!(
+                    deployment = %job.deployment_name,
+                    job_id = %job.id,
+                    kind = %job.kind,
+                    "Waiting for running deploy job"
+                );
+            }
+        }
+        tokio::time::sleep(drain_timeout).await;
+    };
+
+    tokio::select! {
+        result = drain => result?,
+        _ = deadline => {
+            warn!(timeout = ?drain_timeout, "Drain timeout elapsed, exiting with work still in flight");
+            checkpoint_interrupted(&state).await;
+        }
+    }
 
     info!("Server shutdown complete");
     Ok(())
 }
 
+/// Start the retention cleanup task with the configured retention periods
+fn spawn_retention(config: &Config, db: &Arc<storage::Database>) {
+    let retention = &config.modules.storage.retention;
+    tokio::spawn(aggregation::retention_task(
+        db.clone(),
+        parse_retention_days(&retention.raw_data),
+        parse_retention_days(&retention.hourly_data),
+        parse_retention_days(&retention.daily_data),
+        parse_retention_days(&retention.suspicious_requests),
+        parse_retention_days(&retention.deploy_history),
+    ));
+}
+
+/// Record deploy jobs cut off by the drain timeout so the next start runs them again
+async fn checkpoint_interrupted(state: &AppState) {
+    let Some(ref queue) = state.deploy_queue else {
+        return;
+    };
+    let interrupted = queue.checkpoint_running().await;
+    if interrupted == 0 {
+        return;
+    }
+    warn!(
+        jobs = interrupted,
+        "Deploy jobs interrupted, they will be re-run on the next start"
+    );
+    if let Some(writer) = state.deploy_writer() {
+        if tokio::time::timeout(CHECKPOINT_FLUSH_TIMEOUT, writer.flush())
+            .await
+            .is_err()
+        {
+            warn!("Timed out saving interrupted deploy jobs");
+        }
+    }
+}
+
 fn create_router(state: Arc<AppState>) -> Router {
     let config = state.config.clone();
 
@@ -143,6 +362,10 @@ fn create_router(state: Arc<AppState>) -> Router {
         }
     }
 
+    if config.server.grpc {
+        router = router.merge(grpc::routes(state.clone()));
+    }
+
     // Apply security middleware (order matters: first applied = last executed)
     router = router
         .layer(axum_mw::from_fn(middleware::request_timing))
@@ -157,10 +380,63 @@ fn create_router(state: Arc<AppState>) -> Router {
         .layer(axum_mw::from_fn_with_state(
             state.clone(),
             middleware::network_isolation,
+        ))
+        .layer(axum_mw::from_fn_with_state(
+            state.clone(),
+            middleware::ip_ban,
         ));
 
+    if config.server.grpc {
+        router = router.layer(axum_mw::from_fn(grpc::status_errors));
+    }
+
     // Apply common middleware (compression, cors, tracing)
     router = middleware::apply(router, state.clone());
 
     router.with_state(state)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::agents::AgentRequest;
+    use crate::server::auth::{Audience, JwtManager};
+    use axum::http::Method;
+
+    #[tokio::test]
+    async fn test_home_serves_deployment_configs_to_agents() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("deployments.d")).unwrap();
+        std::fs::write(
+            dir.path().join("deployments.d/web.yaml"),
+            "- name: web\n  type: custom_script\n  script: echo deployed\n",
+        )
+        .unwrap();
+        let config_path = dir.path().join("config.yaml");
+        std::fs::write(
+            &config_path,
+            format!(
+                "mode: home\nserver: {{}}\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\nmodules:\n  deploy:\n    external_deployments_path: \"{}\"\n",
+                dir.path().display()
+            ),
+        )
+        .unwrap();
+        let config = crate::config::load(&config_path).unwrap();
+        let token = JwtManager::new(&config.auth.jwt_secret)
+            .generate_token("web-1", 1, &[Audience::Agent])
+            .unwrap();
+        let router = create_router(AppState::new(config));
+        let get = |path: &str| {
+            tunnel::dispatch(
+                router.clone(),
+                AgentRequest::new(Method::GET, path).bearer(&token),
+            )
+        };
+
+        let reply = get("/api/deployments/web").await;
+        assert_eq!(reply.status, 200, "{}", reply.body);
+        let deployment: crate::config::DeploymentConfig = reply.json().unwrap();
+        assert_eq!(deployment.name, "web");
+        assert_eq!(get("/api/deployments/api").await.status, 404);
+    }
+}
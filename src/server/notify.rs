@@ -0,0 +1,109 @@
+use crate::config::NotificationChannel;
+use crate::server::AppState;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Send `message` to every configured notification channel in the
+/// background, so the caller (e.g. health polling) is never held up by a
+/// slow endpoint. Does nothing unless `notifications.enabled`.
+pub fn send(state: &AppState, event: &str, agent: &str, message: String) {
+    let notifications = &state.config.notifications;
+    if !notifications.enabled {
+        return;
+    }
+    for channel in &notifications.channels {
+        let Some((url, body)) = payload(channel, event, agent, &message) else {
+            warn!(channel = %channel.channel_type, "Notification channel has no URL");
+            continue;
+        };
+        let mut request = state
+            .http
+            .client()
+            .post(url)
+            .json(&body)
+            .timeout(Duration::from_secs(10));
+        for (name, value) in &channel.headers {
+            request = request.header(name, value);
+        }
+        let http = state.http.clone();
+        let channel_type = channel.channel_type.clone();
+        tokio::spawn(async move {
+            match http.send(request).await {
+                Ok(resp) if resp.status().is_success() => {
+                    debug!(channel = %channel_type, "Notification sent");
+                }
+                Ok(resp) => {
+                    warn!(channel = %channel_type, status = %resp.status(), "Notification rejected");
+                }
+                Err(e) => {
+                    warn!(channel = %channel_type, error = %e.without_url(), "Notification failed");
+                }
+            }
+        });
+    }
+}
+
+/// URL and JSON body for one channel:
+/// `slack` posts `{text, channel}` to `webhook_url`, `telegram` posts
+/// `{chat_id, text}` to `url`, anything else posts `{event, agent, message}` to `url`
+fn payload(
+    channel: &NotificationChannel,
+    event: &str,
+    agent: &str,
+    message: &str,
+) -> Option<(String, Value)> {
+    match channel.channel_type.as_str() {
+        "slack" => {
+            let url = channel.webhook_url.clone().or(channel.url.clone())?;
+            let mut body = json!({ "text": message });
+            if let Some(target) = &channel.channel {
+                body["channel"] = json!(target);
+            }
+            Some((url, body))
+        }
+        "telegram" => Some((
+            channel.url.clone()?,
+            json!({ "chat_id": channel.channel, "text": message }),
+        )),
+        _ => Some((
+            channel.url.clone().or(channel.webhook_url.clone())?,
+            json!({ "event": event, "agent": agent, "message": message }),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(yaml: &str) -> NotificationChannel {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_notification_payloads() {
+        let slack =
+            channel("type: slack\nwebhook_url: https://hooks.slack.test/x\nchannel: \"#ops\"");
+        let (url, body) = payload(&slack, "agent_offline", "web-1", "web-1 is offline").unwrap();
+        assert_eq!(url, "https://hooks.slack.test/x");
+        assert_eq!(
+            body,
+            json!({ "text": "web-1 is offline", "channel": "#ops" })
+        );
+
+        let telegram =
+            channel("type: telegram\nurl: https://api.telegram.test/send\nchannel: \"42\"");
+        let (_, body) = payload(&telegram, "agent_offline", "web-1", "down").unwrap();
+        assert_eq!(body, json!({ "chat_id": "42", "text": "down" }));
+
+        let webhook = channel("type: webhook\nurl: https://alerts.test/hook");
+        let (_, body) = payload(&webhook, "agent_recovered", "web-1", "up").unwrap();
+        assert_eq!(
+            body,
+            json!({ "event": "agent_recovered", "agent": "web-1", "message": "up" })
+        );
+
+        assert!(payload(&channel("type: webhook"), "agent_offline", "web-1", "down").is_none());
+    }
+}
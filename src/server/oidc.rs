@@ -0,0 +1,346 @@
+use crate::config::OidcConfig;
+use crate::http_client::HttpClient;
+use crate::server::auth::constant_time_eq;
+use crate::server::session;
+use base64::Engine;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// How long a started login may take to come back to the callback
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Upper bound on logins waiting for their callback
+const MAX_PENDING_LOGINS: usize = 1000;
+
+/// Provider metadata from `/.well-known/openid-configuration`
+#[derive(Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+struct IdClaims {
+    sub: String,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: Option<bool>,
+    #[serde(default)]
+    nonce: Option<String>,
+}
+
+/// A login waiting for the provider to redirect back
+struct PendingLogin {
+    nonce: String,
+    verifier: String,
+    next: String,
+    started: Instant,
+}
+
+/// Authorization code flow (with PKCE) against an OpenID Connect provider
+pub struct OidcClient {
+    config: OidcConfig,
+    http: HttpClient,
+    pending: Mutex<HashMap<String, PendingLogin>>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig, http: HttpClient) -> Self {
+        Self {
+            config,
+            http,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Session cookies are `Secure` when the dashboard is reached over HTTPS
+    pub fn secure_cookies(&self) -> bool {
+        self.config.redirect_url.starts_with("https://")
+    }
+
+    /// How long the browser keeps the state of a started login
+    pub fn login_timeout(&self) -> Duration {
+        LOGIN_TIMEOUT
+    }
+
+    pub fn session_ttl(&self) -> Duration {
+        humantime::parse_duration(&self.config.session_ttl).unwrap_or(Duration::from_secs(8 * 3600))
+    }
+
+    /// Start a login: remember its state and return the provider's
+    /// authorization URL and the state, which the browser keeps in a cookie
+    pub async fn login_url(&self, next: Option<&str>) -> Result<(String, String), String> {
+        let discovery = self.discover().await?;
+
+        let state = random_token();
+        let nonce = random_token();
+        let verifier = random_token();
+        let challenge =
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(&verifier));
+
+        let url = reqwest::Url::parse_with_params(
+            &discovery.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.config.redirect_url.as_str()),
+                ("scope", self.config.scopes.join(" ").as_str()),
+                ("state", state.as_str()),
+                ("nonce", nonce.as_str()),
+                ("code_challenge", challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|e| format!("Invalid authorization endpoint: {}", e))?;
+
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.retain(|_, login| login.started.elapsed() < LOGIN_TIMEOUT);
+        if pending.len() >= MAX_PENDING_LOGINS {
+            return Err("Too many logins in progress".to_string());
+        }
+        pending.insert(
+            state.clone(),
+            PendingLogin {
+                nonce,
+                verifier,
+                next: session::local_path(next).to_string(),
+                started: Instant::now(),
+            },
+        );
+        Ok((url.to_string(), state))
+    }
+
+    /// Finish a login from the callback: returns the user's email and the page
+    /// to return to. `cookie_state` is the state saved in the browser that
+    /// started the login; a callback from any other browser is rejected.
+    pub async fn complete(
+        &self,
+        code: &str,
+        state: &str,
+        cookie_state: Option<&str>,
+    ) -> Result<(String, String), String> {
+        if !cookie_state.is_some_and(|saved| constant_time_eq(saved.as_bytes(), state.as_bytes())) {
+            return Err("Login state does not belong to this browser".to_string());
+        }
+        let login = self
+            .pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(state)
+            .filter(|login| login.started.elapsed() < LOGIN_TIMEOUT)
+            .ok_or("Unknown or expired login state")?;
+
+        let discovery = self.discover().await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_url.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", login.verifier.as_str()),
+        ];
+        if let Some(ref secret) = self.config.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+        let response = self
+            .http
+            .send(
+                self.http
+                    .client()
+                    .post(&discovery.token_endpoint)
+                    .form(&form),
+            )
+            .await
+            .map_err(|e| format!("Token request failed: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Token endpoint returned {}", response.status()));
+        }
+        let tokens: TokenResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Invalid token response: {}", e))?;
+
+        let claims = self.verify_id_token(&discovery, &tokens.id_token).await?;
+        if claims.nonce.as_deref() != Some(login.nonce.as_str()) {
+            return Err("ID token nonce mismatch".to_string());
+        }
+
+        let user = self.authorize(&claims)?;
+        Ok((user, login.next))
+    }
+
+    /// Check the ID token signature against the provider's keys, plus issuer,
+    /// audience and expiry
+    async fn verify_id_token(
+        &self,
+        discovery: &Discovery,
+        id_token: &str,
+    ) -> Result<IdClaims, String> {
+        let header = decode_header(id_token).map_err(|e| format!("Invalid ID token: {}", e))?;
+        // Shared-secret algorithms would let anyone holding the client secret mint tokens
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(format!("Unsupported ID token algorithm {:?}", header.alg));
+        }
+
+        let jwks: JwkSet = self
+            .http
+            .send(self.http.client().get(&discovery.jwks_uri))
+            .await
+            .map_err(|e| format!("Failed to fetch provider keys: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Invalid provider keys: {}", e))?;
+        let jwk = match header.kid {
+            Some(ref kid) => jwks.find(kid),
+            None => jwks.keys.first(),
+        }
+        .ok_or("ID token signed with an unknown key")?;
+        let key =
+            DecodingKey::from_jwk(jwk).map_err(|e| format!("Unusable provider key: {}", e))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_issuer(&[&discovery.issuer]);
+        decode::<IdClaims>(id_token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(|e| format!("ID token rejected: {}", e))
+    }
+
+    /// Apply `allowed_emails`; the provider must mark the email verified, and
+    /// an empty list admits nobody
+    fn authorize(&self, claims: &IdClaims) -> Result<String, String> {
+        let email = claims
+            .email
+            .as_deref()
+            .filter(|_| claims.email_verified == Some(true))
+            .ok_or_else(|| {
+                format!(
+                    "Provider did not return a verified email for subject {}",
+                    claims.sub
+                )
+            })?
+            .to_lowercase();
+        let allowed = self.config.allowed_emails.iter().any(|pattern| {
+            glob::Pattern::new(&pattern.to_lowercase())
+                .map(|p| p.matches(&email))
+                .unwrap_or(false)
+        });
+        if allowed {
+            Ok(email)
+        } else {
+            Err(format!("{} is not allowed to use the dashboard", email))
+        }
+    }
+
+    async fn discover(&self) -> Result<Discovery, String> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            self.config.issuer.trim_end_matches('/')
+        );
+        self.http
+            .send(self.http.client().get(&url))
+            .await
+            .map_err(|e| format!("OIDC discovery failed: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Invalid OIDC discovery document: {}", e))
+    }
+}
+
+fn random_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(allowed_emails: &[&str]) -> OidcClient {
+        OidcClient::new(
+            OidcConfig {
+                issuer: "https://sso.example.com".to_string(),
+                client_id: "infractl".to_string(),
+                client_secret: None,
+                redirect_url: "https://home.example.com/auth/oidc/callback".to_string(),
+                scopes: vec!["openid".to_string(), "email".to_string()],
+                allowed_emails: allowed_emails.iter().map(|s| s.to_string()).collect(),
+                session_ttl: "8h".to_string(),
+            },
+            HttpClient::default(),
+        )
+    }
+
+    fn claims(email: Option<&str>, verified: Option<bool>) -> IdClaims {
+        IdClaims {
+            sub: "1234".to_string(),
+            email: email.map(String::from),
+            email_verified: verified,
+            nonce: None,
+        }
+    }
+
+    #[test]
+    fn test_allowed_emails() {
+        let closed = client(&[]);
+        assert!(closed.authorize(&claims(None, None)).is_err());
+        assert!(closed
+            .authorize(&claims(Some("alice@ops.example.com"), Some(true)))
+            .is_err());
+
+        let ops = client(&["*@ops.example.com"]);
+        assert_eq!(
+            ops.authorize(&claims(Some("Alice@ops.example.com"), Some(true)))
+                .unwrap(),
+            "alice@ops.example.com"
+        );
+        assert!(ops
+            .authorize(&claims(Some("bob@example.com"), Some(true)))
+            .is_err());
+        assert!(ops
+            .authorize(&claims(Some("eve@ops.example.com"), Some(false)))
+            .is_err());
+        assert!(ops.authorize(&claims(None, None)).is_err());
+        // A provider that does not say the email is verified is not trusted
+        assert!(ops
+            .authorize(&claims(Some("alice@ops.example.com"), None))
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_login_state_is_bound_to_the_browser() {
+        let ops = client(&["*@ops.example.com"]);
+        ops.pending.lock().unwrap().insert(
+            "state-1".to_string(),
+            PendingLogin {
+                nonce: random_token(),
+                verifier: random_token(),
+                next: "/monitoring".to_string(),
+                started: Instant::now(),
+            },
+        );
+
+        // Rejected before the state is used up or the provider is contacted
+        for cookie in [None, Some("state-2")] {
+            let err = ops.complete("code", "state-1", cookie).await.unwrap_err();
+            assert!(err.contains("browser"), "{}", err);
+        }
+        assert!(ops.pending.lock().unwrap().contains_key("state-1"));
+    }
+}
@@ -0,0 +1,289 @@
+use crate::config::{Config, CustomMetricConfig};
+use crate::metrics::{custom, DockerCollector, MetricsReport, SystemCollector};
+use crate::server::agents::agent_url;
+use crate::server::auth::Audience;
+use crate::server::AppState;
+use crate::storage::buffer::MetricsBuffer;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, warn};
+
+/// Longest a single push may take
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Buffered reports sent per interval once Home is back, so a long backlog
+/// catches up gradually instead of arriving at once
+const FLUSH_PER_INTERVAL: usize = 100;
+
+/// Collect metrics every `modules.metrics.collect_interval` and push them to
+/// Home's `POST /api/ingest/metrics` until shutdown starts (agent mode)
+pub async fn push_metrics(state: Arc<AppState>, home_address: String) {
+    let metrics = &state.config.modules.metrics;
+    let interval =
+        humantime::parse_duration(&metrics.collect_interval).unwrap_or(Duration::from_secs(30));
+    let agent_name = agent_name(&state.config);
+    let url = agent_url(&home_address, "/api/ingest/metrics");
+
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut shutdown = state.shutdown.subscribe();
+    let mut failing = false;
+    let buffer = open_buffer(&state.config);
+
+    info!(home = %home_address, agent = %agent_name, interval = ?interval, "Pushing metrics to Home");
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown.wait_for(|stopping| *stopping) => break,
+        }
+
+        let report = collect(&state).await;
+        let result = match buffer {
+            Some(ref buffer) => push_buffered(&state, &url, &agent_name, buffer, &report).await,
+            None => push(&state, &url, &agent_name, &report).await,
+        };
+        match result {
+            Ok(()) => {
+                if failing {
+                    info!(home = %home_address, "Metrics push recovered");
+                }
+                failing = false;
+            }
+            // Log once per outage, not on every interval
+            Err(e) if !failing => {
+                warn!(home = %home_address, error = %e, "Metrics push failed");
+                failing = true;
+            }
+            Err(e) => debug!(error = %e, "Metrics push failed"),
+        }
+    }
+}
+
+/// Name this agent is known by on Home (`modules.metrics.agent_name`, or the hostname)
+pub fn agent_name(config: &Config) -> String {
+    config
+        .modules
+        .metrics
+        .agent_name
+        .clone()
+        .or_else(sysinfo::System::host_name)
+        .unwrap_or_else(|| "agent".to_string())
+}
+
+/// Local buffer for reports Home did not accept (`modules.metrics.buffer`)
+fn open_buffer(config: &Config) -> Option<MetricsBuffer> {
+    let settings = &config.modules.metrics.buffer;
+    if !settings.enabled {
+        return None;
+    }
+    if let Some(parent) = Path::new(&settings.path).parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    match MetricsBuffer::open(&settings.path, settings.max_reports) {
+        Ok(buffer) => Some(buffer),
+        Err(e) => {
+            warn!(path = %settings.path, error = %e, "Metrics buffer unavailable, failed pushes are lost");
+            None
+        }
+    }
+}
+
+/// Send buffered reports oldest first, then `report`. On failure, or while
+/// older reports are still waiting, `report` is buffered behind them, so
+/// Home receives every report in the order it was collected.
+async fn push_buffered(
+    state: &AppState,
+    url: &str,
+    agent_name: &str,
+    buffer: &MetricsBuffer,
+    report: &MetricsReport,
+) -> Result<(), String> {
+    let buffer_error = |e: rusqlite::Error| format!("Metrics buffer: {}", e);
+
+    let waiting = buffer.oldest(FLUSH_PER_INTERVAL).map_err(buffer_error)?;
+    let mut result = Ok(());
+    let mut sent = 0;
+    for (id, json) in &waiting {
+        // Reports that no longer parse (older agent version) are skipped
+        if let Ok(buffered) = serde_json::from_str::<MetricsReport>(json) {
+            result = push(state, url, agent_name, &buffered).await;
+            if result.is_err() {
+                break;
+            }
+            sent += 1;
+        }
+        buffer.remove(*id).map_err(buffer_error)?;
+    }
+    if sent > 0 {
+        info!(reports = sent, "Sent buffered metrics");
+    }
+
+    if result.is_ok() && buffer.len().map_err(buffer_error)? == 0 {
+        result = push(state, url, agent_name, report).await;
+        if result.is_ok() {
+            return Ok(());
+        }
+    }
+    let json = serde_json::to_string(report).map_err(|e| e.to_string())?;
+    let dropped = buffer.push(&json).map_err(buffer_error)?;
+    if dropped > 0 {
+        debug!(dropped, "Metrics buffer full, dropped the oldest reports");
+    }
+    result
+}
+
+async fn collect(state: &AppState) -> MetricsReport {
+    let system =
+        SystemCollector::collect_with_processes(state.config.modules.metrics.top_processes);
+    let docker = if state.config.modules.metrics.docker_stats {
+        match DockerCollector::new(state.config.modules.deploy.container_runtime).await {
+            Ok(collector) => Some(collector.collect().await),
+            Err(_) => None,
+        }
+    } else {
+        None
+    };
+
+    MetricsReport {
+        collected_at: OffsetDateTime::now_utc(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_seconds: state.uptime_seconds(),
+        system,
+        docker,
+        custom: state.custom_metrics.take_unreported(),
+    }
+}
+
+/// Run a `modules.metrics.custom` command every `interval` (default
+/// `collect_interval`) and keep its samples for the next metrics report,
+/// until shutdown starts
+pub async fn run_custom_metric(state: Arc<AppState>, metric: CustomMetricConfig) {
+    let interval = metric
+        .interval
+        .as_deref()
+        .unwrap_or(&state.config.modules.metrics.collect_interval);
+    let interval = humantime::parse_duration(interval).unwrap_or(Duration::from_secs(30));
+    let timeout = humantime::parse_duration(&metric.timeout).unwrap_or(Duration::from_secs(10));
+
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut shutdown = state.shutdown.subscribe();
+    let mut failing = false;
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown.wait_for(|stopping| *stopping) => break,
+        }
+
+        match custom::run(&metric.command, timeout).await {
+            Ok(value) => {
+                state.custom_metrics.set(&metric.name, value);
+                failing = false;
+            }
+            Err(e) => {
+                state.custom_metrics.clear(&metric.name);
+                // Log once per run of failures, not on every interval
+                if !failing {
+                    warn!(metric = %metric.name, error = %e, "Custom metric failed");
+                }
+                failing = true;
+            }
+        }
+    }
+}
+
+async fn push(
+    state: &AppState,
+    url: &str,
+    agent_name: &str,
+    report: &MetricsReport,
+) -> Result<(), String> {
+    let token = state
+        .jwt_keys
+        .signer()
+        .generate_token(agent_name, 1, &[Audience::Agent])
+        .map_err(|e| format!("Failed to generate token: {}", e))?;
+
+    // No retries: the next interval brings fresher metrics
+    let resp = state
+        .http
+        .client()
+        .post(url)
+        .timeout(PUSH_TIMEOUT)
+        .header("Authorization", format!("Bearer {}", token))
+        .json(report)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to connect: {}", e))?;
+
+    if !resp.status().is_success() {
+        return Err(format!("Home returned {}", resp.status()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::SystemCollector;
+    use axum::{http::StatusCode, routing::post, Json, Router};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_failed_pushes_are_sent_later_in_order() {
+        let home_up = Arc::new(AtomicBool::new(false));
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (up, log) = (home_up.clone(), received.clone());
+        tokio::spawn(async move {
+            let app = Router::new().route(
+                "/api/ingest/metrics",
+                post(move |Json(report): Json<MetricsReport>| async move {
+                    if !up.load(Ordering::Relaxed) {
+                        return StatusCode::SERVICE_UNAVAILABLE;
+                    }
+                    log.lock().unwrap().push(report.uptime_seconds);
+                    StatusCode::ACCEPTED
+                }),
+            );
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let config: Config = serde_yaml::from_str(
+            "mode: agent\nserver: {}\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\n",
+        )
+        .unwrap();
+        let state = AppState::new(config);
+        let url = agent_url(&address.to_string(), "/api/ingest/metrics");
+        let buffer = MetricsBuffer::open(":memory:", 2).unwrap();
+        let system = SystemCollector::collect();
+        let report = |n: u64| MetricsReport {
+            collected_at: OffsetDateTime::now_utc(),
+            version: "1.2.3".to_string(),
+            uptime_seconds: n,
+            system: system.clone(),
+            docker: None,
+            custom: Default::default(),
+        };
+
+        // Home down: reports wait, the oldest dropped beyond capacity
+        for n in 1..=3 {
+            assert!(push_buffered(&state, &url, "web-1", &buffer, &report(n))
+                .await
+                .is_err());
+        }
+        assert_eq!(buffer.len().unwrap(), 2);
+
+        home_up.store(true, Ordering::Relaxed);
+        push_buffered(&state, &url, "web-1", &buffer, &report(4))
+            .await
+            .unwrap();
+        assert_eq!(*received.lock().unwrap(), [2, 3, 4]);
+        assert_eq!(buffer.len().unwrap(), 0);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::config::{self, Config};
+use crate::server::app_state::LiveConfig;
+use crate::server::{agents, AppState};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+/// Reload the config file on every SIGHUP until shutdown starts
+#[cfg(unix)]
+pub async fn watch_sighup(state: Arc<AppState>, config_path: PathBuf) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup()).expect("Failed to install SIGHUP handler");
+    let mut shutdown = state.shutdown.subscribe();
+
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => {}
+            _ = shutdown.wait_for(|stopping| *stopping) => break,
+        }
+
+        info!(path = %config_path.display(), "Received SIGHUP, reloading config");
+        if let Err(e) = reload(&state, &config_path).await {
+            error!(error = %e, "Config reload failed, keeping current config");
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn watch_sighup(_state: Arc<AppState>, _config_path: PathBuf) {}
+
+/// Load and validate the config file, then swap in its reloadable parts.
+/// An invalid file leaves the running config untouched.
+pub async fn reload(state: &Arc<AppState>, config_path: &Path) -> Result<(), String> {
+    let config = config::load(config_path).map_err(|e| e.to_string())?;
+    apply(state, &config).await;
+    Ok(())
+}
+
+/// Replace deployments, webhook endpoints, network isolation, rate limits and
+/// the polled agents. Open connections, queued jobs and rate limiter counters
+/// are kept; other settings (bind address, TLS, auth, modules) still need a
+/// restart.
+pub async fn apply(state: &Arc<AppState>, config: &Config) {
+    if config.mode != state.config.mode {
+        warn!(
+            current = ?state.config.mode,
+            configured = ?config.mode,
+            "Mode changes take effect after a restart"
+        );
+    }
+
+    *state.deploy_config.write().await = config.modules.deploy.clone();
+    let live = LiveConfig::load(config, state.db.as_deref());
+    agents::sync_health_polls(state, &live.agents);
+    *state.live.write().await = live;
+    state
+        .rate_limiter
+        .reconfigure(&config.server.rate_limit, config.fleet_ips())
+        .await;
+
+    info!(
+        deployments = config.modules.deploy.deployments.len(),
+        webhooks = config.modules.webhooks.endpoints.len(),
+        allowed_networks = config.server.allowed_networks.len(),
+        "Config reloaded"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+
+    fn config(yaml: &str) -> Config {
+        serde_yaml::from_str(&format!(
+            "mode: agent\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\n{}",
+            yaml
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_apply_swaps_reloadable_settings() {
+        let state = AppState::new(config(
+            "server:\n  allowed_networks: [\"10.0.0.0/8\"]\n  rate_limit:\n    max_requests: 1\n",
+        ));
+        let ip: IpAddr = "192.168.1.5".parse().unwrap();
+        assert!(state.rate_limiter.check(ip, "/api/status").await);
+        assert!(!state.rate_limiter.check(ip, "/api/status").await);
+
+        apply(
+            &state,
+            &config(concat!(
+                "server:\n  allowed_networks: [\"192.168.0.0/16\"]\n",
+                "  rate_limit:\n    exempt_networks: [\"192.168.0.0/16\"]\n",
+                "modules:\n  webhooks:\n    endpoints:\n      - path: /webhook/app\n        deployment: app\n",
+            )),
+        )
+        .await;
+
+        let live = state.live.read().await;
+        assert_eq!(live.allowed_networks, vec!["192.168.0.0/16".to_string()]);
+        assert_eq!(live.webhooks.endpoints.len(), 1);
+        assert!(state.rate_limiter.check(ip, "/api/status").await);
+    }
+}
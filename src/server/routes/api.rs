@@ -1,11 +1,19 @@
-use crate::config::DeploymentConfig;
+use crate::config::{self, DeploymentConfig};
+use crate::deploy::ComposeDiff;
+use crate::metrics::MetricsReport;
+use crate::server::auth::Claims;
+use crate::server::etag;
 use crate::server::middleware::ErrorResponse;
 use crate::server::AppState;
-use crate::storage::{AggregationType, DeployRecord, MetricRecord, MetricsQuery};
+use crate::storage::{
+    AgentStatus, AggregationType, ContainerMetricsQuery, CustomMetricsQuery, DbWrite,
+    DeployHistoryQuery, DeployRecord, DeploySearchHit, DeployStatus, MetricRecord, MetricsQuery,
+};
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
-    Json,
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
+    Extension, Json,
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -109,10 +117,8 @@ pub async fn get_metrics(
             })))
         }
         AggregationType::Daily => {
-            // For daily, we'd need to add a get_daily_metrics method
-            // For now, use hourly as fallback
             let agent = params.agent.unwrap_or_default();
-            let metrics = db.get_hourly_metrics(&agent, from, to).map_err(|e| {
+            let metrics = db.get_daily_metrics(&agent, from, to).map_err(|e| {
                 ErrorResponse::new(
                     StatusCode::INTERNAL_SERVER_ERROR,
                     &format!("Database error: {}", e),
@@ -129,9 +135,156 @@ pub async fn get_metrics(
     }
 }
 
+#[derive(Deserialize)]
+pub struct ContainerMetricsParams {
+    pub agent: Option<String>,
+    pub project: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub limit: Option<u32>,
+    #[serde(rename = "type")]
+    pub aggregation_type: Option<String>,
+}
+
+/// Largest number of rows `GET /api/containers/:name/metrics` returns
+const MAX_CONTAINER_METRICS: u32 = 10_000;
+
+/// GET /api/containers/:name/metrics - Stats of a container (by container
+/// or compose service name), newest first; `?type=hourly` for rollups
+pub async fn get_container_metrics(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(params): Query<ContainerMetricsParams>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    let db = state.db.as_ref().ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Database not available (Agent mode?)",
+        )
+    })?;
+
+    let parse_time = |value: &Option<String>, field: &str| {
+        value
+            .as_deref()
+            .map(|s| OffsetDateTime::parse(s, &Rfc3339))
+            .transpose()
+            .map_err(|_| {
+                ErrorResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    &format!("Invalid '{}': expected an RFC 3339 timestamp", field),
+                )
+            })
+    };
+    let hourly = match params.aggregation_type.as_deref() {
+        None | Some("raw") => false,
+        Some("hourly") => true,
+        Some(other) => {
+            return Err(ErrorResponse::new(
+                StatusCode::BAD_REQUEST,
+                &format!("Invalid type '{}': expected raw or hourly", other),
+            ))
+        }
+    };
+    let query = ContainerMetricsQuery {
+        name,
+        agent_name: params.agent,
+        compose_project: params.project,
+        from: parse_time(&params.from, "from")?,
+        to: parse_time(&params.to, "to")?,
+        limit: params
+            .limit
+            .unwrap_or(if hourly { 168 } else { 100 })
+            .min(MAX_CONTAINER_METRICS),
+    };
+
+    let db_error = |e: rusqlite::Error| {
+        ErrorResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Database error: {}", e),
+        )
+    };
+    let metrics = if hourly {
+        serde_json::json!(db.get_container_hourly_metrics(&query).map_err(db_error)?)
+    } else {
+        serde_json::json!(db.get_container_metrics(&query).map_err(db_error)?)
+    };
+
+    let count = metrics.as_array().map_or(0, Vec::len);
+    Ok(Json(serde_json::json!({
+        "container": query.name,
+        "metrics": metrics,
+        "count": count,
+        "type": if hourly { "hourly" } else { "raw" },
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct CustomMetricsParams {
+    pub agent: Option<String>,
+    pub name: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub limit: Option<u32>,
+}
+
+/// Largest number of rows `GET /api/metrics/custom` returns
+const MAX_CUSTOM_METRICS: u32 = 10_000;
+
+/// GET /api/metrics/custom - Values of user-defined metrics pushed by
+/// agents (`?agent=&name=&from=&to=`), newest first
+pub async fn get_custom_metrics(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<CustomMetricsParams>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    let db = state.db.as_ref().ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Database not available (Agent mode?)",
+        )
+    })?;
+
+    let parse_time = |value: &Option<String>, field: &str| {
+        value
+            .as_deref()
+            .map(|s| OffsetDateTime::parse(s, &Rfc3339))
+            .transpose()
+            .map_err(|_| {
+                ErrorResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    &format!("Invalid '{}': expected an RFC 3339 timestamp", field),
+                )
+            })
+    };
+    let query = CustomMetricsQuery {
+        agent_name: params.agent,
+        name: params.name,
+        from: parse_time(&params.from, "from")?,
+        to: parse_time(&params.to, "to")?,
+        limit: params.limit.unwrap_or(100).min(MAX_CUSTOM_METRICS),
+    };
+
+    let metrics = db.get_custom_metrics(&query).map_err(|e| {
+        ErrorResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Database error: {}", e),
+        )
+    })?;
+    Ok(Json(serde_json::json!({
+        "count": metrics.len(),
+        "metrics": metrics,
+    })))
+}
+
 #[derive(Deserialize)]
 pub struct DeployQueryParams {
     pub agent: Option<String>,
+    pub deployment: Option<String>,
+    pub status: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub offset: Option<u32>,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<i64>,
     pub limit: Option<u32>,
 }
 
@@ -139,8 +292,15 @@ pub struct DeployQueryParams {
 pub struct DeployHistoryResponse {
     pub deployments: Vec<DeployRecord>,
     pub count: usize,
+    /// Records matching the filters across all pages
+    pub total: u64,
+    /// Pass as `cursor` to fetch the next page; absent on the last page
+    pub next_cursor: Option<i64>,
 }
 
+/// Largest page `GET /api/deploys` returns
+const MAX_DEPLOY_PAGE: u32 = 500;
+
 /// GET /api/deploys - Get deployment history
 pub async fn get_deploy_history(
     State(state): State<Arc<AppState>>,
@@ -153,8 +313,138 @@ pub async fn get_deploy_history(
         )
     })?;
 
+    let parse_time = |value: &Option<String>, field: &str| {
+        value
+            .as_deref()
+            .map(|s| OffsetDateTime::parse(s, &Rfc3339))
+            .transpose()
+            .map_err(|_| {
+                ErrorResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    &format!("Invalid '{}': expected an RFC 3339 timestamp", field),
+                )
+            })
+    };
+    let status = params
+        .status
+        .as_deref()
+        .map(str::parse::<DeployStatus>)
+        .transpose()
+        .map_err(|e| ErrorResponse::new(StatusCode::BAD_REQUEST, &e))?;
+
+    let query = DeployHistoryQuery {
+        agent_name: params.agent,
+        deployment_name: params.deployment,
+        status,
+        from: parse_time(&params.from, "from")?,
+        to: parse_time(&params.to, "to")?,
+        before_id: params.cursor,
+        offset: params.offset.unwrap_or(0),
+        limit: params.limit.unwrap_or(50).min(MAX_DEPLOY_PAGE),
+    };
+
+    let page = db.get_deploy_history(&query).map_err(|e| {
+        ErrorResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Database error: {}", e),
+        )
+    })?;
+
+    let count = page.deployments.len();
+    let next_cursor = match page.deployments.last() {
+        Some(last) if count as u32 == query.limit => last.id,
+        _ => None,
+    };
+    Ok(Json(DeployHistoryResponse {
+        deployments: page.deployments,
+        count,
+        total: page.total,
+        next_cursor,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct DeploySearchParams {
+    pub q: Option<String>,
+    pub agent: Option<String>,
+    pub deployment: Option<String>,
+    pub status: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// `newest` (default) or `oldest`
+    pub order: Option<String>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct DeploySearchResponse {
+    pub query: String,
+    pub deployments: Vec<DeploySearchHit>,
+    pub count: usize,
+}
+
+/// GET /api/deploys/search - Deploys whose output or error message contain
+/// the phrase `q`
+pub async fn search_deploys(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<DeploySearchParams>,
+) -> Result<Json<DeploySearchResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let db = state.db.as_ref().ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Database not available (Agent mode?)",
+        )
+    })?;
+
+    let text = params
+        .q
+        .as_deref()
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .ok_or_else(|| ErrorResponse::new(StatusCode::BAD_REQUEST, "Missing search text 'q'"))?
+        .to_string();
+    let oldest_first = match params.order.as_deref() {
+        None | Some("newest") => false,
+        Some("oldest") => true,
+        Some(other) => {
+            return Err(ErrorResponse::new(
+                StatusCode::BAD_REQUEST,
+                &format!("Invalid order '{}': expected newest or oldest", other),
+            ))
+        }
+    };
+    let parse_time = |value: &Option<String>, field: &str| {
+        value
+            .as_deref()
+            .map(|s| OffsetDateTime::parse(s, &Rfc3339))
+            .transpose()
+            .map_err(|_| {
+                ErrorResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    &format!("Invalid '{}': expected an RFC 3339 timestamp", field),
+                )
+            })
+    };
+    let status = params
+        .status
+        .as_deref()
+        .map(str::parse::<DeployStatus>)
+        .transpose()
+        .map_err(|e| ErrorResponse::new(StatusCode::BAD_REQUEST, &e))?;
+
+    let query = DeployHistoryQuery {
+        agent_name: params.agent,
+        deployment_name: params.deployment,
+        status,
+        from: parse_time(&params.from, "from")?,
+        to: parse_time(&params.to, "to")?,
+        before_id: None,
+        offset: 0,
+        limit: params.limit.unwrap_or(20).min(MAX_DEPLOY_PAGE),
+    };
+
     let deployments = db
-        .get_deploy_history(params.agent.as_deref(), params.limit.unwrap_or(50))
+        .search_deploys(&text, &query, oldest_first)
         .map_err(|e| {
             ErrorResponse::new(
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -162,8 +452,45 @@ pub async fn get_deploy_history(
             )
         })?;
 
-    let count = deployments.len();
-    Ok(Json(DeployHistoryResponse { deployments, count }))
+    Ok(Json(DeploySearchResponse {
+        query: text,
+        count: deployments.len(),
+        deployments,
+    }))
+}
+
+/// GET /api/deploys/:id/log - Full output of a deployment (plain text)
+pub async fn get_deploy_log(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
+    let db = state.db.as_ref().ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Database not available (Agent mode?)",
+        )
+    })?;
+
+    let record = db
+        .get_deploy(id)
+        .map_err(|e| {
+            ErrorResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("Database error: {}", e),
+            )
+        })?
+        .ok_or_else(|| ErrorResponse::new(StatusCode::NOT_FOUND, "Deploy not found"))?;
+
+    // Older records (and jobs without output) only have the output column
+    match record.log_file {
+        Some(path) => tokio::fs::read_to_string(&path).await.map_err(|e| {
+            ErrorResponse::new(
+                StatusCode::NOT_FOUND,
+                &format!("Log file {} not available: {}", path, e),
+            )
+        }),
+        None => Ok(record.output.unwrap_or_default()),
+    }
 }
 
 #[derive(Deserialize)]
@@ -199,6 +526,74 @@ pub async fn get_suspicious_requests(
     })))
 }
 
+/// GET /api/bans - List active IP bans
+pub async fn list_bans(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let bans = state.ip_bans.list().await;
+    Json(serde_json::json!({
+        "count": bans.len(),
+        "bans": bans,
+    }))
+}
+
+/// DELETE /api/bans/:ip - Lift a temporary IP ban
+pub async fn unban_ip(
+    State(state): State<Arc<AppState>>,
+    Path(ip): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    let addr: std::net::IpAddr = ip.parse().map_err(|_| {
+        ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid IP address: {}", ip),
+        )
+    })?;
+
+    if !state.ip_bans.unban(addr).await {
+        return Err(ErrorResponse::new(
+            StatusCode::NOT_FOUND,
+            &format!("IP {} is not banned", addr),
+        ));
+    }
+
+    tracing::info!(ip = %addr, "IP ban lifted");
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "ip": addr.to_string(),
+    })))
+}
+
+/// POST /api/ingest/metrics - Metrics pushed by an agent. They are stored
+/// under the token subject, which also marks the agent online.
+pub async fn ingest_metrics(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Json(report): Json<MetricsReport>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let writer = state.db_writer.as_ref().ok_or_else(|| {
+        ErrorResponse::new(StatusCode::SERVICE_UNAVAILABLE, "Database not available")
+    })?;
+
+    writer
+        .send(DbWrite::Metric(report.record(&claims.sub)))
+        .await;
+    for container in report.container_records(&claims.sub) {
+        writer.send(DbWrite::ContainerMetric(container)).await;
+    }
+    for custom in report.custom_records(&claims.sub) {
+        writer.send(DbWrite::CustomMetric(custom)).await;
+    }
+    writer
+        .send(DbWrite::AgentStatus(AgentStatus {
+            agent_name: claims.sub,
+            last_seen: OffsetDateTime::now_utc(),
+            status: "online".to_string(),
+            version: Some(report.version).filter(|v| !v.is_empty()),
+            uptime_seconds: Some(report.uptime_seconds),
+        }))
+        .await;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
 /// GET /api/agents/:name/status - Get agent status
 pub async fn get_agent_status(
     State(state): State<Arc<AppState>>,
@@ -224,9 +619,17 @@ pub async fn get_agent_status(
     }
 }
 
-/// GET /api/agents/statuses - Get all agent statuses
-pub async fn get_all_agent_statuses(
+#[derive(Debug, Deserialize)]
+pub struct AgentEventsParams {
+    pub limit: Option<u32>,
+}
+
+/// GET /api/agents/:name/events - Offline/online transitions of an agent,
+/// newest first
+pub async fn get_agent_events(
     State(state): State<Arc<AppState>>,
+    Path(agent_name): Path<String>,
+    Query(params): Query<AgentEventsParams>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
     let db = state.db.as_ref().ok_or_else(|| {
         ErrorResponse::new(
@@ -235,7 +638,8 @@ pub async fn get_all_agent_statuses(
         )
     })?;
 
-    let statuses = db.get_all_agent_statuses().map_err(|e| {
+    let limit = params.limit.unwrap_or(100).min(1000);
+    let events = db.get_agent_events(&agent_name, limit).map_err(|e| {
         ErrorResponse::new(
             StatusCode::INTERNAL_SERVER_ERROR,
             &format!("Database error: {}", e),
@@ -243,11 +647,69 @@ pub async fn get_all_agent_statuses(
     })?;
 
     Ok(Json(serde_json::json!({
-        "agents": statuses,
-        "count": statuses.len()
+        "agent": agent_name,
+        "events": events,
+        "count": events.len(),
     })))
 }
 
+/// GET /api/agents/discovered - Agents advertising on the LAN that are not
+/// in `agents` yet (`server.mdns`)
+pub async fn get_discovered_agents(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let live = state.live.read().await;
+    let agents: Vec<_> = state
+        .discovered
+        .list()
+        .into_iter()
+        .filter(|d| {
+            !live
+                .agents
+                .iter()
+                .any(|a| a.name == d.name || a.address == d.address)
+        })
+        .collect();
+
+    Json(serde_json::json!({
+        "enabled": state.config.server.mdns,
+        "agents": agents,
+        "count": agents.len(),
+    }))
+}
+
+/// GET /api/agents/statuses - Get all agent statuses
+pub async fn get_all_agent_statuses(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let db = state.db.as_ref().ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Database not available (Agent mode?)",
+        )
+    })?;
+
+    // Unchanged since the client's copy: answer without touching SQLite
+    let etag = format!("\"agents-{:x}\"", db.agent_status_version());
+    if let Some(not_modified) = etag::not_modified(&headers, &etag) {
+        return Ok(not_modified);
+    }
+
+    let statuses = db.get_all_agent_statuses().map_err(|e| {
+        ErrorResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Database error: {}", e),
+        )
+    })?;
+
+    Ok(etag::tagged(
+        Json(serde_json::json!({
+            "agents": statuses,
+            "count": statuses.len()
+        })),
+        &etag,
+    ))
+}
+
 /// GET /api/deployments/:name - Get full deployment config by name
 pub async fn get_deployment_config(
     State(state): State<Arc<AppState>>,
@@ -270,8 +732,105 @@ pub async fn get_deployment_config(
         })
 }
 
+/// PUT /api/deployments/:name - Store a deployment pushed from Home in
+/// `deployments.d/` and load it
+pub async fn put_deployment_config(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(deployment): Json<DeploymentConfig>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    if deployment.name != name || !config::valid_name(&name) {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid deployment name '{}'", deployment.name),
+        ));
+    }
+    let config_path = state.config_path.get().ok_or_else(|| {
+        ErrorResponse::new(StatusCode::SERVICE_UNAVAILABLE, "Config file not known")
+    })?;
+
+    // Held throughout, so pushes are applied one at a time
+    let mut deploy_config = state.deploy_config.write().await;
+    let external_path = deploy_config
+        .external_deployments_path
+        .clone()
+        .ok_or_else(|| {
+            ErrorResponse::new(
+                StatusCode::CONFLICT,
+                "modules.deploy.external_deployments_path is not set",
+            )
+        })?;
+    let path = config::pushed_deployment_path(&external_path, &name);
+    let exists = path.exists();
+    if !exists && deploy_config.deployments.iter().any(|d| d.name == name) {
+        return Err(ErrorResponse::new(
+            StatusCode::CONFLICT,
+            &format!("Deployment '{}' is defined in the agent's own config", name),
+        ));
+    }
+
+    *deploy_config = config::save_pushed_deployment(config_path, &external_path, &deployment)
+        .map_err(|e| {
+            ErrorResponse::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                &format!("Deployment rejected: {}", e),
+            )
+        })?;
+
+    Ok(Json(serde_json::json!({
+        "deployment": name,
+        "path": path,
+        "created": !exists,
+    })))
+}
+
+#[derive(Serialize)]
+pub struct PreviewResponse {
+    pub deployment: String,
+    pub diff: ComposeDiff,
+}
+
+/// GET /api/deployments/:name/preview - Diff a docker_pull deployment's
+/// compose file against the config applied by its last successful deploy
+pub async fn preview_deployment(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<PreviewResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let deploy_config = state.deploy_config.read().await;
+    let allowed_deploy_paths = deploy_config.allowed_deploy_paths.clone();
+    let deployment = deploy_config
+        .deployments
+        .iter()
+        .find(|d| d.name == name)
+        .cloned()
+        .ok_or_else(|| {
+            ErrorResponse::new(
+                StatusCode::NOT_FOUND,
+                &format!("Deployment '{}' not found", name),
+            )
+        })?;
+    drop(deploy_config);
+
+    let executor = state.deploy_executor.as_ref().ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Deploy executor not available",
+        )
+    })?;
+
+    let diff = executor
+        .preview(&deployment, &allowed_deploy_paths)
+        .await
+        .map_err(|e| ErrorResponse::new(StatusCode::UNPROCESSABLE_ENTITY, &e))?;
+
+    Ok(Json(PreviewResponse {
+        deployment: name,
+        diff,
+    }))
+}
+
 /// GET /api/deployments - Get configured deployments list
-pub async fn get_deployments(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+pub async fn get_deployments(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
     let deploy_config = state.deploy_config.read().await;
     let deployments: Vec<_> = deploy_config
         .deployments
@@ -286,8 +845,15 @@ pub async fn get_deployments(State(state): State<Arc<AppState>>) -> Json<serde_j
         })
         .collect();
 
-    Json(serde_json::json!({
+    let body = serde_json::to_vec(&serde_json::json!({
         "deployments": deployments,
         "count": deployments.len()
     }))
+    .unwrap_or_default();
+
+    let etag = etag::of_bytes(&body);
+    if let Some(not_modified) = etag::not_modified(&headers, &etag) {
+        return not_modified;
+    }
+    etag::tagged(([(header::CONTENT_TYPE, "application/json")], body), &etag)
 }
@@ -0,0 +1,250 @@
+use crate::server::auth::{constant_time_eq, Audience, Claims};
+use crate::server::middleware::ErrorResponse;
+use crate::server::AppState;
+use crate::server::{assets, session};
+use axum::{
+    extract::{ConnectInfo, Form, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{AppendHeaders, IntoResponse, Redirect, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Serialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: &'static str,
+    /// Access token lifetime in seconds
+    pub expires_in: u64,
+    /// Replacement for the redeemed refresh token
+    pub refresh_token: String,
+}
+
+/// POST /auth/refresh - Exchange a refresh token for an access token
+pub async fn refresh(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(request): Json<RefreshRequest>,
+) -> Result<Json<TokenResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let redeemed = state
+        .refresh_tokens
+        .redeem(&request.refresh_token)
+        .map_err(|e| {
+            warn!(error = %e, "Refresh token store unavailable");
+            ErrorResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Token store unavailable")
+        })?;
+
+    let Some((grant, refresh_token)) = redeemed else {
+        state.ip_bans.record_violation(addr.ip()).await;
+        return Err(ErrorResponse::new(
+            StatusCode::UNAUTHORIZED,
+            "Invalid or expired refresh token",
+        ));
+    };
+
+    let ttl = humantime::parse_duration(&state.config.auth.access_token_ttl)
+        .unwrap_or(std::time::Duration::from_secs(15 * 60));
+    let mut claims = Claims::with_ttl(
+        &grant.subject,
+        time::Duration::seconds(ttl.as_secs() as i64),
+        &[],
+    );
+    claims.aud = grant.audiences;
+    claims.scopes = grant.scopes;
+
+    let access_token = state
+        .jwt_keys
+        .signer()
+        .encode_claims(&claims)
+        .map_err(|e| ErrorResponse::new(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+
+    info!(subject = %claims.sub, "Access token refreshed");
+    Ok(Json(TokenResponse {
+        access_token,
+        token_type: "Bearer",
+        expires_in: ttl.as_secs(),
+        refresh_token,
+    }))
+}
+
+/// POST /auth/revoke - Invalidate a refresh token (logout)
+pub async fn revoke(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<RefreshRequest>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    match state.refresh_tokens.revoke(&request.refresh_token) {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(ErrorResponse::new(
+            StatusCode::NOT_FOUND,
+            "Unknown refresh token",
+        )),
+        Err(e) => {
+            warn!(error = %e, "Refresh token store unavailable");
+            Err(ErrorResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Token store unavailable",
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LoginForm {
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+/// GET /auth/login - Dashboard login form
+pub async fn login_page(State(state): State<Arc<AppState>>) -> Response {
+    assets::serve_login_page(
+        state.config.auth.dashboard.password.is_some(),
+        state.oidc.is_some(),
+    )
+    .await
+}
+
+/// POST /auth/login - Start a dashboard session with the shared password or an API token
+pub async fn login(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Form(form): Form<LoginForm>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let next = session::local_path(form.next.as_deref());
+
+    let Some(user) = login_user(&state, &form) else {
+        warn!(client_ip = %addr.ip(), "Dashboard login rejected");
+        state.ip_bans.record_violation(addr.ip()).await;
+        return Ok(Redirect::to(&session::login_path(next, true)).into_response());
+    };
+
+    let auth = &state.config.auth;
+    let ttl = humantime::parse_duration(&auth.dashboard.session_ttl)
+        .unwrap_or(std::time::Duration::from_secs(8 * 3600));
+    let cookie = session::issue(
+        &state.jwt_keys,
+        &user,
+        ttl,
+        state.config.server.tls.is_some(),
+    )
+    .map_err(|e| ErrorResponse::new(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+
+    info!(user = %user, "Dashboard login");
+    Ok(([(header::SET_COOKIE, cookie)], Redirect::to(next)).into_response())
+}
+
+/// Session user for a login form: "dashboard" for the shared password, the
+/// token subject for an `api` token allowed to read metrics
+fn login_user(state: &AppState, form: &LoginForm) -> Option<String> {
+    let auth = &state.config.auth;
+
+    if let (Some(expected), Some(given)) = (&auth.dashboard.password, &form.password) {
+        if !given.is_empty() && constant_time_eq(expected.as_bytes(), given.as_bytes()) {
+            return Some("dashboard".to_string());
+        }
+    }
+
+    let token = form
+        .token
+        .as_deref()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())?;
+    state
+        .jwt_keys
+        .validate(token)
+        .ok()
+        .filter(|claims| claims.has_audience(&[Audience::Api]) && claims.has_scope("metrics:read"))
+        .map(|claims| claims.sub)
+}
+
+/// POST /auth/logout - End the dashboard session
+pub async fn logout(State(state): State<Arc<AppState>>) -> Response {
+    let cookie = session::clear(state.config.server.tls.is_some());
+    ([(header::SET_COOKIE, cookie)], Redirect::to("/auth/login")).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct OidcLoginQuery {
+    pub next: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: String,
+    pub state: String,
+}
+
+/// GET /auth/oidc/login - Redirect to the SSO provider; the login state is
+/// kept in a short-lived cookie so only this browser can finish the login
+pub async fn oidc_login(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<OidcLoginQuery>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let oidc = state
+        .oidc
+        .as_ref()
+        .ok_or_else(|| ErrorResponse::new(StatusCode::NOT_FOUND, "OIDC login is not configured"))?;
+
+    let (url, login_state) = oidc.login_url(query.next.as_deref()).await.map_err(|e| {
+        warn!(error = %e, "Failed to start OIDC login");
+        ErrorResponse::new(StatusCode::BAD_GATEWAY, "SSO provider unavailable")
+    })?;
+    let cookie = session::login_state(&login_state, oidc.login_timeout(), oidc.secure_cookies());
+    Ok(([(header::SET_COOKIE, cookie)], Redirect::to(&url)).into_response())
+}
+
+/// GET /auth/oidc/callback - Finish the SSO login and set the dashboard session cookie
+pub async fn oidc_callback(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<OidcCallbackQuery>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let oidc = state
+        .oidc
+        .as_ref()
+        .ok_or_else(|| ErrorResponse::new(StatusCode::NOT_FOUND, "OIDC login is not configured"))?;
+
+    let saved_state = session::saved_login_state(&headers);
+    let (user, next) = match oidc.complete(&query.code, &query.state, saved_state).await {
+        Ok(login) => login,
+        Err(e) => {
+            warn!(client_ip = %addr.ip(), error = %e, "OIDC login rejected");
+            state.ip_bans.record_violation(addr.ip()).await;
+            return Err(ErrorResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "SSO login failed",
+            ));
+        }
+    };
+
+    let cookie = session::issue(
+        &state.jwt_keys,
+        &user,
+        oidc.session_ttl(),
+        oidc.secure_cookies(),
+    )
+    .map_err(|e| ErrorResponse::new(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))?;
+
+    info!(user = %user, "Dashboard SSO login");
+    let clear_state = session::clear_login_state(oidc.secure_cookies());
+    Ok((
+        AppendHeaders([
+            (header::SET_COOKIE, cookie),
+            (header::SET_COOKIE, clear_state),
+        ]),
+        Redirect::to(&next),
+    )
+        .into_response())
+}
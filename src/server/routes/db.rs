@@ -0,0 +1,27 @@
+use crate::server::middleware::ErrorResponse;
+use crate::server::AppState;
+use crate::storage::backup::{self, BackupResult};
+use axum::{extract::State, http::StatusCode, Json};
+use std::sync::Arc;
+
+/// POST /api/db/backup - Copy the database to a timestamped file in the
+/// backup directory, deleting backups beyond `modules.storage.backup.keep`
+pub async fn backup(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<BackupResult>, (StatusCode, Json<ErrorResponse>)> {
+    let db = state.db.clone().ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Database not available (Agent mode?)",
+        )
+    })?;
+    let storage = &state.config.modules.storage;
+    let (dir, keep) = (storage.backup_dir(), storage.backup.keep);
+
+    tokio::task::spawn_blocking(move || backup::backup(&db, &dir, keep))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result)
+        .map(Json)
+        .map_err(|e| ErrorResponse::new(StatusCode::INTERNAL_SERVER_ERROR, &e))
+}
@@ -0,0 +1,304 @@
+use crate::config::AgentConfig;
+use crate::server::agents;
+use crate::server::auth::AgentKeyStore;
+use crate::server::middleware::ErrorResponse;
+use crate::server::AppState;
+use crate::storage::EnrolledAgent;
+use axum::{
+    extract::{ConnectInfo, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+#[derive(Deserialize)]
+pub struct EnrollRequest {
+    pub join_token: String,
+    /// Used as the agent name when the join token names none
+    pub hostname: Option<String>,
+    /// URL Home reaches the agent at; defaults to the request's source IP and `port`
+    pub address: Option<String>,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Home address as the agent reached it, written to the agent's config
+    pub home_address: String,
+}
+
+fn default_port() -> u16 {
+    8111
+}
+
+#[derive(Serialize)]
+pub struct EnrollResponse {
+    pub name: String,
+    pub address: String,
+    /// Initial agent config (YAML), including the agent's own signing key
+    pub config: String,
+}
+
+/// POST /auth/enroll - Register a new agent with a join token (Home mode)
+pub async fn enroll(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(request): Json<EnrollRequest>,
+) -> Result<Json<EnrollResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let db = state.db.as_ref().ok_or_else(|| {
+        ErrorResponse::new(StatusCode::SERVICE_UNAVAILABLE, "Database not available")
+    })?;
+    // Enrolled hosts get a key of their own, never Home's master secret
+    let agent_keys = state.agent_keys.as_ref().ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::FORBIDDEN,
+            "Enrollment requires per-agent keys (auth.agent_keys)",
+        )
+    })?;
+
+    // Validate before spending the token, so a bad request can be retried
+    let grant = state
+        .join_tokens
+        .peek(&request.join_token)
+        .map_err(store_unavailable)?;
+    let Some(grant) = grant else {
+        state.ip_bans.record_violation(addr.ip()).await;
+        return Err(ErrorResponse::new(
+            StatusCode::UNAUTHORIZED,
+            "Invalid or expired join token",
+        ));
+    };
+
+    let name = grant
+        .name
+        .clone()
+        .or(request.hostname.clone())
+        .ok_or_else(|| {
+            ErrorResponse::new(
+                StatusCode::BAD_REQUEST,
+                "The join token names no agent, hostname is required",
+            )
+        })?;
+    if !valid_agent_name(&name) {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid agent name '{}'", name),
+        ));
+    }
+    if state
+        .live
+        .read()
+        .await
+        .agents
+        .iter()
+        .any(|a| a.name == name)
+    {
+        return Err(ErrorResponse::new(
+            StatusCode::CONFLICT,
+            &format!("Agent '{}' already exists", name),
+        ));
+    }
+
+    let address = request.address.clone().unwrap_or_else(|| match addr.ip() {
+        IpAddr::V4(ip) => format!("http://{}:{}", ip, request.port),
+        IpAddr::V6(ip) => format!("http://[{}]:{}", ip, request.port),
+    });
+
+    // Before the token is spent: a store error must not lose it
+    let config = agent_config(&state, agent_keys, &name, &request).map_err(store_unavailable)?;
+
+    // Two enrollments racing for one token: only one redeems it
+    if state
+        .join_tokens
+        .redeem(&request.join_token)
+        .map_err(store_unavailable)?
+        .is_none()
+    {
+        return Err(ErrorResponse::new(
+            StatusCode::UNAUTHORIZED,
+            "Invalid or expired join token",
+        ));
+    }
+
+    let inserted = db
+        .insert_enrolled_agent(&EnrolledAgent {
+            name: name.clone(),
+            address: address.clone(),
+            tags: grant.tags.clone(),
+            enrolled_at: OffsetDateTime::now_utc(),
+        })
+        .map_err(|e| {
+            ErrorResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("Failed to store agent: {}", e),
+            )
+        })?;
+    if !inserted {
+        return Err(ErrorResponse::new(
+            StatusCode::CONFLICT,
+            &format!("Agent '{}' already exists", name),
+        ));
+    }
+
+    let agent = AgentConfig::new(&name, &address, grant.tags);
+    state.live.write().await.agents.push(agent.clone());
+    agents::spawn_health_poll(&state, &agent);
+
+    info!(agent = %name, address = %address, "Agent enrolled");
+    Ok(Json(EnrollResponse {
+        config,
+        name,
+        address,
+    }))
+}
+
+fn store_unavailable(e: String) -> (StatusCode, Json<ErrorResponse>) {
+    warn!(error = %e, "Join token store unavailable");
+    ErrorResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Token store unavailable")
+}
+
+/// Agent names end up in URLs, tokens and metrics rows
+fn valid_agent_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Config for the new agent: it gets its own key and pushes metrics under
+/// its enrolled name
+fn agent_config(
+    state: &AppState,
+    agent_keys: &AgentKeyStore,
+    name: &str,
+    request: &EnrollRequest,
+) -> Result<String, String> {
+    let jwt_secret = agent_keys.signing_key(&state.config.auth.jwt_secret, name)?;
+    let config = serde_json::json!({
+        "mode": "agent",
+        "auth": {
+            "jwt_secret": jwt_secret,
+        },
+        "server": {
+            "port": request.port,
+            "home_address": request.home_address,
+        },
+        "modules": {
+            "metrics": {
+                "agent_name": name,
+            },
+        },
+    });
+    Ok(serde_yaml::to_string(&config).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::storage::{Database, DbWriter};
+
+    fn request(token: &str, hostname: &str) -> EnrollRequest {
+        EnrollRequest {
+            join_token: token.to_string(),
+            hostname: Some(hostname.to_string()),
+            address: None,
+            port: 8111,
+            home_address: "10.0.0.1:8111".to_string(),
+        }
+    }
+
+    fn home_config(dir: &std::path::Path, agent_keys: bool) -> Config {
+        serde_yaml::from_str(&format!(
+            "mode: home\nserver: {{}}\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\n  join_tokens_file: \"{}\"\n  agent_keys: {}\n  agent_keys_file: \"{}\"\nagents:\n  - name: web-1\n    address: \"10.0.0.10:8111\"\n",
+            dir.join("join_tokens.json").display(),
+            agent_keys,
+            dir.join("agent_keys.json").display()
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_enroll_with_join_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = home_config(dir.path(), true);
+        let db = Arc::new(Database::new(":memory:").unwrap());
+        db.migrate().unwrap();
+        let writer = DbWriter::spawn(db.clone(), &config.modules.storage.write_buffer);
+        let state = AppState::with_database(config.clone(), db.clone(), writer.clone());
+
+        let peer: SocketAddr = "10.0.0.20:50000".parse().unwrap();
+
+        // A taken name leaves the token unspent
+        let token = state
+            .join_tokens
+            .issue(None, &["web".to_string()], time::Duration::hours(1))
+            .unwrap();
+        let taken = enroll(
+            State(state.clone()),
+            ConnectInfo(peer),
+            Json(request(&token, "web-1")),
+        )
+        .await;
+        assert_eq!(taken.err().map(|e| e.0), Some(StatusCode::CONFLICT));
+
+        let Json(enrolled) = enroll(
+            State(state.clone()),
+            ConnectInfo(peer),
+            Json(request(&token, "web-2")),
+        )
+        .await
+        .ok()
+        .unwrap();
+        assert_eq!(enrolled.name, "web-2");
+        assert_eq!(enrolled.address, "http://10.0.0.20:8111");
+        let agent_config: Config = serde_yaml::from_str(&enrolled.config).unwrap();
+        assert_eq!(
+            agent_config.auth.jwt_secret,
+            crate::server::auth::agent_key(&config.auth.jwt_secret, "web-2", 0)
+        );
+        assert_eq!(
+            agent_config.modules.metrics.agent_name.as_deref(),
+            Some("web-2")
+        );
+
+        let spent = enroll(
+            State(state.clone()),
+            ConnectInfo(peer),
+            Json(request(&token, "web-3")),
+        )
+        .await;
+        assert_eq!(spent.err().map(|e| e.0), Some(StatusCode::UNAUTHORIZED));
+
+        // Enrolled agents survive a restart
+        let restarted = AppState::with_database(config, db, writer);
+        let live = restarted.live.read().await;
+        assert_eq!(live.agents.len(), 2);
+        assert_eq!(live.agents[1].tags, ["web"]);
+    }
+
+    #[tokio::test]
+    async fn test_enroll_requires_agent_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = home_config(dir.path(), false);
+        let db = Arc::new(Database::new(":memory:").unwrap());
+        db.migrate().unwrap();
+        let writer = DbWriter::spawn(db.clone(), &config.modules.storage.write_buffer);
+        let state = AppState::with_database(config, db, writer);
+
+        let token = state
+            .join_tokens
+            .issue(Some("web-2"), &[], time::Duration::hours(1))
+            .unwrap();
+        let refused = enroll(
+            State(state.clone()),
+            ConnectInfo("10.0.0.20:50000".parse().unwrap()),
+            Json(request(&token, "web-2")),
+        )
+        .await;
+        assert_eq!(refused.err().map(|e| e.0), Some(StatusCode::FORBIDDEN));
+        assert!(state.join_tokens.peek(&token).unwrap().is_some());
+    }
+}
@@ -0,0 +1,100 @@
+use crate::deploy::{CommandPolicy, ResourceLimits, ScriptRunner};
+use crate::server::auth::Claims;
+use crate::server::middleware::ErrorResponse;
+use crate::server::AppState;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExecResult {
+    pub command: String,
+    pub success: bool,
+    /// stdout and stderr, or the failure with the exit code
+    pub output: String,
+    pub duration_ms: u64,
+}
+
+/// POST /api/exec/:command - Run a command allowlisted in `modules.exec.commands`
+///
+/// Only the command's name is sent; what runs is taken from this agent's
+/// config, under the strict shell policy.
+pub async fn run(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Path(command): Path<String>,
+) -> Result<Json<ExecResult>, (StatusCode, Json<ErrorResponse>)> {
+    let exec = &state.config.modules.exec;
+    let entry = exec.commands.get(&command).ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::NOT_FOUND,
+            &format!("Command '{}' is not allowed on this agent", command),
+        )
+    })?;
+    let timeout = humantime::parse_duration(&exec.timeout).unwrap_or(Duration::from_secs(300));
+
+    info!(command = %command, by = %claims.sub, "Running remote command");
+    let started = Instant::now();
+    let result = ScriptRunner::with_timeout(timeout.as_secs())
+        .run_command(
+            entry.cmd(),
+            None,
+            &HashMap::new(),
+            entry.user(),
+            &ResourceLimits::default(),
+            &CommandPolicy::default(),
+        )
+        .await;
+    if let Err(ref e) = result {
+        warn!(command = %command, error = %e, "Remote command failed");
+    }
+
+    Ok(Json(ExecResult {
+        command,
+        success: result.is_ok(),
+        output: result.unwrap_or_else(|e| e),
+        duration_ms: started.elapsed().as_millis() as u64,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::server::auth::Audience;
+
+    #[tokio::test]
+    async fn test_only_allowlisted_commands_run() {
+        let config: Config = serde_yaml::from_str(concat!(
+            "mode: agent\nserver: {}\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\n",
+            "modules:\n  exec:\n    commands:\n      hello: \"echo hello\"\n      fail: \"false\"\n",
+        ))
+        .unwrap();
+        let state = AppState::new(config);
+        let claims = || Extension(Claims::new("home", 1, &[Audience::Api]));
+
+        let Json(hello) = run(State(state.clone()), claims(), Path("hello".to_string()))
+            .await
+            .ok()
+            .unwrap();
+        assert!(hello.success);
+        assert_eq!(hello.output.trim(), "hello");
+
+        let Json(fail) = run(State(state.clone()), claims(), Path("fail".to_string()))
+            .await
+            .ok()
+            .unwrap();
+        assert!(!fail.success);
+        assert!(fail.output.contains("exit code 1"));
+
+        let unknown = run(State(state), claims(), Path("rm".to_string())).await;
+        assert_eq!(unknown.err().map(|e| e.0), Some(StatusCode::NOT_FOUND));
+    }
+}
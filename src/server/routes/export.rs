@@ -0,0 +1,352 @@
+use crate::server::middleware::ErrorResponse;
+use crate::server::AppState;
+use crate::storage::{
+    Database, DeployHistoryQuery, DeployRecord, DeployStatus, MetricRecord, MetricsQuery,
+};
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::warn;
+
+/// Rows read from SQLite per chunk of the response
+const EXPORT_PAGE: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(Deserialize)]
+pub struct ExportParams {
+    pub format: Option<String>,
+    pub agent: Option<String>,
+    pub deployment: Option<String>,
+    pub status: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// GET /api/metrics/export - Raw metrics as CSV (default) or a JSON array,
+/// oldest first (`?agent=&from=&to=`)
+pub async fn metrics(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ExportParams>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let format = parse_format(&params)?;
+    let query = MetricsQuery {
+        agent_name: params.agent,
+        from: parse_time(&params.from, "from")?,
+        to: parse_time(&params.to, "to")?,
+        limit: None,
+        aggregation: None,
+    };
+    stream(&state, "metrics", format, move |db, after| {
+        db.export_metrics(&query, after, EXPORT_PAGE)
+    })
+}
+
+/// GET /api/deploys/export - Deploy history as CSV (default) or a JSON
+/// array, oldest first (`?agent=&deployment=&status=&from=&to=`)
+pub async fn deploys(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ExportParams>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let format = parse_format(&params)?;
+    let status = params
+        .status
+        .as_deref()
+        .map(str::parse::<DeployStatus>)
+        .transpose()
+        .map_err(|e| ErrorResponse::new(StatusCode::BAD_REQUEST, &e))?;
+    let query = DeployHistoryQuery {
+        agent_name: params.agent,
+        deployment_name: params.deployment,
+        status,
+        from: parse_time(&params.from, "from")?,
+        to: parse_time(&params.to, "to")?,
+        before_id: None,
+        offset: 0,
+        limit: EXPORT_PAGE,
+    };
+    stream(&state, "deploys", format, move |db, after| {
+        db.export_deploys(&query, after, EXPORT_PAGE)
+    })
+}
+
+/// A record that can be exported
+trait ExportRow: Serialize + Send + 'static {
+    const CSV_HEADER: &'static str;
+
+    fn id(&self) -> i64;
+    fn csv(&self) -> String;
+}
+
+impl ExportRow for MetricRecord {
+    const CSV_HEADER: &'static str = "id,agent,collected_at,cpu_usage,memory_usage_percent,\
+        memory_used,memory_total,load_one,load_five,load_fifteen,disk_usage_percent,\
+        containers_running,containers_total,network_rx_bytes,network_tx_bytes";
+
+    fn id(&self) -> i64 {
+        self.id.unwrap_or_default()
+    }
+
+    fn csv(&self) -> String {
+        [
+            self.id().to_string(),
+            csv_field(&self.agent_name),
+            timestamp(self.collected_at),
+            self.cpu_usage.to_string(),
+            self.memory_usage_percent.to_string(),
+            self.memory_used.to_string(),
+            self.memory_total.to_string(),
+            self.load_one.to_string(),
+            self.load_five.to_string(),
+            self.load_fifteen.to_string(),
+            optional(self.disk_usage_percent),
+            optional(self.containers_running),
+            optional(self.containers_total),
+            optional(self.network_rx_bytes),
+            optional(self.network_tx_bytes),
+        ]
+        .join(",")
+    }
+}
+
+impl ExportRow for DeployRecord {
+    const CSV_HEADER: &'static str = "id,agent,deployment,type,status,started_at,completed_at,\
+        duration_ms,trigger_source,commit_sha,attempt,error_message";
+
+    fn id(&self) -> i64 {
+        self.id.unwrap_or_default()
+    }
+
+    fn csv(&self) -> String {
+        [
+            self.id().to_string(),
+            csv_field(&self.agent_name),
+            csv_field(&self.deployment_name),
+            csv_field(&self.deploy_type),
+            self.status.to_string(),
+            timestamp(self.started_at),
+            self.completed_at.map(timestamp).unwrap_or_default(),
+            optional(self.duration_ms),
+            csv_field(self.trigger_source.as_deref().unwrap_or_default()),
+            csv_field(self.commit_sha.as_deref().unwrap_or_default()),
+            self.attempt.to_string(),
+            csv_field(self.error_message.as_deref().unwrap_or_default()),
+        ]
+        .join(",")
+    }
+}
+
+/// Stream the rows `page` returns (pages after the last id sent) as the
+/// response body. Only one page is held in memory; a slow client holds up
+/// the next query instead of rows piling up.
+fn stream<T: ExportRow>(
+    state: &AppState,
+    name: &'static str,
+    format: Format,
+    page: impl Fn(&Database, i64) -> rusqlite::Result<Vec<T>> + Send + 'static,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let db = state.db.clone().ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Database not available (Agent mode?)",
+        )
+    })?;
+    // Fail with a status code while one can still be sent
+    let first = page(&db, 0).map_err(|e| {
+        ErrorResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Database error: {}", e),
+        )
+    })?;
+
+    let (mut chunks, body) = futures::channel::mpsc::channel::<String>(1);
+    tokio::spawn(async move {
+        let mut chunk = match format {
+            Format::Csv => format!("{}\n", T::CSV_HEADER),
+            Format::Json => "[".to_string(),
+        };
+        let mut rows = first;
+        let mut first_row = true;
+        loop {
+            for row in &rows {
+                match format {
+                    Format::Csv => chunk.push_str(&row.csv()),
+                    Format::Json => {
+                        if !first_row {
+                            chunk.push(',');
+                        }
+                        chunk.push_str(&serde_json::to_string(row).unwrap_or_default());
+                    }
+                }
+                chunk.push('\n');
+                first_row = false;
+            }
+            let last = rows.last().map(ExportRow::id);
+            if chunks.send(std::mem::take(&mut chunk)).await.is_err() {
+                return; // Client went away
+            }
+            let Some(after) = last.filter(|_| rows.len() as u32 == EXPORT_PAGE) else {
+                break;
+            };
+            rows = match page(&db, after) {
+                Ok(rows) => rows,
+                Err(e) => {
+                    // Too late for an error status: the export ends short
+                    warn!(export = name, error = %e, "Export aborted");
+                    return;
+                }
+            };
+        }
+        if format == Format::Json {
+            let _ = chunks.send("]\n".to_string()).await;
+        }
+    });
+
+    let (content_type, extension) = match format {
+        Format::Csv => ("text/csv; charset=utf-8", "csv"),
+        Format::Json => ("application/json", "json"),
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.{}\"", name, extension),
+            ),
+        ],
+        Body::from_stream(body.map(Ok::<_, std::convert::Infallible>)),
+    )
+        .into_response())
+}
+
+fn parse_format(params: &ExportParams) -> Result<Format, (StatusCode, Json<ErrorResponse>)> {
+    match params.format.as_deref() {
+        None | Some("csv") => Ok(Format::Csv),
+        Some("json") => Ok(Format::Json),
+        Some(other) => Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid format '{}': expected csv or json", other),
+        )),
+    }
+}
+
+fn parse_time(
+    value: &Option<String>,
+    field: &str,
+) -> Result<Option<OffsetDateTime>, (StatusCode, Json<ErrorResponse>)> {
+    value
+        .as_deref()
+        .map(|s| OffsetDateTime::parse(s, &Rfc3339))
+        .transpose()
+        .map_err(|_| {
+            ErrorResponse::new(
+                StatusCode::BAD_REQUEST,
+                &format!("Invalid '{}': expected an RFC 3339 timestamp", field),
+            )
+        })
+}
+
+fn timestamp(value: OffsetDateTime) -> String {
+    value.format(&Rfc3339).unwrap_or_default()
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// Quote a CSV field when it contains a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::storage::DbWriter;
+
+    #[tokio::test]
+    async fn test_export_streams_every_page() {
+        let config: Config = serde_yaml::from_str(
+            "mode: home\nserver: {}\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\n",
+        )
+        .unwrap();
+        let db = Arc::new(Database::new(":memory:").unwrap());
+        db.migrate().unwrap();
+        let start = OffsetDateTime::now_utc() - time::Duration::hours(1);
+        for i in 0..EXPORT_PAGE + 5 {
+            db.insert_metric(&MetricRecord {
+                id: None,
+                agent_name: if i % 2 == 0 { "web-1" } else { "web,2" }.to_string(),
+                collected_at: start + time::Duration::seconds(i as i64),
+                cpu_usage: 1.5,
+                memory_usage_percent: 50.0,
+                memory_used: 1,
+                memory_total: 2,
+                load_one: 0.1,
+                load_five: 0.2,
+                load_fifteen: 0.3,
+                disk_usage_percent: None,
+                containers_running: Some(3),
+                containers_total: Some(4),
+                raw_json: None,
+                network_rx_bytes: None,
+                network_tx_bytes: None,
+            })
+            .unwrap();
+        }
+        let writer = DbWriter::spawn(db.clone(), &config.modules.storage.write_buffer);
+        let state = AppState::with_database(config, db, writer);
+        let export = |format: &str| ExportParams {
+            format: Some(format.to_string()),
+            agent: None,
+            deployment: None,
+            status: None,
+            from: None,
+            to: None,
+        };
+
+        let response = metrics(State(state.clone()), Query(export("csv")))
+            .await
+            .ok()
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let csv = String::from_utf8(body.to_vec()).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), EXPORT_PAGE as usize + 6);
+        assert_eq!(lines[0], MetricRecord::CSV_HEADER);
+        assert!(lines[2].starts_with("2,\"web,2\","));
+
+        let response = deploys(State(state.clone()), Query(export("json")))
+            .await
+            .ok()
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let rows: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+        assert!(rows.is_empty());
+
+        let invalid = metrics(State(state), Query(export("xml"))).await;
+        assert_eq!(invalid.err().map(|e| e.0), Some(StatusCode::BAD_REQUEST));
+    }
+}
@@ -0,0 +1,1199 @@
+use super::exec::ExecResult;
+use crate::config::{self, AgentConfig, DeployCategory};
+use crate::metrics::MetricsReport;
+use crate::server::agents::{self, agent_timeout, AgentRequest};
+use crate::server::auth::Audience;
+use crate::server::middleware::ErrorResponse;
+use crate::server::AppState;
+use crate::storage::{
+    AgentStatus, DbWrite, DbWriter, DeployHistoryQuery, DeployRecord, DeployStatus, MetricRecord,
+};
+use crate::updater::github::is_newer_version;
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+/// How often a forwarded job's status is polled on the agent
+const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Give up tracking a forwarded job after this long
+const STATUS_POLL_LIMIT: Duration = Duration::from_secs(3600);
+/// Agents download and swap their binary before answering a fleet update
+const UPDATE_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Deserialize)]
+pub struct FleetQuery {
+    /// Only agents carrying these tags (`web,edge` needs both)
+    pub tag: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct FleetResult {
+    pub agent: String,
+    pub success: bool,
+    pub job_id: Option<String>,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct FleetResponse {
+    pub deployment: String,
+    pub triggered: usize,
+    pub failed: usize,
+    pub results: Vec<FleetResult>,
+}
+
+/// POST /api/fleet/deploy/:name - Trigger a deployment on every configured agent
+///
+/// The deployment is forwarded to each agent's `/webhook/deploy/:name`
+/// concurrently. Every agent gets its own deploy history row, which is
+/// completed once the agent reports the job as finished.
+pub async fn deploy(
+    State(state): State<Arc<AppState>>,
+    Path(deployment_name): Path<String>,
+    Query(query): Query<FleetQuery>,
+) -> Result<Json<FleetResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let deploy_config = state.deploy_config.read().await;
+    let deployment = deploy_config
+        .deployments
+        .iter()
+        .find(|d| d.name == deployment_name)
+        .cloned();
+    drop(deploy_config);
+
+    let deployment = deployment.ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::NOT_FOUND,
+            &format!("Deployment '{}' not found", deployment_name),
+        )
+    })?;
+
+    // Same rule as the CLI: system deployments are never triggered remotely
+    if deployment.category == DeployCategory::System {
+        return Err(ErrorResponse::new(
+            StatusCode::FORBIDDEN,
+            &format!(
+                "Deployment '{}' is a system deployment and cannot be triggered via fleet deploy",
+                deployment_name
+            ),
+        ));
+    }
+
+    let agents: Vec<AgentConfig> = state
+        .live
+        .read()
+        .await
+        .agents
+        .iter()
+        .filter(|a| a.matches_tags(query.tag.as_deref()))
+        .cloned()
+        .collect();
+    if agents.is_empty() {
+        return Err(ErrorResponse::new(
+            StatusCode::NOT_FOUND,
+            "No agents match the fleet deploy",
+        ));
+    }
+
+    info!(
+        deployment = %deployment_name,
+        agents = agents.len(),
+        tag = ?query.tag,
+        "Fleet deploy"
+    );
+
+    let results = futures::future::join_all(
+        agents
+            .iter()
+            .map(|agent| trigger_agent(&state, agent, &deployment_name)),
+    )
+    .await;
+
+    // One history row per agent, completed by a tracker polling the agent
+    if let Some(ref writer) = state.db_writer {
+        for (agent, result) in agents.iter().zip(&results) {
+            let row_id = uuid::Uuid::new_v4().to_string();
+            writer
+                .send(DbWrite::DeployStarted {
+                    job_id: row_id.clone(),
+                    record: DeployRecord {
+                        id: None,
+                        agent_name: agent.name.clone(),
+                        deployment_name: deployment_name.clone(),
+                        deploy_type: format!("{:?}", deployment.deploy_type),
+                        status: DeployStatus::Running,
+                        started_at: OffsetDateTime::now_utc(),
+                        completed_at: None,
+                        duration_ms: None,
+                        trigger_source: Some("fleet".to_string()),
+                        commit_sha: None,
+                        output: None,
+                        error_message: None,
+                        attempt: 1,
+                        image_digests: None,
+                        log_file: None,
+                    },
+                })
+                .await;
+
+            match result.job_id {
+                Some(ref job_id) => {
+                    tokio::spawn(track_agent_job(
+                        state.clone(),
+                        writer.clone(),
+                        agent.clone(),
+                        job_id.clone(),
+                        row_id,
+                    ));
+                }
+                None => {
+                    finish(
+                        writer,
+                        row_id,
+                        DeployStatus::Failed,
+                        0,
+                        Some(result.message.clone()),
+                    )
+                    .await
+                }
+            }
+        }
+    }
+
+    let triggered = results.iter().filter(|r| r.success).count();
+    Ok(Json(FleetResponse {
+        deployment: deployment_name,
+        triggered,
+        failed: results.len() - triggered,
+        results,
+    }))
+}
+
+/// Forward the deployment to one agent
+async fn trigger_agent(state: &AppState, agent: &AgentConfig, name: &str) -> FleetResult {
+    let result = async {
+        // No retry: the agent may have queued the job before the reply was
+        // lost, and a second POST would queue another deploy
+        let request = AgentRequest::new(Method::POST, &format!("/webhook/deploy/{}", name))
+            .bearer(&agent_token(state, &agent.name, Audience::Webhook, &[])?);
+        let reply = agents::call(state, agent, request, agent_timeout(agent)).await?;
+        if !reply.is_success() {
+            return Err(format!(
+                "Agent returned {}: {}",
+                reply.status,
+                reply.error()
+            ));
+        }
+        Ok(reply.json::<serde_json::Value>().unwrap_or_default())
+    }
+    .await;
+
+    match result {
+        Ok(body) => FleetResult {
+            agent: agent.name.clone(),
+            success: true,
+            job_id: body["job_id"].as_str().map(String::from),
+            message: body["message"].as_str().unwrap_or_default().to_string(),
+        },
+        Err(message) => {
+            warn!(agent = %agent.name, deployment = %name, error = %message, "Fleet deploy failed");
+            FleetResult {
+                agent: agent.name.clone(),
+                success: false,
+                job_id: None,
+                message,
+            }
+        }
+    }
+}
+
+/// Poll the agent until its job finishes, then complete the history row
+async fn track_agent_job(
+    state: Arc<AppState>,
+    writer: DbWriter,
+    agent: AgentConfig,
+    job_id: String,
+    row_id: String,
+) {
+    let path = format!("/webhook/status/{}", job_id);
+    let started = std::time::Instant::now();
+
+    while started.elapsed() < STATUS_POLL_LIMIT {
+        tokio::time::sleep(STATUS_POLL_INTERVAL).await;
+
+        let Ok(token) = agent_token(&state, &agent.name, Audience::Webhook, &[]) else {
+            break;
+        };
+        let request = AgentRequest::new(Method::GET, &path).bearer(&token).retry();
+        let body: serde_json::Value =
+            match agents::call(&state, &agent, request, agent_timeout(&agent)).await {
+                Ok(reply) if reply.is_success() => reply.json().unwrap_or_default(),
+                // Agent temporarily unreachable: keep polling
+                _ => continue,
+            };
+
+        let status = match body["status"].as_str() {
+            Some("Completed") => DeployStatus::Success,
+            Some("Failed") => DeployStatus::Failed,
+            Some("Cancelled") => DeployStatus::Cancelled,
+            _ => continue,
+        };
+        let error = (status != DeployStatus::Success)
+            .then(|| format!("Job {} on agent {} {}", job_id, agent.name, status));
+        finish(
+            &writer,
+            row_id,
+            status,
+            started.elapsed().as_millis() as i64,
+            error,
+        )
+        .await;
+        return;
+    }
+
+    warn!(agent = %agent.name, job_id = %job_id, "Gave up tracking fleet deploy job");
+    finish(
+        &writer,
+        row_id,
+        DeployStatus::Failed,
+        started.elapsed().as_millis() as i64,
+        Some(format!("No final status from agent {}", agent.name)),
+    )
+    .await;
+}
+
+async fn finish(
+    writer: &DbWriter,
+    row_id: String,
+    status: DeployStatus,
+    duration_ms: i64,
+    error_message: Option<String>,
+) {
+    writer
+        .send(DbWrite::DeployFinished {
+            job_id: row_id,
+            status,
+            completed_at: OffsetDateTime::now_utc(),
+            duration_ms,
+            output: String::new(),
+            error_message,
+        })
+        .await;
+}
+
+#[derive(Deserialize)]
+pub struct FleetExecRequest {
+    /// Name of a command in the agents' `modules.exec.commands`
+    pub command: String,
+    /// Agents to run it on, by name
+    #[serde(default)]
+    pub agents: Vec<String>,
+    /// Run it on every agent instead (narrowed by `tag`)
+    #[serde(default)]
+    pub all: bool,
+    pub tag: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct FleetExecResult {
+    pub agent: String,
+    pub success: bool,
+    pub output: String,
+    pub duration_ms: u64,
+}
+
+/// POST /api/fleet/exec - Run an allowlisted command on selected agents
+///
+/// Agents run the command concurrently. The response is newline-delimited
+/// JSON: one [`FleetExecResult`] per agent as soon as it finishes, then a
+/// summary line with the `succeeded` and `failed` counts.
+pub async fn exec(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<FleetExecRequest>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    if !config::valid_name(&request.command) {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid command name '{}'", request.command),
+        ));
+    }
+    if request.all != request.agents.is_empty() {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "Select agents with either 'agents' or 'all', not both",
+        ));
+    }
+
+    let live = state.live.read().await;
+    let mut agents = Vec::new();
+    for name in &request.agents {
+        let agent = live
+            .agents
+            .iter()
+            .find(|a| &a.name == name)
+            .ok_or_else(|| {
+                ErrorResponse::new(
+                    StatusCode::NOT_FOUND,
+                    &format!("Agent '{}' not found", name),
+                )
+            })?;
+        agents.push(agent.clone());
+    }
+    if request.all {
+        agents = live.agents.clone();
+    }
+    drop(live);
+    agents.retain(|a| a.matches_tags(request.tag.as_deref()));
+    if agents.is_empty() {
+        return Err(ErrorResponse::new(
+            StatusCode::NOT_FOUND,
+            "No agents match the fleet exec",
+        ));
+    }
+
+    info!(command = %request.command, agents = agents.len(), "Fleet exec");
+
+    let mut runs: FuturesUnordered<_> = agents
+        .into_iter()
+        .map(|agent| {
+            let state = state.clone();
+            let command = request.command.clone();
+            async move { exec_agent(&state, &agent, &command).await }
+        })
+        .collect();
+
+    let (lines, body) = futures::channel::mpsc::unbounded::<String>();
+    let command = request.command;
+    tokio::spawn(async move {
+        let (mut succeeded, mut failed) = (0, 0);
+        while let Some(result) = runs.next().await {
+            if result.success {
+                succeeded += 1;
+            } else {
+                failed += 1;
+            }
+            let line = serde_json::to_string(&result).unwrap_or_default();
+            // Keep going if the client left: the commands are running anyway
+            let _ = lines.unbounded_send(line + "\n");
+        }
+        let summary = serde_json::json!({
+            "command": command,
+            "succeeded": succeeded,
+            "failed": failed,
+        });
+        let _ = lines.unbounded_send(summary.to_string() + "\n");
+    });
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(body.map(Ok::<_, std::convert::Infallible>)),
+    )
+        .into_response())
+}
+
+/// Run the command on one agent. Never retried: commands need not be idempotent.
+async fn exec_agent(state: &AppState, agent: &AgentConfig, command: &str) -> FleetExecResult {
+    let timeout = humantime::parse_duration(&state.config.modules.exec.timeout)
+        .unwrap_or(Duration::from_secs(300));
+    let result = async {
+        // Agents only accept a token scoped to this one command
+        let scopes = [format!("exec:{}", command)];
+        let token = agent_token(state, &agent.name, Audience::Api, &scopes)?;
+        let request =
+            AgentRequest::new(Method::POST, &format!("/api/exec/{}", command)).bearer(&token);
+        let reply = agents::call(state, agent, request, timeout).await?;
+        if !reply.is_success() {
+            return Err(format!(
+                "Agent returned {}: {}",
+                reply.status,
+                reply.error()
+            ));
+        }
+        reply
+            .json::<ExecResult>()
+            .map_err(|e| format!("Invalid exec response: {}", e))
+    }
+    .await;
+
+    match result {
+        Ok(result) => FleetExecResult {
+            agent: agent.name.clone(),
+            success: result.success,
+            output: result.output,
+            duration_ms: result.duration_ms,
+        },
+        Err(output) => {
+            warn!(agent = %agent.name, command = %command, error = %output, "Fleet exec failed");
+            FleetExecResult {
+                agent: agent.name.clone(),
+                success: false,
+                output,
+                duration_ms: 0,
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct FleetUpdateResult {
+    pub agent: String,
+    pub success: bool,
+    pub from_version: Option<String>,
+    pub to_version: Option<String>,
+    /// The agent installed a new release and restarts
+    pub requires_restart: bool,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct FleetUpdateResponse {
+    pub updated: usize,
+    pub failed: usize,
+    pub results: Vec<FleetUpdateResult>,
+}
+
+/// POST /api/fleet/update - Self-update every configured agent
+///
+/// Each agent's `POST /api/self-update` is called concurrently; agents that
+/// installed a new release restart right after answering. Home itself is not
+/// updated (use `/api/update/apply`).
+pub async fn update(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<FleetQuery>,
+) -> Result<Json<FleetUpdateResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let agents: Vec<AgentConfig> = state
+        .live
+        .read()
+        .await
+        .agents
+        .iter()
+        .filter(|a| a.matches_tags(query.tag.as_deref()))
+        .cloned()
+        .collect();
+    if agents.is_empty() {
+        return Err(ErrorResponse::new(
+            StatusCode::NOT_FOUND,
+            "No agents match the fleet update",
+        ));
+    }
+
+    info!(agents = agents.len(), tag = ?query.tag, "Fleet update");
+    let results =
+        futures::future::join_all(agents.iter().map(|agent| update_agent(&state, agent))).await;
+
+    Ok(Json(FleetUpdateResponse {
+        updated: results.iter().filter(|r| r.requires_restart).count(),
+        failed: results.iter().filter(|r| !r.success).count(),
+        results,
+    }))
+}
+
+/// Not retried: a second request while the first installs only gets `409`
+async fn update_agent(state: &AppState, agent: &AgentConfig) -> FleetUpdateResult {
+    let result = async {
+        let token = agent_token(state, &agent.name, Audience::Api, &[])?;
+        let request = AgentRequest::new(Method::POST, "/api/self-update").bearer(&token);
+        let reply = agents::call(state, agent, request, UPDATE_TIMEOUT).await?;
+        if !reply.is_success() {
+            return Err(format!(
+                "Agent returned {}: {}",
+                reply.status,
+                reply.error()
+            ));
+        }
+        Ok(reply.json::<serde_json::Value>().unwrap_or_default())
+    }
+    .await;
+
+    match result {
+        Ok(body) => FleetUpdateResult {
+            agent: agent.name.clone(),
+            success: true,
+            from_version: body["from_version"].as_str().map(String::from),
+            to_version: body["to_version"].as_str().map(String::from),
+            requires_restart: body["requires_restart"].as_bool().unwrap_or(false),
+            message: body["message"].as_str().unwrap_or_default().to_string(),
+        },
+        Err(message) => {
+            warn!(agent = %agent.name, error = %message, "Fleet update failed");
+            FleetUpdateResult {
+                agent: agent.name.clone(),
+                success: false,
+                from_version: None,
+                to_version: None,
+                requires_restart: false,
+                message,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct InventoryEntry {
+    pub agent: String,
+    pub tags: Vec<String>,
+    /// `online`/`offline`; `None` until Home has heard from the agent
+    pub status: Option<String>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_seen: Option<OffsetDateTime>,
+    pub version: Option<String>,
+    /// The agent runs an older release than `latest_version`
+    pub update_available: bool,
+    pub hostname: Option<String>,
+    pub os_name: Option<String>,
+    pub os_version: Option<String>,
+    pub kernel_version: Option<String>,
+    pub docker_version: Option<String>,
+    pub containers_running: Option<u32>,
+    pub containers_stopped: Option<u32>,
+    pub containers_total: Option<u32>,
+    /// When the metrics the host details come from were collected
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub reported_at: Option<OffsetDateTime>,
+}
+
+#[derive(Serialize)]
+pub struct InventoryResponse {
+    /// Newest release known to Home: its own version or the latest update check
+    pub latest_version: String,
+    pub outdated: usize,
+    pub agents: Vec<InventoryEntry>,
+}
+
+/// GET /api/fleet/inventory - Version, OS and container counts of every agent
+///
+/// Built from what Home already stores: the agent statuses kept by health
+/// polling and metrics pushes, and each agent's latest metrics report. No
+/// agent is contacted, so offline agents show their last known state.
+pub async fn inventory(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<FleetQuery>,
+) -> Result<Json<InventoryResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let db = state.db.as_ref().ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Database not available (Agent mode?)",
+        )
+    })?;
+    let db_error = |e: rusqlite::Error| {
+        ErrorResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Database error: {}", e),
+        )
+    };
+    let statuses = db.get_all_agent_statuses().map_err(db_error)?;
+    let metrics = db.get_latest_metrics().map_err(db_error)?;
+
+    let mut latest_version = env!("CARGO_PKG_VERSION").to_string();
+    if let Some(updater) = state.updater.get() {
+        if let Some(released) = updater.get_state().await.latest_version {
+            if is_newer_version(&latest_version, &released).unwrap_or(false) {
+                latest_version = released;
+            }
+        }
+    }
+
+    let agents: Vec<_> = state
+        .live
+        .read()
+        .await
+        .agents
+        .iter()
+        .filter(|a| a.matches_tags(query.tag.as_deref()))
+        .map(|agent| {
+            inventory_entry(
+                agent,
+                statuses.iter().find(|s| s.agent_name == agent.name),
+                metrics.iter().find(|m| m.agent_name == agent.name),
+                &latest_version,
+            )
+        })
+        .collect();
+
+    Ok(Json(InventoryResponse {
+        outdated: agents.iter().filter(|a| a.update_available).count(),
+        latest_version,
+        agents,
+    }))
+}
+
+fn inventory_entry(
+    agent: &AgentConfig,
+    status: Option<&AgentStatus>,
+    metric: Option<&MetricRecord>,
+    latest_version: &str,
+) -> InventoryEntry {
+    // Reports pushed over gRPC carry no raw JSON: counts only, no host details
+    let report = metric
+        .and_then(|m| m.raw_json.as_deref())
+        .and_then(|json| serde_json::from_str::<MetricsReport>(json).ok());
+    let system = report.as_ref().map(|r| &r.system);
+    let docker = report.as_ref().and_then(|r| r.docker.as_ref());
+    let version = status.and_then(|s| s.version.clone());
+
+    InventoryEntry {
+        agent: agent.name.clone(),
+        tags: agent.tags.clone(),
+        status: status.map(|s| s.status.clone()),
+        last_seen: status.map(|s| s.last_seen),
+        update_available: version
+            .as_deref()
+            .is_some_and(|v| is_newer_version(v, latest_version).unwrap_or(false)),
+        version,
+        hostname: system.map(|s| s.hostname.clone()),
+        os_name: system.and_then(|s| s.os_name.clone()),
+        os_version: system.and_then(|s| s.os_version.clone()),
+        kernel_version: system.and_then(|s| s.kernel_version.clone()),
+        docker_version: docker.and_then(|d| d.version.clone()),
+        containers_running: metric.and_then(|m| m.containers_running),
+        containers_stopped: docker.map(|d| d.containers_stopped),
+        containers_total: metric.and_then(|m| m.containers_total),
+        reported_at: metric.map(|m| m.collected_at),
+    }
+}
+
+/// Highest reading of one metric across the fleet
+#[derive(Debug, Serialize)]
+pub struct WorstReading {
+    pub agent: String,
+    pub percent: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FleetHealth {
+    /// `healthy` when every agent is online, `degraded` when some are not,
+    /// `down` when none is
+    pub status: &'static str,
+    pub agents_total: usize,
+    pub agents_online: usize,
+    pub agents_offline: usize,
+    /// Agents Home has not heard from yet
+    pub agents_unknown: usize,
+    pub containers_running: u32,
+    pub containers_total: u32,
+    pub worst_cpu: Option<WorstReading>,
+    pub worst_memory: Option<WorstReading>,
+    /// Usage of `/`
+    pub worst_disk: Option<WorstReading>,
+    pub failed_deploys_24h: u64,
+}
+
+/// GET /api/fleet/health - One rollup of the whole fleet
+///
+/// Meant for status badges and uptime checkers: `503` unless every agent is
+/// online, with the same body. Readings are from each agent's latest
+/// metrics report.
+pub async fn health(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<FleetQuery>,
+) -> Result<(StatusCode, Json<FleetHealth>), (StatusCode, Json<ErrorResponse>)> {
+    let db = state.db.as_ref().ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Database not available (Agent mode?)",
+        )
+    })?;
+    let db_error = |e: rusqlite::Error| {
+        ErrorResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Database error: {}", e),
+        )
+    };
+    let statuses = db.get_all_agent_statuses().map_err(db_error)?;
+    let metrics = db.get_latest_metrics().map_err(db_error)?;
+    let failed = db
+        .get_deploy_history(&DeployHistoryQuery {
+            status: Some(DeployStatus::Failed),
+            from: Some(OffsetDateTime::now_utc() - time::Duration::hours(24)),
+            ..Default::default()
+        })
+        .map_err(db_error)?;
+
+    let agents: Vec<_> = state
+        .live
+        .read()
+        .await
+        .agents
+        .iter()
+        .filter(|a| a.matches_tags(query.tag.as_deref()))
+        .map(|a| a.name.clone())
+        .collect();
+    let health = fleet_health(&agents, &statuses, &metrics, failed.total);
+
+    let code = if health.status == "healthy" {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Ok((code, Json(health)))
+}
+
+fn fleet_health(
+    agents: &[String],
+    statuses: &[AgentStatus],
+    metrics: &[MetricRecord],
+    failed_deploys: u64,
+) -> FleetHealth {
+    let status_of = |name: &str| {
+        statuses
+            .iter()
+            .find(|s| s.agent_name == name)
+            .map(|s| s.status.as_str())
+    };
+    let online = agents
+        .iter()
+        .filter(|a| status_of(a) == Some("online"))
+        .count();
+    let unknown = agents.iter().filter(|a| status_of(a).is_none()).count();
+    let metrics: Vec<_> = metrics
+        .iter()
+        .filter(|m| agents.contains(&m.agent_name))
+        .collect();
+    let worst = |reading: fn(&MetricRecord) -> Option<f64>| {
+        metrics
+            .iter()
+            .filter_map(|m| Some((m, reading(m)?)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(m, percent)| WorstReading {
+                agent: m.agent_name.clone(),
+                percent,
+            })
+    };
+
+    FleetHealth {
+        status: match online {
+            n if n == agents.len() => "healthy",
+            0 => "down",
+            _ => "degraded",
+        },
+        agents_total: agents.len(),
+        agents_online: online,
+        agents_offline: agents.len() - online - unknown,
+        agents_unknown: unknown,
+        containers_running: metrics.iter().filter_map(|m| m.containers_running).sum(),
+        containers_total: metrics.iter().filter_map(|m| m.containers_total).sum(),
+        worst_cpu: worst(|m| Some(m.cpu_usage)),
+        worst_memory: worst(|m| Some(m.memory_usage_percent)),
+        worst_disk: worst(|m| m.disk_usage_percent),
+        failed_deploys_24h: failed_deploys,
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PushDeploymentsRequest {
+    /// Deployments from Home's catalog to push
+    pub deployments: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct PushResult {
+    pub deployment: String,
+    pub success: bool,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct PushResponse {
+    pub agent: String,
+    pub pushed: usize,
+    pub failed: usize,
+    pub results: Vec<PushResult>,
+}
+
+/// POST /api/agents/:name/deployments - Push deployments from Home's
+/// catalog into the agent's `deployments.d/`
+///
+/// The agent persists each one and loads it right away; an agent-side
+/// definition of the same name is never overwritten.
+pub async fn push_deployments(
+    State(state): State<Arc<AppState>>,
+    Path(agent_name): Path<String>,
+    Json(request): Json<PushDeploymentsRequest>,
+) -> Result<Json<PushResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let agent = state
+        .live
+        .read()
+        .await
+        .agents
+        .iter()
+        .find(|a| a.name == agent_name)
+        .cloned()
+        .ok_or_else(|| {
+            ErrorResponse::new(
+                StatusCode::NOT_FOUND,
+                &format!("Agent '{}' not found", agent_name),
+            )
+        })?;
+    if request.deployments.is_empty() {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "No deployments to push",
+        ));
+    }
+
+    let deploy_config = state.deploy_config.read().await;
+    let mut deployments = Vec::new();
+    for name in &request.deployments {
+        let deployment = deploy_config
+            .deployments
+            .iter()
+            .find(|d| &d.name == name)
+            .ok_or_else(|| {
+                ErrorResponse::new(
+                    StatusCode::NOT_FOUND,
+                    &format!("Deployment '{}' not found", name),
+                )
+            })?;
+        deployments.push(deployment.clone());
+    }
+    drop(deploy_config);
+
+    let token = agent_token(
+        &state,
+        &agent.name,
+        Audience::Api,
+        &["config:write".to_string()],
+    )
+    .map_err(|e| ErrorResponse::new(StatusCode::INTERNAL_SERVER_ERROR, &e))?;
+
+    info!(agent = %agent.name, deployments = deployments.len(), "Pushing deployments");
+    let mut results = Vec::new();
+    for deployment in &deployments {
+        results.push(push_deployment(&state, &agent, deployment, &token).await);
+    }
+
+    let pushed = results.iter().filter(|r| r.success).count();
+    Ok(Json(PushResponse {
+        agent: agent.name,
+        pushed,
+        failed: results.len() - pushed,
+        results,
+    }))
+}
+
+async fn push_deployment(
+    state: &AppState,
+    agent: &AgentConfig,
+    deployment: &config::DeploymentConfig,
+    token: &str,
+) -> PushResult {
+    let result = async {
+        // PUT replaces the whole file, so a retry is safe
+        let request = AgentRequest::new(
+            Method::PUT,
+            &format!("/api/deployments/{}", deployment.name),
+        )
+        .bearer(token)
+        .json(deployment)
+        .retry();
+        let reply = agents::call(state, agent, request, agent_timeout(agent)).await?;
+        if !reply.is_success() {
+            return Err(format!(
+                "Agent returned {}: {}",
+                reply.status,
+                reply.error()
+            ));
+        }
+        Ok(reply.json::<serde_json::Value>().unwrap_or_default())
+    }
+    .await;
+
+    match result {
+        Ok(body) => PushResult {
+            deployment: deployment.name.clone(),
+            success: true,
+            message: if body["created"].as_bool() == Some(true) {
+                "Created".to_string()
+            } else {
+                "Updated".to_string()
+            },
+        },
+        Err(message) => {
+            warn!(agent = %agent.name, deployment = %deployment.name, error = %message, "Deployment push failed");
+            PushResult {
+                deployment: deployment.name.clone(),
+                success: false,
+                message,
+            }
+        }
+    }
+}
+
+/// Short-lived token for calling an agent, signed with the key it accepts
+fn agent_token(
+    state: &AppState,
+    agent: &str,
+    audience: Audience,
+    scopes: &[String],
+) -> Result<String, String> {
+    state
+        .agent_signer(agent)?
+        .generate_scoped_token("home", 1, &[audience], scopes)
+        .map_err(|e| format!("Failed to generate token: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Router};
+
+    #[tokio::test]
+    async fn test_fleet_inventory() {
+        let config: config::Config = serde_yaml::from_str(concat!(
+            "mode: home\nserver: {}\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\n",
+            "agents:\n  - name: web-1\n    address: \"10.0.0.10:8111\"\n    tags: [web]\n",
+            "  - name: db-1\n    address: \"10.0.0.20:8111\"\n",
+        ))
+        .unwrap();
+        let db = Arc::new(crate::storage::Database::new(":memory:").unwrap());
+        db.migrate().unwrap();
+        let writer = DbWriter::spawn(db.clone(), &config.modules.storage.write_buffer);
+
+        let report = MetricsReport {
+            collected_at: OffsetDateTime::now_utc(),
+            version: "0.1.0".to_string(),
+            uptime_seconds: 60,
+            system: crate::metrics::SystemCollector::collect(),
+            docker: Some(crate::metrics::DockerMetrics {
+                available: true,
+                version: Some("27.1.1".to_string()),
+                containers_running: 3,
+                containers_paused: 0,
+                containers_stopped: 1,
+                containers_total: 4,
+                images_count: 5,
+                containers: Vec::new(),
+                compose_projects: Vec::new(),
+            }),
+            custom: Default::default(),
+        };
+        db.insert_metric(&report.record("web-1")).unwrap();
+        db.update_agent_status(&AgentStatus {
+            agent_name: "web-1".to_string(),
+            last_seen: OffsetDateTime::now_utc(),
+            status: "online".to_string(),
+            version: Some("0.1.0".to_string()),
+            uptime_seconds: Some(60),
+        })
+        .unwrap();
+        let state = AppState::with_database(config, db, writer);
+
+        let Json(all) = inventory(State(state.clone()), Query(FleetQuery { tag: None }))
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!(all.latest_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(all.outdated, 1);
+        let web = &all.agents[0];
+        assert_eq!(web.status.as_deref(), Some("online"));
+        assert!(web.update_available);
+        assert_eq!(web.docker_version.as_deref(), Some("27.1.1"));
+        assert_eq!(web.containers_running, Some(3));
+        assert_eq!(web.containers_stopped, Some(1));
+        assert_eq!(web.kernel_version, report.system.kernel_version);
+        // Never heard from: listed without details
+        let db_1 = &all.agents[1];
+        assert_eq!(db_1.status, None);
+        assert!(!db_1.update_available);
+        assert_eq!(db_1.containers_total, None);
+
+        let Json(web_only) = inventory(
+            State(state),
+            Query(FleetQuery {
+                tag: Some("web".to_string()),
+            }),
+        )
+        .await
+        .ok()
+        .unwrap();
+        assert_eq!(web_only.agents.len(), 1);
+    }
+
+    #[test]
+    fn test_fleet_health_rollup() {
+        let status = |agent: &str, status: &str| AgentStatus {
+            agent_name: agent.to_string(),
+            last_seen: OffsetDateTime::now_utc(),
+            status: status.to_string(),
+            version: None,
+            uptime_seconds: None,
+        };
+        let metric = |agent: &str, cpu: f64, disk: Option<f64>| MetricRecord {
+            id: None,
+            agent_name: agent.to_string(),
+            collected_at: OffsetDateTime::now_utc(),
+            cpu_usage: cpu,
+            memory_usage_percent: 50.0,
+            memory_used: 0,
+            memory_total: 0,
+            load_one: 0.0,
+            load_five: 0.0,
+            load_fifteen: 0.0,
+            disk_usage_percent: disk,
+            containers_running: Some(2),
+            containers_total: Some(3),
+            raw_json: None,
+            network_rx_bytes: None,
+            network_tx_bytes: None,
+        };
+        let agents = ["web-1", "web-2", "db-1"].map(String::from);
+        let statuses = [status("web-1", "online"), status("web-2", "offline")];
+        let metrics = [
+            metric("web-1", 12.0, Some(91.5)),
+            metric("web-2", 80.0, None),
+            metric("old-1", 99.0, Some(99.0)),
+        ];
+
+        let health = fleet_health(&agents, &statuses, &metrics, 2);
+        assert_eq!(health.status, "degraded");
+        assert_eq!(
+            (
+                health.agents_online,
+                health.agents_offline,
+                health.agents_unknown
+            ),
+            (1, 1, 1)
+        );
+        // Agents no longer configured are left out
+        assert_eq!(health.containers_total, 6);
+        let worst_cpu = health.worst_cpu.unwrap();
+        assert_eq!(
+            (worst_cpu.agent.as_str(), worst_cpu.percent),
+            ("web-2", 80.0)
+        );
+        assert_eq!(health.worst_disk.unwrap().agent, "web-1");
+        assert_eq!(health.failed_deploys_24h, 2);
+
+        let statuses = [status("web-1", "online")];
+        assert_eq!(
+            fleet_health(&agents[..1], &statuses, &[], 0).status,
+            "healthy"
+        );
+        assert_eq!(fleet_health(&agents[1..], &statuses, &[], 0).status, "down");
+    }
+
+    #[tokio::test]
+    async fn test_fleet_update_reports_per_agent() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let app = Router::new().route(
+                "/api/self-update",
+                post(|| async {
+                    Json(serde_json::json!({
+                        "success": true,
+                        "from_version": "0.9.0",
+                        "to_version": "v1.0.0",
+                        "message": "Updated from v0.9.0 to v1.0.0",
+                        "requires_restart": true,
+                    }))
+                }),
+            );
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let config: config::Config = serde_yaml::from_str(&format!(
+            concat!(
+                "mode: home\nserver: {{}}\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\n",
+                "agents:\n  - name: web-1\n    address: \"{}\"\n    tags: [web]\n",
+                "  - name: web-2\n    address: \"127.0.0.1:1\"\n    tags: [web]\n",
+            ),
+            address
+        ))
+        .unwrap();
+        let state = AppState::new(config);
+
+        let none = update(
+            State(state.clone()),
+            Query(FleetQuery {
+                tag: Some("db".to_string()),
+            }),
+        )
+        .await;
+        assert_eq!(none.err().map(|e| e.0), Some(StatusCode::NOT_FOUND));
+
+        let Json(response) = update(State(state), Query(FleetQuery { tag: None }))
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!(response.updated, 1);
+        assert_eq!(response.failed, 1);
+        assert_eq!(response.results[0].to_version.as_deref(), Some("v1.0.0"));
+        assert!(!response.results[1].success);
+    }
+
+    #[tokio::test]
+    async fn test_fleet_exec_streams_results() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let app = Router::new().route(
+                "/api/exec/:command",
+                post(|Path(command): Path<String>| async move {
+                    Json(ExecResult {
+                        command,
+                        success: true,
+                        output: "Total reclaimed space: 0B\n".to_string(),
+                        duration_ms: 5,
+                    })
+                }),
+            );
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let config: config::Config = serde_yaml::from_str(&format!(
+            concat!(
+                "mode: home\nserver: {{}}\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\n",
+                "modules:\n  exec:\n    timeout: 5s\n",
+                "agents:\n  - name: web-1\n    address: \"{}\"\n    tags: [web]\n",
+                "  - name: web-2\n    address: \"127.0.0.1:1\"\n    tags: [web]\n",
+                "  - name: db-1\n    address: \"127.0.0.1:1\"\n",
+            ),
+            address
+        ))
+        .unwrap();
+        let state = AppState::new(config);
+        let request = |agents: &[&str], all: bool| FleetExecRequest {
+            command: "docker-prune".to_string(),
+            agents: agents.iter().map(|a| a.to_string()).collect(),
+            all,
+            tag: Some("web".to_string()),
+        };
+
+        let neither = exec(State(state.clone()), Json(request(&[], false))).await;
+        assert_eq!(neither.err().map(|e| e.0), Some(StatusCode::BAD_REQUEST));
+        let unknown = exec(State(state.clone()), Json(request(&["web-9"], false))).await;
+        assert_eq!(unknown.err().map(|e| e.0), Some(StatusCode::NOT_FOUND));
+
+        let response = exec(State(state), Json(request(&[], true)))
+            .await
+            .ok()
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let lines: Vec<serde_json::Value> = body
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 3);
+        let web_1 = lines.iter().find(|l| l["agent"] == "web-1").unwrap();
+        assert_eq!(web_1["success"], true);
+        assert_eq!(web_1["output"], "Total reclaimed space: 0B\n");
+        let web_2 = lines.iter().find(|l| l["agent"] == "web-2").unwrap();
+        assert_eq!(web_2["success"], false);
+        assert_eq!(lines[2]["succeeded"], 1);
+        assert_eq!(lines[2]["failed"], 1);
+    }
+}
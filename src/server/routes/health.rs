@@ -1,26 +1,68 @@
 use crate::metrics::{DockerCollector, SystemCollector};
 use crate::server::AppState;
-use axum::{extract::State, Json};
-use serde::Serialize;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
+/// Fields returned by `?light=true`
+const LIGHT_FIELDS: &[&str] = &["status", "version", "uptime_seconds"];
+
+#[derive(Debug, Deserialize, Default)]
+pub struct HealthQuery {
+    /// Comma-separated list of fields to return (e.g. `status,version,system`)
+    pub fields: Option<String>,
+    /// Only status/version/uptime, skipping system and docker collection
+    #[serde(default)]
+    pub light: bool,
+}
+
+impl HealthQuery {
+    /// Whether a field should be included in the response
+    fn wants(&self, field: &str) -> bool {
+        if let Some(fields) = &self.fields {
+            return fields.split(',').any(|f| f.trim() == field);
+        }
+        !self.light || LIGHT_FIELDS.contains(&field)
+    }
+}
+
 #[derive(Serialize)]
 pub struct HealthResponse {
-    pub status: String,
-    pub version: String,
-    pub uptime_seconds: u64,
-    pub mode: String,
-    pub system: crate::metrics::SystemMetrics,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uptime_seconds: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<crate::metrics::SystemMetrics>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub docker: Option<crate::metrics::DockerMetrics>,
+    /// Latest values of `modules.metrics.custom`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub custom: Option<std::collections::BTreeMap<String, serde_json::Value>>,
 }
 
-pub async fn health_check(State(state): State<Arc<AppState>>) -> Json<HealthResponse> {
-    let system = SystemCollector::collect();
+pub async fn health_check(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HealthQuery>,
+) -> Json<HealthResponse> {
+    // System and Docker collection are the expensive parts, only run them when requested
+    let top_processes = state.config.modules.metrics.top_processes;
+    let system = query
+        .wants("system")
+        .then(|| SystemCollector::collect_with_processes(top_processes));
 
     // Collect Docker metrics if enabled
-    let docker = if state.config.modules.metrics.docker_stats {
-        match DockerCollector::new().await {
+    let docker = if query.wants("docker") && state.config.modules.metrics.docker_stats {
+        match DockerCollector::new(state.config.modules.deploy.container_runtime).await {
             Ok(collector) => Some(collector.collect().await),
             Err(_) => None,
         }
@@ -29,13 +71,150 @@ pub async fn health_check(State(state): State<Arc<AppState>>) -> Json<HealthResp
     };
 
     let response = HealthResponse {
-        status: "healthy".to_string(),
-        version: env!("CARGO_PKG_VERSION").to_string(),
-        uptime_seconds: state.uptime_seconds(),
-        mode: format!("{:?}", state.config.mode).to_lowercase(),
+        status: query.wants("status").then(|| "healthy".to_string()),
+        version: query
+            .wants("version")
+            .then(|| env!("CARGO_PKG_VERSION").to_string()),
+        uptime_seconds: query
+            .wants("uptime_seconds")
+            .then(|| state.uptime_seconds()),
+        mode: query
+            .wants("mode")
+            .then(|| format!("{:?}", state.config.mode).to_lowercase()),
         system,
         docker,
+        custom: (query.wants("custom") && !state.config.modules.metrics.custom.is_empty())
+            .then(|| state.custom_metrics.snapshot()),
     };
 
     Json(response)
 }
+
+#[derive(Serialize)]
+pub struct LiveResponse {
+    pub status: &'static str,
+    pub uptime_seconds: u64,
+}
+
+/// Liveness probe: the process is up and serving requests. Never touches
+/// Docker or the database, so it is safe to poll often.
+pub async fn live(State(state): State<Arc<AppState>>) -> Json<LiveResponse> {
+    Json(LiveResponse {
+        status: "alive",
+        uptime_seconds: state.uptime_seconds(),
+    })
+}
+
+#[derive(Serialize)]
+pub struct ReadyChecks {
+    pub config: bool,
+    /// `None` when the mode has no database
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub database: Option<bool>,
+    /// `None` when the deploy module is disabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub worker: Option<bool>,
+    pub shutting_down: bool,
+}
+
+impl ReadyChecks {
+    fn ready(&self) -> bool {
+        self.config
+            && self.database != Some(false)
+            && self.worker != Some(false)
+            && !self.shutting_down
+    }
+}
+
+#[derive(Serialize)]
+pub struct ReadyResponse {
+    pub status: &'static str,
+    pub checks: ReadyChecks,
+}
+
+/// Readiness probe: `503` until the database answers and the deployment
+/// worker runs, and again once shutdown has started
+pub async fn ready(State(state): State<Arc<AppState>>) -> (StatusCode, Json<ReadyResponse>) {
+    let checks = ready_checks(&state).await;
+    let (code, status) = if checks.ready() {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "not_ready")
+    };
+    (code, Json(ReadyResponse { status, checks }))
+}
+
+async fn ready_checks(state: &Arc<AppState>) -> ReadyChecks {
+    let database = match state.db.clone() {
+        Some(db) => Some(
+            tokio::task::spawn_blocking(move || db.ping().is_ok())
+                .await
+                .unwrap_or(false),
+        ),
+        None => None,
+    };
+
+    ReadyChecks {
+        // The config was loaded and validated before the server started
+        config: true,
+        database,
+        worker: state
+            .config
+            .modules
+            .deploy
+            .enabled
+            .then(|| state.worker_running.load(Ordering::Relaxed)),
+        shutting_down: *state.shutdown.borrow(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_field_selection() {
+        let full = HealthQuery::default();
+        assert!(full.wants("system") && full.wants("docker") && full.wants("status"));
+
+        let light = HealthQuery {
+            light: true,
+            ..HealthQuery::default()
+        };
+        assert!(light.wants("status") && light.wants("uptime_seconds"));
+        assert!(!light.wants("system") && !light.wants("docker") && !light.wants("mode"));
+
+        let fields = HealthQuery {
+            fields: Some("status, system".to_string()),
+            light: true,
+        };
+        assert!(fields.wants("status") && fields.wants("system"));
+        assert!(!fields.wants("version") && !fields.wants("docker"));
+    }
+
+    #[tokio::test]
+    async fn test_ready_waits_for_worker_and_database() {
+        let config: crate::config::Config = serde_yaml::from_str(concat!(
+            "mode: home\nserver: {}\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\n",
+            "modules:\n  deploy:\n    enabled: true\n    workers: 1\n",
+        ))
+        .unwrap();
+        let db = Arc::new(crate::storage::Database::new(":memory:").unwrap());
+        let writer = crate::storage::DbWriter::spawn(db.clone(), &Default::default());
+        let state = AppState::with_database(config, db, writer);
+
+        let (code, Json(body)) = ready(State(state.clone())).await;
+        assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body.checks.database, Some(true));
+        assert_eq!(body.checks.worker, Some(false));
+
+        let running = crate::server::app_state::WorkerGuard::new(state.clone());
+        assert_eq!(ready(State(state.clone())).await.0, StatusCode::OK);
+
+        drop(running);
+        state.shutdown.send_replace(true);
+        let (code, Json(body)) = ready(State(state)).await;
+        assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(body.checks.shutting_down);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::config::{self, MIN_JWT_SECRET_LENGTH};
+use crate::server::agents::{self, agent_timeout, AgentRequest};
+use crate::server::auth::{agent_key, Audience};
+use crate::server::middleware::ErrorResponse;
+use crate::server::AppState;
+use axum::{
+    extract::{Path, State},
+    http::{Method, StatusCode},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+#[derive(Serialize, Deserialize)]
+pub struct RotateKeyRequest {
+    pub key: String,
+}
+
+#[derive(Serialize)]
+pub struct RotateKeyResponse {
+    pub agent: String,
+    pub generation: u32,
+    /// Seconds the previous key is still accepted
+    pub overlap_secs: u64,
+}
+
+/// POST /api/agents/:name/rotate-key - Move the agent to a new signing key
+///
+/// The agent is sent its next key (signed with the current one) and Home
+/// switches only once the agent has stored it. Both sides keep accepting
+/// the previous key for `auth.key_rotation_overlap`.
+pub async fn rotate_agent_key(
+    State(state): State<Arc<AppState>>,
+    Path(agent_name): Path<String>,
+) -> Result<Json<RotateKeyResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let Some(store) = &state.agent_keys else {
+        return Err(ErrorResponse::new(
+            StatusCode::CONFLICT,
+            "Per-agent keys are disabled (auth.agent_keys)",
+        ));
+    };
+    let agent = state
+        .live
+        .read()
+        .await
+        .agents
+        .iter()
+        .find(|a| a.name == agent_name)
+        .cloned()
+        .ok_or_else(|| {
+            ErrorResponse::new(
+                StatusCode::NOT_FOUND,
+                &format!("Agent '{}' not found", agent_name),
+            )
+        })?;
+    let overlap = overlap(&state);
+    let internal = |e: String| ErrorResponse::new(StatusCode::INTERNAL_SERVER_ERROR, &e);
+
+    let generation = store.state(&agent.name).map_err(internal)?.generation + 1;
+    let key = agent_key(&state.config.auth.jwt_secret, &agent.name, generation);
+    let token = state
+        .agent_signer(&agent.name)
+        .and_then(|signer| {
+            signer
+                .generate_token("home", 1, &[Audience::Api])
+                .map_err(|e| format!("Failed to generate token: {}", e))
+        })
+        .map_err(internal)?;
+
+    // Not retried: a lost reply leaves the agent on the new key, which a
+    // second rotation would skip past
+    let request = AgentRequest::new(Method::POST, "/api/auth/rotate-key")
+        .bearer(&token)
+        .json(&RotateKeyRequest { key });
+    let reply = agents::call(&state, &agent, request, agent_timeout(&agent))
+        .await
+        .map_err(|e| {
+            ErrorResponse::new(
+                StatusCode::BAD_GATEWAY,
+                &format!("Agent '{}' is unreachable: {}", agent.name, e),
+            )
+        })?;
+    if !reply.is_success() {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_GATEWAY,
+            &format!(
+                "Agent '{}' rejected the key: {} {}",
+                agent.name,
+                reply.status,
+                reply.error()
+            ),
+        ));
+    }
+
+    let generation = store
+        .rotate(
+            &agent.name,
+            time::Duration::seconds(overlap.as_secs() as i64),
+        )
+        .map_err(|e| {
+            warn!(agent = %agent.name, error = %e, "Agent switched keys but Home did not");
+            internal(e)
+        })?;
+    info!(agent = %agent.name, generation, "Agent key rotated");
+
+    Ok(Json(RotateKeyResponse {
+        agent: agent.name,
+        generation,
+        overlap_secs: overlap.as_secs(),
+    }))
+}
+
+/// POST /api/auth/rotate-key - Switch to the key Home sent (Agent mode)
+///
+/// The key is stored in `auth.rotated_key_file` so it survives restarts;
+/// the previous key is accepted for `auth.key_rotation_overlap`.
+pub async fn rotate_key(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<RotateKeyRequest>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    if request.key.len() < MIN_JWT_SECRET_LENGTH {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            &format!("Key must be at least {} characters", MIN_JWT_SECRET_LENGTH),
+        ));
+    }
+    config::save_rotated_key(&state.config.auth.rotated_key_file, &request.key).map_err(|e| {
+        ErrorResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to store key: {}", e),
+        )
+    })?;
+    state.jwt_keys.rotate(&request.key, overlap(&state));
+    info!("Signing key rotated by Home");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn overlap(state: &AppState) -> Duration {
+    humantime::parse_duration(&state.config.auth.key_rotation_overlap)
+        .unwrap_or(Duration::from_secs(600))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::auth::JwtManager;
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    #[tokio::test]
+    async fn test_agent_accepts_rotated_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_file = dir.path().join("jwt_key");
+        let config: config::Config = serde_yaml::from_str(&format!(
+            "mode: agent\nserver: {{}}\nauth:\n  jwt_secret: \"{}\"\n  rotated_key_file: \"{}\"\n",
+            SECRET,
+            key_file.display()
+        ))
+        .unwrap();
+        let state = AppState::new(config);
+        let old = JwtManager::new(SECRET)
+            .generate_token("home", 1, &[Audience::Api])
+            .unwrap();
+
+        let short = rotate_key(
+            State(state.clone()),
+            Json(RotateKeyRequest {
+                key: "short".to_string(),
+            }),
+        )
+        .await;
+        assert_eq!(short.err().map(|e| e.0), Some(StatusCode::BAD_REQUEST));
+
+        let key = agent_key(SECRET, "web-1", 1);
+        let status = rotate_key(
+            State(state.clone()),
+            Json(RotateKeyRequest { key: key.clone() }),
+        )
+        .await
+        .ok();
+        assert_eq!(status, Some(StatusCode::NO_CONTENT));
+        assert_eq!(std::fs::read_to_string(&key_file).unwrap(), key);
+        // Home's tokens signed with the old key still work during the overlap
+        assert!(state.jwt_keys.validate(&old).is_ok());
+        let new = JwtManager::new(&key)
+            .generate_token("home", 1, &[Audience::Api])
+            .unwrap();
+        assert!(state.jwt_keys.validate(&new).is_ok());
+    }
+}
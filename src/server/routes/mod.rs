@@ -1,14 +1,28 @@
 mod api;
+mod auth;
+mod db;
+mod enroll;
+mod exec;
+mod export;
+mod fleet;
 mod health;
+mod keys;
+mod prometheus;
+mod proxy;
+mod tunnel;
+mod update;
 mod webhook;
+mod ws;
 
-use crate::server::assets;
+use crate::server::middleware::ErrorResponse;
 use crate::server::AppState;
+use crate::server::{assets, session};
 use axum::{
     extract::State,
-    response::Response,
-    routing::{get, post},
-    Router,
+    http::{header, HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
+    routing::{any, delete, get, post},
+    Json, Router,
 };
 use std::sync::Arc;
 
@@ -16,57 +30,195 @@ use std::sync::Arc;
 pub fn common() -> Router<Arc<AppState>> {
     Router::new()
         .route("/", get(root))
-        // Webhook routes available on both modes
-        .route("/api/deployments/:name", get(api::get_deployment_config))
+        .route("/auth/refresh", post(auth::refresh))
+        .route("/auth/revoke", post(auth::revoke))
+        .route("/auth/login", get(auth::login_page).post(auth::login))
+        .route("/auth/logout", post(auth::logout))
+        .route("/auth/oidc/login", get(auth::oidc_login))
+        .route("/auth/oidc/callback", get(auth::oidc_callback))
+        // Deployment configs; on Home this is where agents fetch the ones
+        // they do not define themselves (including deployments.d files)
+        .route(
+            "/api/deployments/:name",
+            get(api::get_deployment_config).put(api::put_deployment_config),
+        )
+        .route(
+            "/api/deployments/:name/preview",
+            get(api::preview_deployment),
+        )
         .route("/webhook/deploy/:name", post(webhook::trigger_deploy))
         .route("/webhook/shutdown/:name", post(webhook::trigger_shutdown))
+        .route("/webhook/start/:name", post(webhook::trigger_start))
+        .route("/webhook/restart/:name", post(webhook::trigger_restart))
+        .route("/webhook/rollback/:name", post(webhook::trigger_rollback))
+        .route("/webhook/cancel/:job_id", post(webhook::cancel_job))
+        .route("/webhook/approve/:job_id", post(webhook::approve_job))
         .route("/webhook/status/:job_id", get(webhook::get_job_status))
         .route("/webhook/queue", get(webhook::get_queue_status))
+        .route("/api/queue/clear", post(webhook::clear_queue))
+        .route("/api/queue/:job_id", delete(webhook::remove_job))
+        .route("/api/queue/:job_id/retry", post(webhook::retry_job))
+        .route("/api/queue/:job_id/promote", post(webhook::promote_job))
         .route("/api/pipeline/:id", get(webhook::get_pipeline_status))
+        .route("/api/bans", get(api::list_bans))
+        .route("/api/bans/:ip", delete(api::unban_ip))
+        .route("/api/update/status", get(update::status))
+        .route("/api/update/check", post(update::check))
+        .route("/api/update/apply", post(update::apply))
+        .route("/metrics", get(prometheus::metrics))
 }
 
 /// Agent mode routes
 pub fn agent() -> Router<Arc<AppState>> {
-    Router::new().route("/health", get(health::health_check))
+    Router::new()
+        .route("/health", get(health::health_check))
+        .route("/health/live", get(health::live))
+        .route("/health/ready", get(health::ready))
+        .route("/api/exec/:command", post(exec::run))
+        .route("/api/self-update", post(update::apply))
+        .route("/api/auth/rotate-key", post(keys::rotate_key))
 }
 
 /// Home mode routes
 pub fn home() -> Router<Arc<AppState>> {
     Router::new()
         .route("/health", get(health::health_check))
+        .route("/health/live", get(health::live))
+        .route("/health/ready", get(health::ready))
+        .route("/auth/enroll", post(enroll::enroll))
         .route("/monitoring", get(monitoring_dashboard))
+        .route("/monitoring/token", get(monitoring_token))
+        .route("/monitoring/*path", get(monitoring_asset))
         // Agent list
         .route("/api/agents", get(list_agents))
         .route("/api/agents/statuses", get(api::get_all_agent_statuses))
+        .route("/api/agents/discovered", get(api::get_discovered_agents))
         .route("/api/agents/:name/status", get(api::get_agent_status))
+        .route("/api/agents/:name/events", get(api::get_agent_events))
+        .route(
+            "/api/agents/:name/deployments",
+            post(fleet::push_deployments),
+        )
+        .route("/api/agents/:name/proxy/*path", any(proxy::forward))
+        .route("/api/agents/:name/rotate-key", post(keys::rotate_agent_key))
+        // Reverse connections from agents behind NAT
+        .route("/api/tunnel/poll", post(tunnel::poll))
+        .route("/api/tunnel/reply", post(tunnel::reply))
         // Historical data
         .route("/api/metrics", get(api::get_metrics))
+        .route("/api/metrics/export", get(export::metrics))
+        .route("/api/metrics/custom", get(api::get_custom_metrics))
+        .route("/api/ingest/metrics", post(api::ingest_metrics))
+        .route(
+            "/api/containers/:name/metrics",
+            get(api::get_container_metrics),
+        )
         .route("/api/deploys", get(api::get_deploy_history))
+        .route("/api/deploys/export", get(export::deploys))
+        .route("/api/deploys/search", get(api::search_deploys))
+        .route("/api/deploys/:id/log", get(api::get_deploy_log))
         .route("/api/suspicious", get(api::get_suspicious_requests))
+        .route("/api/db/backup", post(db::backup))
         // Deployments config
         .route("/api/deployments", get(api::get_deployments))
+        .route("/api/fleet/deploy/:name", post(fleet::deploy))
+        .route("/api/fleet/exec", post(fleet::exec))
+        .route("/api/fleet/update", post(fleet::update))
+        .route("/api/fleet/inventory", get(fleet::inventory))
+        .route("/api/fleet/health", get(fleet::health))
+        // Live dashboard updates
+        .route("/ws", get(ws::live_updates))
 }
 
 async fn root() -> &'static str {
     "infractl"
 }
 
-async fn monitoring_dashboard(State(state): State<Arc<AppState>>) -> Response {
-    assets::serve_dashboard_with_token(&state.config.auth.jwt_secret).await
+async fn monitoring_dashboard(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Response {
+    match dashboard_user(&state, &headers, &uri) {
+        Ok(_) => assets::serve_dashboard(&headers).await,
+        Err(login) => login.into_response(),
+    }
+}
+
+async fn monitoring_asset(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    uri: Uri,
+    axum::extract::Path(path): axum::extract::Path<String>,
+) -> Response {
+    match dashboard_user(&state, &headers, &uri) {
+        Ok(_) => assets::serve_monitoring_path(&path, &headers).await,
+        Err(login) => login.into_response(),
+    }
+}
+
+/// GET /monitoring/token - API token for the dashboard's session user.
+/// Pages fetch it on load and before it expires.
+async fn monitoring_token(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Response {
+    let Ok(user) = dashboard_user(&state, &headers, &uri) else {
+        return ErrorResponse::new(StatusCode::UNAUTHORIZED, "Login required").into_response();
+    };
+
+    match session::dashboard_token(&state.jwt_keys, &user) {
+        Ok(token) => (
+            [(header::CACHE_CONTROL, "no-store")],
+            Json(serde_json::json!({
+                "token": token,
+                "expires_in": session::DASHBOARD_TOKEN_TTL_HOURS * 3600,
+            })),
+        )
+            .into_response(),
+        Err(e) => ErrorResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to issue token: {}", e),
+        )
+        .into_response(),
+    }
+}
+
+/// Who the dashboard token is issued to. With a login required (the default,
+/// or OIDC configured) this is the session user; without a session the
+/// browser is sent to the login page.
+fn dashboard_user(state: &AppState, headers: &HeaderMap, uri: &Uri) -> Result<String, Redirect> {
+    if state.oidc.is_none() && !state.config.auth.dashboard.require_login {
+        return Ok("dashboard".to_string());
+    }
+    session::subject(headers, &state.jwt_keys)
+        .ok_or_else(|| Redirect::to(&session::login_path(uri.path(), false)))
 }
 
 async fn list_agents(
     axum::extract::State(state): axum::extract::State<Arc<AppState>>,
 ) -> axum::Json<serde_json::Value> {
+    // Kept up to date by the health polls; agents never reached stay "unknown"
+    let statuses = state
+        .db
+        .as_ref()
+        .and_then(|db| db.get_all_agent_statuses().ok())
+        .unwrap_or_default();
+
     let agents: Vec<_> = state
-        .config
+        .live
+        .read()
+        .await
         .agents
         .iter()
         .map(|a| {
+            let status = statuses.iter().find(|s| s.agent_name == a.name);
             serde_json::json!({
                 "name": a.name,
                 "address": a.address,
-                "status": "unknown"
+                "status": status.map_or("unknown", |s| s.status.as_str()),
+                "last_seen": status.and_then(|s| s.last_seen.format(&time::format_description::well_known::Rfc3339).ok()),
             })
         })
         .collect();
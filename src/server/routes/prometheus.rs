@@ -0,0 +1,43 @@
+use crate::server::AppState;
+use axum::{extract::State, http::header, response::IntoResponse};
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// GET /metrics - Prometheus text exposition
+pub async fn metrics(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let limiter = state.rate_limiter.stats().await;
+
+    let mut body = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+        let _ = writeln!(body, "# HELP {} {}", name, help);
+        let _ = writeln!(body, "# TYPE {} {}", name, kind);
+        let _ = writeln!(body, "{} {}", name, value);
+    };
+
+    metric(
+        "infractl_uptime_seconds",
+        "gauge",
+        "Seconds since the server started",
+        state.uptime_seconds().to_string(),
+    );
+    metric(
+        "infractl_rate_limiter_tracked_ips",
+        "gauge",
+        "IPs currently tracked by the rate limiter",
+        limiter.tracked_ips.to_string(),
+    );
+    metric(
+        "infractl_rate_limiter_rejected_total",
+        "counter",
+        "Requests rejected by the rate limiter",
+        limiter.rejected_total.to_string(),
+    );
+    metric(
+        "infractl_rate_limiter_evicted_total",
+        "counter",
+        "Tracked IPs evicted to stay under max_tracked_ips",
+        limiter.evicted_total.to_string(),
+    );
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
@@ -0,0 +1,193 @@
+use crate::server::agents::{self, agent_timeout, AgentRequest};
+use crate::server::auth::Claims;
+use crate::server::middleware::ErrorResponse;
+use crate::server::AppState;
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use std::sync::Arc;
+use time::{Duration, OffsetDateTime};
+use tracing::{info, warn};
+
+/// Lifetime of the token a proxied request carries to the agent
+const PROXY_TOKEN_TTL: Duration = Duration::minutes(5);
+
+/// Request headers passed on to the agent; `Authorization` is replaced
+const FORWARDED_HEADERS: &[header::HeaderName] =
+    &[header::ACCEPT, header::CONTENT_TYPE, header::IF_NONE_MATCH];
+
+/// Response headers passed back to the caller
+const RETURNED_HEADERS: &[header::HeaderName] =
+    &[header::CONTENT_TYPE, header::ETAG, header::CACHE_CONTROL];
+
+/// ANY /api/agents/:name/proxy/*path - Forward a request to the named agent
+///
+/// The auth middleware checks the caller's token against the forwarded
+/// path, and the agent gets a short-lived token with the same subject,
+/// audiences and scopes, so proxying never widens what the caller may do.
+/// Requests are sent once: a deploy trigger must not run twice. Bodies are
+/// text, as every agent API takes JSON.
+pub async fn forward(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Path((agent_name, path)): Path<(String, String)>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let agent = state
+        .live
+        .read()
+        .await
+        .agents
+        .iter()
+        .find(|a| a.name == agent_name)
+        .cloned()
+        .ok_or_else(|| {
+            ErrorResponse::new(
+                StatusCode::NOT_FOUND,
+                &format!("Agent '{}' not found", agent_name),
+            )
+        })?;
+
+    let token = proxy_token(&state, &agent.name, &claims).map_err(|e| {
+        ErrorResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to generate token: {}", e),
+        )
+    })?;
+
+    let body = String::from_utf8(body.to_vec()).map_err(|_| {
+        ErrorResponse::new(StatusCode::BAD_REQUEST, "Request body must be UTF-8 text")
+    })?;
+    let target = match uri.query() {
+        Some(query) => format!("/{}?{}", path, query),
+        None => format!("/{}", path),
+    };
+    info!(agent = %agent.name, method = %method, path = %path, by = %claims.sub, "Proxying request");
+
+    let mut request = AgentRequest::new(method, &target).bearer(&token);
+    for name in FORWARDED_HEADERS {
+        if let Some(value) = headers.get(name).and_then(|v| v.to_str().ok()) {
+            request = request.header(name.as_str(), value);
+        }
+    }
+    request.body = body;
+
+    let reply = agents::call(&state, &agent, request, agent_timeout(&agent))
+        .await
+        .map_err(|e| {
+            warn!(agent = %agent.name, error = %e, "Proxied request failed");
+            ErrorResponse::new(
+                StatusCode::BAD_GATEWAY,
+                &format!("Agent '{}' is unreachable: {}", agent.name, e),
+            )
+        })?;
+
+    let status = StatusCode::from_u16(reply.status).unwrap_or(StatusCode::BAD_GATEWAY);
+    let mut returned = HeaderMap::new();
+    for name in RETURNED_HEADERS {
+        let value = reply
+            .headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name.as_str()))
+            .and_then(|(_, value)| HeaderValue::from_str(value).ok());
+        if let Some(value) = value {
+            returned.insert(name, value);
+        }
+    }
+
+    Ok((status, returned, reply.body).into_response())
+}
+
+/// Token for the agent on behalf of the caller, never outliving the caller's own
+fn proxy_token(state: &AppState, agent: &str, claims: &Claims) -> Result<String, String> {
+    let now = OffsetDateTime::now_utc();
+    let forwarded = Claims {
+        iat: now.unix_timestamp(),
+        exp: claims.exp.min((now + PROXY_TOKEN_TTL).unix_timestamp()),
+        ..claims.clone()
+    };
+    state
+        .agent_signer(agent)?
+        .encode_claims(&forwarded)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::server::auth::{Audience, JwtManager};
+    use axum::{routing::post, Router};
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    #[tokio::test]
+    async fn test_forward_carries_caller_claims() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let app = Router::new().route(
+                "/webhook/deploy/:name",
+                post(|headers: HeaderMap, uri: Uri, body: Bytes| async move {
+                    let token = headers[header::AUTHORIZATION].to_str().unwrap()[7..].to_string();
+                    let claims = JwtManager::new(SECRET).validate_token(&token).unwrap();
+                    (
+                        StatusCode::ACCEPTED,
+                        Json(serde_json::json!({
+                            "sub": claims.sub,
+                            "aud": claims.aud,
+                            "scopes": claims.scopes,
+                            "query": uri.query(),
+                            "body": String::from_utf8_lossy(&body),
+                        })),
+                    )
+                }),
+            );
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let config: config::Config = serde_yaml::from_str(&format!(
+            "mode: home\nserver: {{}}\nauth:\n  jwt_secret: \"{}\"\nagents:\n  - name: web-1\n    address: \"{}\"\n",
+            SECRET, address
+        ))
+        .unwrap();
+        let state = AppState::new(config);
+        let mut claims = Claims::new("github-ci", 1, &[Audience::Webhook]);
+        claims.scopes = vec!["deploy:web-app".to_string()];
+        let call = |agent: &str| {
+            forward(
+                State(state.clone()),
+                Extension(claims.clone()),
+                Path((agent.to_string(), "webhook/deploy/web-app".to_string())),
+                Method::POST,
+                "/api/agents/web-1/proxy/webhook/deploy/web-app?ref=v1.2.0"
+                    .parse()
+                    .unwrap(),
+                HeaderMap::new(),
+                Bytes::from_static(b"{}"),
+            )
+        };
+
+        let unknown = call("web-9").await;
+        assert_eq!(unknown.err().map(|e| e.0), Some(StatusCode::NOT_FOUND));
+
+        let response = call("web-1").await.ok().unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["sub"], "github-ci");
+        assert_eq!(body["aud"], serde_json::json!(["webhook"]));
+        assert_eq!(body["scopes"], serde_json::json!(["deploy:web-app"]));
+        assert_eq!(body["query"], "ref=v1.2.0");
+        assert_eq!(body["body"], "{}");
+    }
+}
@@ -0,0 +1,52 @@
+use crate::server::auth::Claims;
+use crate::server::middleware::ErrorResponse;
+use crate::server::tunnel::{TunnelReply, TunnelRequest};
+use crate::server::AppState;
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use std::sync::Arc;
+use tracing::{debug, info};
+
+/// POST /api/tunnel/poll - Requests queued for the calling agent
+///
+/// Held open until a request is queued or `POLL_WAIT` passes. The agent is
+/// the token's subject and must be one of the configured agents.
+pub async fn poll(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Vec<TunnelRequest>>, (StatusCode, Json<ErrorResponse>)> {
+    known_agent(&state, &claims.sub).await?;
+    if !state.tunnels.is_connected(&claims.sub) {
+        info!(agent = %claims.sub, "Agent connected over reverse connection");
+    }
+    Ok(Json(state.tunnels.poll(&claims.sub).await))
+}
+
+/// POST /api/tunnel/reply - The calling agent's reply to a polled request
+pub async fn reply(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Json(reply): Json<TunnelReply>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    known_agent(&state, &claims.sub).await?;
+    let id = reply.id;
+    if !state.tunnels.reply(&claims.sub, reply) {
+        // The caller gave up waiting
+        debug!(agent = %claims.sub, id, "Reply to a request nobody waits for");
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn known_agent(
+    state: &AppState,
+    name: &str,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let live = state.live.read().await;
+    if live.agents.iter().any(|a| a.name == name) {
+        Ok(())
+    } else {
+        Err(ErrorResponse::new(
+            StatusCode::NOT_FOUND,
+            &format!("Agent '{}' not found", name),
+        ))
+    }
+}
@@ -0,0 +1,119 @@
+use crate::server::middleware::ErrorResponse;
+use crate::server::AppState;
+use crate::updater::{self, binary, Updater, UpdaterState};
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Time for the apply response to reach the client before the process restarts
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Serialize)]
+pub struct UpdateStatusResponse {
+    pub enabled: bool,
+    pub self_update: bool,
+    pub current_version: &'static str,
+    #[serde(flatten)]
+    pub state: UpdaterState,
+}
+
+async fn status_of(updater: Option<&Updater>) -> UpdateStatusResponse {
+    let state = match updater {
+        Some(updater) => updater.get_state().await,
+        None => UpdaterState::default(),
+    };
+    UpdateStatusResponse {
+        enabled: updater.is_some(),
+        self_update: updater.is_some_and(|u| u.self_update_enabled()),
+        current_version: env!("CARGO_PKG_VERSION"),
+        state,
+    }
+}
+
+fn self_updater(state: &AppState) -> Result<&Updater, (StatusCode, Json<ErrorResponse>)> {
+    let updater = state.updater.get().ok_or_else(|| {
+        ErrorResponse::new(StatusCode::SERVICE_UNAVAILABLE, "Updates are not enabled")
+    })?;
+    if !updater.self_update_enabled() {
+        return Err(ErrorResponse::new(
+            StatusCode::CONFLICT,
+            "Self-update is disabled",
+        ));
+    }
+    Ok(updater)
+}
+
+/// GET /api/update/status - Last check, latest version and last error
+pub async fn status(State(state): State<Arc<AppState>>) -> Json<UpdateStatusResponse> {
+    Json(status_of(state.updater.get().map(Arc::as_ref)).await)
+}
+
+/// POST /api/update/check - Check for a new release now
+pub async fn check(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<UpdateStatusResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let updater = self_updater(&state)?;
+    updater.check_for_updates().await.map_err(|e| {
+        ErrorResponse::new(
+            StatusCode::BAD_GATEWAY,
+            &format!("Update check failed: {}", e),
+        )
+    })?;
+    Ok(Json(status_of(Some(updater)).await))
+}
+
+/// POST /api/update/apply (and `/api/self-update` on agents) - Install the
+/// latest release and restart
+pub async fn apply(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    let updater = self_updater(&state)?;
+    let result = updater.self_update().await.map_err(|e| {
+        if e == updater::UPDATE_IN_PROGRESS {
+            ErrorResponse::new(StatusCode::CONFLICT, &e)
+        } else {
+            ErrorResponse::new(StatusCode::BAD_GATEWAY, &format!("Update failed: {}", e))
+        }
+    })?;
+
+    info!(
+        from = %result.from_version,
+        to = %result.to_version,
+        restart = result.requires_restart,
+        "Update applied via API"
+    );
+    if result.requires_restart {
+        tokio::spawn(async {
+            tokio::time::sleep(RESTART_DELAY).await;
+            if let Err(e) = binary::signal_systemd_restart() {
+                error!(error = %e, "Failed to restart after update");
+            }
+        });
+    }
+
+    Ok(Json(serde_json::json!(result)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn test_status_without_updater() {
+        let config: Config = serde_yaml::from_str(
+            "mode: agent\nserver: {}\nauth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\n",
+        )
+        .unwrap();
+        let state = AppState::new(config);
+
+        let Json(body) = status(State(state.clone())).await;
+        assert!(!body.enabled);
+        assert!(!body.state.update_available);
+
+        let err = check(State(state)).await.unwrap_err();
+        assert_eq!(err.0, StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
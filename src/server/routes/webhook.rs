@@ -1,17 +1,21 @@
-use crate::config::{DeployCategory, DeploymentConfig};
-use crate::deploy::DeployJob;
-use crate::server::auth::JwtManager;
+use crate::config::{
+    DeployCategory, DeploymentConfig, OutsideHours, ScheduleConstraint, WebhookEndpoint,
+    WebhooksConfig,
+};
+use crate::deploy::{DeployJob, JobKind, JobStatus};
+use crate::server::agents::agent_url;
+use crate::server::auth::{constant_time_eq, Audience, Claims};
 use crate::server::middleware::ErrorResponse;
-use crate::server::AppState;
+use crate::server::{push, AppState};
 use axum::{
     body::Bytes,
     extract::{ConnectInfo, Path, State},
     http::{HeaderMap, StatusCode},
-    Json,
+    Extension, Json,
 };
 use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
-use sha2::Sha256;
+use sha2::{Digest, Sha256};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -38,6 +42,9 @@ pub struct WebhookResponse {
 #[derive(Deserialize)]
 pub struct TriggerRequest {
     pub source: Option<String>,
+    /// git_pull: tag or commit to deploy instead of the configured `ref`/branch tip
+    #[serde(default, rename = "ref")]
+    pub git_ref: Option<String>,
 }
 
 /// POST /webhook/deploy/:name - Trigger a deployment
@@ -49,32 +56,10 @@ pub async fn trigger_deploy(
     headers: HeaderMap,
     body: Bytes,
 ) -> Result<Json<WebhookResponse>, (StatusCode, Json<ErrorResponse>)> {
-    // Look up deployment in local config
-    let deploy_config = state.deploy_config.read().await;
-    let deployment = deploy_config
-        .deployments
-        .iter()
-        .find(|d| d.name == deployment_name)
-        .cloned();
-    drop(deploy_config);
-
-    // If not found locally, try fetching from Home
-    let deployment = match deployment {
-        Some(d) => d,
-        None => fetch_from_home(&state, &deployment_name)
-            .await
-            .map_err(|e| {
-                ErrorResponse::new(
-                    StatusCode::NOT_FOUND,
-                    &format!("Deployment '{}': {}", deployment_name, e),
-                )
-            })?,
-    };
+    let deployment = resolve_deployment(&state, &deployment_name).await?;
 
     // Check force flag (only from localhost via CLI)
-    let is_localhost = addr.ip().is_loopback();
-    let force =
-        is_localhost && headers.get("X-Deploy-Force").and_then(|v| v.to_str().ok()) == Some("true");
+    let force = force_requested(&addr, &headers);
 
     // Block system deployments always
     if deployment.category == DeployCategory::System {
@@ -99,10 +84,8 @@ pub async fn trigger_deploy(
     }
 
     // Find webhook config for this deployment
-    let webhook_config = state
-        .config
-        .modules
-        .webhooks
+    let webhooks = state.live.read().await.webhooks.clone();
+    let webhook_config = webhooks
         .endpoints
         .iter()
         .find(|e| e.deployment.as_ref() == Some(&deployment_name));
@@ -111,10 +94,63 @@ pub async fn trigger_deploy(
     if let Some(wh) = webhook_config {
         if let Some(secret) = &wh.secret {
             if !secret.is_empty() {
-                verify_signature(&headers, &body, secret).map_err(|e| {
+                let now = OffsetDateTime::now_utc().unix_timestamp();
+                let signed = verify_signature(&headers, &body, secret, &webhooks, now).map_err(|e| {
                     warn!(deployment = %deployment_name, error = %e, "Webhook signature verification failed");
                     ErrorResponse::new(StatusCode::UNAUTHORIZED, &e)
                 })?;
+
+                // Reject replays of an already accepted request. The key is
+                // what the secret vouches for, so unsigned headers (delivery
+                // ids) cannot be changed to get a replay through.
+                let window = humantime::parse_duration(&webhooks.replay_window)
+                    .unwrap_or(Duration::from_secs(24 * 3600));
+                let key = format!("{}:{}", deployment_name, signed);
+                if !state.webhook_replay.check_and_record(&key, window) {
+                    warn!(deployment = %deployment_name, "Webhook replay rejected");
+                    return Err(ErrorResponse::new(
+                        StatusCode::CONFLICT,
+                        "Duplicate webhook delivery",
+                    ));
+                }
+            }
+        }
+    }
+
+    // Skip pushes to other branches or touching no relevant files
+    if let Some(reason) = webhook_config.and_then(|wh| push_filter_mismatch(wh, &body)) {
+        info!(deployment = %deployment_name, reason = %reason, "Webhook push filtered, deploy skipped");
+        return Ok(Json(WebhookResponse {
+            success: true,
+            message: format!("Deployment '{}' skipped: {}", deployment_name, reason),
+            job_id: None,
+            pipeline_id: None,
+        }));
+    }
+
+    // Outside the allowed hours: reject, or defer the job to the next window
+    let mut not_before = None;
+    if let Some(constraint) = webhook_config
+        .and_then(|wh| wh.schedule_constraint.as_ref())
+        .filter(|_| !force)
+    {
+        let now = OffsetDateTime::now_utc();
+        let window = next_allowed_time(constraint, now)
+            .map_err(|e| ErrorResponse::new(StatusCode::INTERNAL_SERVER_ERROR, &e))?;
+        if window > now {
+            match constraint.outside_hours {
+                OutsideHours::Reject => {
+                    warn!(deployment = %deployment_name, "Webhook outside allowed hours, rejected");
+                    return Err(ErrorResponse::new(
+                        StatusCode::FORBIDDEN,
+                        &format!(
+                            "Deployment '{}' is outside its allowed hours, next window opens at {}",
+                            deployment_name,
+                            format_rfc3339(window)
+                        ),
+                    ));
+                }
+                OutsideHours::Defer => not_before = Some(window),
             }
         }
     }
@@ -135,13 +171,25 @@ pub async fn trigger_deploy(
         deployment.force = true;
     }
 
-    let job = DeployJob::new(
+    // Manual triggers may pin a tag/commit; provider push payloads carry their own `ref`
+    if trigger_source.as_deref() == Some("manual") {
+        if let Some(git_ref) =
+            requested_git_ref(&body).map_err(|e| ErrorResponse::new(StatusCode::BAD_REQUEST, &e))?
+        {
+            info!(deployment = %deployment_name, git_ref = %git_ref, "Deploying pinned git ref");
+            deployment.git_ref = Some(git_ref);
+        }
+    }
+
+    let mut job = DeployJob::new(
         "local".to_string(), // Agent name for local deployments
         deployment_name.clone(),
         deployment,
         trigger_source,
         None, // new pipeline_id
     );
+    job.not_before = not_before;
+    let awaiting_approval = job.status == JobStatus::AwaitingApproval;
 
     let pipeline_id = job.pipeline_id.clone();
     let job_id = queue.enqueue(job).await;
@@ -149,12 +197,24 @@ pub async fn trigger_deploy(
     info!(
         deployment = %deployment_name,
         job_id = %job_id,
+        not_before = ?not_before,
         "Deployment queued"
     );
 
+    let message = match not_before {
+        _ if awaiting_approval => {
+            format!("Deployment '{}' queued, awaiting approval", deployment_name)
+        }
+        Some(at) => format!(
+            "Deployment '{}' deferred until {}",
+            deployment_name,
+            format_rfc3339(at)
+        ),
+        None => format!("Deployment '{}' queued", deployment_name),
+    };
     Ok(Json(WebhookResponse {
         success: true,
-        message: format!("Deployment '{}' queued", deployment_name),
+        message,
         job_id: Some(job_id),
         pipeline_id: Some(pipeline_id),
     }))
@@ -164,9 +224,66 @@ pub async fn trigger_deploy(
 /// Config is resolved locally or fetched from Home (never accepted from body)
 pub async fn trigger_shutdown(
     State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(deployment_name): Path<String>,
+    headers: HeaderMap,
+    _body: Bytes,
+) -> Result<Json<WebhookResponse>, (StatusCode, Json<ErrorResponse>)> {
+    queue_lifecycle(&state, &addr, &headers, JobKind::Shutdown, deployment_name).await
+}
+
+/// POST /webhook/start/:name - Start a stopped docker_pull deployment (`docker compose start`)
+pub async fn trigger_start(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(deployment_name): Path<String>,
+    headers: HeaderMap,
+    _body: Bytes,
+) -> Result<Json<WebhookResponse>, (StatusCode, Json<ErrorResponse>)> {
+    queue_lifecycle(&state, &addr, &headers, JobKind::Start, deployment_name).await
+}
+
+/// POST /webhook/restart/:name - Restart a docker_pull deployment without redeploying
+pub async fn trigger_restart(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     Path(deployment_name): Path<String>,
+    headers: HeaderMap,
     _body: Bytes,
 ) -> Result<Json<WebhookResponse>, (StatusCode, Json<ErrorResponse>)> {
+    queue_lifecycle(&state, &addr, &headers, JobKind::Restart, deployment_name).await
+}
+
+/// Queue a shutdown/start/restart job, so it gets a job ID, status and history row.
+/// Same category rules as deploy.
+async fn queue_lifecycle(
+    state: &AppState,
+    addr: &SocketAddr,
+    headers: &HeaderMap,
+    kind: JobKind,
+    deployment_name: String,
+) -> Result<Json<WebhookResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let action = match kind {
+        JobKind::Start => "started via webhook",
+        JobKind::Restart => "restarted via webhook",
+        _ => "shut down via webhook",
+    };
+    let mut deployment = resolve_deployment(state, &deployment_name).await?;
+    deployment.force = check_category(
+        addr,
+        headers,
+        &deployment_name,
+        &deployment.category,
+        action,
+    )?;
+    enqueue_lifecycle(state, kind, deployment_name, deployment).await
+}
+
+/// Deployment config from the local config, or fetched from Home
+async fn resolve_deployment(
+    state: &AppState,
+    deployment_name: &str,
+) -> Result<DeploymentConfig, (StatusCode, Json<ErrorResponse>)> {
     // Look up deployment in local config
     let deploy_config = state.deploy_config.read().await;
     let deployment = deploy_config
@@ -177,57 +294,369 @@ pub async fn trigger_shutdown(
     drop(deploy_config);
 
     // If not found locally, try fetching from Home
-    let deployment = match deployment {
-        Some(d) => d,
-        None => fetch_from_home(&state, &deployment_name)
-            .await
-            .map_err(|e| {
-                ErrorResponse::new(
-                    StatusCode::NOT_FOUND,
-                    &format!("Deployment '{}': {}", deployment_name, e),
-                )
-            })?,
-    };
+    match deployment {
+        Some(d) => Ok(d),
+        None => fetch_from_home(state, deployment_name).await.map_err(|e| {
+            ErrorResponse::new(
+                StatusCode::NOT_FOUND,
+                &format!("Deployment '{}': {}", deployment_name, e),
+            )
+        }),
+    }
+}
 
-    // Get executor
-    let executor = state.deploy_executor.as_ref().ok_or_else(|| {
+async fn enqueue_lifecycle(
+    state: &AppState,
+    kind: JobKind,
+    deployment_name: String,
+    deployment: DeploymentConfig,
+) -> Result<Json<WebhookResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let queue = state.deploy_queue.as_ref().ok_or_else(|| {
         ErrorResponse::new(
             StatusCode::SERVICE_UNAVAILABLE,
-            "Deploy executor not available",
+            "Deployment queue not available",
         )
     })?;
 
-    info!(deployment = %deployment_name, "Executing shutdown");
+    let job = DeployJob::lifecycle(
+        kind,
+        "local".to_string(),
+        deployment_name.clone(),
+        deployment,
+    );
+    let pipeline_id = job.pipeline_id.clone();
+    let job_id = queue.enqueue(job).await;
 
-    // Execute shutdown synchronously (not queued)
-    let result = executor.shutdown(&deployment).await;
+    info!(deployment = %deployment_name, job_id = %job_id, kind = %kind, "Lifecycle job queued");
 
-    if result.success {
-        info!(
-            deployment = %deployment_name,
-            duration_ms = result.duration_ms,
-            "Shutdown completed successfully"
-        );
-        Ok(Json(WebhookResponse {
-            success: true,
-            message: format!(
-                "Deployment '{}' shutdown complete\n{}",
-                deployment_name, result.output
+    Ok(Json(WebhookResponse {
+        success: true,
+        message: format!("Deployment '{}' {} queued", deployment_name, kind),
+        job_id: Some(job_id),
+        pipeline_id: Some(pipeline_id),
+    }))
+}
+
+/// POST /webhook/rollback/:name - Revert a deployment to its previous commit/images
+/// Config is resolved locally or fetched from Home (never accepted from body).
+/// The rollback is queued like a deploy, so it gets a job ID and history row
+/// and never runs next to another job of its concurrency group.
+pub async fn trigger_rollback(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(deployment_name): Path<String>,
+    headers: HeaderMap,
+    _body: Bytes,
+) -> Result<Json<WebhookResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let mut deployment = resolve_deployment(&state, &deployment_name).await?;
+
+    deployment.force = check_category(
+        &addr,
+        &headers,
+        &deployment_name,
+        &deployment.category,
+        "rolled back via webhook",
+    )?;
+
+    enqueue_lifecycle(&state, JobKind::Rollback, deployment_name, deployment).await
+}
+
+/// POST /webhook/cancel/:job_id - Cancel a pending or running deployment job
+/// Running jobs are aborted and their git/docker/script processes killed
+pub async fn cancel_job(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(job_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<WebhookResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let queue = state.deploy_queue.as_ref().ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Deployment queue not available",
+        )
+    })?;
+
+    let job = queue
+        .get_job(&job_id)
+        .await
+        .ok_or_else(|| ErrorResponse::new(StatusCode::NOT_FOUND, "Job not found"))?;
+
+    check_category(
+        &addr,
+        &headers,
+        &job.deployment_name,
+        &job.config.category,
+        "cancelled via webhook",
+    )?;
+
+    if !queue.cancel(&job_id).await {
+        return Err(ErrorResponse::new(
+            StatusCode::CONFLICT,
+            &format!("Job is already {:?}", job.status),
+        ));
+    }
+
+    info!(
+        deployment = %job.deployment_name,
+        job_id = %job_id,
+        status = ?job.status,
+        "Deployment job cancelled"
+    );
+
+    Ok(Json(WebhookResponse {
+        success: true,
+        message: format!(
+            "Deployment '{}' job cancelled ({:?})",
+            job.deployment_name, job.status
+        ),
+        job_id: Some(job_id),
+        pipeline_id: Some(job.pipeline_id),
+    }))
+}
+
+/// POST /api/queue/:job_id/retry - Queue a failed or cancelled job again with
+/// the deployment's current config
+pub async fn retry_job(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(job_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<WebhookResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let queue = state.deploy_queue.as_ref().ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Deployment queue not available",
+        )
+    })?;
+
+    let job = queue
+        .get_job(&job_id)
+        .await
+        .ok_or_else(|| ErrorResponse::new(StatusCode::NOT_FOUND, "Job not found"))?;
+    if !matches!(job.status, JobStatus::Failed | JobStatus::Cancelled) {
+        return Err(ErrorResponse::new(
+            StatusCode::CONFLICT,
+            &format!(
+                "Job is {:?}, only failed or cancelled jobs can be retried",
+                job.status
             ),
-            job_id: None,
-            pipeline_id: None,
-        }))
-    } else {
+        ));
+    }
+    if job.cancelled_before_approval() {
+        return Err(ErrorResponse::new(
+            StatusCode::CONFLICT,
+            "Job was cancelled before it was approved, trigger a new deployment instead",
+        ));
+    }
+
+    let config = state
+        .deploy_config
+        .read()
+        .await
+        .deployments
+        .iter()
+        .find(|d| d.name == job.deployment_name)
+        .cloned()
+        .ok_or_else(|| {
+            ErrorResponse::new(
+                StatusCode::NOT_FOUND,
+                &format!(
+                    "Deployment '{}' is no longer configured",
+                    job.deployment_name
+                ),
+            )
+        })?;
+
+    check_category(
+        &addr,
+        &headers,
+        &job.deployment_name,
+        &config.category,
+        "retried via API",
+    )?;
+
+    let retry_id = queue.retry(&job_id, config).await.ok_or_else(|| {
+        ErrorResponse::new(StatusCode::CONFLICT, "Job is no longer failed or cancelled")
+    })?;
+
+    info!(
+        deployment = %job.deployment_name,
+        job_id = %job_id,
+        retry_id = %retry_id,
+        "Deployment job retried"
+    );
+
+    Ok(Json(WebhookResponse {
+        success: true,
+        message: format!(
+            "Deployment '{}' queued again (attempt {})",
+            job.deployment_name,
+            job.attempt + 1
+        ),
+        job_id: Some(retry_id),
+        pipeline_id: Some(job.pipeline_id),
+    }))
+}
+
+/// DELETE /api/queue/:job_id - Drop a pending or awaiting approval job
+pub async fn remove_job(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(job_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<WebhookResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let queue = state.deploy_queue.as_ref().ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Deployment queue not available",
+        )
+    })?;
+
+    let job = queue
+        .get_job(&job_id)
+        .await
+        .ok_or_else(|| ErrorResponse::new(StatusCode::NOT_FOUND, "Job not found"))?;
+
+    check_category(
+        &addr,
+        &headers,
+        &job.deployment_name,
+        &job.config.category,
+        "removed via API",
+    )?;
+
+    if !queue.remove(&job_id).await {
+        return Err(ErrorResponse::new(
+            StatusCode::CONFLICT,
+            &format!("Job is {:?}, only waiting jobs can be removed", job.status),
+        ));
+    }
+
+    info!(deployment = %job.deployment_name, job_id = %job_id, "Deployment job removed from queue");
+
+    Ok(Json(WebhookResponse {
+        success: true,
+        message: format!(
+            "Deployment '{}' job removed from queue",
+            job.deployment_name
+        ),
+        job_id: Some(job_id),
+        pipeline_id: Some(job.pipeline_id),
+    }))
+}
+
+/// POST /api/queue/:job_id/promote - Run a waiting job before the others of its priority
+pub async fn promote_job(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<String>,
+) -> Result<Json<WebhookResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let queue = state.deploy_queue.as_ref().ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Deployment queue not available",
+        )
+    })?;
+
+    let job = queue
+        .get_job(&job_id)
+        .await
+        .ok_or_else(|| ErrorResponse::new(StatusCode::NOT_FOUND, "Job not found"))?;
+    if !queue.promote(&job_id).await {
+        return Err(ErrorResponse::new(
+            StatusCode::CONFLICT,
+            &format!("Job is {:?}, only waiting jobs can be promoted", job.status),
+        ));
+    }
+
+    Ok(Json(WebhookResponse {
+        success: true,
+        message: format!(
+            "Deployment '{}' job moved to the front",
+            job.deployment_name
+        ),
+        job_id: Some(job_id),
+        pipeline_id: Some(job.pipeline_id),
+    }))
+}
+
+/// POST /api/queue/clear - Drop every waiting job; running jobs are left alone
+pub async fn clear_queue(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    let queue = state.deploy_queue.as_ref().ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Deployment queue not available",
+        )
+    })?;
+
+    let removed = queue.clear().await;
+    warn!(removed, "Deployment queue cleared");
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "removed": removed,
+    })))
+}
+
+/// POST /webhook/approve/:job_id - Approve a job of a `requires_approval` deployment
+/// The token subject must be listed in the deployment's `approvers`
+pub async fn approve_job(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Path(job_id): Path<String>,
+) -> Result<Json<WebhookResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let queue = state.deploy_queue.as_ref().ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Deployment queue not available",
+        )
+    })?;
+
+    let job = queue
+        .get_job(&job_id)
+        .await
+        .ok_or_else(|| ErrorResponse::new(StatusCode::NOT_FOUND, "Job not found"))?;
+
+    if !job.config.approvers.contains(&claims.sub) {
         warn!(
-            deployment = %deployment_name,
-            error = ?result.error,
-            "Shutdown failed"
+            deployment = %job.deployment_name,
+            job_id = %job_id,
+            subject = %claims.sub,
+            "Approval rejected: subject is not an approver"
         );
-        Err(ErrorResponse::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            &format!("Shutdown failed: {}", result.error.unwrap_or_default()),
-        ))
+        return Err(ErrorResponse::new(
+            StatusCode::FORBIDDEN,
+            &format!(
+                "'{}' is not an approver for deployment '{}'",
+                claims.sub, job.deployment_name
+            ),
+        ));
+    }
+
+    if !queue.approve(&job_id, &claims.sub).await {
+        return Err(ErrorResponse::new(
+            StatusCode::CONFLICT,
+            &format!("Job is {:?}, not awaiting approval", job.status),
+        ));
     }
+
+    info!(
+        deployment = %job.deployment_name,
+        job_id = %job_id,
+        approver = %claims.sub,
+        "Deployment job approved"
+    );
+
+    Ok(Json(WebhookResponse {
+        success: true,
+        message: format!(
+            "Deployment '{}' job approved by '{}'",
+            job.deployment_name, claims.sub
+        ),
+        job_id: Some(job_id),
+        pipeline_id: Some(job.pipeline_id),
+    }))
 }
 
 /// GET /webhook/status/:job_id - Get deployment job status
@@ -251,11 +680,15 @@ pub async fn get_job_status(
         "id": job.id,
         "deployment": job.deployment_name,
         "agent": job.agent_name,
+        "kind": job.kind,
         "status": format!("{:?}", job.status),
         "created_at": format_rfc3339(job.created_at),
         "started_at": job.started_at.map(format_rfc3339),
         "completed_at": job.completed_at.map(format_rfc3339),
         "trigger_source": job.trigger_source,
+        "attempt": job.attempt,
+        "not_before": job.not_before.map(format_rfc3339),
+        "approved_by": job.approved_by,
     })))
 }
 
@@ -278,12 +711,15 @@ pub async fn get_queue_status(
         "jobs": jobs.iter().map(|j| serde_json::json!({
             "id": j.id,
             "deployment": j.deployment_name,
+            "kind": j.kind,
             "status": format!("{:?}", j.status),
+            "priority": j.priority,
             "created_at": format_rfc3339(j.created_at),
         })).collect::<Vec<_>>(),
         "history": history.iter().map(|j| serde_json::json!({
             "id": j.id,
             "deployment": j.deployment_name,
+            "kind": j.kind,
             "status": format!("{:?}", j.status),
             "created_at": format_rfc3339(j.created_at),
             "completed_at": j.completed_at.map(format_rfc3339),
@@ -327,6 +763,34 @@ pub async fn get_pipeline_status(
 }
 
 /// Fetch deployment config from Home server
+/// `X-Deploy-Force: true` counts only from localhost (the CLI's `--force`)
+fn force_requested(addr: &SocketAddr, headers: &HeaderMap) -> bool {
+    addr.ip().is_loopback()
+        && headers.get("X-Deploy-Force").and_then(|v| v.to_str().ok()) == Some("true")
+}
+
+/// Same category rules as deploy: system never, protected only with --force
+/// from localhost. Returns whether force was requested.
+fn check_category(
+    addr: &SocketAddr,
+    headers: &HeaderMap,
+    deployment_name: &str,
+    category: &DeployCategory,
+    action: &str,
+) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+    let force = force_requested(addr, headers);
+    if *category == DeployCategory::System || (*category == DeployCategory::Protected && !force) {
+        return Err(ErrorResponse::new(
+            StatusCode::FORBIDDEN,
+            &format!(
+                "Deployment '{}' is a {:?} deployment and cannot be {}",
+                deployment_name, category, action
+            ),
+        ));
+    }
+    Ok(force)
+}
+
 async fn fetch_from_home(state: &AppState, name: &str) -> Result<DeploymentConfig, String> {
     let home_addr = state
         .config
@@ -335,12 +799,14 @@ async fn fetch_from_home(state: &AppState, name: &str) -> Result<DeploymentConfi
         .as_ref()
         .ok_or_else(|| "not found locally and home_address not configured".to_string())?;
 
-    let jwt = JwtManager::new(&state.config.auth.jwt_secret);
-    let token = jwt
-        .generate_token("agent", 1)
+    // Signed as this agent, so Home also accepts it with `auth.agent_keys`
+    let token = state
+        .jwt_keys
+        .signer()
+        .generate_token(&push::agent_name(&state.config), 1, &[Audience::Agent])
         .map_err(|e| format!("failed to generate token: {}", e))?;
 
-    let url = format!("http://{}/api/deployments/{}", home_addr, name);
+    let url = agent_url(home_addr, &format!("/api/deployments/{}", name));
 
     info!(
         deployment = %name,
@@ -348,11 +814,15 @@ async fn fetch_from_home(state: &AppState, name: &str) -> Result<DeploymentConfi
         "Fetching deployment config from Home"
     );
 
-    let resp = reqwest::Client::new()
+    let request = state
+        .http
+        .client()
         .get(&url)
         .header("Authorization", format!("Bearer {}", token))
-        .timeout(Duration::from_secs(10))
-        .send()
+        .timeout(Duration::from_secs(10));
+    let resp = state
+        .http
+        .send(request)
         .await
         .map_err(|e| format!("failed to reach Home at {}: {}", home_addr, e))?;
 
@@ -369,32 +839,72 @@ async fn fetch_from_home(state: &AppState, name: &str) -> Result<DeploymentConfi
         .map_err(|e| format!("failed to parse config from Home: {}", e))
 }
 
-/// Verify webhook signature (GitHub-style HMAC-SHA256)
-fn verify_signature(headers: &HeaderMap, body: &[u8], secret: &str) -> Result<(), String> {
-    // Try GitHub signature first
-    if let Some(sig) = headers.get("x-hub-signature-256") {
-        let sig_str = sig.to_str().map_err(|_| "Invalid signature header")?;
-        let expected = sig_str
-            .strip_prefix("sha256=")
-            .ok_or("Invalid signature format")?;
+/// Verify a webhook request against the endpoint secret. Supported schemes:
+/// - `X-Webhook-Signature: sha256=HMAC(secret, "{timestamp}.{body}")` with `X-Webhook-Timestamp`
+/// - `X-Hub-Signature-256: sha256=HMAC(secret, body)` (GitHub)
+/// - `X-Gitlab-Token: secret` (GitLab)
+///
+/// A present `X-Webhook-Timestamp` (unix seconds) must be within `timestamp_tolerance` of `now`;
+/// with `require_timestamp` only the timestamped scheme is accepted.
+///
+/// Returns the replay key of the request: the verified signature, or for a
+/// GitLab token (which signs nothing) the SHA-256 of the body.
+fn verify_signature(
+    headers: &HeaderMap,
+    body: &[u8],
+    secret: &str,
+    policy: &WebhooksConfig,
+    now: i64,
+) -> Result<String, String> {
+    let timestamp = match headers.get("x-webhook-timestamp") {
+        Some(ts) => {
+            let ts: i64 = ts
+                .to_str()
+                .ok()
+                .and_then(|s| s.trim().parse().ok())
+                .ok_or("Invalid timestamp header")?;
+            let tolerance = humantime::parse_duration(&policy.timestamp_tolerance)
+                .unwrap_or(Duration::from_secs(300))
+                .as_secs() as i64;
+            if (now - ts).abs() > tolerance {
+                return Err("Webhook timestamp outside allowed tolerance".to_string());
+            }
+            Some(ts)
+        }
+        None if policy.require_timestamp => {
+            return Err("Missing X-Webhook-Timestamp header".to_string());
+        }
+        None => None,
+    };
 
+    // Timestamped signature: the timestamp is covered by the HMAC
+    if let Some(sig) = headers.get("x-webhook-signature") {
+        let ts = timestamp.ok_or("X-Webhook-Signature requires X-Webhook-Timestamp")?;
         let mut mac =
             HmacSha256::new_from_slice(secret.as_bytes()).map_err(|_| "Invalid secret key")?;
+        mac.update(ts.to_string().as_bytes());
+        mac.update(b".");
         mac.update(body);
-        let computed = hex::encode(mac.finalize().into_bytes());
+        return verify_hmac_header(sig, mac);
+    }
 
-        if computed == expected {
-            return Ok(());
-        } else {
-            return Err("Signature mismatch".to_string());
-        }
+    if policy.require_timestamp {
+        return Err("Timestamped signature required (X-Webhook-Signature)".to_string());
+    }
+
+    // Try GitHub signature
+    if let Some(sig) = headers.get("x-hub-signature-256") {
+        let mut mac =
+            HmacSha256::new_from_slice(secret.as_bytes()).map_err(|_| "Invalid secret key")?;
+        mac.update(body);
+        return verify_hmac_header(sig, mac);
     }
 
     // Try GitLab token
     if let Some(token) = headers.get("x-gitlab-token") {
         let token_str = token.to_str().map_err(|_| "Invalid token header")?;
-        if token_str == secret {
-            return Ok(());
+        if constant_time_eq(token_str.as_bytes(), secret.as_bytes()) {
+            return Ok(hex::encode(Sha256::digest(body)));
         } else {
             return Err("Token mismatch".to_string());
         }
@@ -403,6 +913,117 @@ fn verify_signature(headers: &HeaderMap, body: &[u8], secret: &str) -> Result<()
     Err("No signature or token provided".to_string())
 }
 
+/// Check a `sha256=<hex>` signature header against a prepared MAC in constant
+/// time; returns the signature (lowercase hex)
+fn verify_hmac_header(header: &axum::http::HeaderValue, mac: HmacSha256) -> Result<String, String> {
+    let sig_str = header.to_str().map_err(|_| "Invalid signature header")?;
+    let expected = sig_str
+        .strip_prefix("sha256=")
+        .ok_or("Invalid signature format")?;
+    let expected = hex::decode(expected).map_err(|_| "Invalid signature format")?;
+
+    mac.verify_slice(&expected)
+        .map_err(|_| "Signature mismatch".to_string())?;
+    Ok(hex::encode(expected))
+}
+
+/// Why a push payload does not pass the endpoint's `branch_filter`/`path_filter`.
+/// Bodies that are not GitHub/GitLab push payloads (no `ref`) are not filtered.
+fn push_filter_mismatch(endpoint: &WebhookEndpoint, body: &[u8]) -> Option<String> {
+    if endpoint.branch_filter.is_empty() && endpoint.path_filter.is_empty() {
+        return None;
+    }
+    let payload: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let git_ref = payload["ref"].as_str()?;
+
+    if !endpoint.branch_filter.is_empty() {
+        let Some(branch) = git_ref.strip_prefix("refs/heads/") else {
+            return Some(format!("'{}' is not a branch", git_ref));
+        };
+        if !glob_any(&endpoint.branch_filter, branch, false) {
+            return Some(format!("branch '{}' does not match branch_filter", branch));
+        }
+    }
+
+    // Both GitHub and GitLab list added/modified/removed files per commit
+    if !endpoint.path_filter.is_empty() {
+        let commits = payload["commits"].as_array()?;
+        let changed = commits
+            .iter()
+            .flat_map(|commit| ["added", "modified", "removed"].map(|key| &commit[key]))
+            .filter_map(|files| files.as_array())
+            .flatten()
+            .filter_map(|file| file.as_str());
+        if !changed
+            .clone()
+            .any(|file| glob_any(&endpoint.path_filter, file, true))
+        {
+            return Some(format!(
+                "no changed file matches path_filter ({} checked)",
+                changed.count()
+            ));
+        }
+    }
+
+    None
+}
+
+/// Start of the next allowed hour in the constraint's time zone, or `now` inside one
+fn next_allowed_time(
+    constraint: &ScheduleConstraint,
+    now: OffsetDateTime,
+) -> Result<OffsetDateTime, String> {
+    let tz = tz::TimeZone::from_posix_tz(&constraint.timezone)
+        .map_err(|e| format!("Unknown timezone '{}': {}", constraint.timezone, e))?;
+    let allowed = |unix: i64| -> Result<bool, String> {
+        let offset = tz
+            .find_local_time_type(unix)
+            .map_err(|e| e.to_string())?
+            .ut_offset() as i64;
+        let hour = ((unix + offset).rem_euclid(86_400) / 3600) as u8;
+        Ok(constraint.allowed_hours.contains(&hour))
+    };
+
+    if allowed(now.unix_timestamp())? {
+        return Ok(now);
+    }
+
+    // UTC offsets are whole quarter hours, so every local hour starts on one
+    const STEP: i64 = 15 * 60;
+    let first = now.unix_timestamp().div_euclid(STEP) * STEP + STEP;
+    for unix in (0..2 * 24 * 4).map(|i| first + i * STEP) {
+        if allowed(unix)? {
+            return OffsetDateTime::from_unix_timestamp(unix).map_err(|e| e.to_string());
+        }
+    }
+    Err("schedule_constraint has no allowed hours".to_string())
+}
+
+/// Whether any glob matches; with `literal_separator`, `*` stops at `/` and `**` crosses it
+fn glob_any(patterns: &[String], value: &str, literal_separator: bool) -> bool {
+    let options = glob::MatchOptions {
+        require_literal_separator: literal_separator,
+        ..Default::default()
+    };
+    patterns
+        .iter()
+        .any(|pattern| glob::Pattern::new(pattern).is_ok_and(|p| p.matches_with(value, options)))
+}
+
+/// `ref` override from a manual trigger body; empty or non-JSON bodies carry none
+fn requested_git_ref(body: &[u8]) -> Result<Option<String>, String> {
+    let Ok(request) = serde_json::from_slice::<TriggerRequest>(body) else {
+        return Ok(None);
+    };
+    match request.git_ref {
+        Some(git_ref) => {
+            crate::deploy::validate_git_ref(&git_ref)?;
+            Ok(Some(git_ref))
+        }
+        None => Ok(None),
+    }
+}
+
 /// Detect the source of the webhook trigger
 fn detect_trigger_source(headers: &HeaderMap, _body: &[u8]) -> Option<String> {
     // Check for GitHub
@@ -423,3 +1044,233 @@ fn detect_trigger_source(headers: &HeaderMap, _body: &[u8]) -> Option<String> {
     // Manual trigger
     Some("manual".to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "webhook-secret";
+
+    fn sign(payload: &[u8]) -> String {
+        let mut mac = HmacSha256::new_from_slice(SECRET.as_bytes()).unwrap();
+        mac.update(payload);
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    fn headers(pairs: &[(&'static str, String)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, value.parse().unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn test_github_signature() {
+        let policy = WebhooksConfig {
+            require_timestamp: false,
+            ..WebhooksConfig::default()
+        };
+        let body = br#"{"ref":"refs/heads/main"}"#;
+
+        let ok = headers(&[("x-hub-signature-256", sign(body))]);
+        let key = verify_signature(&ok, body, SECRET, &policy, 0).unwrap();
+        assert_eq!(format!("sha256={}", key), sign(body));
+        // Another delivery id does not change the replay key
+        let redelivered = headers(&[
+            ("x-hub-signature-256", sign(body)),
+            ("x-github-delivery", "another-id".to_string()),
+        ]);
+        assert_eq!(
+            verify_signature(&redelivered, body, SECRET, &policy, 0).unwrap(),
+            key
+        );
+
+        let bad = headers(&[("x-hub-signature-256", sign(b"tampered"))]);
+        assert_eq!(
+            verify_signature(&bad, body, SECRET, &policy, 0).unwrap_err(),
+            "Signature mismatch"
+        );
+
+        // Timestamps are required by default
+        assert!(verify_signature(&ok, body, SECRET, &WebhooksConfig::default(), 0).is_err());
+    }
+
+    #[test]
+    fn test_timestamped_signature() {
+        let policy = WebhooksConfig {
+            require_timestamp: true,
+            ..WebhooksConfig::default()
+        };
+        let body = b"{}";
+        let now = 1_700_000_000;
+
+        let signed = headers(&[
+            ("x-webhook-timestamp", now.to_string()),
+            (
+                "x-webhook-signature",
+                sign(format!("{}.{{}}", now).as_bytes()),
+            ),
+        ]);
+        assert!(verify_signature(&signed, body, SECRET, &policy, now + 10).is_ok());
+
+        // Stale request
+        let err = verify_signature(&signed, body, SECRET, &policy, now + 3600).unwrap_err();
+        assert!(err.contains("tolerance"));
+
+        // Timestamp changed without re-signing
+        let forged = headers(&[
+            ("x-webhook-timestamp", (now + 3600).to_string()),
+            (
+                "x-webhook-signature",
+                sign(format!("{}.{{}}", now).as_bytes()),
+            ),
+        ]);
+        assert!(verify_signature(&forged, body, SECRET, &policy, now + 3600).is_err());
+    }
+
+    #[test]
+    fn test_push_filters() {
+        let endpoint = WebhookEndpoint {
+            path: "/hooks/api".to_string(),
+            deployment: Some("api".to_string()),
+            event: None,
+            secret: None,
+            allowed_ips: Vec::new(),
+            schedule_constraint: None,
+            branch_filter: vec!["main".to_string(), "release/*".to_string()],
+            path_filter: vec!["services/api/**".to_string(), "*.lock".to_string()],
+        };
+        let push = |git_ref: &str, files: &[&str]| {
+            serde_json::json!({
+                "ref": git_ref,
+                "commits": [{ "added": [], "modified": files, "removed": [] }],
+            })
+            .to_string()
+        };
+
+        let body = push("refs/heads/release/1.4", &["services/api/src/main.rs"]);
+        assert_eq!(push_filter_mismatch(&endpoint, body.as_bytes()), None);
+        let body = push("refs/heads/main", &["docs/README.md", "Cargo.lock"]);
+        assert_eq!(push_filter_mismatch(&endpoint, body.as_bytes()), None);
+
+        let body = push("refs/heads/feature/x", &["services/api/src/main.rs"]);
+        assert!(push_filter_mismatch(&endpoint, body.as_bytes())
+            .unwrap()
+            .contains("branch 'feature/x'"));
+        let body = push("refs/tags/v1.4.0", &["services/api/src/main.rs"]);
+        assert!(push_filter_mismatch(&endpoint, body.as_bytes()).is_some());
+        let body = push(
+            "refs/heads/main",
+            &["services/web/app.js", "docs/Cargo.lock"],
+        );
+        assert!(push_filter_mismatch(&endpoint, body.as_bytes())
+            .unwrap()
+            .contains("path_filter"));
+
+        // Manual triggers carry no push payload
+        assert_eq!(push_filter_mismatch(&endpoint, b""), None);
+    }
+
+    #[test]
+    fn test_next_allowed_time() {
+        let constraint = |hours: &[u8], timezone: &str| ScheduleConstraint {
+            allowed_hours: hours.to_vec(),
+            timezone: timezone.to_string(),
+            outside_hours: OutsideHours::Defer,
+        };
+        let at = |s: &str| OffsetDateTime::parse(s, &Rfc3339).unwrap();
+
+        let office = constraint(&[9, 10, 11, 12, 13, 14, 15, 16], "UTC");
+        let now = at("2026-10-16T11:20:00Z");
+        assert_eq!(next_allowed_time(&office, now).unwrap(), now);
+        assert_eq!(
+            next_allowed_time(&office, at("2026-10-16T20:30:00Z")).unwrap(),
+            at("2026-10-17T09:00:00Z")
+        );
+
+        // 09:00 at UTC+5:30 is 03:30 UTC
+        let india = constraint(&[9], "IST-5:30");
+        assert_eq!(
+            next_allowed_time(&india, at("2026-10-16T00:00:00Z")).unwrap(),
+            at("2026-10-16T03:30:00Z")
+        );
+
+        assert!(next_allowed_time(&constraint(&[], "UTC"), now).is_err());
+    }
+
+    #[test]
+    fn test_gitlab_token_replay_key() {
+        let policy = WebhooksConfig {
+            require_timestamp: false,
+            ..WebhooksConfig::default()
+        };
+        let ok = headers(&[
+            ("x-gitlab-token", SECRET.to_string()),
+            ("x-gitlab-event-uuid", "abc-123".to_string()),
+        ]);
+        let key = verify_signature(&ok, b"{}", SECRET, &policy, 0).unwrap();
+        let other_uuid = headers(&[
+            ("x-gitlab-token", SECRET.to_string()),
+            ("x-gitlab-event-uuid", "def-456".to_string()),
+        ]);
+        assert_eq!(
+            verify_signature(&other_uuid, b"{}", SECRET, &policy, 0).unwrap(),
+            key
+        );
+        assert_ne!(
+            verify_signature(&ok, b"{\"a\":1}", SECRET, &policy, 0).unwrap(),
+            key
+        );
+
+        let bad = headers(&[("x-gitlab-token", "wrong".to_string())]);
+        assert!(verify_signature(&bad, b"", SECRET, &policy, 0).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_jobs_respect_category() {
+        let config: crate::config::Config = serde_yaml::from_str(concat!(
+            "mode: agent\nserver: {}\n",
+            "auth:\n  jwt_secret: \"0123456789abcdef0123456789abcdef\"\n",
+            "modules:\n  deploy:\n    enabled: true\n    deployments:\n",
+            "      - {name: infra, type: custom_script, script: make, category: system}\n",
+            "      - {name: db, type: custom_script, script: make, category: protected}\n",
+            "      - {name: app, type: custom_script, script: make}\n",
+        ))
+        .unwrap();
+        let state = AppState::new(config);
+        let remote: SocketAddr = "10.0.0.9:40000".parse().unwrap();
+        let local: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let force = headers(&[("X-Deploy-Force", "true".to_string())]);
+        let status = |result: Result<Json<WebhookResponse>, (StatusCode, Json<ErrorResponse>)>| {
+            result.err().map(|(status, _)| status)
+        };
+
+        for kind in [JobKind::Shutdown, JobKind::Start, JobKind::Restart] {
+            let infra = queue_lifecycle(&state, &local, &force, kind, "infra".into()).await;
+            assert_eq!(status(infra), Some(StatusCode::FORBIDDEN));
+            let db = queue_lifecycle(&state, &remote, &force, kind, "db".into()).await;
+            assert_eq!(status(db), Some(StatusCode::FORBIDDEN));
+            let db = queue_lifecycle(&state, &local, &HeaderMap::new(), kind, "db".into()).await;
+            assert_eq!(status(db), Some(StatusCode::FORBIDDEN));
+
+            let db = queue_lifecycle(&state, &local, &force, kind, "db".into()).await;
+            assert_eq!(status(db), None);
+            let app = queue_lifecycle(&state, &remote, &HeaderMap::new(), kind, "app".into()).await;
+            assert_eq!(status(app), None);
+        }
+    }
+
+    #[test]
+    fn test_requested_git_ref() {
+        assert_eq!(requested_git_ref(b"").unwrap(), None);
+        assert_eq!(requested_git_ref(b"{}").unwrap(), None);
+        assert_eq!(
+            requested_git_ref(br#"{"ref": "v1.4.2"}"#)
+                .unwrap()
+                .as_deref(),
+            Some("v1.4.2")
+        );
+        assert!(requested_git_ref(br#"{"ref": "--upload-pack=sh"}"#).is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use crate::deploy::JobEvent;
+use crate::server::AppState;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, warn};
+
+/// How often agent statuses are checked for changes
+const AGENT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// GET /ws - Live dashboard updates
+///
+/// Sends a `snapshot` (queue + agent statuses) on connect, then `job` events
+/// for every queue status transition and `agents` whenever agent statuses
+/// change. Browsers cannot set headers on WebSocket requests, so the token
+/// may also be passed as `?token=`.
+pub async fn live_updates(ws: WebSocketUpgrade, State(state): State<Arc<AppState>>) -> Response {
+    ws.on_upgrade(move |socket| stream_updates(socket, state))
+}
+
+async fn stream_updates(mut socket: WebSocket, state: Arc<AppState>) {
+    let mut jobs = state.deploy_queue.as_ref().map(|q| q.subscribe());
+    let mut shutdown = state.shutdown.subscribe();
+    let mut ticker = tokio::time::interval(AGENT_POLL_INTERVAL);
+    ticker.tick().await; // first tick completes immediately
+
+    let mut agents = agent_statuses(&state);
+    if send(&mut socket, snapshot(&state, &agents).await)
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    loop {
+        let message = tokio::select! {
+            event = next_job_event(&mut jobs) => match event {
+                Ok(event) => serde_json::json!({ "type": "job", "job": event }),
+                // Missed events: resend the full state
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(skipped, "Live update subscriber lagged, resending snapshot");
+                    snapshot(&state, &agents).await
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = ticker.tick() => {
+                let latest = agent_statuses(&state);
+                if latest == agents {
+                    continue;
+                }
+                agents = latest;
+                serde_json::json!({ "type": "agents", "agents": agents })
+            }
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            _ = async { shutdown.wait_for(|stopping| *stopping).await.is_ok() } => break,
+        };
+
+        if send(&mut socket, message).await.is_err() {
+            break;
+        }
+    }
+
+    debug!("Live update connection closed");
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+async fn send(socket: &mut WebSocket, message: serde_json::Value) -> Result<(), axum::Error> {
+    socket.send(Message::Text(message.to_string())).await
+}
+
+/// Wait for the next queue event (forever if the deploy module is disabled)
+async fn next_job_event(
+    jobs: &mut Option<broadcast::Receiver<JobEvent>>,
+) -> Result<JobEvent, RecvError> {
+    match jobs {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+fn agent_statuses(state: &AppState) -> serde_json::Value {
+    let statuses = state
+        .db
+        .as_ref()
+        .and_then(|db| db.get_all_agent_statuses().ok())
+        .unwrap_or_default();
+    serde_json::json!(statuses)
+}
+
+async fn snapshot(state: &AppState, agents: &serde_json::Value) -> serde_json::Value {
+    let jobs = match state.deploy_queue {
+        Some(ref queue) => queue
+            .get_queue_status()
+            .await
+            .iter()
+            .map(JobEvent::from)
+            .collect(),
+        None => Vec::new(),
+    };
+
+    serde_json::json!({
+        "type": "snapshot",
+        "jobs": jobs,
+        "agents": agents,
+    })
+}
@@ -0,0 +1,190 @@
+use crate::server::auth::{Audience, Claims, JwtError, JwtKeys};
+use axum::http::{header, HeaderMap};
+
+/// Cookie holding the dashboard session: a JWT with the `dashboard` audience
+pub const SESSION_COOKIE: &str = "infractl_session";
+
+/// Cookie binding an SSO login to the browser that started it
+pub const LOGIN_STATE_COOKIE: &str = "infractl_oidc_state";
+
+/// Where to land after login when no (or no local) page was requested
+const DEFAULT_NEXT: &str = "/monitoring";
+
+/// `Set-Cookie` value for a new session of `subject`, signed with the current key
+pub fn issue(
+    keys: &JwtKeys,
+    subject: &str,
+    ttl: std::time::Duration,
+    secure: bool,
+) -> Result<String, JwtError> {
+    let claims = Claims::with_ttl(
+        subject,
+        time::Duration::seconds(ttl.as_secs() as i64),
+        &[Audience::Dashboard],
+    );
+    let token = keys.signer().encode_claims(&claims)?;
+    Ok(format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax{}",
+        SESSION_COOKIE,
+        token,
+        ttl.as_secs(),
+        if secure { "; Secure" } else { "" }
+    ))
+}
+
+/// Lifetime of the API token handed to the dashboard (`GET /monitoring/token`)
+pub const DASHBOARD_TOKEN_TTL_HOURS: i64 = 1;
+
+/// Read-only API token for the dashboard of `subject`
+pub fn dashboard_token(keys: &JwtKeys, subject: &str) -> Result<String, JwtError> {
+    keys.signer()
+        .generate_token(subject, DASHBOARD_TOKEN_TTL_HOURS, &[Audience::Dashboard])
+}
+
+/// `Set-Cookie` value that ends the session
+pub fn clear(secure: bool) -> String {
+    format!(
+        "{}=; Path=/; Max-Age=0; HttpOnly; SameSite=Lax{}",
+        SESSION_COOKIE,
+        if secure { "; Secure" } else { "" }
+    )
+}
+
+/// `Set-Cookie` value saving the `state` of a started SSO login in the
+/// browser, until the callback or `max_age`
+pub fn login_state(state: &str, max_age: std::time::Duration, secure: bool) -> String {
+    format!(
+        "{}={}; Path=/auth/oidc; Max-Age={}; HttpOnly; SameSite=Lax{}",
+        LOGIN_STATE_COOKIE,
+        state,
+        max_age.as_secs(),
+        if secure { "; Secure" } else { "" }
+    )
+}
+
+/// `Set-Cookie` value that drops the saved SSO login state
+pub fn clear_login_state(secure: bool) -> String {
+    format!(
+        "{}=; Path=/auth/oidc; Max-Age=0; HttpOnly; SameSite=Lax{}",
+        LOGIN_STATE_COOKIE,
+        if secure { "; Secure" } else { "" }
+    )
+}
+
+/// SSO login state saved in the request's cookie
+pub fn saved_login_state(headers: &HeaderMap) -> Option<&str> {
+    cookie(headers, LOGIN_STATE_COOKIE)
+}
+
+/// Subject of a valid session cookie on the request; cookies signed with the
+/// previous key stay valid during a rotation overlap
+pub fn subject(headers: &HeaderMap, keys: &JwtKeys) -> Option<String> {
+    let token = cookie(headers, SESSION_COOKIE)?;
+    keys.validate(token)
+        .ok()
+        .filter(|claims| claims.has_audience(&[Audience::Dashboard]))
+        .map(|claims| claims.sub)
+}
+
+/// Only same-site dashboard pages are valid post-login targets
+pub fn local_path(next: Option<&str>) -> &str {
+    match next {
+        Some(path) if path.starts_with(DEFAULT_NEXT) && !path.contains("//") => path,
+        _ => DEFAULT_NEXT,
+    }
+}
+
+/// Login page URL that returns to `next` afterwards
+pub fn login_path(next: &str, failed: bool) -> String {
+    let mut params = vec![("next", next)];
+    if failed {
+        params.insert(0, ("failed", "1"));
+    }
+    reqwest::Url::parse_with_params("http://localhost/auth/login", &params)
+        .map(|url| format!("{}?{}", url.path(), url.query().unwrap_or_default()))
+        .unwrap_or_else(|_| "/auth/login".to_string())
+}
+
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|pair| {
+            let (key, value) = pair.trim().split_once('=')?;
+            (key == name).then_some(value)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    const SECRET: &str = "test_secret_that_is_long_enough_for_hs256";
+
+    #[test]
+    fn test_session_cookie_roundtrip() {
+        let keys = JwtKeys::new(SECRET);
+        let set_cookie = issue(
+            &keys,
+            "alice@example.com",
+            std::time::Duration::from_secs(60),
+            true,
+        )
+        .unwrap();
+        assert!(set_cookie.ends_with("; HttpOnly; SameSite=Lax; Secure"));
+
+        let pair = set_cookie.split(';').next().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_str(&format!("theme=dark; {}", pair)).unwrap(),
+        );
+        assert_eq!(
+            subject(&headers, &keys).as_deref(),
+            Some("alice@example.com")
+        );
+        assert_eq!(
+            subject(
+                &headers,
+                &JwtKeys::new("another_secret_that_is_long_enough")
+            ),
+            None
+        );
+
+        // Sessions survive a key rotation while the old key is accepted
+        keys.rotate(
+            "rotated_secret_that_is_long_enough_for_hs256",
+            std::time::Duration::from_secs(60),
+        );
+        assert_eq!(
+            subject(&headers, &keys).as_deref(),
+            Some("alice@example.com")
+        );
+        keys.rotate(
+            "another_rotated_secret_long_enough_for_hs256",
+            std::time::Duration::ZERO,
+        );
+        assert_eq!(subject(&headers, &keys), None);
+    }
+
+    #[test]
+    fn test_next_stays_on_dashboard() {
+        assert_eq!(
+            local_path(Some("/monitoring/deploys")),
+            "/monitoring/deploys"
+        );
+        assert_eq!(local_path(Some("https://evil.example.com")), "/monitoring");
+        assert_eq!(
+            local_path(Some("/monitoring//evil.example.com")),
+            "/monitoring"
+        );
+        assert_eq!(local_path(None), "/monitoring");
+        assert_eq!(
+            login_path("/monitoring/a b", true),
+            "/auth/login?failed=1&next=%2Fmonitoring%2Fa+b"
+        );
+    }
+}
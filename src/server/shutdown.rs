@@ -19,17 +19,6 @@ pub async fn signal() {
     #[cfg(not(unix))]
     let terminate = std::future::pending::<()>();
 
-    #[cfg(unix)]
-    let hangup = async {
-        signal::unix::signal(signal::unix::SignalKind::hangup())
-            .expect("Failed to install SIGHUP handler")
-            .recv()
-            .await;
-    };
-
-    #[cfg(not(unix))]
-    let hangup = std::future::pending::<()>();
-
     tokio::select! {
         _ = ctrl_c => {
             info!("Received Ctrl+C, initiating shutdown");
@@ -37,9 +26,5 @@ pub async fn signal() {
         _ = terminate => {
             info!("Received SIGTERM, initiating shutdown");
         }
-        _ = hangup => {
-            info!("Received SIGHUP, config reload requested");
-            // TODO: Implement hot-reload
-        }
     }
 }
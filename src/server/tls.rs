@@ -0,0 +1,64 @@
+use crate::config::TlsConfig;
+use anyhow::{Context, Result};
+use axum_server::tls_rustls::RustlsConfig;
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// Load the certificate chain and key from `server.tls`
+pub async fn load(config: &TlsConfig) -> Result<RustlsConfig> {
+    // Fails only when a provider is already installed, which is fine
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    RustlsConfig::from_pem_file(&config.cert_path, &config.key_path)
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to load TLS certificate '{}' / key '{}'",
+                config.cert_path, config.key_path
+            )
+        })
+}
+
+/// Reload the certificate when either file changes; new connections pick it up,
+/// open ones keep the old one. A bad pair is logged and the current one kept.
+pub async fn watch_certificate(
+    rustls: RustlsConfig,
+    config: TlsConfig,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let interval =
+        humantime::parse_duration(&config.reload_interval).unwrap_or(Duration::from_secs(60));
+    let mut last = modified(&config);
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.wait_for(|stopping| *stopping) => return,
+        }
+
+        let current = modified(&config);
+        if current == last {
+            continue;
+        }
+        last = current;
+
+        match rustls
+            .reload_from_pem_file(&config.cert_path, &config.key_path)
+            .await
+        {
+            Ok(()) => info!(cert = %config.cert_path, "TLS certificate reloaded"),
+            Err(e) => warn!(
+                cert = %config.cert_path,
+                error = %e,
+                "Failed to reload TLS certificate, keeping the current one"
+            ),
+        }
+    }
+}
+
+/// Modification times of the certificate and key files
+fn modified(config: &TlsConfig) -> (Option<SystemTime>, Option<SystemTime>) {
+    let mtime = |path: &str| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    (mtime(&config.cert_path), mtime(&config.key_path))
+}
@@ -0,0 +1,354 @@
+use crate::server::agents::{agent_url, AgentReply, AgentRequest};
+use crate::server::auth::Audience;
+use crate::server::{push, AppState};
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{Request, StatusCode},
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, Notify};
+use tower::ServiceExt;
+use tracing::{debug, info, warn};
+
+/// How long Home holds a poll open when nothing is queued
+pub const POLL_WAIT: Duration = Duration::from_secs(25);
+/// An agent that has not polled for this long is reached directly again
+const CONNECTED_GRACE: Duration = Duration::from_secs(60);
+/// Wait before polling again after a failed poll
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Largest response body the agent sends back
+const MAX_REPLY_BYTES: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelRequest {
+    pub id: u64,
+    #[serde(flatten)]
+    pub request: AgentRequest,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelReply {
+    pub id: u64,
+    #[serde(flatten)]
+    pub reply: AgentReply,
+}
+
+#[derive(Default)]
+struct Mailbox {
+    queue: Vec<TunnelRequest>,
+    waiting: HashMap<u64, oneshot::Sender<AgentReply>>,
+    notify: Arc<Notify>,
+    last_poll: Option<Instant>,
+}
+
+/// Requests waiting for agents on reverse connections (Home mode)
+///
+/// Agents with `server.reverse_connect` long-poll `POST /api/tunnel/poll`
+/// and answer each request on `POST /api/tunnel/reply`.
+#[derive(Default)]
+pub struct Tunnels {
+    mailboxes: Mutex<HashMap<String, Mailbox>>,
+    next_id: AtomicU64,
+}
+
+impl Tunnels {
+    /// Whether the agent polled recently enough to be reached over its tunnel
+    pub fn is_connected(&self, agent: &str) -> bool {
+        self.mailboxes
+            .lock()
+            .unwrap()
+            .get(agent)
+            .and_then(|mailbox| mailbox.last_poll)
+            .is_some_and(|last| last.elapsed() < CONNECTED_GRACE)
+    }
+
+    /// Queue a request for the agent's next poll and wait for its reply
+    pub async fn send(
+        &self,
+        agent: &str,
+        request: AgentRequest,
+        timeout: Duration,
+    ) -> Result<AgentReply, String> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut mailboxes = self.mailboxes.lock().unwrap();
+            let mailbox = mailboxes.entry(agent.to_string()).or_default();
+            mailbox.queue.push(TunnelRequest { id, request });
+            mailbox.waiting.insert(id, tx);
+            mailbox.notify.notify_one();
+        }
+
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(reply)) => Ok(reply),
+            _ => {
+                // Never deliver it late: a deploy must not start after the caller gave up
+                if let Some(mailbox) = self.mailboxes.lock().unwrap().get_mut(agent) {
+                    mailbox.queue.retain(|r| r.id != id);
+                    mailbox.waiting.remove(&id);
+                }
+                Err("No reply over the reverse connection".to_string())
+            }
+        }
+    }
+
+    /// Requests queued for the agent, waiting up to [`POLL_WAIT`] for one
+    pub async fn poll(&self, agent: &str) -> Vec<TunnelRequest> {
+        let notify = {
+            let mut mailboxes = self.mailboxes.lock().unwrap();
+            let mailbox = mailboxes.entry(agent.to_string()).or_default();
+            mailbox.last_poll = Some(Instant::now());
+            if !mailbox.queue.is_empty() {
+                return std::mem::take(&mut mailbox.queue);
+            }
+            mailbox.notify.clone()
+        };
+
+        let _ = tokio::time::timeout(POLL_WAIT, notify.notified()).await;
+        let mut mailboxes = self.mailboxes.lock().unwrap();
+        let mailbox = mailboxes.entry(agent.to_string()).or_default();
+        mailbox.last_poll = Some(Instant::now());
+        std::mem::take(&mut mailbox.queue)
+    }
+
+    /// Hand a reply to the request waiting for it; `false` if nobody is
+    pub fn reply(&self, agent: &str, reply: TunnelReply) -> bool {
+        let waiting = self
+            .mailboxes
+            .lock()
+            .unwrap()
+            .get_mut(agent)
+            .and_then(|mailbox| mailbox.waiting.remove(&reply.id));
+        waiting.is_some_and(|tx| tx.send(reply.reply).is_ok())
+    }
+}
+
+/// Poll Home for requests and run them against this agent's own routes
+/// until shutdown starts (agent mode, `server.reverse_connect`)
+pub async fn connect(state: Arc<AppState>, router: Router, home_address: String) {
+    let agent_name = push::agent_name(&state.config);
+    let poll_url = agent_url(&home_address, "/api/tunnel/poll");
+    let reply_url = agent_url(&home_address, "/api/tunnel/reply");
+    let mut shutdown = state.shutdown.subscribe();
+    let mut failing = false;
+
+    info!(home = %home_address, agent = %agent_name, "Reverse connection to Home started");
+    loop {
+        let poll = tokio::select! {
+            poll = poll_once(&state, &poll_url, &agent_name) => poll,
+            _ = shutdown.wait_for(|stopping| *stopping) => break,
+        };
+        match poll {
+            Ok(requests) => {
+                if failing {
+                    info!(home = %home_address, "Reverse connection restored");
+                }
+                failing = false;
+                for request in requests {
+                    tokio::spawn(handle(
+                        state.clone(),
+                        router.clone(),
+                        reply_url.clone(),
+                        agent_name.clone(),
+                        request,
+                    ));
+                }
+                continue;
+            }
+            // Log once per outage, not on every attempt
+            Err(e) if !failing => {
+                warn!(home = %home_address, error = %e, "Reverse connection lost");
+                failing = true;
+            }
+            Err(e) => debug!(error = %e, "Reverse connection poll failed"),
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(RECONNECT_DELAY) => {}
+            _ = shutdown.wait_for(|stopping| *stopping) => break,
+        }
+    }
+}
+
+fn agent_token(state: &AppState, agent_name: &str) -> Result<String, String> {
+    state
+        .jwt_keys
+        .signer()
+        .generate_token(agent_name, 1, &[Audience::Agent])
+        .map_err(|e| format!("Failed to generate token: {}", e))
+}
+
+async fn poll_once(
+    state: &AppState,
+    url: &str,
+    agent_name: &str,
+) -> Result<Vec<TunnelRequest>, String> {
+    let resp = state
+        .http
+        .client()
+        .post(url)
+        .timeout(POLL_WAIT + Duration::from_secs(10))
+        .bearer_auth(agent_token(state, agent_name)?)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to connect: {}", e))?;
+    if !resp.status().is_success() {
+        return Err(format!("Home returned {}", resp.status()));
+    }
+    resp.json()
+        .await
+        .map_err(|e| format!("Invalid poll response: {}", e))
+}
+
+/// Run one request and send the reply back to Home
+async fn handle(
+    state: Arc<AppState>,
+    router: Router,
+    reply_url: String,
+    agent_name: String,
+    request: TunnelRequest,
+) {
+    debug!(method = %request.request.method, path = %request.request.path, "Request from Home");
+    let reply = TunnelReply {
+        id: request.id,
+        reply: dispatch(router, request.request).await,
+    };
+
+    let result = async {
+        let resp = state
+            .http
+            .send(
+                state
+                    .http
+                    .client()
+                    .post(&reply_url)
+                    .bearer_auth(agent_token(&state, &agent_name)?)
+                    .json(&reply),
+            )
+            .await
+            .map_err(|e| format!("Failed to connect: {}", e))?;
+        if !resp.status().is_success() {
+            return Err(format!("Home returned {}", resp.status()));
+        }
+        Ok(())
+    }
+    .await;
+    if let Err(e) = result {
+        warn!(id = reply.id, error = %e, "Failed to send reply to Home");
+    }
+}
+
+/// Run a request through the agent's routes, middleware included, as if it
+/// came in over loopback
+pub async fn dispatch(router: Router, request: AgentRequest) -> AgentReply {
+    let mut builder = Request::builder()
+        .method(request.method.as_str())
+        .uri(&request.path);
+    for (name, value) in &request.headers {
+        builder = builder.header(name, value);
+    }
+    let mut http_request = match builder.body(Body::from(request.body)) {
+        Ok(http_request) => http_request,
+        Err(e) => {
+            return AgentReply {
+                status: StatusCode::BAD_REQUEST.as_u16(),
+                headers: Vec::new(),
+                body: serde_json::json!({ "error": format!("Invalid request: {}", e) }).to_string(),
+            }
+        }
+    };
+    http_request
+        .extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0))));
+
+    let Ok(response) = router.oneshot(http_request).await;
+    let status = response.status().as_u16();
+    let headers = response
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    let body = axum::body::to_bytes(response.into_body(), MAX_REPLY_BYTES)
+        .await
+        .map(|body| String::from_utf8_lossy(&body).into_owned())
+        .unwrap_or_default();
+    AgentReply {
+        status,
+        headers,
+        body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::Method, routing::post, Json};
+
+    #[tokio::test]
+    async fn test_requests_reach_polling_agent() {
+        let tunnels = Arc::new(Tunnels::default());
+        assert!(!tunnels.is_connected("web-1"));
+
+        let agent = tokio::spawn({
+            let tunnels = tunnels.clone();
+            async move {
+                let requests = tunnels.poll("web-1").await;
+                assert_eq!(requests.len(), 1);
+                let router = Router::new().route(
+                    "/webhook/deploy/:name",
+                    post(|| async {
+                        (
+                            StatusCode::ACCEPTED,
+                            Json(serde_json::json!({ "job_id": "42" })),
+                        )
+                    }),
+                );
+                let request = requests.into_iter().next().unwrap();
+                let reply = dispatch(router, request.request).await;
+                assert!(tunnels.reply(
+                    "web-1",
+                    TunnelReply {
+                        id: request.id,
+                        reply
+                    }
+                ));
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(tunnels.is_connected("web-1"));
+
+        let reply = tunnels
+            .send(
+                "web-1",
+                AgentRequest::new(Method::POST, "/webhook/deploy/web-app"),
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+        agent.await.unwrap();
+        assert_eq!(reply.status, 202);
+        assert_eq!(reply.json::<serde_json::Value>().unwrap()["job_id"], "42");
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_request_is_dropped() {
+        let tunnels = Tunnels::default();
+        let result = tunnels
+            .send(
+                "web-1",
+                AgentRequest::new(Method::POST, "/webhook/deploy/web-app"),
+                Duration::from_millis(10),
+            )
+            .await;
+        assert!(result.is_err());
+        // The agent polling later does not run it
+        let late = tokio::time::timeout(Duration::from_secs(1), tunnels.poll("web-1")).await;
+        assert!(late.unwrap_or_default().is_empty());
+    }
+}
@@ -1,30 +1,110 @@
+use super::partitions;
 use super::repository::Database;
 use std::sync::Arc;
+use time::{Duration, OffsetDateTime};
 use tracing::{error, info};
 
+/// Nearest-rank p95/p99 of CPU and memory usage over rows of
+/// [`ranked_samples`], grouped by period
+const PERCENTILES: &str = "
+            MAX(CASE WHEN cpu_rank <= (95 * samples + 99) / 100 THEN cpu_usage END) as cpu_p95,
+            MAX(CASE WHEN cpu_rank <= (99 * samples + 99) / 100 THEN cpu_usage END) as cpu_p99,
+            MAX(CASE WHEN memory_rank <= (95 * samples + 99) / 100
+                THEN memory_usage_percent END) as memory_p95,
+            MAX(CASE WHEN memory_rank <= (99 * samples + 99) / 100
+                THEN memory_usage_percent END) as memory_p99";
+
+/// Raw samples of `source` (see [`partitions::source`]) collected in the
+/// last `since` (an SQLite date modifier), each with its period (`period_format` applied to `collected_at`), its
+/// rank by CPU and by memory usage within the agent's period, and the
+/// number of samples in that period
+fn ranked_samples(source: &str, period_format: &str, since: &str) -> String {
+    format!(
+        "(SELECT *,
+            strftime('{format}', collected_at) as period,
+            ROW_NUMBER() OVER (bucket ORDER BY cpu_usage) as cpu_rank,
+            ROW_NUMBER() OVER (bucket ORDER BY memory_usage_percent) as memory_rank,
+            COUNT(*) OVER bucket as samples
+        FROM {source}
+        WHERE collected_at >= datetime('now', '{since}')
+        WINDOW bucket AS (PARTITION BY agent_name, strftime('{format}', collected_at)))",
+        source = source,
+        format = period_format,
+        since = since,
+    )
+}
+
 /// Run hourly aggregation for all agents
 pub fn aggregate_hourly(db: &Database) -> rusqlite::Result<u32> {
     let conn_guard = db.conn.lock().unwrap();
+    let source = partitions::source(
+        &conn_guard,
+        Some(OffsetDateTime::now_utc() - Duration::hours(2)),
+        None,
+    )?;
 
-    // Aggregate raw metrics into hourly buckets
-    let affected = conn_guard.execute(
+    // Aggregate raw metrics into hourly buckets. Network rates come from the
+    // counter growth over the samples' time span: NULL with a single sample,
+    // and never negative when a counter resets (agent host rebooted).
+    let sql = format!(
         "INSERT OR REPLACE INTO metrics_hourly (
             agent_name, hour_start, cpu_avg, cpu_max, memory_avg, memory_max,
-            load_avg, load_max, samples_count
+            load_avg, load_max, samples_count,
+            disk_avg, disk_max, network_rx_rate, network_tx_rate,
+            cpu_p95, cpu_p99, memory_p95, memory_p99
         )
         SELECT
             agent_name,
-            strftime('%Y-%m-%dT%H:00:00Z', collected_at) as hour_start,
+            period as hour_start,
             AVG(cpu_usage) as cpu_avg,
             MAX(cpu_usage) as cpu_max,
             AVG(memory_usage_percent) as memory_avg,
             MAX(memory_usage_percent) as memory_max,
             AVG(load_one) as load_avg,
             MAX(load_one) as load_max,
+            COUNT(*) as samples_count,
+            AVG(disk_usage_percent) as disk_avg,
+            MAX(disk_usage_percent) as disk_max,
+            MAX(0, (MAX(network_rx_bytes) - MIN(network_rx_bytes)) / NULLIF(
+                (julianday(MAX(collected_at)) - julianday(MIN(collected_at))) * 86400, 0
+            )) as network_rx_rate,
+            MAX(0, (MAX(network_tx_bytes) - MIN(network_tx_bytes)) / NULLIF(
+                (julianday(MAX(collected_at)) - julianday(MIN(collected_at))) * 86400, 0
+            )) as network_tx_rate,{}
+        FROM {}
+        GROUP BY agent_name, period",
+        PERCENTILES,
+        ranked_samples(&source, "%Y-%m-%dT%H:00:00Z", "-2 hours"),
+    );
+    let affected = conn_guard.execute(&sql, [])?;
+
+    Ok(affected as u32)
+}
+
+/// Roll raw container stats of the last two hours up into hourly buckets
+pub fn aggregate_container_hourly(db: &Database) -> rusqlite::Result<u32> {
+    let conn_guard = db.conn.lock().unwrap();
+
+    let affected = conn_guard.execute(
+        "INSERT OR REPLACE INTO container_metrics_hourly (
+            agent_name, container_name, compose_project, compose_service, hour_start,
+            cpu_avg, cpu_max, memory_avg, memory_max, samples_count
+        )
+        SELECT
+            agent_name,
+            container_name,
+            compose_project,
+            MAX(compose_service) as compose_service,
+            strftime('%Y-%m-%dT%H:00:00Z', collected_at) as hour_start,
+            AVG(cpu_percent) as cpu_avg,
+            MAX(cpu_percent) as cpu_max,
+            AVG(memory_usage) as memory_avg,
+            MAX(memory_usage) as memory_max,
             COUNT(*) as samples_count
-        FROM metrics_raw
+        FROM container_metrics
         WHERE collected_at >= datetime('now', '-2 hours')
-        GROUP BY agent_name, strftime('%Y-%m-%dT%H:00:00Z', collected_at)",
+        GROUP BY agent_name, container_name, compose_project,
+                 strftime('%Y-%m-%dT%H:00:00Z', collected_at)",
         [],
     )?;
 
@@ -39,7 +119,8 @@ pub fn aggregate_daily(db: &Database) -> rusqlite::Result<u32> {
     let affected = conn_guard.execute(
         "INSERT OR REPLACE INTO metrics_daily (
             agent_name, day_start, cpu_avg, cpu_max, memory_avg, memory_max,
-            load_avg, load_max, samples_count
+            load_avg, load_max, samples_count,
+            disk_avg, disk_max, network_rx_rate, network_tx_rate
         )
         SELECT
             agent_name,
@@ -50,13 +131,41 @@ pub fn aggregate_daily(db: &Database) -> rusqlite::Result<u32> {
             MAX(memory_max) as memory_max,
             AVG(load_avg) as load_avg,
             MAX(load_max) as load_max,
-            SUM(samples_count) as samples_count
+            SUM(samples_count) as samples_count,
+            AVG(disk_avg) as disk_avg,
+            MAX(disk_max) as disk_max,
+            AVG(network_rx_rate) as network_rx_rate,
+            AVG(network_tx_rate) as network_tx_rate
         FROM metrics_hourly
         WHERE hour_start >= datetime('now', '-2 days')
         GROUP BY agent_name, strftime('%Y-%m-%dT00:00:00Z', hour_start)",
         [],
     )?;
 
+    // Percentiles cannot be combined from hourly ones: rank the raw samples
+    // of each day instead (days whose raw data is gone keep their values)
+    let source = partitions::source(
+        &conn_guard,
+        Some(OffsetDateTime::now_utc() - Duration::days(2)),
+        None,
+    )?;
+    conn_guard.execute(
+        &format!(
+            "UPDATE metrics_daily SET
+                cpu_p95 = p.cpu_p95, cpu_p99 = p.cpu_p99,
+                memory_p95 = p.memory_p95, memory_p99 = p.memory_p99
+            FROM (
+                SELECT agent_name, period,{}
+                FROM {}
+                GROUP BY agent_name, period
+            ) p
+            WHERE metrics_daily.agent_name = p.agent_name AND metrics_daily.day_start = p.period",
+            PERCENTILES,
+            ranked_samples(&source, "%Y-%m-%dT00:00:00Z", "-2 days"),
+        ),
+        [],
+    )?;
+
     Ok(affected as u32)
 }
 
@@ -66,15 +175,25 @@ pub fn run_retention_cleanup(
     raw_days: u32,
     hourly_days: u32,
     daily_days: u32,
+    suspicious_days: u32,
+    deploy_days: u32,
 ) -> rusqlite::Result<(usize, usize, usize)> {
     let raw_deleted = db.cleanup_old_metrics(raw_days)?;
     let hourly_deleted = db.cleanup_old_hourly(hourly_days)?;
     let daily_deleted = db.cleanup_old_daily(daily_days)?;
+    let container_deleted = db.cleanup_old_container_metrics(raw_days, hourly_days)?;
+    let custom_deleted = db.cleanup_old_custom_metrics(raw_days)?;
+    let suspicious_deleted = db.cleanup_old_suspicious(suspicious_days)?;
+    let deploys_deleted = db.cleanup_old_deploys(deploy_days)?;
 
     info!(
         raw = raw_deleted,
         hourly = hourly_deleted,
         daily = daily_deleted,
+        containers = container_deleted,
+        custom = custom_deleted,
+        suspicious = suspicious_deleted,
+        deploys = deploys_deleted,
         "Retention cleanup completed"
     );
 
@@ -114,6 +233,15 @@ pub async fn aggregation_task(db: Arc<Database>, interval_secs: u64) {
             }
             Err(e) => error!("Hourly aggregation failed: {}", e),
         }
+
+        match aggregate_container_hourly(&db) {
+            Ok(count) => {
+                if count > 0 {
+                    info!(records = count, "Hourly container aggregation completed");
+                }
+            }
+            Err(e) => error!("Hourly container aggregation failed: {}", e),
+        }
     }
 }
 
@@ -137,14 +265,28 @@ pub async fn daily_aggregation_task(db: Arc<Database>) {
 }
 
 /// Background task for retention cleanup
-pub async fn retention_task(db: Arc<Database>, raw_days: u32, hourly_days: u32, daily_days: u32) {
+pub async fn retention_task(
+    db: Arc<Database>,
+    raw_days: u32,
+    hourly_days: u32,
+    daily_days: u32,
+    suspicious_days: u32,
+    deploy_days: u32,
+) {
     // Run every 6 hours
     let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(6 * 3600));
 
     loop {
         interval.tick().await;
 
-        if let Err(e) = run_retention_cleanup(&db, raw_days, hourly_days, daily_days) {
+        if let Err(e) = run_retention_cleanup(
+            &db,
+            raw_days,
+            hourly_days,
+            daily_days,
+            suspicious_days,
+            deploy_days,
+        ) {
             error!("Retention cleanup failed: {}", e);
         }
     }
@@ -153,6 +295,98 @@ pub async fn retention_task(db: Arc<Database>, raw_days: u32, hourly_days: u32,
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::storage::MetricRecord;
+    use time::{Duration, OffsetDateTime, Time};
+
+    #[test]
+    fn test_hourly_rollup_keeps_disk_and_network_rates() {
+        let db = Database::new(":memory:").unwrap();
+        db.migrate().unwrap();
+        let hour = OffsetDateTime::now_utc().replace_time(Time::MIDNIGHT)
+            + Duration::hours(i64::from(OffsetDateTime::now_utc().hour()));
+        for (i, (disk, rx, tx)) in [
+            (40.0, 1_000, 500),
+            (50.0, 7_000, 800),
+            (60.0, 13_000, 1_100),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            db.insert_metric(&MetricRecord {
+                id: None,
+                agent_name: "web-1".to_string(),
+                collected_at: hour + Duration::minutes(i as i64),
+                cpu_usage: 10.0,
+                memory_usage_percent: 20.0,
+                memory_used: 1,
+                memory_total: 2,
+                load_one: 0.5,
+                load_five: 0.5,
+                load_fifteen: 0.5,
+                disk_usage_percent: Some(disk),
+                containers_running: None,
+                containers_total: None,
+                raw_json: None,
+                network_rx_bytes: Some(rx),
+                network_tx_bytes: Some(tx),
+            })
+            .unwrap();
+        }
+
+        aggregate_hourly(&db).unwrap();
+        let hourly = db.get_hourly_metrics("web-1", None, None).unwrap();
+        assert_eq!(hourly.len(), 1);
+        let bucket = &hourly[0];
+        assert_eq!(bucket.disk_avg, Some(50.0));
+        assert_eq!(bucket.disk_max, Some(60.0));
+        // 12000 bytes received and 600 sent over two minutes
+        assert!((bucket.network_rx_rate.unwrap() - 100.0).abs() < 0.01);
+        assert!((bucket.network_tx_rate.unwrap() - 5.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_rollups_keep_cpu_and_memory_percentiles() {
+        let db = Database::new(":memory:").unwrap();
+        db.migrate().unwrap();
+        let now = OffsetDateTime::now_utc();
+        let hour = now.replace_time(Time::MIDNIGHT) + Duration::hours(i64::from(now.hour()));
+        // A single memory spike reaches the max but not the percentiles
+        for i in 1..=100 {
+            db.insert_metric(&MetricRecord {
+                id: None,
+                agent_name: "web-1".to_string(),
+                collected_at: hour + Duration::seconds(i * 30),
+                cpu_usage: i as f64,
+                memory_usage_percent: if i == 100 { 99.0 } else { 40.0 },
+                memory_used: 1,
+                memory_total: 2,
+                load_one: 0.5,
+                load_five: 0.5,
+                load_fifteen: 0.5,
+                disk_usage_percent: None,
+                containers_running: None,
+                containers_total: None,
+                raw_json: None,
+                network_rx_bytes: None,
+                network_tx_bytes: None,
+            })
+            .unwrap();
+        }
+
+        aggregate_hourly(&db).unwrap();
+        aggregate_daily(&db).unwrap();
+        for rollups in [
+            db.get_hourly_metrics("web-1", None, None).unwrap(),
+            db.get_daily_metrics("web-1", None, None).unwrap(),
+        ] {
+            assert_eq!(rollups.len(), 1);
+            assert_eq!(rollups[0].cpu_p95, Some(95.0));
+            assert_eq!(rollups[0].cpu_p99, Some(99.0));
+            assert_eq!(rollups[0].memory_p95, Some(40.0));
+            assert_eq!(rollups[0].memory_p99, Some(40.0));
+            assert_eq!(rollups[0].memory_max, 99.0);
+        }
+    }
 
     #[test]
     fn test_parse_retention_days() {
@@ -0,0 +1,201 @@
+use super::repository::{unlock, Database};
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use time::macros::format_description;
+use time::OffsetDateTime;
+use tracing::{error, info, warn};
+
+const BACKUP_PREFIX: &str = "infractl-";
+const BACKUP_SUFFIX: &str = ".db";
+
+#[derive(Debug, Serialize)]
+pub struct BackupResult {
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    /// Older backups deleted to keep `keep`
+    pub removed: Vec<PathBuf>,
+}
+
+/// Copy the database to `<dir>/infractl-<UTC timestamp>.db` and delete all
+/// but the newest `keep` backups. Safe while the server runs: the copy is
+/// one consistent snapshot and does not block writers.
+pub fn backup(db: &Database, dir: &Path, keep: usize) -> Result<BackupResult, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let timestamp = OffsetDateTime::now_utc()
+        .format(format_description!(
+            "[year][month][day]T[hour][minute][second]Z"
+        ))
+        .map_err(|e| e.to_string())?;
+    let path = dir.join(format!("{}{}{}", BACKUP_PREFIX, timestamp, BACKUP_SUFFIX));
+    if path.exists() {
+        return Err(format!("Backup {} already exists", path.display()));
+    }
+
+    db.backup_to(&path)
+        .map_err(|e| format!("Backup to {} failed: {}", path.display(), e))?;
+    let size_bytes = fs::metadata(&path).map(|m| m.len()).unwrap_or_default();
+    let removed = prune(dir, keep)?;
+    info!(path = %path.display(), size_bytes, removed = removed.len(), "Database backed up");
+
+    Ok(BackupResult {
+        path,
+        size_bytes,
+        removed,
+    })
+}
+
+/// Delete all but the newest `keep` backups in `dir`
+fn prune(dir: &Path, keep: usize) -> Result<Vec<PathBuf>, String> {
+    let mut backups: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| {
+                    name.starts_with(BACKUP_PREFIX) && name.ends_with(BACKUP_SUFFIX)
+                })
+        })
+        .collect();
+    // Timestamps in the names sort chronologically
+    backups.sort();
+
+    let excess = backups.len().saturating_sub(keep);
+    let mut removed = Vec::new();
+    for path in backups.into_iter().take(excess) {
+        match fs::remove_file(&path) {
+            Ok(()) => removed.push(path),
+            Err(e) => warn!(path = %path.display(), error = %e, "Failed to remove old backup"),
+        }
+    }
+    Ok(removed)
+}
+
+/// Replace the database at `db_path` with the contents of `backup`, after
+/// checking the backup is intact. The server must not be running. Backups of
+/// an encrypted database need its `key`.
+pub fn restore(backup: &Path, db_path: &Path, key: Option<&str>) -> Result<(), String> {
+    let source = Connection::open_with_flags(backup, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Failed to open {}: {}", backup.display(), e))?;
+    unlock(&source, key).map_err(|e| format!("Failed to open {}: {}", backup.display(), e))?;
+    let check: String = source
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|e| format!("{} is not a usable database: {}", backup.display(), e))?;
+    if check != "ok" {
+        return Err(format!(
+            "{} failed the integrity check: {}",
+            backup.display(),
+            check
+        ));
+    }
+    let version: Option<i32> = source
+        .query_row("SELECT MAX(version) FROM schema_migrations", [], |row| {
+            row.get(0)
+        })
+        .unwrap_or(None);
+    if version.is_none() {
+        return Err(format!("{} is not an infractl database", backup.display()));
+    }
+
+    let mut target = Connection::open(db_path)
+        .map_err(|e| format!("Failed to open {}: {}", db_path.display(), e))?;
+    unlock(&target, key).map_err(|e| format!("Failed to open {}: {}", db_path.display(), e))?;
+    copy(&source, &mut target)
+        .map_err(|e| format!("Restore into {} failed: {}", db_path.display(), e))?;
+    info!(from = %backup.display(), to = %db_path.display(), "Database restored");
+    Ok(())
+}
+
+/// Copy every page of `source` into `target` in one step, so concurrent
+/// writes cannot restart or tear the copy
+pub(super) fn copy(source: &Connection, target: &mut Connection) -> rusqlite::Result<()> {
+    match Backup::new(source, target)?.step(-1)? {
+        StepResult::Done => Ok(()),
+        _ => Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            Some("database is busy".to_string()),
+        )),
+    }
+}
+
+/// Back up every `interval` (`modules.storage.backup.interval`)
+pub async fn backup_task(db: Arc<Database>, dir: PathBuf, keep: usize, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick is immediate: start with a full interval instead
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+
+        let (db, dir) = (db.clone(), dir.clone());
+        match tokio::task::spawn_blocking(move || backup(&db, &dir, keep)).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => error!("Scheduled backup failed: {}", e),
+            Err(e) => error!("Scheduled backup failed: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{DeployRecord, DeployStatus};
+
+    #[test]
+    fn test_backup_retention_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("metrics.db");
+        let backups = dir.path().join("backups");
+        let db = Database::open(db_path.to_str().unwrap(), 1).unwrap();
+        db.migrate().unwrap();
+        let id = db
+            .insert_deploy(&DeployRecord {
+                id: None,
+                agent_name: "local".to_string(),
+                deployment_name: "web".to_string(),
+                deploy_type: "GitPull".to_string(),
+                status: DeployStatus::Success,
+                started_at: OffsetDateTime::now_utc(),
+                completed_at: None,
+                duration_ms: None,
+                trigger_source: None,
+                commit_sha: None,
+                output: None,
+                error_message: None,
+                attempt: 1,
+                image_digests: None,
+                log_file: None,
+            })
+            .unwrap();
+
+        // Older backups beyond `keep` are removed, newest first by name
+        fs::create_dir_all(&backups).unwrap();
+        for old in [
+            "infractl-20240101T000000Z.db",
+            "infractl-20240102T000000Z.db",
+        ] {
+            fs::write(backups.join(old), b"").unwrap();
+        }
+        fs::write(backups.join("notes.txt"), b"").unwrap();
+        let result = backup(&db, &backups, 2).unwrap();
+        assert!(result.size_bytes > 0);
+        assert_eq!(
+            result.removed,
+            vec![backups.join("infractl-20240101T000000Z.db")]
+        );
+        assert!(backups.join("notes.txt").exists());
+
+        let restored = dir.path().join("restored.db");
+        restore(&result.path, &restored, None).unwrap();
+        let copy = Database::open(restored.to_str().unwrap(), 0).unwrap();
+        assert!(copy.get_deploy(id).unwrap().is_some());
+
+        assert!(restore(&backups.join("notes.txt"), &restored, None).is_err());
+    }
+}
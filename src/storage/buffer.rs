@@ -0,0 +1,90 @@
+use rusqlite::{params, Connection};
+use std::sync::Mutex;
+
+/// Metrics reports an agent could not push to Home, kept in a small local
+/// SQLite file until Home is reachable again. Bounded: once `capacity`
+/// reports are waiting, the oldest are dropped to make room.
+pub struct MetricsBuffer {
+    conn: Mutex<Connection>,
+    capacity: usize,
+}
+
+impl MetricsBuffer {
+    pub fn open(path: &str, capacity: usize) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode=WAL;
+             PRAGMA synchronous=NORMAL;
+             CREATE TABLE IF NOT EXISTS pending_reports (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                report TEXT NOT NULL
+             );",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            capacity,
+        })
+    }
+
+    /// Queue a report (JSON). Returns how many old reports were dropped.
+    pub fn push(&self, report: &str) -> rusqlite::Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO pending_reports (report) VALUES (?1)",
+            params![report],
+        )?;
+        conn.execute(
+            "DELETE FROM pending_reports WHERE id NOT IN (
+                SELECT id FROM pending_reports ORDER BY id DESC LIMIT ?1
+            )",
+            params![self.capacity as i64],
+        )
+    }
+
+    /// Up to `limit` waiting reports, oldest first, with their ids
+    pub fn oldest(&self, limit: usize) -> rusqlite::Result<Vec<(i64, String)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT id, report FROM pending_reports ORDER BY id LIMIT ?1")?;
+        let rows = stmt.query_map(params![limit as i64], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    /// Forget a report once Home has accepted it
+    pub fn remove(&self, id: i64) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM pending_reports WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> rusqlite::Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row("SELECT COUNT(*) FROM pending_reports", [], |row| {
+            row.get::<_, i64>(0)
+        })
+        .map(|n| n as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_keeps_newest_reports_in_order() {
+        let buffer = MetricsBuffer::open(":memory:", 3).unwrap();
+        for i in 0..5 {
+            let dropped = buffer.push(&format!("{{\"n\":{}}}", i)).unwrap();
+            assert_eq!(dropped, usize::from(i >= 3));
+        }
+        assert_eq!(buffer.len().unwrap(), 3);
+
+        let waiting = buffer.oldest(2).unwrap();
+        let reports: Vec<&str> = waiting.iter().map(|(_, r)| r.as_str()).collect();
+        assert_eq!(reports, ["{\"n\":2}", "{\"n\":3}"]);
+
+        buffer.remove(waiting[0].0).unwrap();
+        assert_eq!(buffer.oldest(1).unwrap()[0].1, "{\"n\":3}");
+        assert_eq!(buffer.len().unwrap(), 2);
+    }
+}
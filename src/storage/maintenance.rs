@@ -0,0 +1,147 @@
+use super::repository::Database;
+use crate::deploy::CronSchedule;
+use rusqlite::Connection;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+/// Free pages released per incremental vacuum step; the writer is unlocked
+/// between steps so queued writes are not held up for the whole run
+const VACUUM_STEP_PAGES: u32 = 1000;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct MaintenanceReport {
+    /// Bytes the database file shrank by
+    pub reclaimed_bytes: u64,
+    pub size_bytes: u64,
+    /// The file was switched to incremental auto-vacuum with a full VACUUM
+    pub converted: bool,
+    /// WAL frames written back to the database file
+    pub checkpointed_frames: i64,
+}
+
+/// Return free pages to the filesystem, refresh the query planner
+/// statistics and truncate the WAL
+pub fn run(db: &Database) -> rusqlite::Result<MaintenanceReport> {
+    let mut report = MaintenanceReport::default();
+    let before = size(&db.conn.lock().unwrap())?;
+
+    // Files created before incremental auto-vacuum was enabled need one full
+    // VACUUM to switch; after that free pages can be released a few at a time
+    let auto_vacuum: i64 = {
+        let conn = db.conn.lock().unwrap();
+        conn.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))?
+    };
+    if auto_vacuum != 2 {
+        let conn = db.conn.lock().unwrap();
+        conn.execute_batch("PRAGMA auto_vacuum = INCREMENTAL; VACUUM;")?;
+        report.converted = true;
+    } else {
+        loop {
+            let conn = db.conn.lock().unwrap();
+            let free: u32 = conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))?;
+            if free == 0 {
+                break;
+            }
+            conn.execute_batch(&format!("PRAGMA incremental_vacuum({})", VACUUM_STEP_PAGES))?;
+        }
+    }
+
+    let conn = db.conn.lock().unwrap();
+    conn.execute_batch("ANALYZE")?;
+    report.checkpointed_frames =
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| row.get(2))?;
+    report.size_bytes = size(&conn)?;
+    report.reclaimed_bytes = before.saturating_sub(report.size_bytes);
+    Ok(report)
+}
+
+/// Database size from its page count (WAL not included)
+fn size(conn: &Connection) -> rusqlite::Result<u64> {
+    conn.query_row(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Run maintenance whenever `schedule` (`modules.storage.maintenance.schedule`)
+/// matches, until shutdown starts
+pub async fn maintenance_task(
+    db: Arc<Database>,
+    schedule: String,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let schedule = match CronSchedule::parse(&schedule) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            warn!(error = %e, "Invalid database maintenance schedule");
+            return;
+        }
+    };
+
+    loop {
+        // Wake up at the start of every minute
+        let now = OffsetDateTime::now_utc();
+        let wait = Duration::from_secs(60)
+            - Duration::from_secs(u64::from(now.second()))
+            - Duration::from_nanos(u64::from(now.nanosecond()));
+
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = shutdown.wait_for(|stopping| *stopping) => break,
+        }
+        if !schedule.matches(OffsetDateTime::now_utc()) {
+            continue;
+        }
+
+        let db = db.clone();
+        match tokio::task::spawn_blocking(move || run(&db)).await {
+            Ok(Ok(report)) => info!(
+                reclaimed_bytes = report.reclaimed_bytes,
+                size_bytes = report.size_bytes,
+                converted = report.converted,
+                checkpointed_frames = report.checkpointed_frames,
+                "Database maintenance completed"
+            ),
+            Ok(Err(e)) => error!("Database maintenance failed: {}", e),
+            Err(e) => error!("Database maintenance failed: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maintenance_reclaims_deleted_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metrics.db");
+        let db = Database::open(path.to_str().unwrap(), 0).unwrap();
+        db.migrate().unwrap();
+        {
+            let conn = db.conn.lock().unwrap();
+            conn.execute_batch(
+                "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 2000)
+                 INSERT INTO suspicious_requests (recorded_at, source_ip, reason, headers)
+                 SELECT datetime('now'), '10.0.0.1', 'test', printf('%.2000c', 'x') FROM n;
+                 DELETE FROM suspicious_requests;",
+            )
+            .unwrap();
+        }
+
+        let report = run(&db).unwrap();
+        assert!(!report.converted);
+        assert!(report.reclaimed_bytes > 1_000_000);
+        let free: u32 = db
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("PRAGMA freelist_count", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(free, 0);
+    }
+}
@@ -1,169 +1,574 @@
-use rusqlite::Connection;
+use super::repository::Database;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
 
-/// Database schema version
-const SCHEMA_VERSION: i32 = 1;
+/// One schema version: the SQL that applies it and the SQL that reverts it
+pub struct Migration {
+    pub version: i32,
+    pub description: &'static str,
+    pub up: &'static str,
+    pub down: &'static str,
+}
 
-/// Run all pending migrations
-pub fn run_migrations(conn: &Connection) -> rusqlite::Result<()> {
-    // Create migrations table if not exists
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS schema_migrations (
-            version INTEGER PRIMARY KEY,
-            applied_at TEXT NOT NULL DEFAULT (datetime('now'))
-        )",
-        [],
-    )?;
+/// Every schema version, oldest first. Append new versions here; never edit
+/// a released one, since databases already at that version will not rerun it.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Initial schema",
+        up: "
+            -- Raw metrics from agents
+            CREATE TABLE IF NOT EXISTS metrics_raw (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                agent_name TEXT NOT NULL,
+                collected_at TEXT NOT NULL DEFAULT (datetime('now')),
+                cpu_usage REAL,
+                memory_usage_percent REAL,
+                memory_used INTEGER,
+                memory_total INTEGER,
+                load_one REAL,
+                load_five REAL,
+                load_fifteen REAL,
+                disk_usage_percent REAL,
+                containers_running INTEGER,
+                containers_total INTEGER,
+                raw_json TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_metrics_raw_agent_time
+            ON metrics_raw(agent_name, collected_at);
+
+            -- Hourly aggregated metrics
+            CREATE TABLE IF NOT EXISTS metrics_hourly (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                agent_name TEXT NOT NULL,
+                hour_start TEXT NOT NULL,
+                cpu_avg REAL,
+                cpu_max REAL,
+                memory_avg REAL,
+                memory_max REAL,
+                load_avg REAL,
+                load_max REAL,
+                samples_count INTEGER,
+                UNIQUE(agent_name, hour_start)
+            );
+
+            -- Daily aggregated metrics
+            CREATE TABLE IF NOT EXISTS metrics_daily (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                agent_name TEXT NOT NULL,
+                day_start TEXT NOT NULL,
+                cpu_avg REAL,
+                cpu_max REAL,
+                memory_avg REAL,
+                memory_max REAL,
+                load_avg REAL,
+                load_max REAL,
+                samples_count INTEGER,
+                UNIQUE(agent_name, day_start)
+            );
+
+            -- Deployment history
+            CREATE TABLE IF NOT EXISTS deploy_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                agent_name TEXT NOT NULL,
+                deployment_name TEXT NOT NULL,
+                deploy_type TEXT NOT NULL,
+                status TEXT NOT NULL,
+                started_at TEXT NOT NULL DEFAULT (datetime('now')),
+                completed_at TEXT,
+                duration_ms INTEGER,
+                trigger_source TEXT,
+                commit_sha TEXT,
+                output TEXT,
+                error_message TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_deploy_history_agent_time
+            ON deploy_history(agent_name, started_at);
+
+            -- Suspicious requests log
+            CREATE TABLE IF NOT EXISTS suspicious_requests (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                recorded_at TEXT NOT NULL DEFAULT (datetime('now')),
+                source_ip TEXT NOT NULL,
+                method TEXT,
+                path TEXT,
+                reason TEXT NOT NULL,
+                user_agent TEXT,
+                headers TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_suspicious_requests_time
+            ON suspicious_requests(recorded_at);
+
+            -- Agent status tracking
+            CREATE TABLE IF NOT EXISTS agent_status (
+                agent_name TEXT PRIMARY KEY,
+                last_seen TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'unknown',
+                version TEXT,
+                uptime_seconds INTEGER
+            );",
+        down: "
+            DROP TABLE IF EXISTS agent_status;
+            DROP TABLE IF EXISTS suspicious_requests;
+            DROP TABLE IF EXISTS deploy_history;
+            DROP TABLE IF EXISTS metrics_daily;
+            DROP TABLE IF EXISTS metrics_hourly;
+            DROP TABLE IF EXISTS metrics_raw;",
+    },
+    Migration {
+        version: 2,
+        description: "Pending deploy jobs, so queued deployments survive a restart",
+        up: "
+            CREATE TABLE IF NOT EXISTS deploy_queue (
+                id TEXT PRIMARY KEY,
+                pipeline_id TEXT NOT NULL,
+                agent_name TEXT NOT NULL,
+                deployment_name TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                trigger_source TEXT
+            );",
+        down: "
+            DROP TABLE IF EXISTS deploy_queue;",
+    },
+    Migration {
+        version: 3,
+        description: "Retry attempt number for deploy history and queued jobs",
+        up: "
+            ALTER TABLE deploy_history ADD COLUMN attempt INTEGER NOT NULL DEFAULT 1;
+            ALTER TABLE deploy_queue ADD COLUMN attempt INTEGER NOT NULL DEFAULT 1;",
+        down: "
+            ALTER TABLE deploy_queue DROP COLUMN attempt;
+            ALTER TABLE deploy_history DROP COLUMN attempt;",
+    },
+    Migration {
+        version: 4,
+        description: "Image digests recorded by docker_pull deployments",
+        up: "
+            ALTER TABLE deploy_history ADD COLUMN image_digests TEXT;",
+        down: "
+            ALTER TABLE deploy_history DROP COLUMN image_digests;",
+    },
+    Migration {
+        version: 5,
+        description: "Earliest start time of queued jobs (deferred by a webhook schedule window)",
+        up: "
+            ALTER TABLE deploy_queue ADD COLUMN not_before TEXT;",
+        down: "
+            ALTER TABLE deploy_queue DROP COLUMN not_before;",
+    },
+    Migration {
+        version: 6,
+        description: "Full deploy output lives in a log file; `output` keeps the tail",
+        up: "
+            ALTER TABLE deploy_history ADD COLUMN log_file TEXT;",
+        down: "
+            ALTER TABLE deploy_history DROP COLUMN log_file;",
+    },
+    Migration {
+        version: 7,
+        description: "Queued jobs are deploys or shutdowns",
+        up: "
+            ALTER TABLE deploy_queue ADD COLUMN kind TEXT NOT NULL DEFAULT 'deploy';",
+        down: "
+            ALTER TABLE deploy_queue DROP COLUMN kind;",
+    },
+    Migration {
+        version: 8,
+        description: "Temporary IP bans, restored on restart",
+        up: "
+            CREATE TABLE IF NOT EXISTS ip_bans (
+                ip TEXT PRIMARY KEY,
+                banned_until TEXT NOT NULL
+            );",
+        down: "
+            DROP TABLE IF EXISTS ip_bans;",
+    },
+    Migration {
+        version: 9,
+        description: "Agents enrolled with join tokens, added to the configured `agents`",
+        up: "
+            CREATE TABLE IF NOT EXISTS enrolled_agents (
+                name TEXT PRIMARY KEY,
+                address TEXT NOT NULL,
+                tags TEXT NOT NULL DEFAULT '[]',
+                enrolled_at TEXT NOT NULL
+            );",
+        down: "
+            DROP TABLE IF EXISTS enrolled_agents;",
+    },
+    Migration {
+        version: 10,
+        description: "Agents going offline and coming back, as seen by health polling",
+        up: "
+            CREATE TABLE IF NOT EXISTS agent_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                agent_name TEXT NOT NULL,
+                event TEXT NOT NULL,
+                occurred_at TEXT NOT NULL,
+                detail TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_agent_events_agent_time
+            ON agent_events(agent_name, occurred_at);",
+        down: "
+            DROP TABLE IF EXISTS agent_events;",
+    },
+    Migration {
+        version: 11,
+        description: "Per-container stats from agents' Docker metrics, raw and hourly",
+        // `compose_project` is '' for containers outside a compose project so
+        // it can be part of the unique key
+        up: "
+            CREATE TABLE IF NOT EXISTS container_metrics (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                agent_name TEXT NOT NULL,
+                container_name TEXT NOT NULL,
+                compose_project TEXT NOT NULL DEFAULT '',
+                compose_service TEXT,
+                collected_at TEXT NOT NULL,
+                cpu_percent REAL,
+                memory_usage INTEGER,
+                memory_limit INTEGER,
+                memory_percent REAL,
+                network_rx_bytes INTEGER,
+                network_tx_bytes INTEGER,
+                block_read_bytes INTEGER,
+                block_write_bytes INTEGER,
+                pids INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS idx_container_metrics_name_time
+            ON container_metrics(container_name, collected_at);
+            CREATE INDEX IF NOT EXISTS idx_container_metrics_service_time
+            ON container_metrics(compose_service, collected_at);
+
+            CREATE TABLE IF NOT EXISTS container_metrics_hourly (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                agent_name TEXT NOT NULL,
+                container_name TEXT NOT NULL,
+                compose_project TEXT NOT NULL DEFAULT '',
+                compose_service TEXT,
+                hour_start TEXT NOT NULL,
+                cpu_avg REAL,
+                cpu_max REAL,
+                memory_avg REAL,
+                memory_max REAL,
+                samples_count INTEGER,
+                UNIQUE(agent_name, container_name, compose_project, hour_start)
+            );",
+        down: "
+            DROP TABLE IF EXISTS container_metrics_hourly;
+            DROP TABLE IF EXISTS container_metrics;",
+    },
+    Migration {
+        version: 12,
+        description: "Network counters in raw metrics; disk usage and network rates in the \
+                      hourly and daily rollups",
+        up: "
+            ALTER TABLE metrics_raw ADD COLUMN network_rx_bytes INTEGER;
+            ALTER TABLE metrics_raw ADD COLUMN network_tx_bytes INTEGER;
+            ALTER TABLE metrics_hourly ADD COLUMN disk_avg REAL;
+            ALTER TABLE metrics_hourly ADD COLUMN disk_max REAL;
+            ALTER TABLE metrics_hourly ADD COLUMN network_rx_rate REAL;
+            ALTER TABLE metrics_hourly ADD COLUMN network_tx_rate REAL;
+            ALTER TABLE metrics_daily ADD COLUMN disk_avg REAL;
+            ALTER TABLE metrics_daily ADD COLUMN disk_max REAL;
+            ALTER TABLE metrics_daily ADD COLUMN network_rx_rate REAL;
+            ALTER TABLE metrics_daily ADD COLUMN network_tx_rate REAL;",
+        down: "
+            ALTER TABLE metrics_daily DROP COLUMN network_tx_rate;
+            ALTER TABLE metrics_daily DROP COLUMN network_rx_rate;
+            ALTER TABLE metrics_daily DROP COLUMN disk_max;
+            ALTER TABLE metrics_daily DROP COLUMN disk_avg;
+            ALTER TABLE metrics_hourly DROP COLUMN network_tx_rate;
+            ALTER TABLE metrics_hourly DROP COLUMN network_rx_rate;
+            ALTER TABLE metrics_hourly DROP COLUMN disk_max;
+            ALTER TABLE metrics_hourly DROP COLUMN disk_avg;
+            ALTER TABLE metrics_raw DROP COLUMN network_tx_bytes;
+            ALTER TABLE metrics_raw DROP COLUMN network_rx_bytes;",
+    },
+    Migration {
+        version: 13,
+        description: "Full-text index over deploy output and error messages, kept in sync \
+                      with `deploy_history` by triggers",
+        up: "
+            CREATE VIRTUAL TABLE IF NOT EXISTS deploy_search USING fts5(
+                output, error_message,
+                content = 'deploy_history', content_rowid = 'id'
+            );
+
+            CREATE TRIGGER IF NOT EXISTS deploy_search_insert AFTER INSERT ON deploy_history BEGIN
+                INSERT INTO deploy_search (rowid, output, error_message)
+                VALUES (new.id, new.output, new.error_message);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS deploy_search_delete AFTER DELETE ON deploy_history BEGIN
+                INSERT INTO deploy_search (deploy_search, rowid, output, error_message)
+                VALUES ('delete', old.id, old.output, old.error_message);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS deploy_search_update
+            AFTER UPDATE OF output, error_message ON deploy_history BEGIN
+                INSERT INTO deploy_search (deploy_search, rowid, output, error_message)
+                VALUES ('delete', old.id, old.output, old.error_message);
+                INSERT INTO deploy_search (rowid, output, error_message)
+                VALUES (new.id, new.output, new.error_message);
+            END;
+
+            INSERT INTO deploy_search (deploy_search) VALUES ('rebuild');",
+        down: "
+            DROP TRIGGER IF EXISTS deploy_search_update;
+            DROP TRIGGER IF EXISTS deploy_search_delete;
+            DROP TRIGGER IF EXISTS deploy_search_insert;
+            DROP TABLE IF EXISTS deploy_search;",
+    },
+    Migration {
+        version: 14,
+        description: "95th and 99th percentiles of CPU and memory usage in the rollups",
+        up: "
+            ALTER TABLE metrics_hourly ADD COLUMN cpu_p95 REAL;
+            ALTER TABLE metrics_hourly ADD COLUMN cpu_p99 REAL;
+            ALTER TABLE metrics_hourly ADD COLUMN memory_p95 REAL;
+            ALTER TABLE metrics_hourly ADD COLUMN memory_p99 REAL;
+            ALTER TABLE metrics_daily ADD COLUMN cpu_p95 REAL;
+            ALTER TABLE metrics_daily ADD COLUMN cpu_p99 REAL;
+            ALTER TABLE metrics_daily ADD COLUMN memory_p95 REAL;
+            ALTER TABLE metrics_daily ADD COLUMN memory_p99 REAL;",
+        down: "
+            ALTER TABLE metrics_daily DROP COLUMN memory_p99;
+            ALTER TABLE metrics_daily DROP COLUMN memory_p95;
+            ALTER TABLE metrics_daily DROP COLUMN cpu_p99;
+            ALTER TABLE metrics_daily DROP COLUMN cpu_p95;
+            ALTER TABLE metrics_hourly DROP COLUMN memory_p99;
+            ALTER TABLE metrics_hourly DROP COLUMN memory_p95;
+            ALTER TABLE metrics_hourly DROP COLUMN cpu_p99;
+            ALTER TABLE metrics_hourly DROP COLUMN cpu_p95;",
+    },
+    Migration {
+        version: 15,
+        description: "Values of user-defined metric commands from agents",
+        up: "
+            CREATE TABLE IF NOT EXISTS custom_metrics (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                agent_name TEXT NOT NULL,
+                name TEXT NOT NULL,
+                collected_at TEXT NOT NULL,
+                value REAL,
+                json TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_custom_metrics_agent_name_time
+            ON custom_metrics(agent_name, name, collected_at);",
+        down: "
+            DROP TABLE IF EXISTS custom_metrics;",
+    },
+];
 
-    let current_version: i32 = conn
-        .query_row(
-            "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
-            [],
-            |row| row.get(0),
-        )
-        .unwrap_or(0);
-
-    if current_version < SCHEMA_VERSION {
-        for version in (current_version + 1)..=SCHEMA_VERSION {
-            apply_migration(conn, version)?;
-            conn.execute(
-                "INSERT INTO schema_migrations (version) VALUES (?1)",
-                [version],
-            )?;
+/// Database schema version this build expects
+pub fn latest_version() -> i32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+/// One migration to apply or revert
+pub struct Step {
+    pub migration: &'static Migration,
+    pub direction: Direction,
+}
+
+impl Step {
+    pub fn sql(&self) -> &'static str {
+        match self.direction {
+            Direction::Up => self.migration.up,
+            Direction::Down => self.migration.down,
         }
     }
+}
 
-    Ok(())
+/// A known schema version and when it was applied (`None`: pending)
+#[derive(Debug, Serialize)]
+pub struct VersionStatus {
+    pub version: i32,
+    pub description: &'static str,
+    pub applied_at: Option<String>,
 }
 
-fn apply_migration(conn: &Connection, version: i32) -> rusqlite::Result<()> {
-    match version {
-        1 => migration_v1(conn),
-        _ => Ok(()),
+/// Apply all pending migrations
+pub fn run_migrations(conn: &Connection) -> rusqlite::Result<()> {
+    let current = current_version(conn)?;
+    // A newer schema (from a later release) is left alone
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        apply(
+            conn,
+            &Step {
+                migration,
+                direction: Direction::Up,
+            },
+        )?;
     }
+    Ok(())
 }
 
-/// Initial schema
-fn migration_v1(conn: &Connection) -> rusqlite::Result<()> {
-    // Raw metrics from agents
+/// Schema version of the database; creates the bookkeeping table if needed
+pub fn current_version(conn: &Connection) -> rusqlite::Result<i32> {
     conn.execute(
-        "CREATE TABLE IF NOT EXISTS metrics_raw (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            agent_name TEXT NOT NULL,
-            collected_at TEXT NOT NULL DEFAULT (datetime('now')),
-            cpu_usage REAL,
-            memory_usage_percent REAL,
-            memory_used INTEGER,
-            memory_total INTEGER,
-            load_one REAL,
-            load_five REAL,
-            load_fifteen REAL,
-            disk_usage_percent REAL,
-            containers_running INTEGER,
-            containers_total INTEGER,
-            raw_json TEXT
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            applied_at TEXT NOT NULL DEFAULT (datetime('now'))
         )",
         [],
     )?;
-
-    conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_metrics_raw_agent_time
-         ON metrics_raw(agent_name, collected_at)",
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
         [],
-    )?;
+        |row| row.get(0),
+    )
+}
 
-    // Hourly aggregated metrics
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS metrics_hourly (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            agent_name TEXT NOT NULL,
-            hour_start TEXT NOT NULL,
-            cpu_avg REAL,
-            cpu_max REAL,
-            memory_avg REAL,
-            memory_max REAL,
-            load_avg REAL,
-            load_max REAL,
-            samples_count INTEGER,
-            UNIQUE(agent_name, hour_start)
-        )",
-        [],
-    )?;
+/// Every known version with its applied time
+pub fn status(db: &Database) -> rusqlite::Result<Vec<VersionStatus>> {
+    let conn = db.conn.lock().unwrap();
+    current_version(&conn)?;
+    MIGRATIONS
+        .iter()
+        .map(|m| {
+            let applied_at = conn
+                .query_row(
+                    "SELECT applied_at FROM schema_migrations WHERE version = ?1",
+                    params![m.version],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(VersionStatus {
+                version: m.version,
+                description: m.description,
+                applied_at,
+            })
+        })
+        .collect()
+}
 
-    // Daily aggregated metrics
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS metrics_daily (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            agent_name TEXT NOT NULL,
-            day_start TEXT NOT NULL,
-            cpu_avg REAL,
-            cpu_max REAL,
-            memory_avg REAL,
-            memory_max REAL,
-            load_avg REAL,
-            load_max REAL,
-            samples_count INTEGER,
-            UNIQUE(agent_name, day_start)
-        )",
-        [],
-    )?;
+/// Steps that would bring the database to `target` (the dry run of [`migrate`])
+pub fn plan(db: &Database, target: i32) -> Result<Vec<Step>, String> {
+    let conn = db.conn.lock().unwrap();
+    let current = current_version(&conn).map_err(|e| e.to_string())?;
+    steps(current, target)
+}
 
-    // Deployment history
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS deploy_history (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            agent_name TEXT NOT NULL,
-            deployment_name TEXT NOT NULL,
-            deploy_type TEXT NOT NULL,
-            status TEXT NOT NULL,
-            started_at TEXT NOT NULL DEFAULT (datetime('now')),
-            completed_at TEXT,
-            duration_ms INTEGER,
-            trigger_source TEXT,
-            commit_sha TEXT,
-            output TEXT,
-            error_message TEXT
-        )",
-        [],
-    )?;
+/// Apply or revert migrations until the schema is at `target`. Each step runs
+/// in its own transaction, so a failure leaves the last completed version.
+pub fn migrate(db: &Database, target: i32) -> Result<Vec<Step>, String> {
+    let conn = db.conn.lock().unwrap();
+    let current = current_version(&conn).map_err(|e| e.to_string())?;
+    let steps = steps(current, target)?;
+    for step in &steps {
+        apply(&conn, step).map_err(|e| {
+            format!(
+                "Migration {} ({:?}) failed: {}",
+                step.migration.version, step.direction, e
+            )
+        })?;
+    }
+    Ok(steps)
+}
 
-    conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_deploy_history_agent_time
-         ON deploy_history(agent_name, started_at)",
-        [],
-    )?;
+fn steps(current: i32, target: i32) -> Result<Vec<Step>, String> {
+    if target > latest_version() || target < 0 {
+        return Err(unknown_version(target));
+    }
+    if current > latest_version() {
+        return Err(unknown_version(current));
+    }
+    let steps = if target >= current {
+        MIGRATIONS
+            .iter()
+            .filter(|m| m.version > current && m.version <= target)
+            .map(|migration| Step {
+                migration,
+                direction: Direction::Up,
+            })
+            .collect()
+    } else {
+        MIGRATIONS
+            .iter()
+            .rev()
+            .filter(|m| m.version > target && m.version <= current)
+            .map(|migration| Step {
+                migration,
+                direction: Direction::Down,
+            })
+            .collect()
+    };
+    Ok(steps)
+}
 
-    // Suspicious requests log
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS suspicious_requests (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            recorded_at TEXT NOT NULL DEFAULT (datetime('now')),
-            source_ip TEXT NOT NULL,
-            method TEXT,
-            path TEXT,
-            reason TEXT NOT NULL,
-            user_agent TEXT,
-            headers TEXT
-        )",
-        [],
-    )?;
+fn unknown_version(version: i32) -> String {
+    format!(
+        "Schema version {} is not known to this build (latest {})",
+        version,
+        latest_version()
+    )
+}
 
-    conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_suspicious_requests_time
-         ON suspicious_requests(recorded_at)",
-        [],
-    )?;
+fn apply(conn: &Connection, step: &Step) -> rusqlite::Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute_batch(step.sql())?;
+    match step.direction {
+        Direction::Up => tx.execute(
+            "INSERT INTO schema_migrations (version) VALUES (?1)",
+            params![step.migration.version],
+        )?,
+        Direction::Down => tx.execute(
+            "DELETE FROM schema_migrations WHERE version = ?1",
+            params![step.migration.version],
+        )?,
+    };
+    tx.commit()
+}
 
-    // Agent status tracking
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS agent_status (
-            agent_name TEXT PRIMARY KEY,
-            last_seen TEXT NOT NULL,
-            status TEXT NOT NULL DEFAULT 'unknown',
-            version TEXT,
-            uptime_seconds INTEGER
-        )",
-        [],
-    )?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(())
+    #[test]
+    fn test_migrations_revert_and_reapply() {
+        let db = Database::new(":memory:").unwrap();
+        db.migrate().unwrap();
+        assert!(status(&db).unwrap().iter().all(|v| v.applied_at.is_some()));
+
+        // Dry run changes nothing
+        let pending = plan(&db, 10).unwrap();
+        assert_eq!(pending.len() as i32, latest_version() - 10);
+        assert_eq!(pending[0].migration.version, latest_version());
+        assert_eq!(pending[0].direction, Direction::Down);
+        assert_eq!(
+            current_version(&db.conn.lock().unwrap()).unwrap(),
+            latest_version()
+        );
+
+        // Every version reverts cleanly, down to an empty database
+        migrate(&db, 0).unwrap();
+        let tables: i64 = db
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master
+                 WHERE type = 'table' AND name NOT IN ('schema_migrations', 'sqlite_sequence')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(tables, 0);
+        let versions = status(&db).unwrap();
+        assert!(versions.iter().all(|v| v.applied_at.is_none()));
+
+        let applied = migrate(&db, latest_version()).unwrap();
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        assert!(plan(&db, latest_version()).unwrap().is_empty());
+        assert!(plan(&db, latest_version() + 1).is_err());
+    }
 }
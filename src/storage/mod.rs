@@ -1,14 +1,21 @@
 pub mod aggregation;
-mod migrations;
+pub mod backup;
+pub mod buffer;
+pub mod maintenance;
+pub mod migrations;
 mod models;
+mod partitions;
+mod pool;
 mod repository;
+mod writer;
 
 pub use aggregation::parse_retention_days;
 pub use models::*;
-pub use repository::Database;
+pub use repository::{Database, DEFAULT_READ_CONNECTIONS};
+pub use writer::{DbWrite, DbWriter};
 
-use crate::config::Config;
-use anyhow::Result;
+use crate::config::{Config, MetricsPartitioning};
+use anyhow::{bail, Result};
 use std::path::Path;
 use std::sync::Arc;
 use tracing::info;
@@ -22,10 +29,29 @@ pub async fn init(config: &Config) -> Result<Arc<Database>> {
         std::fs::create_dir_all(parent)?;
     }
 
-    info!(path = %db_path, "Initializing database");
+    let read_connections = config.modules.storage.read_connections;
+    info!(path = %db_path, read_connections, "Initializing database");
 
-    let db = Database::new(db_path)?;
-    db.migrate()?;
+    let key = config.modules.storage.encryption_key.as_deref();
+    let db = Database::open_with_key(db_path, read_connections, key)?;
+    db.set_metrics_partitioning(
+        config.modules.storage.partitioning == MetricsPartitioning::Monthly,
+    );
+    if config.modules.storage.auto_migrate {
+        db.migrate()?;
+    } else {
+        let pending = migrations::status(&db)?
+            .iter()
+            .filter(|v| v.applied_at.is_none())
+            .count();
+        if pending > 0 {
+            bail!(
+                "{} pending schema migration(s) and modules.storage.auto_migrate is off: \
+                 review them with `infractl db migrate --dry-run`, then run `infractl db migrate`",
+                pending
+            );
+        }
+    }
 
     Ok(Arc::new(db))
 }
@@ -1,3 +1,4 @@
+use crate::deploy::JobKind;
 use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 
@@ -18,6 +19,12 @@ pub struct MetricRecord {
     pub containers_running: Option<u32>,
     pub containers_total: Option<u32>,
     pub raw_json: Option<String>,
+    /// Bytes received since boot, summed over all interfaces but loopback
+    #[serde(default)]
+    pub network_rx_bytes: Option<u64>,
+    /// Bytes sent since boot, summed over all interfaces but loopback
+    #[serde(default)]
+    pub network_tx_bytes: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,6 +40,97 @@ pub struct AggregatedMetric {
     pub load_avg: f64,
     pub load_max: f64,
     pub samples_count: u32,
+    /// Usage of `/` in percent
+    #[serde(default)]
+    pub disk_avg: Option<f64>,
+    #[serde(default)]
+    pub disk_max: Option<f64>,
+    /// Average bytes per second over the period
+    #[serde(default)]
+    pub network_rx_rate: Option<f64>,
+    #[serde(default)]
+    pub network_tx_rate: Option<f64>,
+    /// Nearest-rank percentiles of the raw samples in the period
+    #[serde(default)]
+    pub cpu_p95: Option<f64>,
+    #[serde(default)]
+    pub cpu_p99: Option<f64>,
+    #[serde(default)]
+    pub memory_p95: Option<f64>,
+    #[serde(default)]
+    pub memory_p99: Option<f64>,
+}
+
+/// Stats of one container at one point in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerMetricRecord {
+    pub id: Option<i64>,
+    pub agent_name: String,
+    pub container_name: String,
+    pub compose_project: Option<String>,
+    pub compose_service: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub collected_at: OffsetDateTime,
+    pub cpu_percent: f64,
+    pub memory_usage: u64,
+    pub memory_limit: u64,
+    pub memory_percent: f64,
+    pub network_rx_bytes: u64,
+    pub network_tx_bytes: u64,
+    pub block_read_bytes: u64,
+    pub block_write_bytes: u64,
+    pub pids: u64,
+}
+
+/// One value of a user-defined metric (`modules.metrics.custom`): `value`
+/// for numbers, `json` for any other output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomMetricRecord {
+    pub id: Option<i64>,
+    pub agent_name: String,
+    pub name: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub collected_at: OffsetDateTime,
+    pub value: Option<f64>,
+    pub json: Option<String>,
+}
+
+/// Filters of `GET /api/metrics/custom`
+#[derive(Debug, Clone, Default)]
+pub struct CustomMetricsQuery {
+    pub agent_name: Option<String>,
+    pub name: Option<String>,
+    pub from: Option<OffsetDateTime>,
+    pub to: Option<OffsetDateTime>,
+    pub limit: u32,
+}
+
+/// Hourly rollup of one container's stats; memory in bytes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerAggregatedMetric {
+    pub agent_name: String,
+    pub container_name: String,
+    pub compose_project: Option<String>,
+    pub compose_service: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub period_start: OffsetDateTime,
+    pub cpu_avg: f64,
+    pub cpu_max: f64,
+    pub memory_avg: f64,
+    pub memory_max: f64,
+    pub samples_count: u32,
+}
+
+/// Filters of `GET /api/containers/:name/metrics`. `name` matches the
+/// container name or its compose service.
+#[derive(Debug, Clone, Default)]
+pub struct ContainerMetricsQuery {
+    pub name: String,
+    pub agent_name: Option<String>,
+    pub compose_project: Option<String>,
+    pub from: Option<OffsetDateTime>,
+    pub to: Option<OffsetDateTime>,
+    pub limit: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -51,6 +149,19 @@ pub struct DeployRecord {
     pub commit_sha: Option<String>,
     pub output: Option<String>,
     pub error_message: Option<String>,
+    /// Retry attempt (1 for the first run)
+    #[serde(default = "default_attempt")]
+    pub attempt: u32,
+    /// Newline-separated `image@digest` of the images a docker_pull deployment started
+    #[serde(default)]
+    pub image_digests: Option<String>,
+    /// Log file with the full output (`output` holds its tail)
+    #[serde(default)]
+    pub log_file: Option<String>,
+}
+
+fn default_attempt() -> u32 {
+    1
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -61,6 +172,8 @@ pub enum DeployStatus {
     Success,
     Failed,
     Cancelled,
+    /// Still running when the service stopped; queued again for the next start
+    Interrupted,
 }
 
 impl std::fmt::Display for DeployStatus {
@@ -71,6 +184,7 @@ impl std::fmt::Display for DeployStatus {
             DeployStatus::Success => write!(f, "success"),
             DeployStatus::Failed => write!(f, "failed"),
             DeployStatus::Cancelled => write!(f, "cancelled"),
+            DeployStatus::Interrupted => write!(f, "interrupted"),
         }
     }
 }
@@ -85,11 +199,42 @@ impl std::str::FromStr for DeployStatus {
             "success" => Ok(DeployStatus::Success),
             "failed" => Ok(DeployStatus::Failed),
             "cancelled" => Ok(DeployStatus::Cancelled),
+            "interrupted" => Ok(DeployStatus::Interrupted),
             _ => Err(format!("Unknown deploy status: {}", s)),
         }
     }
 }
 
+/// Filters and paging for the deploy history, newest first
+#[derive(Debug, Clone, Default)]
+pub struct DeployHistoryQuery {
+    pub agent_name: Option<String>,
+    pub deployment_name: Option<String>,
+    pub status: Option<DeployStatus>,
+    pub from: Option<OffsetDateTime>,
+    pub to: Option<OffsetDateTime>,
+    /// Only records with a lower id: the `next_cursor` of the previous page
+    pub before_id: Option<i64>,
+    pub offset: u32,
+    pub limit: u32,
+}
+
+/// One page of deploy history and the number of records matching the filters
+#[derive(Debug, Clone, Serialize)]
+pub struct DeployHistoryPage {
+    pub deployments: Vec<DeployRecord>,
+    pub total: u64,
+}
+
+/// A deploy whose output or error message matched a search, with the
+/// matching text in context (matches wrapped in `[` `]`)
+#[derive(Debug, Clone, Serialize)]
+pub struct DeploySearchHit {
+    #[serde(flatten)]
+    pub deploy: DeployRecord,
+    pub snippet: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SuspiciousRequest {
     pub id: Option<i64>,
@@ -103,6 +248,55 @@ pub struct SuspiciousRequest {
     pub headers: Option<String>,
 }
 
+/// Temporary ban of an IP after repeated violations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpBan {
+    pub ip: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub banned_until: OffsetDateTime,
+}
+
+/// Agent added by `infractl agent enroll`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnrolledAgent {
+    pub name: String,
+    pub address: String,
+    pub tags: Vec<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub enrolled_at: OffsetDateTime,
+}
+
+/// An agent going `offline` or coming back `online`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentEvent {
+    pub id: Option<i64>,
+    pub agent_name: String,
+    pub event: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub occurred_at: OffsetDateTime,
+    /// Last health check error when going offline
+    pub detail: Option<String>,
+}
+
+/// Pending deploy job. Only the deployment name is stored; its config is
+/// resolved again from the current config when the job is restored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedJob {
+    pub id: String,
+    pub pipeline_id: String,
+    pub agent_name: String,
+    pub deployment_name: String,
+    #[serde(default)]
+    pub kind: JobKind,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    pub trigger_source: Option<String>,
+    #[serde(default = "default_attempt")]
+    pub attempt: u32,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub not_before: Option<OffsetDateTime>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentStatus {
     pub agent_name: String,
@@ -0,0 +1,253 @@
+//! Monthly partitions of `metrics_raw` (`modules.storage.partitioning: monthly`).
+//!
+//! Raw metrics go to one table per month of `collected_at`, named
+//! `metrics_raw_YYYYMM`, with the same columns and index as `metrics_raw`.
+//! Queries read `metrics_raw` and the partitions their time range overlaps,
+//! so rows written before partitioning was turned on (or after it is turned
+//! off) stay visible, and retention drops whole months instead of deleting
+//! row by row. Ids come from the `metrics_raw` sequence and stay unique across
+//! all tables, so exports can keep paging by id.
+//!
+//! Migrations that change `metrics_raw` columns must change the partitions too.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use time::{Date, Month, OffsetDateTime, UtcOffset};
+
+/// Columns of `metrics_raw` and its partitions, in the order of
+/// `metric_from_row`
+pub(super) const METRIC_COLUMNS: &str = "id, agent_name, collected_at, cpu_usage, \
+    memory_usage_percent, memory_used, memory_total, load_one, load_five, load_fifteen, \
+    disk_usage_percent, containers_running, containers_total, raw_json, \
+    network_rx_bytes, network_tx_bytes";
+
+const PREFIX: &str = "metrics_raw_";
+
+/// First day of the month of `at` (UTC)
+fn month_of(at: OffsetDateTime) -> Date {
+    let date = at.to_offset(UtcOffset::UTC).date();
+    date.replace_day(1).unwrap_or(date)
+}
+
+/// Partition holding metrics collected in the month of `at`
+fn name(at: OffsetDateTime) -> String {
+    let month = month_of(at);
+    format!(
+        "{}{:04}{:02}",
+        PREFIX,
+        month.year(),
+        u8::from(month.month())
+    )
+}
+
+/// First day of the month a partition holds, from its name
+fn month(name: &str) -> Option<Date> {
+    let suffix = name.strip_prefix(PREFIX)?;
+    if suffix.len() != 6 {
+        return None;
+    }
+    let year = suffix[..4].parse().ok()?;
+    let month = Month::try_from(suffix[4..].parse::<u8>().ok()?).ok()?;
+    Date::from_calendar_date(year, month, 1).ok()
+}
+
+/// First day of the month after `month`
+fn next_month(month: Date) -> Date {
+    let (year, next) = match month.month() {
+        Month::December => (month.year() + 1, Month::January),
+        m => (month.year(), m.next()),
+    };
+    Date::from_calendar_date(year, next, 1).unwrap_or(month)
+}
+
+/// Existing partitions, oldest first
+pub(super) fn list(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master
+         WHERE type = 'table' AND name GLOB 'metrics_raw_[0-9][0-9][0-9][0-9][0-9][0-9]'
+         ORDER BY name",
+    )?;
+    let names = stmt.query_map([], |row| row.get(0))?;
+    names.collect()
+}
+
+/// What to select raw metrics collected between `from` and `to` from:
+/// `metrics_raw` alone while there are no partitions in that range,
+/// otherwise a `UNION ALL` of it and those partitions
+pub(super) fn source(
+    conn: &Connection,
+    from: Option<OffsetDateTime>,
+    to: Option<OffsetDateTime>,
+) -> rusqlite::Result<String> {
+    let (from, to) = (from.map(month_of), to.map(month_of));
+    let partitions: Vec<String> = list(conn)?
+        .into_iter()
+        .filter(|p| {
+            month(p).is_some_and(|month| {
+                from.is_none_or(|from| month >= from) && to.is_none_or(|to| month <= to)
+            })
+        })
+        .collect();
+    if partitions.is_empty() {
+        return Ok("metrics_raw".to_string());
+    }
+
+    let tables = std::iter::once("metrics_raw").chain(partitions.iter().map(String::as_str));
+    let selects: Vec<String> = tables
+        .map(|table| format!("SELECT {} FROM {}", METRIC_COLUMNS, table))
+        .collect();
+    Ok(format!("({})", selects.join(" UNION ALL ")))
+}
+
+/// Partition for a metric collected at `at`, created on first use with the
+/// current columns of `metrics_raw`
+pub(super) fn ensure(conn: &Connection, at: OffsetDateTime) -> rusqlite::Result<String> {
+    let name = name(at);
+    let exists = conn
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            params![name],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    if !exists {
+        let schema: String = conn.query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'metrics_raw'",
+            [],
+            |row| row.get(0),
+        )?;
+        // Ids are assigned by `next_id`, not by the partition
+        let schema = schema
+            .replacen("metrics_raw", &name, 1)
+            .replace(" AUTOINCREMENT", "");
+        conn.execute_batch(&format!(
+            "{};
+             CREATE INDEX IF NOT EXISTS idx_{name}_agent_time ON {name}(agent_name, collected_at);",
+            schema,
+            name = name,
+        ))?;
+    }
+    Ok(name)
+}
+
+/// Next id from the `metrics_raw` sequence, so ids never repeat across
+/// `metrics_raw` and its partitions
+pub(super) fn next_id(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.execute(
+        "INSERT INTO sqlite_sequence (name, seq)
+         SELECT 'metrics_raw', COALESCE((SELECT MAX(id) FROM metrics_raw), 0)
+         WHERE NOT EXISTS (SELECT 1 FROM sqlite_sequence WHERE name = 'metrics_raw')",
+        [],
+    )?;
+    conn.query_row(
+        "UPDATE sqlite_sequence SET seq = seq + 1 WHERE name = 'metrics_raw' RETURNING seq",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Delete partitioned metrics collected more than `days` ago: partitions of
+/// months that ended before then are dropped, the month in progress at the
+/// cutoff is trimmed. Returns the number of rows removed.
+pub(super) fn cleanup(conn: &Connection, days: u32) -> rusqlite::Result<usize> {
+    let cutoff = (OffsetDateTime::now_utc() - time::Duration::days(i64::from(days))).date();
+    let mut deleted = 0;
+    for partition in list(conn)? {
+        let Some(month) = month(&partition) else {
+            continue;
+        };
+        if next_month(month) <= cutoff {
+            deleted +=
+                conn.query_row(&format!("SELECT COUNT(*) FROM {}", partition), [], |row| {
+                    row.get::<_, i64>(0)
+                })? as usize;
+            conn.execute_batch(&format!("DROP TABLE {}", partition))?;
+        } else if month <= cutoff {
+            deleted += conn.execute(
+                &format!(
+                    "DELETE FROM {} WHERE collected_at < datetime('now', ?1)",
+                    partition
+                ),
+                params![format!("-{} days", days)],
+            )?;
+        }
+    }
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{aggregation, Database, MetricRecord, MetricsQuery};
+
+    fn metric(agent: &str, collected_at: OffsetDateTime) -> MetricRecord {
+        MetricRecord {
+            id: None,
+            agent_name: agent.to_string(),
+            collected_at,
+            cpu_usage: 10.0,
+            memory_usage_percent: 50.0,
+            memory_used: 1,
+            memory_total: 2,
+            load_one: 0.1,
+            load_five: 0.2,
+            load_fifteen: 0.3,
+            disk_usage_percent: None,
+            containers_running: None,
+            containers_total: None,
+            raw_json: None,
+            network_rx_bytes: None,
+            network_tx_bytes: None,
+        }
+    }
+
+    #[test]
+    fn test_monthly_partitions_are_read_and_expired_transparently() {
+        let db = Database::new(":memory:").unwrap();
+        db.migrate().unwrap();
+        let now = OffsetDateTime::now_utc();
+        let legacy = db
+            .insert_metric(&metric("web-1", now - time::Duration::seconds(1)))
+            .unwrap();
+
+        db.set_metrics_partitioning(true);
+        let old = db
+            .insert_metric(&metric("web-1", now - time::Duration::days(100)))
+            .unwrap();
+        let recent = db.insert_metric(&metric("web-2", now)).unwrap();
+        assert!(legacy < old && old < recent);
+        {
+            let conn = db.conn.lock().unwrap();
+            assert_eq!(
+                list(&conn).unwrap(),
+                [name(now - time::Duration::days(100)), name(now)]
+            );
+            assert!(!source(&conn, Some(now - time::Duration::hours(1)), None)
+                .unwrap()
+                .contains(&name(now - time::Duration::days(100))));
+        }
+
+        let all = db
+            .get_metrics(&MetricsQuery {
+                agent_name: None,
+                from: None,
+                to: None,
+                limit: None,
+                aggregation: None,
+            })
+            .unwrap();
+        let ids: Vec<i64> = all.iter().filter_map(|m| m.id).collect();
+        assert_eq!(ids, [recent, legacy, old]);
+        let latest = db.get_latest_metrics().unwrap();
+        let latest: Vec<(&str, Option<i64>)> = latest
+            .iter()
+            .map(|m| (m.agent_name.as_str(), m.id))
+            .collect();
+        assert_eq!(latest, [("web-1", Some(legacy)), ("web-2", Some(recent))]);
+        assert!(aggregation::aggregate_hourly(&db).unwrap() >= 2);
+
+        assert_eq!(db.cleanup_old_metrics(30).unwrap(), 1);
+        let conn = db.conn.lock().unwrap();
+        assert_eq!(list(&conn).unwrap(), [name(now)]);
+    }
+}
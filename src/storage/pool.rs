@@ -0,0 +1,49 @@
+use rusqlite::{Connection, OpenFlags};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+/// Read-only connections for queries. With WAL they read the last committed
+/// state without waiting for the writer connection, so the dashboard stays
+/// responsive while metrics are inserted or aggregated.
+pub struct ReadPool {
+    conns: Vec<Mutex<Connection>>,
+    next: AtomicUsize,
+}
+
+impl ReadPool {
+    /// Open `size` read-only connections to `path`. In-memory databases are
+    /// private to one connection, so they get none and queries use the writer.
+    pub fn open(path: &str, size: usize, key: Option<&str>) -> rusqlite::Result<Self> {
+        let size = if path == ":memory:" { 0 } else { size };
+        let conns = (0..size)
+            .map(|_| {
+                let conn = Connection::open_with_flags(
+                    path,
+                    OpenFlags::SQLITE_OPEN_READ_ONLY
+                        | OpenFlags::SQLITE_OPEN_URI
+                        | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+                )?;
+                super::repository::unlock(&conn, key)?;
+                conn.execute_batch("PRAGMA cache_size=-16000;")?;
+                Ok(Mutex::new(conn))
+            })
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(Self {
+            conns,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// A free connection, or the next one in turn when all are busy;
+    /// `None` without read connections
+    pub fn get(&self) -> Option<MutexGuard<'_, Connection>> {
+        if self.conns.is_empty() {
+            return None;
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let len = self.conns.len();
+        (0..len)
+            .find_map(|i| self.conns[(start + i) % len].try_lock().ok())
+            .or_else(|| Some(self.conns[start % len].lock().unwrap()))
+    }
+}
@@ -1,7 +1,10 @@
 use super::migrations;
 use super::models::*;
+use super::partitions::{self, METRIC_COLUMNS};
+use super::pool::ReadPool;
 use rusqlite::{params, Connection, OptionalExtension};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
 use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
 
@@ -9,36 +12,141 @@ fn parse_rfc3339(s: &str) -> OffsetDateTime {
     OffsetDateTime::parse(s, &Rfc3339).unwrap_or_else(|_| OffsetDateTime::now_utc())
 }
 
+fn metric_from_row(row: &rusqlite::Row) -> rusqlite::Result<MetricRecord> {
+    Ok(MetricRecord {
+        id: Some(row.get(0)?),
+        agent_name: row.get(1)?,
+        collected_at: parse_rfc3339(&row.get::<_, String>(2)?),
+        cpu_usage: row.get(3)?,
+        memory_usage_percent: row.get(4)?,
+        memory_used: row.get::<_, i64>(5)? as u64,
+        memory_total: row.get::<_, i64>(6)? as u64,
+        load_one: row.get(7)?,
+        load_five: row.get(8)?,
+        load_fifteen: row.get(9)?,
+        disk_usage_percent: row.get(10)?,
+        containers_running: row.get(11)?,
+        containers_total: row.get(12)?,
+        raw_json: row.get(13)?,
+        network_rx_bytes: row.get::<_, Option<i64>>(14)?.map(|b| b as u64),
+        network_tx_bytes: row.get::<_, Option<i64>>(15)?.map(|b| b as u64),
+    })
+}
+
 fn format_rfc3339(dt: OffsetDateTime) -> String {
     dt.format(&Rfc3339).unwrap_or_else(|_| String::new())
 }
 
+/// Connections to one SQLite file: the writer (`conn`) for inserts,
+/// updates and migrations, and a pool of read-only connections for queries
 pub struct Database {
     pub(crate) conn: Mutex<Connection>,
+    readers: ReadPool,
+    /// Bumped on every agent status write (ETag of `/api/agents/statuses`).
+    /// Starts at the open time so versions differ across restarts.
+    agent_status_version: AtomicU64,
+    /// SQLCipher key, also used for backups
+    key: Option<String>,
+    /// Write raw metrics to monthly partitions (see `partitions`)
+    partition_metrics: AtomicBool,
+}
+
+/// Read connections used by `Database::new`
+pub const DEFAULT_READ_CONNECTIONS: usize = 4;
+
+/// Give SQLCipher the key of an encrypted database; must come before
+/// anything reads the file. Fails when the key is wrong or the file is not
+/// encrypted.
+pub(super) fn unlock(conn: &Connection, key: Option<&str>) -> rusqlite::Result<()> {
+    let Some(key) = key else {
+        return Ok(());
+    };
+    conn.pragma_update(None, "key", key)?;
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |_| Ok(()))
 }
 
 impl Database {
+    #[allow(dead_code)]
     pub fn new(path: &str) -> rusqlite::Result<Self> {
+        Self::open(path, DEFAULT_READ_CONNECTIONS)
+    }
+
+    /// Open the writer connection, switch the file to WAL, then open
+    /// `read_connections` read-only connections next to it
+    pub fn open(path: &str, read_connections: usize) -> rusqlite::Result<Self> {
+        Self::open_with_key(path, read_connections, None)
+    }
+
+    /// Like [`Database::open`], for a database encrypted with `key`
+    /// (`modules.storage.encryption_key`, SQLCipher builds)
+    pub fn open_with_key(
+        path: &str,
+        read_connections: usize,
+        key: Option<&str>,
+    ) -> rusqlite::Result<Self> {
         let conn = Connection::open(path)?;
+        unlock(&conn, key)?;
 
-        // Enable WAL mode for better concurrent access
+        // Enable WAL mode for better concurrent access. Incremental
+        // auto-vacuum only takes effect on new files (see `maintenance`).
         conn.execute_batch(
-            "PRAGMA journal_mode=WAL;
+            "PRAGMA auto_vacuum=INCREMENTAL;
+             PRAGMA journal_mode=WAL;
              PRAGMA synchronous=NORMAL;
              PRAGMA foreign_keys=ON;
              PRAGMA cache_size=-64000;",
         )?;
 
         Ok(Self {
+            readers: ReadPool::open(path, read_connections, key)?,
             conn: Mutex::new(conn),
+            agent_status_version: AtomicU64::new(
+                OffsetDateTime::now_utc().unix_timestamp_nanos() as u64
+            ),
+            key: key.map(str::to_string),
+            partition_metrics: AtomicBool::new(false),
         })
     }
 
+    /// Connection for queries: a pooled read-only one, or the writer when
+    /// there are none (in-memory databases)
+    fn reader(&self) -> MutexGuard<'_, Connection> {
+        self.readers
+            .get()
+            .unwrap_or_else(|| self.conn.lock().unwrap())
+    }
+
+    /// Copy the whole database to a new file at `path` with SQLite's online
+    /// backup, in one step so the copy is a single consistent snapshot
+    pub fn backup_to(&self, path: &std::path::Path) -> rusqlite::Result<()> {
+        let source = self.reader();
+        let mut target = Connection::open(path)?;
+        // Pages are copied as they are: the backup is encrypted with the same key
+        unlock(&target, self.key.as_deref())?;
+        super::backup::copy(&source, &mut target)
+    }
+
+    /// Write new raw metrics to monthly partitions
+    /// (`modules.storage.partitioning`); reads cover them either way
+    pub fn set_metrics_partitioning(&self, enabled: bool) {
+        self.partition_metrics.store(enabled, Ordering::Relaxed);
+    }
+
+    pub(crate) fn partitions_metrics(&self) -> bool {
+        self.partition_metrics.load(Ordering::Relaxed)
+    }
+
     pub fn migrate(&self) -> rusqlite::Result<()> {
         let conn = self.conn.lock().unwrap();
         migrations::run_migrations(&conn)
     }
 
+    /// Cheap query to check the database is usable (readiness probe)
+    pub fn ping(&self) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row("SELECT 1", [], |_| Ok(()))
+    }
+
     // =========================================================================
     // Metrics Operations
     // =========================================================================
@@ -46,13 +154,30 @@ impl Database {
     #[allow(dead_code)]
     pub fn insert_metric(&self, metric: &MetricRecord) -> rusqlite::Result<i64> {
         let conn = self.conn.lock().unwrap();
+        Self::insert_metric_with(&conn, metric, self.partitions_metrics())
+    }
+
+    /// Insert into `metrics_raw`, or into the partition of the metric's
+    /// month when `partitioned`
+    pub(crate) fn insert_metric_with(
+        conn: &Connection,
+        metric: &MetricRecord,
+        partitioned: bool,
+    ) -> rusqlite::Result<i64> {
+        let (table, id) = if partitioned {
+            let table = partitions::ensure(conn, metric.collected_at)?;
+            (table, Some(partitions::next_id(conn)?))
+        } else {
+            ("metrics_raw".to_string(), None)
+        };
         conn.execute(
-            "INSERT INTO metrics_raw (
-                agent_name, collected_at, cpu_usage, memory_usage_percent,
-                memory_used, memory_total, load_one, load_five, load_fifteen,
-                disk_usage_percent, containers_running, containers_total, raw_json
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            &format!(
+                "INSERT INTO {} ({}) VALUES
+                 (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+                table, METRIC_COLUMNS
+            ),
             params![
+                id,
                 metric.agent_name,
                 format_rfc3339(metric.collected_at),
                 metric.cpu_usage,
@@ -66,19 +191,20 @@ impl Database {
                 metric.containers_running,
                 metric.containers_total,
                 metric.raw_json,
+                metric.network_rx_bytes.map(|b| b as i64),
+                metric.network_tx_bytes.map(|b| b as i64),
             ],
         )?;
         Ok(conn.last_insert_rowid())
     }
 
     pub fn get_metrics(&self, query: &MetricsQuery) -> rusqlite::Result<Vec<MetricRecord>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.reader();
 
-        let mut sql = String::from(
-            "SELECT id, agent_name, collected_at, cpu_usage, memory_usage_percent,
-                    memory_used, memory_total, load_one, load_five, load_fifteen,
-                    disk_usage_percent, containers_running, containers_total, raw_json
-             FROM metrics_raw WHERE 1=1",
+        let mut sql = format!(
+            "SELECT {} FROM {} WHERE 1=1",
+            METRIC_COLUMNS,
+            partitions::source(&conn, query.from, query.to)?
         );
 
         let mut params_vec: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
@@ -106,55 +232,120 @@ impl Database {
             params_vec.iter().map(|p| p.as_ref()).collect();
 
         let mut stmt = conn.prepare(&sql)?;
-        let rows = stmt.query_map(params_refs.as_slice(), |row| {
-            Ok(MetricRecord {
-                id: Some(row.get(0)?),
-                agent_name: row.get(1)?,
-                collected_at: parse_rfc3339(&row.get::<_, String>(2)?),
-                cpu_usage: row.get(3)?,
-                memory_usage_percent: row.get(4)?,
-                memory_used: row.get::<_, i64>(5)? as u64,
-                memory_total: row.get::<_, i64>(6)? as u64,
-                load_one: row.get(7)?,
-                load_five: row.get(8)?,
-                load_fifteen: row.get(9)?,
-                disk_usage_percent: row.get(10)?,
-                containers_running: row.get(11)?,
-                containers_total: row.get(12)?,
-                raw_json: row.get(13)?,
-            })
-        })?;
+        let rows = stmt.query_map(params_refs.as_slice(), metric_from_row)?;
 
         rows.collect()
     }
 
+    /// Raw metrics matching the filters (`limit` of the query is ignored)
+    /// with an id above `after_id`, oldest first. Exports page through them
+    /// by passing the last id.
+    pub fn export_metrics(
+        &self,
+        query: &MetricsQuery,
+        after_id: i64,
+        limit: u32,
+    ) -> rusqlite::Result<Vec<MetricRecord>> {
+        let conn = self.reader();
+
+        let mut sql = format!(
+            "SELECT {} FROM {} WHERE id > ?",
+            METRIC_COLUMNS,
+            partitions::source(&conn, query.from, query.to)?
+        );
+        let mut params_vec: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(after_id)];
+
+        if let Some(agent) = &query.agent_name {
+            sql.push_str(" AND agent_name = ?");
+            params_vec.push(Box::new(agent.clone()));
+        }
+        if let Some(from) = &query.from {
+            sql.push_str(" AND collected_at >= ?");
+            params_vec.push(Box::new(format_rfc3339(*from)));
+        }
+        if let Some(to) = &query.to {
+            sql.push_str(" AND collected_at <= ?");
+            params_vec.push(Box::new(format_rfc3339(*to)));
+        }
+        sql.push_str(&format!(" ORDER BY id LIMIT {}", limit));
+
+        let params_refs: Vec<&dyn rusqlite::ToSql> =
+            params_vec.iter().map(|p| p.as_ref()).collect();
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_refs.as_slice(), metric_from_row)?;
+        rows.collect()
+    }
+
+    /// Most recent raw metrics row of every agent
+    pub fn get_latest_metrics(&self) -> rusqlite::Result<Vec<MetricRecord>> {
+        let conn = self.reader();
+        // The other columns of a MAX() group come from the row with the maximum
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, agent_name, MAX(collected_at), cpu_usage, memory_usage_percent,
+                    memory_used, memory_total, load_one, load_five, load_fifteen,
+                    disk_usage_percent, containers_running, containers_total, raw_json,
+                    network_rx_bytes, network_tx_bytes
+             FROM {}
+             GROUP BY agent_name
+             ORDER BY agent_name",
+            partitions::source(&conn, None, None)?
+        ))?;
+        let rows = stmt.query_map([], metric_from_row)?;
+        rows.collect()
+    }
+
     pub fn get_hourly_metrics(
         &self,
         agent_name: &str,
         from: Option<OffsetDateTime>,
         to: Option<OffsetDateTime>,
     ) -> rusqlite::Result<Vec<AggregatedMetric>> {
-        let conn = self.conn.lock().unwrap();
+        self.get_rollups("metrics_hourly", "hour_start", agent_name, from, to)
+    }
 
-        let mut sql = String::from(
-            "SELECT id, agent_name, hour_start, cpu_avg, cpu_max,
-                    memory_avg, memory_max, load_avg, load_max, samples_count
-             FROM metrics_hourly WHERE agent_name = ?",
+    pub fn get_daily_metrics(
+        &self,
+        agent_name: &str,
+        from: Option<OffsetDateTime>,
+        to: Option<OffsetDateTime>,
+    ) -> rusqlite::Result<Vec<AggregatedMetric>> {
+        self.get_rollups("metrics_daily", "day_start", agent_name, from, to)
+    }
+
+    /// Rows of a rollup table (`metrics_hourly` or `metrics_daily`), newest first
+    fn get_rollups(
+        &self,
+        table: &str,
+        period_column: &str,
+        agent_name: &str,
+        from: Option<OffsetDateTime>,
+        to: Option<OffsetDateTime>,
+    ) -> rusqlite::Result<Vec<AggregatedMetric>> {
+        let conn = self.reader();
+
+        let mut sql = format!(
+            "SELECT id, agent_name, {period}, cpu_avg, cpu_max,
+                    memory_avg, memory_max, load_avg, load_max, samples_count,
+                    disk_avg, disk_max, network_rx_rate, network_tx_rate,
+                    cpu_p95, cpu_p99, memory_p95, memory_p99
+             FROM {table} WHERE agent_name = ?",
+            period = period_column,
+            table = table,
         );
 
         let mut params_vec: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
         params_vec.push(Box::new(agent_name.to_string()));
 
         if let Some(from) = from {
-            sql.push_str(" AND hour_start >= ?");
+            sql.push_str(&format!(" AND {} >= ?", period_column));
             params_vec.push(Box::new(format_rfc3339(from)));
         }
         if let Some(to) = to {
-            sql.push_str(" AND hour_start <= ?");
+            sql.push_str(&format!(" AND {} <= ?", period_column));
             params_vec.push(Box::new(format_rfc3339(to)));
         }
 
-        sql.push_str(" ORDER BY hour_start DESC");
+        sql.push_str(&format!(" ORDER BY {} DESC", period_column));
 
         let params_refs: Vec<&dyn rusqlite::ToSql> =
             params_vec.iter().map(|p| p.as_ref()).collect();
@@ -172,12 +363,228 @@ impl Database {
                 load_avg: row.get(7)?,
                 load_max: row.get(8)?,
                 samples_count: row.get(9)?,
+                disk_avg: row.get(10)?,
+                disk_max: row.get(11)?,
+                network_rx_rate: row.get(12)?,
+                network_tx_rate: row.get(13)?,
+                cpu_p95: row.get(14)?,
+                cpu_p99: row.get(15)?,
+                memory_p95: row.get(16)?,
+                memory_p99: row.get(17)?,
             })
         })?;
 
         rows.collect()
     }
 
+    // =========================================================================
+    // Container Metrics Operations
+    // =========================================================================
+
+    pub(crate) fn insert_container_metric_with(
+        conn: &Connection,
+        metric: &ContainerMetricRecord,
+    ) -> rusqlite::Result<i64> {
+        conn.execute(
+            "INSERT INTO container_metrics (
+                agent_name, container_name, compose_project, compose_service, collected_at,
+                cpu_percent, memory_usage, memory_limit, memory_percent, network_rx_bytes,
+                network_tx_bytes, block_read_bytes, block_write_bytes, pids
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                metric.agent_name,
+                metric.container_name,
+                metric.compose_project.as_deref().unwrap_or_default(),
+                metric.compose_service,
+                format_rfc3339(metric.collected_at),
+                metric.cpu_percent,
+                metric.memory_usage as i64,
+                metric.memory_limit as i64,
+                metric.memory_percent,
+                metric.network_rx_bytes as i64,
+                metric.network_tx_bytes as i64,
+                metric.block_read_bytes as i64,
+                metric.block_write_bytes as i64,
+                metric.pids as i64,
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Raw stats of the containers matching the query, newest first
+    pub fn get_container_metrics(
+        &self,
+        query: &ContainerMetricsQuery,
+    ) -> rusqlite::Result<Vec<ContainerMetricRecord>> {
+        let conn = self.reader();
+        let (filter, params_vec) = Self::container_filter(query, "collected_at");
+        let sql = format!(
+            "SELECT id, agent_name, container_name, compose_project, compose_service,
+                    collected_at, cpu_percent, memory_usage, memory_limit, memory_percent,
+                    network_rx_bytes, network_tx_bytes, block_read_bytes, block_write_bytes, pids
+             FROM container_metrics{} ORDER BY collected_at DESC LIMIT {}",
+            filter, query.limit
+        );
+
+        let params_refs: Vec<&dyn rusqlite::ToSql> =
+            params_vec.iter().map(|p| p.as_ref()).collect();
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_refs.as_slice(), |row| {
+            Ok(ContainerMetricRecord {
+                id: Some(row.get(0)?),
+                agent_name: row.get(1)?,
+                container_name: row.get(2)?,
+                compose_project: Some(row.get::<_, String>(3)?).filter(|p| !p.is_empty()),
+                compose_service: row.get(4)?,
+                collected_at: parse_rfc3339(&row.get::<_, String>(5)?),
+                cpu_percent: row.get(6)?,
+                memory_usage: row.get::<_, i64>(7)? as u64,
+                memory_limit: row.get::<_, i64>(8)? as u64,
+                memory_percent: row.get(9)?,
+                network_rx_bytes: row.get::<_, i64>(10)? as u64,
+                network_tx_bytes: row.get::<_, i64>(11)? as u64,
+                block_read_bytes: row.get::<_, i64>(12)? as u64,
+                block_write_bytes: row.get::<_, i64>(13)? as u64,
+                pids: row.get::<_, i64>(14)? as u64,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Hourly rollups of the containers matching the query, newest first
+    pub fn get_container_hourly_metrics(
+        &self,
+        query: &ContainerMetricsQuery,
+    ) -> rusqlite::Result<Vec<ContainerAggregatedMetric>> {
+        let conn = self.reader();
+        let (filter, params_vec) = Self::container_filter(query, "hour_start");
+        let sql = format!(
+            "SELECT agent_name, container_name, compose_project, compose_service, hour_start,
+                    cpu_avg, cpu_max, memory_avg, memory_max, samples_count
+             FROM container_metrics_hourly{} ORDER BY hour_start DESC LIMIT {}",
+            filter, query.limit
+        );
+
+        let params_refs: Vec<&dyn rusqlite::ToSql> =
+            params_vec.iter().map(|p| p.as_ref()).collect();
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_refs.as_slice(), |row| {
+            Ok(ContainerAggregatedMetric {
+                agent_name: row.get(0)?,
+                container_name: row.get(1)?,
+                compose_project: Some(row.get::<_, String>(2)?).filter(|p| !p.is_empty()),
+                compose_service: row.get(3)?,
+                period_start: parse_rfc3339(&row.get::<_, String>(4)?),
+                cpu_avg: row.get(5)?,
+                cpu_max: row.get(6)?,
+                memory_avg: row.get(7)?,
+                memory_max: row.get(8)?,
+                samples_count: row.get(9)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// `WHERE` clause and parameters of a container metrics query; `time`
+    /// is the timestamp column of the table
+    fn container_filter(
+        query: &ContainerMetricsQuery,
+        time: &str,
+    ) -> (String, Vec<Box<dyn rusqlite::ToSql>>) {
+        let mut filter = String::from(" WHERE (container_name = ? OR compose_service = ?)");
+        let mut params_vec: Vec<Box<dyn rusqlite::ToSql>> =
+            vec![Box::new(query.name.clone()), Box::new(query.name.clone())];
+
+        if let Some(agent) = &query.agent_name {
+            filter.push_str(" AND agent_name = ?");
+            params_vec.push(Box::new(agent.clone()));
+        }
+        if let Some(project) = &query.compose_project {
+            filter.push_str(" AND compose_project = ?");
+            params_vec.push(Box::new(project.clone()));
+        }
+        if let Some(from) = &query.from {
+            filter.push_str(&format!(" AND {} >= ?", time));
+            params_vec.push(Box::new(format_rfc3339(*from)));
+        }
+        if let Some(to) = &query.to {
+            filter.push_str(&format!(" AND {} <= ?", time));
+            params_vec.push(Box::new(format_rfc3339(*to)));
+        }
+        (filter, params_vec)
+    }
+
+    // =========================================================================
+    // Custom Metrics Operations
+    // =========================================================================
+
+    pub(crate) fn insert_custom_metric_with(
+        conn: &Connection,
+        metric: &CustomMetricRecord,
+    ) -> rusqlite::Result<i64> {
+        conn.execute(
+            "INSERT INTO custom_metrics (agent_name, name, collected_at, value, json)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                metric.agent_name,
+                metric.name,
+                format_rfc3339(metric.collected_at),
+                metric.value,
+                metric.json,
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Values of user-defined metrics matching the query, newest first
+    pub fn get_custom_metrics(
+        &self,
+        query: &CustomMetricsQuery,
+    ) -> rusqlite::Result<Vec<CustomMetricRecord>> {
+        let conn = self.reader();
+        let mut sql = String::from(
+            "SELECT id, agent_name, name, collected_at, value, json
+             FROM custom_metrics WHERE 1=1",
+        );
+        let mut params_vec: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+
+        if let Some(agent) = &query.agent_name {
+            sql.push_str(" AND agent_name = ?");
+            params_vec.push(Box::new(agent.clone()));
+        }
+        if let Some(name) = &query.name {
+            sql.push_str(" AND name = ?");
+            params_vec.push(Box::new(name.clone()));
+        }
+        if let Some(from) = &query.from {
+            sql.push_str(" AND collected_at >= ?");
+            params_vec.push(Box::new(format_rfc3339(*from)));
+        }
+        if let Some(to) = &query.to {
+            sql.push_str(" AND collected_at <= ?");
+            params_vec.push(Box::new(format_rfc3339(*to)));
+        }
+        sql.push_str(&format!(
+            " ORDER BY collected_at DESC LIMIT {}",
+            query.limit
+        ));
+
+        let params_refs: Vec<&dyn rusqlite::ToSql> =
+            params_vec.iter().map(|p| p.as_ref()).collect();
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_refs.as_slice(), |row| {
+            Ok(CustomMetricRecord {
+                id: Some(row.get(0)?),
+                agent_name: row.get(1)?,
+                name: row.get(2)?,
+                collected_at: parse_rfc3339(&row.get::<_, String>(3)?),
+                value: row.get(4)?,
+                json: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+
     // =========================================================================
     // Deploy History Operations
     // =========================================================================
@@ -185,11 +592,19 @@ impl Database {
     #[allow(dead_code)]
     pub fn insert_deploy(&self, deploy: &DeployRecord) -> rusqlite::Result<i64> {
         let conn = self.conn.lock().unwrap();
+        Self::insert_deploy_with(&conn, deploy)
+    }
+
+    pub(crate) fn insert_deploy_with(
+        conn: &Connection,
+        deploy: &DeployRecord,
+    ) -> rusqlite::Result<i64> {
         conn.execute(
             "INSERT INTO deploy_history (
                 agent_name, deployment_name, deploy_type, status, started_at,
-                completed_at, duration_ms, trigger_source, commit_sha, output, error_message
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                completed_at, duration_ms, trigger_source, commit_sha, output, error_message,
+                attempt, image_digests, log_file
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
             params![
                 deploy.agent_name,
                 deploy.deployment_name,
@@ -202,6 +617,9 @@ impl Database {
                 deploy.commit_sha,
                 deploy.output,
                 deploy.error_message,
+                deploy.attempt,
+                deploy.image_digests,
+                deploy.log_file,
             ],
         )?;
         Ok(conn.last_insert_rowid())
@@ -218,6 +636,26 @@ impl Database {
         error_message: Option<&str>,
     ) -> rusqlite::Result<()> {
         let conn = self.conn.lock().unwrap();
+        Self::update_deploy_status_with(
+            &conn,
+            id,
+            status,
+            completed_at,
+            duration_ms,
+            output,
+            error_message,
+        )
+    }
+
+    pub(crate) fn update_deploy_status_with(
+        conn: &Connection,
+        id: i64,
+        status: DeployStatus,
+        completed_at: Option<OffsetDateTime>,
+        duration_ms: Option<i64>,
+        output: Option<&str>,
+        error_message: Option<&str>,
+    ) -> rusqlite::Result<()> {
         conn.execute(
             "UPDATE deploy_history SET
                 status = ?1, completed_at = ?2, duration_ms = ?3, output = ?4, error_message = ?5
@@ -234,35 +672,200 @@ impl Database {
         Ok(())
     }
 
+    pub(crate) fn set_deploy_image_digests_with(
+        conn: &Connection,
+        id: i64,
+        image_digests: &str,
+    ) -> rusqlite::Result<()> {
+        conn.execute(
+            "UPDATE deploy_history SET image_digests = ?1 WHERE id = ?2",
+            params![image_digests, id],
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn set_deploy_log_file_with(
+        conn: &Connection,
+        id: i64,
+        log_file: &str,
+    ) -> rusqlite::Result<()> {
+        conn.execute(
+            "UPDATE deploy_history SET log_file = ?1 WHERE id = ?2",
+            params![log_file, id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_deploy(&self, id: i64) -> rusqlite::Result<Option<DeployRecord>> {
+        let conn = self.reader();
+        conn.query_row(
+            "SELECT id, agent_name, deployment_name, deploy_type, status, started_at,
+                    completed_at, duration_ms, trigger_source, commit_sha, output, error_message,
+                    attempt, image_digests, log_file
+             FROM deploy_history WHERE id = ?1",
+            params![id],
+            Self::map_deploy_row,
+        )
+        .optional()
+    }
+
     pub fn get_deploy_history(
         &self,
-        agent_name: Option<&str>,
+        query: &DeployHistoryQuery,
+    ) -> rusqlite::Result<DeployHistoryPage> {
+        let conn = self.reader();
+        let (mut filter, mut params_vec) = Self::deploy_filter(query);
+
+        // The total ignores the cursor and offset
+        let params_refs: Vec<&dyn rusqlite::ToSql> =
+            params_vec.iter().map(|p| p.as_ref()).collect();
+        let total: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM deploy_history{}", filter),
+            params_refs.as_slice(),
+            |row| row.get(0),
+        )?;
+
+        if let Some(before_id) = query.before_id {
+            filter.push_str(" AND id < ?");
+            params_vec.push(Box::new(before_id));
+        }
+        let sql = format!(
+            "SELECT id, agent_name, deployment_name, deploy_type, status, started_at,
+                    completed_at, duration_ms, trigger_source, commit_sha, output, error_message,
+                    attempt, image_digests, log_file
+             FROM deploy_history{} ORDER BY started_at DESC, id DESC LIMIT {} OFFSET {}",
+            filter, query.limit, query.offset
+        );
+
+        let params_refs: Vec<&dyn rusqlite::ToSql> =
+            params_vec.iter().map(|p| p.as_ref()).collect();
+        let mut stmt = conn.prepare(&sql)?;
+        let deployments = stmt
+            .query_map(params_refs.as_slice(), Self::map_deploy_row)?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(DeployHistoryPage {
+            deployments,
+            total: total as u64,
+        })
+    }
+
+    /// Deploy records matching the filters (`before_id`, `offset` and
+    /// `limit` of the query are ignored) with an id above `after_id`, oldest
+    /// first. Exports page through them by passing the last id.
+    pub fn export_deploys(
+        &self,
+        query: &DeployHistoryQuery,
+        after_id: i64,
         limit: u32,
     ) -> rusqlite::Result<Vec<DeployRecord>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.reader();
+        let (mut filter, mut params_vec) = Self::deploy_filter(query);
+        filter.push_str(" AND id > ?");
+        params_vec.push(Box::new(after_id));
 
-        let sql = if agent_name.is_some() {
-            "SELECT id, agent_name, deployment_name, deploy_type, status, started_at,
-                    completed_at, duration_ms, trigger_source, commit_sha, output, error_message
-             FROM deploy_history WHERE agent_name = ?1
-             ORDER BY started_at DESC LIMIT ?2"
-        } else {
+        let sql = format!(
             "SELECT id, agent_name, deployment_name, deploy_type, status, started_at,
-                    completed_at, duration_ms, trigger_source, commit_sha, output, error_message
-             FROM deploy_history ORDER BY started_at DESC LIMIT ?1"
-        };
-
-        let mut stmt = conn.prepare(sql)?;
+                    completed_at, duration_ms, trigger_source, commit_sha, output, error_message,
+                    attempt, image_digests, log_file
+             FROM deploy_history{} ORDER BY id LIMIT {}",
+            filter, limit
+        );
+        let params_refs: Vec<&dyn rusqlite::ToSql> =
+            params_vec.iter().map(|p| p.as_ref()).collect();
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_refs.as_slice(), Self::map_deploy_row)?;
+        rows.collect()
+    }
 
-        let rows = if let Some(agent) = agent_name {
-            stmt.query_map(params![agent, limit], Self::map_deploy_row)?
-        } else {
-            stmt.query_map(params![limit], Self::map_deploy_row)?
-        };
+    /// Deploys whose output or error message contain `text` as a phrase,
+    /// within the filters of `query` (`before_id` and `offset` are ignored).
+    /// Newest first, or oldest first to find where a message first appeared.
+    pub fn search_deploys(
+        &self,
+        text: &str,
+        query: &DeployHistoryQuery,
+        oldest_first: bool,
+    ) -> rusqlite::Result<Vec<DeploySearchHit>> {
+        let conn = self.reader();
+        let (mut filter, mut params_vec) = Self::deploy_filter(query);
+        filter.push_str(" AND deploy_search MATCH ?");
+        // Quoted as one phrase so punctuation in log lines is not parsed as
+        // FTS5 query syntax
+        params_vec.push(Box::new(format!("\"{}\"", text.replace('"', "\"\""))));
 
+        let order = if oldest_first { "ASC" } else { "DESC" };
+        let sql = format!(
+            "SELECT d.id, d.agent_name, d.deployment_name, d.deploy_type, d.status,
+                    d.started_at, d.completed_at, d.duration_ms, d.trigger_source,
+                    d.commit_sha, d.output, d.error_message, d.attempt, d.image_digests,
+                    d.log_file, snippet(deploy_search, -1, '[', ']', '...', 16)
+             FROM deploy_search JOIN deploy_history d ON d.id = deploy_search.rowid{}
+             ORDER BY d.started_at {}, d.id {} LIMIT {}",
+            filter, order, order, query.limit
+        );
+        let params_refs: Vec<&dyn rusqlite::ToSql> =
+            params_vec.iter().map(|p| p.as_ref()).collect();
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_refs.as_slice(), |row| {
+            Ok(DeploySearchHit {
+                deploy: Self::map_deploy_row(row)?,
+                snippet: row.get(15)?,
+            })
+        })?;
         rows.collect()
     }
 
+    /// `WHERE` clause and parameters for the filters of a deploy history query
+    fn deploy_filter(query: &DeployHistoryQuery) -> (String, Vec<Box<dyn rusqlite::ToSql>>) {
+        let mut filter = String::from(" WHERE 1=1");
+        let mut params_vec: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+
+        if let Some(agent) = &query.agent_name {
+            filter.push_str(" AND agent_name = ?");
+            params_vec.push(Box::new(agent.clone()));
+        }
+        if let Some(deployment) = &query.deployment_name {
+            filter.push_str(" AND deployment_name = ?");
+            params_vec.push(Box::new(deployment.clone()));
+        }
+        if let Some(status) = &query.status {
+            filter.push_str(" AND status = ?");
+            params_vec.push(Box::new(status.to_string()));
+        }
+        if let Some(from) = &query.from {
+            filter.push_str(" AND started_at >= ?");
+            params_vec.push(Box::new(format_rfc3339(*from)));
+        }
+        if let Some(to) = &query.to {
+            filter.push_str(" AND started_at <= ?");
+            params_vec.push(Box::new(format_rfc3339(*to)));
+        }
+        (filter, params_vec)
+    }
+
+    /// Completion time of the latest successful deploy of a deployment;
+    /// lifecycle and rollback jobs (recorded under their kind) do not count
+    pub fn last_successful_deploy(
+        &self,
+        agent_name: &str,
+        deployment_name: &str,
+    ) -> rusqlite::Result<Option<OffsetDateTime>> {
+        let conn = self.reader();
+        let completed_at: Option<Option<String>> = conn
+            .query_row(
+                "SELECT completed_at FROM deploy_history
+                 WHERE agent_name = ?1 AND deployment_name = ?2 AND status = 'success'
+                   AND (trigger_source IS NULL
+                        OR trigger_source NOT IN ('shutdown', 'start', 'restart', 'rollback'))
+                 ORDER BY completed_at DESC LIMIT 1",
+                params![agent_name, deployment_name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(completed_at.flatten().map(|s| parse_rfc3339(&s)))
+    }
+
     fn map_deploy_row(row: &rusqlite::Row) -> rusqlite::Result<DeployRecord> {
         Ok(DeployRecord {
             id: Some(row.get(0)?),
@@ -280,9 +883,154 @@ impl Database {
             commit_sha: row.get(9)?,
             output: row.get(10)?,
             error_message: row.get(11)?,
+            attempt: row.get(12)?,
+            image_digests: row.get(13)?,
+            log_file: row.get(14)?,
         })
     }
 
+    // =========================================================================
+    // Deploy Queue Operations
+    // =========================================================================
+
+    pub fn insert_queued_job(&self, job: &QueuedJob) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO deploy_queue (
+                id, pipeline_id, agent_name, deployment_name, created_at, trigger_source, attempt,
+                not_before, kind
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                job.id,
+                job.pipeline_id,
+                job.agent_name,
+                job.deployment_name,
+                format_rfc3339(job.created_at),
+                job.trigger_source,
+                job.attempt,
+                job.not_before.map(format_rfc3339),
+                job.kind.to_string(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_queued_job(&self, id: &str) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM deploy_queue WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// Pending jobs in queue order
+    pub fn get_queued_jobs(&self) -> rusqlite::Result<Vec<QueuedJob>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT id, pipeline_id, agent_name, deployment_name, created_at, trigger_source,
+                    attempt, not_before, kind
+             FROM deploy_queue ORDER BY created_at, rowid",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok(QueuedJob {
+                id: row.get(0)?,
+                pipeline_id: row.get(1)?,
+                agent_name: row.get(2)?,
+                deployment_name: row.get(3)?,
+                created_at: parse_rfc3339(&row.get::<_, String>(4)?),
+                trigger_source: row.get(5)?,
+                attempt: row.get(6)?,
+                not_before: row.get::<_, Option<String>>(7)?.map(|s| parse_rfc3339(&s)),
+                kind: row.get::<_, String>(8)?.parse().unwrap_or_default(),
+            })
+        })?;
+
+        rows.collect()
+    }
+
+    // =========================================================================
+    // IP Ban Operations
+    // =========================================================================
+
+    pub(crate) fn insert_ip_ban_with(conn: &Connection, ban: &IpBan) -> rusqlite::Result<()> {
+        conn.execute(
+            "INSERT OR REPLACE INTO ip_bans (ip, banned_until) VALUES (?1, ?2)",
+            params![ban.ip, format_rfc3339(ban.banned_until)],
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn delete_ip_ban_with(conn: &Connection, ip: &str) -> rusqlite::Result<()> {
+        conn.execute("DELETE FROM ip_bans WHERE ip = ?1", params![ip])?;
+        Ok(())
+    }
+
+    /// Bans that have not expired yet; expired rows are removed
+    pub fn get_ip_bans(&self) -> rusqlite::Result<Vec<IpBan>> {
+        let bans = {
+            let conn = self.reader();
+            let mut stmt = conn.prepare("SELECT ip, banned_until FROM ip_bans")?;
+            let bans = stmt
+                .query_map([], |row| {
+                    Ok(IpBan {
+                        ip: row.get(0)?,
+                        banned_until: parse_rfc3339(&row.get::<_, String>(1)?),
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            bans
+        };
+
+        let now = OffsetDateTime::now_utc();
+        let (active, expired): (Vec<_>, Vec<_>) =
+            bans.into_iter().partition(|ban| ban.banned_until > now);
+        if !expired.is_empty() {
+            // Readers are read-only; deleting needs the writer
+            let conn = self.conn.lock().unwrap();
+            for ban in expired {
+                Self::delete_ip_ban_with(&conn, &ban.ip)?;
+            }
+        }
+        Ok(active)
+    }
+
+    // =========================================================================
+    // Enrolled Agent Operations
+    // =========================================================================
+
+    /// Store a newly enrolled agent. Returns false if the name is taken.
+    pub fn insert_enrolled_agent(&self, agent: &EnrolledAgent) -> rusqlite::Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO enrolled_agents (name, address, tags, enrolled_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                agent.name,
+                agent.address,
+                serde_json::to_string(&agent.tags).unwrap_or_else(|_| "[]".to_string()),
+                format_rfc3339(agent.enrolled_at),
+            ],
+        )?;
+        Ok(inserted == 1)
+    }
+
+    pub fn get_enrolled_agents(&self) -> rusqlite::Result<Vec<EnrolledAgent>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT name, address, tags, enrolled_at FROM enrolled_agents ORDER BY name",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok(EnrolledAgent {
+                name: row.get(0)?,
+                address: row.get(1)?,
+                tags: serde_json::from_str(&row.get::<_, String>(2)?).unwrap_or_default(),
+                enrolled_at: parse_rfc3339(&row.get::<_, String>(3)?),
+            })
+        })?;
+
+        rows.collect()
+    }
+
     // =========================================================================
     // Suspicious Requests Operations
     // =========================================================================
@@ -290,6 +1038,13 @@ impl Database {
     #[allow(dead_code)]
     pub fn insert_suspicious_request(&self, req: &SuspiciousRequest) -> rusqlite::Result<i64> {
         let conn = self.conn.lock().unwrap();
+        Self::insert_suspicious_request_with(&conn, req)
+    }
+
+    pub(crate) fn insert_suspicious_request_with(
+        conn: &Connection,
+        req: &SuspiciousRequest,
+    ) -> rusqlite::Result<i64> {
         conn.execute(
             "INSERT INTO suspicious_requests (
                 recorded_at, source_ip, method, path, reason, user_agent, headers
@@ -308,7 +1063,7 @@ impl Database {
     }
 
     pub fn get_suspicious_requests(&self, limit: u32) -> rusqlite::Result<Vec<SuspiciousRequest>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.reader();
         let mut stmt = conn.prepare(
             "SELECT id, recorded_at, source_ip, method, path, reason, user_agent, headers
              FROM suspicious_requests ORDER BY recorded_at DESC LIMIT ?1",
@@ -334,9 +1089,27 @@ impl Database {
     // Agent Status Operations
     // =========================================================================
 
-    #[allow(dead_code)]
     pub fn update_agent_status(&self, status: &AgentStatus) -> rusqlite::Result<()> {
         let conn = self.conn.lock().unwrap();
+        Self::update_agent_status_with(&conn, status)?;
+        self.agent_statuses_changed();
+        Ok(())
+    }
+
+    /// Changes whenever an agent status is written
+    pub fn agent_status_version(&self) -> u64 {
+        self.agent_status_version.load(Ordering::Relaxed)
+    }
+
+    /// Record an agent status write made through `update_agent_status_with`
+    pub(crate) fn agent_statuses_changed(&self) {
+        self.agent_status_version.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn update_agent_status_with(
+        conn: &Connection,
+        status: &AgentStatus,
+    ) -> rusqlite::Result<()> {
         conn.execute(
             "INSERT INTO agent_status (agent_name, last_seen, status, version, uptime_seconds)
              VALUES (?1, ?2, ?3, ?4, ?5)
@@ -354,7 +1127,7 @@ impl Database {
     }
 
     pub fn get_agent_status(&self, agent_name: &str) -> rusqlite::Result<Option<AgentStatus>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.reader();
         conn.query_row(
             "SELECT agent_name, last_seen, status, version, uptime_seconds
              FROM agent_status WHERE agent_name = ?1",
@@ -373,7 +1146,7 @@ impl Database {
     }
 
     pub fn get_all_agent_statuses(&self) -> rusqlite::Result<Vec<AgentStatus>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.reader();
         let mut stmt = conn.prepare(
             "SELECT agent_name, last_seen, status, version, uptime_seconds
              FROM agent_status ORDER BY agent_name",
@@ -392,16 +1165,57 @@ impl Database {
         rows.collect()
     }
 
+    pub fn insert_agent_event(&self, event: &AgentEvent) -> rusqlite::Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO agent_events (agent_name, event, occurred_at, detail)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                event.agent_name,
+                event.event,
+                format_rfc3339(event.occurred_at),
+                event.detail,
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Latest events of an agent, newest first
+    pub fn get_agent_events(
+        &self,
+        agent_name: &str,
+        limit: u32,
+    ) -> rusqlite::Result<Vec<AgentEvent>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT id, agent_name, event, occurred_at, detail FROM agent_events
+             WHERE agent_name = ?1 ORDER BY occurred_at DESC, id DESC LIMIT ?2",
+        )?;
+
+        let rows = stmt.query_map(params![agent_name, limit], |row| {
+            Ok(AgentEvent {
+                id: Some(row.get(0)?),
+                agent_name: row.get(1)?,
+                event: row.get(2)?,
+                occurred_at: parse_rfc3339(&row.get::<_, String>(3)?),
+                detail: row.get(4)?,
+            })
+        })?;
+
+        rows.collect()
+    }
+
     // =========================================================================
     // Cleanup Operations
     // =========================================================================
 
     pub fn cleanup_old_metrics(&self, days: u32) -> rusqlite::Result<usize> {
         let conn = self.conn.lock().unwrap();
-        conn.execute(
+        let deleted = conn.execute(
             "DELETE FROM metrics_raw WHERE collected_at < datetime('now', ?1)",
             params![format!("-{} days", days)],
-        )
+        )?;
+        Ok(deleted + partitions::cleanup(&conn, days)?)
     }
 
     pub fn cleanup_old_hourly(&self, days: u32) -> rusqlite::Result<usize> {
@@ -412,6 +1226,33 @@ impl Database {
         )
     }
 
+    /// Raw container stats older than `raw_days` and hourly rollups older
+    /// than `hourly_days`
+    pub fn cleanup_old_container_metrics(
+        &self,
+        raw_days: u32,
+        hourly_days: u32,
+    ) -> rusqlite::Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let raw = conn.execute(
+            "DELETE FROM container_metrics WHERE collected_at < datetime('now', ?1)",
+            params![format!("-{} days", raw_days)],
+        )?;
+        let hourly = conn.execute(
+            "DELETE FROM container_metrics_hourly WHERE hour_start < datetime('now', ?1)",
+            params![format!("-{} days", hourly_days)],
+        )?;
+        Ok(raw + hourly)
+    }
+
+    pub fn cleanup_old_custom_metrics(&self, days: u32) -> rusqlite::Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM custom_metrics WHERE collected_at < datetime('now', ?1)",
+            params![format!("-{} days", days)],
+        )
+    }
+
     pub fn cleanup_old_daily(&self, days: u32) -> rusqlite::Result<usize> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
@@ -420,7 +1261,37 @@ impl Database {
         )
     }
 
-    #[allow(dead_code)]
+    /// Delete deploy history rows started more than `days` ago, together
+    /// with their log files
+    pub fn cleanup_old_deploys(&self, days: u32) -> rusqlite::Result<usize> {
+        let cutoff =
+            format_rfc3339(OffsetDateTime::now_utc() - time::Duration::days(i64::from(days)));
+        let (deleted, log_files) = {
+            let conn = self.conn.lock().unwrap();
+            let log_files = conn
+                .prepare(
+                    "SELECT log_file FROM deploy_history
+                     WHERE started_at < ?1 AND log_file IS NOT NULL",
+                )?
+                .query_map(params![cutoff], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            let deleted = conn.execute(
+                "DELETE FROM deploy_history WHERE started_at < ?1",
+                params![cutoff],
+            )?;
+            (deleted, log_files)
+        };
+
+        for path in &log_files {
+            if let Err(e) = std::fs::remove_file(path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    tracing::warn!(path = %path, error = %e, "Failed to remove deploy log file");
+                }
+            }
+        }
+        Ok(deleted)
+    }
+
     pub fn cleanup_old_suspicious(&self, days: u32) -> rusqlite::Result<usize> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
@@ -429,3 +1300,363 @@ impl Database {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(deployment: &str, status: DeployStatus, started_at: OffsetDateTime) -> DeployRecord {
+        DeployRecord {
+            id: None,
+            agent_name: "local".to_string(),
+            deployment_name: deployment.to_string(),
+            deploy_type: "GitPull".to_string(),
+            status,
+            started_at,
+            completed_at: None,
+            duration_ms: None,
+            trigger_source: None,
+            commit_sha: None,
+            output: None,
+            error_message: None,
+            attempt: 1,
+            image_digests: None,
+            log_file: None,
+        }
+    }
+
+    #[test]
+    fn test_custom_metrics_filters() {
+        let db = Database::new(":memory:").unwrap();
+        db.migrate().unwrap();
+        let now = OffsetDateTime::now_utc();
+        for (i, (agent, name, value, json)) in [
+            ("web-1", "queue_depth", Some(3.0), None),
+            ("web-1", "queue_depth", Some(5.0), None),
+            ("web-1", "workers", None, Some("{\"busy\":2}")),
+            ("web-2", "queue_depth", Some(9.0), None),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let conn = db.conn.lock().unwrap();
+            Database::insert_custom_metric_with(
+                &conn,
+                &CustomMetricRecord {
+                    id: None,
+                    agent_name: agent.to_string(),
+                    name: name.to_string(),
+                    collected_at: now + time::Duration::seconds(i as i64),
+                    value,
+                    json: json.map(str::to_string),
+                },
+            )
+            .unwrap();
+        }
+
+        let metrics = db
+            .get_custom_metrics(&CustomMetricsQuery {
+                agent_name: Some("web-1".to_string()),
+                name: Some("queue_depth".to_string()),
+                limit: 10,
+                ..Default::default()
+            })
+            .unwrap();
+        let values: Vec<Option<f64>> = metrics.iter().map(|m| m.value).collect();
+        assert_eq!(values, [Some(5.0), Some(3.0)]);
+
+        let all = db
+            .get_custom_metrics(&CustomMetricsQuery {
+                limit: 10,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[1].json.as_deref(), Some("{\"busy\":2}"));
+    }
+
+    #[test]
+    fn test_container_metrics_by_service_and_hourly() {
+        let db = Database::new(":memory:").unwrap();
+        db.migrate().unwrap();
+        let now = OffsetDateTime::now_utc();
+        for (container, project, memory) in [
+            ("shop-web-1", Some("shop"), 100),
+            ("shop-web-1", Some("shop"), 300),
+            ("blog-web-1", Some("blog"), 50),
+        ] {
+            let conn = db.conn.lock().unwrap();
+            Database::insert_container_metric_with(
+                &conn,
+                &ContainerMetricRecord {
+                    id: None,
+                    agent_name: "web-1".to_string(),
+                    container_name: container.to_string(),
+                    compose_project: project.map(str::to_string),
+                    compose_service: Some("web".to_string()),
+                    collected_at: now,
+                    cpu_percent: 1.0,
+                    memory_usage: memory,
+                    memory_limit: 1000,
+                    memory_percent: memory as f64 / 10.0,
+                    network_rx_bytes: 0,
+                    network_tx_bytes: 0,
+                    block_read_bytes: 0,
+                    block_write_bytes: 0,
+                    pids: 3,
+                },
+            )
+            .unwrap();
+        }
+        crate::storage::aggregation::aggregate_container_hourly(&db).unwrap();
+
+        let mut query = ContainerMetricsQuery {
+            name: "web".to_string(),
+            limit: 100,
+            ..Default::default()
+        };
+        assert_eq!(db.get_container_metrics(&query).unwrap().len(), 3);
+        query.compose_project = Some("shop".to_string());
+        let raw = db.get_container_metrics(&query).unwrap();
+        assert_eq!(raw.len(), 2);
+        assert_eq!(raw[0].compose_project.as_deref(), Some("shop"));
+
+        let hourly = db.get_container_hourly_metrics(&query).unwrap();
+        assert_eq!(hourly.len(), 1);
+        assert_eq!(hourly[0].memory_avg, 200.0);
+        assert_eq!(hourly[0].memory_max, 300.0);
+        assert_eq!(hourly[0].samples_count, 2);
+    }
+
+    #[test]
+    fn test_queries_do_not_wait_for_the_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metrics.db");
+        let db = Database::open(path.to_str().unwrap(), 2).unwrap();
+        db.migrate().unwrap();
+        let id = db
+            .insert_deploy(&record(
+                "web",
+                DeployStatus::Success,
+                OffsetDateTime::now_utc(),
+            ))
+            .unwrap();
+
+        // Aggregation or a write batch holds the writer
+        let writer = db.conn.lock().unwrap();
+        assert!(db.get_deploy(id).unwrap().is_some());
+        assert!(db
+            .reader()
+            .execute("DELETE FROM deploy_history", [])
+            .is_err());
+        drop(writer);
+    }
+
+    #[test]
+    fn test_deploy_retention_removes_log_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(":memory:").unwrap();
+        db.migrate().unwrap();
+
+        let now = OffsetDateTime::now_utc();
+        let mut ids = Vec::new();
+        for (name, age) in [("old", 100), ("recent", 1)] {
+            let log_file = dir.path().join(format!("{}.log", name));
+            std::fs::write(&log_file, "output").unwrap();
+            let id = db
+                .insert_deploy(&record(
+                    name,
+                    DeployStatus::Success,
+                    now - time::Duration::days(age),
+                ))
+                .unwrap();
+            let conn = db.conn.lock().unwrap();
+            Database::set_deploy_log_file_with(&conn, id, log_file.to_str().unwrap()).unwrap();
+            ids.push((id, log_file));
+        }
+
+        assert_eq!(db.cleanup_old_deploys(90).unwrap(), 1);
+        assert!(db.get_deploy(ids[0].0).unwrap().is_none());
+        assert!(!ids[0].1.exists());
+        assert!(db.get_deploy(ids[1].0).unwrap().is_some());
+        assert!(ids[1].1.exists());
+    }
+
+    #[test]
+    fn test_expired_ip_bans_are_removed_with_a_read_pool() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metrics.db");
+        let db = Database::open(path.to_str().unwrap(), 2).unwrap();
+        db.migrate().unwrap();
+        let now = OffsetDateTime::now_utc();
+        {
+            let conn = db.conn.lock().unwrap();
+            for (ip, until) in [
+                ("10.0.0.1", now + time::Duration::hours(1)),
+                ("10.0.0.2", now - time::Duration::hours(1)),
+            ] {
+                let ban = IpBan {
+                    ip: ip.to_string(),
+                    banned_until: until,
+                };
+                Database::insert_ip_ban_with(&conn, &ban).unwrap();
+            }
+        }
+
+        let active: Vec<String> = db
+            .get_ip_bans()
+            .unwrap()
+            .into_iter()
+            .map(|b| b.ip)
+            .collect();
+        assert_eq!(active, ["10.0.0.1"]);
+        let stored: i64 = db
+            .reader()
+            .query_row("SELECT COUNT(*) FROM ip_bans", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stored, 1);
+    }
+
+    #[test]
+    fn test_search_deploys_by_output_and_error() {
+        let db = Database::new(":memory:").unwrap();
+        db.migrate().unwrap();
+
+        let start = OffsetDateTime::now_utc() - time::Duration::days(3);
+        let mut ids = Vec::new();
+        for day in 0..3 {
+            let mut deploy = record(
+                "api",
+                DeployStatus::Running,
+                start + time::Duration::days(day),
+            );
+            deploy.output = Some("Pulling images\nStarting api".to_string());
+            ids.push(db.insert_deploy(&deploy).unwrap());
+        }
+        // Output written when the deploy finishes is indexed too
+        for &id in &ids[1..] {
+            db.update_deploy_status(
+                id,
+                DeployStatus::Failed,
+                None,
+                None,
+                Some("Error: bind 0.0.0.0:80: address already in use"),
+                Some("container \"api\" exited"),
+            )
+            .unwrap();
+        }
+
+        let query = DeployHistoryQuery {
+            limit: 10,
+            ..Default::default()
+        };
+        let first = db
+            .search_deploys("address already in use", &query, true)
+            .unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[0].deploy.id, Some(ids[1]));
+        assert!(first[0].snippet.contains("[address already in use]"));
+
+        // Quotes and punctuation are matched as text, not query syntax
+        let hits = db.search_deploys("\"api\" exited", &query, false).unwrap();
+        assert_eq!(hits[0].deploy.id, Some(ids[2]));
+        assert_eq!(
+            db.search_deploys("in use:", &query, false).unwrap().len(),
+            2
+        );
+
+        // Replaced output no longer matches
+        let hits = db.search_deploys("Starting api", &query, false).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].deploy.id, Some(ids[0]));
+    }
+
+    #[test]
+    fn test_deploy_history_filters_and_pages() {
+        let db = Database::new(":memory:").unwrap();
+        db.migrate().unwrap();
+
+        let start = OffsetDateTime::now_utc() - time::Duration::days(10);
+        for day in 0..10 {
+            let status = if day % 2 == 0 {
+                DeployStatus::Success
+            } else {
+                DeployStatus::Failed
+            };
+            let name = if day < 6 { "api" } else { "web" };
+            db.insert_deploy(&record(name, status, start + time::Duration::days(day)))
+                .unwrap();
+        }
+
+        // Newest first, total counts every match
+        let query = DeployHistoryQuery {
+            deployment_name: Some("api".to_string()),
+            limit: 4,
+            ..Default::default()
+        };
+        let first = db.get_deploy_history(&query).unwrap();
+        assert_eq!(first.total, 6);
+        assert_eq!(first.deployments.len(), 4);
+        assert!(first.deployments[0].started_at > first.deployments[3].started_at);
+
+        // Cursor and offset reach the same next page
+        let cursor = first.deployments.last().unwrap().id;
+        let by_cursor = db
+            .get_deploy_history(&DeployHistoryQuery {
+                before_id: cursor,
+                ..query.clone()
+            })
+            .unwrap();
+        let by_offset = db
+            .get_deploy_history(&DeployHistoryQuery {
+                offset: 4,
+                ..query.clone()
+            })
+            .unwrap();
+        let ids =
+            |page: &DeployHistoryPage| page.deployments.iter().map(|d| d.id).collect::<Vec<_>>();
+        assert_eq!(by_cursor.deployments.len(), 2);
+        assert_eq!(ids(&by_cursor), ids(&by_offset));
+
+        let failed_recently = db
+            .get_deploy_history(&DeployHistoryQuery {
+                status: Some(DeployStatus::Failed),
+                from: Some(start + time::Duration::days(5)),
+                limit: 50,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(failed_recently.total, 3);
+        assert!(failed_recently
+            .deployments
+            .iter()
+            .all(|d| d.status == DeployStatus::Failed));
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn test_encrypted_database_needs_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metrics.db");
+        let path = path.to_str().unwrap();
+        let db = Database::open_with_key(path, 1, Some("s3cret")).unwrap();
+        db.migrate().unwrap();
+        let id = db
+            .insert_deploy(&record(
+                "web",
+                DeployStatus::Success,
+                OffsetDateTime::now_utc(),
+            ))
+            .unwrap();
+        let backup = dir.path().join("backup.db");
+        db.backup_to(&backup).unwrap();
+        drop(db);
+
+        assert!(Database::open(path, 0).is_err());
+        assert!(Database::open_with_key(path, 0, Some("wrong")).is_err());
+        let db = Database::open_with_key(path, 1, Some("s3cret")).unwrap();
+        assert!(db.get_deploy(id).unwrap().is_some());
+        let copy = Database::open_with_key(backup.to_str().unwrap(), 0, Some("s3cret")).unwrap();
+        assert!(copy.get_deploy(id).unwrap().is_some());
+    }
+}
@@ -0,0 +1,405 @@
+use super::models::*;
+use super::repository::Database;
+use crate::config::WriteBufferConfig;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, error};
+
+/// A write queued for the background writer
+pub enum DbWrite {
+    Metric(MetricRecord),
+    /// Stats of one container from a metrics push
+    ContainerMetric(ContainerMetricRecord),
+    /// Value of a user-defined metric from a metrics push
+    CustomMetric(CustomMetricRecord),
+    /// Agent seen (metrics push); upserts `agent_status`
+    AgentStatus(AgentStatus),
+    /// New deploy history row, keyed by queue job id for the later update
+    DeployStarted {
+        job_id: String,
+        record: DeployRecord,
+    },
+    /// IP banned (or ban extended)
+    IpBanned(IpBan),
+    /// Request rejected by the auth, isolation or rate limit middleware
+    SuspiciousRequest(SuspiciousRequest),
+    /// Ban lifted through the API
+    IpUnbanned {
+        ip: String,
+    },
+    /// Digests of the images a deployment started (before `DeployFinished`)
+    DeployImageDigests {
+        job_id: String,
+        image_digests: String,
+    },
+    /// Log file holding the full output (before `DeployFinished`)
+    DeployLogFile {
+        job_id: String,
+        log_file: String,
+    },
+    DeployFinished {
+        job_id: String,
+        status: DeployStatus,
+        completed_at: OffsetDateTime,
+        duration_ms: i64,
+        output: String,
+        error_message: Option<String>,
+    },
+}
+
+impl DbWrite {
+    /// Name of the write, for logs
+    fn kind(&self) -> &'static str {
+        match self {
+            DbWrite::Metric(_) => "metric",
+            DbWrite::ContainerMetric(_) => "container_metric",
+            DbWrite::CustomMetric(_) => "custom_metric",
+            DbWrite::AgentStatus(_) => "agent_status",
+            DbWrite::DeployStarted { .. } => "deploy_started",
+            DbWrite::IpBanned(_) => "ip_banned",
+            DbWrite::SuspiciousRequest(_) => "suspicious_request",
+            DbWrite::IpUnbanned { .. } => "ip_unbanned",
+            DbWrite::DeployImageDigests { .. } => "deploy_image_digests",
+            DbWrite::DeployLogFile { .. } => "deploy_log_file",
+            DbWrite::DeployFinished { .. } => "deploy_finished",
+        }
+    }
+}
+
+enum Message {
+    Write(Box<DbWrite>),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Handle to the buffered database writer. Writes are queued on a bounded
+/// mpsc channel and committed in batches (one transaction per flush), so
+/// SQLite lock contention never blocks request handlers or the deploy worker.
+/// When the writer falls `queue_capacity` writes behind, senders wait.
+#[derive(Clone)]
+pub struct DbWriter {
+    tx: mpsc::Sender<Message>,
+}
+
+impl DbWriter {
+    /// Spawn the writer task
+    pub fn spawn(db: Arc<Database>, config: &WriteBufferConfig) -> Self {
+        let (tx, rx) = mpsc::channel(config.queue_capacity.max(1));
+        let batch_size = config.batch_size.max(1);
+        let interval =
+            humantime::parse_duration(&config.flush_interval).unwrap_or(Duration::from_secs(1));
+
+        tokio::spawn(writer_task(db, rx, batch_size, interval));
+        Self { tx }
+    }
+
+    /// Queue a write; waits while the queue is full
+    pub async fn send(&self, write: DbWrite) {
+        if self.tx.send(Message::Write(Box::new(write))).await.is_err() {
+            error!("Database writer stopped, dropping write");
+        }
+    }
+
+    /// Commit everything queued so far
+    pub async fn flush(&self) {
+        let (ack_tx, ack_rx) = oneshot::channel();
+        if self.tx.send(Message::Flush(ack_tx)).await.is_ok() {
+            let _ = ack_rx.await;
+        }
+    }
+}
+
+async fn writer_task(
+    db: Arc<Database>,
+    mut rx: mpsc::Receiver<Message>,
+    batch_size: usize,
+    interval: Duration,
+) {
+    let mut pending: Vec<DbWrite> = Vec::with_capacity(batch_size);
+    let mut deploy_ids: HashMap<String, i64> = HashMap::new();
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    ticker.tick().await; // first tick completes immediately
+
+    loop {
+        let flush_ack = tokio::select! {
+            msg = rx.recv() => match msg {
+                Some(Message::Write(write)) => {
+                    pending.push(*write);
+                    if pending.len() < batch_size {
+                        continue;
+                    }
+                    None
+                }
+                Some(Message::Flush(ack)) => Some(ack),
+                None => {
+                    flush(&db, &mut pending, &mut deploy_ids).await;
+                    break;
+                }
+            },
+            _ = ticker.tick() => None,
+        };
+
+        flush(&db, &mut pending, &mut deploy_ids).await;
+        if let Some(ack) = flush_ack {
+            let _ = ack.send(());
+        }
+    }
+}
+
+async fn flush(
+    db: &Arc<Database>,
+    pending: &mut Vec<DbWrite>,
+    deploy_ids: &mut HashMap<String, i64>,
+) {
+    if pending.is_empty() {
+        return;
+    }
+
+    let batch = std::mem::take(pending);
+    let count = batch.len();
+    let db = db.clone();
+    let ids = std::mem::take(deploy_ids);
+
+    match tokio::task::spawn_blocking(move || {
+        let mut ids = ids;
+        let result = apply_batch(&db, batch, &mut ids);
+        (result, ids)
+    })
+    .await
+    {
+        Ok((result, ids)) => {
+            *deploy_ids = ids;
+            match result {
+                Ok(()) => debug!(count, "Flushed database writes"),
+                Err(e) => error!(error = %e, count, "Failed to flush database writes"),
+            }
+        }
+        Err(e) => error!(error = %e, "Database writer panicked"),
+    }
+}
+
+/// Apply a batch of writes in a single transaction. Each write runs in its
+/// own savepoint: one that fails is logged and rolled back without losing
+/// the rest of the batch.
+fn apply_batch(
+    db: &Database,
+    batch: Vec<DbWrite>,
+    deploy_ids: &mut HashMap<String, i64>,
+) -> rusqlite::Result<()> {
+    let mut conn = db.conn.lock().unwrap();
+    let mut tx = conn.transaction()?;
+    let mut agent_statuses_changed = false;
+
+    for write in batch {
+        let kind = write.kind();
+        let savepoint = tx.savepoint()?;
+        match apply_write(db, &savepoint, write, deploy_ids) {
+            Ok(status_changed) => {
+                savepoint.commit()?;
+                agent_statuses_changed |= status_changed;
+            }
+            // Dropping the savepoint rolls the write back
+            Err(e) => error!(error = %e, write = kind, "Failed to apply database write"),
+        }
+    }
+
+    tx.commit()?;
+    if agent_statuses_changed {
+        db.agent_statuses_changed();
+    }
+    Ok(())
+}
+
+/// Apply one write; returns whether an agent status changed
+fn apply_write(
+    db: &Database,
+    conn: &rusqlite::Connection,
+    write: DbWrite,
+    deploy_ids: &mut HashMap<String, i64>,
+) -> rusqlite::Result<bool> {
+    let mut agent_statuses_changed = false;
+    match write {
+        DbWrite::Metric(metric) => {
+            Database::insert_metric_with(conn, &metric, db.partitions_metrics())?;
+        }
+        DbWrite::ContainerMetric(metric) => {
+            Database::insert_container_metric_with(conn, &metric)?;
+        }
+        DbWrite::CustomMetric(metric) => {
+            Database::insert_custom_metric_with(conn, &metric)?;
+        }
+        DbWrite::AgentStatus(status) => {
+            Database::update_agent_status_with(conn, &status)?;
+            agent_statuses_changed = true;
+        }
+        DbWrite::DeployStarted { job_id, record } => {
+            let id = Database::insert_deploy_with(conn, &record)?;
+            deploy_ids.insert(job_id, id);
+        }
+        DbWrite::IpBanned(ban) => {
+            Database::insert_ip_ban_with(conn, &ban)?;
+        }
+        DbWrite::SuspiciousRequest(req) => {
+            Database::insert_suspicious_request_with(conn, &req)?;
+        }
+        DbWrite::IpUnbanned { ip } => {
+            Database::delete_ip_ban_with(conn, &ip)?;
+        }
+        DbWrite::DeployImageDigests {
+            job_id,
+            image_digests,
+        } => {
+            if let Some(&id) = deploy_ids.get(&job_id) {
+                Database::set_deploy_image_digests_with(conn, id, &image_digests)?;
+            }
+        }
+        DbWrite::DeployLogFile { job_id, log_file } => {
+            if let Some(&id) = deploy_ids.get(&job_id) {
+                Database::set_deploy_log_file_with(conn, id, &log_file)?;
+            }
+        }
+        DbWrite::DeployFinished {
+            job_id,
+            status,
+            completed_at,
+            duration_ms,
+            output,
+            error_message,
+        } => {
+            if let Some(id) = deploy_ids.remove(&job_id) {
+                Database::update_deploy_status_with(
+                    conn,
+                    id,
+                    status,
+                    Some(completed_at),
+                    Some(duration_ms),
+                    Some(&output),
+                    error_message.as_deref(),
+                )?;
+            }
+        }
+    }
+    Ok(agent_statuses_changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(status: DeployStatus) -> DeployRecord {
+        DeployRecord {
+            id: None,
+            agent_name: "local".to_string(),
+            deployment_name: "app".to_string(),
+            deploy_type: "Git".to_string(),
+            status,
+            started_at: OffsetDateTime::now_utc(),
+            completed_at: None,
+            duration_ms: None,
+            trigger_source: None,
+            commit_sha: None,
+            output: None,
+            error_message: None,
+            attempt: 1,
+            image_digests: None,
+            log_file: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_writer_batches_deploy_lifecycle() {
+        let db = Arc::new(Database::new(":memory:").unwrap());
+        db.migrate().unwrap();
+
+        let writer = DbWriter::spawn(
+            db.clone(),
+            &WriteBufferConfig {
+                batch_size: 100,
+                flush_interval: "1h".to_string(),
+                queue_capacity: 100,
+            },
+        );
+
+        writer
+            .send(DbWrite::DeployStarted {
+                job_id: "job-1".to_string(),
+                record: record(DeployStatus::Running),
+            })
+            .await;
+        writer
+            .send(DbWrite::DeployFinished {
+                job_id: "job-1".to_string(),
+                status: DeployStatus::Success,
+                completed_at: OffsetDateTime::now_utc(),
+                duration_ms: 42,
+                output: "ok".to_string(),
+                error_message: None,
+            })
+            .await;
+
+        // Nothing is written until the batch is flushed
+        let query = DeployHistoryQuery {
+            limit: 10,
+            ..Default::default()
+        };
+        assert!(db
+            .get_deploy_history(&query)
+            .unwrap()
+            .deployments
+            .is_empty());
+
+        writer.flush().await;
+        let history = db.get_deploy_history(&query).unwrap().deployments;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].status, DeployStatus::Success);
+        assert_eq!(history[0].duration_ms, Some(42));
+    }
+
+    #[tokio::test]
+    async fn test_failed_write_does_not_roll_back_the_batch() {
+        let db = Arc::new(Database::new(":memory:").unwrap());
+        db.migrate().unwrap();
+        db.conn
+            .lock()
+            .unwrap()
+            .execute_batch("DROP TABLE suspicious_requests")
+            .unwrap();
+
+        let writer = DbWriter::spawn(
+            db.clone(),
+            &WriteBufferConfig {
+                batch_size: 100,
+                flush_interval: "1h".to_string(),
+                queue_capacity: 1,
+            },
+        );
+        writer
+            .send(DbWrite::SuspiciousRequest(SuspiciousRequest {
+                id: None,
+                recorded_at: OffsetDateTime::now_utc(),
+                source_ip: "10.0.0.1".to_string(),
+                method: None,
+                path: None,
+                reason: "test".to_string(),
+                user_agent: None,
+                headers: None,
+            }))
+            .await;
+        writer
+            .send(DbWrite::DeployStarted {
+                job_id: "job-1".to_string(),
+                record: record(DeployStatus::Running),
+            })
+            .await;
+        writer.flush().await;
+
+        let query = DeployHistoryQuery {
+            limit: 10,
+            ..Default::default()
+        };
+        assert_eq!(db.get_deploy_history(&query).unwrap().deployments.len(), 1);
+    }
+}
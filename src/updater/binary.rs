@@ -1,3 +1,4 @@
+use serde::Serialize;
 use sha2::{Digest, Sha256};
 use std::fs::{self, File};
 use std::io::Write;
@@ -7,8 +8,7 @@ use std::process::Command;
 use tracing::{debug, error, info, warn};
 
 /// Result of a binary update operation
-#[derive(Debug)]
-#[allow(dead_code)]
+#[derive(Debug, Serialize)]
 pub struct UpdateResult {
     pub success: bool,
     pub from_version: String,
@@ -1,4 +1,5 @@
 use super::github::GitHubClient;
+use crate::http_client::HttpClient;
 use sha2::{Digest, Sha256};
 use std::fs::{self, File};
 use std::io::Write;
@@ -20,10 +21,11 @@ pub struct ConfigSync {
     backup_dir: PathBuf,
     github_client: Option<GitHubClient>,
     raw_url: Option<String>,
+    http: HttpClient,
 }
 
 impl ConfigSync {
-    pub fn new(config_path: &Path) -> Self {
+    pub fn new(config_path: &Path, http: HttpClient) -> Self {
         let backup_dir = config_path
             .parent()
             .map(|p| p.join(".config-backup"))
@@ -34,6 +36,7 @@ impl ConfigSync {
             backup_dir,
             github_client: None,
             raw_url: None,
+            http,
         }
     }
 
@@ -47,7 +50,7 @@ impl ConfigSync {
                 let parts: Vec<&str> = url.split('/').collect();
                 if parts.len() >= 5 {
                     let repo = format!("{}/{}", parts[3], parts[4]);
-                    self.github_client = Some(GitHubClient::new(&repo));
+                    self.github_client = Some(GitHubClient::new(&repo, self.http.clone()));
                 }
             }
         }
@@ -78,17 +81,11 @@ impl ConfigSync {
 
     /// Fetch remote config content
     async fn fetch_remote_config(&self, url: &str) -> Result<String, String> {
-        let client = reqwest::Client::builder()
-            .user_agent(format!("infractl/{}", env!("CARGO_PKG_VERSION")))
-            .timeout(std::time::Duration::from_secs(30))
-            .build()
-            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
-
         debug!(url = %url, "Fetching remote config");
 
-        let response = client
-            .get(url)
-            .send()
+        let response = self
+            .http
+            .send(self.http.client().get(url))
             .await
             .map_err(|e| format!("Failed to fetch remote config: {}", e))?;
 
@@ -1,4 +1,4 @@
-use reqwest::Client;
+use crate::http_client::HttpClient;
 use semver::Version;
 use serde::Deserialize;
 use tracing::{debug, info};
@@ -25,20 +25,14 @@ pub struct ReleaseAsset {
 }
 
 pub struct GitHubClient {
-    client: Client,
+    http: HttpClient,
     repo: String,
 }
 
 impl GitHubClient {
-    pub fn new(repo: &str) -> Self {
-        let client = Client::builder()
-            .user_agent(format!("infractl/{}", env!("CARGO_PKG_VERSION")))
-            .timeout(std::time::Duration::from_secs(30))
-            .build()
-            .unwrap_or_default();
-
+    pub fn new(repo: &str, http: HttpClient) -> Self {
         Self {
-            client,
+            http,
             repo: repo.to_string(),
         }
     }
@@ -58,11 +52,14 @@ impl GitHubClient {
 
             debug!(url = %url, "Fetching latest release");
 
-            let response = self
-                .client
+            let request = self
+                .http
+                .client()
                 .get(&url)
-                .header("Accept", "application/vnd.github.v3+json")
-                .send()
+                .header("Accept", "application/vnd.github.v3+json");
+            let response = self
+                .http
+                .send(request)
                 .await
                 .map_err(|e| format!("Failed to fetch release: {}", e))?;
 
@@ -81,6 +78,40 @@ impl GitHubClient {
         }
     }
 
+    /// Fetch the release with the given tag
+    pub async fn get_release_by_tag(&self, tag: &str) -> Result<Release, String> {
+        let url = format!(
+            "https://api.github.com/repos/{}/releases/tags/{}",
+            self.repo, tag
+        );
+
+        debug!(url = %url, "Fetching release by tag");
+
+        let request = self
+            .http
+            .client()
+            .get(&url)
+            .header("Accept", "application/vnd.github.v3+json");
+        let response = self
+            .http
+            .send(request)
+            .await
+            .map_err(|e| format!("Failed to fetch release: {}", e))?;
+
+        if response.status() == 404 {
+            return Err(format!("Release '{}' not found", tag));
+        }
+
+        if !response.status().is_success() {
+            return Err(format!("GitHub API error: {}", response.status()));
+        }
+
+        response
+            .json::<Release>()
+            .await
+            .map_err(|e| format!("Failed to parse release: {}", e))
+    }
+
     /// Fetch recent releases from GitHub
     pub async fn get_releases(&self, limit: usize) -> Result<Vec<Release>, String> {
         let url = format!(
@@ -90,11 +121,14 @@ impl GitHubClient {
 
         debug!(url = %url, "Fetching releases");
 
-        let response = self
-            .client
+        let request = self
+            .http
+            .client()
             .get(&url)
-            .header("Accept", "application/vnd.github.v3+json")
-            .send()
+            .header("Accept", "application/vnd.github.v3+json");
+        let response = self
+            .http
+            .send(request)
             .await
             .map_err(|e| format!("Failed to fetch releases: {}", e))?;
 
@@ -116,11 +150,14 @@ impl GitHubClient {
             "Downloading release asset"
         );
 
-        let response = self
-            .client
+        let request = self
+            .http
+            .client()
             .get(&asset.browser_download_url)
-            .header("Accept", "application/octet-stream")
-            .send()
+            .header("Accept", "application/octet-stream");
+        let response = self
+            .http
+            .send(request)
             .await
             .map_err(|e| format!("Failed to download asset: {}", e))?;
 
@@ -161,10 +198,10 @@ impl GitHubClient {
     pub async fn fetch_raw_file(&self, url: &str) -> Result<String, String> {
         debug!(url = %url, "Fetching raw file");
 
+        let request = self.http.client().get(url);
         let response = self
-            .client
-            .get(url)
-            .send()
+            .http
+            .send(request)
             .await
             .map_err(|e| format!("Failed to fetch file: {}", e))?;
 
@@ -3,17 +3,23 @@ pub mod config_sync;
 pub mod github;
 
 use crate::config::UpdatesConfig;
+use crate::http_client::HttpClient;
 use binary::{BinaryUpdater, UpdateResult};
 use config_sync::{ConfigSync, ConfigSyncResult};
 use github::{is_newer_version, GitHubClient};
+use serde::Serialize;
 use std::path::Path;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 use tracing::{debug, error, info, warn};
 
+/// Error of `self_update` while another update is running
+pub const UPDATE_IN_PROGRESS: &str = "An update is already in progress";
+
 /// State of the auto-updater
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct UpdaterState {
+    #[serde(with = "time::serde::rfc3339::option")]
     pub last_check: Option<time::OffsetDateTime>,
     pub latest_version: Option<String>,
     pub update_available: bool,
@@ -29,12 +35,17 @@ pub struct Updater {
     binary_updater: Option<BinaryUpdater>,
     config_sync: Option<ConfigSync>,
     state: Arc<RwLock<UpdaterState>>,
+    /// Held while a binary update runs, so checker and API never overlap
+    update_lock: Mutex<()>,
 }
 
 impl Updater {
-    pub fn new(updates_config: &UpdatesConfig, config_path: &str) -> Self {
+    pub fn new(updates_config: &UpdatesConfig, config_path: &str, http: HttpClient) -> Self {
         let github_client = if !updates_config.self_update.github_repo.is_empty() {
-            Some(GitHubClient::new(&updates_config.self_update.github_repo))
+            Some(GitHubClient::new(
+                &updates_config.self_update.github_repo,
+                http.clone(),
+            ))
         } else {
             None
         };
@@ -52,7 +63,7 @@ impl Updater {
         };
 
         let config_sync = if updates_config.config_update.enabled {
-            let sync = ConfigSync::new(Path::new(config_path))
+            let sync = ConfigSync::new(Path::new(config_path), http)
                 .with_github_url(&updates_config.config_update.github_raw_url);
             Some(sync)
         } else {
@@ -66,11 +77,26 @@ impl Updater {
             binary_updater,
             config_sync,
             state: Arc::new(RwLock::new(UpdaterState::default())),
+            update_lock: Mutex::new(()),
         }
     }
 
-    /// Check for available updates
+    pub fn self_update_enabled(&self) -> bool {
+        self.config.self_update.enabled
+    }
+
+    /// Check for available updates; a failure is kept as `last_error`
     pub async fn check_for_updates(&self) -> Result<bool, String> {
+        let result = self.fetch_latest().await;
+        if let Err(e) = &result {
+            let mut state = self.state.write().await;
+            state.last_check = Some(time::OffsetDateTime::now_utc());
+            state.last_error = Some(e.clone());
+        }
+        result
+    }
+
+    async fn fetch_latest(&self) -> Result<bool, String> {
         if !self.config.self_update.enabled {
             return Ok(false);
         }
@@ -117,8 +143,21 @@ impl Updater {
         Ok(update_available)
     }
 
-    /// Perform self-update
+    /// Perform self-update; a failure is kept as `last_error`
     pub async fn self_update(&self) -> Result<UpdateResult, String> {
+        let _updating = self
+            .update_lock
+            .try_lock()
+            .map_err(|_| UPDATE_IN_PROGRESS.to_string())?;
+
+        let result = self.install_latest().await;
+        if let Err(e) = &result {
+            self.state.write().await.last_error = Some(e.clone());
+        }
+        result
+    }
+
+    async fn install_latest(&self) -> Result<UpdateResult, String> {
         if !self.config.self_update.enabled {
             return Err("Self-update is disabled".to_string());
         }
@@ -203,6 +242,7 @@ impl Updater {
             let mut state = self.state.write().await;
             state.update_available = false;
             state.latest_version = Some(remote_version);
+            state.last_error = None;
         }
 
         Ok(result)
@@ -237,7 +277,6 @@ impl Updater {
     }
 
     /// Get current state
-    #[allow(dead_code)]
     pub async fn get_state(&self) -> UpdaterState {
         self.state.read().await.clone()
     }
@@ -288,9 +327,6 @@ pub async fn start_update_checker(
             }
             Err(e) => {
                 error!(error = %e, "Update check failed");
-                // Update state with error
-                let mut state = updater.state.write().await;
-                state.last_error = Some(e);
             }
         }
     }
@@ -340,7 +376,7 @@ pub async fn self_update_standalone(
     force: bool,
     prerelease: bool,
 ) -> Result<binary::UpdateResult, String> {
-    let client = GitHubClient::new(repo);
+    let client = GitHubClient::new(repo, HttpClient::default());
     let updater = BinaryUpdater::new()?;
 
     info!("Checking for updates from {}...", repo);